@@ -0,0 +1,9 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "serve")]
+    {
+        println!("cargo:rerun-if-changed=proto/rust_bert.proto");
+        tonic_build::compile_protos("proto/rust_bert.proto")
+            .expect("Failed to compile the protobuf definitions of the `serve` module");
+    }
+}
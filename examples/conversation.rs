@@ -29,7 +29,7 @@ fn main() -> anyhow::Result<()> {
         conversation_manager.create("Going to the movies tonight - any suggestions?");
     let _conversation_2_id = conversation_manager.create("What's the last book you have read?");
 
-    let output = conversation_model.generate_responses(&mut conversation_manager);
+    let output = conversation_model.generate_responses(&mut conversation_manager)?;
 
     println!("{:?}", output);
 
@@ -38,11 +38,11 @@ fn main() -> anyhow::Result<()> {
         .unwrap()
         .add_user_input("Is it an action movie?");
 
-    let output = conversation_model.generate_responses(&mut conversation_manager);
+    let output = conversation_model.generate_responses(&mut conversation_manager)?;
 
     println!("{:?}", output);
 
-    let output = conversation_model.generate_responses(&mut conversation_manager);
+    let output = conversation_model.generate_responses(&mut conversation_manager)?;
 
     println!("{:?}", output);
 
@@ -0,0 +1,51 @@
+// Copyright 2019 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+extern crate anyhow;
+
+use rust_bert::bert::{BertConfigResources, BertModelResources, BertVocabResources};
+use rust_bert::distilbert::{
+    DistilBertConfigResources, DistilBertModelResources, DistilBertVocabResources,
+};
+use rust_bert::pipelines::common::ModelType;
+use rust_bert::pipelines::registry::{ModelSpec, TaskType};
+use rust_bert::resources::RemoteResource;
+use rust_bert::serve::{BatchConfig, RustBertService};
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    //    Set-up models
+    let mut sentiment_spec = ModelSpec::new(
+        ModelType::DistilBert,
+        RemoteResource::from_pretrained(DistilBertModelResources::DISTIL_BERT_SST2),
+        RemoteResource::from_pretrained(DistilBertConfigResources::DISTIL_BERT_SST2),
+        RemoteResource::from_pretrained(DistilBertVocabResources::DISTIL_BERT_SST2),
+        None,
+    );
+    sentiment_spec.lower_case = true;
+    let ner_spec = ModelSpec::new(
+        ModelType::Bert,
+        RemoteResource::from_pretrained(BertModelResources::BERT_NER),
+        RemoteResource::from_pretrained(BertConfigResources::BERT_NER),
+        RemoteResource::from_pretrained(BertVocabResources::BERT_NER),
+        None,
+    );
+
+    let mut service = RustBertService::new(BatchConfig::default());
+    service
+        .add_model("sentiment", TaskType::Sentiment, sentiment_spec)?
+        .add_model("ner", TaskType::NER, ner_spec)?;
+
+    //    Serve models
+    service.serve("0.0.0.0:50051".parse()?).await?;
+
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use rust_bert::pipelines::sentence_embeddings::{EmbeddingIndex, SentenceEmbeddingsBuilder};
+
+/// Download model:
+///   ```sh
+///   git lfs install
+///   git -C resources clone https://huggingface.co/sentence-transformers/LaBSE
+///   ```
+/// Prepare model (the LaBSE checkpoint includes a dense projection layer):
+///   ```sh
+///   python ./utils/convert_model.py resources/LaBSE/pytorch_model.bin
+///   python ./utils/convert_model.py resources/LaBSE/2_Dense/pytorch_model.bin --suffix
+///   ```
+fn main() -> anyhow::Result<()> {
+    // Set-up multilingual sentence embeddings model
+    let model = SentenceEmbeddingsBuilder::local("resources/LaBSE")
+        .with_device(tch::Device::cuda_if_available())
+        .create_model()?;
+
+    // Index an English corpus
+    let corpus = [
+        "A man is eating food.",
+        "A man is riding a horse.",
+        "The new movie is awesome.",
+        "The central bank raised interest rates.",
+        "A cheetah is running behind its prey.",
+    ];
+    let index = EmbeddingIndex::build(&model, &corpus)?;
+
+    // Query the English index in other languages
+    let queries = [
+        "Un homme mange un sandwich.",
+        "Der Film war großartig.",
+        "El banco central subió los tipos de interés.",
+    ];
+    for (query, hits) in queries.iter().zip(index.search(&model, &queries, 2)?) {
+        println!("{}", query);
+        for hit in hits {
+            println!("\t{} ({:.4})", hit.text, hit.score);
+        }
+    }
+    Ok(())
+}
@@ -17,15 +17,17 @@ use crate::bart::encoder::BartEncoder;
 use crate::common::activations::Activation;
 use crate::common::dropout::Dropout;
 use crate::common::kind::get_negative_infinity;
+use crate::common::loading::{check_device, load_weights};
+use crate::common::placement::check_layer_placement_unsupported;
 use crate::pipelines::common::{ModelType, TokenizerOption};
 use crate::pipelines::generation_utils::private_generation_utils::{
-    PreparedInput, PrivateLanguageGenerator,
+    pad_prompt_ids, PreparedInput, PrivateLanguageGenerator,
 };
 use crate::pipelines::generation_utils::{
     Cache, GenerateConfig, LMHeadModel, LMModelOutput, LanguageGenerator,
 };
 use crate::{Config, RustBertError};
-use rust_tokenizers::tokenizer::{RobertaTokenizer, TruncationStrategy};
+use rust_tokenizers::tokenizer::RobertaTokenizer;
 use rust_tokenizers::vocab::{RobertaVocab, Vocab};
 use serde::{Deserialize, Serialize};
 use std::borrow::Borrow;
@@ -987,6 +989,7 @@ impl LMHeadModel for BartForConditionalGeneration {
         Ok(LMModelOutput {
             lm_logits,
             cache: Cache::BARTCache(base_model_output.cache),
+            all_hidden_states: None,
         })
     }
 }
@@ -1098,11 +1101,13 @@ impl BartGenerator {
         let weights_path = generate_config.model_resource.get_local_path()?;
         let device = generate_config.device;
 
-        generate_config.validate();
+        generate_config.validate()?;
+        check_layer_placement_unsupported(&generate_config.layer_placement, "BART")?;
+        check_device(device)?;
         let mut var_store = nn::VarStore::new(device);
-        let config = BartConfig::from_file(config_path);
+        let config = BartConfig::try_from_file(config_path)?;
         let model = BartForConditionalGeneration::new(&var_store.root(), &config);
-        var_store.load(weights_path)?;
+        load_weights(&mut var_store, weights_path)?;
 
         let bos_token_id = Some(config.bos_token_id.unwrap_or(0));
         let eos_token_ids = Some(match config.eos_token_id {
@@ -1235,24 +1240,11 @@ impl PrivateLanguageGenerator<BartForConditionalGeneration, RobertaVocab, Robert
         prompt_text: &[S],
         max_len: Option<i64>,
         pad_token_id: Option<i64>,
-    ) -> Tensor
+    ) -> (Tensor, Tensor)
     where
         S: AsRef<str> + Sync,
     {
-        let tokens = self._get_tokenizer().encode_list(
-            prompt_text,
-            max_len
-                .map(|max_len| max_len as usize)
-                .unwrap_or(usize::MAX),
-            &TruncationStrategy::LongestFirst,
-            0,
-        );
-        let token_ids = tokens
-            .into_iter()
-            .map(|tokenized_input| tokenized_input.token_ids)
-            .collect::<Vec<Vec<i64>>>();
-
-        let max_len = token_ids.iter().map(|input| input.len()).max().unwrap();
+        let token_ids = self.tokenize_prompt_text(prompt_text, max_len, true);
 
         let pad_token = match pad_token_id {
             Some(value) => value,
@@ -1261,17 +1253,7 @@ impl PrivateLanguageGenerator<BartForConditionalGeneration, RobertaVocab, Robert
                 .convert_tokens_to_ids(&[RobertaVocab::unknown_value()])[0],
         };
 
-        let token_ids = token_ids
-            .into_iter()
-            .map(|mut input| {
-                let temp = vec![pad_token; max_len - input.len()];
-                input.extend(temp);
-                input
-            })
-            .map(|tokens| Tensor::of_slice(&tokens).to(self.get_var_store().device()))
-            .collect::<Vec<Tensor>>();
-
-        Tensor::stack(&token_ids, 0)
+        pad_prompt_ids(token_ids, pad_token, false, self.get_var_store().device())
     }
 
     fn reorder_cache(
@@ -1084,6 +1084,331 @@ impl BertForTokenClassification {
     }
 }
 
+/// # BERT for joint intent detection and slot filling
+/// Model predicting both a sequence-level label (intent) and a label for each token (slots) from a
+/// single encoder pass, following the JointBERT architecture.
+/// It is made of the following blocks:
+/// - `bert`: Base BertModel
+/// - `intent_classifier`: Linear layer for intent classification, applied to the pooled output
+/// - `slot_classifier`: Linear layer for slot filling, applied to the token hidden states
+pub struct BertForIntentSlotClassification {
+    bert: BertModel<BertEmbeddings>,
+    dropout: Dropout,
+    intent_classifier: nn::Linear,
+    slot_classifier: nn::Linear,
+}
+
+impl BertForIntentSlotClassification {
+    /// Build a new `BertForIntentSlotClassification`
+    ///
+    /// # Arguments
+    ///
+    /// * `p` - Variable store path for the root of the BertForIntentSlotClassification model
+    /// * `config` - `BertConfig` object defining the model architecture
+    /// * `num_intent_labels` - Number of intent classes
+    /// * `num_slot_labels` - Number of slot labels (e.g. BIO tags)
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use rust_bert::bert::{BertConfig, BertForIntentSlotClassification};
+    /// use rust_bert::Config;
+    /// use std::path::Path;
+    /// use tch::{nn, Device};
+    ///
+    /// let config_path = Path::new("path/to/config.json");
+    /// let device = Device::Cpu;
+    /// let p = nn::VarStore::new(device);
+    /// let config = BertConfig::from_file(config_path);
+    /// let bert = BertForIntentSlotClassification::new(&p.root(), &config, 7, 72);
+    /// ```
+    pub fn new<'p, P>(
+        p: P,
+        config: &BertConfig,
+        num_intent_labels: i64,
+        num_slot_labels: i64,
+    ) -> BertForIntentSlotClassification
+    where
+        P: Borrow<nn::Path<'p>>,
+    {
+        let p = p.borrow();
+
+        let bert = BertModel::new(p / "bert", config);
+        let dropout = Dropout::new(config.hidden_dropout_prob);
+        let intent_classifier = nn::linear(
+            p / "intent_classifier",
+            config.hidden_size,
+            num_intent_labels,
+            Default::default(),
+        );
+        let slot_classifier = nn::linear(
+            p / "slot_classifier",
+            config.hidden_size,
+            num_slot_labels,
+            Default::default(),
+        );
+
+        BertForIntentSlotClassification {
+            bert,
+            dropout,
+            intent_classifier,
+            slot_classifier,
+        }
+    }
+
+    /// Forward pass through the model
+    ///
+    /// # Arguments
+    ///
+    /// * `input_ids` - Optional input tensor of shape (*batch size*, *sequence_length*). If None, pre-computed embeddings must be provided (see `input_embeds`)
+    /// * `mask` - Optional mask of shape (*batch size*, *sequence_length*). Masked position have value 0, non-masked value 1. If None set to 1
+    /// * `token_type_ids` -Optional segment id of shape (*batch size*, *sequence_length*). Convention is value of 0 for the first sentence (incl. *SEP*) and 1 for the second sentence. If None set to 0.
+    /// * `position_ids` - Optional position ids of shape (*batch size*, *sequence_length*). If None, will be incremented from 0.
+    /// * `input_embeds` - Optional pre-computed input embeddings of shape (*batch size*, *sequence_length*, *hidden_size*). If None, input ids must be provided (see `input_ids`)
+    /// * `train` - boolean flag to turn on/off the dropout layers in the model. Should be set to false for inference.
+    ///
+    /// # Returns
+    ///
+    /// * `BertIntentSlotClassificationOutput` containing:
+    ///   - `intent_logits` - `Tensor` of shape (*batch size*, *num_intent_labels*)
+    ///   - `slot_logits` - `Tensor` of shape (*batch size*, *sequence_length*, *num_slot_labels*)
+    ///   - `all_hidden_states` - `Option<Vec<Tensor>>` of length *num_hidden_layers* with shape (*batch size*, *sequence_length*, *hidden_size*)
+    ///   - `all_attentions` - `Option<Vec<Tensor>>` of length *num_hidden_layers* with shape (*batch size*, *sequence_length*, *hidden_size*)
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use rust_bert::bert::{BertForIntentSlotClassification, BertConfig};
+    /// # use tch::{nn, Device, Tensor, no_grad};
+    /// # use rust_bert::Config;
+    /// # use std::path::Path;
+    /// # use tch::kind::Kind::Int64;
+    /// # let config_path = Path::new("path/to/config.json");
+    /// # let device = Device::Cpu;
+    /// # let vs = nn::VarStore::new(device);
+    /// # let config = BertConfig::from_file(config_path);
+    /// # let bert_model = BertForIntentSlotClassification::new(&vs.root(), &config, 7, 72);
+    /// let (batch_size, sequence_length) = (64, 128);
+    /// let input_tensor = Tensor::rand(&[batch_size, sequence_length], (Int64, device));
+    /// let mask = Tensor::zeros(&[batch_size, sequence_length], (Int64, device));
+    /// let token_type_ids = Tensor::zeros(&[batch_size, sequence_length], (Int64, device));
+    /// let position_ids = Tensor::arange(sequence_length, (Int64, device))
+    ///     .expand(&[batch_size, sequence_length], true);
+    ///
+    /// let model_output = no_grad(|| {
+    ///     bert_model.forward_t(
+    ///         Some(&input_tensor),
+    ///         Some(&mask),
+    ///         Some(&token_type_ids),
+    ///         Some(&position_ids),
+    ///         None,
+    ///         false,
+    ///     )
+    /// });
+    /// ```
+    pub fn forward_t(
+        &self,
+        input_ids: Option<&Tensor>,
+        mask: Option<&Tensor>,
+        token_type_ids: Option<&Tensor>,
+        position_ids: Option<&Tensor>,
+        input_embeds: Option<&Tensor>,
+        train: bool,
+    ) -> BertIntentSlotClassificationOutput {
+        let base_model_output = self
+            .bert
+            .forward_t(
+                input_ids,
+                mask,
+                token_type_ids,
+                position_ids,
+                input_embeds,
+                None,
+                None,
+                train,
+            )
+            .unwrap();
+
+        let intent_logits = base_model_output
+            .pooled_output
+            .unwrap()
+            .apply_t(&self.dropout, train)
+            .apply(&self.intent_classifier);
+        let slot_logits = base_model_output
+            .hidden_state
+            .apply_t(&self.dropout, train)
+            .apply(&self.slot_classifier);
+        BertIntentSlotClassificationOutput {
+            intent_logits,
+            slot_logits,
+            all_hidden_states: base_model_output.all_hidden_states,
+            all_attentions: base_model_output.all_attentions,
+        }
+    }
+}
+/// # BERT for multi-task inference
+/// Shares a single BERT encoder pass between several task heads, each of them optional:
+/// - `sequence_classifier`: Linear layer on the pooled output for sequence classification
+/// - `token_classifier`: Linear layer on the token hidden states for token classification (e.g. NER)
+///
+/// The final hidden states are returned as well, for example to compute sentence embeddings.
+/// It is made of the following blocks:
+/// - `bert`: Base BertModel
+/// - `dropout`: Dropout layer applied before the classification heads
+pub struct BertForMultiTask {
+    bert: BertModel<BertEmbeddings>,
+    dropout: Dropout,
+    sequence_classifier: Option<nn::Linear>,
+    token_classifier: Option<nn::Linear>,
+}
+
+impl BertForMultiTask {
+    /// Build a new `BertForMultiTask`
+    ///
+    /// # Arguments
+    ///
+    /// * `p` - Variable store path for the root of the BertForMultiTask model
+    /// * `config` - `BertConfig` object defining the model architecture
+    /// * `num_sequence_labels` - Optional number of sequence classes (no sequence classification head if None)
+    /// * `num_token_labels` - Optional number of token labels (no token classification head if None)
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use rust_bert::bert::{BertConfig, BertForMultiTask};
+    /// use rust_bert::Config;
+    /// use std::path::Path;
+    /// use tch::{nn, Device};
+    ///
+    /// let config_path = Path::new("path/to/config.json");
+    /// let device = Device::Cpu;
+    /// let p = nn::VarStore::new(device);
+    /// let config = BertConfig::from_file(config_path);
+    /// let bert = BertForMultiTask::new(&p.root(), &config, Some(2), Some(9));
+    /// ```
+    pub fn new<'p, P>(
+        p: P,
+        config: &BertConfig,
+        num_sequence_labels: Option<i64>,
+        num_token_labels: Option<i64>,
+    ) -> BertForMultiTask
+    where
+        P: Borrow<nn::Path<'p>>,
+    {
+        let p = p.borrow();
+
+        let bert = BertModel::new(p / "bert", config);
+        let dropout = Dropout::new(config.hidden_dropout_prob);
+        let sequence_classifier = num_sequence_labels.map(|num_labels| {
+            nn::linear(
+                p / "sequence_classifier",
+                config.hidden_size,
+                num_labels,
+                Default::default(),
+            )
+        });
+        let token_classifier = num_token_labels.map(|num_labels| {
+            nn::linear(
+                p / "token_classifier",
+                config.hidden_size,
+                num_labels,
+                Default::default(),
+            )
+        });
+
+        BertForMultiTask {
+            bert,
+            dropout,
+            sequence_classifier,
+            token_classifier,
+        }
+    }
+
+    /// Forward pass through the model
+    ///
+    /// # Arguments
+    ///
+    /// * `input_ids` - Optional input tensor of shape (*batch size*, *sequence_length*). If None, pre-computed embeddings must be provided (see `input_embeds`)
+    /// * `mask` - Optional mask of shape (*batch size*, *sequence_length*). Masked position have value 0, non-masked value 1. If None set to 1
+    /// * `token_type_ids` -Optional segment id of shape (*batch size*, *sequence_length*). Convention is value of 0 for the first sentence (incl. *SEP*) and 1 for the second sentence. If None set to 0.
+    /// * `position_ids` - Optional position ids of shape (*batch size*, *sequence_length*). If None, will be incremented from 0.
+    /// * `input_embeds` - Optional pre-computed input embeddings of shape (*batch size*, *sequence_length*, *hidden_size*). If None, input ids must be provided (see `input_ids`)
+    /// * `train` - boolean flag to turn on/off the dropout layers in the model. Should be set to false for inference.
+    ///
+    /// # Returns
+    ///
+    /// * `BertMultiTaskOutput` containing:
+    ///   - `sequence_logits` - `Option<Tensor>` of shape (*batch size*, *num_sequence_labels*)
+    ///   - `token_logits` - `Option<Tensor>` of shape (*batch size*, *sequence_length*, *num_token_labels*)
+    ///   - `hidden_state` - `Tensor` of shape (*batch size*, *sequence_length*, *hidden_size*)
+    ///   - `pooled_output` - `Option<Tensor>` of shape (*batch size*, *hidden_size*)
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use rust_bert::bert::{BertForMultiTask, BertConfig};
+    /// # use tch::{nn, Device, Tensor, no_grad};
+    /// # use rust_bert::Config;
+    /// # use std::path::Path;
+    /// # use tch::kind::Kind::Int64;
+    /// # let config_path = Path::new("path/to/config.json");
+    /// # let device = Device::Cpu;
+    /// # let vs = nn::VarStore::new(device);
+    /// # let config = BertConfig::from_file(config_path);
+    /// # let bert_model = BertForMultiTask::new(&vs.root(), &config, Some(2), Some(9));
+    /// let (batch_size, sequence_length) = (64, 128);
+    /// let input_tensor = Tensor::rand(&[batch_size, sequence_length], (Int64, device));
+    /// let mask = Tensor::zeros(&[batch_size, sequence_length], (Int64, device));
+    ///
+    /// let model_output =
+    ///     no_grad(|| bert_model.forward_t(Some(&input_tensor), Some(&mask), None, None, None, false));
+    /// ```
+    pub fn forward_t(
+        &self,
+        input_ids: Option<&Tensor>,
+        mask: Option<&Tensor>,
+        token_type_ids: Option<&Tensor>,
+        position_ids: Option<&Tensor>,
+        input_embeds: Option<&Tensor>,
+        train: bool,
+    ) -> BertMultiTaskOutput {
+        let base_model_output = self
+            .bert
+            .forward_t(
+                input_ids,
+                mask,
+                token_type_ids,
+                position_ids,
+                input_embeds,
+                None,
+                None,
+                train,
+            )
+            .unwrap();
+
+        let sequence_logits = self.sequence_classifier.as_ref().map(|classifier| {
+            base_model_output
+                .pooled_output
+                .as_ref()
+                .unwrap()
+                .apply_t(&self.dropout, train)
+                .apply(classifier)
+        });
+        let token_logits = self.token_classifier.as_ref().map(|classifier| {
+            base_model_output
+                .hidden_state
+                .apply_t(&self.dropout, train)
+                .apply(classifier)
+        });
+        BertMultiTaskOutput {
+            sequence_logits,
+            token_logits,
+            hidden_state: base_model_output.hidden_state,
+            pooled_output: base_model_output.pooled_output,
+        }
+    }
+}
+
 /// # BERT for question answering
 /// Extractive question-answering model based on a BERT language model. Identifies the segment of a context that answers a provided question.
 /// Please note that a significant amount of pre- and post-processing is required to perform end-to-end question answering.
@@ -1270,6 +1595,30 @@ pub struct BertTokenClassificationOutput {
     pub all_attentions: Option<Vec<Tensor>>,
 }
 
+/// Container for the BERT joint intent detection and slot filling model output.
+pub struct BertIntentSlotClassificationOutput {
+    /// Logits for each input (sequence) for each intent class
+    pub intent_logits: Tensor,
+    /// Logits for each sequence item (token) for each slot label
+    pub slot_logits: Tensor,
+    /// Hidden states for all intermediate layers
+    pub all_hidden_states: Option<Vec<Tensor>>,
+    /// Attention weights for all intermediate layers
+    pub all_attentions: Option<Vec<Tensor>>,
+}
+
+/// Container for the BERT multi-task model output.
+pub struct BertMultiTaskOutput {
+    /// Logits for each input (sequence) for each sequence class, if the model has a sequence classification head
+    pub sequence_logits: Option<Tensor>,
+    /// Logits for each sequence item (token) for each token label, if the model has a token classification head
+    pub token_logits: Option<Tensor>,
+    /// Last hidden state of the encoder
+    pub hidden_state: Tensor,
+    /// Pooled output of the encoder (first token hidden state, after the pooler layer)
+    pub pooled_output: Option<Tensor>,
+}
+
 /// Container for the BERT question answering model output.
 pub struct BertQuestionAnsweringOutput {
     /// Logits for the start position for token of each input sequence
@@ -58,9 +58,10 @@ mod embeddings;
 pub(crate) mod encoder;
 
 pub use bert_model::{
-    BertConfig, BertConfigResources, BertForMaskedLM, BertForMultipleChoice,
-    BertForQuestionAnswering, BertForSentenceEmbeddings, BertForSequenceClassification,
-    BertForTokenClassification, BertMaskedLMOutput, BertModel, BertModelOutput, BertModelResources,
+    BertConfig, BertConfigResources, BertForIntentSlotClassification, BertForMaskedLM,
+    BertForMultiTask, BertForMultipleChoice, BertForQuestionAnswering, BertForSentenceEmbeddings,
+    BertForSequenceClassification, BertForTokenClassification, BertIntentSlotClassificationOutput,
+    BertMaskedLMOutput, BertModel, BertModelOutput, BertModelResources, BertMultiTaskOutput,
     BertQuestionAnsweringOutput, BertSequenceClassificationOutput, BertTokenClassificationOutput,
     BertVocabResources,
 };
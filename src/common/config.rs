@@ -9,6 +9,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::RustBertError;
 use serde::Deserialize;
 use std::fs::File;
 use std::io::BufReader;
@@ -42,4 +43,44 @@ where
         let config: Self = serde_json::from_reader(br).expect("could not parse configuration");
         config
     }
+
+    /// Loads a `Config` object from a JSON file, returning an error instead of panicking if the
+    /// file cannot be read (`IOError`) or does not match the configuration expected by the model
+    /// (`InvalidConfigurationError`).
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - `Path` to the configuration JSON file.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::gpt2::Gpt2Config;
+    /// use rust_bert::Config;
+    /// use std::path::Path;
+    ///
+    /// let config_path = Path::new("path/to/config.json");
+    /// let config = Gpt2Config::try_from_file(config_path)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn try_from_file<P: AsRef<Path>>(path: P) -> Result<Self, RustBertError> {
+        let path = path.as_ref();
+        let f = File::open(path).map_err(|error| {
+            RustBertError::IOError(format!(
+                "Could not open configuration file {}: {}",
+                path.display(),
+                error
+            ))
+        })?;
+        let br = BufReader::new(f);
+        serde_json::from_reader(br).map_err(|error| {
+            RustBertError::InvalidConfigurationError(format!(
+                "Could not parse configuration file {}: {}",
+                path.display(),
+                error
+            ))
+        })
+    }
 }
@@ -0,0 +1,46 @@
+//! # Deterministic mode
+//! Seeds the random number generators and disables the non-deterministic CUDA algorithms, so that
+//! the outputs of the pipelines (including sampling-based generation) can be reproduced.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use tch::Cuda;
+
+static DETERMINISTIC: AtomicBool = AtomicBool::new(false);
+static SEED: AtomicU64 = AtomicU64::new(0);
+
+/// Enables the deterministic mode for the current process:
+/// - seeds the random number generators of libtorch (CPU and CUDA devices)
+/// - disables cuDNN and its benchmark mode, which may select non-deterministic algorithms
+/// - the random number generators are re-seeded with `seed` before each sampling-based generation,
+/// making the outputs of a generation call independent of the calls that preceded it
+///
+/// Note that a few CUDA kernels remain non-deterministic (e.g. some scatter and index operations
+/// used by the Longformer and Reformer attention mechanisms).
+///
+/// # Arguments
+///
+/// * `seed` - seed for the random number generators
+///
+/// # Example
+///
+/// ```no_run
+/// use rust_bert::set_deterministic;
+///
+/// set_deterministic(42);
+/// ```
+pub fn set_deterministic(seed: u64) {
+    SEED.store(seed, Ordering::SeqCst);
+    DETERMINISTIC.store(true, Ordering::SeqCst);
+    tch::manual_seed(seed as i64);
+    Cuda::cudnn_set_benchmark(false);
+    Cuda::set_user_enabled_cudnn(false);
+}
+
+/// Returns the seed set with `set_deterministic`, or `None` if the deterministic mode is disabled
+pub fn deterministic_seed() -> Option<u64> {
+    if DETERMINISTIC.load(Ordering::SeqCst) {
+        Some(SEED.load(Ordering::SeqCst))
+    } else {
+        None
+    }
+}
@@ -1,5 +1,8 @@
+use crate::common::loading::weights_load_error;
 use crate::RustBertError;
-use tch::nn::Embedding;
+use std::collections::HashMap;
+use std::path::Path;
+use tch::nn::{Embedding, VarStore};
 use tch::{Device, Tensor};
 
 pub fn process_ids_embeddings_pair(
@@ -52,3 +55,198 @@ pub fn get_shape_and_device_from_ids_embeddings_pair(
         }
     })
 }
+
+const WORD_EMBEDDINGS_NAMES: [&str; 6] = [
+    "word_embeddings.weight",
+    "word_embedding.weight",
+    "wte.weight",
+    "shared.weight",
+    "tokens_embed.weight",
+    "embed_tokens.weight",
+];
+
+/// Suffixes of the output projection (language model head) weights tied to the word embeddings
+/// in the reference implementations of the models
+const OUTPUT_PROJECTION_NAMES: [&str; 4] = [
+    "decoder.weight",
+    "lm_head.weight",
+    "vocab_projector.weight",
+    "lm_loss.weight",
+];
+
+fn is_output_projection(name: &str) -> bool {
+    OUTPUT_PROJECTION_NAMES
+        .iter()
+        .any(|projection_name| name.ends_with(projection_name))
+}
+
+fn find_word_embeddings(
+    variables: &HashMap<String, Tensor>,
+) -> Result<(&String, &Tensor), RustBertError> {
+    variables
+        .iter()
+        .find(|(name, _)| {
+            WORD_EMBEDDINGS_NAMES
+                .iter()
+                .any(|embeddings_name| name.ends_with(embeddings_name))
+        })
+        .ok_or_else(|| {
+            RustBertError::InvalidConfigurationError(
+                "Could not find the word embeddings of the model".to_string(),
+            )
+        })
+}
+
+/// Returns the number of tokens of the word embeddings matrix stored in a `VarStore`
+pub(crate) fn get_vocab_size(var_store: &VarStore) -> Result<i64, RustBertError> {
+    find_word_embeddings(&var_store.variables()).map(|(_, variable)| variable.size()[0])
+}
+
+/// Shares the word embeddings tensor with the output projections of the model matching `condition`
+/// (called with the name, weights and word embeddings), returning the names of the tied projections
+fn tie_output_projections<F>(
+    var_store: &VarStore,
+    condition: F,
+) -> Result<Vec<String>, RustBertError>
+where
+    F: Fn(&str, &Tensor, &Tensor) -> bool,
+{
+    let variables = var_store.variables();
+    let (embeddings_name, embeddings) = find_word_embeddings(&variables)?;
+    let mut tied_projections = vec![];
+    tch::no_grad(|| {
+        for (name, variable) in variables.iter() {
+            if name == embeddings_name
+                || !is_output_projection(name)
+                || variable.size() != embeddings.size()
+                || variable.data_ptr() == embeddings.data_ptr()
+                || !condition(name, variable, embeddings)
+            {
+                continue;
+            }
+            variable.shallow_clone().set_data(embeddings);
+            tied_projections.push(name.clone());
+        }
+    });
+    Ok(tied_projections)
+}
+
+/// Ties the output projections (language model heads) of a model to its word embeddings, so that
+/// they share a single tensor. Only the projections holding the same values as the word embeddings
+/// are tied, leaving the predictions of the model unchanged: this avoids keeping two copies of the
+/// vocabulary matrix in memory for checkpoints storing the duplicated projection.
+///
+/// # Arguments
+///
+/// * `var_store` - `VarStore` holding the (loaded) model variables
+///
+/// # Returns
+///
+/// * `Result<Vec<String>, RustBertError>` names of the output projections tied to the word embeddings
+pub fn tie_word_embeddings(var_store: &VarStore) -> Result<Vec<String>, RustBertError> {
+    tie_output_projections(var_store, |_, projection, embeddings| {
+        projection.equal(embeddings)
+    })
+}
+
+/// Loads the weights of a model, supporting checkpoints that omit the output projection tied to
+/// the word embeddings (language model head):
+/// - output projections missing from the checkpoint are tied to the word embeddings
+/// - output projections identical to the word embeddings are tied to them (see `tie_word_embeddings`)
+///
+/// Any other variable missing from the checkpoint results in an error, as with `VarStore::load`.
+///
+/// # Arguments
+///
+/// * `var_store` - `VarStore` holding the model variables
+/// * `weights_path` - path to the model weights
+pub fn load_weights_with_tied_embeddings<P: AsRef<Path>>(
+    var_store: &mut VarStore,
+    weights_path: P,
+) -> Result<(), RustBertError> {
+    let weights_path = weights_path.as_ref();
+    let mut missing_variables = var_store
+        .load_partial(weights_path)
+        .map_err(|error| weights_load_error(weights_path, error))?;
+    if !missing_variables.is_empty() {
+        let tied_projections = tie_output_projections(var_store, |name, _, _| {
+            missing_variables.iter().any(|missing| missing == name)
+        })?;
+        missing_variables.retain(|name| !tied_projections.contains(name));
+        if !missing_variables.is_empty() {
+            return Err(RustBertError::InvalidConfigurationError(format!(
+                "Variables missing from the model weights: {}",
+                missing_variables.join(", ")
+            )));
+        }
+    }
+    tie_word_embeddings(var_store)?;
+    Ok(())
+}
+
+/// Resizes the variables of a `VarStore` whose first dimension is the vocabulary size (word
+/// embeddings, output projections and biases), in place. The rows added are initialized with the
+/// mean of the existing rows. Variables sharing their storage (e.g. output projections tied to the
+/// word embeddings) remain tied after resizing.
+pub(crate) fn resize_token_embeddings(var_store: &VarStore, vocab_size: i64, new_vocab_size: i64) {
+    tch::no_grad(|| {
+        let mut resized_variables: HashMap<usize, Tensor> = HashMap::new();
+        for (_, mut variable) in var_store.variables() {
+            let mut size = variable.size();
+            if size.first() != Some(&vocab_size) {
+                continue;
+            }
+            let resized = resized_variables
+                .entry(variable.data_ptr() as usize)
+                .or_insert_with(|| {
+                    size[0] = new_vocab_size - vocab_size;
+                    let new_rows = variable
+                        .mean_dim(&[0], true, variable.kind())
+                        .expand(&size, false);
+                    Tensor::cat(&[&variable, &new_rows], 0)
+                });
+            variable.set_data(resized);
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tch::nn::Init;
+
+    #[test]
+    fn tied_embeddings_resize() -> Result<(), RustBertError> {
+        let var_store = VarStore::new(Device::Cpu);
+        let root = var_store.root();
+        let init = Init::Uniform { lo: -1.0, up: 1.0 };
+        let embeddings = (&root / "word_embeddings").var("weight", &[4, 3], init);
+        let _ = (&root / "lm_head").var_copy("weight", &embeddings);
+        let _ = (&root / "decoder").var("weight", &[4, 3], init);
+
+        assert_eq!(tie_word_embeddings(&var_store)?, ["lm_head.weight"]);
+        let data_ptr = |name: &str| var_store.variables()[name].data_ptr();
+        assert_eq!(
+            data_ptr("lm_head.weight"),
+            data_ptr("word_embeddings.weight")
+        );
+        assert_ne!(
+            data_ptr("decoder.weight"),
+            data_ptr("word_embeddings.weight")
+        );
+
+        resize_token_embeddings(&var_store, 4, 6);
+        let variables = var_store.variables();
+        assert_eq!(variables["word_embeddings.weight"].size(), [6, 3]);
+        assert_eq!(variables["decoder.weight"].size(), [6, 3]);
+        assert_eq!(
+            data_ptr("lm_head.weight"),
+            data_ptr("word_embeddings.weight")
+        );
+        assert_ne!(
+            data_ptr("decoder.weight"),
+            data_ptr("word_embeddings.weight")
+        );
+        Ok(())
+    }
+}
@@ -17,6 +17,15 @@ pub enum RustBertError {
     #[error("Tokenizer error: {0}")]
     TokenizerError(String),
 
+    #[error("Tokenizer IO error: {0}")]
+    TokenizerIOError(String),
+
+    #[error("Weights loading error: {0}")]
+    WeightsLoadError(String),
+
+    #[error("Device error: {0}")]
+    DeviceError(String),
+
     #[error("Invalid configuration error: {0}")]
     InvalidConfigurationError(String),
 
@@ -32,7 +41,29 @@ impl From<std::io::Error> for RustBertError {
 
 impl From<TokenizerError> for RustBertError {
     fn from(error: TokenizerError) -> Self {
-        RustBertError::TokenizerError(error.to_string())
+        match error {
+            TokenizerError::FileNotFound(_) | TokenizerError::IOError(_) => {
+                RustBertError::TokenizerIOError(error.to_string())
+            }
+            _ => RustBertError::TokenizerError(error.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for RustBertError {
+    fn from(error: serde_json::Error) -> Self {
+        RustBertError::ValueError(error.to_string())
+    }
+}
+
+#[cfg(feature = "csv")]
+impl From<csv::Error> for RustBertError {
+    fn from(error: csv::Error) -> Self {
+        if error.is_io_error() {
+            RustBertError::IOError(error.to_string())
+        } else {
+            RustBertError::ValueError(error.to_string())
+        }
     }
 }
 
@@ -41,3 +72,17 @@ impl From<TchError> for RustBertError {
         RustBertError::TchError(error.to_string())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tokenizer_errors() {
+        let error: RustBertError = TokenizerError::FileNotFound("vocab.txt".to_string()).into();
+        assert!(matches!(error, RustBertError::TokenizerIOError(_)));
+
+        let error: RustBertError = TokenizerError::TokenNotFound("[UNK]".to_string()).into();
+        assert!(matches!(error, RustBertError::TokenizerError(_)));
+    }
+}
@@ -0,0 +1,67 @@
+//! # Loading of the model weights
+//! Reports the failures to place a model on its device or to load its weights as specific
+//! `RustBertError` variants (`DeviceError` and `WeightsLoadError`), rather than generic tensor
+//! errors.
+
+use crate::RustBertError;
+use std::path::Path;
+use tch::{nn, Cuda, Device};
+
+/// Checks that a model can be placed on the `device`, i.e. that the CUDA device exists when a
+/// CUDA device is requested.
+pub(crate) fn check_device(device: Device) -> Result<(), RustBertError> {
+    if let Device::Cuda(index) = device {
+        let device_count = Cuda::device_count();
+        if index as i64 >= device_count {
+            return Err(RustBertError::DeviceError(format!(
+                "CUDA device {} is not available ({} CUDA devices found)",
+                index, device_count
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Loads the weights of the model from `weights_path` into the `var_store`.
+pub(crate) fn load_weights<P: AsRef<Path>>(
+    var_store: &mut nn::VarStore,
+    weights_path: P,
+) -> Result<(), RustBertError> {
+    let weights_path = weights_path.as_ref();
+    var_store
+        .load(weights_path)
+        .map_err(|error| weights_load_error(weights_path, error))
+}
+
+/// Error returned when the weights at `weights_path` cannot be loaded.
+pub(crate) fn weights_load_error(weights_path: &Path, error: tch::TchError) -> RustBertError {
+    RustBertError::WeightsLoadError(format!(
+        "Could not load the model weights from {}: {}",
+        weights_path.display(),
+        error
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unavailable_device() {
+        assert!(check_device(Device::Cpu).is_ok());
+        let missing_device = Device::Cuda(Cuda::device_count() as usize);
+        assert!(matches!(
+            check_device(missing_device),
+            Err(RustBertError::DeviceError(_))
+        ));
+    }
+
+    #[test]
+    fn missing_weights() {
+        let mut var_store = nn::VarStore::new(Device::Cpu);
+        assert!(matches!(
+            load_weights(&mut var_store, "missing/rust_model.ot"),
+            Err(RustBertError::WeightsLoadError(_))
+        ));
+    }
+}
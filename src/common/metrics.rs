@@ -0,0 +1,365 @@
+//! # Metrics facade
+//! Pipelines report the requests they process, the number of tokens consumed and generated, and
+//! cache lookups to a global [`MetricsRecorder`]. No metrics are recorded until a recorder is
+//! installed with [`set_recorder`], which can be done once per process (typically when a server
+//! starts). The [`PrometheusRecorder`] aggregates the metrics in memory and renders them in the
+//! Prometheus text exposition format.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::metrics::{set_recorder, PrometheusRecorder};
+//! use std::sync::Arc;
+//!
+//! let recorder = Arc::new(PrometheusRecorder::new());
+//! set_recorder(recorder.clone())?;
+//! // ... run pipelines
+//! println!("{}", recorder.render());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::RustBertError;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// # Recorder receiving the metrics reported by the pipelines
+/// All methods have a no-op default implementation, allowing to record a subset of the metrics.
+pub trait MetricsRecorder: Send + Sync {
+    /// Records a request processed by a pipeline (e.g. `generation`, `sentence_embeddings`)
+    ///
+    /// # Arguments
+    ///
+    /// * `pipeline` - name of the pipeline processing the request
+    /// * `batch_size` - number of inputs in the request
+    fn record_request(&self, _pipeline: &str, _batch_size: usize) {}
+
+    /// Records the tokens processed for a request
+    ///
+    /// # Arguments
+    ///
+    /// * `pipeline` - name of the pipeline processing the request
+    /// * `input_tokens` - number of input tokens (including padding)
+    /// * `output_tokens` - number of tokens generated (0 for pipelines that do not generate text)
+    /// * `duration` - processing time of the request
+    fn record_tokens(
+        &self,
+        _pipeline: &str,
+        _input_tokens: usize,
+        _output_tokens: usize,
+        _duration: Duration,
+    ) {
+    }
+
+    /// Records a cache lookup (e.g. `resources` for the cache of downloaded resources)
+    ///
+    /// # Arguments
+    ///
+    /// * `cache` - name of the cache
+    /// * `hit` - `true` if the value was found in the cache
+    fn record_cache_lookup(&self, _cache: &str, _hit: bool) {}
+}
+
+const UNINITIALIZED: usize = 0;
+const INITIALIZING: usize = 1;
+const INITIALIZED: usize = 2;
+
+static STATE: AtomicUsize = AtomicUsize::new(UNINITIALIZED);
+static mut RECORDER: Option<&'static dyn MetricsRecorder> = None;
+
+/// Installs the global metrics recorder. Returns an error if a recorder is already installed.
+///
+/// # Arguments
+///
+/// * `recorder` - `MetricsRecorder` receiving the metrics of all pipelines
+pub fn set_recorder(recorder: Arc<dyn MetricsRecorder>) -> Result<(), RustBertError> {
+    match STATE.compare_exchange(
+        UNINITIALIZED,
+        INITIALIZING,
+        Ordering::SeqCst,
+        Ordering::SeqCst,
+    ) {
+        Ok(_) => {
+            let recorder: &'static Arc<dyn MetricsRecorder> = Box::leak(Box::new(recorder));
+            // Safety: the recorder is only written once, before the state is set to initialized
+            unsafe {
+                RECORDER = Some(recorder.as_ref());
+            }
+            STATE.store(INITIALIZED, Ordering::SeqCst);
+            Ok(())
+        }
+        Err(_) => Err(RustBertError::ValueError(
+            "A metrics recorder is already installed".to_string(),
+        )),
+    }
+}
+
+/// Returns the global metrics recorder, if installed
+pub(crate) fn recorder() -> Option<&'static dyn MetricsRecorder> {
+    if STATE.load(Ordering::SeqCst) == INITIALIZED {
+        // Safety: the recorder is never modified once initialized
+        unsafe { RECORDER }
+    } else {
+        None
+    }
+}
+
+#[derive(Default)]
+struct PipelineMetrics {
+    requests: u64,
+    inputs: u64,
+    batch_size_buckets: [u64; BATCH_SIZE_BUCKETS.len()],
+    input_tokens: u64,
+    output_tokens: u64,
+    duration_seconds: f64,
+}
+
+#[derive(Default)]
+struct CacheMetrics {
+    hits: u64,
+    misses: u64,
+}
+
+#[derive(Default)]
+struct Metrics {
+    pipelines: BTreeMap<String, PipelineMetrics>,
+    caches: BTreeMap<String, CacheMetrics>,
+}
+
+const BATCH_SIZE_BUCKETS: [usize; 8] = [1, 2, 4, 8, 16, 32, 64, 128];
+
+/// # Recorder aggregating the metrics in memory
+/// Exposes the metrics in the Prometheus text format with `render`, e.g. from the `/metrics`
+/// endpoint of a server:
+/// - `rust_bert_requests_total` and `rust_bert_inputs_total` (counters per pipeline)
+/// - `rust_bert_batch_size` (histogram per pipeline)
+/// - `rust_bert_input_tokens_total` and `rust_bert_output_tokens_total` (counters per pipeline)
+/// - `rust_bert_processing_seconds_total` (counter per pipeline), allowing to compute the token throughput
+/// - `rust_bert_cache_hits_total` and `rust_bert_cache_misses_total` (counters per cache)
+#[derive(Default)]
+pub struct PrometheusRecorder {
+    metrics: Mutex<Metrics>,
+}
+
+impl PrometheusRecorder {
+    /// Creates a new recorder without metrics
+    pub fn new() -> PrometheusRecorder {
+        Default::default()
+    }
+
+    /// Renders the metrics recorded so far in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let metrics = self.metrics.lock().unwrap();
+        let mut output = String::new();
+        let pipeline_counters: [(&str, &str, fn(&PipelineMetrics) -> String); 5] = [
+            (
+                "rust_bert_requests_total",
+                "Number of requests processed",
+                |metrics| metrics.requests.to_string(),
+            ),
+            (
+                "rust_bert_inputs_total",
+                "Number of inputs processed",
+                |metrics| metrics.inputs.to_string(),
+            ),
+            (
+                "rust_bert_input_tokens_total",
+                "Number of input tokens processed",
+                |metrics| metrics.input_tokens.to_string(),
+            ),
+            (
+                "rust_bert_output_tokens_total",
+                "Number of tokens generated",
+                |metrics| metrics.output_tokens.to_string(),
+            ),
+            (
+                "rust_bert_processing_seconds_total",
+                "Time spent processing requests",
+                |metrics| metrics.duration_seconds.to_string(),
+            ),
+        ];
+        for (name, help, value) in pipeline_counters.iter() {
+            let _ = writeln!(output, "# HELP {} {}\n# TYPE {} counter", name, help, name);
+            for (pipeline, pipeline_metrics) in metrics.pipelines.iter() {
+                let _ = writeln!(
+                    output,
+                    "{}{{pipeline=\"{}\"}} {}",
+                    name,
+                    pipeline,
+                    value(pipeline_metrics)
+                );
+            }
+        }
+
+        let _ = writeln!(
+            output,
+            "# HELP rust_bert_batch_size Number of inputs per request\n# TYPE rust_bert_batch_size histogram"
+        );
+        for (pipeline, pipeline_metrics) in metrics.pipelines.iter() {
+            for (bucket, count) in BATCH_SIZE_BUCKETS
+                .iter()
+                .zip(pipeline_metrics.batch_size_buckets.iter())
+            {
+                let _ = writeln!(
+                    output,
+                    "rust_bert_batch_size_bucket{{pipeline=\"{}\",le=\"{}\"}} {}",
+                    pipeline, bucket, count
+                );
+            }
+            let _ = writeln!(
+                output,
+                "rust_bert_batch_size_bucket{{pipeline=\"{}\",le=\"+Inf\"}} {}\n\
+                rust_bert_batch_size_sum{{pipeline=\"{}\"}} {}\n\
+                rust_bert_batch_size_count{{pipeline=\"{}\"}} {}",
+                pipeline,
+                pipeline_metrics.requests,
+                pipeline,
+                pipeline_metrics.inputs,
+                pipeline,
+                pipeline_metrics.requests
+            );
+        }
+
+        for (name, help, hit) in [
+            ("rust_bert_cache_hits_total", "Number of cache hits", true),
+            (
+                "rust_bert_cache_misses_total",
+                "Number of cache misses",
+                false,
+            ),
+        ] {
+            let _ = writeln!(output, "# HELP {} {}\n# TYPE {} counter", name, help, name);
+            for (cache, cache_metrics) in metrics.caches.iter() {
+                let value = if hit {
+                    cache_metrics.hits
+                } else {
+                    cache_metrics.misses
+                };
+                let _ = writeln!(output, "{}{{cache=\"{}\"}} {}", name, cache, value);
+            }
+        }
+        output
+    }
+}
+
+impl MetricsRecorder for PrometheusRecorder {
+    fn record_request(&self, pipeline: &str, batch_size: usize) {
+        let mut metrics = self.metrics.lock().unwrap();
+        let pipeline_metrics = metrics.pipelines.entry(pipeline.to_string()).or_default();
+        pipeline_metrics.requests += 1;
+        pipeline_metrics.inputs += batch_size as u64;
+        for (bucket, count) in BATCH_SIZE_BUCKETS
+            .iter()
+            .zip(pipeline_metrics.batch_size_buckets.iter_mut())
+        {
+            if batch_size <= *bucket {
+                *count += 1;
+            }
+        }
+    }
+
+    fn record_tokens(
+        &self,
+        pipeline: &str,
+        input_tokens: usize,
+        output_tokens: usize,
+        duration: Duration,
+    ) {
+        let mut metrics = self.metrics.lock().unwrap();
+        let pipeline_metrics = metrics.pipelines.entry(pipeline.to_string()).or_default();
+        pipeline_metrics.input_tokens += input_tokens as u64;
+        pipeline_metrics.output_tokens += output_tokens as u64;
+        pipeline_metrics.duration_seconds += duration.as_secs_f64();
+    }
+
+    fn record_cache_lookup(&self, cache: &str, hit: bool) {
+        let mut metrics = self.metrics.lock().unwrap();
+        let cache_metrics = metrics.caches.entry(cache.to_string()).or_default();
+        if hit {
+            cache_metrics.hits += 1;
+        } else {
+            cache_metrics.misses += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn prometheus_rendering() {
+        let recorder = PrometheusRecorder::new();
+        recorder.record_request("generation", 3);
+        recorder.record_request("generation", 1);
+        recorder.record_tokens("generation", 10, 5, Duration::from_millis(1500));
+        recorder.record_tokens("generation", 6, 3, Duration::from_millis(500));
+        recorder.record_request("sentence_embeddings", 64);
+        recorder.record_cache_lookup("resources", true);
+        recorder.record_cache_lookup("resources", false);
+        recorder.record_cache_lookup("resources", true);
+
+        let expected = "\
+# HELP rust_bert_requests_total Number of requests processed
+# TYPE rust_bert_requests_total counter
+rust_bert_requests_total{pipeline=\"generation\"} 2
+rust_bert_requests_total{pipeline=\"sentence_embeddings\"} 1
+# HELP rust_bert_inputs_total Number of inputs processed
+# TYPE rust_bert_inputs_total counter
+rust_bert_inputs_total{pipeline=\"generation\"} 4
+rust_bert_inputs_total{pipeline=\"sentence_embeddings\"} 64
+# HELP rust_bert_input_tokens_total Number of input tokens processed
+# TYPE rust_bert_input_tokens_total counter
+rust_bert_input_tokens_total{pipeline=\"generation\"} 16
+rust_bert_input_tokens_total{pipeline=\"sentence_embeddings\"} 0
+# HELP rust_bert_output_tokens_total Number of tokens generated
+# TYPE rust_bert_output_tokens_total counter
+rust_bert_output_tokens_total{pipeline=\"generation\"} 8
+rust_bert_output_tokens_total{pipeline=\"sentence_embeddings\"} 0
+# HELP rust_bert_processing_seconds_total Time spent processing requests
+# TYPE rust_bert_processing_seconds_total counter
+rust_bert_processing_seconds_total{pipeline=\"generation\"} 2
+rust_bert_processing_seconds_total{pipeline=\"sentence_embeddings\"} 0
+# HELP rust_bert_batch_size Number of inputs per request
+# TYPE rust_bert_batch_size histogram
+rust_bert_batch_size_bucket{pipeline=\"generation\",le=\"1\"} 1
+rust_bert_batch_size_bucket{pipeline=\"generation\",le=\"2\"} 1
+rust_bert_batch_size_bucket{pipeline=\"generation\",le=\"4\"} 2
+rust_bert_batch_size_bucket{pipeline=\"generation\",le=\"8\"} 2
+rust_bert_batch_size_bucket{pipeline=\"generation\",le=\"16\"} 2
+rust_bert_batch_size_bucket{pipeline=\"generation\",le=\"32\"} 2
+rust_bert_batch_size_bucket{pipeline=\"generation\",le=\"64\"} 2
+rust_bert_batch_size_bucket{pipeline=\"generation\",le=\"128\"} 2
+rust_bert_batch_size_bucket{pipeline=\"generation\",le=\"+Inf\"} 2
+rust_bert_batch_size_sum{pipeline=\"generation\"} 4
+rust_bert_batch_size_count{pipeline=\"generation\"} 2
+rust_bert_batch_size_bucket{pipeline=\"sentence_embeddings\",le=\"1\"} 0
+rust_bert_batch_size_bucket{pipeline=\"sentence_embeddings\",le=\"2\"} 0
+rust_bert_batch_size_bucket{pipeline=\"sentence_embeddings\",le=\"4\"} 0
+rust_bert_batch_size_bucket{pipeline=\"sentence_embeddings\",le=\"8\"} 0
+rust_bert_batch_size_bucket{pipeline=\"sentence_embeddings\",le=\"16\"} 0
+rust_bert_batch_size_bucket{pipeline=\"sentence_embeddings\",le=\"32\"} 0
+rust_bert_batch_size_bucket{pipeline=\"sentence_embeddings\",le=\"64\"} 1
+rust_bert_batch_size_bucket{pipeline=\"sentence_embeddings\",le=\"128\"} 1
+rust_bert_batch_size_bucket{pipeline=\"sentence_embeddings\",le=\"+Inf\"} 1
+rust_bert_batch_size_sum{pipeline=\"sentence_embeddings\"} 64
+rust_bert_batch_size_count{pipeline=\"sentence_embeddings\"} 1
+# HELP rust_bert_cache_hits_total Number of cache hits
+# TYPE rust_bert_cache_hits_total counter
+rust_bert_cache_hits_total{cache=\"resources\"} 2
+# HELP rust_bert_cache_misses_total Number of cache misses
+# TYPE rust_bert_cache_misses_total counter
+rust_bert_cache_misses_total{cache=\"resources\"} 1
+";
+        assert_eq!(recorder.render(), expected);
+    }
+
+    #[test]
+    fn empty_prometheus_rendering() {
+        let rendered = PrometheusRecorder::new().render();
+        assert!(rendered.lines().all(|line| line.starts_with("# ")));
+        assert_eq!(rendered.lines().count(), 16);
+    }
+}
@@ -1,12 +1,19 @@
 pub(crate) mod activations;
 pub mod config;
+pub(crate) mod determinism;
 pub(crate) mod dropout;
 pub(crate) mod embeddings;
 pub mod error;
 pub(crate) mod kind;
 pub(crate) mod linear;
+pub(crate) mod loading;
+pub mod metrics;
+pub(crate) mod placement;
 pub mod resources;
+pub(crate) mod runtime;
+pub(crate) mod serde_utils;
 pub(crate) mod summary;
+pub(crate) mod trace;
 
 pub use activations::Activation;
 pub use config::Config;
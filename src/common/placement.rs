@@ -0,0 +1,193 @@
+//! # Per-layer placement of the model weights
+//! Places groups of layers of a model on a different device or floating point precision than the
+//! rest of the model, for example to keep the (large) embeddings of a model with a large
+//! vocabulary on the CPU in single precision while the transformer blocks run on the GPU in half
+//! precision.
+//!
+//! Layers are identified by prefixes of the names of their variables in the model weights (e.g.
+//! `transformer.wte` for the GPT2 token embeddings, `transformer.h` for all its blocks and
+//! `transformer.h.0` for its first block). When several prefixes match a variable, the longest
+//! prefix applies. The activations are moved between devices and precisions during the forward
+//! pass, at the boundaries between layers.
+//!
+//! Per-layer placement is only supported by GPT2: the generators of the other architectures
+//! reject a non-empty `layer_placement` when they are created.
+
+use crate::common::loading::check_device;
+use crate::RustBertError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tch::{nn, Device, Kind, Tensor};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+/// # Device and precision of a group of layers
+/// Settings left to `None` keep the device or precision of the rest of the model.
+pub struct LayerPlacement {
+    /// Device of the layers (e.g. `cpu` or `cuda:1` when serialized)
+    #[serde(default, with = "crate::common::serde_utils::optional_device")]
+    pub device: Option<Device>,
+    /// Floating point precision of the layers (e.g. `half` or `float` when serialized)
+    #[serde(default, with = "crate::common::serde_utils::optional_kind")]
+    pub kind: Option<Kind>,
+}
+
+/// Moves the variables of the `var_store` to the device and precision of the longest matching
+/// prefix of `layer_placement`. Must be called after the weights are loaded.
+pub(crate) fn apply_layer_placement(
+    var_store: &nn::VarStore,
+    layer_placement: &HashMap<String, LayerPlacement>,
+) -> Result<(), RustBertError> {
+    for (prefix, placement) in layer_placement {
+        if let Some(device) = placement.device {
+            check_device(device)?;
+        }
+        if let Some(kind) = placement.kind {
+            if !matches!(
+                kind,
+                Kind::Float | Kind::Double | Kind::Half | Kind::BFloat16
+            ) {
+                return Err(RustBertError::InvalidConfigurationError(format!(
+                    "Invalid precision {:?} for the layers {}, expected a floating point kind",
+                    kind, prefix
+                )));
+            }
+        }
+    }
+    if layer_placement.is_empty() {
+        return Ok(());
+    }
+
+    let mut num_placed_variables = 0;
+    for (name, mut variable) in var_store.variables() {
+        if let Some(placement) = matching_placement(&name, layer_placement) {
+            let mut value = variable.shallow_clone();
+            if let Some(kind) = placement.kind {
+                if value.is_floating_point() {
+                    value = value.f_to_kind(kind)?;
+                }
+            }
+            if let Some(device) = placement.device {
+                value = value.f_to_device(device)?;
+            }
+            tch::no_grad(|| variable.set_data(&value));
+            num_placed_variables += 1;
+        }
+    }
+    if num_placed_variables == 0 {
+        return Err(RustBertError::InvalidConfigurationError(format!(
+            "No variable of the model matches the layer placement prefixes {:?}",
+            layer_placement.keys().collect::<Vec<&String>>()
+        )));
+    }
+    Ok(())
+}
+
+/// Rejects a non-empty `layer_placement` for models that do not move their activations between
+/// layers placed on different devices or precisions.
+pub(crate) fn check_layer_placement_unsupported(
+    layer_placement: &HashMap<String, LayerPlacement>,
+    model_name: &str,
+) -> Result<(), RustBertError> {
+    if layer_placement.is_empty() {
+        Ok(())
+    } else {
+        Err(RustBertError::InvalidConfigurationError(format!(
+            "Per-layer placement (`layer_placement`) is only supported by GPT2, not by {}",
+            model_name
+        )))
+    }
+}
+
+/// Returns the placement of the longest prefix matching the variable name
+fn matching_placement<'a>(
+    variable_name: &str,
+    layer_placement: &'a HashMap<String, LayerPlacement>,
+) -> Option<&'a LayerPlacement> {
+    layer_placement
+        .iter()
+        .filter(|(prefix, _)| {
+            variable_name
+                .strip_prefix(prefix.as_str())
+                .map_or(false, |suffix| suffix.is_empty() || suffix.starts_with('.'))
+        })
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, placement)| placement)
+}
+
+/// Moves activations to the device of the weights of the next layer, casting floating point
+/// activations to the precision of the weights. No-op if the layers share the same placement.
+pub(crate) fn to_layer_placement(activations: &Tensor, weights: &Tensor) -> Tensor {
+    let activations = activations.to_device(weights.device());
+    if activations.is_floating_point() && (activations.kind() != weights.kind()) {
+        activations.to_kind(weights.kind())
+    } else {
+        activations
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn longest_prefix_placement() {
+        let cpu = LayerPlacement {
+            device: Some(Device::Cpu),
+            kind: Some(Kind::Float),
+        };
+        let half = LayerPlacement {
+            device: None,
+            kind: Some(Kind::Half),
+        };
+        let layer_placement: HashMap<String, LayerPlacement> = vec![
+            ("transformer.wte".to_string(), cpu),
+            ("transformer.h".to_string(), half),
+            ("transformer.h.1".to_string(), cpu),
+        ]
+        .into_iter()
+        .collect();
+
+        let placement = |name: &str| matching_placement(name, &layer_placement).copied();
+        assert_eq!(placement("transformer.wte.weight"), Some(cpu));
+        assert_eq!(placement("transformer.h.0.attn.c_attn.weight"), Some(half));
+        assert_eq!(placement("transformer.h.1.mlp.c_fc.weight"), Some(cpu));
+        assert_eq!(placement("transformer.h.10.mlp.c_fc.weight"), Some(half));
+        assert_eq!(placement("transformer.wpe.weight"), None);
+        assert_eq!(placement("transformer.hidden"), None);
+    }
+
+    #[test]
+    fn unsupported_layer_placement() {
+        let mut layer_placement = HashMap::new();
+        assert!(check_layer_placement_unsupported(&layer_placement, "BART").is_ok());
+
+        layer_placement.insert("model.shared".to_string(), LayerPlacement::default());
+        let error = check_layer_placement_unsupported(&layer_placement, "BART").unwrap_err();
+        assert!(matches!(
+            error,
+            RustBertError::InvalidConfigurationError(message) if message.ends_with("not by BART")
+        ));
+    }
+
+    #[test]
+    fn layer_placement_deserialization() {
+        let layer_placement: HashMap<String, LayerPlacement> = serde_json::from_str(
+            r#"{"transformer.wte": {"device": "cpu", "kind": "float32"}, "transformer.h": {"kind": "half"}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            layer_placement["transformer.wte"],
+            LayerPlacement {
+                device: Some(Device::Cpu),
+                kind: Some(Kind::Float),
+            }
+        );
+        assert_eq!(
+            layer_placement["transformer.h"],
+            LayerPlacement {
+                device: None,
+                kind: Some(Kind::Half),
+            }
+        );
+    }
+}
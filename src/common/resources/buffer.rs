@@ -0,0 +1,133 @@
+use crate::common::error::RustBertError;
+use crate::resources::ResourceProvider;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+static WRITE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// # In-memory resource
+/// Resource holding the content of a file in memory, for example a fixture generated by a test or
+/// a model embedded in the binary with `include_bytes!`. The models and tokenizers load their
+/// resources from files: the content is written to a file of the temporary directory the first
+/// time its path is requested. The file is named after a hash of the content, so that resources
+/// with identical content share the same file, and is not deleted when the resource is dropped.
+pub struct BufferResource {
+    file_name: String,
+    data: Arc<[u8]>,
+    local_path: Mutex<Option<PathBuf>>,
+}
+
+impl BufferResource {
+    /// Creates a new `BufferResource` from its content.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_name` - `&str` name of the file the content is written to. Some loaders rely on the extension (e.g. `.json` or `.ot`)
+    /// * `data` - content of the resource
+    ///
+    /// # Returns
+    ///
+    /// * `BufferResource` BufferResource object
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use rust_bert::resources::{BufferResource, ResourceProvider};
+    /// let config_resource = BufferResource::new("config.json", br#"{"vocab_size": 30522}"#.to_vec());
+    /// let config_path = config_resource.get_local_path();
+    /// ```
+    pub fn new(file_name: &str, data: impl Into<Arc<[u8]>>) -> BufferResource {
+        BufferResource {
+            file_name: file_name.to_string(),
+            data: data.into(),
+            local_path: Mutex::new(None),
+        }
+    }
+
+    /// Returns the content of the resource
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl ResourceProvider for BufferResource {
+    /// Gets the path of the file holding the content of the resource, writing the file if needed.
+    ///
+    /// # Returns
+    ///
+    /// * `PathBuf` pointing to the resource file
+    fn get_local_path(&self) -> Result<PathBuf, RustBertError> {
+        let mut local_path = self.local_path.lock().unwrap();
+        if let Some(path) = local_path.as_ref() {
+            if path.exists() {
+                return Ok(path.clone());
+            }
+        }
+        let path = write_buffer(&std::env::temp_dir(), &self.file_name, &self.data)?;
+        *local_path = Some(path.clone());
+        Ok(path)
+    }
+}
+
+/// Writes the buffer to `root/rustbert-buffers/{content hash}/{file_name}`, unless the file already
+/// exists. The content is written to a temporary file that is then renamed, so that concurrent
+/// writers never expose a partially written file.
+fn write_buffer(root: &Path, file_name: &str, data: &[u8]) -> Result<PathBuf, RustBertError> {
+    let file_name = Path::new(file_name)
+        .file_name()
+        .filter(|name| name.len() == file_name.len())
+        .ok_or_else(|| {
+            RustBertError::ValueError(format!(
+                "Invalid file name for a buffer resource: {}",
+                file_name
+            ))
+        })?;
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    let directory = root
+        .join("rustbert-buffers")
+        .join(format!("{:016x}", hasher.finish()));
+    let path = directory.join(file_name);
+    if fs::metadata(&path).map_or(false, |metadata| metadata.len() == data.len() as u64) {
+        return Ok(path);
+    }
+
+    fs::create_dir_all(&directory)?;
+    let temporary_path = directory.join(format!(
+        ".{}.{}.{}",
+        file_name.to_string_lossy(),
+        std::process::id(),
+        WRITE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&temporary_path, data)?;
+    fs::rename(&temporary_path, &path)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn buffer_written_once_per_content() -> anyhow::Result<()> {
+        let root = tempfile::tempdir()?;
+        let path = write_buffer(root.path(), "vocab.txt", b"[PAD]\n[UNK]")?;
+        assert_eq!(path.file_name().unwrap(), "vocab.txt");
+        assert_eq!(fs::read(&path)?, b"[PAD]\n[UNK]");
+        assert_eq!(
+            write_buffer(root.path(), "vocab.txt", b"[PAD]\n[UNK]")?,
+            path
+        );
+
+        let other_path = write_buffer(root.path(), "vocab.txt", b"[PAD]")?;
+        assert_ne!(other_path, path);
+        assert_eq!(fs::read(&other_path)?, b"[PAD]");
+
+        assert!(write_buffer(root.path(), "../vocab.txt", b"[PAD]").is_err());
+        Ok(())
+    }
+}
@@ -1,9 +1,10 @@
 use crate::common::error::RustBertError;
-use crate::resources::ResourceProvider;
+use crate::resources::{ResourceDefinition, ResourceProvider};
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
 /// # Local resource
-#[derive(PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct LocalResource {
     /// Local path for the resource
     pub local_path: PathBuf,
@@ -29,6 +30,10 @@ impl ResourceProvider for LocalResource {
     fn get_local_path(&self) -> Result<PathBuf, RustBertError> {
         Ok(self.local_path.clone())
     }
+
+    fn definition(&self) -> Option<ResourceDefinition> {
+        Some(ResourceDefinition::Local(self.clone()))
+    }
 }
 
 impl From<PathBuf> for LocalResource {
@@ -8,19 +8,28 @@
 //! - (optional) merges files for BPE-based tokenizers
 //!
 //! These are expected in the pipelines configurations or are used as utilities to reference to the
-//! resource location. Two types of resources are pre-defined:
+//! resource location. Three types of resources are pre-defined:
 //! - LocalResource: points to a local file
 //! - RemoteResource: points to a remote file via a URL
+//! - BufferResource: holds the content of a file in memory (e.g. test fixtures or models embedded in the binary)
 //!
-//! For both types of resources, the local location of the file can be retrieved using
+//! For all types of resources, the local location of the file can be retrieved using
 //! `get_local_path`, allowing to reference the resource file location regardless if it is a remote
 //! or local resource. Default implementations for a number of `RemoteResources` are available as
-//! pre-trained models in each model module.
+//! pre-trained models in each model module. Other sources of resources can be supported by
+//! implementing the `ResourceProvider` trait.
+//!
+//! For air-gapped deployments, the cache of the remote resources can be bundled with the
+//! application (see `RUSTBERT_CACHE`): setting the `RUSTBERT_OFFLINE` environment variable to `1`
+//! or `true` resolves the remote resources from the cache only, without any network access.
 
+mod buffer;
 mod local;
 
 use crate::common::error::RustBertError;
+pub use buffer::BufferResource;
 pub use local::LocalResource;
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
 /// # Resource Trait that can provide the location of the model, configuration or vocabulary resources
@@ -42,6 +51,50 @@ pub trait ResourceProvider {
     /// let config_path = config_resource.get_local_path();
     /// ```
     fn get_local_path(&self) -> Result<PathBuf, RustBertError>;
+
+    /// Provides a serializable definition of the resource, used to serialize pipeline configurations.
+    /// Returns `None` for resources that cannot be described in a configuration file (default).
+    fn definition(&self) -> Option<ResourceDefinition> {
+        None
+    }
+}
+
+impl<T: ResourceProvider + ?Sized> ResourceProvider for Box<T> {
+    fn get_local_path(&self) -> Result<PathBuf, RustBertError> {
+        T::get_local_path(self)
+    }
+
+    fn definition(&self) -> Option<ResourceDefinition> {
+        T::definition(self)
+    }
+}
+
+/// # Serializable resource definition
+/// Representation of a resource in serialized pipeline configurations, for example
+/// `{"local": {"local_path": "path/to/model.ot"}}` or
+/// `{"remote": {"url": "https://...", "cache_subdir": "bert-ner/model"}}` in JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResourceDefinition {
+    /// Local resource
+    Local(LocalResource),
+    /// Remote resource
+    #[cfg(feature = "remote")]
+    Remote(RemoteResource),
+}
+
+impl ResourceProvider for ResourceDefinition {
+    fn get_local_path(&self) -> Result<PathBuf, RustBertError> {
+        match self {
+            ResourceDefinition::Local(resource) => resource.get_local_path(),
+            #[cfg(feature = "remote")]
+            ResourceDefinition::Remote(resource) => resource.get_local_path(),
+        }
+    }
+
+    fn definition(&self) -> Option<ResourceDefinition> {
+        Some(self.clone())
+    }
 }
 
 #[cfg(feature = "remote")]
@@ -1,12 +1,14 @@
 use super::*;
 use crate::common::error::RustBertError;
+use crate::common::metrics;
 use cached_path::{Cache, Options, ProgressBar};
 use dirs::cache_dir;
 use lazy_static::lazy_static;
 use std::path::PathBuf;
+use std::time::SystemTime;
 
 /// # Remote resource that will be downloaded and cached locally on demand
-#[derive(PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct RemoteResource {
     /// Remote path/url for the resource
     pub url: String,
@@ -89,10 +91,22 @@ impl ResourceProvider for RemoteResource {
     /// let config_path = config_resource.get_local_path();
     /// ```
     fn get_local_path(&self) -> Result<PathBuf, RustBertError> {
+        let start_time = SystemTime::now();
         let cached_path = CACHE
             .cached_path_with_options(&self.url, &Options::default().subdir(&self.cache_subdir))?;
+        if let Some(recorder) = metrics::recorder() {
+            // The resource was not downloaded if the cached file was last modified before the lookup
+            let hit = std::fs::metadata(&cached_path)
+                .and_then(|metadata| metadata.modified())
+                .map_or(false, |modified| modified < start_time);
+            recorder.record_cache_lookup("resources", hit);
+        }
         Ok(cached_path)
     }
+
+    fn definition(&self) -> Option<ResourceDefinition> {
+        Some(ResourceDefinition::Remote(self.clone()))
+    }
 }
 
 lazy_static! {
@@ -101,12 +115,21 @@ lazy_static! {
 /// If the environment variable `RUSTBERT_CACHE` is set, will save the cache model files at that
 /// location. Otherwise defaults to `$XDG_CACHE_HOME/.rustbert`, or corresponding user cache for
 /// the current system.
+/// If the environment variable `RUSTBERT_OFFLINE` is set to `1` or `true`, resources are only
+/// resolved from the cache and are never downloaded: a resource missing from the cache is an error.
     pub static ref CACHE: Cache = Cache::builder()
         .dir(_get_cache_directory())
         .progress_bar(Some(ProgressBar::Light))
+        .offline(_is_offline())
         .build().unwrap();
 }
 
+fn _is_offline() -> bool {
+    std::env::var("RUSTBERT_OFFLINE").map_or(false, |value| {
+        value == "1" || value.eq_ignore_ascii_case("true")
+    })
+}
+
 fn _get_cache_directory() -> PathBuf {
     match std::env::var("RUSTBERT_CACHE") {
         Ok(value) => PathBuf::from(value),
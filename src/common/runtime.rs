@@ -0,0 +1,183 @@
+//! # Runtime configuration
+//! Controls the thread pools used by the pipelines of the process:
+//! - the libtorch intra-op pool, parallelizing individual operations (e.g. matrix multiplications)
+//! - the libtorch inter-op pool, running independent operations concurrently
+//! - the global rayon pool, used by the tokenizers to encode batches of inputs
+//!
+//! By default, each pool uses as many threads as there are cores. When several pipelines run
+//! concurrently in one process (e.g. one per request handler), the pools oversubscribe the cores
+//! and the throughput drops: the threads should then be split between the pipelines, for example
+//! with `RuntimeConfig::for_concurrent_pipelines`.
+
+use crate::RustBertError;
+use serde::{Deserialize, Serialize};
+use std::panic;
+use std::sync::Once;
+
+static APPLY_RUNTIME_CONFIG: Once = Once::new();
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// # Thread pools configuration
+/// Settings left to `None` keep the default of the corresponding pool. The configuration is
+/// process-wide and can only be applied once, at the start of the process, before any pipeline is
+/// loaded: libtorch does not allow changing the number of inter-op threads once inter-op work has
+/// started, and the global rayon pool is initialized by the first tokenization of a batch.
+pub struct RuntimeConfig {
+    /// Number of threads used by libtorch to parallelize an operation (intra-op parallelism)
+    pub intra_op_threads: Option<usize>,
+    /// Number of threads used by libtorch to run independent operations concurrently (inter-op parallelism)
+    pub inter_op_threads: Option<usize>,
+    /// Number of threads of the global rayon pool used by the tokenizers
+    pub tokenizer_threads: Option<usize>,
+}
+
+impl RuntimeConfig {
+    /// Creates a configuration splitting the available cores between `num_pipelines` pipelines
+    /// running concurrently: each pipeline gets an equal share of the cores for its intra-op
+    /// parallelism (at least one thread), libtorch uses a single inter-op thread and the
+    /// tokenizers share a pool with one thread per pipeline.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_pipelines` - number of pipelines running concurrently in the process
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::RuntimeConfig;
+    ///
+    /// RuntimeConfig::for_concurrent_pipelines(4).apply()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn for_concurrent_pipelines(num_pipelines: usize) -> RuntimeConfig {
+        let num_cores = std::thread::available_parallelism()
+            .map(|num_cores| num_cores.get())
+            .unwrap_or(1);
+        RuntimeConfig::split_cores(num_cores, num_pipelines)
+    }
+
+    fn split_cores(num_cores: usize, num_pipelines: usize) -> RuntimeConfig {
+        let num_pipelines = num_pipelines.max(1);
+        RuntimeConfig {
+            intra_op_threads: Some((num_cores / num_pipelines).max(1)),
+            inter_op_threads: Some(1),
+            tokenizer_threads: Some(num_pipelines.min(num_cores)),
+        }
+    }
+
+    /// Returns the current configuration of the thread pools
+    pub fn current() -> RuntimeConfig {
+        RuntimeConfig {
+            intra_op_threads: Some(tch::get_num_threads() as usize),
+            inter_op_threads: Some(tch::get_num_interop_threads() as usize),
+            tokenizer_threads: Some(rayon::current_num_threads()),
+        }
+    }
+
+    fn validate(&self) -> Result<(), RustBertError> {
+        for (name, num_threads) in [
+            ("intra_op_threads", self.intra_op_threads),
+            ("inter_op_threads", self.inter_op_threads),
+            ("tokenizer_threads", self.tokenizer_threads),
+        ] {
+            if num_threads == Some(0) {
+                return Err(RustBertError::InvalidConfigurationError(format!(
+                    "`{}` must be strictly positive",
+                    name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the configuration to the thread pools of the process. This must be called before
+    /// any pipeline is loaded, and only once per process: later calls return an error without
+    /// changing the thread pools. The libtorch pools are configured even if the rayon pool was
+    /// already initialized.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), RustBertError>` - error if a number of threads is 0, if the configuration was already applied, if libtorch already started inter-op work or if the global rayon pool was already initialized
+    pub fn apply(&self) -> Result<(), RustBertError> {
+        self.validate()?;
+        let mut result = Err(RustBertError::InvalidConfigurationError(
+            "The runtime configuration can only be applied once per process".to_string(),
+        ));
+        APPLY_RUNTIME_CONFIG.call_once(|| result = self.apply_thread_pools());
+        result
+    }
+
+    fn apply_thread_pools(&self) -> Result<(), RustBertError> {
+        if let Some(intra_op_threads) = self.intra_op_threads {
+            tch::set_num_threads(intra_op_threads as i32);
+        }
+        let inter_op_result = match self.inter_op_threads {
+            Some(inter_op_threads)
+                if tch::get_num_interop_threads() as usize != inter_op_threads =>
+            {
+                // libtorch raises an error (turned into a panic by tch) once inter-op work started
+                panic::catch_unwind(|| tch::set_num_interop_threads(inter_op_threads as i32))
+                    .map_err(|_| {
+                        RustBertError::InvalidConfigurationError(
+                            "Could not configure the libtorch inter-op thread pool: inter-op work \
+                            has already started"
+                                .to_string(),
+                        )
+                    })
+            }
+            _ => Ok(()),
+        };
+        let tokenizer_result = match self.tokenizer_threads {
+            Some(tokenizer_threads) => rayon::ThreadPoolBuilder::new()
+                .num_threads(tokenizer_threads)
+                .build_global()
+                .map_err(|error| {
+                    RustBertError::InvalidConfigurationError(format!(
+                        "Could not configure the tokenizers thread pool: {}",
+                        error
+                    ))
+                }),
+            None => Ok(()),
+        };
+        inter_op_result.and(tokenizer_result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn concurrent_pipelines_split() {
+        let config = RuntimeConfig::split_cores(16, 4);
+        assert_eq!(config.intra_op_threads, Some(4));
+        assert_eq!(config.inter_op_threads, Some(1));
+        assert_eq!(config.tokenizer_threads, Some(4));
+
+        let config = RuntimeConfig::split_cores(2, 8);
+        assert_eq!(config.intra_op_threads, Some(1));
+        assert_eq!(config.tokenizer_threads, Some(2));
+
+        assert!(RuntimeConfig::split_cores(8, 0).validate().is_ok());
+        let invalid_config = RuntimeConfig {
+            intra_op_threads: Some(0),
+            ..Default::default()
+        };
+        assert!(invalid_config.validate().is_err());
+    }
+
+    #[test]
+    fn apply_once() {
+        let config = RuntimeConfig {
+            intra_op_threads: Some(tch::get_num_threads() as usize),
+            ..Default::default()
+        };
+        assert!(config.apply().is_ok());
+        assert_eq!(
+            config.apply().unwrap_err().to_string(),
+            "Invalid configuration error: The runtime configuration can only be applied once per process"
+        );
+    }
+}
@@ -0,0 +1,195 @@
+//! Serialization helpers for configuration fields that do not implement `Serialize`/`Deserialize`
+//! (devices, floating point kinds, resources and truncation strategies), used with `#[serde(with = "...")]`.
+
+pub(crate) mod device {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use tch::Device;
+
+    pub fn serialize<S>(device: &Device, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match device {
+            Device::Cpu => serializer.serialize_str("cpu"),
+            Device::Cuda(index) => serializer.serialize_str(&format!("cuda:{}", index)),
+            #[allow(unreachable_patterns)]
+            _ => serializer.serialize_str(&format!("{:?}", device).to_lowercase()),
+        }
+    }
+
+    /// Accepts `cpu`, `cuda`, `cuda:<index>` or `auto` (CUDA if available, CPU otherwise)
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Device, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let device = String::deserialize(deserializer)?;
+        match device.to_lowercase().as_str() {
+            "auto" => Ok(Device::cuda_if_available()),
+            "cpu" => Ok(Device::Cpu),
+            "cuda" => Ok(Device::Cuda(0)),
+            value => value
+                .strip_prefix("cuda:")
+                .and_then(|index| index.parse::<usize>().ok())
+                .map(Device::Cuda)
+                .ok_or_else(|| de::Error::custom(format!("Invalid device: {}", device))),
+        }
+    }
+
+    pub fn default() -> Device {
+        Device::cuda_if_available()
+    }
+}
+
+pub(crate) mod optional_device {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use tch::Device;
+
+    pub fn serialize<S>(device: &Option<Device>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match device {
+            Some(device) => super::device::serialize(device, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Device>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct DeviceValue(#[serde(with = "super::device")] Device);
+
+        Ok(Option::<DeviceValue>::deserialize(deserializer)?.map(|value| value.0))
+    }
+}
+
+pub(crate) mod optional_kind {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use tch::Kind;
+
+    pub fn serialize<S>(kind: &Option<Kind>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match kind {
+            Some(kind) => serializer.serialize_str(&format!("{:?}", kind).to_lowercase()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    /// Accepts the floating point kinds `half` (or `float16`), `bfloat16`, `float` (or `float32`) and `double` (or `float64`)
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Kind>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|kind| match kind.to_lowercase().as_str() {
+                "half" | "float16" => Ok(Kind::Half),
+                "bfloat16" => Ok(Kind::BFloat16),
+                "float" | "float32" => Ok(Kind::Float),
+                "double" | "float64" => Ok(Kind::Double),
+                _ => Err(de::Error::custom(format!(
+                    "Invalid floating point kind: {}",
+                    kind
+                ))),
+            })
+            .transpose()
+    }
+}
+
+pub(crate) mod truncation_strategy {
+    use rust_tokenizers::tokenizer::TruncationStrategy;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(strategy: &TruncationStrategy, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(match strategy {
+            TruncationStrategy::LongestFirst => "longest_first",
+            TruncationStrategy::OnlyFirst => "only_first",
+            TruncationStrategy::OnlySecond => "only_second",
+            TruncationStrategy::DoNotTruncate => "do_not_truncate",
+        })
+    }
+
+    /// Accepts `longest_first`, `only_first`, `only_second` or `do_not_truncate`
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<TruncationStrategy, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let strategy = String::deserialize(deserializer)?;
+        match strategy.to_lowercase().as_str() {
+            "longest_first" => Ok(TruncationStrategy::LongestFirst),
+            "only_first" => Ok(TruncationStrategy::OnlyFirst),
+            "only_second" => Ok(TruncationStrategy::OnlySecond),
+            "do_not_truncate" => Ok(TruncationStrategy::DoNotTruncate),
+            _ => Err(de::Error::custom(format!(
+                "Invalid truncation strategy: {}",
+                strategy
+            ))),
+        }
+    }
+
+    pub fn default() -> TruncationStrategy {
+        TruncationStrategy::LongestFirst
+    }
+}
+
+pub(crate) mod resource {
+    use crate::resources::{ResourceDefinition, ResourceProvider};
+    use serde::{ser, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<R, S>(resource: &R, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        R: ResourceProvider + ?Sized,
+        S: Serializer,
+    {
+        resource
+            .definition()
+            .ok_or_else(|| ser::Error::custom("Resource cannot be serialized"))?
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(
+        deserializer: D,
+    ) -> Result<Box<dyn ResourceProvider + Send>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(Box::new(ResourceDefinition::deserialize(deserializer)?))
+    }
+}
+
+pub(crate) mod optional_resource {
+    use crate::resources::{ResourceDefinition, ResourceProvider};
+    use serde::{ser, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<R, S>(resource: &Option<R>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        R: ResourceProvider,
+        S: Serializer,
+    {
+        resource
+            .as_ref()
+            .map(|resource| {
+                resource
+                    .definition()
+                    .ok_or_else(|| ser::Error::custom("Resource cannot be serialized"))
+            })
+            .transpose()?
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(
+        deserializer: D,
+    ) -> Result<Option<Box<dyn ResourceProvider + Send>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(Option::<ResourceDefinition>::deserialize(deserializer)?
+            .map(|resource| Box::new(resource) as Box<dyn ResourceProvider + Send>))
+    }
+}
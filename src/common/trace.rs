@@ -0,0 +1,20 @@
+//! Instrumentation helpers creating `tracing` spans when the `tracing` feature is enabled. The
+//! span fields are not evaluated when the feature is disabled.
+
+/// Placeholder returned by `trace_span!` when the `tracing` feature is disabled
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct DisabledSpan;
+
+/// Enters a new span with the given level (`INFO`, `DEBUG`, `TRACE`...), name and fields, and
+/// returns a guard exiting the span when dropped.
+macro_rules! trace_span {
+    ($level:ident, $name:expr $(, $($fields:tt)+)?) => {{
+        #[cfg(feature = "tracing")]
+        let span = tracing::span!(tracing::Level::$level, $name $(, $($fields)+)?).entered();
+        #[cfg(not(feature = "tracing"))]
+        let span = $crate::common::trace::DisabledSpan;
+        span
+    }};
+}
+
+pub(crate) use trace_span;
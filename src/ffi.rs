@@ -0,0 +1,271 @@
+//! # C foreign function interface
+//! Exposes the pipelines through `extern "C"` functions, allowing to embed the library in C, C++,
+//! Go or Java applications (enabled with the `ffi` feature). Pipelines are created from a task name
+//! and a JSON model specification (see [`ModelSpec`]) and exchange UTF-8 JSON strings with the
+//! caller (see [`Pipeline::predict_json`] for the input and output formats of each task).
+//!
+//! All strings returned by the library are owned by the caller and must be released with
+//! `rust_bert_string_free`. Pipelines must be released with `rust_bert_pipeline_free`. Functions
+//! return a null pointer on failure, and the error message for the current thread can be retrieved
+//! with `rust_bert_last_error`.
+//!
+//! The library can be compiled as a shared library with
+//! `cargo rustc --release --lib --features ffi --crate-type cdylib`. The corresponding C declarations are:
+//!
+//! ```c
+//! typedef struct RustBertPipeline RustBertPipeline;
+//!
+//! RustBertPipeline *rust_bert_pipeline_new(const char *task, const char *model_spec_json);
+//! char *rust_bert_pipeline_predict(const RustBertPipeline *pipeline, const char *input_json);
+//! void rust_bert_pipeline_free(RustBertPipeline *pipeline);
+//! void rust_bert_string_free(char *string);
+//! const char *rust_bert_last_error(void);
+//! ```
+//!
+//! Example of model specification for a sentiment analysis pipeline (task `"Sentiment"`):
+//!
+//! ```json
+//! {
+//!   "model_type": "DistilBert",
+//!   "model_resource": {"local": {"local_path": "path/to/rust_model.ot"}},
+//!   "config_resource": {"local": {"local_path": "path/to/config.json"}},
+//!   "vocab_resource": {"local": {"local_path": "path/to/vocab.txt"}},
+//!   "lower_case": true,
+//!   "device": "cpu"
+//! }
+//! ```
+
+use crate::pipelines::registry::{ModelSpec, Pipeline, TaskType};
+use crate::RustBertError;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+/// Opaque handle to a pipeline created through the C interface
+pub struct RustBertPipeline {
+    pipeline: Pipeline,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', ""))
+        .unwrap_or_else(|_| CString::new("Invalid error message").unwrap());
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+/// Runs `f`, converting errors and panics to a null pointer and recording the error message
+fn wrap_ffi<T, F>(f: F) -> *mut T
+where
+    F: FnOnce() -> Result<*mut T, RustBertError>,
+{
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(error)) => {
+            set_last_error(error.to_string());
+            ptr::null_mut()
+        }
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "Unknown panic".to_string());
+            set_last_error(format!("Panic: {}", message));
+            ptr::null_mut()
+        }
+    }
+}
+
+unsafe fn read_str<'a>(value: *const c_char, name: &str) -> Result<&'a str, RustBertError> {
+    if value.is_null() {
+        return Err(RustBertError::ValueError(format!(
+            "`{}` is a null pointer",
+            name
+        )));
+    }
+    CStr::from_ptr(value)
+        .to_str()
+        .map_err(|_| RustBertError::ValueError(format!("`{}` is not valid UTF-8", name)))
+}
+
+/// Creates a pipeline for a task (e.g. `"Sentiment"`, `"NER"`, `"Summarization"`) from a JSON
+/// model specification. Returns a null pointer on failure.
+///
+/// # Safety
+///
+/// `task` and `model_spec_json` must be valid null-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn rust_bert_pipeline_new(
+    task: *const c_char,
+    model_spec_json: *const c_char,
+) -> *mut RustBertPipeline {
+    wrap_ffi(|| {
+        let task = read_str(task, "task")?;
+        let task: TaskType = serde_json::from_value(Value::String(task.to_string()))?;
+        let model_spec: ModelSpec =
+            serde_json::from_str(read_str(model_spec_json, "model_spec_json")?)?;
+        let pipeline = Pipeline::new(task, model_spec)?;
+        Ok(Box::into_raw(Box::new(RustBertPipeline { pipeline })))
+    })
+}
+
+/// Runs a pipeline on JSON inputs and returns its outputs as a JSON string, to be released with
+/// `rust_bert_string_free`. Returns a null pointer on failure.
+///
+/// # Safety
+///
+/// `pipeline` must be a pointer returned by `rust_bert_pipeline_new` that has not been released,
+/// and `input_json` a valid null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rust_bert_pipeline_predict(
+    pipeline: *const RustBertPipeline,
+    input_json: *const c_char,
+) -> *mut c_char {
+    wrap_ffi(|| {
+        let pipeline = pipeline
+            .as_ref()
+            .ok_or_else(|| RustBertError::ValueError("`pipeline` is a null pointer".to_string()))?;
+        let input: Value = serde_json::from_str(read_str(input_json, "input_json")?)?;
+        let output = pipeline.pipeline.predict_json(&input)?.to_string();
+        Ok(CString::new(output)
+            .map_err(|error| RustBertError::ValueError(error.to_string()))?
+            .into_raw())
+    })
+}
+
+/// Releases a pipeline created by `rust_bert_pipeline_new`. Null pointers are ignored.
+///
+/// # Safety
+///
+/// `pipeline` must be a pointer returned by `rust_bert_pipeline_new` that has not been released.
+#[no_mangle]
+pub unsafe extern "C" fn rust_bert_pipeline_free(pipeline: *mut RustBertPipeline) {
+    if !pipeline.is_null() {
+        drop(Box::from_raw(pipeline));
+    }
+}
+
+/// Releases a string returned by the library. Null pointers are ignored.
+///
+/// # Safety
+///
+/// `string` must be a pointer returned by the library that has not been released.
+#[no_mangle]
+pub unsafe extern "C" fn rust_bert_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// Returns the message of the last error that occurred on the current thread, or a null pointer
+/// if no error occurred. The string is owned by the library and valid until the next failing call.
+#[no_mangle]
+pub extern "C" fn rust_bert_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn last_error() -> Option<String> {
+        let message = rust_bert_last_error();
+        if message.is_null() {
+            None
+        } else {
+            Some(
+                unsafe { CStr::from_ptr(message) }
+                    .to_str()
+                    .unwrap()
+                    .to_string(),
+            )
+        }
+    }
+
+    #[test]
+    fn null_pointers_are_reported() {
+        let model_spec_json = CString::new("{}").unwrap();
+        let pipeline = unsafe { rust_bert_pipeline_new(ptr::null(), model_spec_json.as_ptr()) };
+        assert!(pipeline.is_null());
+        assert_eq!(
+            last_error().unwrap(),
+            "Value error: `task` is a null pointer"
+        );
+
+        let task = CString::new("Sentiment").unwrap();
+        let pipeline = unsafe { rust_bert_pipeline_new(task.as_ptr(), ptr::null()) };
+        assert!(pipeline.is_null());
+        assert_eq!(
+            last_error().unwrap(),
+            "Value error: `model_spec_json` is a null pointer"
+        );
+
+        let input_json = CString::new("[]").unwrap();
+        let output = unsafe { rust_bert_pipeline_predict(ptr::null(), input_json.as_ptr()) };
+        assert!(output.is_null());
+        assert_eq!(
+            last_error().unwrap(),
+            "Value error: `pipeline` is a null pointer"
+        );
+
+        //    Releasing null pointers is a no-op
+        unsafe {
+            rust_bert_pipeline_free(ptr::null_mut());
+            rust_bert_string_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn invalid_utf8_is_reported() {
+        let task = CString::new(vec![0xF0, 0x28, 0x8C, 0x28]).unwrap();
+        let model_spec_json = CString::new("{}").unwrap();
+        let pipeline = unsafe { rust_bert_pipeline_new(task.as_ptr(), model_spec_json.as_ptr()) };
+        assert!(pipeline.is_null());
+        assert_eq!(
+            last_error().unwrap(),
+            "Value error: `task` is not valid UTF-8"
+        );
+    }
+
+    #[test]
+    fn last_error_round_trip() {
+        //    No error has occurred yet on this thread
+        assert!(last_error().is_none());
+
+        let task = CString::new("NotATask").unwrap();
+        let model_spec_json = CString::new("{}").unwrap();
+        let pipeline = unsafe { rust_bert_pipeline_new(task.as_ptr(), model_spec_json.as_ptr()) };
+        assert!(pipeline.is_null());
+        let unknown_task_error = last_error().unwrap();
+        assert!(unknown_task_error.contains("NotATask"));
+
+        //    The message is replaced by the next failing call
+        let task = CString::new("Sentiment").unwrap();
+        let model_spec_json = CString::new("{\"model_type\": ").unwrap();
+        let pipeline = unsafe { rust_bert_pipeline_new(task.as_ptr(), model_spec_json.as_ptr()) };
+        assert!(pipeline.is_null());
+        let invalid_json_error = last_error().unwrap();
+        assert!(invalid_json_error.starts_with("Value error: "));
+        assert_ne!(invalid_json_error, unknown_task_error);
+
+        //    Panics are caught and reported
+        let result = wrap_ffi::<RustBertPipeline, _>(|| panic!("failed to load"));
+        assert!(result.is_null());
+        assert_eq!(last_error().unwrap(), "Panic: failed to load");
+
+        //    Messages are stored without interior null bytes
+        set_last_error("first\0second".to_string());
+        assert_eq!(last_error().unwrap(), "firstsecond");
+    }
+}
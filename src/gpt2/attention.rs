@@ -108,6 +108,11 @@ impl Attention {
         }
     }
 
+    /// Weights of the query, key and value projection, on the device and precision of the layer
+    pub(crate) fn weight(&self) -> &Tensor {
+        &self.c_attn.weight
+    }
+
     fn split_heads(&self, x: &Tensor, k: bool) -> Tensor {
         let x = x.view((x.size()[0], -1, self.n_head, self.dim_per_head));
         if k {
@@ -15,6 +15,8 @@
 use crate::common::activations::Activation;
 use crate::common::dropout::Dropout;
 use crate::common::embeddings::process_ids_embeddings_pair;
+use crate::common::loading::{check_device, load_weights};
+use crate::common::placement::{apply_layer_placement, to_layer_placement};
 use crate::gpt2::transformer::Block;
 use crate::pipelines::common::{ModelType, TokenizerOption};
 use crate::pipelines::generation_utils::private_generation_utils::{
@@ -393,8 +395,10 @@ impl Gpt2Model {
         input_embeds: Option<&Tensor>,
         train: bool,
     ) -> Result<Gpt2ModelOutput, RustBertError> {
+        // The embeddings may be placed on another device than the inputs (see `LayerPlacement`)
+        let input_ids = input_ids.map(|input_ids| input_ids.to_device(self.wte.ws.device()));
         let (calc_input_embeddings, input_size, _) =
-            process_ids_embeddings_pair(input_ids, input_embeds, &self.wte)?;
+            process_ids_embeddings_pair(input_ids.as_ref(), input_embeds, &self.wte)?;
         let input_embeddings =
             input_embeds.unwrap_or_else(|| calc_input_embeddings.as_ref().unwrap());
 
@@ -443,9 +447,14 @@ impl Gpt2Model {
             attention_mask.to_kind(input_embeddings.kind())
         });
 
-        let position_embeds = position_ids.apply(&self.wpe);
+        let position_embeds = to_layer_placement(
+            &position_ids
+                .to_device(self.wpe.ws.device())
+                .apply(&self.wpe),
+            input_embeddings,
+        );
         let token_type_embeds = match token_type_ids {
-            Some(value) => value.apply(&self.wte),
+            Some(value) => value.to_device(self.wte.ws.device()).apply(&self.wte),
             None => Tensor::zeros_like(&position_embeds),
         };
         let mut hidden_state: Tensor =
@@ -480,6 +489,9 @@ impl Gpt2Model {
             };
         }
 
+        if let Some(ln_f_weight) = &self.ln_f.ws {
+            hidden_state = to_layer_placement(&hidden_state, ln_f_weight);
+        }
         Ok(Gpt2ModelOutput {
             output: hidden_state.apply(&self.ln_f),
             cache: all_presents,
@@ -552,6 +564,7 @@ impl LMHeadModel for GPT2LMHeadModel {
     /// * `LMModelOutput` containing:
     ///   - `lm_logits` - `Tensor` of shape (*batch size*, *sequence_length*, *vocab_size*) representing the logits for each vocab item and position
     ///   - `cache` - `Gpt2Cache` made of `Option<Vec<Tensor>>` of length *n_layer* containing the past keys and values of each layer of shape (*2*, *batch size*, *number of heads*, *past_sequence_length*, *hidden size per head*)
+    ///   - `all_hidden_states` - `Option<Vec<Tensor>>` of length *n_layer* with shape (*batch size*, *sequence_length*, *hidden_size*) if the model is configured to output hidden states
     ///
     /// # Example
     ///
@@ -642,14 +655,36 @@ impl LMHeadModel for GPT2LMHeadModel {
             }
         }?;
 
-        let lm_logits = base_model_output
-            .output
-            .linear::<Tensor>(&self.transformer.wte.ws, None);
+        // The logits are returned on the device of the inputs, the language modeling head being
+        // tied to the embeddings that may be placed on another device (see `LayerPlacement`)
+        let output_device = input_ids
+            .or(input_embeds)
+            .map(|input| input.device())
+            .unwrap_or_else(|| base_model_output.output.device());
+        let lm_logits = to_layer_placement(&base_model_output.output, &self.transformer.wte.ws)
+            .linear::<Tensor>(&self.transformer.wte.ws, None)
+            .to_device(output_device);
         Ok(LMModelOutput {
             lm_logits,
             cache: Cache::GPT2Cache(base_model_output.cache),
+            all_hidden_states: base_model_output.all_hidden_states,
         })
     }
+
+    fn project_hidden_states(&self, hidden_states: &Tensor) -> Option<Tensor> {
+        let mut projected_states = hidden_states.shallow_clone();
+        if let Some(ln_f_weight) = &self.transformer.ln_f.ws {
+            projected_states = to_layer_placement(&projected_states, ln_f_weight);
+        }
+        Some(
+            to_layer_placement(
+                &projected_states.apply(&self.transformer.ln_f),
+                &self.transformer.wte.ws,
+            )
+            .linear::<Tensor>(&self.transformer.wte.ws, None)
+            .to_device(hidden_states.device()),
+        )
+    }
 }
 
 /// Container for the GPT2 model output.
@@ -738,12 +773,23 @@ impl GPT2Generator {
         let weights_path = generate_config.model_resource.get_local_path()?;
         let device = generate_config.device;
 
-        generate_config.validate();
+        generate_config.validate()?;
+        check_device(device)?;
         let mut var_store = nn::VarStore::new(device);
 
-        let config = Gpt2Config::from_file(config_path);
+        let mut config = Gpt2Config::try_from_file(config_path)?;
+        if let Some(dola_layers) = &generate_config.dola_layers {
+            if dola_layers.iter().any(|layer| *layer >= config.n_layer) {
+                return Err(RustBertError::InvalidConfigurationError(format!(
+                    "dola_layers must be lower than the number of layers of the model ({})",
+                    config.n_layer
+                )));
+            }
+            config.output_hidden_states = Some(true);
+        }
         let model = GPT2LMHeadModel::new(&var_store.root(), &config);
-        var_store.load(weights_path)?;
+        load_weights(&mut var_store, weights_path)?;
+        apply_layer_placement(&var_store, &generate_config.layer_placement)?;
 
         let bos_token_id = tokenizer.get_bos_id();
         let eos_token_ids = tokenizer.get_eos_id().map(|id| vec![id]);
@@ -797,6 +843,21 @@ impl PrivateLanguageGenerator<GPT2LMHeadModel, Gpt2Vocab, Gpt2Tokenizer> for GPT
     fn is_encoder_decoder(&self) -> bool {
         self.is_encoder_decoder
     }
+    fn supports_lazy_beam_expansion(&self) -> bool {
+        true
+    }
+    fn supports_layer_contrastive_decoding(&self) -> bool {
+        true
+    }
+    fn supports_chunked_prefill(&self) -> bool {
+        true
+    }
+    fn supports_attention_sinks(&self) -> bool {
+        true
+    }
+    fn supports_layer_placement(&self) -> bool {
+        true
+    }
     fn get_vocab_size(&self) -> i64 {
         self.vocab_size
     }
@@ -807,6 +868,32 @@ impl PrivateLanguageGenerator<GPT2LMHeadModel, Gpt2Vocab, Gpt2Tokenizer> for GPT
         self.max_position_embeddings
     }
 
+    fn prepare_prefill_chunk_inputs<'a>(
+        &self,
+        input_ids: &Tensor,
+        attention_mask: &Tensor,
+        past: Cache,
+        start: i64,
+        end: i64,
+    ) -> PreparedInput<'a> {
+        let past = match past {
+            Cache::GPT2Cache(past) => past,
+            Cache::None => None,
+            _ => panic!("Cache type incompatible with GPT2"),
+        };
+        let attention_mask = attention_mask.slice(1, 0, end, 1);
+        let position_ids = (attention_mask.totype(Kind::Int64).cumsum(-1, Kind::Int64) - 1)
+            .masked_fill(&attention_mask.eq(0), 1);
+        PreparedInput {
+            prepared_input: Some(input_ids.slice(1, start, end, 1)),
+            prepared_attention_mask: Some(attention_mask),
+            prepared_encoder_output: None,
+            prepared_decoder_input: None,
+            prepared_position_ids: Some(position_ids.slice(1, start, end, 1)),
+            prepared_past: Cache::GPT2Cache(past),
+        }
+    }
+
     fn prepare_inputs_for_generation<'a>(
         &self,
         input_ids: Tensor,
@@ -861,7 +948,8 @@ impl PrivateLanguageGenerator<GPT2LMHeadModel, Gpt2Vocab, Gpt2Tokenizer> for GPT
             Cache::GPT2Cache(cached_decoder_state) => match cached_decoder_state {
                 Some(value) => {
                     for layer_past in value.iter_mut() {
-                        *layer_past = layer_past.index_select(1, beam_indices);
+                        *layer_past = layer_past
+                            .index_select(1, &beam_indices.to_device(layer_past.device()));
                     }
                     None
                 }
@@ -873,6 +961,29 @@ impl PrivateLanguageGenerator<GPT2LMHeadModel, Gpt2Vocab, Gpt2Tokenizer> for GPT
             }
         }
     }
+
+    fn select_cache_positions(&self, past: &mut Cache, positions: &Tensor) {
+        match past {
+            Cache::GPT2Cache(Some(cached_decoder_state)) => {
+                // Layer caches are stacked keys and values of shape (2, batch size, heads, sequence length, head dim)
+                for layer_past in cached_decoder_state.iter_mut() {
+                    let size = layer_past.size();
+                    let index = positions
+                        .to_device(layer_past.device())
+                        .view((1, size[1], 1, -1, 1))
+                        .expand(
+                            &[size[0], size[1], size[2], positions.size()[1], size[4]],
+                            true,
+                        );
+                    *layer_past = layer_past.gather(3, &index, false);
+                }
+            }
+            Cache::GPT2Cache(None) | Cache::None => {}
+            _ => {
+                panic!("Invalid cache for GPT2 model");
+            }
+        }
+    }
 }
 
 impl LanguageGenerator<GPT2LMHeadModel, Gpt2Vocab, Gpt2Tokenizer> for GPT2Generator {}
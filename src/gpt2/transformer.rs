@@ -14,6 +14,7 @@
 
 use crate::common::activations::{Activation, TensorFunction};
 use crate::common::dropout::Dropout;
+use crate::common::placement::to_layer_placement;
 use crate::gpt2::attention::{Attention, GPTConv1D};
 use crate::gpt2::gpt2_model::Gpt2Config;
 use std::borrow::Borrow;
@@ -97,9 +98,16 @@ impl Block {
         attention_mask: Option<&Tensor>,
         train: bool,
     ) -> (Tensor, Tensor, Option<Tensor>) {
-        let (output, present, attentions) =
-            self.attn
-                .forward_t(&x.apply(&self.ln_1), layer_past, attention_mask, train);
+        // The block may be placed on another device or precision than the previous layer
+        let x = to_layer_placement(x, self.attn.weight());
+        let attention_mask =
+            attention_mask.map(|mask| to_layer_placement(mask, self.attn.weight()));
+        let (output, present, attentions) = self.attn.forward_t(
+            &x.apply(&self.ln_1),
+            layer_past,
+            attention_mask.as_ref(),
+            train,
+        );
         let x = x + output;
         let m = self.mlp.forward_t(&x.apply(&self.ln_2), train);
         let x = x + m;
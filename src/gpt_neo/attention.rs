@@ -43,6 +43,19 @@ impl LayerState {
             .as_ref()
             .map(|value| value.index_select(0, new_indices));
     }
+
+    /// Keeps the cached `positions` (of shape (batch size, number of kept positions)) of each sequence
+    pub(crate) fn select_positions(&mut self, positions: &Tensor) {
+        let select = |cached: &Tensor| {
+            let size = cached.size();
+            let index = positions
+                .view((size[0], 1, -1, 1))
+                .expand(&[size[0], size[1], positions.size()[1], size[3]], true);
+            cached.gather(2, &index, false)
+        };
+        self.prev_key = select(&self.prev_key);
+        self.prev_value = self.prev_value.as_ref().map(select);
+    }
 }
 
 pub struct GptNeoSelfAttention {
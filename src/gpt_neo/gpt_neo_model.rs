@@ -12,6 +12,8 @@
 
 use crate::common::dropout::Dropout;
 use crate::common::embeddings::process_ids_embeddings_pair;
+use crate::common::loading::{check_device, load_weights};
+use crate::common::placement::check_layer_placement_unsupported;
 use crate::gpt_neo::decoder::GptNeoBlock;
 use crate::gpt_neo::LayerState;
 use crate::pipelines::common::{ModelType, TokenizerOption};
@@ -612,6 +614,7 @@ impl LMHeadModel for GptNeoForCausalLM {
         Ok(LMModelOutput {
             lm_logits: base_model_output.lm_logits,
             cache: Cache::GPTNeoCache(base_model_output.next_cache),
+            all_hidden_states: None,
         })
     }
 }
@@ -713,11 +716,13 @@ impl GptNeoGenerator {
         let weights_path = generate_config.model_resource.get_local_path()?;
         let device = generate_config.device;
 
-        generate_config.validate();
+        generate_config.validate()?;
+        check_layer_placement_unsupported(&generate_config.layer_placement, "GPT-Neo")?;
+        check_device(device)?;
         let mut var_store = nn::VarStore::new(device);
-        let config = GptNeoConfig::from_file(config_path);
+        let config = GptNeoConfig::try_from_file(config_path)?;
         let model = GptNeoForCausalLM::new(&var_store.root(), &config)?;
-        var_store.load(weights_path)?;
+        load_weights(&mut var_store, weights_path)?;
 
         let bos_token_id = tokenizer.get_bos_id();
         let eos_token_ids = tokenizer.get_eos_id().map(|id| vec![id]);
@@ -771,6 +776,15 @@ impl PrivateLanguageGenerator<GptNeoForCausalLM, Gpt2Vocab, Gpt2Tokenizer> for G
     fn is_encoder_decoder(&self) -> bool {
         self.is_encoder_decoder
     }
+    fn supports_lazy_beam_expansion(&self) -> bool {
+        true
+    }
+    fn supports_chunked_prefill(&self) -> bool {
+        true
+    }
+    fn supports_attention_sinks(&self) -> bool {
+        true
+    }
     fn get_vocab_size(&self) -> i64 {
         self.vocab_size
     }
@@ -781,6 +795,32 @@ impl PrivateLanguageGenerator<GptNeoForCausalLM, Gpt2Vocab, Gpt2Tokenizer> for G
         self.max_position_embeddings
     }
 
+    fn prepare_prefill_chunk_inputs<'a>(
+        &self,
+        input_ids: &Tensor,
+        attention_mask: &Tensor,
+        past: Cache,
+        start: i64,
+        end: i64,
+    ) -> PreparedInput<'a> {
+        let past = match past {
+            Cache::GPTNeoCache(past) => past,
+            Cache::None => None,
+            _ => panic!("Cache type incompatible with GPT-Neo"),
+        };
+        let attention_mask = attention_mask.slice(1, 0, end, 1);
+        let position_ids = (attention_mask.totype(Kind::Int64).cumsum(-1, Kind::Int64) - 1)
+            .masked_fill(&attention_mask.eq(0), 1);
+        PreparedInput {
+            prepared_input: Some(input_ids.slice(1, start, end, 1)),
+            prepared_attention_mask: Some(attention_mask),
+            prepared_encoder_output: None,
+            prepared_decoder_input: None,
+            prepared_position_ids: Some(position_ids.slice(1, start, end, 1)),
+            prepared_past: Cache::GPTNeoCache(past),
+        }
+    }
+
     fn prepare_inputs_for_generation<'a>(
         &self,
         input_ids: Tensor,
@@ -849,6 +889,20 @@ impl PrivateLanguageGenerator<GptNeoForCausalLM, Gpt2Vocab, Gpt2Tokenizer> for G
             }
         }
     }
+
+    fn select_cache_positions(&self, past: &mut Cache, positions: &Tensor) {
+        match past {
+            Cache::GPTNeoCache(Some(cached_decoder_state)) => {
+                for layer_state in cached_decoder_state.iter_mut().flatten() {
+                    layer_state.select_positions(positions);
+                }
+            }
+            Cache::GPTNeoCache(None) | Cache::None => {}
+            _ => {
+                panic!("Invalid cache for GPT-Neo model");
+            }
+        }
+    }
 }
 
 impl LanguageGenerator<GptNeoForCausalLM, Gpt2Vocab, Gpt2Tokenizer> for GptNeoGenerator {}
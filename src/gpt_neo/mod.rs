@@ -54,7 +54,7 @@
 //!
 //!     let input_context_1 = "It was a very nice and sunny";
 //!     let input_context_2 = "It was a gloom winter night, and";
-//!     let output = model.generate(&[input_context_1, input_context_2], None);
+//!     let output = model.generate(&[input_context_1, input_context_2], None)?;
 //!
 //!     for sentence in output {
 //!         println!("{}", sentence);
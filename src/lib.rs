@@ -83,6 +83,7 @@
 //!
 //! Furthermore, this library relies on a cache folder for downloading pre-trained models.
 //! This cache location defaults to `~/.cache/.rustbert`, but can be changed by setting the `RUSTBERT_CACHE` environment variable. Note that the language models used by this library are in the order of the 100s of MBs to GBs.
+//! For air-gapped environments, the cache folder can be copied from a machine with network access and the `RUSTBERT_OFFLINE` environment variable set to `1`: the models are then loaded from the cache without any download.
 //!
 //! ### Manual installation (recommended)
 //!
@@ -235,7 +236,7 @@
 //! telescope — scheduled for launch in 2021 — and the European Space Agency's 2028 ARIEL program, could reveal more
 //! about exoplanets like K2-18b."];
 //!
-//! let output = model.summarize(&input);
+//! let output = model.summarize(&input)?;
 //! # Ok(())
 //! # }
 //! ```
@@ -271,7 +272,7 @@
 //!
 //! let conversation_id =
 //!     conversation_manager.create("Going to the movies tonight - any suggestions?");
-//! let output = conversation_model.generate_responses(&mut conversation_manager);
+//! let output = conversation_model.generate_responses(&mut conversation_manager)?;
 //! # Ok(())
 //! # }
 //! ```
@@ -302,7 +303,7 @@
 //!
 //! let prefix = None; // Optional prefix to append prompts with, will be excluded from the generated output
 //!
-//! let output = model.generate(&[input_context_1, input_context_2], prefix);
+//! let output = model.generate(&[input_context_1, input_context_2], prefix)?;
 //! # Ok(())
 //! # }
 //! ```
@@ -659,6 +660,7 @@
 //! Examples on how to prepare the date using a native tokenizers Rust library are available in `./examples` for BERT, DistilBERT, RoBERTa, GPT, GPT2 and BART.
 //! Note that when importing models from Pytorch, the convention for parameters naming needs to be aligned with the Rust schema. Loading of the pre-trained weights will fail if any of the model parameters weights cannot be found in the weight files.
 //! If this quality check is to be skipped, an alternative method `load_partial` can be invoked from the variables store.
+//! Checkpoints omitting the language model head tied to the word embeddings (e.g. `lm_head.weight`) can be loaded with `load_weights_with_tied_embeddings`, which ties the missing head to the word embeddings.
 //!
 //! Pretrained models are available on Hugging face's [model hub](https://huggingface.co/models?filter=rust) and can be loaded using `RemoteResources` defined in this library.
 //! A conversion utility script is included in `./utils` to convert Pytorch weights to a set of weights compatible with this library. This script requires Python and `torch` to be set-up, and can be used as follows:
@@ -703,6 +705,8 @@ pub mod deberta;
 pub mod deberta_v2;
 pub mod distilbert;
 pub mod electra;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 pub mod fnet;
 pub mod gpt2;
 pub mod gpt_neo;
@@ -715,11 +719,24 @@ pub mod openai_gpt;
 pub mod pegasus;
 pub mod pipelines;
 pub mod prophetnet;
+#[cfg(feature = "python")]
+pub mod python;
 pub mod reformer;
+#[cfg(feature = "rest")]
+pub mod rest;
 pub mod roberta;
+#[cfg(feature = "serve")]
+pub mod serve;
 pub mod t5;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
 pub mod xlnet;
 
+pub use common::determinism::{deterministic_seed, set_deterministic};
+pub use common::embeddings::{load_weights_with_tied_embeddings, tie_word_embeddings};
 pub use common::error::RustBertError;
+pub use common::metrics;
+pub use common::placement::LayerPlacement;
 pub use common::resources;
+pub use common::runtime::RuntimeConfig;
 pub use common::{Activation, Config};
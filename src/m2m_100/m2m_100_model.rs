@@ -10,20 +10,22 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::common::loading::{check_device, load_weights};
+use crate::common::placement::check_layer_placement_unsupported;
 use crate::m2m_100::decoder::M2M100Decoder;
 use crate::m2m_100::encoder::M2M100Encoder;
 use crate::m2m_100::LayerState;
 use crate::mbart::{MBartConfig, MBartModelOutput};
 use crate::pipelines::common::{ModelType, TokenizerOption};
 use crate::pipelines::generation_utils::private_generation_utils::{
-    PreparedInput, PrivateLanguageGenerator,
+    pad_prompt_ids, PreparedInput, PrivateLanguageGenerator,
 };
 use crate::pipelines::generation_utils::{
     Cache, GenerateConfig, LMHeadModel, LMModelOutput, LanguageGenerator,
 };
 use crate::pipelines::translation::Language;
 use crate::{Config, RustBertError};
-use rust_tokenizers::tokenizer::{M2M100Tokenizer, TruncationStrategy};
+use rust_tokenizers::tokenizer::M2M100Tokenizer;
 use rust_tokenizers::vocab::{M2M100Vocab, Vocab};
 use std::borrow::Borrow;
 use tch::nn::{embedding, EmbeddingConfig};
@@ -557,6 +559,7 @@ impl LMHeadModel for M2M100ForConditionalGeneration {
         Ok(LMModelOutput {
             lm_logits,
             cache: Cache::BARTCache(base_model_output.cache),
+            all_hidden_states: None,
         })
     }
 }
@@ -647,12 +650,14 @@ impl M2M100Generator {
         let weights_path = generate_config.model_resource.get_local_path()?;
         let device = generate_config.device;
 
-        generate_config.validate();
+        generate_config.validate()?;
+        check_layer_placement_unsupported(&generate_config.layer_placement, "M2M100")?;
+        check_device(device)?;
         let mut var_store = nn::VarStore::new(device);
 
-        let config = M2M100Config::from_file(config_path);
+        let config = M2M100Config::try_from_file(config_path)?;
         let model = M2M100ForConditionalGeneration::new(&var_store.root(), &config);
-        var_store.load(weights_path)?;
+        load_weights(&mut var_store, weights_path)?;
 
         let bos_token_id = Some(config.bos_token_id.unwrap_or(0));
         let eos_token_ids = Some(match config.eos_token_id {
@@ -783,24 +788,11 @@ impl PrivateLanguageGenerator<M2M100ForConditionalGeneration, M2M100Vocab, M2M10
         prompt_text: &[S],
         max_len: Option<i64>,
         pad_token_id: Option<i64>,
-    ) -> Tensor
+    ) -> (Tensor, Tensor)
     where
         S: AsRef<str> + Sync,
     {
-        let tokens = self._get_tokenizer().encode_list(
-            prompt_text,
-            max_len
-                .map(|max_len| max_len as usize)
-                .unwrap_or(usize::MAX),
-            &TruncationStrategy::LongestFirst,
-            0,
-        );
-        let token_ids = tokens
-            .into_iter()
-            .map(|tokenized_input| tokenized_input.token_ids)
-            .collect::<Vec<Vec<i64>>>();
-
-        let max_len = token_ids.iter().map(|input| input.len()).max().unwrap();
+        let token_ids = self.tokenize_prompt_text(prompt_text, max_len, true);
 
         let pad_token = match pad_token_id {
             Some(value) => value,
@@ -809,17 +801,7 @@ impl PrivateLanguageGenerator<M2M100ForConditionalGeneration, M2M100Vocab, M2M10
                 .convert_tokens_to_ids(&[M2M100Vocab::unknown_value()])[0],
         };
 
-        let token_ids = token_ids
-            .into_iter()
-            .map(|mut input| {
-                let temp = vec![pad_token; max_len - input.len()];
-                input.extend(temp);
-                input
-            })
-            .map(|tokens| Tensor::of_slice(&tokens).to(self.get_var_store().device()))
-            .collect::<Vec<Tensor>>();
-
-        Tensor::stack(&token_ids, 0)
+        pad_prompt_ids(token_ids, pad_token, false, self.get_var_store().device())
     }
 
     fn reorder_cache(
@@ -12,19 +12,21 @@
 
 use crate::bart::BartModelOutput;
 use crate::common::dropout::Dropout;
+use crate::common::loading::{check_device, load_weights};
+use crate::common::placement::check_layer_placement_unsupported;
 use crate::mbart::decoder::MBartDecoder;
 use crate::mbart::encoder::MBartEncoder;
 use crate::mbart::LayerState;
 use crate::pipelines::common::{ModelType, TokenizerOption};
 use crate::pipelines::generation_utils::private_generation_utils::{
-    PreparedInput, PrivateLanguageGenerator,
+    pad_prompt_ids, PreparedInput, PrivateLanguageGenerator,
 };
 use crate::pipelines::generation_utils::{
     Cache, GenerateConfig, LMHeadModel, LMModelOutput, LanguageGenerator,
 };
 use crate::pipelines::translation::Language;
 use crate::{Activation, Config, RustBertError};
-use rust_tokenizers::tokenizer::{MBart50Tokenizer, TruncationStrategy};
+use rust_tokenizers::tokenizer::MBart50Tokenizer;
 use rust_tokenizers::vocab::{MBart50Vocab, Vocab};
 use serde::{Deserialize, Serialize};
 use std::borrow::Borrow;
@@ -812,6 +814,7 @@ impl LMHeadModel for MBartForConditionalGeneration {
         Ok(LMModelOutput {
             lm_logits,
             cache: Cache::BARTCache(base_model_output.cache),
+            all_hidden_states: None,
         })
     }
 }
@@ -896,12 +899,14 @@ impl MBartGenerator {
         let weights_path = generate_config.model_resource.get_local_path()?;
         let device = generate_config.device;
 
-        generate_config.validate();
+        generate_config.validate()?;
+        check_layer_placement_unsupported(&generate_config.layer_placement, "MBart")?;
+        check_device(device)?;
         let mut var_store = nn::VarStore::new(device);
 
-        let config = MBartConfig::from_file(config_path);
+        let config = MBartConfig::try_from_file(config_path)?;
         let model = MBartForConditionalGeneration::new(&var_store.root(), &config);
-        var_store.load(weights_path)?;
+        load_weights(&mut var_store, weights_path)?;
 
         let bos_token_id = Some(config.bos_token_id.unwrap_or(0));
         let eos_token_ids = Some(match config.eos_token_id {
@@ -1032,24 +1037,11 @@ impl PrivateLanguageGenerator<MBartForConditionalGeneration, MBart50Vocab, MBart
         prompt_text: &[S],
         max_len: Option<i64>,
         pad_token_id: Option<i64>,
-    ) -> Tensor
+    ) -> (Tensor, Tensor)
     where
         S: AsRef<str> + Sync,
     {
-        let tokens = self._get_tokenizer().encode_list(
-            prompt_text,
-            max_len
-                .map(|max_len| max_len as usize)
-                .unwrap_or(usize::MAX),
-            &TruncationStrategy::LongestFirst,
-            0,
-        );
-        let token_ids = tokens
-            .into_iter()
-            .map(|tokenized_input| tokenized_input.token_ids)
-            .collect::<Vec<Vec<i64>>>();
-
-        let max_len = token_ids.iter().map(|input| input.len()).max().unwrap();
+        let token_ids = self.tokenize_prompt_text(prompt_text, max_len, true);
 
         let pad_token = match pad_token_id {
             Some(value) => value,
@@ -1058,17 +1050,7 @@ impl PrivateLanguageGenerator<MBartForConditionalGeneration, MBart50Vocab, MBart
                 .convert_tokens_to_ids(&[MBart50Vocab::unknown_value()])[0],
         };
 
-        let token_ids = token_ids
-            .into_iter()
-            .map(|mut input| {
-                let temp = vec![pad_token; max_len - input.len()];
-                input.extend(temp);
-                input
-            })
-            .map(|tokens| Tensor::of_slice(&tokens).to(self.get_var_store().device()))
-            .collect::<Vec<Tensor>>();
-
-        Tensor::stack(&token_ids, 0)
+        pad_prompt_ids(token_ids, pad_token, false, self.get_var_store().device())
     }
 
     fn reorder_cache(
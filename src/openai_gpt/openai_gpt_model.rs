@@ -13,8 +13,10 @@
 // limitations under the License.
 
 use crate::common::dropout::Dropout;
-use crate::common::embeddings::process_ids_embeddings_pair;
+use crate::common::embeddings::{load_weights_with_tied_embeddings, process_ids_embeddings_pair};
 use crate::common::linear::{linear_no_bias, LinearNoBias};
+use crate::common::loading::check_device;
+use crate::common::placement::check_layer_placement_unsupported;
 use crate::gpt2::Gpt2Config;
 use crate::openai_gpt::transformer::Block;
 use crate::pipelines::common::{ModelType, TokenizerOption};
@@ -413,6 +415,7 @@ impl LMHeadModel for OpenAIGPTLMHeadModel {
         Ok(LMModelOutput {
             lm_logits,
             cache: Cache::None,
+            all_hidden_states: None,
         })
     }
 }
@@ -496,16 +499,18 @@ impl OpenAIGenerator {
         generate_config: GenerateConfig,
         tokenizer: TokenizerOption,
     ) -> Result<OpenAIGenerator, RustBertError> {
-        generate_config.validate();
+        generate_config.validate()?;
+        check_layer_placement_unsupported(&generate_config.layer_placement, "OpenAI GPT")?;
 
         let config_path = generate_config.config_resource.get_local_path()?;
         let weights_path = generate_config.model_resource.get_local_path()?;
         let device = generate_config.device;
 
+        check_device(device)?;
         let mut var_store = nn::VarStore::new(device);
-        let config = Gpt2Config::from_file(config_path);
+        let config = Gpt2Config::try_from_file(config_path)?;
         let model = OpenAIGPTLMHeadModel::new(&var_store.root(), &config);
-        var_store.load(weights_path)?;
+        load_weights_with_tied_embeddings(&mut var_store, weights_path)?;
 
         let bos_token_id = tokenizer.get_bos_id();
         let eos_token_ids = tokenizer.get_eos_id().map(|id| vec![id]);
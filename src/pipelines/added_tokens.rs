@@ -0,0 +1,396 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Added tokens
+//! Additional tokens (e.g. special markers or domain-specific terms) can be registered on the
+//! tokenizer of a pipeline with `with_added_tokens`. These tokens are never split by the tokenizer
+//! and are assigned ids following the vocabulary of the model. The embedding matrix of the model
+//! (and output projections or biases of the same size) is resized accordingly, the embeddings of
+//! the new tokens being initialized with the mean embedding of the existing vocabulary.
+//!
+//! This is supported by the sequence classification, token classification, question answering,
+//! zero-shot classification and masked language pipelines.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::sequence_classification::SequenceClassificationModel;
+//!
+//! let model = SequenceClassificationModel::new(Default::default())?
+//!     .with_added_tokens(&["<product>", "<brand>"])?;
+//! let output = model.predict(&["This <product> from <brand> is great!"]);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::common::embeddings::{get_vocab_size, resize_token_embeddings};
+use crate::pipelines::common::TokenizerOption;
+use crate::RustBertError;
+use regex::Regex;
+use rust_tokenizers::tokenizer::{truncate_sequences, TruncationStrategy};
+use rust_tokenizers::{
+    Mask, Offset, OffsetSize, TokenIdsWithOffsets, TokenIdsWithSpecialTokens, TokenizedInput,
+    TokensWithOffsets,
+};
+use std::collections::HashMap;
+use tch::nn::VarStore;
+
+/// # Tokenizer with additional tokens
+/// Wraps a `TokenizerOption`, splitting the input text on the added tokens before tokenizing the
+/// remaining segments with the wrapped tokenizer.
+pub struct AddedTokensTokenizer {
+    tokenizer: Box<TokenizerOption>,
+    values: HashMap<String, i64>,
+    indices: HashMap<i64, String>,
+    pattern: Option<Regex>,
+}
+
+impl AddedTokensTokenizer {
+    /// Creates a new `AddedTokensTokenizer` without added tokens
+    ///
+    /// # Arguments
+    ///
+    /// * `tokenizer` - `TokenizerOption` used to tokenize the text between added tokens
+    pub fn new(tokenizer: TokenizerOption) -> AddedTokensTokenizer {
+        AddedTokensTokenizer {
+            tokenizer: Box::new(tokenizer),
+            values: HashMap::new(),
+            indices: HashMap::new(),
+            pattern: None,
+        }
+    }
+
+    /// Returns the wrapped tokenizer
+    pub fn get_tokenizer(&self) -> &TokenizerOption {
+        &self.tokenizer
+    }
+
+    /// Returns the added tokens and their ids
+    pub fn get_added_tokens(&self) -> &HashMap<String, i64> {
+        &self.values
+    }
+
+    /// Registers additional tokens. Tokens already part of the vocabulary (or already added) are
+    /// ignored, new tokens are assigned consecutive ids starting from `vocab_size`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tokens` - tokens to add
+    /// * `vocab_size` - size of the vocabulary of the model (first id available)
+    pub fn add_tokens<S: AsRef<str>>(&mut self, tokens: &[S], vocab_size: i64) {
+        let unk_id = self.tokenizer.get_unk_id();
+        let mut next_id = self
+            .indices
+            .keys()
+            .max()
+            .map_or(vocab_size, |max_id| (max_id + 1).max(vocab_size));
+        for token in tokens {
+            let token = token.as_ref();
+            if token.is_empty()
+                || self.values.contains_key(token)
+                || self.tokenizer.convert_tokens_to_ids(&[token])[0] != unk_id
+            {
+                continue;
+            }
+            self.values.insert(token.to_string(), next_id);
+            self.indices.insert(next_id, token.to_string());
+            next_id += 1;
+        }
+
+        let mut added_tokens = self.values.keys().collect::<Vec<&String>>();
+        // Longest tokens are matched first
+        added_tokens.sort_by(|token_a, token_b| token_b.len().cmp(&token_a.len()));
+        self.pattern = if added_tokens.is_empty() {
+            None
+        } else {
+            Some(
+                Regex::new(
+                    &added_tokens
+                        .iter()
+                        .map(|token| regex::escape(token))
+                        .collect::<Vec<String>>()
+                        .join("|"),
+                )
+                .unwrap(),
+            )
+        };
+    }
+
+    /// Tokenizes a text, returning the tokens with their character offsets
+    pub fn tokenize_with_offsets(&self, text: &str) -> TokensWithOffsets {
+        let mut output = TokensWithOffsets {
+            tokens: vec![],
+            offsets: vec![],
+            reference_offsets: vec![],
+            masks: vec![],
+        };
+        let mut segment_start = 0;
+        let mut char_position = 0;
+        let matches = self
+            .pattern
+            .as_ref()
+            .map(|pattern| pattern.find_iter(text).collect::<Vec<_>>())
+            .unwrap_or_default();
+        for added_token in matches {
+            let segment = &text[segment_start..added_token.start()];
+            self.extend_with_segment(&mut output, segment, char_position);
+            char_position += segment.chars().count() as OffsetSize;
+
+            let token_length = added_token.as_str().chars().count() as OffsetSize;
+            output.tokens.push(added_token.as_str().to_string());
+            output.offsets.push(Some(Offset::new(
+                char_position,
+                char_position + token_length,
+            )));
+            output
+                .reference_offsets
+                .push((char_position..char_position + token_length).collect());
+            output.masks.push(Mask::None);
+            char_position += token_length;
+            segment_start = added_token.end();
+        }
+        self.extend_with_segment(&mut output, &text[segment_start..], char_position);
+        output
+    }
+
+    fn extend_with_segment(
+        &self,
+        output: &mut TokensWithOffsets,
+        segment: &str,
+        char_offset: OffsetSize,
+    ) {
+        if segment.is_empty() {
+            return;
+        }
+        let tokens = self.tokenizer.tokenize_with_offsets(segment);
+        output.tokens.extend(tokens.tokens);
+        output
+            .offsets
+            .extend(tokens.offsets.into_iter().map(|offset| {
+                offset
+                    .map(|offset| Offset::new(offset.begin + char_offset, offset.end + char_offset))
+            }));
+        output.reference_offsets.extend(
+            tokens
+                .reference_offsets
+                .into_iter()
+                .map(|positions| positions.into_iter().map(|p| p + char_offset).collect()),
+        );
+        output.masks.extend(tokens.masks);
+    }
+
+    /// Tokenizes a text
+    pub fn tokenize(&self, text: &str) -> Vec<String> {
+        self.tokenize_with_offsets(text).tokens
+    }
+
+    /// Tokenizes a list of texts
+    pub fn tokenize_list<S>(&self, text_list: &[S]) -> Vec<Vec<String>>
+    where
+        S: AsRef<str>,
+    {
+        text_list
+            .iter()
+            .map(|text| self.tokenize(text.as_ref()))
+            .collect()
+    }
+
+    /// Converts tokens to their ids
+    pub fn convert_tokens_to_ids<S>(&self, tokens: &[S]) -> Vec<i64>
+    where
+        S: AsRef<str>,
+    {
+        tokens
+            .iter()
+            .map(|token| match self.values.get(token.as_ref()) {
+                Some(id) => *id,
+                None => self.tokenizer.convert_tokens_to_ids(&[token.as_ref()])[0],
+            })
+            .collect()
+    }
+
+    fn encode_sequence(&self, text: &str) -> TokenIdsWithOffsets {
+        let tokens = self.tokenize_with_offsets(text);
+        TokenIdsWithOffsets {
+            ids: self.convert_tokens_to_ids(&tokens.tokens),
+            offsets: tokens.offsets,
+            reference_offsets: tokens.reference_offsets,
+            masks: tokens.masks,
+        }
+    }
+
+    /// Encodes a text or a pair of texts, truncating the sequences following the `TruncationStrategy`
+    /// and adding the special tokens of the wrapped tokenizer
+    pub fn encode(
+        &self,
+        text_1: &str,
+        text_2: Option<&str>,
+        max_len: usize,
+        truncation_strategy: &TruncationStrategy,
+        stride: usize,
+    ) -> TokenizedInput {
+        let token_ids_with_offsets_1 = self.encode_sequence(text_1);
+        let token_ids_with_offsets_2 = text_2.map(|text| self.encode_sequence(text));
+        let empty_sequence = || TokenIdsWithOffsets {
+            ids: vec![],
+            offsets: vec![],
+            reference_offsets: vec![],
+            masks: vec![],
+        };
+        let num_special_tokens = self
+            .tokenizer
+            .build_input_with_special_tokens(empty_sequence(), text_2.map(|_| empty_sequence()))
+            .token_ids
+            .len();
+        let total_len = token_ids_with_offsets_1.ids.len()
+            + token_ids_with_offsets_2
+                .as_ref()
+                .map_or(0, |token_ids| token_ids.ids.len())
+            + num_special_tokens;
+        let num_truncated_tokens = total_len.saturating_sub(max_len);
+
+        let (
+            token_ids_with_offsets_1,
+            token_ids_with_offsets_2,
+            overflowing_tokens,
+            _overflowing_offsets,
+        ) = truncate_sequences(
+            token_ids_with_offsets_1,
+            token_ids_with_offsets_2,
+            num_truncated_tokens,
+            truncation_strategy,
+            stride,
+        )
+        .unwrap();
+
+        let merged_tokenized_input = self
+            .tokenizer
+            .build_input_with_special_tokens(token_ids_with_offsets_1, token_ids_with_offsets_2);
+
+        TokenizedInput {
+            overflowing_tokens,
+            num_truncated_tokens,
+            ..merged_tokenized_input
+        }
+    }
+
+    /// Encodes a list of texts
+    pub fn encode_list<S>(
+        &self,
+        text_list: &[S],
+        max_len: usize,
+        truncation_strategy: &TruncationStrategy,
+        stride: usize,
+    ) -> Vec<TokenizedInput>
+    where
+        S: AsRef<str>,
+    {
+        text_list
+            .iter()
+            .map(|text| self.encode(text.as_ref(), None, max_len, truncation_strategy, stride))
+            .collect()
+    }
+
+    /// Encodes a list of text pairs
+    pub fn encode_pair_list(
+        &self,
+        text_pair_list: &[(&str, &str)],
+        max_len: usize,
+        truncation_strategy: &TruncationStrategy,
+        stride: usize,
+    ) -> Vec<TokenizedInput> {
+        text_pair_list
+            .iter()
+            .map(|(text_1, text_2)| {
+                self.encode(text_1, Some(text_2), max_len, truncation_strategy, stride)
+            })
+            .collect()
+    }
+
+    /// Adds the special tokens of the wrapped tokenizer to a sequence or pair of sequences of token ids
+    pub fn build_input_with_special_tokens(
+        &self,
+        token_ids_with_offsets_1: TokenIdsWithOffsets,
+        token_ids_with_offsets_2: Option<TokenIdsWithOffsets>,
+    ) -> TokenIdsWithSpecialTokens {
+        let tokenized_input = self
+            .tokenizer
+            .build_input_with_special_tokens(token_ids_with_offsets_1, token_ids_with_offsets_2);
+        TokenIdsWithSpecialTokens {
+            token_ids: tokenized_input.token_ids,
+            segment_ids: tokenized_input.segment_ids,
+            special_tokens_mask: tokenized_input.special_tokens_mask,
+            token_offsets: tokenized_input.token_offsets,
+            reference_offsets: tokenized_input.reference_offsets,
+            mask: tokenized_input.mask,
+        }
+    }
+
+    /// Decodes a sequence of token ids. The added tokens are separated from the surrounding text by
+    /// a whitespace.
+    pub fn decode(
+        &self,
+        token_ids: &[i64],
+        skip_special_tokens: bool,
+        clean_up_tokenization_spaces: bool,
+    ) -> String {
+        let mut segments: Vec<String> = vec![];
+        let mut segment_ids: Vec<i64> = vec![];
+        for token_id in token_ids {
+            match self.indices.get(token_id) {
+                Some(token) => {
+                    if !segment_ids.is_empty() {
+                        segments.push(self.tokenizer.decode(
+                            &segment_ids,
+                            skip_special_tokens,
+                            clean_up_tokenization_spaces,
+                        ));
+                        segment_ids.clear();
+                    }
+                    segments.push(token.clone());
+                }
+                None => segment_ids.push(*token_id),
+            }
+        }
+        if !segment_ids.is_empty() {
+            segments.push(self.tokenizer.decode(
+                &segment_ids,
+                skip_special_tokens,
+                clean_up_tokenization_spaces,
+            ));
+        }
+        segments
+            .iter()
+            .map(|segment| segment.trim())
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+}
+
+/// Registers additional tokens on a pipeline tokenizer and resizes the token embeddings of the
+/// model accordingly
+pub(crate) fn add_tokens_and_resize_embeddings<S: AsRef<str>>(
+    tokenizer: TokenizerOption,
+    var_store: &VarStore,
+    tokens: &[S],
+) -> Result<TokenizerOption, RustBertError> {
+    let vocab_size = get_vocab_size(var_store)?;
+    let tokenizer = tokenizer.with_added_tokens(tokens, vocab_size);
+    let new_vocab_size = tokenizer
+        .convert_tokens_to_ids(tokens)
+        .into_iter()
+        .map(|id| id + 1)
+        .fold(vocab_size, i64::max);
+    if new_vocab_size > vocab_size {
+        resize_token_embeddings(var_store, vocab_size, new_vocab_size);
+    }
+    Ok(tokenizer)
+}
@@ -0,0 +1,296 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Summarization with source attribution
+//! Generates abstractive summaries and aligns each sentence of the summary back to the sentences
+//! of the source document it is derived from. The alignment is extractive: the summary and source
+//! sentences are embedded with a sentence embeddings model, and the most similar source sentences
+//! are returned for each summary sentence along with their character offsets in the source
+//! document (provenance spans).
+//!
+//! Summary sentences without any source sentence above the similarity threshold are reported as
+//! unsupported, allowing to flag content that cannot be traced back to the source document.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::attributed_summarization::AttributedSummarizationModel;
+//!
+//! let model = AttributedSummarizationModel::new(Default::default())?;
+//! let input = ["In findings published Tuesday in Cornell University's arXiv by a team of scientists \
+//! from the University of Montreal and a separate report published Wednesday in Nature Astronomy by a team \
+//! from University College London (UCL), the presence of water vapour was confirmed in the atmosphere of K2-18b, \
+//! a planet circling a star in the constellation Leo."];
+//! let output = model.summarize(&input)?;
+//! for sentence in &output[0].sentences {
+//!     for source in &sentence.sources {
+//!         println!(
+//!             "{} <- {} ({:.3})",
+//!             sentence.text, source.sentence.text, source.score
+//!         );
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::common::error::RustBertError;
+#[cfg(feature = "remote")]
+use crate::pipelines::sentence_embeddings::SentenceEmbeddingsModelType;
+use crate::pipelines::sentence_embeddings::{SentenceEmbeddingsConfig, SentenceEmbeddingsModel};
+use crate::pipelines::sentence_segmentation::{
+    Sentence, SentenceSegmentationConfig, SentenceSegmenter,
+};
+use crate::pipelines::summarization::{SummarizationConfig, SummarizationModel};
+use serde::{Deserialize, Serialize};
+use tch::{Kind, Tensor};
+
+#[derive(Serialize, Deserialize)]
+/// # Configuration for AttributedSummarizationModel
+/// Contains the summarization model generating the summaries and the sentence embeddings model
+/// aligning the summary sentences to the source sentences.
+pub struct AttributedSummarizationConfig {
+    /// `SummarizationConfig` defining the abstractive summarization model
+    pub summarization_config: SummarizationConfig,
+    /// `SentenceEmbeddingsConfig` defining the sentence embeddings model used for the alignment
+    pub sentence_embeddings_config: SentenceEmbeddingsConfig,
+    /// Rules used to split the source documents and summaries into sentences
+    pub sentence_segmentation_config: SentenceSegmentationConfig,
+    /// Maximum number of source sentences returned for each summary sentence (default: 2)
+    pub num_sources: usize,
+    /// Minimum cosine similarity for a source sentence to be attributed to a summary sentence (default: 0.5)
+    pub min_similarity: f64,
+}
+
+#[cfg(feature = "remote")]
+impl Default for AttributedSummarizationConfig {
+    fn default() -> AttributedSummarizationConfig {
+        AttributedSummarizationConfig {
+            summarization_config: SummarizationConfig::default(),
+            sentence_embeddings_config: SentenceEmbeddingsConfig::from(
+                SentenceEmbeddingsModelType::AllMiniLmL6V2,
+            ),
+            sentence_segmentation_config: SentenceSegmentationConfig::default(),
+            num_sources: 2,
+            min_similarity: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Source sentence attributed to a summary sentence
+pub struct SourceAttribution {
+    /// Source sentence, with its character offsets in the source document
+    pub sentence: Sentence,
+    /// Cosine similarity between the summary sentence and the source sentence
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Summary sentence with its source attributions
+pub struct AttributedSentence {
+    /// Text of the summary sentence
+    pub text: String,
+    /// Source sentences supporting the summary sentence, sorted by decreasing similarity
+    pub sources: Vec<SourceAttribution>,
+}
+
+impl AttributedSentence {
+    /// Indicates if at least one source sentence could be attributed to the summary sentence
+    pub fn is_supported(&self) -> bool {
+        !self.sources.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Summary generated by an `AttributedSummarizationModel`
+pub struct AttributedSummary {
+    /// Full text of the summary
+    pub summary: String,
+    /// Sentences of the summary with their source attributions
+    pub sentences: Vec<AttributedSentence>,
+}
+
+/// # AttributedSummarizationModel to generate summaries with source attribution
+pub struct AttributedSummarizationModel {
+    summarization_model: SummarizationModel,
+    sentence_embeddings_model: SentenceEmbeddingsModel,
+    sentence_segmenter: SentenceSegmenter,
+    num_sources: usize,
+    min_similarity: f64,
+}
+
+impl AttributedSummarizationModel {
+    /// Build a new `AttributedSummarizationModel`
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `AttributedSummarizationConfig` object containing the summarization and sentence embeddings configurations
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::attributed_summarization::AttributedSummarizationModel;
+    ///
+    /// let model = AttributedSummarizationModel::new(Default::default())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new(
+        config: AttributedSummarizationConfig,
+    ) -> Result<AttributedSummarizationModel, RustBertError> {
+        if config.num_sources == 0 {
+            return Err(RustBertError::InvalidConfigurationError(
+                "num_sources must be strictly greater than 0".to_string(),
+            ));
+        }
+        let summarization_model = SummarizationModel::new(config.summarization_config)?;
+        let sentence_embeddings_model =
+            SentenceEmbeddingsModel::new(config.sentence_embeddings_config)?;
+        let sentence_segmenter = SentenceSegmenter::new(config.sentence_segmentation_config);
+        Ok(AttributedSummarizationModel {
+            summarization_model,
+            sentence_embeddings_model,
+            sentence_segmenter,
+            num_sources: config.num_sources,
+            min_similarity: config.min_similarity,
+        })
+    }
+
+    /// Summarize texts and attribute the summary sentences to source sentences
+    ///
+    /// # Arguments
+    ///
+    /// * `texts` - `&[&str]` Array of texts to summarize.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<AttributedSummary>` containing the summaries and the source sentences of each summary sentence
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::attributed_summarization::AttributedSummarizationModel;
+    ///
+    /// let model = AttributedSummarizationModel::new(Default::default())?;
+    /// let input = ["A long document to summarize..."];
+    /// let output = model.summarize(&input)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn summarize<S>(&self, texts: &[S]) -> Result<Vec<AttributedSummary>, RustBertError>
+    where
+        S: AsRef<str> + Sync,
+    {
+        let summaries = self.summarization_model.summarize(texts)?;
+        let attributions = self.attribute(texts, &summaries)?;
+        Ok(summaries
+            .into_iter()
+            .zip(attributions)
+            .map(|(summary, sentences)| AttributedSummary { summary, sentences })
+            .collect())
+    }
+
+    /// Attribute the sentences of existing summaries to the sentences of their source texts
+    ///
+    /// # Arguments
+    ///
+    /// * `texts` - `&[&str]` Array of source texts.
+    /// * `summaries` - `&[&str]` Array of summaries, one for each source text.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Vec<AttributedSentence>>` containing the summary sentences and their source sentences for each summary
+    pub fn attribute<S, T>(
+        &self,
+        texts: &[S],
+        summaries: &[T],
+    ) -> Result<Vec<Vec<AttributedSentence>>, RustBertError>
+    where
+        S: AsRef<str> + Sync,
+        T: AsRef<str> + Sync,
+    {
+        if texts.len() != summaries.len() {
+            return Err(RustBertError::ValueError(format!(
+                "Number of summaries ({}) does not match the number of texts ({})",
+                summaries.len(),
+                texts.len()
+            )));
+        }
+        let source_sentences = self.sentence_segmenter.segment(texts);
+        let summary_sentences = self.sentence_segmenter.segment(summaries);
+
+        let mut output = Vec::with_capacity(texts.len());
+        for (source_sentences, summary_sentences) in source_sentences.iter().zip(summary_sentences)
+        {
+            if source_sentences.is_empty() | summary_sentences.is_empty() {
+                output.push(
+                    summary_sentences
+                        .into_iter()
+                        .map(|sentence| AttributedSentence {
+                            text: sentence.text,
+                            sources: vec![],
+                        })
+                        .collect(),
+                );
+                continue;
+            }
+            let source_embeddings = self.embed_sentences(source_sentences)?;
+            let summary_embeddings = self.embed_sentences(&summary_sentences)?;
+            let similarities = summary_embeddings.matmul(&source_embeddings.transpose(0, 1));
+            let (scores, indices) = similarities.topk(
+                (self.num_sources as i64).min(source_sentences.len() as i64),
+                -1,
+                true,
+                true,
+            );
+
+            let mut attributed_sentences = Vec::with_capacity(summary_sentences.len());
+            for (sentence_index, sentence) in summary_sentences.into_iter().enumerate() {
+                let sentence_scores = Vec::<f64>::from(scores.get(sentence_index as i64));
+                let sentence_indices = Vec::<i64>::from(indices.get(sentence_index as i64));
+                let sources = sentence_scores
+                    .into_iter()
+                    .zip(sentence_indices)
+                    .filter(|(score, _)| *score >= self.min_similarity)
+                    .map(|(score, source_index)| SourceAttribution {
+                        sentence: source_sentences[source_index as usize].clone(),
+                        score,
+                    })
+                    .collect();
+                attributed_sentences.push(AttributedSentence {
+                    text: sentence.text,
+                    sources,
+                });
+            }
+            output.push(attributed_sentences);
+        }
+        Ok(output)
+    }
+
+    /// Returns the L2-normalized embeddings of the sentences
+    fn embed_sentences(&self, sentences: &[Sentence]) -> Result<Tensor, RustBertError> {
+        let texts = sentences
+            .iter()
+            .map(|sentence| sentence.text.as_str())
+            .collect::<Vec<&str>>();
+        let embeddings = self
+            .sentence_embeddings_model
+            .encode_as_tensor(&texts)?
+            .embeddings
+            .to_kind(Kind::Float);
+        let norm = embeddings
+            .linalg_norm(2.0, [1i64].as_slice(), true, Kind::Float)
+            .clamp_min(1e-12);
+        Ok(embeddings / norm)
+    }
+}
@@ -0,0 +1,472 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Confidence calibration of classification scores
+//! Neural classifiers are often over-confident: a score of 0.9 does not mean that the prediction
+//! is correct 9 times out of 10. Calibrators are fitted on the logits of a labeled validation set
+//! and map the logits of the model to probabilities that can be used for thresholding.
+//!
+//! The following calibrators are available:
+//! - `TemperatureScaling` divides the logits by a single temperature minimizing the negative
+//!   log-likelihood of the validation set. The ranking of the labels is unchanged.
+//! - `IsotonicCalibration` fits a non-decreasing mapping from the score of each label to the
+//!   observed frequency of the label (pool adjacent violators algorithm). Softmax scores are
+//!   normalized again after the mapping.
+//!
+//! Calibrators are attached to the `SequenceClassificationModel` and `TokenClassificationModel`
+//! pipelines (and to the pipelines built on them) with `with_calibrator`. The calibration quality
+//! can be checked with `expected_calibration_error`.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::calibration::{expected_calibration_error, TemperatureScaling};
+//! use rust_bert::pipelines::sequence_classification::{
+//!     ScoreNormalization, SequenceClassificationModel,
+//! };
+//!
+//! let model = SequenceClassificationModel::new(Default::default())?;
+//! let validation_texts = ["This movie was fantastic!", "I will never watch it again."];
+//! let validation_labels = [1, 0];
+//!
+//! let logits = model.predict_logits(&validation_texts);
+//! let calibrator =
+//!     TemperatureScaling::fit(&logits, &validation_labels, ScoreNormalization::Softmax)?;
+//! let probabilities = calibrator.calibrate_scores(&logits, ScoreNormalization::Softmax);
+//! let error = expected_calibration_error(&probabilities, &validation_labels, 10)?;
+//!
+//! let model = model.with_calibrator(calibrator);
+//! let output = model.predict(&["An instant classic."]);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::pipelines::sequence_classification::ScoreNormalization;
+use crate::RustBertError;
+use serde::{Deserialize, Serialize};
+use tch::{Kind, Tensor};
+
+/// # Calibration of classification logits
+pub trait Calibrator: Send {
+    /// Converts classification logits to calibrated probabilities
+    ///
+    /// # Arguments
+    ///
+    /// * `logits` - Tensor of shape (..., *num_labels*) with the raw classification logits
+    /// * `normalization` - `ScoreNormalization` applied to the logits (softmax for mutually exclusive labels, sigmoid for multi-label classification)
+    ///
+    /// # Returns
+    ///
+    /// * `Tensor` of calibrated probabilities, with the same shape and device as the logits
+    fn calibrate(&self, logits: &Tensor, normalization: ScoreNormalization) -> Tensor;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+/// # Temperature scaling calibrator
+/// Divides the logits by a temperature before normalization. Temperatures above 1 soften
+/// over-confident predictions.
+pub struct TemperatureScaling {
+    /// Temperature dividing the logits
+    pub temperature: f64,
+}
+
+impl TemperatureScaling {
+    /// Creates a calibrator with a given temperature
+    pub fn new(temperature: f64) -> Result<TemperatureScaling, RustBertError> {
+        if !(temperature > 0.0 && temperature.is_finite()) {
+            return Err(RustBertError::InvalidConfigurationError(format!(
+                "The temperature must be strictly positive, got {}",
+                temperature
+            )));
+        }
+        Ok(TemperatureScaling { temperature })
+    }
+
+    /// Fits the temperature minimizing the negative log-likelihood of a validation set
+    ///
+    /// # Arguments
+    ///
+    /// * `logits` - Logits of the validation examples, e.g. obtained with `SequenceClassificationModel::predict_logits`
+    /// * `labels` - Expected label id of each validation example
+    /// * `normalization` - `ScoreNormalization` the calibrator will be used with. For sigmoid normalization, each label is treated as an independent binary problem.
+    pub fn fit(
+        logits: &[Vec<f64>],
+        labels: &[i64],
+        normalization: ScoreNormalization,
+    ) -> Result<TemperatureScaling, RustBertError> {
+        check_validation_set(logits, labels)?;
+        // The negative log-likelihood is convex in the inverse temperature, and therefore
+        // unimodal in the log-temperature: golden section search
+        let ratio = (5f64.sqrt() - 1.0) / 2.0;
+        let (mut low, mut high) = (0.01f64.ln(), 100f64.ln());
+        let loss = |log_temperature: f64| {
+            negative_log_likelihood(logits, labels, log_temperature.exp(), normalization)
+        };
+        for _ in 0..100 {
+            let left = high - ratio * (high - low);
+            let right = low + ratio * (high - low);
+            if loss(left) <= loss(right) {
+                high = right;
+            } else {
+                low = left;
+            }
+        }
+        TemperatureScaling::new(((low + high) / 2.0).exp())
+    }
+
+    /// Calibrates the logits of a set of examples
+    ///
+    /// # Arguments
+    ///
+    /// * `logits` - Logits of the examples
+    /// * `normalization` - `ScoreNormalization` applied to the scaled logits
+    pub fn calibrate_scores(
+        &self,
+        logits: &[Vec<f64>],
+        normalization: ScoreNormalization,
+    ) -> Vec<Vec<f64>> {
+        logits
+            .iter()
+            .map(|example_logits| normalize(example_logits, self.temperature, normalization))
+            .collect()
+    }
+}
+
+impl Calibrator for TemperatureScaling {
+    fn calibrate(&self, logits: &Tensor, normalization: ScoreNormalization) -> Tensor {
+        let logits = logits / self.temperature;
+        match normalization {
+            ScoreNormalization::Softmax => logits.softmax(-1, Kind::Float),
+            ScoreNormalization::Sigmoid => logits.sigmoid(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// # Isotonic regression calibrator
+/// Maps the score of each label with a non-decreasing piecewise linear function fitted on a
+/// validation set.
+pub struct IsotonicCalibration {
+    /// Points (score, calibrated probability) of the mapping of each label, by label id
+    pub mappings: Vec<Vec<(f64, f64)>>,
+}
+
+impl IsotonicCalibration {
+    /// Fits the mapping of each label on a validation set
+    ///
+    /// # Arguments
+    ///
+    /// * `logits` - Logits of the validation examples, e.g. obtained with `SequenceClassificationModel::predict_logits`
+    /// * `labels` - Expected label id of each validation example
+    /// * `normalization` - `ScoreNormalization` the calibrator will be used with
+    pub fn fit(
+        logits: &[Vec<f64>],
+        labels: &[i64],
+        normalization: ScoreNormalization,
+    ) -> Result<IsotonicCalibration, RustBertError> {
+        check_validation_set(logits, labels)?;
+        let scores = logits
+            .iter()
+            .map(|example_logits| normalize(example_logits, 1.0, normalization))
+            .collect::<Vec<Vec<f64>>>();
+        let mappings = (0..logits[0].len())
+            .map(|label_id| {
+                let mut points = scores
+                    .iter()
+                    .zip(labels)
+                    .map(|(example_scores, label)| {
+                        (
+                            example_scores[label_id],
+                            if *label == label_id as i64 { 1.0 } else { 0.0 },
+                        )
+                    })
+                    .collect::<Vec<(f64, f64)>>();
+                points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+                pool_adjacent_violators(&points)
+            })
+            .collect();
+        Ok(IsotonicCalibration { mappings })
+    }
+
+    /// Calibrates the logits of a set of examples
+    ///
+    /// # Arguments
+    ///
+    /// * `logits` - Logits of the examples
+    /// * `normalization` - `ScoreNormalization` applied to the logits before the mapping
+    pub fn calibrate_scores(
+        &self,
+        logits: &[Vec<f64>],
+        normalization: ScoreNormalization,
+    ) -> Vec<Vec<f64>> {
+        logits
+            .iter()
+            .map(|example_logits| {
+                let scores = normalize(example_logits, 1.0, normalization);
+                self.map_scores(&scores, normalization)
+            })
+            .collect()
+    }
+
+    fn map_scores(&self, scores: &[f64], normalization: ScoreNormalization) -> Vec<f64> {
+        let mapped = scores
+            .iter()
+            .zip(&self.mappings)
+            .map(|(score, mapping)| interpolate(mapping, *score))
+            .collect::<Vec<f64>>();
+        match normalization {
+            ScoreNormalization::Softmax => {
+                let total = mapped.iter().sum::<f64>();
+                if total > 0.0 {
+                    mapped.iter().map(|score| score / total).collect()
+                } else {
+                    scores.to_vec()
+                }
+            }
+            ScoreNormalization::Sigmoid => mapped,
+        }
+    }
+}
+
+impl Calibrator for IsotonicCalibration {
+    fn calibrate(&self, logits: &Tensor, normalization: ScoreNormalization) -> Tensor {
+        let size = logits.size();
+        let num_labels = *size.last().unwrap();
+        let scores = match normalization {
+            ScoreNormalization::Softmax => logits.softmax(-1, Kind::Double),
+            ScoreNormalization::Sigmoid => logits.to_kind(Kind::Double).sigmoid(),
+        };
+        let scores = Vec::<f64>::from(scores.to(tch::Device::Cpu).reshape(&[-1]));
+        let calibrated = scores
+            .chunks(num_labels as usize)
+            .flat_map(|example_scores| self.map_scores(example_scores, normalization))
+            .collect::<Vec<f64>>();
+        Tensor::of_slice(&calibrated)
+            .view(size.as_slice())
+            .to_kind(Kind::Float)
+            .to(logits.device())
+    }
+}
+
+/// Computes the expected calibration error of probabilities: the confidence of the predicted
+/// label is compared to the accuracy of the predictions in bins of equal width, weighted by the
+/// number of predictions in each bin.
+///
+/// # Arguments
+///
+/// * `probabilities` - Probabilities of each label for each example
+/// * `labels` - Expected label id of each example
+/// * `num_bins` - Number of confidence bins
+pub fn expected_calibration_error(
+    probabilities: &[Vec<f64>],
+    labels: &[i64],
+    num_bins: usize,
+) -> Result<f64, RustBertError> {
+    check_validation_set(probabilities, labels)?;
+    if num_bins == 0 {
+        return Err(RustBertError::ValueError(
+            "At least one bin is required".to_string(),
+        ));
+    }
+    let mut bins = vec![(0usize, 0f64, 0f64); num_bins];
+    for (example_probabilities, label) in probabilities.iter().zip(labels) {
+        let (predicted, confidence) = example_probabilities.iter().enumerate().fold(
+            (0, f64::MIN),
+            |best, (index, probability)| {
+                if *probability > best.1 {
+                    (index, *probability)
+                } else {
+                    best
+                }
+            },
+        );
+        let bin = &mut bins[((confidence * num_bins as f64) as usize).min(num_bins - 1)];
+        bin.0 += 1;
+        bin.1 += confidence;
+        bin.2 += if predicted as i64 == *label { 1.0 } else { 0.0 };
+    }
+    Ok(bins
+        .iter()
+        .filter(|(count, _, _)| *count > 0)
+        .map(|(_, confidence, correct)| (confidence - correct).abs())
+        .sum::<f64>()
+        / labels.len() as f64)
+}
+
+fn check_validation_set(logits: &[Vec<f64>], labels: &[i64]) -> Result<(), RustBertError> {
+    if logits.is_empty() || logits.len() != labels.len() {
+        return Err(RustBertError::ValueError(format!(
+            "Expected a non-empty validation set with one label per example, got {} examples and {} labels",
+            logits.len(),
+            labels.len()
+        )));
+    }
+    let num_labels = logits[0].len();
+    if logits.iter().any(|example| example.len() != num_labels) {
+        return Err(RustBertError::ValueError(
+            "All examples must have the same number of logits".to_string(),
+        ));
+    }
+    if let Some(label) = labels
+        .iter()
+        .find(|label| **label < 0 || **label as usize >= num_labels)
+    {
+        return Err(RustBertError::ValueError(format!(
+            "Label id {} is out of range for {} labels",
+            label, num_labels
+        )));
+    }
+    Ok(())
+}
+
+fn normalize(logits: &[f64], temperature: f64, normalization: ScoreNormalization) -> Vec<f64> {
+    let scaled = logits.iter().map(|logit| logit / temperature);
+    match normalization {
+        ScoreNormalization::Softmax => {
+            let max = logits.iter().cloned().fold(f64::MIN, f64::max) / temperature;
+            let exponentials = scaled
+                .map(|logit| (logit - max).exp())
+                .collect::<Vec<f64>>();
+            let total = exponentials.iter().sum::<f64>();
+            exponentials.iter().map(|value| value / total).collect()
+        }
+        ScoreNormalization::Sigmoid => scaled.map(|logit| 1.0 / (1.0 + (-logit).exp())).collect(),
+    }
+}
+
+fn negative_log_likelihood(
+    logits: &[Vec<f64>],
+    labels: &[i64],
+    temperature: f64,
+    normalization: ScoreNormalization,
+) -> f64 {
+    let epsilon = 1e-12;
+    logits
+        .iter()
+        .zip(labels)
+        .map(|(example_logits, label)| {
+            let scores = normalize(example_logits, temperature, normalization);
+            match normalization {
+                ScoreNormalization::Softmax => -(scores[*label as usize] + epsilon).ln(),
+                ScoreNormalization::Sigmoid => scores
+                    .iter()
+                    .enumerate()
+                    .map(|(label_id, score)| {
+                        if label_id as i64 == *label {
+                            -(score + epsilon).ln()
+                        } else {
+                            -(1.0 - score + epsilon).ln()
+                        }
+                    })
+                    .sum(),
+            }
+        })
+        .sum()
+}
+
+/// Fits a non-decreasing step function to points sorted by score, returning the (mean score,
+/// mean target) of each block
+fn pool_adjacent_violators(points: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    // Blocks of (sum of scores, sum of targets, count)
+    let mut blocks: Vec<(f64, f64, f64)> = Vec::with_capacity(points.len());
+    for (score, target) in points {
+        blocks.push((*score, *target, 1.0));
+        while blocks.len() > 1 {
+            let (last_score, last_target, last_count) = blocks[blocks.len() - 1];
+            let (previous_score, previous_target, previous_count) = blocks[blocks.len() - 2];
+            if previous_target / previous_count <= last_target / last_count {
+                break;
+            }
+            blocks.pop();
+            *blocks.last_mut().unwrap() = (
+                previous_score + last_score,
+                previous_target + last_target,
+                previous_count + last_count,
+            );
+        }
+    }
+    blocks
+        .iter()
+        .map(|(score, target, count)| (score / count, target / count))
+        .collect()
+}
+
+/// Piecewise linear interpolation of a mapping, constant outside of its range
+fn interpolate(mapping: &[(f64, f64)], score: f64) -> f64 {
+    match mapping
+        .iter()
+        .position(|(point_score, _)| *point_score >= score)
+    {
+        None => mapping.last().map_or(score, |(_, value)| *value),
+        Some(0) => mapping[0].1,
+        Some(index) => {
+            let (left_score, left_value) = mapping[index - 1];
+            let (right_score, right_value) = mapping[index];
+            left_value
+                + (right_value - left_value) * (score - left_score) / (right_score - left_score)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn temperature_scaling_softens_over_confident_logits() -> Result<(), RustBertError> {
+        // The model always predicts label 0 with a large margin but is right 3 times out of 4
+        let logits = vec![vec![4.0, 0.0]; 4];
+        let labels = [0, 0, 0, 1];
+        let calibrator = TemperatureScaling::fit(&logits, &labels, ScoreNormalization::Softmax)?;
+        let probabilities = calibrator.calibrate_scores(&logits, ScoreNormalization::Softmax);
+        assert!((probabilities[0][0] - 0.75).abs() < 1e-3);
+        assert!(calibrator.temperature > 1.0);
+        assert!(TemperatureScaling::new(0.0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn isotonic_calibration() -> Result<(), RustBertError> {
+        let points = [(0.1, 0.0), (0.2, 1.0), (0.3, 0.0), (0.4, 1.0), (0.5, 1.0)];
+        let mapping = pool_adjacent_violators(&points);
+        assert_eq!(mapping.len(), 4);
+        assert!((mapping[1].0 - 0.25).abs() < 1e-9);
+        assert!((mapping[1].1 - 0.5).abs() < 1e-9);
+        assert!(mapping.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+        assert!((interpolate(&mapping, 0.0) - 0.0).abs() < 1e-9);
+        assert!((interpolate(&mapping, 0.3) - 2.0 / 3.0).abs() < 1e-9);
+        assert!((interpolate(&mapping, 0.9) - 1.0).abs() < 1e-9);
+
+        let logits = vec![
+            vec![2.0, 0.0],
+            vec![1.0, 0.0],
+            vec![0.0, 1.0],
+            vec![0.0, 2.0],
+        ];
+        let labels = [0, 1, 0, 1];
+        let calibrator = IsotonicCalibration::fit(&logits, &labels, ScoreNormalization::Softmax)?;
+        for probabilities in calibrator.calibrate_scores(&logits, ScoreNormalization::Softmax) {
+            assert!((probabilities.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        }
+        assert!(
+            IsotonicCalibration::fit(&logits, &[0, 1, 2, 0], ScoreNormalization::Softmax).is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn calibration_error() -> Result<(), RustBertError> {
+        let probabilities = [vec![0.9, 0.1], vec![0.9, 0.1], vec![0.2, 0.8]];
+        let error = expected_calibration_error(&probabilities, &[0, 1, 1], 10)?;
+        // Bin 0.9: confidence 1.8 vs 1 correct, bin 0.8: confidence 0.8 vs 1 correct
+        assert!((error - (0.8 + 0.2) / 3.0).abs() < 1e-9);
+        Ok(())
+    }
+}
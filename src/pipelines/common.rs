@@ -20,6 +20,7 @@ use crate::albert::AlbertConfig;
 use crate::bart::BartConfig;
 use crate::bert::BertConfig;
 use crate::common::error::RustBertError;
+use crate::common::trace::trace_span;
 use crate::deberta::DebertaConfig;
 use crate::deberta_v2::DebertaV2Config;
 use crate::distilbert::DistilBertConfig;
@@ -34,6 +35,9 @@ use crate::mbart::MBartConfig;
 use crate::mobilebert::MobileBertConfig;
 use crate::openai_gpt::OpenAiGptConfig;
 use crate::pegasus::PegasusConfig;
+use crate::pipelines::added_tokens::AddedTokensTokenizer;
+#[cfg(feature = "hf-tokenizers")]
+use crate::pipelines::hf_tokenizers::HFTokenizer;
 use crate::prophetnet::ProphetNetConfig;
 use crate::reformer::ReformerConfig;
 use crate::roberta::RobertaConfig;
@@ -90,6 +94,23 @@ pub enum ModelType {
     FNet,
 }
 
+impl ModelType {
+    /// Returns true if the architecture has token type embeddings distinguishing the sequences
+    /// of an input pair (e.g. BERT or ALBERT). Models such as RoBERTa or DistilBERT either have a
+    /// single token type or no token type embeddings, and should not be given token type ids.
+    pub fn uses_token_type_ids(&self) -> bool {
+        matches!(
+            self,
+            ModelType::Bert
+                | ModelType::Albert
+                | ModelType::Electra
+                | ModelType::MobileBert
+                | ModelType::FNet
+                | ModelType::XLNet
+        )
+    }
+}
+
 /// # Abstraction that holds a model configuration, can be of any of the supported models
 pub enum ConfigOption {
     /// Bart configuration
@@ -176,6 +197,11 @@ pub enum TokenizerOption {
     FNet(FNetTokenizer),
     /// Bart Tokenizer
     Bart(RobertaTokenizer),
+    /// HuggingFace tokenizer loaded from a `tokenizer.json` file
+    #[cfg(feature = "hf-tokenizers")]
+    HFTokenizer(HFTokenizer),
+    /// Tokenizer extended with additional tokens
+    AddedTokens(AddedTokensTokenizer),
 }
 
 impl ConfigOption {
@@ -635,9 +661,46 @@ impl TokenizerOption {
         Ok(tokenizer)
     }
 
+    /// Interface method to load a HuggingFace tokenizer from a `tokenizer.json` file (requires the
+    /// `hf-tokenizers` feature). The special tokens are read from the optional
+    /// `special_tokens_map.json` file, or looked up in the vocabulary if not provided.
+    #[cfg(feature = "hf-tokenizers")]
+    pub fn from_hf_tokenizer_file<P: AsRef<Path>, S: AsRef<Path>>(
+        model_type: ModelType,
+        tokenizer_file: P,
+        special_token_map_file: Option<S>,
+    ) -> Result<Self, RustBertError> {
+        Ok(TokenizerOption::HFTokenizer(HFTokenizer::from_file(
+            model_type,
+            tokenizer_file,
+            special_token_map_file,
+        )?))
+    }
+
+    /// Registers additional tokens on the tokenizer, never split during tokenization. Tokens that
+    /// are not part of the vocabulary are assigned consecutive ids starting from `vocab_size`. Note
+    /// that the embeddings of the model need to be resized to include the new tokens (this is done
+    /// by the `with_added_tokens` method of the pipelines).
+    ///
+    /// # Arguments
+    ///
+    /// * `tokens` - tokens to add
+    /// * `vocab_size` - size of the vocabulary of the model
+    pub fn with_added_tokens<S: AsRef<str>>(self, tokens: &[S], vocab_size: i64) -> Self {
+        let mut tokenizer = match self {
+            Self::AddedTokens(tokenizer) => tokenizer,
+            tokenizer => AddedTokensTokenizer::new(tokenizer),
+        };
+        tokenizer.add_tokens(tokens, vocab_size);
+        Self::AddedTokens(tokenizer)
+    }
+
     /// Returns the model type
     pub fn model_type(&self) -> ModelType {
         match *self {
+            #[cfg(feature = "hf-tokenizers")]
+            Self::HFTokenizer(ref tokenizer) => tokenizer.model_type(),
+            Self::AddedTokens(ref tokenizer) => tokenizer.get_tokenizer().model_type(),
             Self::Bert(_) => ModelType::Bert,
             Self::Deberta(_) => ModelType::Deberta,
             Self::DebertaV2(_) => ModelType::DebertaV2,
@@ -670,7 +733,15 @@ impl TokenizerOption {
     where
         S: AsRef<str> + Sync,
     {
+        let _span = trace_span!(DEBUG, "tokenize", batch_size = text_list.len(), max_len);
         match *self {
+            #[cfg(feature = "hf-tokenizers")]
+            Self::HFTokenizer(ref tokenizer) => {
+                tokenizer.encode_list(text_list, max_len, truncation_strategy, stride)
+            }
+            Self::AddedTokens(ref tokenizer) => {
+                tokenizer.encode_list(text_list, max_len, truncation_strategy, stride)
+            }
             Self::Bert(ref tokenizer) => MultiThreadedTokenizer::encode_list(
                 tokenizer,
                 text_list,
@@ -808,7 +879,20 @@ impl TokenizerOption {
         truncation_strategy: &TruncationStrategy,
         stride: usize,
     ) -> Vec<TokenizedInput> {
+        let _span = trace_span!(
+            DEBUG,
+            "tokenize",
+            batch_size = text_pair_list.len(),
+            max_len
+        );
         match *self {
+            #[cfg(feature = "hf-tokenizers")]
+            Self::HFTokenizer(ref tokenizer) => {
+                tokenizer.encode_pair_list(text_pair_list, max_len, truncation_strategy, stride)
+            }
+            Self::AddedTokens(ref tokenizer) => {
+                tokenizer.encode_pair_list(text_pair_list, max_len, truncation_strategy, stride)
+            }
             Self::Bert(ref tokenizer) => MultiThreadedTokenizer::encode_pair_list(
                 tokenizer,
                 text_pair_list,
@@ -948,6 +1032,13 @@ impl TokenizerOption {
         stride: usize,
     ) -> TokenizedInput {
         match *self {
+            #[cfg(feature = "hf-tokenizers")]
+            Self::HFTokenizer(ref tokenizer) => {
+                tokenizer.encode(text_1, text_2, max_len, truncation_strategy, stride)
+            }
+            Self::AddedTokens(ref tokenizer) => {
+                tokenizer.encode(text_1, text_2, max_len, truncation_strategy, stride)
+            }
             Self::Bert(ref tokenizer) => {
                 tokenizer.encode(text_1, text_2, max_len, truncation_strategy, stride)
             }
@@ -1008,6 +1099,9 @@ impl TokenizerOption {
     /// Interface method to tokenization
     pub fn tokenize(&self, text: &str) -> Vec<String> {
         match *self {
+            #[cfg(feature = "hf-tokenizers")]
+            Self::HFTokenizer(ref tokenizer) => tokenizer.tokenize(text),
+            Self::AddedTokens(ref tokenizer) => tokenizer.tokenize(text),
             Self::Bert(ref tokenizer) => tokenizer.tokenize(text),
             Self::Deberta(ref tokenizer) => tokenizer.tokenize(text),
             Self::DebertaV2(ref tokenizer) => tokenizer.tokenize(text),
@@ -1032,6 +1126,9 @@ impl TokenizerOption {
     /// Interface method to tokenization
     pub fn tokenize_with_offsets(&self, text: &str) -> TokensWithOffsets {
         match *self {
+            #[cfg(feature = "hf-tokenizers")]
+            Self::HFTokenizer(ref tokenizer) => tokenizer.tokenize_with_offsets(text),
+            Self::AddedTokens(ref tokenizer) => tokenizer.tokenize_with_offsets(text),
             Self::Bert(ref tokenizer) => tokenizer.tokenize_with_offsets(text),
             Self::Deberta(ref tokenizer) => tokenizer.tokenize_with_offsets(text),
             Self::DebertaV2(ref tokenizer) => tokenizer.tokenize_with_offsets(text),
@@ -1058,7 +1155,11 @@ impl TokenizerOption {
     where
         S: AsRef<str> + Sync,
     {
+        let _span = trace_span!(DEBUG, "tokenize", batch_size = text.len());
         match *self {
+            #[cfg(feature = "hf-tokenizers")]
+            Self::HFTokenizer(ref tokenizer) => tokenizer.tokenize_list(text),
+            Self::AddedTokens(ref tokenizer) => tokenizer.tokenize_list(text),
             Self::Bert(ref tokenizer) => MultiThreadedTokenizer::tokenize_list(tokenizer, text),
             Self::Deberta(ref tokenizer) => MultiThreadedTokenizer::tokenize_list(tokenizer, text),
             Self::DebertaV2(ref tokenizer) => {
@@ -1096,6 +1197,13 @@ impl TokenizerOption {
         clean_up_tokenization_spaces: bool,
     ) -> String {
         match *self {
+            #[cfg(feature = "hf-tokenizers")]
+            Self::HFTokenizer(ref tokenizer) => {
+                tokenizer.decode(token_ids, skip_special_tokens, clean_up_tokenization_spaces)
+            }
+            Self::AddedTokens(ref tokenizer) => {
+                tokenizer.decode(token_ids, skip_special_tokens, clean_up_tokenization_spaces)
+            }
             Self::Bert(ref tokenizer) => {
                 tokenizer.decode(token_ids, skip_special_tokens, clean_up_tokenization_spaces)
             }
@@ -1160,6 +1268,15 @@ impl TokenizerOption {
         token_ids_with_offsets_2: Option<TokenIdsWithOffsets>,
     ) -> TokenizedInput {
         let token_ids_with_special_tokens = match *self {
+            #[cfg(feature = "hf-tokenizers")]
+            Self::HFTokenizer(ref tokenizer) => tokenizer.build_input_with_special_tokens(
+                token_ids_with_offsets_1,
+                token_ids_with_offsets_2,
+            ),
+            Self::AddedTokens(ref tokenizer) => tokenizer.build_input_with_special_tokens(
+                token_ids_with_offsets_1,
+                token_ids_with_offsets_2,
+            ),
             Self::Bert(ref tokenizer) => tokenizer.build_input_with_special_tokens(
                 token_ids_with_offsets_1,
                 token_ids_with_offsets_2,
@@ -1251,6 +1368,9 @@ impl TokenizerOption {
         S: AsRef<str>,
     {
         match *self {
+            #[cfg(feature = "hf-tokenizers")]
+            Self::HFTokenizer(ref tokenizer) => tokenizer.convert_tokens_to_ids(tokens),
+            Self::AddedTokens(ref tokenizer) => tokenizer.convert_tokens_to_ids(tokens),
             Self::Bert(ref tokenizer) => tokenizer.convert_tokens_to_ids(tokens),
             Self::Deberta(ref tokenizer) => tokenizer.convert_tokens_to_ids(tokens),
             Self::DebertaV2(ref tokenizer) => tokenizer.convert_tokens_to_ids(tokens),
@@ -1275,6 +1395,9 @@ impl TokenizerOption {
     /// Interface method
     pub fn get_unk_id(&self) -> i64 {
         match *self {
+            #[cfg(feature = "hf-tokenizers")]
+            Self::HFTokenizer(ref tokenizer) => tokenizer.get_unk_id(),
+            Self::AddedTokens(ref tokenizer) => tokenizer.get_tokenizer().get_unk_id(),
             Self::Bert(ref tokenizer) => *MultiThreadedTokenizer::vocab(tokenizer)
                 .special_values
                 .get(BertVocab::unknown_value())
@@ -1353,6 +1476,9 @@ impl TokenizerOption {
     /// Interface method
     pub fn get_pad_id(&self) -> Option<i64> {
         match *self {
+            #[cfg(feature = "hf-tokenizers")]
+            Self::HFTokenizer(ref tokenizer) => tokenizer.get_pad_id(),
+            Self::AddedTokens(ref tokenizer) => tokenizer.get_tokenizer().get_pad_id(),
             Self::Bert(ref tokenizer) => Some(
                 *MultiThreadedTokenizer::vocab(tokenizer)
                     .special_values
@@ -1452,6 +1578,9 @@ impl TokenizerOption {
     /// Interface method
     pub fn get_sep_id(&self) -> Option<i64> {
         match *self {
+            #[cfg(feature = "hf-tokenizers")]
+            Self::HFTokenizer(ref tokenizer) => tokenizer.get_sep_id(),
+            Self::AddedTokens(ref tokenizer) => tokenizer.get_tokenizer().get_sep_id(),
             Self::Bert(ref tokenizer) => Some(
                 *MultiThreadedTokenizer::vocab(tokenizer)
                     .special_values
@@ -1536,6 +1665,9 @@ impl TokenizerOption {
     /// Interface method
     pub fn get_mask_id(&self) -> Option<i64> {
         match *self {
+            #[cfg(feature = "hf-tokenizers")]
+            Self::HFTokenizer(ref tokenizer) => tokenizer.get_mask_id(),
+            Self::AddedTokens(ref tokenizer) => tokenizer.get_tokenizer().get_mask_id(),
             Self::Bert(ref tokenizer) => Some(
                 *MultiThreadedTokenizer::vocab(tokenizer)
                     .special_values
@@ -1620,6 +1752,9 @@ impl TokenizerOption {
     /// Interface method
     pub fn get_mask_value(&self) -> Option<&str> {
         match self {
+            #[cfg(feature = "hf-tokenizers")]
+            Self::HFTokenizer(tokenizer) => tokenizer.get_mask_value(),
+            Self::AddedTokens(tokenizer) => tokenizer.get_tokenizer().get_mask_value(),
             Self::Bert(_) => Some(BertVocab::mask_value()),
             Self::Deberta(_) => Some(DeBERTaVocab::mask_value()),
             Self::DebertaV2(_) => Some(DeBERTaV2Vocab::mask_value()),
@@ -1644,6 +1779,9 @@ impl TokenizerOption {
     /// Interface method
     pub fn get_bos_id(&self) -> Option<i64> {
         match *self {
+            #[cfg(feature = "hf-tokenizers")]
+            Self::HFTokenizer(ref tokenizer) => tokenizer.get_bos_id(),
+            Self::AddedTokens(ref tokenizer) => tokenizer.get_tokenizer().get_bos_id(),
             Self::Roberta(ref tokenizer) => Some(
                 *MultiThreadedTokenizer::vocab(tokenizer)
                     .special_values
@@ -1713,6 +1851,9 @@ impl TokenizerOption {
     /// Interface method
     pub fn get_eos_id(&self) -> Option<i64> {
         match *self {
+            #[cfg(feature = "hf-tokenizers")]
+            Self::HFTokenizer(ref tokenizer) => tokenizer.get_eos_id(),
+            Self::AddedTokens(ref tokenizer) => tokenizer.get_tokenizer().get_eos_id(),
             Self::Roberta(ref tokenizer) => Some(
                 *MultiThreadedTokenizer::vocab(tokenizer)
                     .special_values
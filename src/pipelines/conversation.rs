@@ -29,14 +29,14 @@
 //!
 //! let conversation_id =
 //!     conversation_manager.create("Going to the movies tonight - any suggestions?");
-//! let output = conversation_model.generate_responses(&mut conversation_manager);
+//! let output = conversation_model.generate_responses(&mut conversation_manager)?;
 //!
 //! let _ = conversation_manager
 //!     .get(&conversation_id)
 //!     .unwrap()
 //!     .add_user_input("Is it an action movie?")?;
 //!
-//! let output = conversation_model.generate_responses(&mut conversation_manager);
+//! let output = conversation_model.generate_responses(&mut conversation_manager)?;
 //!
 //! # Ok(())
 //! # }
@@ -54,11 +54,19 @@
 //! The authors of this repository are not responsible for any generation
 //! from the 3rd party utilization of the pretrained system.
 use crate::common::error::RustBertError;
+use crate::common::trace::trace_span;
 use crate::gpt2::GPT2Generator;
 use crate::pipelines::common::{ModelType, TokenizerOption};
 use crate::pipelines::generation_utils::private_generation_utils::PrivateLanguageGenerator;
-use crate::pipelines::generation_utils::{GenerateConfig, LanguageGenerator};
+use crate::pipelines::generation_utils::{
+    AttentionSinkConfig, GenerateConfig, GenerateOptions, LanguageGenerator, TokenCallbackFunction,
+    TruncationSide,
+};
+use crate::pipelines::output_filter::OutputFilter;
+use crate::pipelines::prompt_template::{ChatMessage, ChatRole};
 use crate::resources::ResourceProvider;
+use rust_tokenizers::tokenizer::TruncationStrategy;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use tch::{Device, Kind, Tensor};
 use uuid::Uuid;
@@ -69,6 +77,51 @@ use crate::{
     resources::RemoteResource,
 };
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// # Policy used to fit the conversation history in the model context
+/// The system prompt of a conversation (if any) and the new user input are always kept, the policy
+/// defines how the past turns are shortened when the context exceeds the maximum allowed length.
+pub enum HistoryTruncation {
+    /// Truncate the oldest tokens of the history, preferably at a turn boundary (end of sequence token)
+    Tokens,
+    /// Drop the oldest turns of the conversation until the remaining turns fit in the context
+    DropOldestTurns,
+    /// Drop the oldest turns and replace them by a summary generated by the history summarizer. The
+    /// dropped turns are replaced by their summary in the history of the conversation, and the
+    /// summary is summarized again with the turns dropped later.
+    SummarizeDroppedTurns,
+}
+
+/// # Function summarizing the turns dropped from a conversation history
+/// Receives the text of the dropped turns (oldest first) and returns a summary that is prepended to
+/// the remaining history. This can for example wrap a `SummarizationModel`.
+pub type HistorySummarizer = Box<dyn Fn(&[String]) -> String + Send>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+/// # Generation settings of a conversation
+/// Overrides the generation settings of the `ConversationConfig` for the responses of a single
+/// conversation (e.g. to serve users with different settings). Unset values fall back to the
+/// model configuration. Settings are not applied per row within a batch: only conversations with
+/// identical settings are generated in a single batch, and each distinct set of settings among the
+/// active conversations is generated in a separate batch.
+pub struct ConversationGenerationSettings {
+    /// Sampling flag
+    pub do_sample: Option<bool>,
+    /// Temperature setting
+    pub temperature: Option<f64>,
+    /// Top_k value for sampling tokens
+    pub top_k: Option<i64>,
+    /// Top_p value for nucleus sampling
+    pub top_p: Option<f64>,
+    /// Min_p value for sampling tokens
+    pub min_p: Option<f64>,
+    /// Repetition penalty
+    pub repetition_penalty: Option<f64>,
+    /// Maximum number of tokens of the response, capped by the maximum length of the model configuration
+    pub max_new_tokens: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize)]
 /// # Configuration for multi-turn classification
 /// Contains information regarding the model to load, mirrors the GenerationConfig, with a
 /// different set of default parameters and sets the device to place the model on.
@@ -76,12 +129,16 @@ pub struct ConversationConfig {
     /// Model type
     pub model_type: ModelType,
     /// Model weights resource (default: DialoGPT-medium)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub model_resource: Box<dyn ResourceProvider + Send>,
     /// Config resource (default: DialoGPT-medium)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub config_resource: Box<dyn ResourceProvider + Send>,
     /// Vocab resource (default: DialoGPT-medium)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub vocab_resource: Box<dyn ResourceProvider + Send>,
     /// Merges resource (default: DialoGPT-medium)
+    #[serde(default, with = "crate::common::serde_utils::optional_resource")]
     pub merges_resource: Option<Box<dyn ResourceProvider + Send>>,
     /// Minimum sequence length (default: 0)
     pub min_length: i64,
@@ -113,27 +170,50 @@ pub struct ConversationConfig {
     pub num_beam_groups: Option<i64>,
     /// Diversity penalty for diverse beam search. High values will enforce more difference between beam groups (default: 5.5)
     pub diversity_penalty: Option<f64>,
+    /// Policy used to shorten the history when it exceeds the maximum context length (default: `HistoryTruncation::Tokens`)
+    pub history_truncation: HistoryTruncation,
+    /// Summarizer for the turns dropped from the history, required for `HistoryTruncation::SummarizeDroppedTurns` (default: None)
+    #[serde(skip)]
+    pub history_summarizer: Option<HistorySummarizer>,
+    /// Attention sink cache policy bounding the cache of the model while generating long responses (default: None)
+    #[serde(default)]
+    pub attention_sink: Option<AttentionSinkConfig>,
     /// Device to place the model on (default: CUDA/GPU when available)
+    #[serde(
+        with = "crate::common::serde_utils::device",
+        default = "crate::common::serde_utils::device::default"
+    )]
     pub device: Device,
 }
 
-#[cfg(feature = "remote")]
-impl Default for ConversationConfig {
-    fn default() -> ConversationConfig {
+impl ConversationConfig {
+    /// Instantiate a new conversation configuration of the supplied type.
+    ///
+    /// # Arguments
+    ///
+    /// * `model_type` - `ModelType` indicating the model type to load (must match with the actual data to be loaded!)
+    /// * model_resource - The `ResourceProvider` pointing to the model to load (e.g.  model.ot)
+    /// * config_resource - The `ResourceProvider` pointing to the model configuration to load (e.g. config.json)
+    /// * vocab_resource - The `ResourceProvider` pointing to the tokenizer's vocabulary to load (e.g.  vocab.txt/vocab.json)
+    /// * merges_resource - The `ResourceProvider`  pointing to the tokenizer's merge file or SentencePiece model to load (e.g.  merges.txt).
+    pub fn new<RM, RC, RV>(
+        model_type: ModelType,
+        model_resource: RM,
+        config_resource: RC,
+        vocab_resource: RV,
+        merges_resource: Option<RV>,
+    ) -> ConversationConfig
+    where
+        RM: ResourceProvider + Send + 'static,
+        RC: ResourceProvider + Send + 'static,
+        RV: ResourceProvider + Send + 'static,
+    {
         ConversationConfig {
-            model_type: ModelType::GPT2,
-            model_resource: Box::new(RemoteResource::from_pretrained(
-                Gpt2ModelResources::DIALOGPT_MEDIUM,
-            )),
-            config_resource: Box::new(RemoteResource::from_pretrained(
-                Gpt2ConfigResources::DIALOGPT_MEDIUM,
-            )),
-            vocab_resource: Box::new(RemoteResource::from_pretrained(
-                Gpt2VocabResources::DIALOGPT_MEDIUM,
-            )),
-            merges_resource: Some(Box::new(RemoteResource::from_pretrained(
-                Gpt2MergesResources::DIALOGPT_MEDIUM,
-            ))),
+            model_type,
+            model_resource: Box::new(model_resource),
+            config_resource: Box::new(config_resource),
+            vocab_resource: Box::new(vocab_resource),
+            merges_resource: merges_resource.map(|r| Box::new(r) as Box<_>),
             min_length: 0,
             max_length: Some(1000),
             min_length_for_response: 64,
@@ -149,11 +229,29 @@ impl Default for ConversationConfig {
             num_return_sequences: 1,
             num_beam_groups: None,
             diversity_penalty: None,
+            history_truncation: HistoryTruncation::Tokens,
+            history_summarizer: None,
+            attention_sink: None,
             device: Device::cuda_if_available(),
         }
     }
 }
 
+#[cfg(feature = "remote")]
+impl Default for ConversationConfig {
+    fn default() -> ConversationConfig {
+        ConversationConfig::new(
+            ModelType::GPT2,
+            RemoteResource::from_pretrained(Gpt2ModelResources::DIALOGPT_MEDIUM),
+            RemoteResource::from_pretrained(Gpt2ConfigResources::DIALOGPT_MEDIUM),
+            RemoteResource::from_pretrained(Gpt2VocabResources::DIALOGPT_MEDIUM),
+            Some(RemoteResource::from_pretrained(
+                Gpt2MergesResources::DIALOGPT_MEDIUM,
+            )),
+        )
+    }
+}
+
 impl From<ConversationConfig> for GenerateConfig {
     fn from(config: ConversationConfig) -> GenerateConfig {
         GenerateConfig {
@@ -169,12 +267,29 @@ impl From<ConversationConfig> for GenerateConfig {
             temperature: config.temperature,
             top_k: config.top_k,
             top_p: config.top_p,
+            min_p: 0.0,
             repetition_penalty: config.repetition_penalty,
             length_penalty: config.length_penalty,
             no_repeat_ngram_size: config.no_repeat_ngram_size,
             num_return_sequences: config.num_return_sequences,
             num_beam_groups: config.num_beam_groups,
             diversity_penalty: config.diversity_penalty,
+            pad_token_id: None,
+            max_prompt_length: None,
+            prompt_truncation_side: TruncationSide::Right,
+            prompt_truncation_strategy: TruncationStrategy::LongestFirst,
+            dola_layers: None,
+            attention_sink: config.attention_sink,
+            stop_sequences: Vec::new(),
+            seed: None,
+            bad_word_ids: Vec::new(),
+            bad_words: Vec::new(),
+            forced_bos_token_id: None,
+            forced_eos_token_id: None,
+            logit_bias: HashMap::new(),
+            logits_processors: Vec::new(),
+            stopping_criteria: Vec::new(),
+            layer_placement: HashMap::new(),
             device: config.device,
         }
     }
@@ -193,6 +308,10 @@ pub struct Conversation {
     pub new_user_input: Option<String>,
     ///  History of the tokens passed as an input and generated so far used as context for next turn generation
     pub history: Vec<Vec<i64>>,
+    /// Optional system prompt (e.g. persona description), always prepended to the context and never truncated
+    pub system_prompt: Option<String>,
+    /// Generation settings overriding the model configuration for this conversation
+    pub generation_settings: ConversationGenerationSettings,
 }
 
 impl Conversation {
@@ -215,6 +334,8 @@ impl Conversation {
             generated_responses: vec![],
             new_user_input: Some(text.to_string()),
             history: vec![],
+            system_prompt: None,
+            generation_settings: ConversationGenerationSettings::default(),
         }
     }
 
@@ -233,6 +354,8 @@ impl Conversation {
             generated_responses: vec![],
             new_user_input: None,
             history: vec![],
+            system_prompt: None,
+            generation_settings: ConversationGenerationSettings::default(),
         }
     }
 
@@ -294,6 +417,48 @@ impl Conversation {
         old_user_input
     }
 
+    /// Sets the system prompt of the conversation. The system prompt is prepended to the context
+    /// of every turn and is never removed by the history truncation.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - `&str` with the system prompt (e.g. a persona description)
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use rust_bert::pipelines::conversation::Conversation;
+    ///
+    /// let mut conversation = Conversation::new("Hi there!");
+    /// conversation.set_system_prompt("I am a helpful movie critic.");
+    /// ```
+    pub fn set_system_prompt(&mut self, text: &str) {
+        self.system_prompt = Some(text.to_string());
+    }
+
+    /// Sets the generation settings used for the responses of the conversation, overriding the
+    /// settings of the model configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `generation_settings` - `ConversationGenerationSettings` for the next responses
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use rust_bert::pipelines::conversation::{Conversation, ConversationGenerationSettings};
+    ///
+    /// let mut conversation = Conversation::new("Hi there!");
+    /// conversation.set_generation_settings(ConversationGenerationSettings {
+    ///     temperature: Some(0.7),
+    ///     max_new_tokens: Some(32),
+    ///     ..Default::default()
+    /// });
+    /// ```
+    pub fn set_generation_settings(&mut self, generation_settings: ConversationGenerationSettings) {
+        self.generation_settings = generation_settings;
+    }
+
     /// Returns `true` if the conversation contains new user inputs to process
     ///
     /// # Returns
@@ -393,6 +558,40 @@ impl Conversation {
         }
     }
 
+    /// Returns the conversation as a list of chat messages (system prompt, alternating user inputs
+    /// and generated responses, and new user input), allowing to format it with a `ChatFormat`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::conversation::Conversation;
+    /// use rust_bert::pipelines::prompt_template::ChatFormat;
+    ///
+    /// let mut conversation = Conversation::new("Hi There");
+    /// conversation.set_system_prompt("You are a helpful assistant.");
+    /// let prompt = ChatFormat::ChatML.render(&conversation.to_messages(), true)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn to_messages(&self) -> Vec<ChatMessage> {
+        let mut messages = vec![];
+        if let Some(system_prompt) = &self.system_prompt {
+            messages.push(ChatMessage::new(ChatRole::System, system_prompt.as_str()));
+        }
+        let mut responses = self.generated_responses.iter();
+        for user_input in self.past_user_inputs.iter() {
+            messages.push(ChatMessage::new(ChatRole::User, user_input.as_str()));
+            if let Some(response) = responses.next() {
+                messages.push(ChatMessage::new(ChatRole::Assistant, response.as_str()));
+            }
+        }
+        if let Some(new_user_input) = &self.new_user_input {
+            messages.push(ChatMessage::new(ChatRole::User, new_user_input.as_str()));
+        }
+        messages
+    }
+
     fn append(&mut self, text: &str, ids: &[i64]) {
         match &self.new_user_input {
             Some(_) => {
@@ -729,19 +928,34 @@ impl ConversationOption {
         }
     }
 
+    /// Casts the model weights to half precision
+    pub fn half(&mut self) {
+        match self {
+            Self::GPT2(model_ref) => model_ref.half(),
+        }
+    }
+
+    /// Casts the model weights to single precision
+    pub fn float(&mut self) {
+        match self {
+            Self::GPT2(model_ref) => model_ref.float(),
+        }
+    }
+
     /// Interface method to generate_from_ids_and_past() of the particular models.
     pub fn generate_from_ids_and_past(
         &self,
         input_ids: Tensor,
         attention_mask: Option<Tensor>,
-    ) -> Vec<Vec<i64>> {
-        match *self {
+        generate_options: Option<GenerateOptions>,
+    ) -> Result<Vec<Vec<i64>>, RustBertError> {
+        Ok(match *self {
             Self::GPT2(ref model) => model
-                .generate_from_ids_and_past(input_ids, attention_mask, None)
+                .generate_from_ids_and_past(input_ids, attention_mask, generate_options)?
                 .into_iter()
                 .map(|output| output.indices)
                 .collect(),
-        }
+        })
     }
 }
 
@@ -750,7 +964,11 @@ impl ConversationOption {
 pub struct ConversationModel {
     model: ConversationOption,
     eos_token_id: i64,
+    max_length: Option<i64>,
     max_allowed_context_length: Option<i64>,
+    history_truncation: HistoryTruncation,
+    history_summarizer: Option<HistorySummarizer>,
+    output_filters: Vec<Box<dyn OutputFilter>>,
     device: Device,
 }
 
@@ -772,30 +990,91 @@ impl ConversationModel {
     /// # }
     /// ```
     pub fn new(
-        conversation_config: ConversationConfig,
+        mut conversation_config: ConversationConfig,
     ) -> Result<ConversationModel, RustBertError> {
+        let _span = trace_span!(
+            INFO,
+            "load_model",
+            pipeline = "conversation",
+            model_type = ?conversation_config.model_type,
+            device = ?conversation_config.device
+        );
+        let max_length = conversation_config.max_length;
         let max_allowed_length = conversation_config
             .max_length
             .map(|max_length| max_length - conversation_config.min_length_for_response);
         let device = conversation_config.device;
+        let history_truncation = conversation_config.history_truncation;
+        let history_summarizer = conversation_config.history_summarizer.take();
+        if history_truncation == HistoryTruncation::SummarizeDroppedTurns
+            && history_summarizer.is_none()
+        {
+            return Err(RustBertError::InvalidConfigurationError(
+                "A history summarizer must be provided to summarize dropped turns".to_string(),
+            ));
+        }
         let model = ConversationOption::new(conversation_config)?;
         let eos_token_id = model.get_eos_id()?;
         Ok(ConversationModel {
             model,
             eos_token_id,
+            max_length,
             max_allowed_context_length: max_allowed_length,
+            history_truncation,
+            history_summarizer,
+            output_filters: vec![],
             device,
         })
     }
 
+    /// Attaches an `OutputFilter` to the model (e.g. a `RegexRedaction`), rewriting the generated
+    /// responses before they are added to the conversations and returned. Filters are applied in
+    /// the order they were attached. The history used as context for the next turns keeps the
+    /// tokens generated by the model.
+    ///
+    /// # Arguments
+    ///
+    /// * `output_filter` - `OutputFilter` applied to the generated responses
+    pub fn with_output_filter<F>(mut self, output_filter: F) -> ConversationModel
+    where
+        F: OutputFilter + 'static,
+    {
+        self.output_filters.push(Box::new(output_filter));
+        self
+    }
+
+    /// Casts the model weights to half precision
+    pub fn half(&mut self) {
+        self.model.half();
+    }
+
+    /// Casts the model weights to single precision
+    pub fn float(&mut self) {
+        self.model.float();
+    }
+
+    /// Get a reference to the model tokenizer.
+    pub fn get_tokenizer(&self) -> &TokenizerOption {
+        self.model.get_tokenizer()
+    }
+
+    /// Indicates whether output filters are applied to the responses, which can then only be
+    /// returned once fully generated.
+    pub(crate) fn has_output_filters(&self) -> bool {
+        !self.output_filters.is_empty()
+    }
+
     /// Perform a multi-turn conversation based on user input
     ///
+    /// Active conversations are generated in one batch per distinct `ConversationGenerationSettings`:
+    /// conversations with different settings are not batched together.
+    ///
     /// # Arguments
     ///
     /// * `conversation_manager` - `&mut ConversationManager` Conversation manager keeping track of active conversations
     ///
     /// # Returns
-    /// * `HashMap<&Uuid, &str>` Responses from the model for each active conversation, referenced by Uuid
+    /// * `Result<HashMap<&Uuid, &str>, RustBertError>` Responses from the model for each active conversation, referenced by Uuid
     ///
     /// # Example
     ///
@@ -808,14 +1087,68 @@ impl ConversationModel {
     /// let mut conversation_manager = ConversationManager::new();
     /// conversation_manager.create("Hello, how are you?");
     ///
-    /// let output = model.generate_responses(&mut conversation_manager);
+    /// let output = model.generate_responses(&mut conversation_manager)?;
     /// # Ok(())
     /// # }
     /// ```
     pub fn generate_responses<'a>(
         &self,
         conversation_manager: &'a mut ConversationManager,
-    ) -> HashMap<&'a Uuid, &'a str> {
+    ) -> Result<HashMap<&'a Uuid, &'a str>, RustBertError> {
+        self.generate_responses_with_callback_fn(conversation_manager, None)
+    }
+
+    /// Perform a multi-turn conversation based on user input, calling `token_callback_fn` with each
+    /// token of the responses as soon as it is generated (e.g. to stream the responses).
+    ///
+    /// The callback receives the index of the conversation among the active conversations (in the
+    /// order of `ConversationManager::get_active_conversations`) and the token id. The tokens are
+    /// reported with the restrictions of the `token_callback_fn` of `GenerateOptions`: the
+    /// conversation model must not use beam search.
+    ///
+    /// # Arguments
+    ///
+    /// * `conversation_manager` - `&mut ConversationManager` Conversation manager keeping track of active conversations
+    /// * `token_callback_fn` - `TokenCallbackFunction` called with the index of the conversation and each generated token id
+    ///
+    /// # Returns
+    /// * `Result<HashMap<&Uuid, &str>, RustBertError>` Responses from the model for each active conversation, referenced by Uuid
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::conversation::{ConversationManager, ConversationModel};
+    /// use rust_bert::pipelines::streaming::IncrementalDecoder;
+    /// use std::cell::RefCell;
+    /// let model = ConversationModel::new(Default::default())?;
+    ///
+    /// let mut conversation_manager = ConversationManager::new();
+    /// conversation_manager.create("Hello, how are you?");
+    ///
+    /// let decoder = RefCell::new(IncrementalDecoder::new(model.get_tokenizer(), true));
+    /// let print_token = |_conversation_index: usize, token_id: i64| {
+    ///     if let Some(text) = decoder.borrow_mut().push(token_id) {
+    ///         print!("{}", text);
+    ///     }
+    /// };
+    /// let output = model.generate_responses_with_callback(&mut conversation_manager, &print_token)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn generate_responses_with_callback<'a>(
+        &self,
+        conversation_manager: &'a mut ConversationManager,
+        token_callback_fn: TokenCallbackFunction,
+    ) -> Result<HashMap<&'a Uuid, &'a str>, RustBertError> {
+        self.generate_responses_with_callback_fn(conversation_manager, Some(token_callback_fn))
+    }
+
+    fn generate_responses_with_callback_fn<'a>(
+        &self,
+        conversation_manager: &'a mut ConversationManager,
+        token_callback_fn: Option<TokenCallbackFunction>,
+    ) -> Result<HashMap<&'a Uuid, &'a str>, RustBertError> {
         let (active_uuid, active_conversations) = conversation_manager.get_active_conversations();
         if !active_uuid.is_empty() {
             let texts = active_conversations
@@ -823,47 +1156,136 @@ impl ConversationModel {
                 .map(|c| c.new_user_input.as_ref().unwrap().as_str())
                 .collect::<Vec<&str>>();
 
-            let history = active_conversations
+            let prompt_ids = self.encode_prompts(texts.as_ref());
+            let system_prompt_ids = active_conversations
                 .iter()
-                .map(|c| c.history.iter().flatten().copied().collect())
+                .map(|c| match &c.system_prompt {
+                    Some(system_prompt) => self.encode_prompts(&[system_prompt.as_str()]).remove(0),
+                    None => vec![],
+                })
                 .collect::<Vec<Vec<i64>>>();
 
-            let prompt_ids = self.encode_prompts(texts.as_ref());
-            let (input_tensor, attention_mask) =
-                self.concat_input_history(prompt_ids.as_ref(), history);
-            let input_length = *input_tensor.size().last().unwrap() as usize;
-            let mut generated = self
-                .model
-                .generate_from_ids_and_past(input_tensor, Some(attention_mask));
-            let removed_padding_quantities = self.clean_padding_indices(&mut generated);
+            let mut history = active_conversations
+                .iter_mut()
+                .zip(prompt_ids.iter().zip(system_prompt_ids.iter()))
+                .map(|(c, (prompt, system_prompt))| {
+                    self.select_history(c, prompt.len() + system_prompt.len())
+                })
+                .collect::<Vec<Vec<i64>>>();
+
+            let identical_settings_batches = identical_settings_batches(
+                &active_conversations
+                    .iter()
+                    .map(|conversation| conversation.generation_settings)
+                    .collect::<Vec<ConversationGenerationSettings>>(),
+            );
+
+            let mut generated_responses = vec![vec![]; active_conversations.len()];
+            for (settings, indices) in identical_settings_batches {
+                let (input_tensor, attention_mask) = self.concat_input_history(
+                    &indices
+                        .iter()
+                        .map(|&index| prompt_ids[index].clone())
+                        .collect::<Vec<Vec<i64>>>(),
+                    indices
+                        .iter()
+                        .map(|&index| std::mem::take(&mut history[index]))
+                        .collect(),
+                    &indices
+                        .iter()
+                        .map(|&index| system_prompt_ids[index].clone())
+                        .collect::<Vec<Vec<i64>>>(),
+                );
+                let input_length = *input_tensor.size().last().unwrap() as usize;
+                let mut generate_options =
+                    self.get_generate_options(&settings, input_length as i64);
+                // The generator reports the index of the sequence within the batch
+                let batch_token_callback_fn = |sequence_index: usize, token_id: i64| {
+                    if let Some(token_callback_fn) = token_callback_fn {
+                        token_callback_fn(indices[sequence_index], token_id);
+                    }
+                };
+                if token_callback_fn.is_some() {
+                    generate_options.token_callback_fn = Some(&batch_token_callback_fn);
+                }
+                let mut generated = self.model.generate_from_ids_and_past(
+                    input_tensor,
+                    Some(attention_mask),
+                    Some(generate_options),
+                )?;
+                let removed_padding_quantities = self.clean_padding_indices(&mut generated);
+                for ((index, generated_sequence), removed_padding) in indices
+                    .into_iter()
+                    .zip(generated.into_iter())
+                    .zip(removed_padding_quantities.into_iter())
+                {
+                    generated_responses[index] =
+                        generated_sequence[input_length - removed_padding.0..].to_vec();
+                }
+            }
+
+            let response_texts = generated_responses
+                .iter()
+                .map(|generated_response| {
+                    self.model
+                        .get_tokenizer()
+                        .decode(generated_response, true, true)
+                })
+                .collect::<Vec<String>>();
+            let response_texts = self
+                .output_filters
+                .iter()
+                .try_fold(response_texts, |response_texts, output_filter| {
+                    output_filter.filter(response_texts)
+                })?;
 
             let mut output = HashMap::with_capacity(active_uuid.len());
 
             for (
-                ((conversation, (generated_sequence, conversation_promp_ids)), uuid),
-                removed_padding,
+                (conversation, ((generated_response, response_text), conversation_promp_ids)),
+                uuid,
             ) in active_conversations
                 .into_iter()
-                .zip(generated.into_iter().zip(prompt_ids.into_iter()))
+                .zip(
+                    generated_responses
+                        .into_iter()
+                        .zip(response_texts.into_iter())
+                        .zip(prompt_ids.into_iter()),
+                )
                 .zip(active_uuid.into_iter())
-                .zip(removed_padding_quantities.into_iter())
             {
-                let generated_response = &generated_sequence[input_length - removed_padding.0..];
-                conversation
-                    .generated_responses
-                    .push(
-                        self.model
-                            .get_tokenizer()
-                            .decode(generated_response, true, true),
-                    );
+                conversation.generated_responses.push(response_text);
                 conversation.history.push(conversation_promp_ids);
-                conversation.history.push(generated_response.to_vec());
+                conversation.history.push(generated_response);
                 conversation.mark_processed();
                 output.insert(uuid, conversation.get_last_response().unwrap());
             }
-            output
+            Ok(output)
         } else {
-            HashMap::new()
+            Ok(HashMap::new())
+        }
+    }
+
+    fn get_generate_options(
+        &self,
+        settings: &ConversationGenerationSettings,
+        input_length: i64,
+    ) -> GenerateOptions {
+        let max_length = settings.max_new_tokens.map(|max_new_tokens| {
+            let max_length = input_length + max_new_tokens;
+            self.max_length.map_or(max_length, |config_max_length| {
+                max_length.min(config_max_length)
+            })
+        });
+        GenerateOptions {
+            max_length,
+            do_sample: settings.do_sample,
+            temperature: settings.temperature,
+            top_k: settings.top_k,
+            top_p: settings.top_p,
+            min_p: settings.min_p,
+            repetition_penalty: settings.repetition_penalty,
+            ..Default::default()
         }
     }
 
@@ -894,10 +1316,38 @@ impl ConversationModel {
         removed_tokens
     }
 
+    fn select_history(&self, conversation: &mut Conversation, reserved_length: usize) -> Vec<i64> {
+        let budget = self
+            .max_allowed_context_length
+            .map(|max_allowed_context_length| {
+                (max_allowed_context_length as usize).saturating_sub(reserved_length)
+            });
+        let summarize = self.history_summarizer.as_ref().map(|history_summarizer| {
+            move |dropped_turns: &[Vec<i64>]| {
+                let dropped_turns = dropped_turns
+                    .iter()
+                    .map(|turn| self.model.get_tokenizer().decode(turn, true, true))
+                    .collect::<Vec<String>>();
+                let summary = history_summarizer(&dropped_turns);
+                self.encode_prompts(&[summary.as_str()]).remove(0)
+            }
+        });
+        select_turns(
+            &mut conversation.history,
+            self.history_truncation,
+            budget,
+            summarize
+                .as_ref()
+                .map(|summarize| summarize as &dyn Fn(&[Vec<i64>]) -> Vec<i64>),
+            self.eos_token_id,
+        )
+    }
+
     fn concat_input_history(
         &self,
         inputs: &[Vec<i64>],
         history: Vec<Vec<i64>>,
+        system_prompts: &[Vec<i64>],
     ) -> (Tensor, Tensor) {
         // Concatenates the history token indices with new user input
         let pad_token = self
@@ -922,20 +1372,16 @@ impl ConversationModel {
 
         let truncated_concatenated_inputs = concatenated_inputs
             .iter()
-            .map(|input| match self.max_allowed_context_length {
-                Some(max_allowed_context_length)
-                    if input.len() > max_allowed_context_length as usize =>
-                {
-                    let start = self.get_truncated_input_index(
-                        input,
-                        max_allowed_context_length as usize,
-                        pad_token,
-                    );
-                    &input[start..]
-                }
-                _ => input.as_slice(),
+            .zip(system_prompts.iter())
+            .map(|(input, system_prompt)| {
+                build_context(
+                    input,
+                    system_prompt,
+                    self.max_allowed_context_length,
+                    pad_token,
+                )
             })
-            .collect::<Vec<&[i64]>>();
+            .collect::<Vec<Vec<i64>>>();
 
         let max_len = truncated_concatenated_inputs
             .iter()
@@ -966,29 +1412,6 @@ impl ConversationModel {
         (Tensor::stack(&concatenated_inputs, 0), attention_mask)
     }
 
-    fn get_truncated_input_index(
-        &self,
-        history: &[i64],
-        max_length: usize,
-        pad_token: i64,
-    ) -> usize {
-        let start_length = history.len();
-        let eos_indices: Vec<usize> = history
-            .iter()
-            .enumerate()
-            .filter(|(i, &e)| {
-                (e == pad_token)
-                    & (*i != start_length - 1)
-                    & ((start_length as isize - max_length as isize - *i as isize) < 0)
-            })
-            .map(|(i, _)| i + 1)
-            .collect();
-
-        // Return the position of the first EOS index that fits the max length requirement.
-        // If it does not exist, no solution exists and truncate text at a non-EOS position
-        *eos_indices.first().unwrap_or(&(start_length - max_length))
-    }
-
     /// Encodes prompts into Vectors of indices to be processed by the model. This method may be used to
     /// initialize the history of a conversation with a prior state.
     ///
@@ -1030,9 +1453,153 @@ impl ConversationModel {
     }
 }
 
+/// Splits the conversations into batches of identical generation settings, in order of first
+/// appearance. Returns the settings of each batch with the indices of its conversations.
+fn identical_settings_batches(
+    settings: &[ConversationGenerationSettings],
+) -> Vec<(ConversationGenerationSettings, Vec<usize>)> {
+    let mut batches: Vec<(ConversationGenerationSettings, Vec<usize>)> = vec![];
+    for (conversation_index, conversation_settings) in settings.iter().enumerate() {
+        match batches
+            .iter_mut()
+            .find(|(batch_settings, _)| batch_settings == conversation_settings)
+        {
+            Some((_, indices)) => indices.push(conversation_index),
+            None => batches.push((*conversation_settings, vec![conversation_index])),
+        }
+    }
+    batches
+}
+
+/// Index of the first turn of the history kept in the context: the most recent turns are kept
+/// whole while they fit in `budget` tokens
+fn first_kept_turn(history: &[Vec<i64>], budget: usize) -> usize {
+    let mut kept_length = 0;
+    let mut first_kept_turn = history.len();
+    for turn in history.iter().rev() {
+        if kept_length + turn.len() > budget {
+            break;
+        }
+        kept_length += turn.len();
+        first_kept_turn -= 1;
+    }
+    first_kept_turn
+}
+
+/// Selects the past turns used as context according to the history truncation policy, within
+/// `budget` tokens if set. Token-level truncation (`HistoryTruncation::Tokens`) is applied later on
+/// the concatenated history and input.
+fn select_turns(
+    history: &mut Vec<Vec<i64>>,
+    history_truncation: HistoryTruncation,
+    budget: Option<usize>,
+    summarize: Option<&dyn Fn(&[Vec<i64>]) -> Vec<i64>>,
+    eos_token_id: i64,
+) -> Vec<i64> {
+    match (history_truncation, budget, summarize) {
+        (HistoryTruncation::Tokens, _, _) | (_, None, _) => history.concat(),
+        (HistoryTruncation::SummarizeDroppedTurns, Some(budget), Some(summarize)) => {
+            summarize_dropped_turns(history, budget, summarize, eos_token_id);
+            history.concat()
+        }
+        // `ConversationModel::new` requires a summarizer to summarize the dropped turns
+        (HistoryTruncation::DropOldestTurns, Some(budget), _)
+        | (HistoryTruncation::SummarizeDroppedTurns, Some(budget), None) => {
+            truncate_history(history, budget)
+        }
+    }
+}
+
+/// Drops the oldest turns of the history that do not fit in `budget` tokens
+fn truncate_history(history: &[Vec<i64>], budget: usize) -> Vec<i64> {
+    history[first_kept_turn(history, budget)..].concat()
+}
+
+/// Replaces the oldest turns of the history that do not fit in `budget` tokens by a single turn
+/// holding their summary (truncated to the remaining budget and ending with the end of sequence
+/// token), so that each turn is summarized only once. The summary is dropped if there is no room
+/// left for it.
+fn summarize_dropped_turns(
+    history: &mut Vec<Vec<i64>>,
+    budget: usize,
+    summarize: &dyn Fn(&[Vec<i64>]) -> Vec<i64>,
+    eos_token_id: i64,
+) {
+    let first_kept_turn = first_kept_turn(history, budget);
+    if first_kept_turn == 0 {
+        return;
+    }
+    let kept_length: usize = history[first_kept_turn..].iter().map(Vec::len).sum();
+    let summary_ids = summarize(&history[..first_kept_turn]);
+    history.drain(..first_kept_turn);
+    if let Some(summary_ids) = fit_summary(summary_ids, budget - kept_length, eos_token_id) {
+        history.insert(0, summary_ids);
+    }
+}
+
+/// Truncates a summary to `available_length` tokens, ending with the end of sequence token.
+/// Returns `None` if there is no room for a summary.
+fn fit_summary(
+    mut summary_ids: Vec<i64>,
+    available_length: usize,
+    eos_token_id: i64,
+) -> Option<Vec<i64>> {
+    if available_length <= 1 {
+        return None;
+    }
+    if summary_ids.len() > available_length {
+        summary_ids.truncate(available_length - 1);
+        summary_ids.push(eos_token_id);
+    }
+    Some(summary_ids)
+}
+
+/// Position from which the input is kept to fit in `max_length` tokens, preferably just after an
+/// end of sequence (padding) token so that the context starts at a turn boundary
+fn truncated_input_index(history: &[i64], max_length: usize, pad_token: i64) -> usize {
+    let start_length = history.len();
+    let eos_indices: Vec<usize> = history
+        .iter()
+        .enumerate()
+        .filter(|(i, &e)| {
+            (e == pad_token)
+                & (*i != start_length - 1)
+                & ((start_length as isize - max_length as isize - *i as isize) < 0)
+        })
+        .map(|(i, _)| i + 1)
+        .collect();
+
+    // Return the position of the first EOS index that fits the max length requirement.
+    // If it does not exist, no solution exists and truncate text at a non-EOS position
+    *eos_indices.first().unwrap_or(&(start_length - max_length))
+}
+
+/// Builds the model context from the system prompt, which is never truncated, and the input
+/// (history and new user input), truncated from the start to fit in the maximum context length
+fn build_context(
+    input: &[i64],
+    system_prompt: &[i64],
+    max_allowed_context_length: Option<i64>,
+    pad_token: i64,
+) -> Vec<i64> {
+    let max_input_length = max_allowed_context_length
+        .map(|max_length| (max_length as usize).saturating_sub(system_prompt.len()));
+    let input = match max_input_length {
+        Some(max_input_length) if input.len() > max_input_length => {
+            &input[truncated_input_index(input, max_input_length, pad_token)..]
+        }
+        _ => input,
+    };
+    let mut context = Vec::with_capacity(system_prompt.len() + input.len());
+    context.extend_from_slice(system_prompt);
+    context.extend_from_slice(input);
+    context
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::cell::RefCell;
 
     #[test]
     #[ignore] // no need to run, compilation is enough to verify it is Send
@@ -1040,4 +1607,179 @@ mod test {
         let config = ConversationConfig::default();
         let _: Box<dyn Send> = Box::new(ConversationModel::new(config));
     }
+
+    #[test]
+    fn batches_only_group_identical_settings() {
+        let default_settings = ConversationGenerationSettings::default();
+        let low_temperature = ConversationGenerationSettings {
+            temperature: Some(0.5),
+            ..Default::default()
+        };
+        let short_responses = ConversationGenerationSettings {
+            temperature: Some(0.5),
+            max_new_tokens: Some(8),
+            ..Default::default()
+        };
+        let settings = [
+            default_settings,
+            low_temperature,
+            default_settings,
+            short_responses,
+            low_temperature,
+        ];
+
+        assert_eq!(
+            identical_settings_batches(&settings),
+            vec![
+                (default_settings, vec![0, 2]),
+                (low_temperature, vec![1, 4]),
+                (short_responses, vec![3]),
+            ]
+        );
+        assert!(identical_settings_batches(&[]).is_empty());
+    }
+
+    //    Turns of the test histories end with the end of sequence token 0
+    fn history() -> Vec<Vec<i64>> {
+        vec![vec![1, 2, 3, 0], vec![4, 0], vec![5, 6, 0]]
+    }
+
+    #[test]
+    fn token_truncation_starts_at_turn_boundary() {
+        let input = history().concat();
+        //    Fits: no truncation
+        assert_eq!(build_context(&input, &[], Some(9), 0), input);
+        assert_eq!(build_context(&input, &[], None, 0), input);
+        //    Truncated just after the end of a turn
+        assert_eq!(build_context(&input, &[], Some(7), 0), vec![4, 0, 5, 6, 0]);
+        assert_eq!(build_context(&input, &[], Some(6), 0), vec![5, 6, 0]);
+        //    No turn boundary fits: cut at the token level
+        assert_eq!(build_context(&input, &[], Some(2), 0), vec![6, 0]);
+    }
+
+    #[test]
+    fn system_prompt_survives_small_budget() {
+        let input = history().concat();
+        let system_prompt = [7, 8, 0];
+        assert_eq!(
+            build_context(&input, &system_prompt, Some(6), 0),
+            vec![7, 8, 0, 5, 6, 0]
+        );
+        //    Budget smaller than the system prompt: the history is dropped, not the system prompt
+        assert_eq!(
+            build_context(&input, &system_prompt, Some(2), 0),
+            vec![7, 8, 0]
+        );
+
+        //    Turn-level policies reserve the system prompt length before selecting turns
+        let budget = 8usize.saturating_sub(system_prompt.len());
+        let selected = truncate_history(&history(), budget);
+        assert_eq!(selected, vec![4, 0, 5, 6, 0]);
+        assert_eq!(
+            build_context(&selected, &system_prompt, Some(8), 0),
+            vec![7, 8, 0, 4, 0, 5, 6, 0]
+        );
+    }
+
+    #[test]
+    fn drop_oldest_turns_keeps_whole_turns() {
+        let history = history();
+        assert_eq!(first_kept_turn(&history, 9), 0);
+        assert_eq!(first_kept_turn(&history, 8), 1);
+        assert_eq!(first_kept_turn(&history, 4), 2);
+        assert_eq!(first_kept_turn(&history, 2), 3);
+
+        assert_eq!(truncate_history(&history, 9), history.concat());
+        //    The second turn does not fit whole with the last one: it is dropped, not cut
+        assert_eq!(truncate_history(&history, 4), vec![5, 6, 0]);
+        assert_eq!(truncate_history(&history, 2), Vec::<i64>::new());
+    }
+
+    fn select(
+        history: &mut Vec<Vec<i64>>,
+        history_truncation: HistoryTruncation,
+        budget: usize,
+        summarized_turns: &RefCell<Vec<Vec<i64>>>,
+    ) -> Vec<i64> {
+        let summarize = |dropped_turns: &[Vec<i64>]| {
+            summarized_turns
+                .borrow_mut()
+                .extend(dropped_turns.iter().cloned());
+            vec![9, 9, 9, 0]
+        };
+        select_turns(
+            history,
+            history_truncation,
+            Some(budget),
+            Some(&summarize),
+            0,
+        )
+    }
+
+    #[test]
+    fn summarize_dropped_turns() {
+        let summarized_turns = RefCell::new(vec![]);
+        let summarize = HistoryTruncation::SummarizeDroppedTurns;
+        //    No dropped turn: the summarizer is not called
+        let mut turns = history();
+        assert_eq!(
+            select(&mut turns, summarize, 9, &summarized_turns),
+            history().concat()
+        );
+        assert!(summarized_turns.borrow().is_empty());
+
+        //    The summary is truncated to the remaining budget and ends with the end of sequence token
+        assert_eq!(
+            select(&mut turns, summarize, 8, &summarized_turns),
+            vec![9, 9, 0, 4, 0, 5, 6, 0]
+        );
+        assert_eq!(summarized_turns.take(), vec![vec![1, 2, 3, 0]]);
+        //    The dropped turns are replaced by their summary in the history, and are not
+        //    summarized again
+        assert_eq!(turns, vec![vec![9, 9, 0], vec![4, 0], vec![5, 6, 0]]);
+        assert_eq!(
+            select(&mut turns, summarize, 8, &summarized_turns),
+            vec![9, 9, 0, 4, 0, 5, 6, 0]
+        );
+        assert!(summarized_turns.borrow().is_empty());
+        //    Turns dropped later are summarized with the previous summary
+        turns.push(vec![7, 0]);
+        assert_eq!(
+            select(&mut turns, summarize, 9, &summarized_turns),
+            vec![9, 0, 4, 0, 5, 6, 0, 7, 0]
+        );
+        assert_eq!(summarized_turns.take(), vec![vec![9, 9, 0]]);
+
+        let mut turns = history();
+        assert_eq!(
+            select(&mut turns, summarize, 7, &summarized_turns),
+            vec![9, 0, 4, 0, 5, 6, 0]
+        );
+        //    No room left for a summary
+        let mut turns = history();
+        assert_eq!(
+            select(&mut turns, summarize, 6, &summarized_turns),
+            vec![4, 0, 5, 6, 0]
+        );
+        assert_eq!(turns, vec![vec![4, 0], vec![5, 6, 0]]);
+
+        //    Dropping the oldest turns neither calls the summarizer nor changes the history
+        summarized_turns.take();
+        let mut turns = history();
+        assert_eq!(
+            select(
+                &mut turns,
+                HistoryTruncation::DropOldestTurns,
+                4,
+                &summarized_turns
+            ),
+            vec![5, 6, 0]
+        );
+        assert!(summarized_turns.borrow().is_empty());
+        assert_eq!(turns, history());
+
+        assert_eq!(fit_summary(vec![9, 9, 0], 3, 0), Some(vec![9, 9, 0]));
+        assert_eq!(fit_summary(vec![9, 9, 9, 0], 2, 0), Some(vec![9, 0]));
+        assert_eq!(fit_summary(vec![9, 0], 1, 0), None);
+    }
 }
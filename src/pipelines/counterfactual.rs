@@ -0,0 +1,277 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Counterfactual probing of classification pipelines
+//! Measures how the output distribution of a classifier changes when spans of its input are
+//! masked, removed or substituted, for example to check which words a prediction depends on, or to
+//! audit a model for fairness by swapping demographic terms ("he" / "she", first names...) and
+//! checking that the prediction does not change.
+//!
+//! A `Counterfactual` is a set of edits of character spans of the original text, applied jointly.
+//! The original text and all counterfactuals are classified in batches by
+//! `SequenceClassificationModel::probe_counterfactuals`, which reports the scores of each label
+//! and their changes from the original prediction, along with the total variation distance and
+//! the Kullback-Leibler divergence between the original and counterfactual distributions.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::counterfactual::Counterfactual;
+//! use rust_bert::pipelines::sequence_classification::SequenceClassificationModel;
+//!
+//! let model = SequenceClassificationModel::new(Default::default())?;
+//! let text = "He is a nurse and he was very helpful.";
+//! let counterfactuals = [
+//!     Counterfactual::replace_all(text, "he", "she"),
+//!     Counterfactual::replace_all(text, "He", "She"),
+//!     Counterfactual::mask(8, 13),
+//! ];
+//! let report = model.probe_counterfactuals(text, &counterfactuals, 16)?;
+//! for output in &report.counterfactuals {
+//!     println!("{}: {:.3}", output.text, output.total_variation);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::pipelines::sequence_classification::Label;
+use crate::RustBertError;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// # Replacement of a span of the original text
+pub enum SpanReplacement {
+    /// Replaces the span with a single mask token of the tokenizer (e.g. `[MASK]`)
+    Mask,
+    /// Removes the span
+    Remove,
+    /// Replaces the span with another text
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// # Edit of a span of the original text
+pub struct SpanEdit {
+    /// Start of the span (character offset in the original text)
+    pub start: usize,
+    /// End of the span, exclusive (character offset in the original text)
+    pub end: usize,
+    /// Replacement of the span
+    pub replacement: SpanReplacement,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// # Counterfactual input, made of non-overlapping edits of the original text
+pub struct Counterfactual {
+    /// Edits applied jointly to the original text
+    pub edits: Vec<SpanEdit>,
+}
+
+impl Counterfactual {
+    /// Masks the characters `start..end` of the original text
+    pub fn mask(start: usize, end: usize) -> Counterfactual {
+        Counterfactual::default().with_edit(start, end, SpanReplacement::Mask)
+    }
+
+    /// Removes the characters `start..end` of the original text
+    pub fn remove(start: usize, end: usize) -> Counterfactual {
+        Counterfactual::default().with_edit(start, end, SpanReplacement::Remove)
+    }
+
+    /// Replaces the characters `start..end` of the original text with `replacement`
+    pub fn substitute(start: usize, end: usize, replacement: &str) -> Counterfactual {
+        Counterfactual::default().with_edit(
+            start,
+            end,
+            SpanReplacement::Text(replacement.to_string()),
+        )
+    }
+
+    /// Replaces all occurrences of the word `pattern` in `text` with `replacement`. Only whole
+    /// words match (occurrences inside longer words are left unchanged) and the match is case
+    /// sensitive.
+    pub fn replace_all(text: &str, pattern: &str, replacement: &str) -> Counterfactual {
+        let characters = text.chars().collect::<Vec<char>>();
+        let pattern = pattern.chars().collect::<Vec<char>>();
+        let mut counterfactual = Counterfactual::default();
+        if pattern.is_empty() {
+            return counterfactual;
+        }
+        let is_word_boundary = |position: Option<&char>| {
+            position.map_or(true, |character| !character.is_alphanumeric())
+        };
+        let mut start = 0;
+        while start + pattern.len() <= characters.len() {
+            let end = start + pattern.len();
+            if characters[start..end] == pattern[..]
+                && is_word_boundary(start.checked_sub(1).and_then(|index| characters.get(index)))
+                && is_word_boundary(characters.get(end))
+            {
+                counterfactual = counterfactual.with_edit(
+                    start,
+                    end,
+                    SpanReplacement::Text(replacement.to_string()),
+                );
+                start = end;
+            } else {
+                start += 1;
+            }
+        }
+        counterfactual
+    }
+
+    /// Adds an edit to the counterfactual
+    pub fn with_edit(
+        mut self,
+        start: usize,
+        end: usize,
+        replacement: SpanReplacement,
+    ) -> Counterfactual {
+        self.edits.push(SpanEdit {
+            start,
+            end,
+            replacement,
+        });
+        self
+    }
+
+    /// Applies the edits to the original text.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - original text
+    /// * `mask_token` - mask token of the tokenizer, required by `SpanReplacement::Mask` edits
+    ///
+    /// # Returns
+    ///
+    /// * `Result<String, RustBertError>` - counterfactual text, error if spans overlap or exceed the text
+    pub fn apply(&self, text: &str, mask_token: Option<&str>) -> Result<String, RustBertError> {
+        let characters = text.chars().collect::<Vec<char>>();
+        let mut edits = self.edits.iter().collect::<Vec<&SpanEdit>>();
+        edits.sort_by_key(|edit| (edit.start, edit.end));
+
+        let mut output = String::with_capacity(text.len());
+        let mut position = 0;
+        for edit in edits {
+            if edit.start < position || edit.start > edit.end || edit.end > characters.len() {
+                return Err(RustBertError::ValueError(format!(
+                    "Invalid span {}..{} for a text of {} characters (spans must not overlap)",
+                    edit.start,
+                    edit.end,
+                    characters.len()
+                )));
+            }
+            output.extend(&characters[position..edit.start]);
+            match &edit.replacement {
+                SpanReplacement::Mask => output.push_str(mask_token.ok_or_else(|| {
+                    RustBertError::InvalidConfigurationError(
+                        "The tokenizer of the model has no mask token".to_string(),
+                    )
+                })?),
+                SpanReplacement::Remove => {}
+                SpanReplacement::Text(replacement) => output.push_str(replacement),
+            }
+            position = edit.end;
+        }
+        output.extend(&characters[position..]);
+        Ok(output)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Output of the classifier for a counterfactual input
+pub struct CounterfactualOutput {
+    /// Counterfactual text
+    pub text: String,
+    /// Label predicted for the counterfactual text
+    pub label: Label,
+    /// True if the predicted label differs from the label of the original text
+    pub label_changed: bool,
+    /// Scores of all labels (indexed by label id)
+    pub scores: Vec<f64>,
+    /// Score of each label minus its score for the original text (indexed by label id)
+    pub score_changes: Vec<f64>,
+    /// Total variation distance between the original and counterfactual distributions (between 0 and 1)
+    pub total_variation: f64,
+    /// Kullback-Leibler divergence of the counterfactual distribution from the original distribution
+    pub kl_divergence: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Counterfactual probing report of a text
+pub struct CounterfactualReport {
+    /// Label predicted for the original text
+    pub label: Label,
+    /// Scores of all labels for the original text (indexed by label id)
+    pub scores: Vec<f64>,
+    /// Outputs for each counterfactual, in the order they were given
+    pub counterfactuals: Vec<CounterfactualOutput>,
+}
+
+/// Total variation distance between two discrete distributions
+pub(crate) fn total_variation(p: &[f64], q: &[f64]) -> f64 {
+    p.iter()
+        .zip(q.iter())
+        .map(|(p, q)| (p - q).abs())
+        .sum::<f64>()
+        / 2.0
+}
+
+/// Kullback-Leibler divergence KL(p || q) between two discrete distributions
+pub(crate) fn kl_divergence(p: &[f64], q: &[f64]) -> f64 {
+    p.iter()
+        .zip(q.iter())
+        .filter(|(p, _)| **p > 0.0)
+        .map(|(p, q)| p * (p / q.max(1e-12)).ln())
+        .sum()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counterfactual_edits() -> Result<(), RustBertError> {
+        let text = "He said he would help. The helper is here.";
+        let counterfactual = Counterfactual::replace_all(text, "he", "she");
+        assert_eq!(counterfactual.edits.len(), 1);
+        assert_eq!(
+            counterfactual.apply(text, None)?,
+            "He said she would help. The helper is here."
+        );
+
+        let counterfactual = Counterfactual::mask(0, 2)
+            .with_edit(17, 21, SpanReplacement::Remove)
+            .with_edit(8, 10, SpanReplacement::Text("she".to_string()));
+        assert_eq!(
+            counterfactual.apply(text, Some("[MASK]"))?,
+            "[MASK] said she would . The helper is here."
+        );
+        assert!(counterfactual.apply(text, None).is_err());
+        assert!(Counterfactual::remove(40, 50).apply(text, None).is_err());
+        assert!(Counterfactual::remove(0, 5)
+            .with_edit(3, 8, SpanReplacement::Remove)
+            .apply(text, None)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn distribution_distances() {
+        let p = [0.5, 0.5];
+        assert_eq!(total_variation(&p, &p), 0.0);
+        assert_eq!(kl_divergence(&p, &p), 0.0);
+        assert!((total_variation(&p, &[0.9, 0.1]) - 0.4).abs() < 1e-12);
+        let expected = 0.5 * (0.5f64 / 0.9).ln() + 0.5 * (0.5f64 / 0.1).ln();
+        assert!((kl_divergence(&p, &[0.9, 0.1]) - expected).abs() < 1e-12);
+        assert_eq!(kl_divergence(&[1.0, 0.0], &[1.0, 0.0]), 0.0);
+    }
+}
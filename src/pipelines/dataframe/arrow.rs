@@ -0,0 +1,129 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Arrow adapters (requires the `arrow` feature)
+//! Pipelines taking an Arrow `StringArray` as input and returning typed Arrow arrays.
+
+use crate::pipelines::dataframe::{polarity_name, predict_non_null};
+use crate::pipelines::sentence_embeddings::SentenceEmbeddingsModel;
+use crate::pipelines::sentiment::SentimentModel;
+use crate::pipelines::sequence_classification::SequenceClassificationModel;
+use crate::RustBertError;
+use ::arrow::array::{
+    ArrayRef, FixedSizeListArray, Float64Array, Int64Array, StringArray, StructArray,
+};
+use ::arrow::datatypes::{DataType, Field, Float32Type};
+use std::sync::Arc;
+
+/// Runs a text-to-text pipeline (e.g. summarization or translation) on an array of strings.
+///
+/// # Arguments
+///
+/// * `array` - `StringArray` of input texts
+/// * `predict` - closure running the pipeline on a batch of texts, returning one text per input
+///
+/// # Returns
+/// * `StringArray` of outputs, null for null inputs
+pub fn map_strings<F>(array: &StringArray, predict: F) -> Result<StringArray, RustBertError>
+where
+    F: FnOnce(&[&str]) -> Result<Vec<String>, RustBertError>,
+{
+    Ok(StringArray::from(predict_non_null(array.iter(), predict)?))
+}
+
+/// Classifies an array of strings with a sequence classification model.
+///
+/// # Returns
+/// * `StructArray` with the `label` (`Utf8`), `score` (`Float64`) and `id` (`Int64`) of the predicted labels, null for null inputs
+pub fn classify(
+    model: &SequenceClassificationModel,
+    array: &StringArray,
+) -> Result<StructArray, RustBertError> {
+    let labels = predict_non_null(array.iter(), |texts| Ok(model.predict(texts)))?;
+    let text: ArrayRef = Arc::new(StringArray::from(
+        labels
+            .iter()
+            .map(|label| label.as_ref().map(|label| label.text.as_str()))
+            .collect::<Vec<Option<&str>>>(),
+    ));
+    let score: ArrayRef = Arc::new(Float64Array::from(
+        labels
+            .iter()
+            .map(|label| label.as_ref().map(|label| label.score))
+            .collect::<Vec<Option<f64>>>(),
+    ));
+    let id: ArrayRef = Arc::new(Int64Array::from(
+        labels
+            .iter()
+            .map(|label| label.as_ref().map(|label| label.id))
+            .collect::<Vec<Option<i64>>>(),
+    ));
+    Ok(StructArray::from(vec![
+        (Field::new("label", DataType::Utf8, true), text),
+        (Field::new("score", DataType::Float64, true), score),
+        (Field::new("id", DataType::Int64, true), id),
+    ]))
+}
+
+/// Predicts the sentiment of an array of strings.
+///
+/// # Returns
+/// * `StructArray` with the `polarity` (`Utf8`, `positive` or `negative`) and `score` (`Float64`) of the predictions, null for null inputs
+pub fn sentiment(
+    model: &SentimentModel,
+    array: &StringArray,
+) -> Result<StructArray, RustBertError> {
+    let sentiments = predict_non_null(array.iter(), |texts| Ok(model.predict(texts)))?;
+    let polarity: ArrayRef = Arc::new(StringArray::from(
+        sentiments
+            .iter()
+            .map(|sentiment| {
+                sentiment
+                    .as_ref()
+                    .map(|sentiment| polarity_name(&sentiment.polarity))
+            })
+            .collect::<Vec<Option<&str>>>(),
+    ));
+    let score: ArrayRef = Arc::new(Float64Array::from(
+        sentiments
+            .iter()
+            .map(|sentiment| sentiment.as_ref().map(|sentiment| sentiment.score))
+            .collect::<Vec<Option<f64>>>(),
+    ));
+    Ok(StructArray::from(vec![
+        (Field::new("polarity", DataType::Utf8, true), polarity),
+        (Field::new("score", DataType::Float64, true), score),
+    ]))
+}
+
+/// Computes the sentence embeddings of an array of strings.
+///
+/// # Returns
+/// * `FixedSizeListArray` of `Float32` with one embedding per input, null for null inputs
+pub fn embed(
+    model: &SentenceEmbeddingsModel,
+    array: &StringArray,
+) -> Result<FixedSizeListArray, RustBertError> {
+    let embeddings = predict_non_null(array.iter(), |texts| model.encode(texts))?;
+    let embedding_dimension = match embeddings.iter().flatten().next() {
+        Some(embedding) => embedding.len(),
+        // All inputs are null: the dimension of the embeddings is obtained from an empty text
+        None => model.encode(&[""])?[0].len(),
+    };
+    Ok(
+        FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+            embeddings
+                .into_iter()
+                .map(|embedding| embedding.map(|embedding| embedding.into_iter().map(Some))),
+            embedding_dimension as i32,
+        ),
+    )
+}
@@ -0,0 +1,83 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Dataframe adapters for batch pipelines
+//! Adapters running pipelines on columns of dataframe-based ETL jobs:
+//! - `arrow` (requires the `arrow` feature) takes an Arrow `StringArray` as input and returns Arrow arrays
+//! - `polars` (requires the `polars` feature) takes a Polars `Series` of strings as input and returns Polars series
+//!
+//! Both adapters provide the same functions:
+//! - `map_strings` runs any text-to-text pipeline (summarization, translation, generation...) and returns strings
+//! - `classify` runs a `SequenceClassificationModel` and returns a struct array with the `label`, `score` and `id` of the predicted labels
+//! - `sentiment` runs a `SentimentModel` and returns a struct array with the `polarity` and `score` of the predictions
+//! - `embed` runs a `SentenceEmbeddingsModel` and returns the embeddings as a list array of `f32` (a `FixedSizeList` for Arrow)
+//!
+//! Null inputs are not passed to the pipeline and result in null outputs.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use arrow::array::StringArray;
+//! use rust_bert::pipelines::dataframe::arrow::sentiment;
+//! use rust_bert::pipelines::sentiment::SentimentModel;
+//!
+//! let model = SentimentModel::new(Default::default())?;
+//! let reviews = StringArray::from(vec![Some("Great movie!"), None, Some("Terrible plot.")]);
+//! let sentiments = sentiment(&model, &reviews)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::pipelines::sentiment::SentimentPolarity;
+use crate::RustBertError;
+
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "polars")]
+pub mod polars;
+
+/// Runs `predict` on the non-null inputs only and returns the outputs aligned with the inputs,
+/// with `None` for null inputs.
+pub(crate) fn predict_non_null<'a, T, I, F>(
+    inputs: I,
+    predict: F,
+) -> Result<Vec<Option<T>>, RustBertError>
+where
+    I: IntoIterator<Item = Option<&'a str>>,
+    F: FnOnce(&[&'a str]) -> Result<Vec<T>, RustBertError>,
+{
+    let inputs = inputs.into_iter().collect::<Vec<Option<&str>>>();
+    let texts = inputs.iter().flatten().copied().collect::<Vec<&str>>();
+    let num_texts = texts.len();
+    let mut outputs = if texts.is_empty() {
+        vec![]
+    } else {
+        predict(&texts)?
+    };
+    if outputs.len() != num_texts {
+        return Err(RustBertError::ValueError(format!(
+            "The pipeline returned {} outputs for {} inputs",
+            outputs.len(),
+            num_texts
+        )));
+    }
+    outputs.reverse();
+    Ok(inputs
+        .iter()
+        .map(|input| input.and_then(|_| outputs.pop()))
+        .collect())
+}
+
+pub(crate) fn polarity_name(polarity: &SentimentPolarity) -> &'static str {
+    match polarity {
+        SentimentPolarity::Positive => "positive",
+        SentimentPolarity::Negative => "negative",
+    }
+}
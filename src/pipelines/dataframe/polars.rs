@@ -0,0 +1,130 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Polars adapters (requires the `polars` feature)
+//! Pipelines taking a Polars `Series` of strings as input and returning Polars series named after
+//! the input series.
+
+use crate::pipelines::dataframe::{polarity_name, predict_non_null};
+use crate::pipelines::sentence_embeddings::SentenceEmbeddingsModel;
+use crate::pipelines::sentiment::SentimentModel;
+use crate::pipelines::sequence_classification::SequenceClassificationModel;
+use crate::RustBertError;
+use ::polars::prelude::{
+    IntoSeries, ListChunked, NamedFrom, PolarsError, Series, StructChunked, Utf8Chunked,
+};
+
+fn polars_error(error: PolarsError) -> RustBertError {
+    RustBertError::ValueError(error.to_string())
+}
+
+fn utf8(series: &Series) -> Result<&Utf8Chunked, RustBertError> {
+    series.utf8().map_err(polars_error)
+}
+
+/// Runs a text-to-text pipeline (e.g. summarization or translation) on a series of strings.
+///
+/// # Arguments
+///
+/// * `series` - `Series` of input texts (`Utf8` data type)
+/// * `predict` - closure running the pipeline on a batch of texts, returning one text per input
+///
+/// # Returns
+/// * `Series` of outputs (`Utf8`), null for null inputs
+pub fn map_strings<F>(series: &Series, predict: F) -> Result<Series, RustBertError>
+where
+    F: FnOnce(&[&str]) -> Result<Vec<String>, RustBertError>,
+{
+    let outputs = predict_non_null(utf8(series)?.into_iter(), predict)?;
+    Ok(Series::new(series.name(), outputs))
+}
+
+/// Classifies a series of strings with a sequence classification model.
+///
+/// # Returns
+/// * `Series` of structs with the `label` (`Utf8`), `score` (`Float64`) and `id` (`Int64`) of the predicted labels, null for null inputs
+pub fn classify(
+    model: &SequenceClassificationModel,
+    series: &Series,
+) -> Result<Series, RustBertError> {
+    let labels = predict_non_null(utf8(series)?.into_iter(), |texts| Ok(model.predict(texts)))?;
+    let fields = [
+        Series::new(
+            "label",
+            labels
+                .iter()
+                .map(|label| label.as_ref().map(|label| label.text.as_str()))
+                .collect::<Vec<Option<&str>>>(),
+        ),
+        Series::new(
+            "score",
+            labels
+                .iter()
+                .map(|label| label.as_ref().map(|label| label.score))
+                .collect::<Vec<Option<f64>>>(),
+        ),
+        Series::new(
+            "id",
+            labels
+                .iter()
+                .map(|label| label.as_ref().map(|label| label.id))
+                .collect::<Vec<Option<i64>>>(),
+        ),
+    ];
+    Ok(StructChunked::new(series.name(), &fields)
+        .map_err(polars_error)?
+        .into_series())
+}
+
+/// Predicts the sentiment of a series of strings.
+///
+/// # Returns
+/// * `Series` of structs with the `polarity` (`Utf8`, `positive` or `negative`) and `score` (`Float64`) of the predictions, null for null inputs
+pub fn sentiment(model: &SentimentModel, series: &Series) -> Result<Series, RustBertError> {
+    let sentiments = predict_non_null(utf8(series)?.into_iter(), |texts| Ok(model.predict(texts)))?;
+    let fields = [
+        Series::new(
+            "polarity",
+            sentiments
+                .iter()
+                .map(|sentiment| {
+                    sentiment
+                        .as_ref()
+                        .map(|sentiment| polarity_name(&sentiment.polarity))
+                })
+                .collect::<Vec<Option<&str>>>(),
+        ),
+        Series::new(
+            "score",
+            sentiments
+                .iter()
+                .map(|sentiment| sentiment.as_ref().map(|sentiment| sentiment.score))
+                .collect::<Vec<Option<f64>>>(),
+        ),
+    ];
+    Ok(StructChunked::new(series.name(), &fields)
+        .map_err(polars_error)?
+        .into_series())
+}
+
+/// Computes the sentence embeddings of a series of strings.
+///
+/// # Returns
+/// * `Series` of lists of `Float32` with one embedding per input, null for null inputs
+pub fn embed(model: &SentenceEmbeddingsModel, series: &Series) -> Result<Series, RustBertError> {
+    let embeddings = predict_non_null(utf8(series)?.into_iter(), |texts| model.encode(texts))?;
+    let mut embeddings = embeddings
+        .into_iter()
+        .map(|embedding| embedding.map(|embedding| Series::new("", embedding)))
+        .collect::<ListChunked>();
+    embeddings.rename(series.name());
+    Ok(embeddings.into_series())
+}
@@ -0,0 +1,221 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Emotion classification pipeline
+//! Multi-label detection of the emotions expressed in a text (e.g. `admiration`, `anger`, `joy`,
+//! `sadness`... for models trained on the GoEmotions dataset). Contrary to the binary
+//! [sentiment](crate::pipelines::sentiment) pipeline, several emotions may be detected for a single
+//! input: each emotion is scored independently (sigmoid) and returned when its score exceeds the
+//! threshold configured for the emotion.
+//!
+//! The emotions are read from the label mapping of the sequence classification model: any
+//! multi-label emotion classifier supported by the `SequenceClassificationModel` can be used.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::common::ModelType;
+//! use rust_bert::pipelines::emotion::{EmotionConfig, EmotionModel};
+//! use rust_bert::pipelines::sequence_classification::SequenceClassificationConfig;
+//! use rust_bert::resources::LocalResource;
+//! use std::path::PathBuf;
+//!
+//! let sequence_classification_config = SequenceClassificationConfig::new(
+//!     ModelType::Roberta,
+//!     LocalResource::from(PathBuf::from("path/to/rust_model.ot")),
+//!     LocalResource::from(PathBuf::from("path/to/config.json")),
+//!     LocalResource::from(PathBuf::from("path/to/vocab.json")),
+//!     Some(LocalResource::from(PathBuf::from("path/to/merges.txt"))),
+//!     false,
+//!     None,
+//!     None,
+//! );
+//! let mut config = EmotionConfig::new(sequence_classification_config);
+//! config.thresholds.insert("neutral".to_string(), 0.6);
+//!
+//! let emotion_model = EmotionModel::new(config)?;
+//! let output = emotion_model.predict(&["Thank you so much, this made my day!"]);
+//! for prediction in output {
+//!     println!("{:?}", prediction.emotions);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::common::error::RustBertError;
+use crate::pipelines::calibration::Calibrator;
+use crate::pipelines::sequence_classification::{
+    labels_above_threshold, Label, ScoreNormalization, SequenceClassificationConfig,
+    SequenceClassificationModel,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Serialize, Deserialize)]
+/// # Configuration for EmotionModel
+/// Contains the sequence classification model configuration and the detection thresholds.
+pub struct EmotionConfig {
+    /// `SequenceClassificationConfig` of the multi-label emotion classifier
+    pub sequence_classification_config: SequenceClassificationConfig,
+    /// Threshold above which an emotion is detected, if no emotion specific threshold is given (default: 0.3)
+    pub default_threshold: f64,
+    /// Emotion specific thresholds, indexed by label name
+    pub thresholds: HashMap<String, f64>,
+    /// Optional maximum number of emotions returned for each input, keeping the highest scores (default: None)
+    pub max_emotions: Option<usize>,
+    /// Maximum number of inputs processed by the model in a single forward pass (default: 32)
+    pub batch_size: usize,
+}
+
+impl EmotionConfig {
+    /// Instantiate a new emotion configuration from a sequence classification configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `sequence_classification_config` - `SequenceClassificationConfig` of the multi-label emotion classifier
+    pub fn new(sequence_classification_config: SequenceClassificationConfig) -> EmotionConfig {
+        EmotionConfig {
+            sequence_classification_config,
+            default_threshold: 0.3,
+            thresholds: HashMap::new(),
+            max_emotions: None,
+            batch_size: 32,
+        }
+    }
+}
+
+impl From<SequenceClassificationConfig> for EmotionConfig {
+    fn from(sequence_classification_config: SequenceClassificationConfig) -> Self {
+        EmotionConfig::new(sequence_classification_config)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Emotion prediction for a single input
+pub struct EmotionPrediction {
+    /// Scores of all emotions, sorted by decreasing score
+    pub scores: Vec<Label>,
+    /// Detected emotions (score above their threshold), sorted by decreasing score
+    pub emotions: Vec<Label>,
+}
+
+impl EmotionPrediction {
+    /// Returns the detected emotion with the highest score, if any
+    pub fn dominant_emotion(&self) -> Option<&Label> {
+        self.emotions.first()
+    }
+}
+
+/// # EmotionModel for multi-label emotion classification
+pub struct EmotionModel {
+    sequence_classification_model: SequenceClassificationModel,
+    default_threshold: f64,
+    thresholds: HashMap<String, f64>,
+    max_emotions: Option<usize>,
+    batch_size: usize,
+}
+
+impl EmotionModel {
+    /// Build a new `EmotionModel`
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `EmotionConfig` object containing the classifier resources and the detection thresholds
+    pub fn new(config: EmotionConfig) -> Result<EmotionModel, RustBertError> {
+        for (label, threshold) in config
+            .thresholds
+            .iter()
+            .map(|(label, threshold)| (label.as_str(), threshold))
+            .chain(std::iter::once(("default", &config.default_threshold)))
+        {
+            if !(0.0..=1.0).contains(threshold) {
+                return Err(RustBertError::InvalidConfigurationError(format!(
+                    "Emotion threshold for {} must be between 0 and 1, got {}",
+                    label, threshold
+                )));
+            }
+        }
+        if config.max_emotions == Some(0) {
+            return Err(RustBertError::InvalidConfigurationError(
+                "max_emotions must be strictly greater than 0".to_string(),
+            ));
+        }
+        if config.batch_size == 0 {
+            return Err(RustBertError::InvalidConfigurationError(
+                "batch_size must be strictly greater than 0".to_string(),
+            ));
+        }
+        let sequence_classification_model =
+            SequenceClassificationModel::new(config.sequence_classification_config)?;
+        Ok(EmotionModel {
+            sequence_classification_model,
+            default_threshold: config.default_threshold,
+            thresholds: config.thresholds,
+            max_emotions: config.max_emotions,
+            batch_size: config.batch_size,
+        })
+    }
+
+    /// Sets a calibrator converting the logits of the classifier to calibrated probabilities,
+    /// so that the detection thresholds can be set on trustworthy probabilities
+    ///
+    /// # Arguments
+    ///
+    /// * `calibrator` - `Calibrator` fitted on a validation set
+    pub fn with_calibrator<C>(mut self, calibrator: C) -> EmotionModel
+    where
+        C: Calibrator + 'static,
+    {
+        self.sequence_classification_model = self
+            .sequence_classification_model
+            .with_calibrator(calibrator);
+        self
+    }
+
+    /// Detect the emotions expressed in texts
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - `&[&str]` Array of texts to classify.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<EmotionPrediction>` containing the emotion scores and detected emotions for each input text
+    pub fn predict<S>(&self, input: &[S]) -> Vec<EmotionPrediction>
+    where
+        S: AsRef<str>,
+    {
+        let input = input.iter().map(AsRef::as_ref).collect::<Vec<&str>>();
+        let mut output = Vec::with_capacity(input.len());
+        for batch in input.chunks(self.batch_size) {
+            for mut scores in self
+                .sequence_classification_model
+                .predict_full(batch, ScoreNormalization::Sigmoid)
+            {
+                let sentence = output.len();
+                for label in scores.iter_mut() {
+                    label.sentence = sentence;
+                }
+                let emotions = self.detect_emotions(&scores);
+                output.push(EmotionPrediction { scores, emotions });
+            }
+        }
+        output
+    }
+
+    fn detect_emotions(&self, scores: &[Label]) -> Vec<Label> {
+        let mut emotions = labels_above_threshold(scores, self.default_threshold, &self.thresholds);
+        emotions.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+        if let Some(max_emotions) = self.max_emotions {
+            emotions.truncate(max_emotions);
+        }
+        emotions
+    }
+}
@@ -0,0 +1,636 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::pipelines::sequence_classification::SequenceClassificationModel;
+use crate::pipelines::token_classification::TokenClassificationModel;
+use crate::RustBertError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+/// # Confusion matrix of classification predictions
+/// Rows correspond to the expected labels and columns to the predicted labels, in the order the
+/// labels were first seen.
+pub struct ConfusionMatrix {
+    labels: Vec<String>,
+    counts: Vec<Vec<usize>>,
+    #[serde(skip)]
+    label_indices: HashMap<String, usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Scores of a single label
+pub struct LabelScores {
+    /// Label
+    pub label: String,
+    /// Fraction of the predictions of the label that are correct
+    pub precision: f64,
+    /// Fraction of the examples of the label that are predicted correctly
+    pub recall: f64,
+    /// Harmonic mean of the precision and recall
+    pub f1: f64,
+    /// Number of examples of the label
+    pub support: usize,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+/// # Averaged precision, recall and F1 score
+pub struct AveragedScores {
+    /// Averaged precision
+    pub precision: f64,
+    /// Averaged recall
+    pub recall: f64,
+    /// Averaged F1 score
+    pub f1: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Classification metrics computed from a `ConfusionMatrix`
+pub struct ClassificationReport {
+    /// Fraction of correct predictions (over all labels)
+    pub accuracy: f64,
+    /// Scores computed from the true positives, false positives and false negatives summed over the labels
+    pub micro_average: AveragedScores,
+    /// Unweighted mean of the scores of the labels
+    pub macro_average: AveragedScores,
+    /// Scores of each label
+    pub labels: Vec<LabelScores>,
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f64 / denominator as f64
+    }
+}
+
+fn f1_score(precision: f64, recall: f64) -> f64 {
+    if precision + recall == 0.0 {
+        0.0
+    } else {
+        2.0 * precision * recall / (precision + recall)
+    }
+}
+
+impl ConfusionMatrix {
+    /// Creates an empty confusion matrix
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Creates a confusion matrix from the expected and predicted labels of a dataset
+    ///
+    /// # Arguments
+    ///
+    /// * `expected` - expected labels
+    /// * `predicted` - predicted labels, in the same order as the expected labels
+    pub fn from_predictions<S, T>(expected: &[S], predicted: &[T]) -> Result<Self, RustBertError>
+    where
+        S: AsRef<str>,
+        T: AsRef<str>,
+    {
+        if expected.len() != predicted.len() {
+            return Err(RustBertError::ValueError(format!(
+                "Got {} expected labels but {} predicted labels",
+                expected.len(),
+                predicted.len()
+            )));
+        }
+        let mut confusion_matrix = ConfusionMatrix::new();
+        for (expected, predicted) in expected.iter().zip(predicted) {
+            confusion_matrix.add(expected.as_ref(), predicted.as_ref());
+        }
+        Ok(confusion_matrix)
+    }
+
+    fn label_index(&mut self, label: &str) -> usize {
+        if self.label_indices.len() != self.labels.len() {
+            // Indices are not serialized: rebuild them after deserialization
+            self.label_indices = self
+                .labels
+                .iter()
+                .enumerate()
+                .map(|(index, label)| (label.clone(), index))
+                .collect();
+        }
+        if let Some(index) = self.label_indices.get(label) {
+            return *index;
+        }
+        let index = self.labels.len();
+        self.labels.push(label.to_string());
+        self.label_indices.insert(label.to_string(), index);
+        for row in self.counts.iter_mut() {
+            row.push(0);
+        }
+        self.counts.push(vec![0; index + 1]);
+        index
+    }
+
+    /// Adds a prediction to the matrix
+    ///
+    /// # Arguments
+    ///
+    /// * `expected` - expected label
+    /// * `predicted` - predicted label
+    pub fn add(&mut self, expected: &str, predicted: &str) {
+        let expected = self.label_index(expected);
+        let predicted = self.label_index(predicted);
+        self.counts[expected][predicted] += 1;
+    }
+
+    /// Adds the predictions of another confusion matrix (e.g. computed on another part of a dataset)
+    pub fn merge(&mut self, other: &ConfusionMatrix) {
+        for (expected_index, expected) in other.labels.iter().enumerate() {
+            for (predicted_index, predicted) in other.labels.iter().enumerate() {
+                let count = other.counts[expected_index][predicted_index];
+                if count > 0 {
+                    let expected = self.label_index(expected);
+                    let predicted = self.label_index(predicted);
+                    self.counts[expected][predicted] += count;
+                }
+            }
+        }
+    }
+
+    /// Returns the labels of the matrix, in the order of its rows and columns
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// Returns the counts of the matrix (rows: expected labels, columns: predicted labels)
+    pub fn counts(&self) -> &[Vec<usize>] {
+        &self.counts
+    }
+
+    /// Returns the number of examples with an expected and predicted label
+    pub fn count(&self, expected: &str, predicted: &str) -> usize {
+        let position = |label: &str| self.labels.iter().position(|value| value == label);
+        match (position(expected), position(predicted)) {
+            (Some(expected), Some(predicted)) => self.counts[expected][predicted],
+            _ => 0,
+        }
+    }
+
+    /// Returns the total number of predictions
+    pub fn total(&self) -> usize {
+        self.counts.iter().flatten().sum()
+    }
+
+    /// Returns the fraction of correct predictions
+    pub fn accuracy(&self) -> f64 {
+        let correct = (0..self.labels.len()).map(|i| self.counts[i][i]).sum();
+        ratio(correct, self.total())
+    }
+
+    /// Computes the classification metrics over all labels
+    pub fn report(&self) -> ClassificationReport {
+        self.report_excluding::<&str>(&[])
+    }
+
+    /// Computes the classification metrics, excluding some labels (e.g. the outside label `O` for
+    /// token classification) from the per-label scores and averages. The accuracy is computed
+    /// over all labels.
+    ///
+    /// # Arguments
+    ///
+    /// * `excluded_labels` - labels excluded from the per-label scores and averages
+    pub fn report_excluding<S: AsRef<str>>(&self, excluded_labels: &[S]) -> ClassificationReport {
+        let mut labels = vec![];
+        let (mut true_positives, mut predicted, mut expected) = (0, 0, 0);
+        for (index, label) in self.labels.iter().enumerate() {
+            if excluded_labels
+                .iter()
+                .any(|excluded| excluded.as_ref() == label)
+            {
+                continue;
+            }
+            let label_true_positives = self.counts[index][index];
+            let label_predicted = self.counts.iter().map(|row| row[index]).sum();
+            let label_support = self.counts[index].iter().sum();
+            true_positives += label_true_positives;
+            predicted += label_predicted;
+            expected += label_support;
+
+            let precision = ratio(label_true_positives, label_predicted);
+            let recall = ratio(label_true_positives, label_support);
+            labels.push(LabelScores {
+                label: label.clone(),
+                precision,
+                recall,
+                f1: f1_score(precision, recall),
+                support: label_support,
+            });
+        }
+
+        let micro_precision = ratio(true_positives, predicted);
+        let micro_recall = ratio(true_positives, expected);
+        let mean = |score: fn(&LabelScores) -> f64| {
+            if labels.is_empty() {
+                0.0
+            } else {
+                labels.iter().map(score).sum::<f64>() / labels.len() as f64
+            }
+        };
+        let macro_average = AveragedScores {
+            precision: mean(|scores| scores.precision),
+            recall: mean(|scores| scores.recall),
+            f1: mean(|scores| scores.f1),
+        };
+        ClassificationReport {
+            accuracy: self.accuracy(),
+            micro_average: AveragedScores {
+                precision: micro_precision,
+                recall: micro_recall,
+                f1: f1_score(micro_precision, micro_recall),
+            },
+            macro_average,
+            labels,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Comparison of a candidate model against a reference model on a labeled dataset
+/// Typically used to check that a model converted to a lower precision (e.g. half precision or
+/// quantized weights) can replace its full precision reference.
+pub struct ModelComparisonReport {
+    /// Classification metrics of the reference model
+    pub reference: ClassificationReport,
+    /// Classification metrics of the candidate model
+    pub candidate: ClassificationReport,
+    /// Accuracy of the candidate minus accuracy of the reference
+    pub accuracy_delta: f64,
+    /// Macro-averaged F1 score of the candidate minus macro-averaged F1 score of the reference
+    pub macro_f1_delta: f64,
+    /// Fraction of examples for which both models predict the same label
+    pub agreement: f64,
+    /// Mean cosine similarity between the logits of the two models
+    pub mean_logits_similarity: f64,
+    /// Maximum absolute difference between the logits of the two models
+    pub max_logits_difference: f64,
+}
+
+fn cosine_similarity(a: &[f64], b: &[f64]) -> f64 {
+    let dot_product = a.iter().zip(b).map(|(a, b)| a * b).sum::<f64>();
+    let norms =
+        a.iter().map(|a| a * a).sum::<f64>().sqrt() * b.iter().map(|b| b * b).sum::<f64>().sqrt();
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot_product / norms
+    }
+}
+
+impl ModelComparisonReport {
+    /// Builds a comparison report from the predictions of the reference and candidate models
+    ///
+    /// # Arguments
+    ///
+    /// * `expected` - expected labels
+    /// * `reference_predictions` - labels predicted by the reference model
+    /// * `candidate_predictions` - labels predicted by the candidate model
+    /// * `reference_logits` - logits of the reference model for each example
+    /// * `candidate_logits` - logits of the candidate model for each example
+    pub fn from_predictions<S, T>(
+        expected: &[S],
+        reference_predictions: &[T],
+        candidate_predictions: &[T],
+        reference_logits: &[Vec<f64>],
+        candidate_logits: &[Vec<f64>],
+    ) -> Result<Self, RustBertError>
+    where
+        S: AsRef<str>,
+        T: AsRef<str>,
+    {
+        if [
+            candidate_predictions.len(),
+            reference_logits.len(),
+            candidate_logits.len(),
+        ]
+        .iter()
+        .any(|&length| length != reference_predictions.len())
+        {
+            return Err(RustBertError::ValueError(
+                "The predictions and logits of both models must cover the same examples"
+                    .to_string(),
+            ));
+        }
+        if reference_logits
+            .iter()
+            .zip(candidate_logits)
+            .any(|(reference, candidate)| reference.len() != candidate.len())
+        {
+            return Err(RustBertError::ValueError(
+                "The reference and candidate models must have the same number of labels"
+                    .to_string(),
+            ));
+        }
+        let reference =
+            ConfusionMatrix::from_predictions(expected, reference_predictions)?.report();
+        let candidate =
+            ConfusionMatrix::from_predictions(expected, candidate_predictions)?.report();
+        let num_agreements = reference_predictions
+            .iter()
+            .zip(candidate_predictions)
+            .filter(|(reference, candidate)| reference.as_ref() == candidate.as_ref())
+            .count();
+        let mean_logits_similarity = if reference_logits.is_empty() {
+            0.0
+        } else {
+            reference_logits
+                .iter()
+                .zip(candidate_logits)
+                .map(|(reference, candidate)| cosine_similarity(reference, candidate))
+                .sum::<f64>()
+                / reference_logits.len() as f64
+        };
+        let max_logits_difference = reference_logits
+            .iter()
+            .zip(candidate_logits)
+            .flat_map(|(reference, candidate)| {
+                reference
+                    .iter()
+                    .zip(candidate)
+                    .map(|(reference, candidate)| (reference - candidate).abs())
+            })
+            .fold(0.0, f64::max);
+
+        Ok(ModelComparisonReport {
+            accuracy_delta: candidate.accuracy - reference.accuracy,
+            macro_f1_delta: candidate.macro_average.f1 - reference.macro_average.f1,
+            agreement: ratio(num_agreements, reference_predictions.len()),
+            mean_logits_similarity,
+            max_logits_difference,
+            reference,
+            candidate,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Sentence labeled at the word level, for token classification evaluation
+pub struct LabeledWords {
+    /// Words of the sentence
+    pub words: Vec<String>,
+    /// Expected label of each word
+    pub labels: Vec<String>,
+}
+
+/// Runs a sequence classification model on a labeled dataset and returns the confusion matrix of
+/// its predictions.
+///
+/// # Arguments
+///
+/// * `model` - `SequenceClassificationModel` to evaluate
+/// * `dataset` - (text, expected label) pairs
+/// * `batch_size` - number of texts per forward pass
+pub fn evaluate_sequence_classification<S, L>(
+    model: &SequenceClassificationModel,
+    dataset: &[(S, L)],
+    batch_size: usize,
+) -> Result<ConfusionMatrix, RustBertError>
+where
+    S: AsRef<str>,
+    L: AsRef<str>,
+{
+    if batch_size == 0 {
+        return Err(RustBertError::InvalidConfigurationError(
+            "The batch size must be greater than 0".to_string(),
+        ));
+    }
+    let mut confusion_matrix = ConfusionMatrix::new();
+    for batch in dataset.chunks(batch_size) {
+        let texts = batch
+            .iter()
+            .map(|(text, _)| text.as_ref())
+            .collect::<Vec<&str>>();
+        let predictions = model.predict(texts.as_slice());
+        for ((_, expected), predicted) in batch.iter().zip(predictions) {
+            confusion_matrix.add(expected.as_ref(), &predicted.text);
+        }
+    }
+    Ok(confusion_matrix)
+}
+
+/// Runs a reference and a candidate sequence classification model (e.g. full precision and
+/// quantized versions of the same model) on a labeled dataset and reports the difference of their
+/// accuracy and the similarity of their predictions.
+///
+/// # Arguments
+///
+/// * `reference` - reference `SequenceClassificationModel`
+/// * `candidate` - `SequenceClassificationModel` compared to the reference
+/// * `dataset` - (text, expected label) pairs
+/// * `batch_size` - number of texts per forward pass
+pub fn compare_sequence_classification<S, L>(
+    reference: &SequenceClassificationModel,
+    candidate: &SequenceClassificationModel,
+    dataset: &[(S, L)],
+    batch_size: usize,
+) -> Result<ModelComparisonReport, RustBertError>
+where
+    S: AsRef<str>,
+    L: AsRef<str>,
+{
+    if batch_size == 0 {
+        return Err(RustBertError::InvalidConfigurationError(
+            "The batch size must be greater than 0".to_string(),
+        ));
+    }
+    let mut reference_predictions = Vec::with_capacity(dataset.len());
+    let mut candidate_predictions = Vec::with_capacity(dataset.len());
+    let mut reference_logits = Vec::with_capacity(dataset.len());
+    let mut candidate_logits = Vec::with_capacity(dataset.len());
+    for batch in dataset.chunks(batch_size) {
+        let texts = batch
+            .iter()
+            .map(|(text, _)| text.as_ref())
+            .collect::<Vec<&str>>();
+        reference_predictions.extend(
+            reference
+                .predict(texts.as_slice())
+                .into_iter()
+                .map(|label| label.text),
+        );
+        candidate_predictions.extend(
+            candidate
+                .predict(texts.as_slice())
+                .into_iter()
+                .map(|label| label.text),
+        );
+        reference_logits.extend(reference.predict_logits(texts.as_slice()));
+        candidate_logits.extend(candidate.predict_logits(texts.as_slice()));
+    }
+    let expected = dataset
+        .iter()
+        .map(|(_, label)| label.as_ref())
+        .collect::<Vec<&str>>();
+    ModelComparisonReport::from_predictions(
+        &expected,
+        &reference_predictions,
+        &candidate_predictions,
+        &reference_logits,
+        &candidate_logits,
+    )
+}
+
+/// Runs a token classification model on sentences labeled at the word level and returns the
+/// confusion matrix of its word-level predictions. The words of each sentence are joined with
+/// spaces, and each word takes the label of the (consolidated) predicted token starting at the
+/// word. Words without a predicted token take the `default_label`.
+///
+/// # Arguments
+///
+/// * `model` - `TokenClassificationModel` to evaluate
+/// * `dataset` - `LabeledWords` sentences
+/// * `batch_size` - number of sentences per forward pass
+/// * `default_label` - label of the words without prediction (e.g. `O`)
+pub fn evaluate_token_classification(
+    model: &TokenClassificationModel,
+    dataset: &[LabeledWords],
+    batch_size: usize,
+    default_label: &str,
+) -> Result<ConfusionMatrix, RustBertError> {
+    if batch_size == 0 {
+        return Err(RustBertError::InvalidConfigurationError(
+            "The batch size must be greater than 0".to_string(),
+        ));
+    }
+    let mut confusion_matrix = ConfusionMatrix::new();
+    for batch in dataset.chunks(batch_size) {
+        let mut texts = Vec::with_capacity(batch.len());
+        let mut word_offsets = Vec::with_capacity(batch.len());
+        for sentence in batch {
+            if sentence.words.len() != sentence.labels.len() {
+                return Err(RustBertError::ValueError(format!(
+                    "Got {} words but {} labels",
+                    sentence.words.len(),
+                    sentence.labels.len()
+                )));
+            }
+            let mut offsets = Vec::with_capacity(sentence.words.len());
+            let mut position = 0;
+            for word in &sentence.words {
+                offsets.push(position);
+                position += word.chars().count() as u32 + 1;
+            }
+            texts.push(sentence.words.join(" "));
+            word_offsets.push(offsets);
+        }
+        let predictions = model.predict(&texts, true, false);
+        for ((sentence, offsets), tokens) in batch.iter().zip(word_offsets).zip(predictions) {
+            for (expected, offset) in sentence.labels.iter().zip(offsets) {
+                let predicted = tokens
+                    .iter()
+                    .find(|token| {
+                        token.offset.map_or(false, |token_offset| {
+                            token_offset.begin <= offset && offset < token_offset.end
+                        })
+                    })
+                    .map_or(default_label, |token| token.label.as_str());
+                confusion_matrix.add(expected, predicted);
+            }
+        }
+    }
+    Ok(confusion_matrix)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn classification_report() -> Result<(), RustBertError> {
+        let expected = ["cat", "cat", "cat", "dog", "dog", "bird"];
+        let predicted = ["cat", "cat", "dog", "dog", "cat", "bird"];
+        let confusion_matrix = ConfusionMatrix::from_predictions(&expected, &predicted)?;
+
+        assert_eq!(confusion_matrix.labels(), ["cat", "dog", "bird"]);
+        assert_eq!(confusion_matrix.counts(), [[2, 1, 0], [1, 1, 0], [0, 0, 1]]);
+        assert_eq!(confusion_matrix.count("dog", "cat"), 1);
+        assert_eq!(confusion_matrix.total(), 6);
+
+        let report = confusion_matrix.report();
+        assert!((report.accuracy - 4.0 / 6.0).abs() < 1e-9);
+        assert!((report.micro_average.f1 - 4.0 / 6.0).abs() < 1e-9);
+        assert!((report.labels[0].precision - 2.0 / 3.0).abs() < 1e-9);
+        assert!((report.labels[1].recall - 0.5).abs() < 1e-9);
+        assert_eq!(report.labels[2].support, 1);
+        let expected_macro_f1 = (2.0 / 3.0 + 0.5 + 1.0) / 3.0;
+        assert!((report.macro_average.f1 - expected_macro_f1).abs() < 1e-9);
+
+        let report = confusion_matrix.report_excluding(&["bird"]);
+        assert_eq!(report.labels.len(), 2);
+        assert!((report.micro_average.precision - 3.0 / 5.0).abs() < 1e-9);
+
+        assert!(ConfusionMatrix::from_predictions(&expected, &predicted[1..]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn merge_confusion_matrices() -> Result<(), RustBertError> {
+        let mut confusion_matrix = ConfusionMatrix::from_predictions(&["a", "b"], &["a", "a"])?;
+        let other = ConfusionMatrix::from_predictions(&["c", "b"], &["c", "b"])?;
+        confusion_matrix.merge(&other);
+
+        assert_eq!(confusion_matrix.labels(), ["a", "b", "c"]);
+        assert_eq!(confusion_matrix.total(), 4);
+        assert_eq!(confusion_matrix.count("b", "a"), 1);
+        assert_eq!(confusion_matrix.count("b", "b"), 1);
+        assert_eq!(confusion_matrix.count("c", "c"), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn model_comparison_report() -> Result<(), RustBertError> {
+        let expected = ["a", "a", "b", "b"];
+        let reference_predictions = ["a", "a", "b", "a"];
+        let candidate_predictions = ["a", "b", "b", "a"];
+        let reference_logits = vec![
+            vec![2.0, 0.0],
+            vec![1.0, 1.0],
+            vec![0.0, 3.0],
+            vec![1.0, 0.0],
+        ];
+        let candidate_logits = vec![
+            vec![2.0, 0.0],
+            vec![1.0, 1.5],
+            vec![0.0, 3.0],
+            vec![1.0, 0.0],
+        ];
+        let report = ModelComparisonReport::from_predictions(
+            &expected,
+            &reference_predictions,
+            &candidate_predictions,
+            &reference_logits,
+            &candidate_logits,
+        )?;
+
+        assert!((report.reference.accuracy - 0.75).abs() < 1e-9);
+        assert!((report.candidate.accuracy - 0.5).abs() < 1e-9);
+        assert!((report.accuracy_delta + 0.25).abs() < 1e-9);
+        assert!((report.agreement - 0.75).abs() < 1e-9);
+        assert!((report.max_logits_difference - 0.5).abs() < 1e-9);
+        let second_similarity = 2.5 / (2f64.sqrt() * 3.25f64.sqrt());
+        assert!((report.mean_logits_similarity - (3.0 + second_similarity) / 4.0).abs() < 1e-9);
+
+        assert!(ModelComparisonReport::from_predictions(
+            &expected,
+            &reference_predictions,
+            &candidate_predictions[1..],
+            &reference_logits,
+            &candidate_logits,
+        )
+        .is_err());
+        Ok(())
+    }
+}
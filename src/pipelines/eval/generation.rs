@@ -0,0 +1,481 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::RustBertError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Splits a text in words and punctuation marks (punctuation between digits, e.g. in `3.5` or
+/// `1,000`, is kept within the number), as used for BLEU.
+pub fn tokenize_for_bleu(text: &str, lowercase: bool) -> Vec<String> {
+    let text = if lowercase {
+        text.to_lowercase()
+    } else {
+        text.to_string()
+    };
+    let characters = text.chars().collect::<Vec<char>>();
+    let mut tokens = vec![];
+    let mut current = String::new();
+    for (index, character) in characters.iter().enumerate() {
+        if character.is_whitespace() {
+            if !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+        } else if character.is_alphanumeric() {
+            current.push(*character);
+        } else {
+            let within_number = index > 0
+                && characters[index - 1].is_ascii_digit()
+                && matches!(characters.get(index + 1), Some(next) if next.is_ascii_digit())
+                && matches!(character, '.' | ',');
+            if within_number {
+                current.push(*character);
+            } else {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+                tokens.push(character.to_string());
+            }
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+/// Splits a text in lower-cased alphanumeric words, ignoring punctuation, as used for ROUGE.
+pub fn tokenize_for_rouge(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|character: char| !character.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn ngram_counts<S: AsRef<str>>(tokens: &[S], order: usize) -> HashMap<Vec<&str>, usize> {
+    let mut counts = HashMap::new();
+    if tokens.len() >= order {
+        for ngram in tokens.windows(order) {
+            *counts
+                .entry(ngram.iter().map(AsRef::as_ref).collect())
+                .or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Configuration for BLEU
+pub struct BleuConfig {
+    /// Maximum n-gram order (default: 4)
+    pub max_order: usize,
+    /// Add-one smoothing of the n-gram precisions of order greater than 1, avoiding null scores for short texts (default: false)
+    pub smooth: bool,
+    /// Lower-case the texts before tokenization (default: false)
+    pub lowercase: bool,
+}
+
+impl Default for BleuConfig {
+    fn default() -> Self {
+        BleuConfig {
+            max_order: 4,
+            smooth: false,
+            lowercase: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Corpus BLEU score
+pub struct BleuScore {
+    /// BLEU score, between 0 and 100
+    pub score: f64,
+    /// Modified n-gram precisions, for n-grams of order 1 to `max_order`
+    pub precisions: Vec<f64>,
+    /// Brevity penalty applied to hypotheses shorter than the references
+    pub brevity_penalty: f64,
+    /// Total number of tokens of the hypotheses
+    pub hypothesis_length: usize,
+    /// Total number of tokens of the closest references
+    pub reference_length: usize,
+}
+
+/// Computes the corpus BLEU score of hypotheses (e.g. translations) against one or more references
+/// per hypothesis.
+///
+/// # Arguments
+///
+/// * `hypotheses` - generated texts
+/// * `references` - reference texts of each hypothesis
+/// * `config` - `BleuConfig` with the maximum n-gram order, smoothing and casing settings
+///
+/// # Returns
+///
+/// * `BleuScore` of the corpus
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() -> anyhow::Result<()> {
+/// use rust_bert::pipelines::eval::{bleu, BleuConfig};
+///
+/// let hypotheses = ["The cat sits on the mat."];
+/// let references = [vec!["The cat is sitting on the mat.", "A cat sits on the mat."]];
+/// let bleu_score = bleu(&hypotheses, &references, &BleuConfig::default())?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn bleu<S, R, T>(
+    hypotheses: &[S],
+    references: &[R],
+    config: &BleuConfig,
+) -> Result<BleuScore, RustBertError>
+where
+    S: AsRef<str>,
+    R: AsRef<[T]>,
+    T: AsRef<str>,
+{
+    if hypotheses.len() != references.len() {
+        return Err(RustBertError::ValueError(format!(
+            "Got {} hypotheses but {} sets of references",
+            hypotheses.len(),
+            references.len()
+        )));
+    }
+    if config.max_order == 0 {
+        return Err(RustBertError::InvalidConfigurationError(
+            "The maximum n-gram order must be greater than 0".to_string(),
+        ));
+    }
+    let mut matches = vec![0usize; config.max_order];
+    let mut totals = vec![0usize; config.max_order];
+    let mut hypothesis_length = 0;
+    let mut reference_length = 0;
+    for (hypothesis, references) in hypotheses.iter().zip(references) {
+        let hypothesis = tokenize_for_bleu(hypothesis.as_ref(), config.lowercase);
+        let references = references
+            .as_ref()
+            .iter()
+            .map(|reference| tokenize_for_bleu(reference.as_ref(), config.lowercase))
+            .collect::<Vec<Vec<String>>>();
+        if references.is_empty() {
+            return Err(RustBertError::ValueError(
+                "Each hypothesis requires at least one reference".to_string(),
+            ));
+        }
+        hypothesis_length += hypothesis.len();
+        // Length of the reference closest to the hypothesis (shortest on ties)
+        reference_length += references
+            .iter()
+            .map(|reference| reference.len())
+            .min_by_key(|length| {
+                (
+                    (*length as i64 - hypothesis.len() as i64).abs(),
+                    *length as i64,
+                )
+            })
+            .unwrap_or_default();
+
+        for order in 1..=config.max_order {
+            let hypothesis_counts = ngram_counts(&hypothesis, order);
+            let mut max_reference_counts: HashMap<Vec<&str>, usize> = HashMap::new();
+            for reference in &references {
+                for (ngram, count) in ngram_counts(reference, order) {
+                    let max_count = max_reference_counts.entry(ngram).or_insert(0);
+                    *max_count = (*max_count).max(count);
+                }
+            }
+            for (ngram, count) in hypothesis_counts {
+                matches[order - 1] +=
+                    count.min(max_reference_counts.get(&ngram).copied().unwrap_or(0));
+                totals[order - 1] += count;
+            }
+        }
+    }
+
+    let precisions = matches
+        .iter()
+        .zip(totals.iter())
+        .enumerate()
+        .map(|(index, (matches, total))| {
+            if config.smooth && index > 0 {
+                (*matches as f64 + 1.0) / (*total as f64 + 1.0)
+            } else if *total == 0 {
+                0.0
+            } else {
+                *matches as f64 / *total as f64
+            }
+        })
+        .collect::<Vec<f64>>();
+    let brevity_penalty = if hypothesis_length == 0 {
+        0.0
+    } else if hypothesis_length < reference_length {
+        (1.0 - reference_length as f64 / hypothesis_length as f64).exp()
+    } else {
+        1.0
+    };
+    let score = if precisions.contains(&0.0) {
+        0.0
+    } else {
+        let log_precision_mean = precisions
+            .iter()
+            .map(|precision| precision.ln())
+            .sum::<f64>()
+            / precisions.len() as f64;
+        100.0 * brevity_penalty * log_precision_mean.exp()
+    };
+    Ok(BleuScore {
+        score,
+        precisions,
+        brevity_penalty,
+        hypothesis_length,
+        reference_length,
+    })
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+/// # ROUGE precision, recall and F-measure
+pub struct RougeScore {
+    /// Fraction of the hypothesis n-grams (or subsequence tokens) found in the reference
+    pub precision: f64,
+    /// Fraction of the reference n-grams (or subsequence tokens) found in the hypothesis
+    pub recall: f64,
+    /// Harmonic mean of the precision and recall
+    pub f1: f64,
+}
+
+impl RougeScore {
+    fn new(overlap: usize, hypothesis_count: usize, reference_count: usize) -> Self {
+        let precision = if hypothesis_count == 0 {
+            0.0
+        } else {
+            overlap as f64 / hypothesis_count as f64
+        };
+        let recall = if reference_count == 0 {
+            0.0
+        } else {
+            overlap as f64 / reference_count as f64
+        };
+        let f1 = if precision + recall == 0.0 {
+            0.0
+        } else {
+            2.0 * precision * recall / (precision + recall)
+        };
+        RougeScore {
+            precision,
+            recall,
+            f1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+/// # ROUGE-1, ROUGE-2 and ROUGE-L scores
+pub struct RougeScores {
+    /// Unigram overlap
+    pub rouge1: RougeScore,
+    /// Bigram overlap
+    pub rouge2: RougeScore,
+    /// Longest common subsequence
+    pub rouge_l: RougeScore,
+}
+
+fn rouge_n(hypothesis: &[String], reference: &[String], order: usize) -> RougeScore {
+    let hypothesis_counts = ngram_counts(hypothesis, order);
+    let reference_counts = ngram_counts(reference, order);
+    let overlap = hypothesis_counts
+        .iter()
+        .map(|(ngram, count)| (*count).min(reference_counts.get(ngram).copied().unwrap_or(0)))
+        .sum();
+    RougeScore::new(
+        overlap,
+        hypothesis_counts.values().sum(),
+        reference_counts.values().sum(),
+    )
+}
+
+fn longest_common_subsequence(first: &[String], second: &[String]) -> usize {
+    let mut previous = vec![0usize; second.len() + 1];
+    let mut current = vec![0usize; second.len() + 1];
+    for first_token in first {
+        for (index, second_token) in second.iter().enumerate() {
+            current[index + 1] = if first_token == second_token {
+                previous[index] + 1
+            } else {
+                current[index].max(previous[index + 1])
+            };
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[second.len()]
+}
+
+/// Computes the ROUGE-1, ROUGE-2 and ROUGE-L scores of a hypothesis (e.g. a summary) against a
+/// reference. Texts are lower-cased and punctuation is ignored.
+///
+/// # Arguments
+///
+/// * `hypothesis` - generated text
+/// * `reference` - reference text
+///
+/// # Returns
+///
+/// * `RougeScores` of the hypothesis
+pub fn rouge(hypothesis: &str, reference: &str) -> RougeScores {
+    let hypothesis = tokenize_for_rouge(hypothesis);
+    let reference = tokenize_for_rouge(reference);
+    RougeScores {
+        rouge1: rouge_n(&hypothesis, &reference, 1),
+        rouge2: rouge_n(&hypothesis, &reference, 2),
+        rouge_l: RougeScore::new(
+            longest_common_subsequence(&hypothesis, &reference),
+            hypothesis.len(),
+            reference.len(),
+        ),
+    }
+}
+
+/// Computes the ROUGE scores of a corpus, averaging the precision, recall and F-measure of each
+/// (hypothesis, reference) pair.
+///
+/// # Arguments
+///
+/// * `hypotheses` - generated texts
+/// * `references` - reference text of each hypothesis
+///
+/// # Returns
+///
+/// * `RougeScores` averaged over the corpus
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() -> anyhow::Result<()> {
+/// use rust_bert::pipelines::eval::rouge_corpus;
+///
+/// let summaries = ["Water vapour was found on exoplanet K2-18b."];
+/// let references = ["Astronomers find water vapour in the atmosphere of exoplanet K2-18b."];
+/// let scores = rouge_corpus(&summaries, &references)?;
+/// println!("ROUGE-L F1: {:.3}", scores.rouge_l.f1);
+/// # Ok(())
+/// # }
+/// ```
+pub fn rouge_corpus<S, T>(hypotheses: &[S], references: &[T]) -> Result<RougeScores, RustBertError>
+where
+    S: AsRef<str>,
+    T: AsRef<str>,
+{
+    if hypotheses.len() != references.len() {
+        return Err(RustBertError::ValueError(format!(
+            "Got {} hypotheses but {} references",
+            hypotheses.len(),
+            references.len()
+        )));
+    }
+    if hypotheses.is_empty() {
+        return Ok(RougeScores::default());
+    }
+    let mut total = [RougeScore::default(); 3];
+    for (hypothesis, reference) in hypotheses.iter().zip(references) {
+        let scores = rouge(hypothesis.as_ref(), reference.as_ref());
+        for (total, score) in total
+            .iter_mut()
+            .zip([scores.rouge1, scores.rouge2, scores.rouge_l])
+        {
+            total.precision += score.precision;
+            total.recall += score.recall;
+            total.f1 += score.f1;
+        }
+    }
+    let count = hypotheses.len() as f64;
+    let [rouge1, rouge2, rouge_l] = total.map(|score| RougeScore {
+        precision: score.precision / count,
+        recall: score.recall / count,
+        f1: score.f1 / count,
+    });
+    Ok(RougeScores {
+        rouge1,
+        rouge2,
+        rouge_l,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bleu_tokenization() {
+        assert_eq!(
+            tokenize_for_bleu("It costs $3.50, isn't it?", false),
+            ["It", "costs", "$", "3.50", ",", "isn", "'", "t", "it", "?"]
+        );
+    }
+
+    #[test]
+    fn bleu_score() -> Result<(), RustBertError> {
+        let hypotheses = ["the cat sat on the mat"];
+        let identical = bleu(
+            &hypotheses,
+            &[["the cat sat on the mat"]],
+            &BleuConfig::default(),
+        )?;
+        assert!((identical.score - 100.0).abs() < 1e-9);
+        assert!((identical.brevity_penalty - 1.0).abs() < 1e-9);
+
+        let references = [["the cat is on the mat", "there is a cat on the mat"]];
+        let score = bleu(&hypotheses, &references, &BleuConfig::default())?;
+        // 5/6 unigrams, 3/5 bigrams, 1/4 trigrams, no 4-gram match
+        assert!((score.precisions[0] - 5.0 / 6.0).abs() < 1e-9);
+        assert!((score.precisions[1] - 3.0 / 5.0).abs() < 1e-9);
+        assert!((score.precisions[2] - 1.0 / 4.0).abs() < 1e-9);
+        assert_eq!(score.score, 0.0);
+
+        let smoothed_config = BleuConfig {
+            smooth: true,
+            ..Default::default()
+        };
+        let smoothed = bleu(&hypotheses, &references, &smoothed_config)?;
+        assert!(smoothed.score > 0.0 && smoothed.score < 100.0);
+
+        let short = bleu(
+            &["the cat"],
+            &[["the cat sat on the mat"]],
+            &smoothed_config,
+        )?;
+        assert!((short.brevity_penalty - (1.0f64 - 3.0).exp()).abs() < 1e-9);
+
+        assert!(bleu(&hypotheses, &[[""; 0]; 0], &BleuConfig::default()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn rouge_scores() -> Result<(), RustBertError> {
+        let scores = rouge(
+            "The cat was found under the bed.",
+            "The cat was under the bed",
+        );
+        assert!((scores.rouge1.recall - 1.0).abs() < 1e-9);
+        assert!((scores.rouge1.precision - 6.0 / 7.0).abs() < 1e-9);
+        assert!((scores.rouge2.recall - 4.0 / 5.0).abs() < 1e-9);
+        assert!((scores.rouge2.precision - 4.0 / 6.0).abs() < 1e-9);
+        assert!((scores.rouge_l.recall - 1.0).abs() < 1e-9);
+
+        let corpus_scores = rouge_corpus(
+            &["The cat was found under the bed.", "unrelated text"],
+            &["The cat was under the bed", "a reference"],
+        )?;
+        assert!((corpus_scores.rouge1.recall - 0.5).abs() < 1e-9);
+        assert!(rouge_corpus(&["a"], &["a", "b"]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Evaluation of pipelines
+//! Computes quality metrics of pipeline outputs against labeled datasets or references, e.g. to
+//! check that swapping a model does not degrade the predictions.
+//!
+//! Classification metrics:
+//! - `ConfusionMatrix` accumulates the (expected, predicted) label pairs
+//! - `ClassificationReport` contains the accuracy, the precision, recall and F1 score of each label and their micro and macro averages
+//! - `evaluate_sequence_classification` and `evaluate_token_classification` run a pipeline on a labeled dataset and return the confusion matrix of its predictions
+//! - `compare_sequence_classification` runs a reference and a candidate pipeline (e.g. full precision and reduced precision versions of a model) on a labeled dataset and returns a `ModelComparisonReport` with their accuracy delta and the agreement and similarity of their predictions
+//!
+//! For token classification, the metrics are computed at the word level. Labels such as the
+//! outside label `O` can be excluded from the averages with `ConfusionMatrix::report_excluding`.
+//!
+//! Generation metrics, scoring summarization or translation outputs against references:
+//! - `bleu` computes the corpus BLEU score (modified n-gram precisions and brevity penalty) with one or more references per hypothesis
+//! - `rouge` and `rouge_corpus` compute the ROUGE-1, ROUGE-2 and ROUGE-L precision, recall and F-measure
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::eval::evaluate_sequence_classification;
+//! use rust_bert::pipelines::sequence_classification::SequenceClassificationModel;
+//!
+//! let model = SequenceClassificationModel::new(Default::default())?;
+//! let dataset = [
+//!     ("This movie was fantastic!", "POSITIVE"),
+//!     ("I will never watch it again.", "NEGATIVE"),
+//! ];
+//! let confusion_matrix = evaluate_sequence_classification(&model, &dataset, 32)?;
+//! let report = confusion_matrix.report();
+//! println!("Accuracy: {:.3}, macro F1: {:.3}", report.accuracy, report.macro_average.f1);
+//! # Ok(())
+//! # }
+//! ```
+
+mod classification;
+mod generation;
+
+pub use classification::{
+    compare_sequence_classification, evaluate_sequence_classification,
+    evaluate_token_classification, AveragedScores, ClassificationReport, ConfusionMatrix,
+    LabelScores, LabeledWords, ModelComparisonReport,
+};
+pub use generation::{
+    bleu, rouge, rouge_corpus, tokenize_for_bleu, tokenize_for_rouge, BleuConfig, BleuScore,
+    RougeScore, RougeScores,
+};
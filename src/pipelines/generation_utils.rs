@@ -47,7 +47,7 @@
 //! let output = gpt2_generator.generate(
 //!     Some(&[input_context, second_input_context]),
 //!     Some(generate_options),
-//! );
+//! )?;
 //! # Ok(())
 //! # }
 //! ```
@@ -66,43 +66,97 @@
 //! # ;
 //! ```
 
-use rust_tokenizers::tokenizer::Tokenizer;
+use rust_tokenizers::tokenizer::{Tokenizer, TruncationStrategy};
 use rust_tokenizers::vocab::Vocab;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use tch::kind::Kind::Int64;
-use tch::{no_grad, Device, Tensor};
+use tch::{no_grad, Device, Kind, Tensor};
 
-use crate::bart::LayerState as BartLayerState;
+use crate::bart::{BartGenerator, LayerState as BartLayerState};
+use crate::common::determinism::deterministic_seed;
 use crate::common::error::RustBertError;
+use crate::common::metrics;
+use crate::common::placement::LayerPlacement;
 use crate::common::resources::ResourceProvider;
-use crate::gpt_neo::LayerState as GPTNeoLayerState;
+use crate::common::trace::trace_span;
+use crate::gpt2::GPT2Generator;
+use crate::gpt_neo::{GptNeoGenerator, LayerState as GPTNeoLayerState};
+use crate::m2m_100::M2M100Generator;
+use crate::marian::MarianGenerator;
+use crate::mbart::MBartGenerator;
+use crate::openai_gpt::OpenAIGenerator;
+use crate::pegasus::PegasusConditionalGenerator;
 use crate::pipelines::generation_utils::private_generation_utils::{
-    InternalGenerateOptions, PrivateLanguageGenerator,
+    GenerationBudget, InternalGenerateOptions, PrivateLanguageGenerator,
 };
-use crate::prophetnet::LayerState as ProphetNetLayerState;
-use crate::reformer::LayerState as ReformerLayerState;
-use crate::t5::LayerState as T5LayerState;
-use crate::xlnet::LayerState as XLNetLayerState;
+use crate::prophetnet::{LayerState as ProphetNetLayerState, ProphetNetConditionalGenerator};
+use crate::reformer::{LayerState as ReformerLayerState, ReformerGenerator};
+use crate::t5::{LayerState as T5LayerState, T5Generator};
+use crate::xlnet::{LayerState as XLNetLayerState, XLNetGenerator};
 
 use self::ordered_float::OrderedFloat;
 use crate::pipelines::common::TokenizerOption;
+use crate::pipelines::grammar::{Grammar, GrammarConstraint};
+use crate::pipelines::logits_processor::LogitsProcessor;
+use crate::pipelines::stopping_criteria::StoppingCriteria;
+use crate::pipelines::streaming::IncrementalDecoder;
 
 #[cfg(feature = "remote")]
 use crate::{
     gpt2::{Gpt2ConfigResources, Gpt2MergesResources, Gpt2ModelResources, Gpt2VocabResources},
     resources::RemoteResource,
 };
+use serde::{Deserialize, Serialize};
 
 extern crate ordered_float;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// # Side of the prompts truncated when exceeding the maximum prompt length
+pub enum TruncationSide {
+    /// Remove the first tokens of the prompt, keeping the most recent context (e.g. the last turns of a chat)
+    Left,
+    /// Remove the last tokens of the prompt
+    Right,
+}
+
+impl Default for TruncationSide {
+    fn default() -> Self {
+        TruncationSide::Right
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// # Attention sink cache policy for unbounded generation
+/// Implements the cache eviction of [StreamingLLM, Xiao et al.](https://arxiv.org/abs/2309.17453):
+/// once the cache of a decoder exceeds `num_sink_tokens + window_size` tokens, only the first
+/// `num_sink_tokens` tokens of each sequence (attention sinks) and its `window_size` most recent
+/// tokens are kept. The positions of new tokens are assigned within the cache, so that the
+/// generation can continue past the maximum number of positions of the model with a bounded
+/// memory footprint.
+pub struct AttentionSinkConfig {
+    /// Number of initial tokens always kept in the cache (4 in the reference implementation)
+    pub num_sink_tokens: i64,
+    /// Number of most recent tokens kept in the cache
+    pub window_size: i64,
+}
+
+#[derive(Serialize, Deserialize)]
 /// # Configuration for text generation
 pub struct GenerateConfig {
     /// Model weights resource (default: pretrained GPT2 model)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub model_resource: Box<dyn ResourceProvider + Send>,
     /// Config resource (default: pretrained GPT2 model)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub config_resource: Box<dyn ResourceProvider + Send>,
     /// Vocab resource (default: pretrained GPT2 model)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub vocab_resource: Box<dyn ResourceProvider + Send>,
     /// Merges resource (default: pretrained GPT2 model)
+    #[serde(default, with = "crate::common::serde_utils::optional_resource")]
     pub merges_resource: Option<Box<dyn ResourceProvider + Send>>,
     /// Minimum sequence length (default: 0)
     pub min_length: i64,
@@ -120,32 +174,117 @@ pub struct GenerateConfig {
     pub top_k: i64,
     /// Top_p value for [Nucleus sampling, Holtzman et al.](http://arxiv.org/abs/1904.09751). Keep top tokens until cumulative probability reaches top_p (default: 0.9)
     pub top_p: f64,
+    /// Min_p value for sampling tokens. Tokens with a probability lower than `min_p` times the probability of the most likely token are removed. Value higher than 0 will enable the feature (default: 0.0)
+    #[serde(default)]
+    pub min_p: f64,
     /// Repetition penalty (mostly useful for CTRL decoders). Values higher than 1 will penalize tokens that have been already generated. (default: 1.0)
     pub repetition_penalty: f64,
     /// Exponential penalty based on the length of the hypotheses generated (default: 1.0)
     pub length_penalty: f64,
     /// Number of allowed repetitions of n-grams. Values higher than 0 turn on this feature (default: 3)
     pub no_repeat_ngram_size: i64,
-    /// Number of sequences to return for each prompt text (default: 1)
+    /// Number of sequences to return for each prompt text. Beam search (with or without sampling) returns the best hypotheses of the search and requires `num_return_sequences` to be lower than `num_beams` (default: 1)
     pub num_return_sequences: i64,
     /// Number of beam groups for diverse beam generation. If provided and higher than 1, will split the beams into beam subgroups leading to more diverse generation.
     pub num_beam_groups: Option<i64>,
     /// Diversity penalty for diverse beam search. High values will enforce more difference between beam groups (default: 5.5)
     pub diversity_penalty: Option<f64>,
+    /// Padding token id used to batch prompts of different lengths (default: None, using the padding token of the model, or its first end of sequence token for models without padding token such as GPT2)
+    #[serde(default)]
+    pub pad_token_id: Option<i64>,
+    /// Maximum number of tokens of the prompts, including special tokens (default: None, using the maximum number of positions for encoder-decoder models and `max_length` for decoder-only models)
+    #[serde(default)]
+    pub max_prompt_length: Option<i64>,
+    /// Side of the prompts to truncate when exceeding the maximum prompt length (default: right)
+    #[serde(default)]
+    pub prompt_truncation_side: TruncationSide,
+    /// Truncation strategy for prompts exceeding the maximum prompt length. `DoNotTruncate` keeps the full prompts (default: `LongestFirst`)
+    #[serde(
+        with = "crate::common::serde_utils::truncation_strategy",
+        default = "crate::common::serde_utils::truncation_strategy::default"
+    )]
+    pub prompt_truncation_strategy: TruncationStrategy,
+    /// Candidate premature layers (0-based index of the transformer layer outputs) for [DoLa decoding, Chuang et al.](https://arxiv.org/abs/2309.03883).
+    /// When set, the next token log-probabilities of the final layer are contrasted with the ones of the candidate layer diverging the most from the final layer.
+    /// Only supported by models exposing their intermediate hidden states (GPT2) (default: None)
+    #[serde(default)]
+    pub dola_layers: Option<Vec<i64>>,
+    /// Attention sink cache policy bounding the cache of decoder-only models during long generations (e.g. chat sessions).
+    /// Only supported by decoder-only models with a key/value cache (GPT2, GPT-Neo) (default: None)
+    #[serde(default)]
+    pub attention_sink: Option<AttentionSinkConfig>,
+    /// Stop sequences (e.g. `"\n\n"` or `"###"`): the generation of a sequence ends as soon as its generated text contains one of these strings.
+    /// The tokens producing the stop sequence are removed from the output (default: empty)
+    #[serde(default)]
+    pub stop_sequences: Vec<String>,
+    /// Seed of the random number generator used for sampling (`do_sample`): two generations with the same
+    /// prompts, settings and seed produce identical outputs. The seed is applied to the global `tch` generator
+    /// before each generation (default: None)
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Sequences of token ids that can never be generated (e.g. profanity or forbidden strings). A sequence of a single
+    /// token is banned at every step, longer sequences have their last token banned whenever the previous tokens match (default: empty)
+    #[serde(default)]
+    pub bad_word_ids: Vec<Vec<i64>>,
+    /// Words or phrases that can never be generated, tokenized by the generator (with and without a leading space) and
+    /// banned in the same way as `bad_word_ids` (default: empty)
+    #[serde(default)]
+    pub bad_words: Vec<String>,
+    /// Token forced as the first generated token (e.g. the target language code of multilingual seq2seq models) (default: None)
+    #[serde(default)]
+    pub forced_bos_token_id: Option<i64>,
+    /// Token forced as the last generated token when the maximum length is reached (default: None)
+    #[serde(default)]
+    pub forced_eos_token_id: Option<i64>,
+    /// Bias added to the logits of individual tokens at each generation step (before filtering), to boost (positive values)
+    /// or suppress (negative values) tokens. A bias of -100 or lower effectively bans a token (default: empty)
+    #[serde(default)]
+    pub logit_bias: HashMap<i64, f64>,
+    /// Custom transformations of the next token scores applied at each generation step, after the built-in penalties
+    /// (see `LogitsProcessor`). Not serialized (default: empty)
+    #[serde(skip)]
+    pub logits_processors: Vec<Box<dyn LogitsProcessor>>,
+    /// Conditions evaluated after each decoding step, ending the generation once one of them is met (see
+    /// `StoppingCriteria`). Not serialized (default: empty)
+    #[serde(skip)]
+    pub stopping_criteria: Vec<Box<dyn StoppingCriteria>>,
+    /// Device and precision of groups of layers, identified by the prefix of the names of their weights (e.g. `transformer.wte`), overriding
+    /// the `device` of the model and its precision. Only supported by GPT2 (default: empty, all layers are placed on `device`)
+    #[serde(default)]
+    pub layer_placement: HashMap<String, LayerPlacement>,
     /// Device to place the model on (default: CUDA/GPU when available)
+    #[serde(
+        with = "crate::common::serde_utils::device",
+        default = "crate::common::serde_utils::device::default"
+    )]
     pub device: Device,
 }
 
-#[cfg(feature = "remote")]
-impl Default for GenerateConfig {
-    fn default() -> GenerateConfig {
+impl GenerateConfig {
+    /// Instantiate a new text generation configuration of the supplied type.
+    ///
+    /// # Arguments
+    ///
+    /// * model_resource - The `ResourceProvider` pointing to the model to load (e.g.  model.ot)
+    /// * config_resource - The `ResourceProvider` pointing to the model configuration to load (e.g. config.json)
+    /// * vocab_resource - The `ResourceProvider` pointing to the tokenizer's vocabulary to load (e.g.  vocab.txt/vocab.json)
+    /// * merges_resource - An optional `ResourceProvider` pointing to the tokenizer's merge file or SentencePiece model to load (e.g.  merges.txt).
+    pub fn new<RM, RC, RV>(
+        model_resource: RM,
+        config_resource: RC,
+        vocab_resource: RV,
+        merges_resource: Option<RV>,
+    ) -> GenerateConfig
+    where
+        RM: ResourceProvider + Send + 'static,
+        RC: ResourceProvider + Send + 'static,
+        RV: ResourceProvider + Send + 'static,
+    {
         GenerateConfig {
-            model_resource: Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2)),
-            config_resource: Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2)),
-            vocab_resource: Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2)),
-            merges_resource: Some(Box::new(RemoteResource::from_pretrained(
-                Gpt2MergesResources::GPT2,
-            ))),
+            model_resource: Box::new(model_resource),
+            config_resource: Box::new(config_resource),
+            vocab_resource: Box::new(vocab_resource),
+            merges_resource: merges_resource.map(|r| Box::new(r) as Box<_>),
             min_length: 0,
             max_length: Some(56),
             do_sample: true,
@@ -154,63 +293,318 @@ impl Default for GenerateConfig {
             temperature: 1.0,
             top_k: 0,
             top_p: 0.9,
+            min_p: 0.0,
             repetition_penalty: 1.0,
             length_penalty: 1.0,
             no_repeat_ngram_size: 3,
             num_return_sequences: 1,
             num_beam_groups: None,
             diversity_penalty: None,
+            pad_token_id: None,
+            max_prompt_length: None,
+            prompt_truncation_side: TruncationSide::Right,
+            prompt_truncation_strategy: TruncationStrategy::LongestFirst,
+            dola_layers: None,
+            attention_sink: None,
+            stop_sequences: Vec::new(),
+            seed: None,
+            bad_word_ids: Vec::new(),
+            bad_words: Vec::new(),
+            forced_bos_token_id: None,
+            forced_eos_token_id: None,
+            logit_bias: HashMap::new(),
+            logits_processors: Vec::new(),
+            stopping_criteria: Vec::new(),
+            layer_placement: HashMap::new(),
             device: Device::cuda_if_available(),
         }
     }
+
+    pub(crate) fn validate(&self) -> Result<(), RustBertError> {
+        GenerationSettings::new(self, None).validate()?;
+        let check = |condition: bool, message: &str| {
+            if condition {
+                Ok(())
+            } else {
+                Err(RustBertError::InvalidConfigurationError(
+                    message.to_string(),
+                ))
+            }
+        };
+        if let Some(max_prompt_length) = self.max_prompt_length {
+            check(
+                max_prompt_length > 0,
+                "max_prompt_length must be strictly greater than 0",
+            )?;
+        }
+        check(
+            !matches!(
+                self.prompt_truncation_strategy,
+                TruncationStrategy::OnlySecond
+            ),
+            "prompt_truncation_strategy cannot be OnlySecond for single prompts",
+        )?;
+        if let Some(dola_layers) = &self.dola_layers {
+            check(
+                !dola_layers.is_empty(),
+                "dola_layers must contain at least one layer",
+            )?;
+            check(
+                dola_layers.iter().all(|layer| *layer >= 0),
+                "dola_layers must be positive layer indices",
+            )?;
+        }
+        if let Some(attention_sink) = &self.attention_sink {
+            check(
+                attention_sink.num_sink_tokens >= 0,
+                "attention_sink num_sink_tokens must be positive",
+            )?;
+            check(
+                attention_sink.window_size > 0,
+                "attention_sink window_size must be strictly greater than 0",
+            )?;
+        }
+        check(
+            self.bad_words.iter().all(|bad_word| !bad_word.is_empty()),
+            "bad_words must not contain empty strings",
+        )?;
+        Ok(())
+    }
 }
 
-impl GenerateConfig {
-    pub(crate) fn validate(&self) {
-        assert!(self.temperature > 0f64, "temperature must positive");
-        assert!(
-            (self.top_p >= 0f64) & (self.top_p <= 1f64),
-            "top_p must be 0 and 1"
-        );
-        assert!(
-            self.repetition_penalty >= 1f64,
-            "repetition_penalty must be greater than 1"
-        );
-        assert!(
-            self.length_penalty > 0f64,
-            "length_penalty must be strictly greater than 0"
-        );
-        assert!(
-            self.num_return_sequences > 0i64,
-            "num_return_sequences must be strictly greater than 0"
-        );
-        assert!(
-            self.num_beams > 0i64,
-            "num_beams must be strictly greater than 0"
-        );
+#[cfg(feature = "remote")]
+impl Default for GenerateConfig {
+    fn default() -> GenerateConfig {
+        GenerateConfig::new(
+            RemoteResource::from_pretrained(Gpt2ModelResources::GPT2),
+            RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2),
+            RemoteResource::from_pretrained(Gpt2VocabResources::GPT2),
+            Some(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2)),
+        )
+    }
+}
 
-        if !self.do_sample {
-            if self.num_beams == 1 {
-                assert_eq!(
-                    self.num_return_sequences, 1,
-                    "num_return_sequences must be set to 1 for greedy decoding"
-                )
-            } else {
-                assert!(
-                    self.num_beams >= self.num_return_sequences,
-                    "num_return_sequences must be lower than the number of beams"
-                )
+/// # Language generators that can be created from a `GenerateConfig`
+/// Used by the `GeneratorBuilder` to create generators of any supported architecture.
+pub trait GeneratorFromConfig: Sized {
+    /// Creates the generator from a `GenerateConfig`
+    fn from_generate_config(generate_config: GenerateConfig) -> Result<Self, RustBertError>;
+
+    /// Casts the generator weights to the provided floating point `Kind`
+    fn set_kind(&mut self, kind: Kind);
+}
+
+macro_rules! impl_generator_from_config {
+    ($($generator:ty),*) => {
+        $(
+            impl GeneratorFromConfig for $generator {
+                fn from_generate_config(
+                    generate_config: GenerateConfig,
+                ) -> Result<Self, RustBertError> {
+                    <$generator>::new(generate_config)
+                }
+
+                fn set_kind(&mut self, kind: Kind) {
+                    self.get_var_store_mut().set_kind(kind);
+                }
+            }
+        )*
+    };
+}
+
+impl_generator_from_config!(
+    BartGenerator,
+    GPT2Generator,
+    GptNeoGenerator,
+    M2M100Generator,
+    MarianGenerator,
+    MBartGenerator,
+    OpenAIGenerator,
+    PegasusConditionalGenerator,
+    ProphetNetConditionalGenerator,
+    ReformerGenerator,
+    T5Generator,
+    XLNetGenerator
+);
+
+/// # Language generator builder
+/// Creates a validated `GenerateConfig` or a language generator from a set of resources, a device,
+/// a floating point precision and generation settings. Errors are returned (instead of panicking)
+/// for missing resources, invalid generation settings or unsupported precisions.
+///
+/// Resources provided to the builder take precedence over the resources of the `GenerateConfig`
+/// passed with `with_generate_config`. If no `GenerateConfig` is provided, the model, config and vocab
+/// resources are required and default generation settings are used. The merges resource is optional
+/// (e.g. not needed for SentencePiece-based tokenizers).
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() -> anyhow::Result<()> {
+/// use rust_bert::gpt2::{
+///     GPT2Generator, Gpt2ConfigResources, Gpt2MergesResources, Gpt2ModelResources,
+///     Gpt2VocabResources,
+/// };
+/// use rust_bert::pipelines::generation_utils::{GeneratorBuilder, LanguageGenerator};
+/// use rust_bert::resources::RemoteResource;
+/// use tch::{Device, Kind};
+///
+/// let generator: GPT2Generator = GeneratorBuilder::new()
+///     .with_model_resource(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2))
+///     .with_config_resource(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2))
+///     .with_vocab_resource(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2))
+///     .with_merges_resource(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2))
+///     .with_device(Device::cuda_if_available())
+///     .with_kind(Kind::Half)
+///     .create_model()?;
+///
+/// let output = generator.generate(Some(&["The dog"]), None)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct GeneratorBuilder {
+    model_resource: Option<Box<dyn ResourceProvider + Send>>,
+    config_resource: Option<Box<dyn ResourceProvider + Send>>,
+    vocab_resource: Option<Box<dyn ResourceProvider + Send>>,
+    merges_resource: Option<Box<dyn ResourceProvider + Send>>,
+    device: Option<Device>,
+    kind: Option<Kind>,
+    generate_config: Option<GenerateConfig>,
+}
+
+impl GeneratorBuilder {
+    /// Build a new `GeneratorBuilder`
+    pub fn new() -> GeneratorBuilder {
+        Default::default()
+    }
+
+    /// Specify the model weights resource (e.g. model.ot)
+    pub fn with_model_resource<R: ResourceProvider + Send + 'static>(
+        &mut self,
+        model_resource: R,
+    ) -> &mut Self {
+        self.model_resource = Some(Box::new(model_resource));
+        self
+    }
+
+    /// Specify the model configuration resource (e.g. config.json)
+    pub fn with_config_resource<R: ResourceProvider + Send + 'static>(
+        &mut self,
+        config_resource: R,
+    ) -> &mut Self {
+        self.config_resource = Some(Box::new(config_resource));
+        self
+    }
+
+    /// Specify the tokenizer vocabulary resource (e.g. vocab.json)
+    pub fn with_vocab_resource<R: ResourceProvider + Send + 'static>(
+        &mut self,
+        vocab_resource: R,
+    ) -> &mut Self {
+        self.vocab_resource = Some(Box::new(vocab_resource));
+        self
+    }
+
+    /// Specify the tokenizer merges resource (e.g. merges.txt), only required for BPE tokenizers
+    pub fn with_merges_resource<R: ResourceProvider + Send + 'static>(
+        &mut self,
+        merges_resource: R,
+    ) -> &mut Self {
+        self.merges_resource = Some(Box::new(merges_resource));
+        self
+    }
+
+    /// Specify the device to place the model on
+    pub fn with_device(&mut self, device: Device) -> &mut Self {
+        self.device = Some(device);
+        self
+    }
+
+    /// Specify the floating point precision of the model weights (`Float`, `Double`, `Half` or `BFloat16`)
+    pub fn with_kind(&mut self, kind: Kind) -> &mut Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    /// Specify the generation settings. The resources and device of the `GenerateConfig` are used
+    /// unless they are also provided to the builder.
+    pub fn with_generate_config(&mut self, generate_config: GenerateConfig) -> &mut Self {
+        self.generate_config = Some(generate_config);
+        self
+    }
+
+    /// Creates a validated `GenerateConfig` from the builder settings. The resources stored in the
+    /// builder are moved to the configuration.
+    ///
+    /// # Returns
+    /// * `Result<GenerateConfig, RustBertError>` validated generation configuration
+    pub fn build_config(&mut self) -> Result<GenerateConfig, RustBertError> {
+        if let Some(kind) = self.kind {
+            if !matches!(
+                kind,
+                Kind::Float | Kind::Double | Kind::Half | Kind::BFloat16
+            ) {
+                return Err(RustBertError::InvalidConfigurationError(format!(
+                    "Invalid generator precision {:?}, expected a floating point kind",
+                    kind
+                )));
             }
         }
-        if let Some(num_beam_groups_value) = self.num_beam_groups {
-            if num_beam_groups_value > 1 {
-                assert_eq!(
-                    self.num_beams % num_beam_groups_value,
-                    0,
-                    "num_beam_groups must be a multiple of num_beam_groups"
+
+        let mut generate_config = match self.generate_config.take() {
+            Some(generate_config) => generate_config,
+            None => {
+                let missing_resource = |name: &str| {
+                    RustBertError::InvalidConfigurationError(format!(
+                        "A {} resource must be provided to the generator builder",
+                        name
+                    ))
+                };
+                GenerateConfig::new(
+                    self.model_resource
+                        .take()
+                        .ok_or_else(|| missing_resource("model"))?,
+                    self.config_resource
+                        .take()
+                        .ok_or_else(|| missing_resource("config"))?,
+                    self.vocab_resource
+                        .take()
+                        .ok_or_else(|| missing_resource("vocab"))?,
+                    self.merges_resource.take(),
                 )
             }
+        };
+        if let Some(model_resource) = self.model_resource.take() {
+            generate_config.model_resource = model_resource;
+        }
+        if let Some(config_resource) = self.config_resource.take() {
+            generate_config.config_resource = config_resource;
         }
+        if let Some(vocab_resource) = self.vocab_resource.take() {
+            generate_config.vocab_resource = vocab_resource;
+        }
+        if let Some(merges_resource) = self.merges_resource.take() {
+            generate_config.merges_resource = Some(merges_resource);
+        }
+        if let Some(device) = self.device {
+            generate_config.device = device;
+        }
+        generate_config.validate()?;
+        Ok(generate_config)
+    }
+
+    /// Creates a language generator from the builder settings
+    ///
+    /// # Returns
+    /// * `Result<G, RustBertError>` language generator (e.g. `GPT2Generator`, `BartGenerator`)
+    pub fn create_model<G: GeneratorFromConfig>(&mut self) -> Result<G, RustBertError> {
+        let generate_config = self.build_config()?;
+        let mut generator = G::from_generate_config(generate_config)?;
+        if let Some(kind) = self.kind {
+            generator.set_kind(kind);
+        }
+        Ok(generator)
     }
 }
 
@@ -228,21 +622,32 @@ pub enum Cache {
 
 pub(crate) mod private_generation_utils {
     use std::cmp::{max, min};
-    use std::collections::HashMap;
+    use std::collections::{HashMap, VecDeque};
     use std::mem;
+    use std::time::{Duration, Instant};
 
-    use rust_tokenizers::tokenizer::{truncate_sequences, Tokenizer, TruncationStrategy};
+    use rust_tokenizers::tokenizer::{Tokenizer, TruncationStrategy};
     use rust_tokenizers::vocab::Vocab;
-    use rust_tokenizers::TokenIdsWithOffsets;
+    use rust_tokenizers::{Mask, TokenIdsWithOffsets};
     use tch::{nn, Device, Kind, Tensor};
 
     use crate::pipelines::common::TokenizerOption;
     use crate::pipelines::generation_utils::{
-        BeamHypotheses, Cache, GenerateConfig, LMHeadModel, PrefixAllowedFunction,
+        AttentionSinkConfig, BeamHypotheses, Cache, GenerateConfig, GeneratedIndicesOutput,
+        LMHeadModel, PrefillProgressFunction, PrefixAllowedFunction, TokenCallbackFunction,
+        TruncationSide,
+    };
+    use crate::pipelines::grammar::GrammarConstraint;
+    use crate::pipelines::logits_processor::{
+        LogitsProcessor, NoRepeatNGramLogitsProcessor, RepetitionPenaltyLogitsProcessor,
+        TopKTopPLogitsProcessor,
     };
+    use crate::pipelines::stopping_criteria::StoppingState;
 
     use super::ordered_float::OrderedFloat;
     use crate::common::kind::get_positive_infinity;
+    use crate::common::trace::trace_span;
+    use crate::RustBertError;
 
     pub struct InternalGenerateOptions<'a> {
         pub min_length: i64,
@@ -251,6 +656,7 @@ pub(crate) mod private_generation_utils {
         pub temperature: f64,
         pub top_k: i64,
         pub top_p: f64,
+        pub min_p: f64,
         pub repetition_penalty: f64,
         pub no_repeat_ngram_size: i64,
         pub pad_token_id: Option<i64>,
@@ -262,7 +668,58 @@ pub(crate) mod private_generation_utils {
         pub num_beam_groups: Option<i64>,
         pub diversity_penalty: Option<f64>,
         pub forced_bos_token_id: Option<i64>,
+        pub forced_eos_token_id: Option<i64>,
+        pub logit_bias: Option<&'a HashMap<i64, f64>>,
         pub bad_word_ids: Option<&'a Vec<Vec<i64>>>,
+        pub output_beam_hypotheses: bool,
+        pub dola_layers: Option<&'a [i64]>,
+        pub budget: GenerationBudget,
+        pub prefill_chunk_size: Option<i64>,
+        pub prefill_progress_fn: Option<PrefillProgressFunction<'a>>,
+        pub lazy_beam_expansion: bool,
+        pub attention_sink: Option<AttentionSinkConfig>,
+        pub token_callback_fn: Option<TokenCallbackFunction<'a>>,
+        pub stop_sequences: &'a [String],
+        pub grammar: Option<&'a GrammarConstraint>,
+    }
+
+    /// Time and token budget of a generation call
+    #[derive(Clone, Copy)]
+    pub struct GenerationBudget {
+        start_time: Instant,
+        deadline: Option<Instant>,
+        remaining_tokens: Option<i64>,
+    }
+
+    impl GenerationBudget {
+        pub fn new(
+            start_time: Instant,
+            max_time: Option<Duration>,
+            max_total_new_tokens: Option<i64>,
+        ) -> GenerationBudget {
+            GenerationBudget {
+                start_time,
+                deadline: max_time.map(|max_time| start_time + max_time),
+                remaining_tokens: max_total_new_tokens,
+            }
+        }
+
+        pub fn has_token_limit(&self) -> bool {
+            self.remaining_tokens.is_some()
+        }
+
+        pub fn elapsed(&self) -> Duration {
+            self.start_time.elapsed()
+        }
+
+        /// Records the tokens generated by a decoding step, returns true if the budget is exhausted
+        pub fn consume(&mut self, num_tokens: i64) -> bool {
+            if let Some(remaining_tokens) = self.remaining_tokens.as_mut() {
+                *remaining_tokens -= num_tokens;
+            }
+            matches!(self.remaining_tokens, Some(remaining_tokens) if remaining_tokens <= 0)
+                | matches!(self.deadline, Some(deadline) if Instant::now() >= deadline)
+        }
     }
 
     pub struct PreparedInput<'a> {
@@ -278,6 +735,154 @@ pub(crate) mod private_generation_utils {
         pub indices: Tensor,
         pub scores: Option<Vec<f64>>,
         pub token_scores: Option<Vec<Vec<f64>>>,
+        pub beam_hypotheses: Option<Vec<Vec<GeneratedIndicesOutput>>>,
+        pub truncated: Vec<bool>,
+    }
+
+    /// Pads the token ids of a batch of prompts to the same length, returning the padded token ids
+    /// and the attention mask (set to 0 for padding positions only, so that padding tokens also
+    /// used as regular tokens, e.g. an end of sequence token used for padding, remain attended).
+    pub fn pad_prompt_ids(
+        token_ids: Vec<Vec<i64>>,
+        pad_token_id: i64,
+        left_padding: bool,
+        device: Device,
+    ) -> (Tensor, Tensor) {
+        let max_len = token_ids.iter().map(|input| input.len()).max().unwrap_or(0);
+        let (token_ids, attention_masks): (Vec<Tensor>, Vec<Tensor>) = token_ids
+            .into_iter()
+            .map(|input| {
+                let padding_length = max_len - input.len();
+                let mut mask = vec![1i64; input.len()];
+                let mut padding = vec![pad_token_id; padding_length];
+                let (padded_input, mask) = if left_padding {
+                    padding.extend(input);
+                    let mut padded_mask = vec![0i64; padding_length];
+                    padded_mask.append(&mut mask);
+                    (padding, padded_mask)
+                } else {
+                    let mut input = input;
+                    input.append(&mut padding);
+                    mask.resize(max_len, 0);
+                    (input, mask)
+                };
+                (
+                    Tensor::of_slice(&padded_input).to(device),
+                    Tensor::of_slice(&mask).to(device),
+                )
+            })
+            .unzip();
+        (
+            Tensor::stack(&token_ids, 0),
+            Tensor::stack(&attention_masks, 0),
+        )
+    }
+
+    /// Returns the cache positions kept by the attention sink policy for each sequence, given the
+    /// number of (left) padding positions of the sequences. The first `num_sink_tokens` tokens and
+    /// the `window_size` most recent tokens of each sequence are kept. Sequences with fewer tokens
+    /// keep their last padding positions so that all sequences keep the same number of positions.
+    pub fn attention_sink_positions(
+        padding_lengths: &[i64],
+        cache_length: i64,
+        attention_sink: &AttentionSinkConfig,
+    ) -> Vec<Vec<i64>> {
+        let kept_length = attention_sink.num_sink_tokens + attention_sink.window_size;
+        padding_lengths
+            .iter()
+            .map(|&padding_length| {
+                let sink_end = min(
+                    padding_length + attention_sink.num_sink_tokens,
+                    cache_length,
+                );
+                let window_start = max(sink_end, cache_length - attention_sink.window_size);
+                let positions = (0..sink_end)
+                    .chain(window_start..cache_length)
+                    .collect::<Vec<i64>>();
+                positions[positions.len().saturating_sub(kept_length as usize)..].to_vec()
+            })
+            .collect()
+    }
+
+    /// Builds a tensor of shape (1, *vocab_size*) holding the logit bias of each token, to be added
+    /// to the next token logits.
+    pub fn get_logit_bias_tensor(logit_bias: &HashMap<i64, f64>, scores: &Tensor) -> Tensor {
+        let (token_ids, biases): (Vec<i64>, Vec<f64>) = logit_bias.iter().unzip();
+        Tensor::zeros(&[1, scores.size()[1]], (scores.kind(), scores.device())).index_copy(
+            1,
+            &Tensor::of_slice(&token_ids).to_device(scores.device()),
+            &Tensor::of_slice(&biases)
+                .to_kind(scores.kind())
+                .to_device(scores.device())
+                .unsqueeze(0),
+        )
+    }
+
+    /// Forces the first generated token (`forced_bos_token_id`) and the last token generated at the
+    /// maximum length (`forced_eos_token_id`) by masking all other tokens of the scores.
+    pub fn force_bos_eos_tokens(
+        scores: &mut Tensor,
+        current_length: i64,
+        cur_len: i64,
+        gen_opt: &InternalGenerateOptions,
+    ) {
+        let forced_token_id = match (gen_opt.forced_bos_token_id, gen_opt.forced_eos_token_id) {
+            (Some(forced_bos_token_id), _) if current_length == cur_len => forced_bos_token_id,
+            (_, Some(forced_eos_token_id))
+                if gen_opt
+                    .max_length
+                    .map_or(false, |max_length| current_length == max_length - 1) =>
+            {
+                forced_eos_token_id
+            }
+            _ => return,
+        };
+        let impossible_tokens = Tensor::ones(&[scores.size()[1]], (Kind::Bool, scores.device()))
+            .index_fill(
+                0,
+                &Tensor::of_slice(&[forced_token_id]).to_device(scores.device()),
+                0,
+            );
+        let _ = scores.masked_fill_(
+            &impossible_tokens.unsqueeze(0),
+            get_negative_infinity(scores.kind()).unwrap(),
+        );
+    }
+
+    /// Returns the number of trailing tokens to remove from the generated tokens of a sequence if
+    /// their text contains a stop sequence, so that the output ends before the stop sequence.
+    /// Sequences are checked after each generated token: a new stop sequence always ends in the
+    /// last token, and only the last tokens (at most one token per character of the longest stop
+    /// sequence, plus incomplete characters at the boundaries) are decoded.
+    pub fn stop_sequence_trim_length<F>(
+        generated_ids: &[i64],
+        stop_sequences: &[String],
+        decode: F,
+    ) -> Option<usize>
+    where
+        F: Fn(&[i64]) -> String,
+    {
+        let window_length = stop_sequence_window_length(stop_sequences)?;
+        let window = &generated_ids[generated_ids.len().saturating_sub(window_length)..];
+        let text = decode(window);
+        let stop_position = stop_sequences
+            .iter()
+            .filter_map(|stop_sequence| text.find(stop_sequence.as_str()))
+            .min()?;
+        let kept_tokens = (0..window.len())
+            .rev()
+            .find(|&num_tokens| decode(&window[..num_tokens]).len() <= stop_position)
+            .unwrap_or(0);
+        Some(window.len() - kept_tokens)
+    }
+
+    /// Returns the number of trailing tokens that may contain a stop sequence ending in the last
+    /// generated token, or `None` if there are no stop sequences.
+    pub fn stop_sequence_window_length(stop_sequences: &[String]) -> Option<usize> {
+        stop_sequences
+            .iter()
+            .map(|stop_sequence| stop_sequence.chars().count() + 2)
+            .max()
     }
 
     pub trait PrivateLanguageGenerator<T: LMHeadModel, V: Vocab, U: Tokenizer<V>> {
@@ -294,6 +899,84 @@ pub(crate) mod private_generation_utils {
         fn get_decoder_start_id(&self) -> Option<i64>;
         fn get_max_positions_embeddings(&self) -> i64;
 
+        /// Returns the padding token id used for generation: the padding token id of the
+        /// generation configuration if set, otherwise the padding token of the model, falling back
+        /// to the first end of sequence token for models without padding token (e.g. GPT2).
+        fn get_generation_pad_id(&self) -> Option<i64> {
+            self.get_config()
+                .pad_token_id
+                .or_else(|| self.get_pad_id())
+                .or_else(|| {
+                    self.get_eos_ids()
+                        .and_then(|eos_ids| eos_ids.first().copied())
+                })
+        }
+
+        /// Indicates whether the cache of the model can be expanded along the batch dimension after
+        /// the first forward pass. When supported, beam search processes the prompt once per input
+        /// and duplicates the resulting cache and logits for each beam instead of running the
+        /// prompt through the model `num_beams` times.
+        fn supports_lazy_beam_expansion(&self) -> bool {
+            false
+        }
+
+        /// Indicates whether the model returns its intermediate hidden states and can project them
+        /// onto the vocabulary, as required for DoLa decoding.
+        fn supports_layer_contrastive_decoding(&self) -> bool {
+            false
+        }
+
+        /// Indicates whether the cache of the model can be extended by forward passes over several
+        /// tokens, as required to run the prefill of long prompts in chunks.
+        fn supports_chunked_prefill(&self) -> bool {
+            false
+        }
+
+        /// Indicates whether the model can select positions of its cache, as required by the
+        /// attention sink cache policy.
+        fn supports_attention_sinks(&self) -> bool {
+            false
+        }
+
+        /// Indicates whether the model moves its activations between layers placed on different
+        /// devices or precisions, as required by the `layer_placement` of the `GenerateConfig`.
+        fn supports_layer_placement(&self) -> bool {
+            false
+        }
+
+        /// Keeps the cache `positions` (of shape (batch size, number of kept positions)) of each
+        /// sequence, in order.
+        fn select_cache_positions(&self, _past: &mut Cache, _positions: &Tensor) {}
+
+        /// Evicts the cache positions outside of the attention sinks and recent window once the
+        /// cache exceeds the size allowed by the policy. `attention_mask` covers the cached
+        /// positions and is reduced to the kept positions.
+        fn apply_attention_sink(
+            &self,
+            past: &mut Cache,
+            attention_mask: &mut Tensor,
+            attention_sink: &AttentionSinkConfig,
+        ) {
+            let cache_length = *attention_mask.size().last().unwrap();
+            if cache_length <= attention_sink.num_sink_tokens + attention_sink.window_size {
+                return;
+            }
+            let padding_lengths = attention_mask
+                .eq(0)
+                .sum_dim_intlist([1].as_slice(), false, Kind::Int64)
+                .iter::<i64>()
+                .unwrap()
+                .collect::<Vec<i64>>();
+            let positions =
+                attention_sink_positions(&padding_lengths, cache_length, attention_sink)
+                    .iter()
+                    .map(|positions| Tensor::of_slice(positions))
+                    .collect::<Vec<Tensor>>();
+            let positions = Tensor::stack(&positions, 0).to(attention_mask.device());
+            *attention_mask = attention_mask.gather(1, &positions, false);
+            self.select_cache_positions(past, &positions);
+        }
+
         fn prepare_scores_for_generation(
             &self,
             _scores: &mut Tensor,
@@ -324,214 +1007,191 @@ pub(crate) mod private_generation_utils {
             }
         }
 
-        fn encode_prompt_text<S>(
+        /// Prepares the inputs feeding the prompt tokens `start..end` to the model during a chunked
+        /// prefill, `past` holding the cache of the prompt tokens before `start`.
+        fn prepare_prefill_chunk_inputs<'a>(
+            &self,
+            input_ids: &Tensor,
+            attention_mask: &Tensor,
+            past: Cache,
+            start: i64,
+            end: i64,
+        ) -> PreparedInput<'a> {
+            PreparedInput {
+                prepared_input: Some(input_ids.slice(1, start, end, 1)),
+                prepared_attention_mask: Some(attention_mask.slice(1, 0, end, 1)),
+                prepared_encoder_output: None,
+                prepared_decoder_input: None,
+                prepared_position_ids: None,
+                prepared_past: past,
+            }
+        }
+
+        /// Runs the forward pass of the prompt in chunks of `chunk_size` tokens, extending the
+        /// cache after each chunk, and returns the cache. This bounds the size of the activations
+        /// for long prompts. The last prompt token is left to the first decoding step, which
+        /// computes the logits of the first generated token.
+        fn prefill(
+            &self,
+            input_ids: &Tensor,
+            attention_mask: &Tensor,
+            chunk_size: i64,
+            progress_fn: Option<PrefillProgressFunction>,
+        ) -> Result<Cache, RustBertError> {
+            let prompt_length = *input_ids.size().last().unwrap();
+            let mut past = Cache::None;
+            let mut start = 0;
+            while start < prompt_length - 1 {
+                let end = (start + chunk_size).min(prompt_length - 1);
+                let _chunk = trace_span!(DEBUG, "prefill_chunk", start, end);
+                let prepared_input =
+                    self.prepare_prefill_chunk_inputs(input_ids, attention_mask, past, start, end);
+                past = self
+                    .get_model()
+                    .forward_t(
+                        prepared_input.prepared_input.as_ref(),
+                        prepared_input.prepared_past,
+                        prepared_input.prepared_attention_mask.as_ref(),
+                        None,
+                        prepared_input.prepared_position_ids.as_ref(),
+                        None,
+                        None,
+                        None,
+                        false,
+                    )?
+                    .cache;
+                if let Some(progress_fn) = progress_fn {
+                    progress_fn(end, prompt_length);
+                }
+                start = end;
+            }
+            Ok(past)
+        }
+
+        /// Returns the cache of the prompt if the generation options request a chunked prefill
+        /// and the prompt is longer than the chunk size, `Cache::None` otherwise.
+        fn initial_cache(
+            &self,
+            input_ids: &Tensor,
+            attention_mask: &Tensor,
+            gen_opt: &InternalGenerateOptions,
+        ) -> Result<Cache, RustBertError> {
+            match gen_opt.prefill_chunk_size {
+                Some(chunk_size) if *input_ids.size().last().unwrap() > chunk_size => self.prefill(
+                    input_ids,
+                    attention_mask,
+                    chunk_size,
+                    gen_opt.prefill_progress_fn,
+                ),
+                _ => Ok(Cache::None),
+            }
+        }
+
+        /// Tokenizes the prompts and truncates them to `max_len` tokens (including the special
+        /// tokens if `add_special_tokens` is true), following the truncation side and strategy
+        /// of the generation configuration.
+        fn tokenize_prompt_text<S>(
             &self,
             prompt_text: &[S],
             max_len: Option<i64>,
-            pad_token_id: Option<i64>,
-        ) -> Tensor
+            add_special_tokens: bool,
+        ) -> Vec<Vec<i64>>
         where
             S: AsRef<str> + Sync,
         {
-            let tokens = self._get_tokenizer().tokenize_list(prompt_text);
-            let token_ids = tokens
-                .into_iter()
-                .map(|prompt_tokens| self._get_tokenizer().convert_tokens_to_ids(&prompt_tokens))
-                .collect::<Vec<Vec<i64>>>();
-
-            let num_truncated_tokens = token_ids
-                .iter()
-                .map(|token_ids| {
-                    max_len
-                        .map(|max_len| {
-                            if token_ids.len() > max_len as usize {
-                                token_ids.len() - max_len as usize
-                            } else {
-                                0
-                            }
-                        })
-                        .unwrap_or(0)
-                })
-                .collect::<Vec<usize>>();
-
-            let token_ids = token_ids
-                .into_iter()
-                .zip(num_truncated_tokens)
-                .map(|(tokens, num_truncated_tokens)| {
-                    truncate_sequences(
+            let tokenizer = self._get_tokenizer();
+            let config = self.get_config();
+            let with_special_tokens = |ids: Vec<i64>| {
+                let length = ids.len();
+                tokenizer
+                    .build_input_with_special_tokens(
                         TokenIdsWithOffsets {
-                            ids: tokens,
-                            offsets: vec![],
-                            reference_offsets: vec![],
-                            masks: vec![],
+                            ids,
+                            offsets: vec![None; length],
+                            reference_offsets: vec![vec![]; length],
+                            masks: vec![Mask::None; length],
                         },
                         None,
-                        num_truncated_tokens,
-                        &TruncationStrategy::LongestFirst,
-                        0,
                     )
-                    .unwrap()
-                    .0
-                    .ids
-                })
-                .collect::<Vec<Vec<i64>>>();
-
-            let max_len = token_ids.iter().map(|input| input.len()).max().unwrap();
-
-            let pad_token = match pad_token_id {
-                Some(value) => value,
-                None => self._get_tokenizer().get_unk_id(),
+                    .token_ids
+            };
+            let max_len = match (max_len, &config.prompt_truncation_strategy) {
+                (_, TruncationStrategy::DoNotTruncate) | (None, _) => None,
+                (Some(max_len), _) if add_special_tokens => {
+                    Some((max_len as usize).saturating_sub(with_special_tokens(vec![]).len()))
+                }
+                (Some(max_len), _) => Some(max_len as usize),
             };
 
-            let token_ids = token_ids
+            tokenizer
+                .tokenize_list(prompt_text)
                 .into_iter()
-                .map(|input| {
-                    let mut temp = vec![pad_token; max_len - input.len()];
-                    temp.extend(input);
-                    temp
-                })
-                .map(|tokens| Tensor::of_slice(&tokens).to(self.get_var_store().device()))
-                .collect::<Vec<Tensor>>();
-            Tensor::stack(&token_ids, 0)
-        }
-
-        fn enforce_repetition_penalty(
-            &self,
-            next_token_logits: &mut Tensor,
-            batch_size: i64,
-            num_beams: i64,
-            prev_output_tokens: &Tensor,
-            repetition_penalty: f64,
-        ) {
-            for i in 0..(batch_size * num_beams as i64) {
-                for token_position in 0..prev_output_tokens.get(i).size()[0] {
-                    let token = prev_output_tokens.get(i).int64_value(&[token_position]);
-                    let updated_value = &next_token_logits.double_value(&[i, token]);
-                    if updated_value < &0f64 {
-                        let _ = next_token_logits.get(i).index_fill_(
-                            0,
-                            &Tensor::of_slice(&[token])
-                                .to_kind(Kind::Int64)
-                                .to_device(next_token_logits.device()),
-                            updated_value * repetition_penalty,
-                        );
+                .map(|prompt_tokens| {
+                    let mut token_ids = tokenizer.convert_tokens_to_ids(&prompt_tokens);
+                    if let Some(max_len) = max_len {
+                        if token_ids.len() > max_len {
+                            match config.prompt_truncation_side {
+                                TruncationSide::Left => {
+                                    token_ids.drain(..token_ids.len() - max_len);
+                                }
+                                TruncationSide::Right => token_ids.truncate(max_len),
+                            }
+                        }
+                    }
+                    if add_special_tokens {
+                        with_special_tokens(token_ids)
                     } else {
-                        let _ = next_token_logits.get(i).index_fill_(
-                            0,
-                            &Tensor::of_slice(&[token])
-                                .to_kind(Kind::Int64)
-                                .to_device(next_token_logits.device()),
-                            updated_value / repetition_penalty,
-                        );
+                        token_ids
                     }
-                }
-            }
+                })
+                .collect()
         }
 
-        fn get_banned_tokens(
+        fn encode_prompt_text<S>(
             &self,
-            input_ids: &Tensor,
-            no_repeat_ngram_size: i64,
-            cur_len: i64,
-        ) -> Vec<Vec<i64>> {
-            //        Ported from hugging face's transformers and fairseq (https://github.com/pytorch/fairseq/blob/master/fairseq/sequence_generator.py)
-            if cur_len + 1 < no_repeat_ngram_size {
-                vec![vec![]]
-            } else {
-                let input_ids = input_ids.to(Device::Cpu);
-                let num_hypothesis = *input_ids.size().first().unwrap();
-                let mut banned_tokens: Vec<Vec<i64>> = Vec::with_capacity(num_hypothesis as usize);
-                for hypothesis_index in 0..num_hypothesis {
-                    let hypothesis_input_ids = input_ids.get(hypothesis_index);
-                    let mut generated_ngram: HashMap<Vec<i64>, Vec<i64>> = HashMap::new();
-                    let input: Vec<i64> = (0..hypothesis_input_ids.size1().unwrap()).collect();
-                    let hypothesis_input_ids = hypothesis_input_ids
-                        .iter::<i64>()
-                        .unwrap()
-                        .collect::<Vec<i64>>();
-                    let query = &hypothesis_input_ids
-                        [cur_len as usize + 1 - no_repeat_ngram_size as usize..]
-                        .to_vec();
-                    for ngram in input
-                        .windows(no_repeat_ngram_size as usize)
-                        .map(|win| (*win.first().unwrap(), *win.last().unwrap()))
-                    {
-                        let ngram = &hypothesis_input_ids[ngram.0 as usize..ngram.1 as usize + 1];
-                        let key = ngram[..no_repeat_ngram_size as usize - 1].to_vec();
-                        let value = *ngram.last().unwrap();
-                        generated_ngram
-                            .entry(key)
-                            .or_insert_with(|| vec![value])
-                            .push(value);
-                    }
-                    let hypothesis_banned_tokens = match generated_ngram.get(query) {
-                        Some(banned_tokens) => banned_tokens.clone(),
-                        None => vec![],
-                    };
-                    banned_tokens.push(hypothesis_banned_tokens);
+            prompt_text: &[S],
+            max_len: Option<i64>,
+            pad_token_id: Option<i64>,
+        ) -> (Tensor, Tensor)
+        where
+            S: AsRef<str> + Sync,
+        {
+            let mut token_ids = self.tokenize_prompt_text(prompt_text, max_len, false);
+            // Empty prompts are seeded with the BOS token so that they can be batched with other prompts
+            if let Some(bos_id) = self.get_bos_id() {
+                for prompt_ids in token_ids.iter_mut().filter(|ids| ids.is_empty()) {
+                    prompt_ids.push(bos_id);
                 }
-                banned_tokens
             }
+
+            let pad_token = match pad_token_id {
+                Some(value) => value,
+                None => self._get_tokenizer().get_unk_id(),
+            };
+            pad_prompt_ids(token_ids, pad_token, true, self.get_var_store().device())
         }
 
-        fn top_k_top_p_filtering(
+        fn stopping_criteria_met(
             &self,
-            logits: &mut Tensor,
-            top_k: i64,
-            top_p: f64,
-            min_tokens_to_keep: i64,
-        ) {
-            //        Nucleus and top-k filtering introduced by Holtzman et al. (http://arxiv.org/abs/1904.09751)
-            //        Ported from https://gist.github.com/thomwolf/1a5a29f6962089e871b94cbd09daf317
-            let vocab_size = *logits.size().last().unwrap();
-            if top_k > 0 {
-                let top_k = vocab_size - min(max(top_k, min_tokens_to_keep), vocab_size);
-                let (_, indices_to_remove) = logits.topk(top_k, -1, false, false);
-                for index in 0..*logits.size().first().unwrap() {
-                    let _ = logits.get(index).index_fill_(
-                        0,
-                        &indices_to_remove.get(index),
-                        f64::NEG_INFINITY,
-                    );
-                }
-            }
-            if top_p < 1f64 {
-                let (sorted_logits, sorted_indices) = logits.sort(-1, true);
-                let cumulative_probabilities = sorted_logits
-                    .softmax(-1, sorted_logits.kind())
-                    .cumsum(-1, sorted_logits.kind());
-                let mut sorted_indices_to_remove =
-                    cumulative_probabilities.ge(top_p).to_kind(Kind::Int64);
-                if min_tokens_to_keep > 1 {
-                    let _ = sorted_indices_to_remove.index_fill_(
-                        1,
-                        &Tensor::arange_start(
-                            0,
-                            min_tokens_to_keep + 1,
-                            (Kind::Int64, logits.device()),
-                        ),
-                        0,
-                    );
-                }
-                let _ = sorted_indices_to_remove.index_copy_(
-                    1,
-                    &Tensor::arange_start(1, vocab_size, (Kind::Int64, logits.device())),
-                    &sorted_indices_to_remove
-                        .slice(1, 0, vocab_size - 1, 1)
-                        .copy(),
-                );
-                let _ = sorted_indices_to_remove.index_fill_(
-                    1,
-                    &Tensor::of_slice(&[0])
-                        .to_kind(Kind::Int64)
-                        .to_device(sorted_indices_to_remove.device()),
-                    0,
-                );
-                let indices_to_remove = sorted_indices_to_remove
-                    .scatter(1, &sorted_indices, &sorted_indices_to_remove)
-                    .to_kind(Kind::Bool);
-                let _ = logits.masked_fill_(&indices_to_remove, f64::NEG_INFINITY);
+            input_ids: &Tensor,
+            prompt_length: i64,
+            current_length: i64,
+            budget: &GenerationBudget,
+        ) -> bool {
+            let stopping_criteria = &self.get_config().stopping_criteria;
+            if stopping_criteria.is_empty() {
+                return false;
             }
+            let state = StoppingState::new(
+                input_ids,
+                prompt_length,
+                current_length,
+                budget.elapsed(),
+                self._get_tokenizer(),
+            );
+            stopping_criteria
+                .iter()
+                .any(|criteria| criteria.should_stop(&state))
         }
 
         fn run_hamming_diversity_penalty(
@@ -634,6 +1294,60 @@ pub(crate) mod private_generation_utils {
             }
         }
 
+        /// Contrasts the next token log-probabilities of the final layer with the ones of the
+        /// candidate premature layer with the highest Jensen-Shannon divergence from the final
+        /// layer ([DoLa, Chuang et al.](https://arxiv.org/abs/2309.03883)). Tokens with a final
+        /// probability lower than a tenth of the most likely token are discarded (adaptive
+        /// plausibility constraint).
+        fn contrast_layer_logits(
+            &self,
+            lm_logits: &Tensor,
+            hidden_states: &[Tensor],
+            candidate_layers: &[i64],
+        ) -> Tensor {
+            let final_log_probs = lm_logits
+                .select(1, -1)
+                .to_kind(Kind::Float)
+                .log_softmax(-1, Kind::Float);
+            let premature_log_probs = Tensor::stack(
+                &candidate_layers
+                    .iter()
+                    .filter_map(|layer| hidden_states.get(*layer as usize))
+                    .filter_map(|hidden_state| {
+                        self.get_model()
+                            .project_hidden_states(&hidden_state.select(1, -1))
+                    })
+                    .map(|logits| logits.to_kind(Kind::Float).log_softmax(-1, Kind::Float))
+                    .collect::<Vec<Tensor>>(),
+                0,
+            );
+
+            let final_probs = final_log_probs.exp().unsqueeze(0);
+            let premature_probs = premature_log_probs.exp();
+            let mean_log_probs =
+                final_log_probs.unsqueeze(0).logaddexp(&premature_log_probs) - 2f64.ln();
+            let divergence =
+                ((&final_probs * (final_log_probs.unsqueeze(0) - &mean_log_probs))
+                    .sum_dim_intlist([-1].as_slice(), false, Kind::Float)
+                    + (&premature_probs * (&premature_log_probs - &mean_log_probs))
+                        .sum_dim_intlist([-1].as_slice(), false, Kind::Float))
+                    * 0.5;
+            let vocab_size = *final_log_probs.size().last().unwrap();
+            let selected_layers = divergence
+                .argmax(0, false)
+                .view([1, -1, 1])
+                .expand(&[1, -1, vocab_size], true);
+            let selected_log_probs = premature_log_probs
+                .gather(0, &selected_layers, false)
+                .squeeze_dim(0);
+
+            let (max_log_probs, _) = final_log_probs.max_dim(-1, true);
+            let implausible_tokens = final_log_probs.lt_tensor(&(max_log_probs + 0.1f64.ln()));
+            (&final_log_probs - selected_log_probs)
+                .masked_fill(&implausible_tokens, f64::NEG_INFINITY)
+                .to_kind(lm_logits.kind())
+        }
+
         fn calc_static_bad_word_mask(
             &self,
             scores: &Tensor,
@@ -677,11 +1391,21 @@ pub(crate) mod private_generation_utils {
             token_ids: &Tensor,
             scores: &mut Tensor,
         ) {
+            let dynamic_bad_words = match dynamic_bad_words {
+                Some(dynamic_bad_words) if !dynamic_bad_words.is_empty() => dynamic_bad_words,
+                _ => {
+                    if let Some(static_bad_words_mask) = static_bad_words_mask {
+                        let _ = scores.masked_fill_(static_bad_words_mask, f64::NEG_INFINITY);
+                    }
+                    return;
+                }
+            };
+
             let longest_bad_word = dynamic_bad_words
                 .iter()
                 .map(|bad_word| bad_word.len())
                 .max()
-                .unwrap() as i64;
+                .unwrap_or(0) as i64;
 
             let last_token_ids = token_ids.slice(1, -longest_bad_word, None, 1);
             let mut prev_tokens = Vec::new();
@@ -695,50 +1419,26 @@ pub(crate) mod private_generation_utils {
                 )
             }
 
-            let dynamic_bad_words_mask = if let Some(dynamic_bad_words) = dynamic_bad_words {
-                let dynamic_banned_tokens =
-                    self.get_dynamic_bad_word_ids(&prev_tokens, dynamic_bad_words);
-                let dynamic_banned_mask =
-                    Tensor::zeros(scores.size().as_slice(), (Kind::Int, scores.device()));
-                for (sequence_index, sequence_ban_tokens) in
-                    dynamic_banned_tokens.iter().enumerate()
-                {
-                    if !sequence_ban_tokens.is_empty() {
-                        let _ = dynamic_banned_mask.get(sequence_index as i64).index_fill_(
-                            0,
-                            &Tensor::of_slice(sequence_ban_tokens).to_device(scores.device()),
-                            1,
-                        );
-                    }
-                }
-                Some(dynamic_banned_mask.to_kind(Kind::Bool))
-            } else {
-                None
-            };
-
-            let combined_bad_word_mask = {
-                if let (Some(static_mask), Some(dynamic_mask)) =
-                    (static_bad_words_mask, &dynamic_bad_words_mask)
-                {
-                    Some(static_mask.bitwise_or_tensor(dynamic_mask))
-                } else {
-                    None
+            let dynamic_banned_tokens =
+                self.get_dynamic_bad_word_ids(&prev_tokens, dynamic_bad_words);
+            let dynamic_banned_mask =
+                Tensor::zeros(scores.size().as_slice(), (Kind::Int, scores.device()));
+            for (sequence_index, sequence_ban_tokens) in dynamic_banned_tokens.iter().enumerate() {
+                if !sequence_ban_tokens.is_empty() {
+                    let _ = dynamic_banned_mask.get(sequence_index as i64).index_fill_(
+                        0,
+                        &Tensor::of_slice(sequence_ban_tokens).to_device(scores.device()),
+                        1,
+                    );
                 }
-            };
+            }
+            let dynamic_banned_mask = dynamic_banned_mask.to_kind(Kind::Bool);
 
-            let bad_word_mask = if combined_bad_word_mask.is_some() {
-                combined_bad_word_mask.as_ref()
-            } else if static_bad_words_mask.is_some() {
-                static_bad_words_mask
-            } else if dynamic_bad_words_mask.is_some() {
-                dynamic_bad_words_mask.as_ref()
-            } else {
-                None
+            let bad_word_mask = match static_bad_words_mask {
+                Some(static_mask) => static_mask.bitwise_or_tensor(&dynamic_banned_mask),
+                None => dynamic_banned_mask,
             };
-
-            if let Some(bad_word_mask) = bad_word_mask {
-                let _ = scores.masked_fill_(bad_word_mask, f64::NEG_INFINITY);
-            }
+            let _ = scores.masked_fill_(&bad_word_mask, f64::NEG_INFINITY);
         }
 
         fn generate_no_beam_search(
@@ -751,7 +1451,7 @@ pub(crate) mod private_generation_utils {
             gen_opt: InternalGenerateOptions,
             prefix_allowed_tokens_fn: Option<PrefixAllowedFunction>,
             output_scores: bool,
-        ) -> GeneratedOutputWithScores {
+        ) -> Result<GeneratedOutputWithScores, RustBertError> {
             let mut unfinished_sentences =
                 Tensor::ones(&[batch_size], (Kind::Int64, self.get_var_store().device()));
             let mut sentence_lengths: Tensor =
@@ -759,48 +1459,78 @@ pub(crate) mod private_generation_utils {
             let (bad_word_ids_length_1, bad_word_ids_length_greater_than_1) =
                 self.split_bad_word_ids(gen_opt.bad_word_ids);
             let mut static_bad_words_mask: Option<Tensor> = None;
+            let mut logit_bias_tensor: Option<Tensor> = None;
             let mut attention_mask = attention_mask.copy();
             let mut input_ids = input_ids.copy();
-            let mut past: Cache = Cache::None;
+            let mut past = self.initial_cache(&input_ids, &attention_mask, &gen_opt)?;
             let mut outputs: Tensor;
             let mut current_length = cur_len;
             let mut token_scores_output: Option<Vec<Tensor>> =
                 if output_scores { Some(vec![]) } else { None };
+            let mut budget = gen_opt.budget;
+            let mut truncated = vec![false; batch_size as usize];
+            // Tokens are reported to the token callback once they can no longer be removed by a
+            // stop sequence
+            let held_back_tokens = stop_sequence_window_length(gen_opt.stop_sequences).unwrap_or(0);
+            let mut pending_tokens = vec![VecDeque::new(); batch_size as usize];
 
             loop {
+                let _step = trace_span!(DEBUG, "decode_step", current_length);
+                let active_sequences = if budget.has_token_limit() {
+                    i64::from(unfinished_sentences.sum(Kind::Int64))
+                } else {
+                    0
+                };
                 let prepared_input = self.prepare_inputs_for_generation(
                     input_ids.copy(),
                     encoder_outputs.as_ref(),
                     past,
                     attention_mask.copy(),
                 );
-                let temp = self
-                    .get_model()
-                    .forward_t(
-                        prepared_input.prepared_input.as_ref(),
-                        prepared_input.prepared_past,
-                        prepared_input.prepared_attention_mask.as_ref(),
-                        None,
-                        prepared_input.prepared_position_ids.as_ref(),
-                        None,
-                        prepared_input.prepared_encoder_output,
-                        prepared_input.prepared_decoder_input.as_ref(),
-                        false,
-                    )
-                    .unwrap();
+                let temp = self.get_model().forward_t(
+                    prepared_input.prepared_input.as_ref(),
+                    prepared_input.prepared_past,
+                    prepared_input.prepared_attention_mask.as_ref(),
+                    None,
+                    prepared_input.prepared_position_ids.as_ref(),
+                    None,
+                    prepared_input.prepared_encoder_output,
+                    prepared_input.prepared_decoder_input.as_ref(),
+                    false,
+                )?;
                 outputs = temp.lm_logits;
                 past = temp.cache;
+                if let (Some(dola_layers), Some(hidden_states)) =
+                    (gen_opt.dola_layers, temp.all_hidden_states.as_ref())
+                {
+                    outputs = self
+                        .contrast_layer_logits(&outputs, hidden_states, dola_layers)
+                        .unsqueeze(1);
+                }
+                if let Some(attention_sink) = &gen_opt.attention_sink {
+                    self.apply_attention_sink(&mut past, &mut attention_mask, attention_sink);
+                }
 
                 let mut next_token_logits = outputs.select(1, -1);
                 // Reduce probability for repeated inputs
                 if gen_opt.repetition_penalty > 1f64 {
-                    self.enforce_repetition_penalty(
-                        &mut next_token_logits,
-                        batch_size,
-                        1,
+                    RepetitionPenaltyLogitsProcessor {
+                        penalty: gen_opt.repetition_penalty,
+                    }
+                    .process(
                         &input_ids,
-                        gen_opt.repetition_penalty,
-                    )
+                        &mut next_token_logits,
+                        current_length,
+                    );
+                }
+
+                // Apply the logit bias
+                if let Some(logit_bias) = gen_opt.logit_bias {
+                    if logit_bias_tensor.is_none() {
+                        logit_bias_tensor =
+                            Some(get_logit_bias_tensor(logit_bias, &next_token_logits));
+                    }
+                    next_token_logits += logit_bias_tensor.as_ref().unwrap();
                 }
 
                 // Get bad word_ids and set their probability to 0
@@ -824,21 +1554,19 @@ pub(crate) mod private_generation_utils {
 
                 // Get banned tokens and set their probability to 0
                 if gen_opt.no_repeat_ngram_size > 0 {
-                    let banned_tokens = self.get_banned_tokens(
+                    NoRepeatNGramLogitsProcessor {
+                        ngram_size: gen_opt.no_repeat_ngram_size,
+                    }
+                    .process(
                         &input_ids,
-                        gen_opt.no_repeat_ngram_size as i64,
-                        current_length as i64,
+                        &mut next_token_logits,
+                        current_length,
                     );
-                    for (batch_index, index_banned_token) in
-                        (0..banned_tokens.len() as i64).zip(banned_tokens)
-                    {
-                        let _ = next_token_logits.get(batch_index).index_fill_(
-                            0,
-                            &Tensor::of_slice(&index_banned_token)
-                                .to_device(next_token_logits.device()),
-                            f64::NEG_INFINITY,
-                        );
-                    }
+                }
+
+                // Apply the custom logits processors
+                for logits_processor in self.get_config().logits_processors.iter() {
+                    logits_processor.process(&input_ids, &mut next_token_logits, current_length);
                 }
 
                 // Apply custom prefix constraint function
@@ -851,6 +1579,16 @@ pub(crate) mod private_generation_utils {
                     )
                 }
 
+                // Apply the grammar constraint
+                if let Some(grammar) = gen_opt.grammar {
+                    grammar.process(
+                        &input_ids,
+                        cur_len,
+                        gen_opt.eos_token_ids.as_ref(),
+                        &mut next_token_logits,
+                    );
+                }
+
                 // Do not allow eos token if min length is not reached
                 if (gen_opt.eos_token_ids.is_some()) & (current_length < gen_opt.min_length) {
                     let _ = next_token_logits.index_fill_(
@@ -867,17 +1605,23 @@ pub(crate) mod private_generation_utils {
                     gen_opt.max_length,
                     gen_opt.forced_bos_token_id,
                 );
+                force_bos_eos_tokens(&mut next_token_logits, current_length, cur_len, &gen_opt);
 
                 // Top-k and top-p sampling
                 let next_token = if gen_opt.do_sample {
                     if gen_opt.temperature > 1f64 {
                         next_token_logits /= gen_opt.temperature;
                     }
-                    self.top_k_top_p_filtering(
+                    TopKTopPLogitsProcessor {
+                        top_k: gen_opt.top_k,
+                        top_p: gen_opt.top_p,
+                        min_p: gen_opt.min_p,
+                        min_tokens_to_keep: 1,
+                    }
+                    .process(
+                        &input_ids,
                         &mut next_token_logits,
-                        gen_opt.top_k as i64,
-                        gen_opt.top_p,
-                        1,
+                        current_length,
                     );
                     let probabilities = next_token_logits.softmax(-1, next_token_logits.kind());
                     probabilities.multinomial(1, false).squeeze_dim(1)
@@ -897,13 +1641,25 @@ pub(crate) mod private_generation_utils {
                 };
 
                 // Add tokens to unfinished sentences
-                let tokens_to_add = match &gen_opt.eos_token_ids {
-                    Some(_) => {
+                let tokens_to_add =
+                    if gen_opt.eos_token_ids.is_some() | !gen_opt.stop_sequences.is_empty() {
                         next_token * &unfinished_sentences
                             - gen_opt.pad_token_id.unwrap() * (&unfinished_sentences - 1)
+                    } else {
+                        next_token
+                    };
+                if gen_opt.token_callback_fn.is_some() {
+                    for ((token_id, unfinished), pending) in tokens_to_add
+                        .iter::<i64>()
+                        .unwrap()
+                        .zip(unfinished_sentences.iter::<i64>().unwrap())
+                        .zip(pending_tokens.iter_mut())
+                    {
+                        if unfinished == 1 {
+                            pending.push_back(token_id);
+                        }
                     }
-                    None => next_token,
-                };
+                }
 
                 input_ids = Tensor::cat(&[input_ids, tokens_to_add.unsqueeze(-1)], -1);
                 if gen_opt.eos_token_ids.is_some() {
@@ -919,10 +1675,65 @@ pub(crate) mod private_generation_utils {
                         );
                         unfinished_sentences = -unfinished_sentences * (sentence_with_eos - 1);
                     }
-                    if i64::from(unfinished_sentences.max()) == 0 {
-                        break;
+                }
+                if !gen_opt.stop_sequences.is_empty() & (current_length >= gen_opt.min_length) {
+                    let tokenizer = self._get_tokenizer();
+                    let stopped_sequences = unfinished_sentences
+                        .iter::<i64>()
+                        .unwrap()
+                        .enumerate()
+                        .filter(|&(_, unfinished)| unfinished == 1)
+                        .filter_map(|(sequence_index, _)| {
+                            let generated_ids = input_ids
+                                .get(sequence_index as i64)
+                                .narrow(0, cur_len, current_length + 1 - cur_len)
+                                .iter::<i64>()
+                                .unwrap()
+                                .collect::<Vec<i64>>();
+                            stop_sequence_trim_length(
+                                &generated_ids,
+                                gen_opt.stop_sequences,
+                                |token_ids| tokenizer.decode(token_ids, true, false),
+                            )
+                            .map(|trim_length| (sequence_index as i64, trim_length as i64))
+                        })
+                        .collect::<Vec<(i64, i64)>>();
+                    for (sequence_index, trim_length) in stopped_sequences {
+                        let pending = &mut pending_tokens[sequence_index as usize];
+                        pending.truncate(pending.len().saturating_sub(trim_length as usize));
+                        let sentence_length = current_length + 1 - trim_length;
+                        let _ = sentence_lengths.get(sequence_index).fill_(sentence_length);
+                        let _ = unfinished_sentences.get(sequence_index).fill_(0);
+                        let _ = input_ids
+                            .get(sequence_index)
+                            .narrow(0, sentence_length, trim_length)
+                            .fill_(gen_opt.pad_token_id.unwrap());
+                        if let Some(prev_scores) = token_scores_output.as_mut() {
+                            for step_scores in
+                                prev_scores.iter_mut().rev().take(trim_length as usize)
+                            {
+                                let _ = step_scores.view(-1).get(sequence_index).fill_(0);
+                            }
+                        }
+                    }
+                }
+                if let Some(token_callback_fn) = gen_opt.token_callback_fn {
+                    for ((sequence_index, pending), unfinished) in pending_tokens
+                        .iter_mut()
+                        .enumerate()
+                        .zip(unfinished_sentences.iter::<i64>().unwrap())
+                    {
+                        let held_back = if unfinished == 1 { held_back_tokens } else { 0 };
+                        while pending.len() > held_back {
+                            token_callback_fn(sequence_index, pending.pop_front().unwrap());
+                        }
                     }
                 }
+                if (gen_opt.eos_token_ids.is_some() | !gen_opt.stop_sequences.is_empty())
+                    && (i64::from(unfinished_sentences.max()) == 0)
+                {
+                    break;
+                }
                 if !self.is_encoder_decoder() {
                     attention_mask = Tensor::cat(
                         &[
@@ -948,6 +1759,30 @@ pub(crate) mod private_generation_utils {
                         break;
                     }
                 }
+                if budget.consume(active_sequences)
+                    || self.stopping_criteria_met(&input_ids, cur_len, current_length, &budget)
+                {
+                    let _ = sentence_lengths.masked_fill_(
+                        &unfinished_sentences
+                            .to_kind(Kind::Bool)
+                            .to_device(sentence_lengths.device()),
+                        current_length as i64,
+                    );
+                    truncated = unfinished_sentences
+                        .iter::<i64>()
+                        .unwrap()
+                        .map(|unfinished| unfinished == 1)
+                        .collect();
+                    break;
+                }
+            }
+            // Sequences reaching the maximum length or budget did not end with a stop sequence
+            if let Some(token_callback_fn) = gen_opt.token_callback_fn {
+                for (sequence_index, pending) in pending_tokens.into_iter().enumerate() {
+                    for token_id in pending {
+                        token_callback_fn(sequence_index, token_id);
+                    }
+                }
             }
             let scores_output = token_scores_output.as_ref().map(|scores_tensor| {
                 (Tensor::stack(scores_tensor, 1).sum_dim_intlist(
@@ -972,11 +1807,13 @@ pub(crate) mod private_generation_utils {
                     })
                     .collect()
             });
-            GeneratedOutputWithScores {
+            Ok(GeneratedOutputWithScores {
                 indices: input_ids,
                 scores: scores_output,
                 token_scores: token_scores_output,
-            }
+                beam_hypotheses: None,
+                truncated,
+            })
         }
 
         fn generate_beam_search(
@@ -989,13 +1826,14 @@ pub(crate) mod private_generation_utils {
             gen_opt: InternalGenerateOptions,
             prefix_allowed_tokens_fn: Option<PrefixAllowedFunction>,
             output_scores: bool,
-        ) -> GeneratedOutputWithScores {
+        ) -> Result<GeneratedOutputWithScores, RustBertError> {
             let num_beam_groups = gen_opt.num_beam_groups.unwrap_or(1);
             let num_sub_beams = gen_opt.num_beams / num_beam_groups;
             let diversity_penalty = gen_opt.diversity_penalty.unwrap_or(5.5);
             let (bad_word_ids_length_1, bad_word_ids_length_greater_than_1) =
                 self.split_bad_word_ids(gen_opt.bad_word_ids);
             let mut static_bad_words_mask: Option<Tensor> = None;
+            let mut logit_bias_tensor: Option<Tensor> = None;
 
             let mut hypotheses = (0..batch_size)
                 .map(|_| {
@@ -1030,42 +1868,99 @@ pub(crate) mod private_generation_utils {
                 if output_scores { Some(vec![]) } else { None };
             let mut current_tokens = Tensor::new();
 
-            let mut past: Cache = Cache::None;
             let mut done = vec![false; batch_size as usize];
+            let mut budget = gen_opt.budget;
+            let mut truncated_inputs = vec![false; batch_size as usize];
 
             let mut outputs: Tensor;
             let mut encoder_outputs = encoder_outputs;
             let mut current_length = cur_len;
 
+            // The prompt is identical for all beams of an input: its forward pass is run once per
+            // input and the outputs and cache are expanded to the beams afterwards.
+            let mut lazy_expansion = gen_opt.lazy_beam_expansion
+                && gen_opt.num_beams > 1
+                && !self.is_encoder_decoder()
+                && self.supports_lazy_beam_expansion();
+            let mut past = if lazy_expansion {
+                let first_beam_indices = Tensor::arange_start_step(
+                    0,
+                    batch_size * gen_opt.num_beams,
+                    gen_opt.num_beams,
+                    (Kind::Int64, input_ids.device()),
+                );
+                self.initial_cache(
+                    &input_ids.index_select(0, &first_beam_indices),
+                    &attention_mask.index_select(0, &first_beam_indices),
+                    &gen_opt,
+                )?
+            } else {
+                self.initial_cache(&input_ids, &attention_mask, &gen_opt)?
+            };
+
             loop {
+                let _step = trace_span!(DEBUG, "decode_step", current_length);
                 if num_beam_groups > 1 {
                     current_tokens = Tensor::zeros(
                         &[batch_size * gen_opt.num_beams],
                         (input_ids.kind(), input_ids.device()),
                     );
                 }
+                let (model_input_ids, model_attention_mask) = if lazy_expansion {
+                    let first_beam_indices = Tensor::arange_start_step(
+                        0,
+                        batch_size * gen_opt.num_beams,
+                        gen_opt.num_beams,
+                        (Kind::Int64, input_ids.device()),
+                    );
+                    (
+                        input_ids.index_select(0, &first_beam_indices),
+                        attention_mask.index_select(0, &first_beam_indices),
+                    )
+                } else {
+                    (input_ids.copy(), attention_mask.copy())
+                };
                 let prepared_input = self.prepare_inputs_for_generation(
-                    input_ids.copy(),
+                    model_input_ids,
                     encoder_outputs.as_ref(),
                     past,
-                    attention_mask.copy(),
+                    model_attention_mask,
                 );
-                let temp = self
-                    .get_model()
-                    .forward_t(
-                        prepared_input.prepared_input.as_ref(),
-                        prepared_input.prepared_past,
-                        prepared_input.prepared_attention_mask.as_ref(),
-                        None,
-                        prepared_input.prepared_position_ids.as_ref(),
-                        None,
-                        prepared_input.prepared_encoder_output,
-                        prepared_input.prepared_decoder_input.as_ref(),
-                        false,
-                    )
-                    .unwrap();
+                let temp = self.get_model().forward_t(
+                    prepared_input.prepared_input.as_ref(),
+                    prepared_input.prepared_past,
+                    prepared_input.prepared_attention_mask.as_ref(),
+                    None,
+                    prepared_input.prepared_position_ids.as_ref(),
+                    None,
+                    prepared_input.prepared_encoder_output,
+                    prepared_input.prepared_decoder_input.as_ref(),
+                    false,
+                )?;
                 outputs = temp.lm_logits;
                 past = temp.cache;
+                if let (Some(dola_layers), Some(hidden_states)) =
+                    (gen_opt.dola_layers, temp.all_hidden_states.as_ref())
+                {
+                    outputs = self
+                        .contrast_layer_logits(&outputs, hidden_states, dola_layers)
+                        .unsqueeze(1);
+                }
+
+                if lazy_expansion {
+                    let expanded_batch_indices =
+                        Tensor::arange(batch_size, (Kind::Int64, input_ids.device()))
+                            .view((-1, 1))
+                            .repeat(&[1, gen_opt.num_beams])
+                            .view(-1);
+                    outputs = outputs.index_select(0, &expanded_batch_indices);
+                    encoder_outputs =
+                        self.reorder_cache(&mut past, encoder_outputs, &expanded_batch_indices);
+                    lazy_expansion = false;
+                }
+                if let Some(attention_sink) = &gen_opt.attention_sink {
+                    self.apply_attention_sink(&mut past, &mut attention_mask, attention_sink);
+                }
 
                 for beam_group_index in 0..num_beam_groups {
                     let group_start_index = beam_group_index * num_sub_beams;
@@ -1098,15 +1993,26 @@ pub(crate) mod private_generation_utils {
                             .select(1, -1)
                             .index_select(0, batch_group_indices.as_ref().unwrap())
                     };
-                    // Reduce probability for repeated inputs
+                    // Reduce probability for repeated inputs (historically applied to the first
+                    // `batch_size` hypotheses only, kept for reproducibility of the outputs)
                     if gen_opt.repetition_penalty > 1f64 {
-                        self.enforce_repetition_penalty(
-                            &mut next_token_logits,
-                            batch_size,
-                            1,
+                        RepetitionPenaltyLogitsProcessor {
+                            penalty: gen_opt.repetition_penalty,
+                        }
+                        .process(
                             group_input_ids.as_ref().unwrap_or(&input_ids),
-                            gen_opt.repetition_penalty,
-                        )
+                            &mut next_token_logits.narrow(0, 0, batch_size),
+                            current_length,
+                        );
+                    }
+
+                    // Apply the logit bias
+                    if let Some(logit_bias) = gen_opt.logit_bias {
+                        if logit_bias_tensor.is_none() {
+                            logit_bias_tensor =
+                                Some(get_logit_bias_tensor(logit_bias, &next_token_logits));
+                        }
+                        next_token_logits += logit_bias_tensor.as_ref().unwrap();
                     }
 
                     if gen_opt.temperature > 1f64 {
@@ -1118,6 +2024,7 @@ pub(crate) mod private_generation_utils {
                         gen_opt.max_length,
                         gen_opt.forced_bos_token_id,
                     );
+                    force_bos_eos_tokens(&mut next_token_logits, current_length, cur_len, &gen_opt);
 
                     let mut scores = next_token_logits.log_softmax(-1, next_token_logits.kind());
 
@@ -1151,21 +2058,14 @@ pub(crate) mod private_generation_utils {
 
                     // Get repeated tokens and set their probability to 0
                     if gen_opt.no_repeat_ngram_size > 0 {
-                        let banned_tokens = self.get_banned_tokens(
+                        NoRepeatNGramLogitsProcessor {
+                            ngram_size: gen_opt.no_repeat_ngram_size,
+                        }
+                        .process(
                             group_input_ids.as_ref().unwrap_or(&input_ids),
-                            gen_opt.no_repeat_ngram_size,
+                            &mut scores,
                             current_length,
                         );
-                        for (batch_index, index_banned_token) in
-                            (0..banned_tokens.len() as i64).zip(banned_tokens)
-                        {
-                            let _ = scores.get(batch_index).index_fill_(
-                                0,
-                                &Tensor::of_slice(&index_banned_token)
-                                    .to_device(next_token_logits.device()),
-                                f64::NEG_INFINITY,
-                            );
-                        }
                     }
 
                     // Update scores with diversity penalty
@@ -1181,6 +2081,15 @@ pub(crate) mod private_generation_utils {
                         );
                     }
 
+                    // Apply the custom logits processors
+                    for logits_processor in self.get_config().logits_processors.iter() {
+                        logits_processor.process(
+                            group_input_ids.as_ref().unwrap_or(&input_ids),
+                            &mut scores,
+                            current_length,
+                        );
+                    }
+
                     // Apply custom prefix constraint function
                     if let Some(prefix_allowed_tokens_function) = prefix_allowed_tokens_fn {
                         self.apply_prefix_allowed_tokens_function(
@@ -1191,6 +2100,16 @@ pub(crate) mod private_generation_utils {
                         )
                     }
 
+                    // Apply the grammar constraint
+                    if let Some(grammar) = gen_opt.grammar {
+                        grammar.process(
+                            group_input_ids.as_ref().unwrap_or(&input_ids),
+                            cur_len,
+                            gen_opt.eos_token_ids.as_ref(),
+                            &mut scores,
+                        );
+                    }
+
                     let mut next_scores: Tensor = &scores
                         + (if num_beam_groups > 1 {
                             beam_scores
@@ -1202,11 +2121,16 @@ pub(crate) mod private_generation_utils {
                         });
 
                     let (next_scores, next_tokens) = if gen_opt.do_sample {
-                        self.top_k_top_p_filtering(
+                        TopKTopPLogitsProcessor {
+                            top_k: gen_opt.top_k,
+                            top_p: gen_opt.top_p,
+                            min_p: gen_opt.min_p,
+                            min_tokens_to_keep: 2,
+                        }
+                        .process(
+                            &input_ids,
                             &mut next_scores,
-                            gen_opt.top_k,
-                            gen_opt.top_p,
-                            2,
+                            current_length,
                         );
                         let _scores = next_scores
                             .contiguous()
@@ -1236,6 +2160,55 @@ pub(crate) mod private_generation_utils {
                     if let Some(eos_token_id) = eos_token_ids {
                         eos_mask -= token_id_tensor.eq(eos_token_id[0]).to_kind(Kind::Int64);
                     }
+                    // Candidates producing a stop sequence end their beam like an EOS token, the
+                    // hypothesis being truncated before the stop sequence
+                    let mut stop_sequence_lengths = HashMap::new();
+                    if !gen_opt.stop_sequences.is_empty() & (current_length >= gen_opt.min_length) {
+                        let tokenizer = self._get_tokenizer();
+                        for batch_index in 0..batch_size {
+                            if done[batch_index as usize] {
+                                continue;
+                            }
+                            for (beam_index_pos, (token_id, effective_beam_id)) in token_id_tensor
+                                .get(batch_index)
+                                .iter::<i64>()
+                                .unwrap()
+                                .zip(
+                                    effective_beam_ids_tensor
+                                        .get(batch_index)
+                                        .iter::<i64>()
+                                        .unwrap(),
+                                )
+                                .enumerate()
+                            {
+                                if eos_token_ids
+                                    .map_or(false, |eos_token_ids| eos_token_ids[0] == token_id)
+                                {
+                                    continue;
+                                }
+                                let mut generated_ids = input_ids
+                                    .get(effective_beam_id)
+                                    .narrow(0, cur_len, current_length - cur_len)
+                                    .iter::<i64>()
+                                    .unwrap()
+                                    .collect::<Vec<i64>>();
+                                generated_ids.push(token_id);
+                                if let Some(trim_length) = stop_sequence_trim_length(
+                                    &generated_ids,
+                                    gen_opt.stop_sequences,
+                                    |token_ids| tokenizer.decode(token_ids, true, false),
+                                ) {
+                                    stop_sequence_lengths.insert(
+                                        (batch_index, beam_index_pos as i64),
+                                        current_length + 1 - trim_length as i64,
+                                    );
+                                }
+                            }
+                        }
+                    }
+                    for &(batch_index, beam_index_pos) in stop_sequence_lengths.keys() {
+                        let _ = eos_mask.get(batch_index).get(beam_index_pos).fill_(0);
+                    }
                     let eos_mask2 = eos_mask
                         .cumsum(1, Kind::Int64)
                         .le(group_size)
@@ -1261,14 +2234,32 @@ pub(crate) mod private_generation_utils {
                                 .int64_value(&[batch_index, beam_index_pos]);
                             let beam_token_score =
                                 next_scores.double_value(&[batch_index, beam_index_pos]);
+                            let stop_sequence_length =
+                                stop_sequence_lengths.get(&(batch_index, beam_index_pos));
                             let saved_beam_scores =
                                 saved_beam_scores.as_ref().map(|step_wise_scores| {
-                                    Tensor::stack(step_wise_scores, 1)
-                                        .get(effective_beam_id)
-                                        .copy()
+                                    // The cumulative score of the finishing token is appended so
+                                    // that the score of the EOS token is recovered as well
+                                    let mut cumulative_scores = step_wise_scores
+                                        .iter()
+                                        .map(|scores| scores.double_value(&[effective_beam_id]))
+                                        .collect::<Vec<f64>>();
+                                    cumulative_scores.push(beam_token_score);
+                                    if let Some(&sequence_length) = stop_sequence_length {
+                                        cumulative_scores
+                                            .truncate((sequence_length - cur_len) as usize);
+                                    }
+                                    Tensor::of_slice(&cumulative_scores).unsqueeze(0)
                                 });
+                            let hypothesis_ids = match stop_sequence_length {
+                                Some(&sequence_length) => input_ids
+                                    .get(effective_beam_id)
+                                    .narrow(0, 0, sequence_length)
+                                    .copy(),
+                                None => input_ids.get(effective_beam_id).copy(),
+                            };
                             hypotheses[batch_index as usize].add(
-                                input_ids.get(effective_beam_id).copy(),
+                                hypothesis_ids,
                                 beam_token_score,
                                 saved_beam_scores,
                             );
@@ -1326,6 +2317,9 @@ pub(crate) mod private_generation_utils {
                 }
 
                 if let Some(scores_output) = saved_beam_scores.as_mut() {
+                    for step_scores in scores_output.iter_mut() {
+                        *step_scores = step_scores.index_select(0, &beam_indices);
+                    }
                     scores_output.push(beam_scores.copy());
                 }
                 if done.iter().all(|&x| x) {
@@ -1346,6 +2340,12 @@ pub(crate) mod private_generation_utils {
                         break;
                     }
                 }
+                if budget.consume(done.iter().filter(|&&done| !done).count() as i64)
+                    || self.stopping_criteria_met(&input_ids, cur_len, current_length, &budget)
+                {
+                    truncated_inputs = done.iter().map(|&done| !done).collect();
+                    break;
+                }
                 encoder_outputs = self.reorder_cache(&mut past, encoder_outputs, &beam_indices);
 
                 if !self.is_encoder_decoder() {
@@ -1390,18 +2390,18 @@ pub(crate) mod private_generation_utils {
                 }
                 batch_index += 1;
             }
-            let (output_batch_size, output_num_return_sequences_per_batch) = if gen_opt.do_sample {
-                (batch_size, 1)
-            } else {
-                (
-                    batch_size * gen_opt.num_return_sequences,
-                    gen_opt.num_return_sequences,
-                )
-            };
+            // The best `num_return_sequences` hypotheses are returned for each input, with or
+            // without sampling: beam sampling candidates are drawn without replacement, so that
+            // the hypotheses of an input are distinct
+            let (output_batch_size, output_num_return_sequences_per_batch) = (
+                batch_size * gen_opt.num_return_sequences,
+                gen_opt.num_return_sequences,
+            );
 
             let mut sentence_lengths =
                 Tensor::zeros(&[output_batch_size], (Kind::Int64, input_ids.device()));
             let mut best_ids = vec![];
+            let mut truncated = Vec::with_capacity(output_batch_size as usize);
 
             let mut scores_output = if output_scores {
                 Some(Vec::with_capacity(best_ids.len()))
@@ -1413,11 +2413,34 @@ pub(crate) mod private_generation_utils {
             } else {
                 None
             };
+            let mut beam_hypotheses_output = if gen_opt.output_beam_hypotheses {
+                Some(Vec::with_capacity(hypotheses.len()))
+            } else {
+                None
+            };
             for (hypothesis_index, hypothesis) in hypotheses.iter().enumerate() {
                 let mut sorted_hypotheses = hypothesis.clone();
                 sorted_hypotheses
                     .beams
                     .sort_by_key(|(score, _, _)| OrderedFloat(*score));
+                if let Some(beam_hypotheses_output) = &mut beam_hypotheses_output {
+                    beam_hypotheses_output.push(
+                        sorted_hypotheses
+                            .beams
+                            .iter()
+                            .rev()
+                            .map(|(score, hypothesis, token_scores)| GeneratedIndicesOutput {
+                                indices: hypothesis.iter::<i64>().unwrap().collect::<Vec<i64>>(),
+                                score: Some(*score),
+                                token_scores: token_scores.as_ref().map(|token_scores| {
+                                    token_scores.iter::<f64>().unwrap().collect::<Vec<f64>>()
+                                }),
+                                beam_hypotheses: None,
+                                truncated: truncated_inputs[hypothesis_index],
+                            })
+                            .collect::<Vec<GeneratedIndicesOutput>>(),
+                    );
+                }
                 for j in 0..output_num_return_sequences_per_batch {
                     let effective_batch_index =
                         output_num_return_sequences_per_batch * hypothesis_index as i64 + j;
@@ -1430,6 +2453,7 @@ pub(crate) mod private_generation_utils {
                         *best_hyp.size().first().unwrap(),
                     );
                     best_ids.push(best_hyp);
+                    truncated.push(truncated_inputs[hypothesis_index]);
                     if let Some(current_best_scores) = &mut scores_output {
                         current_best_scores.push(best_score);
                     }
@@ -1448,6 +2472,16 @@ pub(crate) mod private_generation_utils {
                 .max_length
                 .map(|max_length| min(i64::from(sentence_lengths.max()) + 1, max_length))
                 .unwrap_or(i64::from(sentence_lengths.max()) + 1);
+            // Token scores are padded to be aligned with the end of the decoded sequences
+            if let Some(token_scores_output) = &mut token_scores_output {
+                for token_scores in token_scores_output.iter_mut() {
+                    let padded_length = max(
+                        token_scores.len(),
+                        (sentence_max_length - cur_len).max(0) as usize,
+                    );
+                    token_scores.resize(padded_length, 0f64);
+                }
+            }
 
             let mut decoded = input_ids.new_empty(
                 &[output_batch_size, sentence_max_length],
@@ -1482,11 +2516,13 @@ pub(crate) mod private_generation_utils {
                     );
                 }
             }
-            GeneratedOutputWithScores {
+            Ok(GeneratedOutputWithScores {
                 indices: decoded,
                 scores: scores_output,
                 token_scores: token_scores_output,
-            }
+                beam_hypotheses: beam_hypotheses_output,
+                truncated,
+            })
         }
 
         fn reorder_cache(
@@ -1511,6 +2547,13 @@ pub(crate) mod private_generation_utils {
 pub struct GeneratedTextOutput {
     pub text: String,
     pub score: Option<f64>,
+    /// All finished beam search hypotheses for the prompt of this sequence, sorted by decreasing
+    /// length-normalized score, if `output_beam_hypotheses` is true
+    pub beam_hypotheses: Option<Vec<GeneratedTextOutput>>,
+    /// Flag indicating if the generation of the sequence was interrupted by the time or token
+    /// budget of the call (`max_time` or `max_total_new_tokens` in `GenerateOptions`) or by one of
+    /// the `stopping_criteria` of the `GenerateConfig`
+    pub truncated: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -1519,10 +2562,73 @@ pub struct GeneratedTextOutput {
 pub struct GeneratedIndicesOutput {
     pub indices: Vec<i64>,
     pub score: Option<f64>,
+    /// Log-probabilities of the generated tokens, aligned with the end of `indices` (padding
+    /// positions have a score of 0)
     pub token_scores: Option<Vec<f64>>,
+    /// All finished beam search hypotheses for the prompt of this sequence, sorted by decreasing
+    /// length-normalized score, if `output_beam_hypotheses` is true
+    pub beam_hypotheses: Option<Vec<GeneratedIndicesOutput>>,
+    /// Flag indicating if the generation of the sequence was interrupted by the time or token
+    /// budget of the call (`max_time` or `max_total_new_tokens` in `GenerateOptions`) or by one of
+    /// the `stopping_criteria` of the `GenerateConfig`
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone)]
+/// # Scored generated text output
+/// Contains generated text with the log-probabilities of its generated tokens, for example to
+/// rerank or filter generations by confidence
+pub struct ScoredTextOutput {
+    pub text: String,
+    /// Cumulative log-probability of the generated tokens
+    pub log_probability: f64,
+    /// Length-normalized log-likelihood score of the sequence (as returned by `generate`)
+    pub score: f64,
+    /// Generated token ids (excluding the prompt and padding)
+    pub token_ids: Vec<i64>,
+    /// Log-probability of each generated token, aligned with `token_ids`
+    pub token_log_probabilities: Vec<f64>,
+    /// Flag indicating if the generation of the sequence was interrupted by the time or token
+    /// budget of the call (`max_time` or `max_total_new_tokens` in `GenerateOptions`) or by one of
+    /// the `stopping_criteria` of the `GenerateConfig`
+    pub truncated: bool,
+}
+
+fn decode_generated_indices(
+    tokenizer: &TokenizerOption,
+    indices_outputs: Vec<GeneratedIndicesOutput>,
+) -> Vec<GeneratedTextOutput> {
+    indices_outputs
+        .into_iter()
+        .map(|generated_sequence| {
+            let beam_hypotheses = generated_sequence.beam_hypotheses.map(|beam_hypotheses| {
+                beam_hypotheses
+                    .iter()
+                    .map(|hypothesis| GeneratedTextOutput {
+                        text: tokenizer.decode(&hypothesis.indices, true, true),
+                        score: hypothesis.score,
+                        beam_hypotheses: None,
+                        truncated: hypothesis.truncated,
+                    })
+                    .collect()
+            });
+            GeneratedTextOutput {
+                text: tokenizer.decode(&generated_sequence.indices, true, true),
+                score: generated_sequence.score,
+                beam_hypotheses,
+                truncated: generated_sequence.truncated,
+            }
+        })
+        .collect()
 }
 
 pub type PrefixAllowedFunction<'a> = &'a dyn Fn(i64, &Tensor) -> Vec<i64>;
+/// Type alias for a function reporting the progress of a chunked prefill, called after each chunk
+/// with the number of prompt tokens processed and the prompt length (in tokens).
+pub type PrefillProgressFunction<'a> = &'a dyn Fn(i64, i64);
+/// Type alias for a function receiving the generated tokens as soon as they are selected, called
+/// with the index of the sequence in the batch and the token id.
+pub type TokenCallbackFunction<'a> = &'a dyn Fn(usize, i64);
 /// Type alias for a function defining allowed tokens based on current tokens generated.
 /// This function should take a `batch_id` and associated tensor of already generated tokens and
 /// should return a vector of allowed tokens. This is useful for controlled generation, i.e.
@@ -1548,6 +2654,7 @@ pub struct GenerateOptions<'a> {
     pub num_return_sequences: Option<i64>,
     /// Number of beams for beam search
     pub num_beams: Option<i64>,
+    /// Number of beam groups for diverse beam generation. `num_beams` must be a multiple of the number of groups
     pub num_beam_groups: Option<i64>,
     /// Sampling flag. If true, will perform top-k and/or nucleus sampling on generated tokens, otherwise greedy (deterministic) decoding
     pub do_sample: Option<bool>,
@@ -1557,6 +2664,8 @@ pub struct GenerateOptions<'a> {
     pub top_k: Option<i64>,
     /// Top_p value for [Nucleus sampling, Holtzman et al.](http://arxiv.org/abs/1904.09751). Keep top tokens until cumulative probability reaches top_p
     pub top_p: Option<f64>,
+    /// Min_p value for sampling tokens. Tokens with a probability lower than `min_p` times the probability of the most likely token are removed
+    pub min_p: Option<f64>,
     /// Repetition penalty (mostly useful for CTRL decoders). Values higher than 1 will penalize tokens that have been already generated.
     pub repetition_penalty: Option<f64>,
     /// Exponential penalty based on the length of the hypotheses generated
@@ -1569,12 +2678,54 @@ pub struct GenerateOptions<'a> {
     pub decoder_start_token_id: Option<i64>,
     /// Forced first token generated
     pub forced_bos_token_id: Option<i64>,
+    /// Forced last token generated when the maximum length is reached
+    pub forced_eos_token_id: Option<i64>,
+    /// Bias added to the logits of individual tokens, overriding the `logit_bias` of the `GenerateConfig`
+    pub logit_bias: Option<&'a HashMap<i64, f64>>,
     /// Function to control the generation process. The function should take a `batch_id` (i64) and a tensor of token_ids already generated and returns a `Vec<i64>` of allowed tokens.
     pub prefix_allowed_tokens_fn: Option<PrefixAllowedFunction<'a>>,
-    /// List of bad word ids (may be a sequence of word ids) that will be banned during the generation
+    /// List of bad word ids (may be a sequence of word ids) that will be banned during the generation,
+    /// in addition to the `bad_word_ids` and `bad_words` of the `GenerateConfig`
     pub bad_word_ids: Option<&'a Vec<Vec<i64>>>,
     /// Flag indicating if text generation scores should be returned
     pub output_scores: bool,
+    /// Flag indicating if all finished beam search hypotheses (and their length-normalized scores)
+    /// should be returned along with the selected sequences, e.g. for re-ranking by an external
+    /// model. Only applies to beam search (`num_beams` > 1).
+    pub output_beam_hypotheses: bool,
+    /// Maximum wall-clock duration of the generation. The budget is checked after each decoding
+    /// step: once exceeded, the sequences generated so far are returned and flagged as `truncated`
+    pub max_time: Option<Duration>,
+    /// Maximum number of new tokens generated for all the sequences of the call. The budget is
+    /// checked after each decoding step: once exceeded, the sequences generated so far are returned
+    /// and flagged as `truncated`
+    pub max_total_new_tokens: Option<i64>,
+    /// Size (in tokens) of the chunks used to run the forward pass of long prompts, filling the
+    /// cache incrementally instead of processing the full prompt at once. This bounds the peak
+    /// activation memory for prompts of thousands of tokens. Only supported by decoder-only models
+    /// with a cache (GPT2, GPT-Neo).
+    pub prefill_chunk_size: Option<i64>,
+    /// Function called after each chunk of a chunked prefill (see `prefill_chunk_size`) with the
+    /// number of prompt tokens processed and the prompt length
+    pub prefill_progress_fn: Option<PrefillProgressFunction<'a>>,
+    /// Run the prompt through the model once per input and expand the cache to the beams
+    /// afterwards, instead of once per beam (default: true). Only applies to beam search with
+    /// decoder-only models supporting it (GPT2, GPT-Neo), and does not change the generated output.
+    pub lazy_beam_expansion: Option<bool>,
+    /// Function called with each token as soon as it is generated, with the index of the sequence
+    /// (in the *number_of_prompts* x *num_return_sequences* output) and the token id. Tokens of
+    /// finished sequences are not reported. With stop sequences, the last tokens are held back until
+    /// they can no longer be part of a stop sequence, and tokens removed by a stop sequence are not
+    /// reported. Not supported with beam search, as the tokens of a beam are only final once the
+    /// search completes.
+    pub token_callback_fn: Option<TokenCallbackFunction<'a>>,
+    /// Stop sequences ending the generation of a sequence as soon as its generated text contains
+    /// one of them, overriding the `stop_sequences` of the `GenerateConfig`
+    pub stop_sequences: Option<&'a [String]>,
+    /// Seed of the random number generator used for sampling, overriding the `seed` of the `GenerateConfig`
+    pub seed: Option<u64>,
+    /// Grammar constraint the generated text must match (see `LanguageGenerator::grammar_constraint`)
+    pub grammar: Option<&'a GrammarConstraint>,
 }
 
 macro_rules! unpack_config {
@@ -1585,6 +2736,208 @@ macro_rules! unpack_config {
     };
 }
 
+/// Settings of a `GenerateConfig` that can be overridden for a single call by `GenerateOptions`.
+/// The settings of the configuration and the ones of each call are checked by the same rules.
+struct GenerationSettings<'a> {
+    min_length: i64,
+    do_sample: bool,
+    early_stopping: bool,
+    num_beams: i64,
+    temperature: f64,
+    top_k: i64,
+    top_p: f64,
+    min_p: f64,
+    repetition_penalty: f64,
+    length_penalty: f64,
+    no_repeat_ngram_size: i64,
+    num_return_sequences: i64,
+    num_beam_groups: Option<i64>,
+    diversity_penalty: Option<f64>,
+    forced_bos_token_id: Option<i64>,
+    forced_eos_token_id: Option<i64>,
+    logit_bias: &'a HashMap<i64, f64>,
+    bad_word_ids: &'a [Vec<i64>],
+    call_bad_word_ids: &'a [Vec<i64>],
+    stop_sequences: &'a [String],
+}
+
+impl<'a> GenerationSettings<'a> {
+    /// Merges the options of a generation call into the settings of the configuration. Options
+    /// provided to the call take priority, bad word ids of the call are banned in addition to the
+    /// ones of the configuration.
+    fn new(
+        config: &'a GenerateConfig,
+        generate_options: Option<GenerateOptions<'a>>,
+    ) -> GenerationSettings<'a> {
+        GenerationSettings {
+            min_length: unpack_config!(min_length, generate_options, config),
+            do_sample: unpack_config!(do_sample, generate_options, config),
+            early_stopping: unpack_config!(early_stopping, generate_options, config),
+            num_beams: unpack_config!(num_beams, generate_options, config),
+            temperature: unpack_config!(temperature, generate_options, config),
+            top_k: unpack_config!(top_k, generate_options, config),
+            top_p: unpack_config!(top_p, generate_options, config),
+            min_p: unpack_config!(min_p, generate_options, config),
+            repetition_penalty: unpack_config!(repetition_penalty, generate_options, config),
+            length_penalty: unpack_config!(length_penalty, generate_options, config),
+            no_repeat_ngram_size: unpack_config!(no_repeat_ngram_size, generate_options, config),
+            num_return_sequences: unpack_config!(num_return_sequences, generate_options, config),
+            num_beam_groups: generate_options
+                .and_then(|opts| opts.num_beam_groups)
+                .or(config.num_beam_groups),
+            diversity_penalty: generate_options
+                .and_then(|opts| opts.diversity_penalty)
+                .or(config.diversity_penalty),
+            forced_bos_token_id: generate_options
+                .and_then(|opts| opts.forced_bos_token_id)
+                .or(config.forced_bos_token_id),
+            forced_eos_token_id: generate_options
+                .and_then(|opts| opts.forced_eos_token_id)
+                .or(config.forced_eos_token_id),
+            logit_bias: generate_options
+                .and_then(|opts| opts.logit_bias)
+                .unwrap_or(&config.logit_bias),
+            bad_word_ids: &config.bad_word_ids,
+            call_bad_word_ids: generate_options
+                .and_then(|opts| opts.bad_word_ids)
+                .map_or(&[][..], |bad_word_ids| bad_word_ids.as_slice()),
+            stop_sequences: generate_options
+                .and_then(|opts| opts.stop_sequences)
+                .unwrap_or(config.stop_sequences.as_slice()),
+        }
+    }
+
+    fn validate(&self) -> Result<(), RustBertError> {
+        let check = |condition: bool, message: &str| {
+            if condition {
+                Ok(())
+            } else {
+                Err(RustBertError::InvalidConfigurationError(
+                    message.to_string(),
+                ))
+            }
+        };
+        check(self.temperature > 0f64, "temperature must positive")?;
+        check(
+            (self.top_p >= 0f64) & (self.top_p <= 1f64),
+            "top_p must be 0 and 1",
+        )?;
+        check(
+            (self.min_p >= 0f64) & (self.min_p <= 1f64),
+            "min_p must be between 0 and 1",
+        )?;
+        check(
+            self.repetition_penalty >= 1f64,
+            "repetition_penalty must be greater than 1",
+        )?;
+        check(
+            self.length_penalty > 0f64,
+            "length_penalty must be strictly greater than 0",
+        )?;
+        check(
+            self.num_return_sequences > 0i64,
+            "num_return_sequences must be strictly greater than 0",
+        )?;
+        check(
+            self.num_beams > 0i64,
+            "num_beams must be strictly greater than 0",
+        )?;
+
+        if self.num_beams > 1 {
+            check(
+                self.num_beams >= self.num_return_sequences,
+                "num_return_sequences must be lower than the number of beams",
+            )?;
+        } else if !self.do_sample {
+            check(
+                self.num_return_sequences == 1,
+                "num_return_sequences must be set to 1 for greedy decoding",
+            )?;
+        }
+        if let Some(num_beam_groups_value) = self.num_beam_groups {
+            if num_beam_groups_value > 1 {
+                check(
+                    self.num_beams % num_beam_groups_value == 0,
+                    "num_beams must be a multiple of num_beam_groups",
+                )?;
+            }
+        }
+        if let Some(diversity_penalty) = self.diversity_penalty {
+            check(
+                diversity_penalty >= 0f64,
+                "diversity_penalty must be non-negative",
+            )?;
+        }
+        check(
+            self.stop_sequences
+                .iter()
+                .all(|stop_sequence| !stop_sequence.is_empty()),
+            "stop_sequences must not contain empty strings",
+        )?;
+        check(
+            self.bad_word_ids
+                .iter()
+                .chain(self.call_bad_word_ids.iter())
+                .all(|bad_word_ids| !bad_word_ids.is_empty()),
+            "bad_word_ids must not contain empty sequences",
+        )?;
+        check(
+            self.forced_bos_token_id
+                .iter()
+                .chain(self.forced_eos_token_id.iter())
+                .all(|token_id| *token_id >= 0),
+            "forced_bos_token_id and forced_eos_token_id must be positive",
+        )?;
+        check(
+            self.logit_bias.keys().all(|token_id| *token_id >= 0),
+            "logit_bias token ids must be positive",
+        )?;
+        check(
+            self.logit_bias.values().all(|bias| !bias.is_nan()),
+            "logit_bias values must not be NaN",
+        )?;
+        Ok(())
+    }
+
+    /// Checks that the token ids of the settings are part of the vocabulary of the model
+    fn validate_vocabulary(&self, vocab_size: i64) -> Result<(), RustBertError> {
+        if self
+            .logit_bias
+            .keys()
+            .any(|token_id| *token_id >= vocab_size)
+        {
+            return Err(RustBertError::InvalidConfigurationError(format!(
+                "logit_bias token ids must be lower than the vocabulary size ({})",
+                vocab_size
+            )));
+        }
+        if self
+            .bad_word_ids
+            .iter()
+            .chain(self.call_bad_word_ids.iter())
+            .flatten()
+            .any(|token_id| (*token_id < 0) | (*token_id >= vocab_size))
+        {
+            return Err(RustBertError::InvalidConfigurationError(format!(
+                "bad_word_ids must be between 0 and the vocabulary size ({})",
+                vocab_size
+            )));
+        }
+        if self
+            .forced_bos_token_id
+            .iter()
+            .chain(self.forced_eos_token_id.iter())
+            .any(|token_id| *token_id >= vocab_size)
+        {
+            return Err(RustBertError::InvalidConfigurationError(format!(
+                "forced_bos_token_id and forced_eos_token_id must be lower than the vocabulary size ({})",
+                vocab_size
+            )));
+        }
+        Ok(())
+    }
+}
+
 /// # Common trait for text generation models.
 /// Main API for text generation
 pub trait LanguageGenerator<T: LMHeadModel, V: Vocab, U: Tokenizer<V>>:
@@ -1598,7 +2951,9 @@ pub trait LanguageGenerator<T: LMHeadModel, V: Vocab, U: Tokenizer<V>>:
     /// * `generate_options` - `Option<GenerateOptions>` Optional set of generate options. If not (or partially) provided, will use the settings provided when creating the generator
     ///
     /// # Returns
-    /// * `Vec<TextOutput>` Vector of length *number_of_prompts* x *num_return_sequences* containing TextOutput with the generated texts and the generation score if `output_scores` is true.
+    /// * `Result<Vec<TextOutput>, RustBertError>` Vector of length *number_of_prompts* x *num_return_sequences* containing TextOutput with the generated texts and the generation score if `output_scores` is true.
+    /// Returns a `RustBertError::InvalidConfigurationError` if the generation settings are not supported by the model (e.g. empty prompts for a model without BOS token).
+    /// Returns a `RustBertError::TchError` if a forward pass of the model fails (e.g. device out of memory).
     ///
     /// # Example
     ///
@@ -1659,7 +3014,7 @@ pub trait LanguageGenerator<T: LMHeadModel, V: Vocab, U: Tokenizer<V>>:
     /// let output = gpt2_generator.generate(
     ///     Some(&[input_context, second_input_context]),
     ///     Some(generate_options),
-    /// );
+    /// )?;
     /// # Ok(())
     /// # }
     /// ```
@@ -1680,21 +3035,234 @@ pub trait LanguageGenerator<T: LMHeadModel, V: Vocab, U: Tokenizer<V>>:
         &self,
         prompt_texts: Option<&[S]>,
         generate_options: Option<GenerateOptions>,
-    ) -> Vec<GeneratedTextOutput>
+    ) -> Result<Vec<GeneratedTextOutput>, RustBertError>
+    where
+        S: AsRef<str> + Sync,
+    {
+        let indices_outputs = self.generate_indices(prompt_texts, generate_options)?;
+        Ok(decode_generated_indices(
+            self._get_tokenizer(),
+            indices_outputs,
+        ))
+    }
+
+    /// Generate text given pre-tokenized inputs, bypassing the encoding of the prompts (useful
+    /// when the inputs have been tokenized separately or contain manually inserted special tokens).
+    /// The generated sequences are decoded and post-processed as for `generate`.
+    ///
+    /// # Arguments
+    ///
+    /// * `input_ids` - `Tensor` of shape (*batch size*, *sequence_length*) with the encoded inputs. Inputs of different lengths should be left-padded for decoder-only models.
+    /// * `attention_mask` - `Option<Tensor>` Optional attention mask of shape (*batch size*, *sequence_length*), with 0 for padding positions.
+    /// * `generate_options` - `Option<GenerateOptions>` Optional set of generate options. If not (or partially) provided, will use the settings provided when creating the generator
+    ///
+    /// # Returns
+    /// * `Result<Vec<TextOutput>, RustBertError>` Vector of length *batch size* x *num_return_sequences* containing TextOutput with the generated texts and the generation score if `output_scores` is true.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::gpt2::GPT2Generator;
+    /// use rust_bert::pipelines::generation_utils::LanguageGenerator;
+    /// use tch::Tensor;
+    ///
+    /// let gpt2_generator = GPT2Generator::new(Default::default())?;
+    /// // "Hello, my name is"
+    /// let input_ids = Tensor::of_slice(&[15496i64, 11, 616, 1438, 318]).unsqueeze(0);
+    ///
+    /// let output = gpt2_generator.generate_from_ids(input_ids, None, None)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn generate_from_ids(
+        &self,
+        input_ids: Tensor,
+        attention_mask: Option<Tensor>,
+        generate_options: Option<GenerateOptions>,
+    ) -> Result<Vec<GeneratedTextOutput>, RustBertError> {
+        let input_shape = input_ids.size();
+        if input_shape.len() != 2 {
+            return Err(RustBertError::ValueError(format!(
+                "Input ids must be of shape (batch size, sequence length), got {:?}",
+                input_shape
+            )));
+        }
+        if let Some(attention_mask) = &attention_mask {
+            if attention_mask.size() != input_shape {
+                return Err(RustBertError::ValueError(format!(
+                    "Attention mask shape {:?} does not match the input ids shape {:?}",
+                    attention_mask.size(),
+                    input_shape
+                )));
+            }
+        }
+        if input_shape[0] == 0 {
+            return Ok(Vec::new());
+        }
+        let device = self.get_var_store().device();
+        let indices_outputs = self.generate_from_ids_and_past(
+            input_ids.to_device(device),
+            attention_mask.map(|attention_mask| attention_mask.to_device(device)),
+            generate_options,
+        )?;
+        Ok(decode_generated_indices(
+            self._get_tokenizer(),
+            indices_outputs,
+        ))
+    }
+
+    /// Generate text based on a vector of prompt texts, streaming the generated text while it is
+    /// generated (e.g. for interactive chat interfaces). The generated tokens are decoded
+    /// incrementally and delivered to the callback as soon as they form valid text, the
+    /// concatenation of the fragments of a sequence forming its generated continuation.
+    ///
+    /// Streaming is not supported with beam search (`num_beams` > 1).
+    ///
+    /// # Arguments
+    ///
+    /// * `prompt_texts` - `Option<Vec<&str>>` Optional vector of text prompts. An empty prompt to the model may be passed if the model implement a `bos_id`.
+    /// * `generate_options` - `Option<GenerateOptions>` Optional set of generate options. If not (or partially) provided, will use the settings provided when creating the generator
+    /// * `callback` - function called with the index of the sequence (in the *number_of_prompts* x *num_return_sequences* output) and each new text fragment
+    ///
+    /// # Returns
+    /// * `Result<Vec<TextOutput>, RustBertError>` Vector of length *number_of_prompts* x *num_return_sequences* containing TextOutput with the generated texts, identical to the output of `generate`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::gpt2::GPT2Generator;
+    /// use rust_bert::pipelines::generation_utils::LanguageGenerator;
+    /// use std::io::Write;
+    ///
+    /// let gpt2_generator = GPT2Generator::new(Default::default())?;
+    /// let output = gpt2_generator.generate_with_callback(
+    ///     Some(&["The dog"]),
+    ///     None,
+    ///     |_sequence_index, text| {
+    ///         print!("{}", text);
+    ///         std::io::stdout().flush().unwrap();
+    ///     },
+    /// )?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn generate_with_callback<S, F>(
+        &self,
+        prompt_texts: Option<&[S]>,
+        generate_options: Option<GenerateOptions>,
+        callback: F,
+    ) -> Result<Vec<GeneratedTextOutput>, RustBertError>
+    where
+        S: AsRef<str> + Sync,
+        F: FnMut(usize, &str),
+    {
+        let tokenizer = self._get_tokenizer();
+        let state = RefCell::new((HashMap::<usize, IncrementalDecoder>::new(), callback));
+        let token_callback = |sequence_index: usize, token_id: i64| {
+            let (decoders, callback) = &mut *state.borrow_mut();
+            let decoder = decoders
+                .entry(sequence_index)
+                .or_insert_with(|| IncrementalDecoder::new(tokenizer, true));
+            if let Some(text) = decoder.push(token_id) {
+                callback(sequence_index, &text);
+            }
+        };
+        let generate_options = GenerateOptions {
+            token_callback_fn: Some(&token_callback),
+            ..generate_options.unwrap_or_default()
+        };
+        let output = self.generate(prompt_texts, Some(generate_options))?;
+
+        let (mut decoders, mut callback) = state.into_inner();
+        let mut sequence_indices = decoders.keys().cloned().collect::<Vec<usize>>();
+        sequence_indices.sort_unstable();
+        for sequence_index in sequence_indices {
+            if let Some(text) = decoders.get_mut(&sequence_index).unwrap().finish() {
+                callback(sequence_index, &text);
+            }
+        }
+        Ok(output)
+    }
+
+    /// Generate text based on a vector of prompt texts, returning for each sequence the cumulative
+    /// log-probability and the log-probability of each generated token (`output_scores` is always
+    /// enabled for this method).
+    ///
+    /// # Arguments
+    ///
+    /// * `prompt_texts` - `Option<Vec<&str>>` Optional vector of text prompts. An empty prompt to the model may be passed if the model implement a `bos_id`.
+    /// * `generate_options` - `Option<GenerateOptions>` Optional set of generate options. If not (or partially) provided, will use the settings provided when creating the generator
+    ///
+    /// # Returns
+    /// * `Result<Vec<ScoredTextOutput>, RustBertError>` Vector of length *number_of_prompts* x *num_return_sequences* containing ScoredTextOutput with the generated texts and their scores.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::gpt2::GPT2Generator;
+    /// use rust_bert::pipelines::generation_utils::{GenerateOptions, LanguageGenerator};
+    ///
+    /// let gpt2_generator = GPT2Generator::new(Default::default())?;
+    /// let generate_options = GenerateOptions {
+    ///     num_return_sequences: Some(3),
+    ///     ..Default::default()
+    /// };
+    /// let mut output = gpt2_generator.generate_with_scores(Some(&["The dog"]), Some(generate_options))?;
+    /// output.sort_by(|a, b| b.log_probability.partial_cmp(&a.log_probability).unwrap());
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn generate_with_scores<S>(
+        &self,
+        prompt_texts: Option<&[S]>,
+        generate_options: Option<GenerateOptions>,
+    ) -> Result<Vec<ScoredTextOutput>, RustBertError>
     where
         S: AsRef<str> + Sync,
     {
-        let indices_outputs = self.generate_indices(prompt_texts, generate_options);
+        let generate_options = GenerateOptions {
+            output_scores: true,
+            ..generate_options.unwrap_or_default()
+        };
+        let eos_token_ids = self.get_eos_ids();
+        let pad_token_id = self.get_generation_pad_id();
+        let indices_outputs = self.generate_indices(prompt_texts, Some(generate_options))?;
         let mut output = Vec::with_capacity(indices_outputs.len());
         for generated_sequence in indices_outputs {
-            output.push(GeneratedTextOutput {
+            let token_scores = generated_sequence.token_scores.unwrap_or_default();
+            let generation_start = generated_sequence
+                .indices
+                .len()
+                .saturating_sub(token_scores.len());
+            let generated_ids = &generated_sequence.indices[generation_start..];
+            let mut token_ids = Vec::with_capacity(generated_ids.len());
+            let mut token_log_probabilities = Vec::with_capacity(generated_ids.len());
+            for (&token_id, &token_score) in generated_ids.iter().zip(token_scores.iter()) {
+                let is_eos = eos_token_ids.map_or(false, |eos_ids| eos_ids.contains(&token_id));
+                if !is_eos & (Some(token_id) == pad_token_id) {
+                    break;
+                }
+                token_ids.push(token_id);
+                token_log_probabilities.push(token_score);
+                if is_eos {
+                    break;
+                }
+            }
+            output.push(ScoredTextOutput {
                 text: self
                     ._get_tokenizer()
                     .decode(&generated_sequence.indices, true, true),
-                score: generated_sequence.score,
+                log_probability: token_log_probabilities.iter().sum(),
+                score: generated_sequence.score.unwrap_or_default(),
+                token_ids,
+                token_log_probabilities,
+                truncated: generated_sequence.truncated,
             });
         }
-        output
+        Ok(output)
     }
 
     /// Generate token indices without decoding (useful for token-level operations before returning final text or as validation step during training).
@@ -1705,7 +3273,7 @@ pub trait LanguageGenerator<T: LMHeadModel, V: Vocab, U: Tokenizer<V>>:
     /// * `generate_options` - `Option<GenerateOptions>` Optional set of generate options. If not (or partially) provided, will use the settings provided when creating the generator
     ///
     /// # Returns
-    /// * `Vec<IndicesOutput>` Vector of length *number_of_prompts* x *num_return_sequences* containing IndicesOutput with the generated indices and the generation score if `output_scores` is true.
+    /// * `Result<Vec<IndicesOutput>, RustBertError>` Vector of length *number_of_prompts* x *num_return_sequences* containing IndicesOutput with the generated indices and the generation score if `output_scores` is true.
     ///
     /// # Example
     ///
@@ -1766,7 +3334,7 @@ pub trait LanguageGenerator<T: LMHeadModel, V: Vocab, U: Tokenizer<V>>:
     /// let output = gpt2_generator.generate_indices(
     ///     Some(&[input_context, second_input_context]),
     ///     Some(generate_options),
-    /// );
+    /// )?;
     /// # Ok(())
     /// # }
     /// ```
@@ -1774,42 +3342,58 @@ pub trait LanguageGenerator<T: LMHeadModel, V: Vocab, U: Tokenizer<V>>:
         &self,
         prompt_texts: Option<&[S]>,
         generate_options: Option<GenerateOptions>,
-    ) -> Vec<GeneratedIndicesOutput>
+    ) -> Result<Vec<GeneratedIndicesOutput>, RustBertError>
     where
         S: AsRef<str> + Sync,
     {
-        let eos_token_ids = self.get_eos_ids();
-
         let config = self.get_config();
 
         let max_length = generate_options.map_or(config.max_length, |generate_options| {
             generate_options.max_length
         });
-        let encoding_max_len = if self.is_encoder_decoder() {
+        let encoding_max_len = if config.max_prompt_length.is_some() {
+            config.max_prompt_length
+        } else if self.is_encoder_decoder() {
             Some(self.get_max_positions_embeddings())
         } else {
             max_length
         };
-        let pad_token_id = match self.get_pad_id() {
-            Some(value) => Some(value),
-            None => eos_token_ids.as_ref().map(|eos_ids| eos_ids[0]),
-        };
+        let pad_token_id = self.get_generation_pad_id();
 
-        let input_ids = match prompt_texts {
+        let (input_ids, attention_mask) = match prompt_texts {
             Some(prompts) if !prompts.is_empty() => {
-                self.encode_prompt_text(prompts, encoding_max_len, pad_token_id)
+                let (input_ids, attention_mask) =
+                    self.encode_prompt_text(prompts, encoding_max_len, pad_token_id);
+                let has_empty_prompts = input_ids.size()[1] > 0
+                    && bool::from(
+                        attention_mask
+                            .sum_dim_intlist([1].as_slice(), false, Int64)
+                            .eq(0)
+                            .any(),
+                    );
+                if has_empty_prompts {
+                    return Err(RustBertError::InvalidConfigurationError(
+                        "A model with a BOS token must be used to start generation with an empty input"
+                            .to_string(),
+                    ));
+                }
+                (input_ids, Some(attention_mask))
             }
             None => match self.get_bos_id() {
-                Some(bos_id) => {
-                    Tensor::ones(&[1, 1], (Int64, self.get_var_store().device())) * bos_id
-                }
-                None => panic!(
-                    "A model with a BOS token must be used to start generation with an empty input"
+                Some(bos_id) => (
+                    Tensor::ones(&[1, 1], (Int64, self.get_var_store().device())) * bos_id,
+                    None,
                 ),
+                None => {
+                    return Err(RustBertError::InvalidConfigurationError(
+                        "A model with a BOS token must be used to start generation with an empty input"
+                            .to_string(),
+                    ));
+                }
             },
-            _ => return Vec::new(),
+            _ => return Ok(Vec::new()),
         };
-        self.generate_from_ids_and_past(input_ids, None, generate_options)
+        self.generate_from_ids_and_past(input_ids, attention_mask, generate_options)
     }
 
     /// Generate token indices given a list of indices (useful when the input has been pre-tokenized).
@@ -1821,7 +3405,7 @@ pub trait LanguageGenerator<T: LMHeadModel, V: Vocab, U: Tokenizer<V>>:
     /// * `generate_options` - `Option<GenerateOptions>` Optional set of generate options. If not (or partially) provided, will use the settings provided when creating the generator
     ///
     /// # Returns
-    /// * `Vec<IndicesOutput>` Vector of length *number_of_prompts* x *num_return_sequences* containing IndicesOutput with the generated indices and the generation score if `output_scores` is true.
+    /// * `Result<Vec<IndicesOutput>, RustBertError>` Vector of length *number_of_prompts* x *num_return_sequences* containing IndicesOutput with the generated indices and the generation score if `output_scores` is true.
     ///
     /// # Example
     ///
@@ -1858,7 +3442,7 @@ pub trait LanguageGenerator<T: LMHeadModel, V: Vocab, U: Tokenizer<V>>:
     ///     input_tensor,
     ///     Some(input_mask),
     ///     Some(generate_options),
-    /// );
+    /// )?;
     /// # Ok(())
     /// # }
     /// ```
@@ -1867,51 +3451,141 @@ pub trait LanguageGenerator<T: LMHeadModel, V: Vocab, U: Tokenizer<V>>:
         mut input_ids: Tensor,
         mut attention_mask: Option<Tensor>,
         generate_options: Option<GenerateOptions>,
-    ) -> Vec<GeneratedIndicesOutput> {
+    ) -> Result<Vec<GeneratedIndicesOutput>, RustBertError> {
+        let start_time = Instant::now();
         let eos_token_ids = PrivateLanguageGenerator::get_eos_ids(self).cloned();
 
         let config = PrivateLanguageGenerator::get_config(self);
 
         // Set generation options. Priority goes to options provided to the `generate` method, then
         // model configuration, then default values.
-        let do_sample = unpack_config!(do_sample, generate_options, config);
-        let num_return_sequences = unpack_config!(num_return_sequences, generate_options, config);
-        let num_beams = unpack_config!(num_beams, generate_options, config);
-        let min_length = unpack_config!(min_length, generate_options, config);
-        let early_stopping = unpack_config!(early_stopping, generate_options, config);
-        let temperature = unpack_config!(temperature, generate_options, config);
-        let top_k = unpack_config!(top_k, generate_options, config);
-        let top_p = unpack_config!(top_p, generate_options, config);
-        let repetition_penalty = unpack_config!(repetition_penalty, generate_options, config);
-        let length_penalty = unpack_config!(length_penalty, generate_options, config);
-        let no_repeat_ngram_size = unpack_config!(no_repeat_ngram_size, generate_options, config);
-        let num_beam_groups = generate_options.map_or(config.num_beam_groups, |opts| {
-            opts.num_beam_groups.or(config.num_beam_groups)
-        });
-        let diversity_penalty = generate_options.map_or(config.diversity_penalty, |opts| {
-            opts.diversity_penalty.or(config.diversity_penalty)
-        });
+        let settings = GenerationSettings::new(config, generate_options);
+        settings.validate()?;
+        settings.validate_vocabulary(self.get_vocab_size())?;
+        let GenerationSettings {
+            min_length,
+            do_sample,
+            early_stopping,
+            num_beams,
+            temperature,
+            top_k,
+            top_p,
+            min_p,
+            repetition_penalty,
+            length_penalty,
+            no_repeat_ngram_size,
+            num_return_sequences,
+            num_beam_groups,
+            diversity_penalty,
+            forced_bos_token_id,
+            forced_eos_token_id,
+            logit_bias,
+            bad_word_ids,
+            call_bad_word_ids,
+            stop_sequences,
+        } = settings;
         let decoder_start_token_id = generate_options.and_then(|opts| opts.decoder_start_token_id);
-        let forced_bos_token_id = generate_options.and_then(|opts| opts.forced_bos_token_id);
-        let bad_word_ids = generate_options.and_then(|opts| opts.bad_word_ids);
+        let logit_bias = if logit_bias.is_empty() {
+            None
+        } else {
+            Some(logit_bias)
+        };
+        // Bad words of the call are banned in addition to the ones of the configuration
+        let mut all_bad_word_ids = bad_word_ids.to_vec();
+        let tokenizer = self._get_tokenizer();
+        for bad_word in &config.bad_words {
+            for bad_word_text in [bad_word.clone(), format!(" {}", bad_word)].iter() {
+                let bad_word_ids =
+                    tokenizer.convert_tokens_to_ids(&tokenizer.tokenize(bad_word_text));
+                if !bad_word_ids.is_empty() && !all_bad_word_ids.contains(&bad_word_ids) {
+                    all_bad_word_ids.push(bad_word_ids);
+                }
+            }
+        }
+        all_bad_word_ids.extend(call_bad_word_ids.iter().cloned());
+        let bad_word_ids = if all_bad_word_ids.is_empty() {
+            None
+        } else {
+            Some(&all_bad_word_ids)
+        };
         let prefix_allowed_tokens_fn =
             generate_options.and_then(|opts| opts.prefix_allowed_tokens_fn);
         let output_scores = generate_options.map_or(false, |opts| opts.output_scores);
+        let output_beam_hypotheses =
+            generate_options.map_or(false, |opts| opts.output_beam_hypotheses);
+        let budget = GenerationBudget::new(
+            start_time,
+            generate_options.and_then(|opts| opts.max_time),
+            generate_options.and_then(|opts| opts.max_total_new_tokens),
+        );
+        let dola_layers = config.dola_layers.as_deref();
+        if dola_layers.is_some() & !self.supports_layer_contrastive_decoding() {
+            return Err(RustBertError::InvalidConfigurationError(
+                "DoLa decoding (`dola_layers`) is not supported by this model".to_string(),
+            ));
+        }
+        let prefill_chunk_size = generate_options.and_then(|opts| opts.prefill_chunk_size);
+        let prefill_progress_fn = generate_options.and_then(|opts| opts.prefill_progress_fn);
+        if let Some(prefill_chunk_size) = prefill_chunk_size {
+            if prefill_chunk_size < 1 {
+                return Err(RustBertError::InvalidConfigurationError(
+                    "`prefill_chunk_size` must be strictly positive".to_string(),
+                ));
+            }
+            if self.is_encoder_decoder() || !self.supports_chunked_prefill() {
+                return Err(RustBertError::InvalidConfigurationError(
+                    "Chunked prefill (`prefill_chunk_size`) is not supported by this model"
+                        .to_string(),
+                ));
+            }
+        }
+        let token_callback_fn = generate_options.and_then(|opts| opts.token_callback_fn);
+        if token_callback_fn.is_some() && num_beams > 1 {
+            return Err(RustBertError::InvalidConfigurationError(
+                "Token callbacks (`token_callback_fn`) are not supported with beam search"
+                    .to_string(),
+            ));
+        }
+        if !config.layer_placement.is_empty() && !self.supports_layer_placement() {
+            return Err(RustBertError::InvalidConfigurationError(
+                "Per-layer placement (`layer_placement`) is not supported by this model"
+                    .to_string(),
+            ));
+        }
+        let attention_sink = config.attention_sink;
+        if attention_sink.is_some()
+            && (self.is_encoder_decoder() || !self.supports_attention_sinks())
+        {
+            return Err(RustBertError::InvalidConfigurationError(
+                "The attention sink cache policy (`attention_sink`) is not supported by this model"
+                    .to_string(),
+            ));
+        }
 
-        let pad_token_id = match self.get_pad_id() {
-            Some(value) => Some(value),
-            None => eos_token_ids.as_ref().map(|eos_ids| eos_ids[0]),
-        };
+        let pad_token_id = self.get_generation_pad_id();
+        if !stop_sequences.is_empty()
+            && (pad_token_id.is_none() || ((num_beams > 1) && eos_token_ids.is_none()))
+        {
+            return Err(RustBertError::InvalidConfigurationError(
+                "Stop sequences (`stop_sequences`) require a padding token, and an end of \
+                sequence token for beam search"
+                    .to_string(),
+            ));
+        }
 
         let input_id_size = input_ids.size();
         let mut input_ids_len = *input_id_size.last().unwrap();
         if input_ids_len == 0 {
+            let bos_id = self.get_bos_id().ok_or_else(|| {
+                RustBertError::InvalidConfigurationError(
+                    "`bos_token_id` has to be defined when no `input_ids` are provided."
+                        .to_string(),
+                )
+            })?;
             input_ids = Tensor::ones(
                 &[*input_id_size.first().unwrap(), 1],
                 (Int64, input_ids.device()),
-            ) * self
-                .get_bos_id()
-                .expect("`bos_token_id` has to be defined when no `input_ids` are provided.");
+            ) * bos_id;
             attention_mask = Some(Tensor::ones(
                 &[*input_id_size.first().unwrap(), 1],
                 (Int64, input_ids.device()),
@@ -1925,13 +3599,25 @@ pub trait LanguageGenerator<T: LMHeadModel, V: Vocab, U: Tokenizer<V>>:
             1
         };
         let batch_size = *input_ids.size().first().unwrap();
+        let _span = trace_span!(
+            INFO,
+            "generate",
+            batch_size,
+            input_length = input_ids_len,
+            num_beams,
+            do_sample,
+            device = ?input_ids.device()
+        );
 
-        let (effective_batch_size, effective_batch_mult) = match do_sample {
-            true => (
+        // Sampling without beams generates each returned sequence from a copy of its prompt, beam
+        // search returns several hypotheses of a single search
+        let (effective_batch_size, effective_batch_mult) = if do_sample & (num_beams == 1) {
+            (
                 batch_size * num_return_sequences as i64,
                 num_return_sequences as i64,
-            ),
-            false => (batch_size, 1),
+            )
+        } else {
+            (batch_size, 1)
         };
 
         let attention_mask = match attention_mask {
@@ -1943,7 +3629,13 @@ pub trait LanguageGenerator<T: LMHeadModel, V: Vocab, U: Tokenizer<V>>:
         };
 
         let encoder_outputs = if self.is_encoder_decoder() {
-            let encoder_outputs = self.encode(&input_ids, Some(&attention_mask)).unwrap();
+            let encoder_outputs =
+                self.encode(&input_ids, Some(&attention_mask))
+                    .ok_or_else(|| {
+                        RustBertError::InvalidConfigurationError(
+                            "Encoder-decoder model did not return encoder outputs".to_string(),
+                        )
+                    })?;
             let expanded_batch_indices = Tensor::arange(batch_size, (Int64, input_ids.device()))
                 .view((-1, 1))
                 .repeat(&[1, num_beams as i64 * effective_batch_mult])
@@ -1977,10 +3669,13 @@ pub trait LanguageGenerator<T: LMHeadModel, V: Vocab, U: Tokenizer<V>>:
                 (input_ids, attention_mask)
             }
         } else {
-            let decoder_start_token_id = decoder_start_token_id.unwrap_or_else(|| {
-                self.get_decoder_start_id()
-                    .expect("decoder start id must be specified for encoder decoders")
-            });
+            let decoder_start_token_id = decoder_start_token_id
+                .or_else(|| self.get_decoder_start_id())
+                .ok_or_else(|| {
+                    RustBertError::InvalidConfigurationError(
+                        "decoder start id must be specified for encoder decoders".to_string(),
+                    )
+                })?;
             let input_ids = Tensor::full(
                 &[effective_batch_size * num_beams as i64, 1],
                 decoder_start_token_id,
@@ -2018,8 +3713,11 @@ pub trait LanguageGenerator<T: LMHeadModel, V: Vocab, U: Tokenizer<V>>:
         };
 
         if max_length.is_none() & eos_token_ids.is_none() {
-            panic!("No maximum length given for a model without an EOS token. \
-            This would lead to an infinite generation loop. Please provide a `max_length` or `max_new_tokens`")
+            return Err(RustBertError::InvalidConfigurationError(
+                "No maximum length given for a model without an EOS token. \
+            This would lead to an infinite generation loop. Please provide a `max_length` or `max_new_tokens`"
+                    .to_string(),
+            ));
         }
 
         let gen_opt = InternalGenerateOptions {
@@ -2029,6 +3727,7 @@ pub trait LanguageGenerator<T: LMHeadModel, V: Vocab, U: Tokenizer<V>>:
             temperature,
             top_k,
             top_p,
+            min_p,
             repetition_penalty,
             no_repeat_ngram_size,
             pad_token_id,
@@ -2040,9 +3739,33 @@ pub trait LanguageGenerator<T: LMHeadModel, V: Vocab, U: Tokenizer<V>>:
             num_beam_groups,
             diversity_penalty,
             forced_bos_token_id,
+            forced_eos_token_id,
+            logit_bias,
             bad_word_ids,
+            output_beam_hypotheses,
+            dola_layers,
+            budget,
+            prefill_chunk_size,
+            prefill_progress_fn,
+            lazy_beam_expansion: generate_options
+                .and_then(|opts| opts.lazy_beam_expansion)
+                .unwrap_or(true),
+            attention_sink,
+            token_callback_fn,
+            stop_sequences,
+            grammar: generate_options.and_then(|opts| opts.grammar),
         };
 
+        if do_sample {
+            let seed = generate_options
+                .and_then(|opts| opts.seed)
+                .or(config.seed)
+                .or_else(deterministic_seed);
+            if let Some(seed) = seed {
+                tch::manual_seed(seed as i64);
+            }
+        }
+
         let generated_output_with_scores = no_grad(|| {
             if num_beams > 1 {
                 self.generate_beam_search(
@@ -2067,13 +3790,20 @@ pub trait LanguageGenerator<T: LMHeadModel, V: Vocab, U: Tokenizer<V>>:
                     output_scores,
                 )
             }
-        });
-        let (decoded, scores, mut token_scores) = (
+        })?;
+        let (decoded, scores, mut token_scores, beam_hypotheses, truncated) = (
             generated_output_with_scores.indices,
             generated_output_with_scores.scores,
             generated_output_with_scores.token_scores,
+            generated_output_with_scores.beam_hypotheses,
+            generated_output_with_scores.truncated,
         );
         let num_sequences = *decoded.size().first().unwrap();
+        let num_sequences_per_prompt = if do_sample & (num_beams == 1) {
+            1
+        } else {
+            num_return_sequences
+        };
         let mut output = Vec::with_capacity(num_sequences as usize);
         for sequence_index in 0..num_sequences {
             let indices = decoded
@@ -2090,13 +3820,44 @@ pub trait LanguageGenerator<T: LMHeadModel, V: Vocab, U: Tokenizer<V>>:
                 .as_mut()
                 .map(|token_scores| std::mem::take(&mut token_scores[sequence_index as usize]));
 
+            // Beam search returns the same hypotheses for all sequences generated from a prompt
+            let beam_hypotheses = beam_hypotheses.as_ref().map(|beam_hypotheses| {
+                beam_hypotheses[(sequence_index / num_sequences_per_prompt) as usize].clone()
+            });
+
             output.push(GeneratedIndicesOutput {
                 indices,
                 score,
                 token_scores,
+                beam_hypotheses,
+                truncated: truncated[sequence_index as usize],
             });
         }
-        output
+        if let Some(recorder) = metrics::recorder() {
+            let generated_tokens = output
+                .iter()
+                .map(|sequence| {
+                    let new_tokens =
+                        &sequence.indices[(cur_len as usize).min(sequence.indices.len())..];
+                    let padding = pad_token_id.map_or(0, |pad_token_id| {
+                        new_tokens
+                            .iter()
+                            .rev()
+                            .take_while(|&&token_id| token_id == pad_token_id)
+                            .count()
+                    });
+                    new_tokens.len() - padding
+                })
+                .sum();
+            recorder.record_request("generation", batch_size as usize);
+            recorder.record_tokens(
+                "generation",
+                (batch_size * input_ids_len) as usize,
+                generated_tokens,
+                start_time.elapsed(),
+            );
+        }
+        Ok(output)
     }
 
     /// Returns a reference to the text generator's tokenizer
@@ -2139,6 +3900,42 @@ pub trait LanguageGenerator<T: LMHeadModel, V: Vocab, U: Tokenizer<V>>:
         self._get_tokenizer()
     }
 
+    /// Creates a constraint forcing the text generated by this model to match a grammar, for the
+    /// tokenizer and vocabulary of the model. The constraint is applied by setting the `grammar`
+    /// field of the `GenerateOptions`, and can be re-used across calls.
+    ///
+    /// # Arguments
+    ///
+    /// * `grammar` - `Grammar` the generated text must match
+    ///
+    /// # Returns
+    ///
+    /// * `GrammarConstraint` - constraint to pass to the `GenerateOptions`
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::gpt2::GPT2Generator;
+    /// use rust_bert::pipelines::generation_utils::{GenerateOptions, LanguageGenerator};
+    /// use rust_bert::pipelines::grammar::Grammar;
+    ///
+    /// let gpt2_generator = GPT2Generator::new(Default::default())?;
+    /// let constraint = gpt2_generator.grammar_constraint(Grammar::json());
+    /// let output = gpt2_generator.generate(
+    ///     Some(&["A JSON list of three colors:"]),
+    ///     Some(GenerateOptions {
+    ///         grammar: Some(&constraint),
+    ///         ..Default::default()
+    ///     }),
+    /// )?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn grammar_constraint(&self, grammar: Grammar) -> GrammarConstraint {
+        GrammarConstraint::new(grammar, self._get_tokenizer(), self.get_vocab_size())
+    }
+
     fn half(&mut self) {
         self.get_var_store_mut().half();
     }
@@ -2344,6 +4141,21 @@ pub trait LMHeadModel {
         decoder_input_ids: Option<&Tensor>,
         train: bool,
     ) -> Result<LMModelOutput, RustBertError>;
+
+    /// Projects hidden states of an intermediate layer onto the vocabulary, applying the final
+    /// layer normalization and language modeling head of the model (early exit logits).
+    /// Returns `None` for models that do not support this projection.
+    ///
+    /// # Arguments
+    ///
+    /// * `hidden_states` - Tensor of shape (*batch size*, *hidden_size*) or (*batch size*, *sequence_length*, *hidden_size*)
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Tensor>` of shape (*batch size*, *vocab_size*) or (*batch size*, *sequence_length*, *vocab_size*)
+    fn project_hidden_states(&self, _hidden_states: &Tensor) -> Option<Tensor> {
+        None
+    }
 }
 
 /// Container holding a language model output for generation tasks
@@ -2352,4 +4164,636 @@ pub struct LMModelOutput {
     pub lm_logits: Tensor,
     /// cached state for improved efficiency during decoding
     pub cache: Cache,
+    /// Hidden states of the intermediate layers, for models configured to output them
+    pub all_hidden_states: Option<Vec<Tensor>>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pipelines::stopping_criteria::MaxTimeCriteria;
+    use crate::resources::LocalResource;
+    use std::path::PathBuf;
+
+    struct IdentityProcessor;
+
+    impl LogitsProcessor for IdentityProcessor {
+        fn process(&self, _input_ids: &Tensor, _scores: &mut Tensor, _current_length: i64) {}
+    }
+
+    fn local_resource(path: &str) -> LocalResource {
+        LocalResource {
+            local_path: PathBuf::from(path),
+        }
+    }
+
+    fn generate_config() -> GenerateConfig {
+        GenerateConfig::new(
+            local_resource("model/rust_model.ot"),
+            local_resource("model/config.json"),
+            local_resource("model/vocab.json"),
+            Some(local_resource("model/merges.txt")),
+        )
+    }
+
+    fn build_error(builder: &mut GeneratorBuilder) -> String {
+        match builder.build_config() {
+            Err(RustBertError::InvalidConfigurationError(message)) => message,
+            Err(error) => panic!("unexpected error: {}", error),
+            Ok(_) => panic!("the configuration should be rejected"),
+        }
+    }
+
+    fn assert_rejected<F: FnOnce(&mut GenerateConfig)>(configure: F, expected_message: &str) {
+        let mut config = generate_config();
+        configure(&mut config);
+        assert_eq!(
+            build_error(GeneratorBuilder::new().with_generate_config(config)),
+            expected_message
+        );
+    }
+
+    #[test]
+    fn builder_accepts_default_settings() -> anyhow::Result<()> {
+        let config = GeneratorBuilder::new()
+            .with_model_resource(local_resource("builder/rust_model.ot"))
+            .with_generate_config(generate_config())
+            .with_device(Device::Cpu)
+            .with_kind(Kind::Half)
+            .build_config()?;
+        //    Resources and device of the builder take precedence over the generation configuration
+        assert_eq!(
+            config.model_resource.get_local_path()?,
+            PathBuf::from("builder/rust_model.ot")
+        );
+        assert_eq!(
+            config.vocab_resource.get_local_path()?,
+            PathBuf::from("model/vocab.json")
+        );
+        assert_eq!(config.device, Device::Cpu);
+        Ok(())
+    }
+
+    #[test]
+    fn builder_rejects_integer_kind() {
+        assert_eq!(
+            build_error(
+                GeneratorBuilder::new()
+                    .with_generate_config(generate_config())
+                    .with_kind(Kind::Int64)
+            ),
+            "Invalid generator precision Int64, expected a floating point kind"
+        );
+    }
+
+    #[test]
+    fn builder_rejects_missing_model_resource() {
+        assert_eq!(
+            build_error(
+                GeneratorBuilder::new()
+                    .with_config_resource(local_resource("model/config.json"))
+                    .with_vocab_resource(local_resource("model/vocab.json"))
+            ),
+            "A model resource must be provided to the generator builder"
+        );
+    }
+
+    #[test]
+    fn builder_rejects_missing_config_resource() {
+        assert_eq!(
+            build_error(
+                GeneratorBuilder::new()
+                    .with_model_resource(local_resource("model/rust_model.ot"))
+                    .with_vocab_resource(local_resource("model/vocab.json"))
+            ),
+            "A config resource must be provided to the generator builder"
+        );
+    }
+
+    #[test]
+    fn builder_rejects_missing_vocab_resource() {
+        assert_eq!(
+            build_error(
+                GeneratorBuilder::new()
+                    .with_model_resource(local_resource("model/rust_model.ot"))
+                    .with_config_resource(local_resource("model/config.json"))
+            ),
+            "A vocab resource must be provided to the generator builder"
+        );
+    }
+
+    #[test]
+    fn rejects_non_positive_temperature() {
+        assert_rejected(
+            |config| {
+                config.temperature = 0.0;
+            },
+            "temperature must positive",
+        );
+    }
+
+    #[test]
+    fn rejects_top_p_above_one() {
+        assert_rejected(
+            |config| {
+                config.top_p = 1.5;
+            },
+            "top_p must be 0 and 1",
+        );
+    }
+
+    #[test]
+    fn rejects_negative_min_p() {
+        assert_rejected(
+            |config| {
+                config.min_p = -0.1;
+            },
+            "min_p must be between 0 and 1",
+        );
+    }
+
+    #[test]
+    fn rejects_repetition_penalty_below_one() {
+        assert_rejected(
+            |config| {
+                config.repetition_penalty = 0.5;
+            },
+            "repetition_penalty must be greater than 1",
+        );
+    }
+
+    #[test]
+    fn rejects_non_positive_length_penalty() {
+        assert_rejected(
+            |config| {
+                config.length_penalty = 0.0;
+            },
+            "length_penalty must be strictly greater than 0",
+        );
+    }
+
+    #[test]
+    fn rejects_zero_return_sequences() {
+        assert_rejected(
+            |config| {
+                config.num_return_sequences = 0;
+            },
+            "num_return_sequences must be strictly greater than 0",
+        );
+    }
+
+    #[test]
+    fn rejects_zero_beams() {
+        assert_rejected(
+            |config| {
+                config.num_beams = 0;
+            },
+            "num_beams must be strictly greater than 0",
+        );
+    }
+
+    #[test]
+    fn rejects_more_return_sequences_than_beams() {
+        assert_rejected(
+            |config| {
+                config.num_beams = 2;
+                config.num_return_sequences = 3;
+            },
+            "num_return_sequences must be lower than the number of beams",
+        );
+    }
+
+    #[test]
+    fn rejects_multiple_greedy_return_sequences() {
+        assert_rejected(
+            |config| {
+                config.num_beams = 1;
+                config.do_sample = false;
+                config.num_return_sequences = 2;
+            },
+            "num_return_sequences must be set to 1 for greedy decoding",
+        );
+    }
+
+    #[test]
+    fn rejects_beams_not_multiple_of_groups() {
+        assert_rejected(
+            |config| {
+                config.num_beams = 5;
+                config.num_beam_groups = Some(2);
+            },
+            "num_beams must be a multiple of num_beam_groups",
+        );
+    }
+
+    #[test]
+    fn rejects_negative_diversity_penalty() {
+        assert_rejected(
+            |config| {
+                config.diversity_penalty = Some(-1.0);
+            },
+            "diversity_penalty must be non-negative",
+        );
+    }
+
+    #[test]
+    fn rejects_zero_max_prompt_length() {
+        assert_rejected(
+            |config| {
+                config.max_prompt_length = Some(0);
+            },
+            "max_prompt_length must be strictly greater than 0",
+        );
+    }
+
+    #[test]
+    fn rejects_only_second_truncation() {
+        assert_rejected(
+            |config| {
+                config.prompt_truncation_strategy = TruncationStrategy::OnlySecond;
+            },
+            "prompt_truncation_strategy cannot be OnlySecond for single prompts",
+        );
+    }
+
+    #[test]
+    fn rejects_empty_dola_layers() {
+        assert_rejected(
+            |config| {
+                config.dola_layers = Some(vec![]);
+            },
+            "dola_layers must contain at least one layer",
+        );
+    }
+
+    #[test]
+    fn rejects_negative_dola_layers() {
+        assert_rejected(
+            |config| {
+                config.dola_layers = Some(vec![0, -1]);
+            },
+            "dola_layers must be positive layer indices",
+        );
+    }
+
+    #[test]
+    fn rejects_negative_sink_tokens() {
+        assert_rejected(
+            |config| {
+                config.attention_sink = Some(AttentionSinkConfig {
+                    num_sink_tokens: -1,
+                    window_size: 16,
+                });
+            },
+            "attention_sink num_sink_tokens must be positive",
+        );
+    }
+
+    #[test]
+    fn rejects_empty_sink_window() {
+        assert_rejected(
+            |config| {
+                config.attention_sink = Some(AttentionSinkConfig {
+                    num_sink_tokens: 4,
+                    window_size: 0,
+                });
+            },
+            "attention_sink window_size must be strictly greater than 0",
+        );
+    }
+
+    #[test]
+    fn rejects_empty_stop_sequence() {
+        assert_rejected(
+            |config| {
+                config.stop_sequences = vec!["\n".to_string(), String::new()];
+            },
+            "stop_sequences must not contain empty strings",
+        );
+    }
+
+    #[test]
+    fn rejects_empty_bad_word_ids() {
+        assert_rejected(
+            |config| {
+                config.bad_word_ids = vec![vec![3], vec![]];
+            },
+            "bad_word_ids must not contain empty sequences",
+        );
+    }
+
+    #[test]
+    fn rejects_empty_bad_word() {
+        assert_rejected(
+            |config| {
+                config.bad_words = vec![String::new()];
+            },
+            "bad_words must not contain empty strings",
+        );
+    }
+
+    #[test]
+    fn rejects_negative_forced_token() {
+        assert_rejected(
+            |config| {
+                config.forced_eos_token_id = Some(-1);
+            },
+            "forced_bos_token_id and forced_eos_token_id must be positive",
+        );
+    }
+
+    #[test]
+    fn rejects_negative_logit_bias_token() {
+        assert_rejected(
+            |config| {
+                config.logit_bias.insert(-1, 1.0);
+            },
+            "logit_bias token ids must be positive",
+        );
+    }
+
+    #[test]
+    fn rejects_nan_logit_bias() {
+        assert_rejected(
+            |config| {
+                config.logit_bias.insert(1, f64::NAN);
+            },
+            "logit_bias values must not be NaN",
+        );
+    }
+
+    fn call_error(config: &GenerateConfig, generate_options: GenerateOptions) -> String {
+        let settings = GenerationSettings::new(config, Some(generate_options));
+        match settings
+            .validate()
+            .and_then(|_| settings.validate_vocabulary(16))
+        {
+            Err(RustBertError::InvalidConfigurationError(message)) => message,
+            Err(error) => panic!("unexpected error: {}", error),
+            Ok(_) => panic!("the options should be rejected"),
+        }
+    }
+
+    #[test]
+    fn call_options_are_validated() {
+        let config = generate_config();
+        let empty_bad_word_ids = vec![vec![]];
+        let out_of_vocabulary_bad_word_ids = vec![vec![3], vec![4, 16]];
+        let negative_bad_word_ids = vec![vec![-1]];
+        let out_of_vocabulary_bias: HashMap<i64, f64> = [(16, 1.0)].iter().copied().collect();
+        let cases = [
+            (
+                GenerateOptions {
+                    temperature: Some(0.0),
+                    ..Default::default()
+                },
+                "temperature must positive",
+            ),
+            (
+                GenerateOptions {
+                    top_p: Some(1.5),
+                    ..Default::default()
+                },
+                "top_p must be 0 and 1",
+            ),
+            (
+                GenerateOptions {
+                    repetition_penalty: Some(0.0),
+                    ..Default::default()
+                },
+                "repetition_penalty must be greater than 1",
+            ),
+            (
+                GenerateOptions {
+                    num_beams: Some(0),
+                    ..Default::default()
+                },
+                "num_beams must be strictly greater than 0",
+            ),
+            (
+                GenerateOptions {
+                    num_beams: Some(2),
+                    num_return_sequences: Some(3),
+                    ..Default::default()
+                },
+                "num_return_sequences must be lower than the number of beams",
+            ),
+            (
+                GenerateOptions {
+                    diversity_penalty: Some(-1.0),
+                    ..Default::default()
+                },
+                "diversity_penalty must be non-negative",
+            ),
+            (
+                GenerateOptions {
+                    bad_word_ids: Some(&empty_bad_word_ids),
+                    ..Default::default()
+                },
+                "bad_word_ids must not contain empty sequences",
+            ),
+            (
+                GenerateOptions {
+                    logit_bias: Some(&out_of_vocabulary_bias),
+                    ..Default::default()
+                },
+                "logit_bias token ids must be lower than the vocabulary size (16)",
+            ),
+            (
+                GenerateOptions {
+                    bad_word_ids: Some(&out_of_vocabulary_bad_word_ids),
+                    ..Default::default()
+                },
+                "bad_word_ids must be between 0 and the vocabulary size (16)",
+            ),
+            (
+                GenerateOptions {
+                    bad_word_ids: Some(&negative_bad_word_ids),
+                    ..Default::default()
+                },
+                "bad_word_ids must be between 0 and the vocabulary size (16)",
+            ),
+            (
+                GenerateOptions {
+                    forced_bos_token_id: Some(16),
+                    ..Default::default()
+                },
+                "forced_bos_token_id and forced_eos_token_id must be lower than the vocabulary size (16)",
+            ),
+            (
+                GenerateOptions {
+                    forced_eos_token_id: Some(20),
+                    ..Default::default()
+                },
+                "forced_bos_token_id and forced_eos_token_id must be lower than the vocabulary size (16)",
+            ),
+        ];
+        for (generate_options, expected_message) in cases.iter() {
+            assert_eq!(call_error(&config, *generate_options), *expected_message);
+        }
+
+        //    Options of the call take priority over the configuration, which applies otherwise
+        let settings = GenerationSettings::new(
+            &config,
+            Some(GenerateOptions {
+                temperature: Some(0.5),
+                ..Default::default()
+            }),
+        );
+        assert!(settings.validate().is_ok());
+        assert_eq!(settings.temperature, 0.5);
+        assert_eq!(settings.top_p, config.top_p);
+
+        let mut invalid_config = generate_config();
+        invalid_config.top_p = 1.5;
+        assert_eq!(
+            call_error(&invalid_config, GenerateOptions::default()),
+            "top_p must be 0 and 1"
+        );
+        let mut invalid_bad_word_ids_config = generate_config();
+        invalid_bad_word_ids_config.bad_word_ids = vec![vec![16]];
+        assert_eq!(
+            call_error(&invalid_bad_word_ids_config, GenerateOptions::default()),
+            "bad_word_ids must be between 0 and the vocabulary size (16)"
+        );
+        let mut invalid_forced_eos_config = generate_config();
+        invalid_forced_eos_config.forced_eos_token_id = Some(16);
+        assert_eq!(
+            call_error(&invalid_forced_eos_config, GenerateOptions::default()),
+            "forced_bos_token_id and forced_eos_token_id must be lower than the vocabulary size (16)"
+        );
+        assert!(GenerationSettings::new(
+            &invalid_config,
+            Some(GenerateOptions {
+                top_p: Some(0.5),
+                ..Default::default()
+            })
+        )
+        .validate()
+        .is_ok());
+    }
+
+    #[test]
+    fn generate_config_serde_round_trip() -> anyhow::Result<()> {
+        let mut config = generate_config();
+        config.max_length = Some(32);
+        config.min_p = 0.05;
+        config.prompt_truncation_side = TruncationSide::Left;
+        config.prompt_truncation_strategy = TruncationStrategy::OnlyFirst;
+        config.attention_sink = Some(AttentionSinkConfig {
+            num_sink_tokens: 4,
+            window_size: 64,
+        });
+        config.stop_sequences = vec!["\n\n".to_string()];
+        config.bad_word_ids = vec![vec![3], vec![4, 5]];
+        config.logit_bias = [(7, -1.5)].iter().copied().collect();
+        config.layer_placement = [(
+            "transformer.h.1".to_string(),
+            LayerPlacement {
+                device: Some(Device::Cuda(1)),
+                kind: Some(Kind::Half),
+            },
+        )]
+        .iter()
+        .cloned()
+        .collect();
+        config.logits_processors = vec![Box::new(IdentityProcessor)];
+        config.stopping_criteria = vec![Box::new(MaxTimeCriteria::new(Duration::from_secs(1)))];
+        config.device = Device::Cuda(1);
+
+        let serialized = serde_json::to_value(&config)?;
+        assert_eq!(serialized["device"], "cuda:1");
+        assert_eq!(
+            serialized["layer_placement"]["transformer.h.1"]["device"],
+            "cuda:1"
+        );
+        assert_eq!(
+            serialized["layer_placement"]["transformer.h.1"]["kind"],
+            "half"
+        );
+        assert_eq!(serialized["prompt_truncation_strategy"], "only_first");
+        //    Logits processors and stopping criteria are skipped
+        assert!(serialized.get("logits_processors").is_none());
+        assert!(serialized.get("stopping_criteria").is_none());
+
+        let deserialized: GenerateConfig = serde_json::from_value(serialized.clone())?;
+        assert_eq!(deserialized.device, Device::Cuda(1));
+        assert_eq!(
+            deserialized.model_resource.get_local_path()?,
+            PathBuf::from("model/rust_model.ot")
+        );
+        assert_eq!(
+            deserialized
+                .merges_resource
+                .as_ref()
+                .unwrap()
+                .get_local_path()?,
+            PathBuf::from("model/merges.txt")
+        );
+        assert_eq!(deserialized.max_length, Some(32));
+        assert_eq!(deserialized.prompt_truncation_side, TruncationSide::Left);
+        assert_eq!(deserialized.attention_sink, config.attention_sink);
+        assert_eq!(deserialized.bad_word_ids, config.bad_word_ids);
+        assert_eq!(deserialized.logit_bias, config.logit_bias);
+        assert_eq!(deserialized.layer_placement, config.layer_placement);
+        assert!(deserialized.logits_processors.is_empty());
+        assert!(deserialized.stopping_criteria.is_empty());
+
+        //    Serializing the deserialized configuration gives back the same document
+        assert_eq!(serde_json::to_value(&deserialized)?, serialized);
+        Ok(())
+    }
+
+    #[test]
+    fn generate_config_serde_defaults() -> anyhow::Result<()> {
+        let mut serialized = serde_json::to_value(&GenerateConfig::new(
+            local_resource("model/rust_model.ot"),
+            local_resource("model/config.json"),
+            local_resource("model/vocab.json"),
+            None,
+        ))?;
+        let fields = serialized.as_object_mut().unwrap();
+        for field in [
+            "min_p",
+            "prompt_truncation_strategy",
+            "stop_sequences",
+            "logit_bias",
+            "layer_placement",
+            "device",
+        ]
+        .iter()
+        {
+            assert!(fields.remove(*field).is_some(), "missing field {}", field);
+        }
+
+        let deserialized: GenerateConfig = serde_json::from_value(serialized.clone())?;
+        assert_eq!(deserialized.min_p, 0.0);
+        assert!(matches!(
+            deserialized.prompt_truncation_strategy,
+            TruncationStrategy::LongestFirst
+        ));
+        assert!(deserialized.stop_sequences.is_empty());
+        assert!(deserialized.logit_bias.is_empty());
+        assert!(deserialized.layer_placement.is_empty());
+        assert_eq!(deserialized.device, Device::cuda_if_available());
+
+        //    The device helper accepts `cpu`, `cuda` and `cuda:<index>` and rejects other values
+        for (device, expected) in [
+            ("cpu", Device::Cpu),
+            ("CPU", Device::Cpu),
+            ("cuda", Device::Cuda(0)),
+            ("cuda:2", Device::Cuda(2)),
+        ]
+        .iter()
+        {
+            serialized["device"] = (*device).into();
+            let deserialized: GenerateConfig = serde_json::from_value(serialized.clone())?;
+            assert_eq!(deserialized.device, *expected);
+        }
+        for &device in ["gpu", "cuda:first"].iter() {
+            serialized["device"] = device.into();
+            assert!(serde_json::from_value::<GenerateConfig>(serialized.clone()).is_err());
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,210 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashSet;
+
+use crate::pipelines::grammar::ebnf::{Element, Grammar};
+
+/// Position in an alternative of a rule: the next element to match
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct Position {
+    rule: usize,
+    alternative: usize,
+    element: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// # State of a grammar after matching a prefix of text
+/// Set of the parse stacks compatible with the prefix. The top of each stack is the next
+/// character element to match, the rest of the stack the positions to resume from once the
+/// current rules are complete. An empty stack means that the prefix is a complete match of the
+/// grammar, an empty set of stacks that the prefix can not be completed into a match.
+pub struct GrammarState {
+    stacks: Vec<Vec<Position>>,
+}
+
+impl GrammarState {
+    fn from_stacks(stacks: HashSet<Vec<Position>>) -> GrammarState {
+        let mut stacks = stacks.into_iter().collect::<Vec<Vec<Position>>>();
+        stacks.sort();
+        GrammarState { stacks }
+    }
+
+    /// Returns true if the text matched so far is a complete match of the grammar (it may still be
+    /// extended into a longer match)
+    pub fn is_complete(&self) -> bool {
+        self.stacks.iter().any(Vec::is_empty)
+    }
+
+    /// Returns true if the text matched so far can not be completed into a match of the grammar
+    pub fn is_rejected(&self) -> bool {
+        self.stacks.is_empty()
+    }
+}
+
+impl Grammar {
+    /// Returns the state of the grammar before any character is matched
+    pub fn initial_state(&self) -> GrammarState {
+        let mut stacks = HashSet::new();
+        for alternative in 0..self.rules[self.root].alternatives.len() {
+            self.expand(
+                vec![Position {
+                    rule: self.root,
+                    alternative,
+                    element: 0,
+                }],
+                &mut stacks,
+            );
+        }
+        GrammarState::from_stacks(stacks)
+    }
+
+    /// Returns true if the character is a valid continuation of the text matched by the state
+    pub fn accepts(&self, state: &GrammarState, character: char) -> bool {
+        state.stacks.iter().any(|stack| {
+            stack
+                .last()
+                .map_or(false, |top| self.position_element(top).matches(character))
+        })
+    }
+
+    /// Returns true if at least one character of the (inclusive) range `start..=end` is a valid
+    /// continuation of the text matched by the state
+    pub fn accepts_range(&self, state: &GrammarState, start: char, end: char) -> bool {
+        state.stacks.iter().any(|stack| {
+            stack.last().map_or(false, |top| {
+                self.position_element(top).matches_range(start, end)
+            })
+        })
+    }
+
+    /// Matches a character, returning the state of the grammar after it (rejected if the character
+    /// is not a valid continuation)
+    pub fn advance(&self, state: &GrammarState, character: char) -> GrammarState {
+        let mut stacks = HashSet::new();
+        for stack in &state.stacks {
+            if let Some(top) = stack.last() {
+                if self.position_element(top).matches(character) {
+                    let mut stack = stack.clone();
+                    let top = stack.pop().unwrap();
+                    self.push_next(&mut stack, top);
+                    self.expand(stack, &mut stacks);
+                }
+            }
+        }
+        GrammarState::from_stacks(stacks)
+    }
+
+    /// Matches a text, returning the state of the grammar after it or `None` if the text is not a
+    /// valid continuation
+    pub fn advance_str(&self, state: &GrammarState, text: &str) -> Option<GrammarState> {
+        let mut current: Option<GrammarState> = None;
+        for character in text.chars() {
+            let next = self.advance(current.as_ref().unwrap_or(state), character);
+            if next.is_rejected() {
+                return None;
+            }
+            current = Some(next);
+        }
+        Some(current.unwrap_or_else(|| state.clone()))
+    }
+
+    /// Returns true if the text is a complete match of the grammar
+    pub fn matches(&self, text: &str) -> bool {
+        self.advance_str(&self.initial_state(), text)
+            .map_or(false, |state| state.is_complete())
+    }
+
+    fn position_element(&self, position: &Position) -> &Element {
+        self.element(position.rule, position.alternative, position.element)
+    }
+
+    /// Pushes the position following `position` in its alternative, if any
+    fn push_next(&self, stack: &mut Vec<Position>, position: Position) {
+        if position.element + 1 < self.alternative_length(position.rule, position.alternative) {
+            stack.push(Position {
+                element: position.element + 1,
+                ..position
+            });
+        }
+    }
+
+    /// Expands the rule references at the top of the stack until a character element (or the end
+    /// of the grammar) is reached, adding the resulting stacks to `output`
+    fn expand(&self, mut stack: Vec<Position>, output: &mut HashSet<Vec<Position>>) {
+        let top = match stack.pop() {
+            Some(top) => top,
+            None => {
+                output.insert(stack);
+                return;
+            }
+        };
+        if top.element == self.alternative_length(top.rule, top.alternative) {
+            //    Empty alternative
+            self.expand(stack, output);
+            return;
+        }
+        match self.position_element(&top) {
+            Element::Characters { .. } => {
+                stack.push(top);
+                output.insert(stack);
+            }
+            Element::Rule(rule) => {
+                //    The continuation is only kept if the reference is not the last element of
+                //    the alternative, so that right-recursive repetitions do not grow the stack
+                self.push_next(&mut stack, top);
+                for alternative in 0..self.rules[*rule].alternatives.len() {
+                    let mut stack = stack.clone();
+                    stack.push(Position {
+                        rule: *rule,
+                        alternative,
+                        element: 0,
+                    });
+                    self.expand(stack, output);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::RustBertError;
+
+    #[test]
+    fn match_grammar() -> Result<(), RustBertError> {
+        let grammar = Grammar::from_ebnf(
+            r#"
+            root ::= "[" ws (item ("," ws item)*)? "]"
+            item ::= number | root
+            number ::= "-"? [0-9]+
+            ws ::= [ ]?
+            "#,
+        )?;
+        assert!(grammar.matches("[]"));
+        assert!(grammar.matches("[1, -23,[4, []]]"));
+        assert!(!grammar.matches("[1,"));
+        assert!(!grammar.matches("[1]]"));
+        assert!(!grammar.matches("[a]"));
+
+        let state = grammar.advance_str(&grammar.initial_state(), "[1").unwrap();
+        assert!(!state.is_complete());
+        assert!(grammar.accepts(&state, '2'));
+        assert!(grammar.accepts(&state, ']'));
+        assert!(!grammar.accepts(&state, ' '));
+        let state = grammar.advance(&state, ']');
+        assert!(state.is_complete());
+        assert!(grammar.advance(&state, ']').is_rejected());
+        assert!(grammar.advance_str(&state, "").is_some());
+        Ok(())
+    }
+}
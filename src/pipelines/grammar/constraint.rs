@@ -0,0 +1,497 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use rust_tokenizers::tokenizer::Tokenizer;
+use tch::Tensor;
+
+use crate::common::kind::get_positive_infinity;
+use crate::pipelines::common::TokenizerOption;
+use crate::pipelines::grammar::automaton::GrammarState;
+use crate::pipelines::grammar::ebnf::Grammar;
+
+/// Maximum number of generated prefixes whose grammar state is cached
+const MAX_CACHED_PREFIXES: usize = 65_536;
+
+/// State of a constraint: state of the grammar after the complete characters generated, and bytes
+/// of an incomplete character (e.g. the first byte-level BPE token of a multi-byte character)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ConstraintState {
+    grammar_state: GrammarState,
+    pending_bytes: Vec<u8>,
+}
+
+impl ConstraintState {
+    fn is_complete(&self) -> bool {
+        self.pending_bytes.is_empty() && self.grammar_state.is_complete()
+    }
+}
+
+/// # Token-level grammar constraint for text generation
+/// Restricts the tokens generated by a `LanguageGenerator` to the continuations of the generated
+/// text that can still be completed into a match of a `Grammar`, and only allows the end of
+/// sequence token once the generated text is a complete match. The constraint is passed to the
+/// `generate` methods with the `grammar` field of the `GenerateOptions`.
+///
+/// The constraint works on the bytes of each token of the vocabulary, as concatenated when
+/// decoding a sequence: tokens holding part of a multi-byte character are buffered until the
+/// character is complete, and the leading spaces of SentencePiece tokens are kept. The bytes of
+/// the tokens are exact for byte-level BPE (e.g. GPT2, RoBERTa) and SentencePiece (e.g. T5,
+/// Marian) tokenizers, other tokenizers use the decoded text of each token. Special tokens are
+/// never generated.
+///
+/// The tokens allowed after each grammar state and the states reached by the generated sequences
+/// are cached, so that a constraint can be re-used across generation calls to amortize the
+/// matching of the vocabulary.
+pub struct GrammarConstraint {
+    grammar: Grammar,
+    initial_state: ConstraintState,
+    tokens: Vec<Vec<u8>>,
+    prefix_states: Mutex<HashMap<Vec<i64>, Option<ConstraintState>>>,
+    allowed_tokens: Mutex<HashMap<ConstraintState, Arc<Vec<i64>>>>,
+}
+
+impl GrammarConstraint {
+    /// Creates a grammar constraint for the vocabulary of a tokenizer. The
+    /// `LanguageGenerator::grammar_constraint` method creates a constraint for the tokenizer and
+    /// vocabulary of a model.
+    ///
+    /// # Arguments
+    ///
+    /// * `grammar` - grammar the generated text must match
+    /// * `tokenizer` - tokenizer of the model
+    /// * `vocab_size` - size of the vocabulary of the model
+    pub fn new(
+        grammar: Grammar,
+        tokenizer: &TokenizerOption,
+        vocab_size: i64,
+    ) -> GrammarConstraint {
+        GrammarConstraint::from_token_bytes(grammar, vocabulary_bytes(tokenizer, vocab_size))
+    }
+
+    /// Creates a grammar constraint from the decoded text of each token of the vocabulary (indexed
+    /// by token id). Special tokens should be given an empty text.
+    pub fn from_token_texts(grammar: Grammar, tokens: Vec<String>) -> GrammarConstraint {
+        GrammarConstraint::from_token_bytes(
+            grammar,
+            tokens.into_iter().map(String::into_bytes).collect(),
+        )
+    }
+
+    /// Creates a grammar constraint from the bytes of each token of the vocabulary (indexed by
+    /// token id). The bytes of a token may hold an incomplete UTF-8 character, completed by the
+    /// following tokens. Special tokens should be given no bytes.
+    pub fn from_token_bytes(grammar: Grammar, tokens: Vec<Vec<u8>>) -> GrammarConstraint {
+        GrammarConstraint {
+            initial_state: ConstraintState {
+                grammar_state: grammar.initial_state(),
+                pending_bytes: vec![],
+            },
+            grammar,
+            tokens,
+            prefix_states: Mutex::new(HashMap::new()),
+            allowed_tokens: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the grammar of the constraint
+    pub fn grammar(&self) -> &Grammar {
+        &self.grammar
+    }
+
+    /// Returns the tokens allowed after a sequence of generated tokens.
+    ///
+    /// # Arguments
+    ///
+    /// * `generated` - tokens generated so far (excluding the prompt)
+    /// * `eos_token_ids` - end of sequence tokens, allowed once the generated text is a complete match
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Vec<i64>>` - allowed tokens, `None` if the generation is not constrained (the
+    ///   sequence already ended, or does not match the grammar)
+    pub fn next_tokens(&self, generated: &[i64], eos_token_ids: &[i64]) -> Option<Vec<i64>> {
+        if generated
+            .iter()
+            .any(|token_id| eos_token_ids.contains(token_id))
+        {
+            return None;
+        }
+        let state = self.prefix_state(generated)?;
+        let mut tokens = self.state_tokens(&state).as_ref().clone();
+        //    The end of sequence is also allowed if the grammar can not be continued with the
+        //    vocabulary, to end the generation instead of masking all tokens
+        if state.is_complete() | tokens.is_empty() {
+            tokens.extend_from_slice(eos_token_ids);
+        }
+        if tokens.is_empty() {
+            None
+        } else {
+            Some(tokens)
+        }
+    }
+
+    /// Masks the scores of the tokens not allowed by the grammar.
+    ///
+    /// # Arguments
+    ///
+    /// * `input_ids` - sequences generated so far, of shape (*num_sequences*, *current_length*)
+    /// * `prompt_length` - length of the prompt (or of the decoder start tokens) of the sequences
+    /// * `eos_token_ids` - end of sequence tokens
+    /// * `scores` - scores of the next token, of shape (*num_sequences*, *vocab_size*)
+    pub(crate) fn process(
+        &self,
+        input_ids: &Tensor,
+        prompt_length: i64,
+        eos_token_ids: Option<&Vec<i64>>,
+        scores: &mut Tensor,
+    ) {
+        let eos_token_ids = eos_token_ids.map_or(&[][..], |eos_token_ids| eos_token_ids.as_slice());
+        let current_length = *input_ids.size().last().unwrap();
+        let vocab_size = *scores.size().last().unwrap();
+        let mask = scores.new_full(
+            scores.size().as_slice(),
+            get_positive_infinity(scores.kind()).unwrap(),
+            (scores.kind(), scores.device()),
+        );
+        for idx in 0..scores.size()[0] {
+            let generated = Vec::<i64>::from(input_ids.get(idx).slice(
+                0,
+                prompt_length,
+                current_length,
+                1,
+            ));
+            match self.next_tokens(&generated, eos_token_ids) {
+                Some(mut allowed_tokens) => {
+                    allowed_tokens.retain(|token_id| *token_id < vocab_size);
+                    let _ = mask.get(idx).index_fill_(
+                        0,
+                        &Tensor::of_slice(allowed_tokens.as_slice()).to(scores.device()),
+                        0,
+                    );
+                }
+                None => {
+                    let _ = mask.get(idx).fill_(0);
+                }
+            }
+        }
+        let _ = scores.subtract_(&mask);
+    }
+
+    /// State of the constraint after the generated tokens, `None` if they do not match the grammar
+    fn prefix_state(&self, generated: &[i64]) -> Option<ConstraintState> {
+        let mut prefix_states = self.prefix_states.lock().unwrap();
+        if prefix_states.len() > MAX_CACHED_PREFIXES {
+            prefix_states.clear();
+        }
+        let mut start = generated.len();
+        while (start > 0) && !prefix_states.contains_key(&generated[..start]) {
+            start -= 1;
+        }
+        let mut state = if start == 0 {
+            Some(self.initial_state.clone())
+        } else {
+            prefix_states[&generated[..start]].clone()
+        };
+        for end in start + 1..=generated.len() {
+            state = state.and_then(|state| self.advance_token(&state, generated[end - 1]));
+            prefix_states.insert(generated[..end].to_vec(), state.clone());
+        }
+        state
+    }
+
+    fn advance_token(&self, state: &ConstraintState, token_id: i64) -> Option<ConstraintState> {
+        let bytes = self.tokens.get(token_id as usize)?;
+        if bytes.is_empty() {
+            return None;
+        }
+        self.advance_bytes(state, bytes)
+    }
+
+    /// Matches the bytes of a token, buffering the bytes of an incomplete character at the end.
+    /// Returns `None` if the bytes are not valid UTF-8, or if the complete characters (or the
+    /// characters the incomplete one may become) are not a valid continuation.
+    fn advance_bytes(&self, state: &ConstraintState, bytes: &[u8]) -> Option<ConstraintState> {
+        let mut pending_bytes = state.pending_bytes.clone();
+        pending_bytes.extend_from_slice(bytes);
+        let valid_length = match std::str::from_utf8(&pending_bytes) {
+            Ok(_) => pending_bytes.len(),
+            Err(error) if error.error_len().is_none() => error.valid_up_to(),
+            Err(_) => return None,
+        };
+        let text = std::str::from_utf8(&pending_bytes[..valid_length]).unwrap();
+        let grammar_state = self.grammar.advance_str(&state.grammar_state, text)?;
+        let pending_bytes = pending_bytes.split_off(valid_length);
+        if !pending_bytes.is_empty() {
+            let (start, end) = incomplete_character_range(&pending_bytes)?;
+            if !self.grammar.accepts_range(&grammar_state, start, end) {
+                return None;
+            }
+        }
+        Some(ConstraintState {
+            grammar_state,
+            pending_bytes,
+        })
+    }
+
+    /// Tokens of the vocabulary that are valid continuations from a state
+    fn state_tokens(&self, state: &ConstraintState) -> Arc<Vec<i64>> {
+        if let Some(tokens) = self.allowed_tokens.lock().unwrap().get(state) {
+            return tokens.clone();
+        }
+        let tokens = Arc::new(
+            self.tokens
+                .iter()
+                .enumerate()
+                .filter(
+                    |(_, bytes)| match (state.pending_bytes.is_empty(), bytes.first()) {
+                        (_, None) => false,
+                        //    Fast rejection of the tokens starting with an ASCII character
+                        (true, Some(&byte)) if byte.is_ascii() => {
+                            self.grammar.accepts(&state.grammar_state, byte as char)
+                                && self.advance_bytes(state, bytes).is_some()
+                        }
+                        _ => self.advance_bytes(state, bytes).is_some(),
+                    },
+                )
+                .map(|(token_id, _)| token_id as i64)
+                .collect::<Vec<i64>>(),
+        );
+        self.allowed_tokens
+            .lock()
+            .unwrap()
+            .insert(state.clone(), tokens.clone());
+        tokens
+    }
+}
+
+/// Range of the characters starting with the bytes of an incomplete UTF-8 character, `None` if
+/// no character starts with these bytes
+fn incomplete_character_range(bytes: &[u8]) -> Option<(char, char)> {
+    let (character_length, min_code_point) = match bytes.first()? {
+        0xC0..=0xDF => (2, 0x80),
+        0xE0..=0xEF => (3, 0x800),
+        0xF0..=0xF7 => (4, 0x10000),
+        _ => return None,
+    };
+    if bytes.len() >= character_length {
+        return None;
+    }
+    let code_point = |continuation_byte: u8| {
+        let mut character_bytes = bytes.to_vec();
+        character_bytes.resize(character_length, continuation_byte);
+        let lead_bits = (character_bytes[0] & (0x7F >> character_length)) as u32;
+        character_bytes[1..]
+            .iter()
+            .fold(lead_bits, |code_point, byte| {
+                (code_point << 6) | (byte & 0x3F) as u32
+            })
+    };
+    let mut start = code_point(0x80).max(min_code_point);
+    let mut end = code_point(0xBF).min(0x10FFFF);
+    //    Surrogates are not characters
+    if (0xD800..=0xDFFF).contains(&start) {
+        start = 0xE000;
+    }
+    if (0xD800..=0xDFFF).contains(&end) {
+        end = 0xD7FF;
+    }
+    Some((char::from_u32(start)?, char::from_u32(end)?)).filter(|(start, end)| start <= end)
+}
+
+/// Bytes of each token of the vocabulary, as concatenated when decoding a sequence. Special tokens
+/// and ids without token are given no bytes.
+fn vocabulary_bytes(tokenizer: &TokenizerOption, vocab_size: i64) -> Vec<Vec<u8>> {
+    let token_bytes = |token_id: i64| -> Vec<u8> {
+        let token_ids = [token_id];
+        let (tokens, byte_level) = match tokenizer {
+            TokenizerOption::GPT2(tokenizer) => (tokenizer.decode_to_vec(&token_ids, true), true),
+            TokenizerOption::Roberta(tokenizer) | TokenizerOption::Bart(tokenizer) => {
+                (tokenizer.decode_to_vec(&token_ids, true), true)
+            }
+            TokenizerOption::Deberta(tokenizer) => {
+                (tokenizer.decode_to_vec(&token_ids, true), true)
+            }
+            TokenizerOption::T5(tokenizer) => (tokenizer.decode_to_vec(&token_ids, true), false),
+            TokenizerOption::Marian(tokenizer) => {
+                (tokenizer.decode_to_vec(&token_ids, true), false)
+            }
+            TokenizerOption::XLNet(tokenizer) => (tokenizer.decode_to_vec(&token_ids, true), false),
+            TokenizerOption::Albert(tokenizer) => {
+                (tokenizer.decode_to_vec(&token_ids, true), false)
+            }
+            TokenizerOption::XLMRoberta(tokenizer) => {
+                (tokenizer.decode_to_vec(&token_ids, true), false)
+            }
+            TokenizerOption::Pegasus(tokenizer) => {
+                (tokenizer.decode_to_vec(&token_ids, true), false)
+            }
+            TokenizerOption::MBart50(tokenizer) => {
+                (tokenizer.decode_to_vec(&token_ids, true), false)
+            }
+            TokenizerOption::M2M100(tokenizer) => {
+                (tokenizer.decode_to_vec(&token_ids, true), false)
+            }
+            TokenizerOption::Reformer(tokenizer) => {
+                (tokenizer.decode_to_vec(&token_ids, true), false)
+            }
+            TokenizerOption::DebertaV2(tokenizer) => {
+                (tokenizer.decode_to_vec(&token_ids, true), false)
+            }
+            TokenizerOption::FNet(tokenizer) => (tokenizer.decode_to_vec(&token_ids, true), false),
+            _ => return tokenizer.decode(&token_ids, true, false).into_bytes(),
+        };
+        //    Ids outside of the vocabulary of the tokenizer are decoded to the unknown token
+        match tokens.first() {
+            Some(token) if tokenizer.convert_tokens_to_ids(&[token]) == token_ids => {
+                if byte_level {
+                    byte_level_token_bytes(token)
+                } else {
+                    sentence_piece_token_bytes(token)
+                }
+            }
+            _ => vec![],
+        }
+    };
+    (0..vocab_size).map(token_bytes).collect()
+}
+
+/// Bytes of a byte-level BPE token, whose characters each stand for a byte: the printable
+/// characters of the first 256 code points stand for themselves, the others are shifted after 255
+fn byte_level_token_bytes(token: &str) -> Vec<u8> {
+    let is_printable = |byte: u32| matches!(byte, 0x21..=0x7E | 0xA1..=0xAC | 0xAE..=0xFF);
+    token
+        .chars()
+        .filter_map(|character| {
+            let code_point = character as u32;
+            if is_printable(code_point) {
+                Some(code_point as u8)
+            } else {
+                (0..=255)
+                    .filter(|byte| !is_printable(*byte))
+                    .nth(code_point.checked_sub(256)? as usize)
+                    .map(|byte| byte as u8)
+            }
+        })
+        .collect()
+}
+
+/// Bytes of a SentencePiece token: the `▁` marker stands for a space, and byte fallback tokens
+/// (e.g. `<0x0A>`) for a single byte
+fn sentence_piece_token_bytes(token: &str) -> Vec<u8> {
+    match token
+        .strip_prefix("<0x")
+        .and_then(|token| token.strip_suffix('>'))
+        .filter(|byte| byte.len() == 2)
+        .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+    {
+        Some(byte) => vec![byte],
+        None => token.replace('\u{2581}', " ").into_bytes(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::RustBertError;
+
+    #[test]
+    fn grammar_token_masking() -> Result<(), RustBertError> {
+        let grammar = Grammar::from_ebnf(r#"root ::= "{\"ok\": " ("true" | "false") "}""#)?;
+        let tokens = [
+            "", "{\"", "ok", "\":", " true", " false", "}", " ", "tr", "ue", "x",
+        ]
+        .iter()
+        .map(|token| token.to_string())
+        .collect();
+        let constraint = GrammarConstraint::from_token_texts(grammar, tokens);
+        let eos = [0];
+
+        assert_eq!(constraint.next_tokens(&[], &eos), Some(vec![1]));
+        assert_eq!(constraint.next_tokens(&[1, 2], &eos), Some(vec![3]));
+        assert_eq!(
+            constraint.next_tokens(&[1, 2, 3], &eos),
+            Some(vec![4, 5, 7])
+        );
+        assert_eq!(constraint.next_tokens(&[1, 2, 3, 7], &eos), Some(vec![8]));
+        assert_eq!(
+            constraint.next_tokens(&[1, 2, 3, 7, 8], &eos),
+            Some(vec![9])
+        );
+        assert_eq!(constraint.next_tokens(&[1, 2, 3, 4], &eos), Some(vec![6]));
+        assert_eq!(
+            constraint.next_tokens(&[1, 2, 3, 4, 6], &eos),
+            Some(vec![0])
+        );
+        //    Finished or invalid sequences are not constrained
+        assert_eq!(constraint.next_tokens(&[1, 2, 3, 4, 6, 0, 0], &eos), None);
+        assert_eq!(constraint.next_tokens(&[10], &eos), None);
+
+        let mut scores = Tensor::zeros(&[1, 11], (tch::Kind::Float, tch::Device::Cpu));
+        let input_ids = Tensor::of_slice(&[42i64, 1, 2]).view((1, 3));
+        constraint.process(&input_ids, 1, Some(&eos.to_vec()), &mut scores);
+        let scores = Vec::<f32>::from(scores.view(-1));
+        assert_eq!(scores[3], 0.0);
+        assert_eq!(scores.iter().filter(|score| score.is_finite()).count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn multi_byte_characters() -> Result<(), RustBertError> {
+        let grammar = Grammar::from_ebnf(r#"root ::= "é"+"#)?;
+        //    Byte-level tokens of "é" (0xC3 0xA9) and "è" (0xC3 0xA8)
+        let tokens = vec![
+            vec![],
+            vec![0xC3],
+            vec![0xA9],
+            b"a".to_vec(),
+            vec![0xC3, 0xA9],
+            vec![0xC3, 0xA8],
+        ];
+        let constraint = GrammarConstraint::from_token_bytes(grammar, tokens);
+        let eos = [0];
+
+        assert_eq!(constraint.next_tokens(&[], &eos), Some(vec![1, 4]));
+        //    The first byte of a character is buffered until the character is complete
+        assert_eq!(constraint.next_tokens(&[1], &eos), Some(vec![2]));
+        assert_eq!(constraint.next_tokens(&[1, 2], &eos), Some(vec![1, 4, 0]));
+        assert_eq!(constraint.next_tokens(&[4, 1], &eos), Some(vec![2]));
+        assert_eq!(constraint.next_tokens(&[5], &eos), None);
+        Ok(())
+    }
+
+    #[test]
+    fn token_bytes() {
+        assert_eq!(byte_level_token_bytes("Ġcat"), b" cat".to_vec());
+        assert_eq!(byte_level_token_bytes("Ã©"), vec![0xC3, 0xA9]);
+        assert_eq!(byte_level_token_bytes("Ċ"), b"\n".to_vec());
+        assert_eq!(sentence_piece_token_bytes("▁the"), b" the".to_vec());
+        assert_eq!(sentence_piece_token_bytes("<0x0A>"), b"\n".to_vec());
+        assert_eq!(sentence_piece_token_bytes("<0x0A"), b"<0x0A".to_vec());
+
+        assert_eq!(
+            incomplete_character_range(&[0xC3]),
+            Some(('\u{C0}', '\u{FF}'))
+        );
+        assert_eq!(
+            incomplete_character_range(&[0xE2, 0x82]),
+            Some(('\u{2080}', '\u{20BF}'))
+        );
+        assert_eq!(
+            incomplete_character_range(&[0xED]),
+            Some(('\u{D000}', '\u{D7FF}'))
+        );
+        assert_eq!(incomplete_character_range(&[0xA9]), None);
+        assert_eq!(incomplete_character_range(&[0xC3, 0xA9]), None);
+    }
+}
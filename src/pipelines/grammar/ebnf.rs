@@ -0,0 +1,546 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use crate::RustBertError;
+
+/// Element of an alternative of a grammar rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Element {
+    /// Single character within one of the (inclusive) ranges, or outside of all of them if negated
+    Characters {
+        ranges: Vec<(char, char)>,
+        negated: bool,
+    },
+    /// Reference to the rule with the given index
+    Rule(usize),
+}
+
+impl Element {
+    pub(crate) fn matches(&self, character: char) -> bool {
+        match self {
+            Element::Characters { ranges, negated } => {
+                ranges
+                    .iter()
+                    .any(|(start, end)| (*start <= character) & (character <= *end))
+                    != *negated
+            }
+            Element::Rule(_) => false,
+        }
+    }
+
+    /// Returns true if at least one character of the (inclusive) range `start..=end` matches
+    pub(crate) fn matches_range(&self, start: char, end: char) -> bool {
+        match self {
+            Element::Characters {
+                ranges,
+                negated: false,
+            } => ranges
+                .iter()
+                .any(|(range_start, range_end)| (*range_start <= end) & (start <= *range_end)),
+            Element::Characters {
+                ranges,
+                negated: true,
+            } => {
+                //    Matches unless the ranges cover all the characters from `start` to `end`
+                let mut ranges = ranges.clone();
+                ranges.sort();
+                let mut next = start as u32;
+                for (range_start, range_end) in ranges {
+                    if range_start as u32 > next {
+                        break;
+                    }
+                    next = next.max(range_end as u32 + 1);
+                }
+                next <= end as u32
+            }
+            Element::Rule(_) => false,
+        }
+    }
+}
+
+/// Grammar rule: a list of alternatives, each made of a sequence of elements
+#[derive(Debug, Clone)]
+pub(crate) struct Rule {
+    pub(crate) name: String,
+    pub(crate) alternatives: Vec<Vec<Element>>,
+}
+
+#[derive(Debug, Clone)]
+/// # Context-free grammar used to constrain the generated text
+/// Created from a grammar in EBNF notation (`Grammar::from_ebnf`) or from a JSON schema
+/// (`Grammar::from_json_schema`). Repetitions and groups are compiled into auxiliary rules, and
+/// literals into sequences of single characters, so that the grammar can be matched one
+/// character at a time.
+pub struct Grammar {
+    pub(crate) rules: Vec<Rule>,
+    pub(crate) root: usize,
+}
+
+impl Grammar {
+    /// Parses a grammar in EBNF notation. Each rule is written `name ::= expression` and the
+    /// generated text must match the rule named `root`. Expressions are made of:
+    /// - literals between double quotes: `"true"`
+    /// - character classes, optionally negated: `[0-9a-f]`, `[^"\\]`
+    /// - `.` matching any character
+    /// - references to other rules by name (letters, digits, `-` and `_`)
+    /// - groups `( ... )`, alternatives `a | b` and the repetition operators `*`, `+` and `?`
+    ///
+    /// Literals and character classes support the escapes `\n`, `\r`, `\t`, `\\`, `\"`, `\[`,
+    /// `\]`, `\-`, `\^`, `\xHH` and `\uHHHH`. Comments start with `#` and run until the end of
+    /// the line. Left-recursive rules (e.g. `list ::= list "," item | item`) are not supported
+    /// and must be written with repetitions instead (`list ::= item ("," item)*`).
+    ///
+    /// # Arguments
+    ///
+    /// * `grammar` - grammar in EBNF notation
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Grammar, RustBertError>` - compiled grammar, error if the grammar is invalid
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::grammar::Grammar;
+    ///
+    /// let grammar = Grammar::from_ebnf(
+    ///     r#"
+    ///     root ::= "{\"answer\": " answer "}"
+    ///     answer ::= "\"yes\"" | "\"no\""
+    ///     "#,
+    /// )?;
+    /// assert!(grammar.matches(r#"{"answer": "yes"}"#));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_ebnf(grammar: &str) -> Result<Grammar, RustBertError> {
+        let mut parser = Parser::new(grammar);
+        parser.parse()?;
+        parser.into_grammar()
+    }
+
+    pub(crate) fn element(&self, rule: usize, alternative: usize, element: usize) -> &Element {
+        &self.rules[rule].alternatives[alternative][element]
+    }
+
+    pub(crate) fn alternative_length(&self, rule: usize, alternative: usize) -> usize {
+        self.rules[rule].alternatives[alternative].len()
+    }
+
+    fn nullable_rules(&self) -> Vec<bool> {
+        let mut nullable = vec![false; self.rules.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (index, rule) in self.rules.iter().enumerate() {
+                if !nullable[index]
+                    && rule.alternatives.iter().any(|alternative| {
+                        alternative.iter().all(|element| match element {
+                            Element::Rule(rule) => nullable[*rule],
+                            Element::Characters { .. } => false,
+                        })
+                    })
+                {
+                    nullable[index] = true;
+                    changed = true;
+                }
+            }
+        }
+        nullable
+    }
+
+    /// Left recursion would expand rules indefinitely without consuming any character
+    fn check_left_recursion(&self) -> Result<(), RustBertError> {
+        let nullable = self.nullable_rules();
+        //    Rules that can be expanded from a rule before matching any character
+        let leftmost_references = self
+            .rules
+            .iter()
+            .map(|rule| {
+                let mut references = Vec::new();
+                for alternative in &rule.alternatives {
+                    for element in alternative {
+                        match element {
+                            Element::Rule(reference) => {
+                                references.push(*reference);
+                                if !nullable[*reference] {
+                                    break;
+                                }
+                            }
+                            Element::Characters { .. } => break,
+                        }
+                    }
+                }
+                references
+            })
+            .collect::<Vec<Vec<usize>>>();
+
+        let mut visit_states = vec![VisitState::Unvisited; self.rules.len()];
+        for rule in 0..self.rules.len() {
+            if let Some(rule) = find_cycle(rule, &leftmost_references, &mut visit_states) {
+                return Err(RustBertError::ValueError(format!(
+                    "Rule `{}` of the grammar is left-recursive, which is not supported",
+                    self.rules[rule].name
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VisitState {
+    Unvisited,
+    InProgress,
+    Done,
+}
+
+fn find_cycle(rule: usize, edges: &[Vec<usize>], states: &mut [VisitState]) -> Option<usize> {
+    match states[rule] {
+        VisitState::InProgress => return Some(rule),
+        VisitState::Done => return None,
+        VisitState::Unvisited => {}
+    }
+    states[rule] = VisitState::InProgress;
+    for &next in &edges[rule] {
+        if let Some(cycle) = find_cycle(next, edges, states) {
+            return Some(cycle);
+        }
+    }
+    states[rule] = VisitState::Done;
+    None
+}
+
+fn is_name_character(character: char) -> bool {
+    character.is_ascii_alphanumeric() | (character == '-') | (character == '_')
+}
+
+struct Parser {
+    characters: Vec<char>,
+    position: usize,
+    rules: Vec<Rule>,
+    defined: Vec<bool>,
+    rule_indices: HashMap<String, usize>,
+}
+
+impl Parser {
+    fn new(grammar: &str) -> Parser {
+        Parser {
+            characters: grammar.chars().collect(),
+            position: 0,
+            rules: Vec::new(),
+            defined: Vec::new(),
+            rule_indices: HashMap::new(),
+        }
+    }
+
+    fn into_grammar(self) -> Result<Grammar, RustBertError> {
+        if let Some(index) = self.defined.iter().position(|defined| !defined) {
+            return Err(RustBertError::ValueError(format!(
+                "Rule `{}` is used but not defined in the grammar",
+                self.rules[index].name
+            )));
+        }
+        let root = *self.rule_indices.get("root").ok_or_else(|| {
+            RustBertError::ValueError("The grammar does not define a `root` rule".to_string())
+        })?;
+        let grammar = Grammar {
+            rules: self.rules,
+            root,
+        };
+        grammar.check_left_recursion()?;
+        Ok(grammar)
+    }
+
+    fn error(&self, message: &str) -> RustBertError {
+        RustBertError::ValueError(format!(
+            "Invalid grammar at character {}: {}",
+            self.position, message
+        ))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.characters.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Result<char, RustBertError> {
+        let character = self
+            .peek()
+            .ok_or_else(|| self.error("unexpected end of the grammar"))?;
+        self.position += 1;
+        Ok(character)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), RustBertError> {
+        for expected_character in expected.chars() {
+            if self.peek() != Some(expected_character) {
+                return Err(self.error(&format!("expected `{}`", expected)));
+            }
+            self.position += 1;
+        }
+        Ok(())
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(character) = self.peek() {
+            if character == '#' {
+                while !matches!(self.peek(), None | Some('\n')) {
+                    self.position += 1;
+                }
+            } else if character.is_whitespace() {
+                self.position += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn parse(&mut self) -> Result<(), RustBertError> {
+        self.skip_whitespace();
+        while self.peek().is_some() {
+            let name = self.parse_name()?;
+            self.skip_whitespace();
+            self.expect("::=")?;
+            let index = self.rule_index(&name);
+            if self.defined[index] {
+                return Err(self.error(&format!("rule `{}` is defined more than once", name)));
+            }
+            self.defined[index] = true;
+            let alternatives = self.parse_alternatives(&name)?;
+            self.rules[index].alternatives = alternatives;
+            self.skip_whitespace();
+        }
+        Ok(())
+    }
+
+    fn parse_name(&mut self) -> Result<String, RustBertError> {
+        let start = self.position;
+        while self.peek().map_or(false, is_name_character) {
+            self.position += 1;
+        }
+        if start == self.position {
+            return Err(self.error("expected a rule name"));
+        }
+        Ok(self.characters[start..self.position].iter().collect())
+    }
+
+    /// Checks if the parser is at the start of a new rule definition (`name ::=`)
+    fn at_rule_definition(&mut self) -> bool {
+        let start = self.position;
+        let is_definition = self.parse_name().is_ok() && {
+            self.skip_whitespace();
+            self.characters[self.position..].starts_with(&[':', ':', '='])
+        };
+        self.position = start;
+        is_definition
+    }
+
+    fn rule_index(&mut self, name: &str) -> usize {
+        if let Some(index) = self.rule_indices.get(name) {
+            return *index;
+        }
+        let index = self.rules.len();
+        self.rules.push(Rule {
+            name: name.to_string(),
+            alternatives: Vec::new(),
+        });
+        self.defined.push(false);
+        self.rule_indices.insert(name.to_string(), index);
+        index
+    }
+
+    /// Adds an auxiliary rule for a group or a repetition of the rule `parent`
+    fn add_rule(&mut self, parent: &str, alternatives: Vec<Vec<Element>>) -> usize {
+        let index = self.rules.len();
+        self.rules.push(Rule {
+            name: format!("{}#{}", parent, index),
+            alternatives,
+        });
+        self.defined.push(true);
+        index
+    }
+
+    /// `item*` is compiled into `rule ::= item rule | ` (right recursion)
+    fn add_repetition(&mut self, parent: &str, mut item: Vec<Element>) -> usize {
+        let index = self.add_rule(parent, Vec::new());
+        item.push(Element::Rule(index));
+        self.rules[index].alternatives = vec![item, Vec::new()];
+        index
+    }
+
+    fn parse_alternatives(&mut self, rule_name: &str) -> Result<Vec<Vec<Element>>, RustBertError> {
+        let mut alternatives = vec![self.parse_sequence(rule_name)?];
+        self.skip_whitespace();
+        while self.peek() == Some('|') {
+            self.position += 1;
+            alternatives.push(self.parse_sequence(rule_name)?);
+            self.skip_whitespace();
+        }
+        Ok(alternatives)
+    }
+
+    fn parse_sequence(&mut self, rule_name: &str) -> Result<Vec<Element>, RustBertError> {
+        let mut sequence = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let mut item = match self.peek() {
+                None | Some('|') | Some(')') => break,
+                Some('"') => self.parse_literal()?,
+                Some('[') => vec![self.parse_character_class()?],
+                Some('.') => {
+                    self.position += 1;
+                    vec![Element::Characters {
+                        ranges: Vec::new(),
+                        negated: true,
+                    }]
+                }
+                Some('(') => {
+                    self.position += 1;
+                    let alternatives = self.parse_alternatives(rule_name)?;
+                    self.expect(")")?;
+                    vec![Element::Rule(self.add_rule(rule_name, alternatives))]
+                }
+                Some(character) if is_name_character(character) => {
+                    if self.at_rule_definition() {
+                        break;
+                    }
+                    let name = self.parse_name()?;
+                    vec![Element::Rule(self.rule_index(&name))]
+                }
+                Some(character) => {
+                    return Err(self.error(&format!("unexpected character `{}`", character)));
+                }
+            };
+            loop {
+                self.skip_whitespace();
+                item = match self.peek() {
+                    Some('*') => vec![Element::Rule(self.add_repetition(rule_name, item))],
+                    Some('+') => {
+                        let repetition = self.add_repetition(rule_name, item.clone());
+                        item.push(Element::Rule(repetition));
+                        item
+                    }
+                    Some('?') => vec![Element::Rule(
+                        self.add_rule(rule_name, vec![item, Vec::new()]),
+                    )],
+                    _ => break,
+                };
+                self.position += 1;
+            }
+            sequence.extend(item);
+        }
+        Ok(sequence)
+    }
+
+    fn parse_literal(&mut self) -> Result<Vec<Element>, RustBertError> {
+        self.expect("\"")?;
+        let mut elements = Vec::new();
+        loop {
+            let character = match self.next()? {
+                '"' => break,
+                '\\' => self.parse_escape()?,
+                character => character,
+            };
+            elements.push(Element::Characters {
+                ranges: vec![(character, character)],
+                negated: false,
+            });
+        }
+        Ok(elements)
+    }
+
+    fn parse_character_class(&mut self) -> Result<Element, RustBertError> {
+        self.expect("[")?;
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.position += 1;
+        }
+        let mut ranges = Vec::new();
+        loop {
+            let start = match self.next()? {
+                ']' => break,
+                '\\' => self.parse_escape()?,
+                character => character,
+            };
+            let is_range = (self.peek() == Some('-'))
+                & !matches!(self.characters.get(self.position + 1), None | Some(']'));
+            let end = if is_range {
+                self.position += 1;
+                match self.next()? {
+                    '\\' => self.parse_escape()?,
+                    character => character,
+                }
+            } else {
+                start
+            };
+            if start > end {
+                return Err(self.error(&format!("invalid character range `{}-{}`", start, end)));
+            }
+            ranges.push((start, end));
+        }
+        Ok(Element::Characters { ranges, negated })
+    }
+
+    fn parse_escape(&mut self) -> Result<char, RustBertError> {
+        Ok(match self.next()? {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'x' => self.parse_code_point(2)?,
+            'u' => self.parse_code_point(4)?,
+            character @ ('\\' | '"' | '[' | ']' | '-' | '^') => character,
+            character => return Err(self.error(&format!("invalid escape `\\{}`", character))),
+        })
+    }
+
+    fn parse_code_point(&mut self, num_digits: usize) -> Result<char, RustBertError> {
+        let mut code_point = 0u32;
+        for _ in 0..num_digits {
+            let digit = self.next()?;
+            code_point = code_point * 16
+                + digit
+                    .to_digit(16)
+                    .ok_or_else(|| self.error(&format!("invalid hexadecimal digit `{}`", digit)))?;
+        }
+        char::from_u32(code_point)
+            .ok_or_else(|| self.error(&format!("invalid code point {:#x}", code_point)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_grammar() -> Result<(), RustBertError> {
+        let grammar = Grammar::from_ebnf(
+            r#"
+            # Comma-separated list of numbers
+            root ::= "[" (number ("," number)*)? "]"
+            number ::= "-"? [0-9]+
+            "#,
+        )?;
+        assert_eq!(grammar.rules[grammar.root].name, "root");
+        assert!(grammar.rules.iter().any(|rule| rule.name == "number"));
+
+        assert!(Grammar::from_ebnf(r#"value ::= "a""#).is_err());
+        assert!(Grammar::from_ebnf(r#"root ::= item"#).is_err());
+        assert!(Grammar::from_ebnf(r#"root ::= "a" root ::= "b""#).is_err());
+        assert!(Grammar::from_ebnf(r#"root ::= "unterminated"#).is_err());
+        assert!(Grammar::from_ebnf(r#"root ::= [z-a]"#).is_err());
+        assert!(Grammar::from_ebnf(r#"root ::= root "a" | "b""#).is_err());
+        assert!(Grammar::from_ebnf(r#"root ::= "a"? root "b" | "c""#).is_err());
+        Ok(())
+    }
+}
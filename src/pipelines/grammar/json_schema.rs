@@ -0,0 +1,369 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashSet;
+
+use serde_json::Value;
+
+use crate::pipelines::grammar::ebnf::Grammar;
+use crate::RustBertError;
+
+/// Rules shared by all grammars compiled from JSON schemas. Whitespace is limited to a single
+/// optional space between tokens, so that the model can not stall by generating whitespace.
+const JSON_RULES: &str = r#"
+ws ::= [ ]?
+value ::= object | array | string | number | boolean | null
+object ::= "{" ws (member ("," ws member)*)? "}"
+member ::= string ws ":" ws value ws
+array ::= "[" ws (value ws ("," ws value ws)*)? "]"
+string ::= "\"" character* "\""
+character ::= [^"\\\x00-\x1f] | "\\" (["\\/bfnrt] | "u" [0-9a-fA-F] [0-9a-fA-F] [0-9a-fA-F] [0-9a-fA-F])
+integer ::= "-"? ("0" | [1-9] [0-9]*)
+number ::= integer ("." [0-9]+)? ([eE] [-+]? [0-9]+)?
+boolean ::= "true" | "false"
+null ::= "null"
+"#;
+
+impl Grammar {
+    /// Grammar of any JSON value (optionally preceded by a space)
+    pub fn json() -> Grammar {
+        Grammar::from_ebnf(&format!("root ::= ws value\n{}", JSON_RULES))
+            .expect("The JSON grammar is valid")
+    }
+
+    /// Compiles a JSON schema into a grammar matching the JSON documents valid against the
+    /// schema (see `json_schema_to_ebnf` for the supported keywords).
+    ///
+    /// # Arguments
+    ///
+    /// * `schema` - JSON schema
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Grammar, RustBertError>` - compiled grammar, error if the schema uses unsupported keywords
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::grammar::Grammar;
+    /// use serde_json::json;
+    ///
+    /// let grammar = Grammar::from_json_schema(&json!({
+    ///     "type": "object",
+    ///     "properties": {
+    ///         "name": {"type": "string"},
+    ///         "age": {"type": "integer"}
+    ///     },
+    ///     "required": ["name"]
+    /// }))?;
+    /// assert!(grammar.matches(r#"{"age": 42, "name": "Ada"}"#));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_json_schema(schema: &Value) -> Result<Grammar, RustBertError> {
+        Grammar::from_ebnf(&json_schema_to_ebnf(schema)?)
+    }
+}
+
+/// Converts a JSON schema into a grammar in EBNF notation, for example to extend it before
+/// compiling it with `Grammar::from_ebnf`. The following keywords are supported:
+/// - `type` (a single type or a list of types): `object`, `array`, `string`, `number`,
+///   `integer`, `boolean` and `null`
+/// - `properties` and `required` for objects. Properties are generated in the order of the schema
+///   object (alphabetical unless the `preserve_order` feature of `serde_json` is enabled), and
+///   properties not listed in `properties` are not allowed
+/// - `items` for arrays
+/// - `enum` and `const`
+/// - `anyOf` and `oneOf` (both are treated as `anyOf`)
+///
+/// Other keywords (e.g. `minLength`, `pattern` or `maximum`) are ignored, and references
+/// (`$ref`) are not supported. Schemas without a type (e.g. `{}`) match any JSON value.
+///
+/// # Arguments
+///
+/// * `schema` - JSON schema
+///
+/// # Returns
+///
+/// * `Result<String, RustBertError>` - grammar in EBNF notation, with the root rule `root`
+pub fn json_schema_to_ebnf(schema: &Value) -> Result<String, RustBertError> {
+    let mut compiler = SchemaCompiler::default();
+    let root = compiler.compile(schema, "root")?;
+    let mut grammar = format!("root ::= ws {}\n", root);
+    for rule in compiler.rules {
+        grammar.push_str(&rule);
+        grammar.push('\n');
+    }
+    grammar.push_str(JSON_RULES);
+    Ok(grammar)
+}
+
+/// Escapes a text into an EBNF literal
+fn literal(text: &str) -> String {
+    let mut output = String::with_capacity(text.len() + 2);
+    output.push('"');
+    for character in text.chars() {
+        match character {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            character if (character as u32) < 0x20 => {
+                output.push_str(&format!("\\x{:02x}", character as u32))
+            }
+            character => output.push(character),
+        }
+    }
+    output.push('"');
+    output
+}
+
+/// Literal matching the serialization of a JSON value
+fn json_literal(value: &Value) -> String {
+    literal(&value.to_string())
+}
+
+#[derive(Default)]
+struct SchemaCompiler {
+    rules: Vec<String>,
+}
+
+impl SchemaCompiler {
+    fn add_rule(&mut self, name: &str, expression: &str) -> String {
+        let name = format!("{}-{}", name, self.rules.len());
+        self.rules.push(format!("{} ::= {}", name, expression));
+        name
+    }
+
+    /// Returns an expression matching the schema, adding the rules it requires
+    fn compile(&mut self, schema: &Value, name: &str) -> Result<String, RustBertError> {
+        let schema = match schema {
+            Value::Bool(true) => return Ok("value".to_string()),
+            Value::Object(schema) => schema,
+            _ => {
+                return Err(RustBertError::ValueError(format!(
+                    "Unsupported JSON schema: {}",
+                    schema
+                )));
+            }
+        };
+        if schema.contains_key("$ref") {
+            return Err(RustBertError::ValueError(
+                "References (`$ref`) are not supported in JSON schemas used for constrained generation"
+                    .to_string(),
+            ));
+        }
+        if let Some(value) = schema.get("const") {
+            return Ok(json_literal(value));
+        }
+        if let Some(values) = schema.get("enum") {
+            let values = values.as_array().ok_or_else(|| {
+                RustBertError::ValueError("`enum` must be a list of values".to_string())
+            })?;
+            return Ok(format!(
+                "({})",
+                values
+                    .iter()
+                    .map(json_literal)
+                    .collect::<Vec<String>>()
+                    .join(" | ")
+            ));
+        }
+        if let Some(schemas) = schema.get("anyOf").or_else(|| schema.get("oneOf")) {
+            let schemas = schemas.as_array().ok_or_else(|| {
+                RustBertError::ValueError(
+                    "`anyOf` and `oneOf` must be lists of schemas".to_string(),
+                )
+            })?;
+            return self.compile_alternatives(schemas.iter(), name);
+        }
+        match schema.get("type") {
+            Some(Value::String(schema_type)) => {
+                self.compile_type(schema_type, &Value::Object(schema.clone()), name)
+            }
+            Some(Value::Array(schema_types)) => {
+                let mut alternatives = Vec::with_capacity(schema_types.len());
+                for schema_type in schema_types {
+                    let schema_type = schema_type.as_str().ok_or_else(|| {
+                        RustBertError::ValueError(format!("Invalid schema type {}", schema_type))
+                    })?;
+                    alternatives.push(self.compile_type(
+                        schema_type,
+                        &Value::Object(schema.clone()),
+                        name,
+                    )?);
+                }
+                Ok(format!("({})", alternatives.join(" | ")))
+            }
+            Some(schema_type) => Err(RustBertError::ValueError(format!(
+                "Invalid schema type {}",
+                schema_type
+            ))),
+            None if schema.contains_key("properties") => {
+                self.compile_type("object", &Value::Object(schema.clone()), name)
+            }
+            None => Ok("value".to_string()),
+        }
+    }
+
+    fn compile_alternatives<'a>(
+        &mut self,
+        schemas: impl Iterator<Item = &'a Value>,
+        name: &str,
+    ) -> Result<String, RustBertError> {
+        let mut alternatives = Vec::new();
+        for schema in schemas {
+            alternatives.push(self.compile(schema, name)?);
+        }
+        Ok(format!("({})", alternatives.join(" | ")))
+    }
+
+    fn compile_type(
+        &mut self,
+        schema_type: &str,
+        schema: &Value,
+        name: &str,
+    ) -> Result<String, RustBertError> {
+        Ok(match schema_type {
+            "string" | "number" | "integer" | "boolean" | "null" => schema_type.to_string(),
+            "array" => match schema.get("items") {
+                Some(items) => {
+                    let item = self.compile(items, &format!("{}-item", name))?;
+                    self.add_rule(
+                        name,
+                        &format!(
+                            r#""[" ws ({item} ws ("," ws {item} ws)*)? "]""#,
+                            item = item
+                        ),
+                    )
+                }
+                None => "array".to_string(),
+            },
+            "object" => match schema.get("properties").and_then(Value::as_object) {
+                Some(properties) => {
+                    let required = schema
+                        .get("required")
+                        .and_then(Value::as_array)
+                        .map(|required| {
+                            required
+                                .iter()
+                                .filter_map(Value::as_str)
+                                .collect::<HashSet<&str>>()
+                        })
+                        .unwrap_or_default();
+                    let mut members = Vec::with_capacity(properties.len());
+                    for (key, property) in properties {
+                        let value =
+                            self.compile(property, &format!("{}-{}", name, rule_name(key)))?;
+                        members.push((
+                            format!(
+                                r#"{} ws ":" ws {} ws"#,
+                                json_literal(&Value::String(key.clone())),
+                                value
+                            ),
+                            required.contains(key.as_str()),
+                        ));
+                    }
+                    let members = self.compile_members(&members, name);
+                    self.add_rule(name, &format!(r#""{{" ws {} "}}""#, members))
+                }
+                None => "object".to_string(),
+            },
+            _ => {
+                return Err(RustBertError::ValueError(format!(
+                    "Invalid schema type `{}`",
+                    schema_type
+                )));
+            }
+        })
+    }
+
+    /// Members of an object, in order, where optional members may be skipped. Two rules are
+    /// created for each member: one for the members following it when no member was generated
+    /// yet (no leading comma), one when a member was already generated (leading comma).
+    fn compile_members(&mut self, members: &[(String, bool)], name: &str) -> String {
+        let mut first = String::new();
+        let mut next = String::new();
+        for (member, required) in members.iter().rev() {
+            let (new_first, new_next) = if *required {
+                (
+                    format!("{} {}", member, next),
+                    format!(r#""," ws {} {}"#, member, next),
+                )
+            } else {
+                (
+                    format!("{} {} | {}", member, next, first),
+                    format!(r#""," ws {} {} | {}"#, member, next, next),
+                )
+            };
+            first = self.add_rule(&format!("{}-first", name), &new_first);
+            next = self.add_rule(&format!("{}-next", name), &new_next);
+        }
+        first
+    }
+}
+
+/// Rule name fragment for a property name
+fn rule_name(key: &str) -> String {
+    key.chars()
+        .map(|character| {
+            if character.is_ascii_alphanumeric() {
+                character
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn compile_json_schema() -> Result<(), RustBertError> {
+        let grammar = Grammar::json();
+        assert!(grammar.matches(r#" {"a": [1, 2.5e3, "x\"y"], "b": {"c": null}}"#));
+        assert!(!grammar.matches(r#"{"a": }"#));
+
+        let grammar = Grammar::from_json_schema(&json!({
+            "type": "object",
+            "properties": {
+                "age": {"type": ["integer", "null"]},
+                "name": {"type": "string"},
+                "tags": {"type": "array", "items": {"enum": ["a", "b"]}},
+            },
+            "required": ["name"]
+        }))?;
+        assert!(grammar.matches(r#"{"name": "Ada"}"#));
+        assert!(grammar.matches(r#"{"age": null, "name": "Ada", "tags": ["a", "b"]}"#));
+        assert!(grammar.matches(r#"{"name": "Ada", "tags": []}"#));
+        assert!(!grammar.matches(r#"{}"#));
+        assert!(!grammar.matches(r#"{"age": 1.5, "name": "Ada"}"#));
+        assert!(!grammar.matches(r#"{"name": "Ada", "tags": ["c"]}"#));
+        assert!(!grammar.matches(r#"{"name": "Ada", "other": 1}"#));
+
+        let grammar = Grammar::from_json_schema(&json!({
+            "properties": {"x": {"const": 1}, "y": {"anyOf": [{"type": "boolean"}, {}]}}
+        }))?;
+        assert!(grammar.matches(r#"{}"#));
+        assert!(grammar.matches(r#"{"y": [true]}"#));
+        assert!(grammar.matches(r#"{"x": 1, "y": false}"#));
+        assert!(!grammar.matches(r#"{"x": 2}"#));
+
+        assert!(Grammar::from_json_schema(&json!({"$ref": "#/definitions/a"})).is_err());
+        assert!(Grammar::from_json_schema(&json!({"type": "date"})).is_err());
+        Ok(())
+    }
+}
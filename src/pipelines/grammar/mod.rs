@@ -0,0 +1,57 @@
+//! # Grammar-constrained generation
+//!
+//! Forces language generators to produce text matching a grammar, for example a JSON document
+//! valid against a JSON schema. At each decoding step, the tokens whose text can not extend the
+//! generated text into a match of the grammar are masked, and the end of sequence token is only
+//! allowed once the generated text is a complete match. The constraint applies to greedy
+//! decoding, sampling and beam search.
+//!
+//! Grammars are written in EBNF notation (`Grammar::from_ebnf`) or compiled from a JSON schema
+//! (`Grammar::from_json_schema`), and matched one character at a time by a pushdown automaton.
+//! Grammars without recursion, such as the grammars compiled from JSON schemas, only reach a
+//! finite number of states: the tokens allowed from each state are computed once and cached by
+//! the `GrammarConstraint`.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::gpt2::GPT2Generator;
+//! use rust_bert::pipelines::generation_utils::{GenerateOptions, LanguageGenerator};
+//! use rust_bert::pipelines::grammar::Grammar;
+//! use serde_json::json;
+//!
+//! let generator = GPT2Generator::new(Default::default())?;
+//! let grammar = Grammar::from_json_schema(&json!({
+//!     "type": "object",
+//!     "properties": {
+//!         "city": {"type": "string"},
+//!         "population": {"type": "integer"}
+//!     },
+//!     "required": ["city", "population"]
+//! }))?;
+//! let constraint = generator.grammar_constraint(grammar);
+//!
+//! let generate_options = GenerateOptions {
+//!     max_new_tokens: Some(32),
+//!     grammar: Some(&constraint),
+//!     ..Default::default()
+//! };
+//! let output = generator.generate(
+//!     Some(&["The largest city of France, as JSON:"]),
+//!     Some(generate_options),
+//! )?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The output is only guaranteed to be complete if the generation ends with an end of sequence
+//! token: a generation stopped by the maximum length returns a valid prefix of a match.
+
+mod automaton;
+mod constraint;
+mod ebnf;
+mod json_schema;
+
+pub use automaton::GrammarState;
+pub use constraint::GrammarConstraint;
+pub use ebnf::Grammar;
+pub use json_schema::json_schema_to_ebnf;
@@ -0,0 +1,168 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Headline generation pipeline
+//! Generates short, news-style titles for input documents. The pipeline relies on a sequence to
+//! sequence summarization model (BART, T5, ProphetNet, Pegasus), with a strict limit on the number
+//! of generated tokens and n-gram repetitions forbidden. The generated headlines are cleaned up
+//! (single line, no trailing period).
+//! By default, the dependencies for this model will be downloaded for a BART model finetuned on
+//! CNN-DailyMail. Models fine-tuned for headline generation can be loaded by overwriting the
+//! resources of the summarization configuration.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::headline_generation::{
+//!     HeadlineGenerationConfig, HeadlineGenerationModel,
+//! };
+//!
+//! let model = HeadlineGenerationModel::new(HeadlineGenerationConfig {
+//!     max_new_tokens: 16,
+//!     ..Default::default()
+//! })?;
+//!
+//! let input = ["In findings published Tuesday in Cornell University's arXiv by a team of scientists \
+//! from the University of Montreal and a separate report published Wednesday in Nature Astronomy by a team \
+//! from University College London (UCL), the presence of water vapour was confirmed in the atmosphere of K2-18b, \
+//! a planet circling a star in the constellation Leo."];
+//! let headlines = model.generate(&input)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::common::error::RustBertError;
+use crate::pipelines::summarization::{SummarizationConfig, SummarizationModel};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+/// # Configuration for HeadlineGenerationModel
+/// The length and repetition settings of the summarization configuration are overwritten by the
+/// headline constraints.
+pub struct HeadlineGenerationConfig {
+    /// `SummarizationConfig` defining the sequence to sequence model generating the headlines
+    pub summarization_config: SummarizationConfig,
+    /// Maximum number of tokens generated for a headline (default: 24)
+    pub max_new_tokens: i64,
+    /// Minimum number of tokens generated for a headline (default: 4)
+    pub min_new_tokens: i64,
+    /// Size of the n-grams that may not be repeated within a headline (default: 2)
+    pub no_repeat_ngram_size: i64,
+    /// Flag indicating if trailing punctuation should be removed from the headlines (default: true)
+    pub strip_trailing_punctuation: bool,
+}
+
+#[cfg(feature = "remote")]
+impl Default for HeadlineGenerationConfig {
+    fn default() -> HeadlineGenerationConfig {
+        HeadlineGenerationConfig {
+            summarization_config: SummarizationConfig::default(),
+            max_new_tokens: 24,
+            min_new_tokens: 4,
+            no_repeat_ngram_size: 2,
+            strip_trailing_punctuation: true,
+        }
+    }
+}
+
+/// # HeadlineGenerationModel to generate titles for documents
+pub struct HeadlineGenerationModel {
+    summarization_model: SummarizationModel,
+    strip_trailing_punctuation: bool,
+}
+
+impl HeadlineGenerationModel {
+    /// Build a new `HeadlineGenerationModel`
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `HeadlineGenerationConfig` object containing the summarization configuration and headline constraints
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::headline_generation::HeadlineGenerationModel;
+    ///
+    /// let model = HeadlineGenerationModel::new(Default::default())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new(config: HeadlineGenerationConfig) -> Result<HeadlineGenerationModel, RustBertError> {
+        if config.max_new_tokens <= 0 {
+            return Err(RustBertError::InvalidConfigurationError(
+                "max_new_tokens must be strictly greater than 0".to_string(),
+            ));
+        }
+        if config.min_new_tokens > config.max_new_tokens {
+            return Err(RustBertError::InvalidConfigurationError(format!(
+                "min_new_tokens ({}) must be lower or equal to max_new_tokens ({})",
+                config.min_new_tokens, config.max_new_tokens
+            )));
+        }
+        // The generated sequences start with the decoder start token
+        let summarization_config = SummarizationConfig {
+            min_length: config.min_new_tokens.max(0) + 1,
+            max_length: Some(config.max_new_tokens + 1),
+            no_repeat_ngram_size: config.no_repeat_ngram_size,
+            num_return_sequences: 1,
+            ..config.summarization_config
+        };
+        let summarization_model = SummarizationModel::new(summarization_config)?;
+        Ok(HeadlineGenerationModel {
+            summarization_model,
+            strip_trailing_punctuation: config.strip_trailing_punctuation,
+        })
+    }
+
+    /// Generate headlines for the texts provided
+    ///
+    /// # Arguments
+    ///
+    /// * `texts` - `&[&str]` Array of texts to generate a headline for.
+    ///
+    /// # Returns
+    /// * `Result<Vec<String>, RustBertError>` Generated headlines, one per input text
+    pub fn generate<S>(&self, texts: &[S]) -> Result<Vec<String>, RustBertError>
+    where
+        S: AsRef<str> + Sync,
+    {
+        Ok(self
+            .summarization_model
+            .summarize(texts)?
+            .iter()
+            .map(|headline| self.clean_up(headline))
+            .collect())
+    }
+
+    fn clean_up(&self, headline: &str) -> String {
+        let headline = headline.split_whitespace().collect::<Vec<&str>>().join(" ");
+        if self.strip_trailing_punctuation {
+            headline
+                .trim_end_matches(|c: char| matches!(c, '.' | ',' | ';' | ':'))
+                .trim_end()
+                .to_string()
+        } else {
+            headline
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[ignore] // no need to run, compilation is enough to verify it is Send
+    fn test() {
+        let config = HeadlineGenerationConfig::default();
+        let _: Box<dyn Send> = Box::new(HeadlineGenerationModel::new(config));
+    }
+}
@@ -0,0 +1,799 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Adapter for HuggingFace fast tokenizers
+//! Allows pipelines to use a tokenizer serialized in a single `tokenizer.json` file (as saved by the
+//! HuggingFace [tokenizers](https://github.com/huggingface/tokenizers) library) instead of the
+//! vocabulary and merges files expected by `rust_tokenizers`. Requires the `hf-tokenizers` feature.
+//!
+//! The special tokens (padding, separator, mask...) are read from an optional
+//! `special_tokens_map.json` file. If this file is not provided, they are looked up in the
+//! vocabulary among the usual BERT (e.g. `[PAD]`) and RoBERTa/T5 (e.g. `<pad>`) tokens.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::common::{ModelType, TokenizerOption};
+//! use rust_bert::pipelines::sequence_classification::SequenceClassificationModel;
+//!
+//! let tokenizer = TokenizerOption::from_hf_tokenizer_file(
+//!     ModelType::Bert,
+//!     "path/to/tokenizer.json",
+//!     Some("path/to/special_tokens_map.json"),
+//! )?;
+//! let model =
+//!     SequenceClassificationModel::new_with_tokenizer(Default::default(), tokenizer)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::pipelines::common::ModelType;
+use crate::RustBertError;
+use rust_tokenizers::tokenizer::{truncate_sequences, TruncationStrategy};
+use rust_tokenizers::{
+    Mask, Offset, OffsetSize, TokenIdsWithOffsets, TokenIdsWithSpecialTokens, TokenizedInput,
+    TokensWithOffsets,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use tokenizers::{Encoding, PostProcessor, Tokenizer};
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SpecialTokenValue {
+    Content(String),
+    AddedToken { content: String },
+}
+
+fn deserialize_special_token<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(
+        Option::<SpecialTokenValue>::deserialize(deserializer)?.map(|value| match value {
+            SpecialTokenValue::Content(content) => content,
+            SpecialTokenValue::AddedToken { content } => content,
+        }),
+    )
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+/// # Special tokens of a HuggingFace tokenizer
+/// Can be read from a `special_tokens_map.json` file, where tokens are either provided as a string
+/// or as an object with a `content` field.
+pub struct SpecialTokenMap {
+    #[serde(default, deserialize_with = "deserialize_special_token")]
+    pub unk_token: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_special_token")]
+    pub pad_token: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_special_token")]
+    pub bos_token: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_special_token")]
+    pub sep_token: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_special_token")]
+    pub cls_token: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_special_token")]
+    pub eos_token: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_special_token")]
+    pub mask_token: Option<String>,
+}
+
+impl SpecialTokenMap {
+    /// Reads the special tokens from a `special_tokens_map.json` file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<SpecialTokenMap, RustBertError> {
+        let f = File::open(path)?;
+        let br = BufReader::new(f);
+        Ok(serde_json::from_reader(br)?)
+    }
+
+    fn from_vocabulary(tokenizer: &Tokenizer) -> SpecialTokenMap {
+        let find_token = |candidates: &[&str]| {
+            candidates
+                .iter()
+                .find(|token| tokenizer.token_to_id(token).is_some())
+                .map(|token| token.to_string())
+        };
+        SpecialTokenMap {
+            unk_token: find_token(&["[UNK]", "<unk>", "<|endoftext|>"]),
+            pad_token: find_token(&["[PAD]", "<pad>"]),
+            bos_token: find_token(&["<s>", "<|endoftext|>"]),
+            sep_token: find_token(&["[SEP]", "</s>"]),
+            cls_token: find_token(&["[CLS]", "<s>"]),
+            eos_token: find_token(&["</s>", "<|endoftext|>"]),
+            mask_token: find_token(&["[MASK]", "<mask>"]),
+        }
+    }
+}
+
+/// # HuggingFace fast tokenizer
+/// Wraps a `tokenizers::Tokenizer` and exposes the interface of the `rust_tokenizers` tokenizers
+/// used by the pipelines. The tokenization and post-processing (addition of special tokens) are
+/// delegated to the HuggingFace tokenizer, while the truncation follows the `TruncationStrategy`
+/// of the pipelines. Offsets are converted to character positions in the input text.
+pub struct HFTokenizer {
+    tokenizer: Tokenizer,
+    special_token_map: SpecialTokenMap,
+    model_type: ModelType,
+}
+
+impl HFTokenizer {
+    /// Loads a tokenizer from a `tokenizer.json` file
+    ///
+    /// # Arguments
+    ///
+    /// * `model_type` - `ModelType` of the model using the tokenizer
+    /// * `tokenizer_file` - path to the `tokenizer.json` file
+    /// * `special_token_map_file` - optional path to the `special_tokens_map.json` file
+    pub fn from_file<P: AsRef<Path>, S: AsRef<Path>>(
+        model_type: ModelType,
+        tokenizer_file: P,
+        special_token_map_file: Option<S>,
+    ) -> Result<HFTokenizer, RustBertError> {
+        let tokenizer = Tokenizer::from_file(tokenizer_file)
+            .map_err(|error| RustBertError::TokenizerError(error.to_string()))?;
+        let special_token_map = match special_token_map_file {
+            Some(path) => SpecialTokenMap::from_file(path)?,
+            None => SpecialTokenMap::from_vocabulary(&tokenizer),
+        };
+        Ok(HFTokenizer {
+            tokenizer,
+            special_token_map,
+            model_type,
+        })
+    }
+
+    /// Returns the `ModelType` of the model using the tokenizer
+    pub fn model_type(&self) -> ModelType {
+        self.model_type
+    }
+
+    /// Returns the special tokens of the tokenizer
+    pub fn special_token_map(&self) -> &SpecialTokenMap {
+        &self.special_token_map
+    }
+
+    /// Returns the underlying HuggingFace tokenizer
+    pub fn get_tokenizer(&self) -> &Tokenizer {
+        &self.tokenizer
+    }
+
+    fn encoding_to_token_ids_with_offsets(encoding: &Encoding, text: &str) -> TokenIdsWithOffsets {
+        let char_positions = char_positions(text);
+        let word_ids = encoding.get_word_ids();
+        let mut offsets = Vec::with_capacity(encoding.len());
+        let mut reference_offsets = Vec::with_capacity(encoding.len());
+        let mut masks = Vec::with_capacity(encoding.len());
+        for (position, (begin, end)) in encoding.get_offsets().iter().enumerate() {
+            if begin < end {
+                let (begin, end) = (char_positions[*begin], char_positions[*end]);
+                offsets.push(Some(Offset::new(begin, end)));
+                reference_offsets.push((begin..end).collect::<Vec<OffsetSize>>());
+            } else {
+                offsets.push(None);
+                reference_offsets.push(vec![]);
+            }
+            let word_id = word_ids[position];
+            let mask = if encoding.get_special_tokens_mask()[position] == 1 {
+                Mask::Special
+            } else if word_id.is_some() && position > 0 && word_ids[position - 1] == word_id {
+                Mask::Continuation
+            } else if word_id.is_some() && word_ids.get(position + 1) == Some(&word_id) {
+                Mask::Begin
+            } else {
+                Mask::None
+            };
+            masks.push(mask);
+        }
+        TokenIdsWithOffsets {
+            ids: encoding.get_ids().iter().map(|id| *id as i64).collect(),
+            offsets,
+            reference_offsets,
+            masks,
+        }
+    }
+
+    fn encode_sequence(&self, text: &str) -> TokenIdsWithOffsets {
+        let encoding = self
+            .tokenizer
+            .encode(text, false)
+            .expect("Tokenization failed");
+        Self::encoding_to_token_ids_with_offsets(&encoding, text)
+    }
+
+    fn token_ids_to_encoding(&self, token_ids: &[i64]) -> Encoding {
+        let ids = token_ids.iter().map(|id| *id as u32).collect::<Vec<u32>>();
+        let tokens = ids
+            .iter()
+            .map(|id| self.tokenizer.id_to_token(*id).unwrap_or_default())
+            .collect();
+        Encoding::new(
+            ids,
+            vec![0; token_ids.len()],
+            tokens,
+            vec![None; token_ids.len()],
+            vec![(0, 0); token_ids.len()],
+            vec![0; token_ids.len()],
+            vec![1; token_ids.len()],
+            vec![],
+            HashMap::new(),
+        )
+    }
+
+    fn num_special_tokens_to_add(&self, is_pair: bool) -> usize {
+        self.tokenizer
+            .get_post_processor()
+            .map_or(0, |post_processor| post_processor.added_tokens(is_pair))
+    }
+
+    /// Tokenizes a text, returning the tokens with their character offsets
+    pub fn tokenize_with_offsets(&self, text: &str) -> TokensWithOffsets {
+        let encoding = self
+            .tokenizer
+            .encode(text, false)
+            .expect("Tokenization failed");
+        let token_ids_with_offsets = Self::encoding_to_token_ids_with_offsets(&encoding, text);
+        TokensWithOffsets {
+            tokens: encoding.get_tokens().to_vec(),
+            offsets: token_ids_with_offsets.offsets,
+            reference_offsets: token_ids_with_offsets.reference_offsets,
+            masks: token_ids_with_offsets.masks,
+        }
+    }
+
+    /// Tokenizes a text
+    pub fn tokenize(&self, text: &str) -> Vec<String> {
+        self.tokenizer
+            .encode(text, false)
+            .expect("Tokenization failed")
+            .get_tokens()
+            .to_vec()
+    }
+
+    /// Tokenizes a list of texts
+    pub fn tokenize_list<S>(&self, text_list: &[S]) -> Vec<Vec<String>>
+    where
+        S: AsRef<str>,
+    {
+        text_list
+            .iter()
+            .map(|text| self.tokenize(text.as_ref()))
+            .collect()
+    }
+
+    /// Encodes a text or a pair of texts, truncating the sequences following the `TruncationStrategy`
+    /// and adding the special tokens of the tokenizer
+    pub fn encode(
+        &self,
+        text_1: &str,
+        text_2: Option<&str>,
+        max_len: usize,
+        truncation_strategy: &TruncationStrategy,
+        stride: usize,
+    ) -> TokenizedInput {
+        let token_ids_with_offsets_1 = self.encode_sequence(text_1);
+        let token_ids_with_offsets_2 = text_2.map(|text| self.encode_sequence(text));
+        let total_len = token_ids_with_offsets_1.ids.len()
+            + token_ids_with_offsets_2
+                .as_ref()
+                .map_or(0, |token_ids| token_ids.ids.len())
+            + self.num_special_tokens_to_add(text_2.is_some());
+        let num_truncated_tokens = total_len.saturating_sub(max_len);
+
+        let (
+            token_ids_with_offsets_1,
+            token_ids_with_offsets_2,
+            overflowing_tokens,
+            _overflowing_offsets,
+        ) = truncate_sequences(
+            token_ids_with_offsets_1,
+            token_ids_with_offsets_2,
+            num_truncated_tokens,
+            truncation_strategy,
+            stride,
+        )
+        .unwrap();
+
+        let merged_tokenized_input = self
+            .build_input_with_special_tokens(token_ids_with_offsets_1, token_ids_with_offsets_2);
+
+        TokenizedInput {
+            token_ids: merged_tokenized_input.token_ids,
+            segment_ids: merged_tokenized_input.segment_ids,
+            special_tokens_mask: merged_tokenized_input.special_tokens_mask,
+            overflowing_tokens,
+            num_truncated_tokens,
+            token_offsets: merged_tokenized_input.token_offsets,
+            reference_offsets: merged_tokenized_input.reference_offsets,
+            mask: merged_tokenized_input.mask,
+        }
+    }
+
+    /// Encodes a list of texts
+    pub fn encode_list<S>(
+        &self,
+        text_list: &[S],
+        max_len: usize,
+        truncation_strategy: &TruncationStrategy,
+        stride: usize,
+    ) -> Vec<TokenizedInput>
+    where
+        S: AsRef<str>,
+    {
+        text_list
+            .iter()
+            .map(|text| self.encode(text.as_ref(), None, max_len, truncation_strategy, stride))
+            .collect()
+    }
+
+    /// Encodes a list of text pairs
+    pub fn encode_pair_list(
+        &self,
+        text_pair_list: &[(&str, &str)],
+        max_len: usize,
+        truncation_strategy: &TruncationStrategy,
+        stride: usize,
+    ) -> Vec<TokenizedInput> {
+        text_pair_list
+            .iter()
+            .map(|(text_1, text_2)| {
+                self.encode(text_1, Some(text_2), max_len, truncation_strategy, stride)
+            })
+            .collect()
+    }
+
+    /// Adds the special tokens of the tokenizer (using its post-processor) to a sequence or pair of
+    /// sequences of token ids
+    pub fn build_input_with_special_tokens(
+        &self,
+        token_ids_with_offsets_1: TokenIdsWithOffsets,
+        token_ids_with_offsets_2: Option<TokenIdsWithOffsets>,
+    ) -> TokenIdsWithSpecialTokens {
+        let encoding_1 = self.token_ids_to_encoding(&token_ids_with_offsets_1.ids);
+        let encoding_2 = token_ids_with_offsets_2
+            .as_ref()
+            .map(|token_ids| self.token_ids_to_encoding(&token_ids.ids));
+        let encoding = match self.tokenizer.get_post_processor() {
+            Some(post_processor) => post_processor
+                .process(encoding_1, encoding_2, true)
+                .expect("Post-processing failed"),
+            None => {
+                let mut encoding_1 = encoding_1;
+                if let Some(mut encoding_2) = encoding_2 {
+                    encoding_2.set_type_ids(vec![1; encoding_2.len()]);
+                    encoding_1.merge_with(encoding_2, false);
+                }
+                encoding_1
+            }
+        };
+
+        // The offsets and masks of the input tokens are carried over in order, the special tokens
+        // added by the post-processor are not related to the input text
+        let mut input_offsets = token_ids_with_offsets_1.offsets.into_iter().chain(
+            token_ids_with_offsets_2
+                .iter()
+                .flat_map(|token_ids| token_ids.offsets.clone()),
+        );
+        let mut input_reference_offsets = token_ids_with_offsets_1
+            .reference_offsets
+            .into_iter()
+            .chain(
+                token_ids_with_offsets_2
+                    .iter()
+                    .flat_map(|token_ids| token_ids.reference_offsets.clone()),
+            );
+        let mut input_masks = token_ids_with_offsets_1.masks.into_iter().chain(
+            token_ids_with_offsets_2
+                .iter()
+                .flat_map(|token_ids| token_ids.masks.clone()),
+        );
+
+        let special_tokens_mask = encoding
+            .get_special_tokens_mask()
+            .iter()
+            .map(|value| *value as i8)
+            .collect::<Vec<i8>>();
+        let mut token_offsets = Vec::with_capacity(special_tokens_mask.len());
+        let mut reference_offsets = Vec::with_capacity(special_tokens_mask.len());
+        let mut mask = Vec::with_capacity(special_tokens_mask.len());
+        for is_special in special_tokens_mask.iter() {
+            if *is_special == 1 {
+                token_offsets.push(None);
+                reference_offsets.push(vec![]);
+                mask.push(Mask::Special);
+            } else {
+                token_offsets.push(input_offsets.next().unwrap_or(None));
+                reference_offsets.push(input_reference_offsets.next().unwrap_or_default());
+                mask.push(input_masks.next().unwrap_or_default());
+            }
+        }
+
+        TokenIdsWithSpecialTokens {
+            token_ids: encoding.get_ids().iter().map(|id| *id as i64).collect(),
+            segment_ids: encoding
+                .get_type_ids()
+                .iter()
+                .map(|type_id| *type_id as i8)
+                .collect(),
+            special_tokens_mask,
+            token_offsets,
+            reference_offsets,
+            mask,
+        }
+    }
+
+    /// Decodes a sequence of token ids
+    pub fn decode(
+        &self,
+        token_ids: &[i64],
+        skip_special_tokens: bool,
+        clean_up_tokenization_spaces: bool,
+    ) -> String {
+        let output = self
+            .tokenizer
+            .decode(
+                token_ids.iter().map(|id| *id as u32).collect(),
+                skip_special_tokens,
+            )
+            .expect("Decoding failed");
+        if clean_up_tokenization_spaces {
+            clean_up_tokenization(output)
+        } else {
+            output
+        }
+    }
+
+    /// Converts tokens to their ids, using the id of the unknown token for tokens not found in the
+    /// vocabulary
+    pub fn convert_tokens_to_ids<S>(&self, tokens: &[S]) -> Vec<i64>
+    where
+        S: AsRef<str>,
+    {
+        tokens
+            .iter()
+            .map(|token| {
+                self.tokenizer
+                    .token_to_id(token.as_ref())
+                    .map(|id| id as i64)
+                    .unwrap_or_else(|| self.get_unk_id())
+            })
+            .collect()
+    }
+
+    fn special_token_id(&self, token: &Option<String>) -> Option<i64> {
+        token
+            .as_ref()
+            .and_then(|token| self.tokenizer.token_to_id(token))
+            .map(|id| id as i64)
+    }
+
+    pub fn get_unk_id(&self) -> i64 {
+        self.special_token_id(&self.special_token_map.unk_token)
+            .expect("UNK token not found in vocabulary")
+    }
+
+    pub fn get_pad_id(&self) -> Option<i64> {
+        self.special_token_id(&self.special_token_map.pad_token)
+    }
+
+    pub fn get_sep_id(&self) -> Option<i64> {
+        self.special_token_id(&self.special_token_map.sep_token)
+    }
+
+    pub fn get_mask_id(&self) -> Option<i64> {
+        self.special_token_id(&self.special_token_map.mask_token)
+    }
+
+    pub fn get_mask_value(&self) -> Option<&str> {
+        self.special_token_map.mask_token.as_deref()
+    }
+
+    pub fn get_bos_id(&self) -> Option<i64> {
+        self.special_token_id(&self.special_token_map.bos_token)
+    }
+
+    pub fn get_eos_id(&self) -> Option<i64> {
+        self.special_token_id(&self.special_token_map.eos_token)
+    }
+}
+
+/// Maps byte positions in a text (including the end of the text) to character positions
+fn char_positions(text: &str) -> Vec<OffsetSize> {
+    let mut positions = vec![0; text.len() + 1];
+    let mut char_position = 0;
+    for (byte_position, character) in text.char_indices() {
+        for position in byte_position..byte_position + character.len_utf8() {
+            positions[position] = char_position;
+        }
+        char_position += 1;
+    }
+    positions[text.len()] = char_position;
+    positions
+}
+
+fn clean_up_tokenization(input_string: String) -> String {
+    input_string
+        .replace(" .", ".")
+        .replace(" !", "!")
+        .replace(" ?", "?")
+        .replace(" ,", ",")
+        .replace(" ' ", "'")
+        .replace(" n't", "n't")
+        .replace(" 'm", "'m")
+        .replace(" do not", " don't")
+        .replace(" 's", "'s")
+        .replace(" 've", "'ve")
+        .replace(" 're", "'re")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn special_token(id: u32, content: &str) -> serde_json::Value {
+        json!({
+            "id": id,
+            "content": content,
+            "single_word": false,
+            "lstrip": false,
+            "rstrip": false,
+            "normalized": false,
+            "special": true
+        })
+    }
+
+    fn template_piece(kind: &str, id: &str, type_id: u32) -> serde_json::Value {
+        json!({ kind: { "id": id, "type_id": type_id } })
+    }
+
+    fn tiny_tokenizer_file() -> anyhow::Result<(TempDir, std::path::PathBuf)> {
+        let directory = tempfile::tempdir()?;
+        let tokenizer = json!({
+            "version": "1.0",
+            "truncation": null,
+            "padding": null,
+            "added_tokens": [
+                special_token(0, "[PAD]"),
+                special_token(1, "[UNK]"),
+                special_token(2, "[CLS]"),
+                special_token(3, "[SEP]"),
+                special_token(4, "[MASK]")
+            ],
+            "normalizer": {
+                "type": "BertNormalizer",
+                "clean_text": true,
+                "handle_chinese_chars": true,
+                "strip_accents": null,
+                "lowercase": true
+            },
+            "pre_tokenizer": { "type": "BertPreTokenizer" },
+            "post_processor": {
+                "type": "TemplateProcessing",
+                "single": [
+                    template_piece("SpecialToken", "[CLS]", 0),
+                    template_piece("Sequence", "A", 0),
+                    template_piece("SpecialToken", "[SEP]", 0)
+                ],
+                "pair": [
+                    template_piece("SpecialToken", "[CLS]", 0),
+                    template_piece("Sequence", "A", 0),
+                    template_piece("SpecialToken", "[SEP]", 0),
+                    template_piece("Sequence", "B", 1),
+                    template_piece("SpecialToken", "[SEP]", 1)
+                ],
+                "special_tokens": {
+                    "[CLS]": { "id": "[CLS]", "ids": [2], "tokens": ["[CLS]"] },
+                    "[SEP]": { "id": "[SEP]", "ids": [3], "tokens": ["[SEP]"] }
+                }
+            },
+            "decoder": { "type": "WordPiece", "prefix": "##", "cleanup": true },
+            "model": {
+                "type": "WordPiece",
+                "unk_token": "[UNK]",
+                "continuing_subword_prefix": "##",
+                "max_input_chars_per_word": 100,
+                "vocab": {
+                    "[PAD]": 0,
+                    "[UNK]": 1,
+                    "[CLS]": 2,
+                    "[SEP]": 3,
+                    "[MASK]": 4,
+                    "hello": 5,
+                    "world": 6,
+                    "un": 7,
+                    "##able": 8,
+                    "!": 9,
+                    "cafe": 10
+                }
+            }
+        });
+        let path = directory.path().join("tokenizer.json");
+        std::fs::write(&path, serde_json::to_vec(&tokenizer)?)?;
+        Ok((directory, path))
+    }
+
+    fn tiny_tokenizer() -> anyhow::Result<(TempDir, HFTokenizer)> {
+        let (directory, path) = tiny_tokenizer_file()?;
+        let tokenizer = HFTokenizer::from_file(ModelType::Bert, path, None::<&Path>)?;
+        Ok((directory, tokenizer))
+    }
+
+    #[test]
+    fn tokenization() -> anyhow::Result<()> {
+        let (_directory, tokenizer) = tiny_tokenizer()?;
+
+        assert_eq!(
+            tokenizer.tokenize("Hello unable world!"),
+            ["hello", "un", "##able", "world", "!"]
+        );
+        assert_eq!(
+            tokenizer.tokenize_list(&["Hello", "goodbye"]),
+            [vec!["hello"], vec!["[UNK]"]]
+        );
+
+        //    Offsets are expressed in characters of the original text
+        let tokens = tokenizer.tokenize_with_offsets("Café unable");
+        assert_eq!(tokens.tokens, ["cafe", "un", "##able"]);
+        assert_eq!(
+            tokens.offsets,
+            [
+                Some(Offset::new(0, 4)),
+                Some(Offset::new(5, 7)),
+                Some(Offset::new(7, 11))
+            ]
+        );
+        assert_eq!(tokens.reference_offsets[0], [0, 1, 2, 3]);
+        assert_eq!(tokens.masks, [Mask::None, Mask::Begin, Mask::Continuation]);
+        Ok(())
+    }
+
+    #[test]
+    fn encoding() -> anyhow::Result<()> {
+        let (_directory, tokenizer) = tiny_tokenizer()?;
+
+        let input = tokenizer.encode(
+            "Hello unable world!",
+            None,
+            128,
+            &TruncationStrategy::LongestFirst,
+            0,
+        );
+        assert_eq!(input.token_ids, [2, 5, 7, 8, 6, 9, 3]);
+        assert_eq!(input.segment_ids, [0; 7]);
+        assert_eq!(input.special_tokens_mask, [1, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(input.num_truncated_tokens, 0);
+        assert_eq!(
+            input.token_offsets,
+            [
+                None,
+                Some(Offset::new(0, 5)),
+                Some(Offset::new(6, 8)),
+                Some(Offset::new(8, 12)),
+                Some(Offset::new(13, 18)),
+                Some(Offset::new(18, 19)),
+                None
+            ]
+        );
+        assert_eq!(
+            input.mask,
+            [
+                Mask::Special,
+                Mask::None,
+                Mask::Begin,
+                Mask::Continuation,
+                Mask::None,
+                Mask::None,
+                Mask::Special
+            ]
+        );
+
+        //    The truncation removes tokens from the longest sequence before adding the special tokens
+        let input = tokenizer.encode(
+            "Hello world!",
+            Some("unable"),
+            7,
+            &TruncationStrategy::LongestFirst,
+            0,
+        );
+        assert_eq!(input.token_ids, [2, 5, 6, 3, 7, 8, 3]);
+        assert_eq!(input.segment_ids, [0, 0, 0, 0, 1, 1, 1]);
+        assert_eq!(input.num_truncated_tokens, 1);
+        assert_eq!(input.overflowing_tokens, [9]);
+        assert_eq!(
+            input.token_offsets,
+            [
+                None,
+                Some(Offset::new(0, 5)),
+                Some(Offset::new(6, 11)),
+                None,
+                Some(Offset::new(0, 2)),
+                Some(Offset::new(2, 6)),
+                None
+            ]
+        );
+
+        let inputs = tokenizer.encode_pair_list(
+            &[("Hello", "world"), ("unable", "cafe")],
+            128,
+            &TruncationStrategy::LongestFirst,
+            0,
+        );
+        assert_eq!(inputs[0].token_ids, [2, 5, 3, 6, 3]);
+        assert_eq!(inputs[1].token_ids, [2, 7, 8, 3, 10, 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn decoding() -> anyhow::Result<()> {
+        let (_directory, tokenizer) = tiny_tokenizer()?;
+
+        assert_eq!(
+            tokenizer.decode(&[2, 5, 7, 8, 6, 9, 3], true, true),
+            "hello unable world!"
+        );
+        assert_eq!(
+            tokenizer.convert_tokens_to_ids(&["hello", "##able", "goodbye"]),
+            [5, 8, 1]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn special_tokens() -> anyhow::Result<()> {
+        let (directory, tokenizer_path) = tiny_tokenizer_file()?;
+
+        //    Special tokens looked up in the vocabulary
+        let tokenizer = HFTokenizer::from_file(ModelType::Bert, &tokenizer_path, None::<&Path>)?;
+        assert_eq!(tokenizer.model_type(), ModelType::Bert);
+        assert_eq!(tokenizer.get_unk_id(), 1);
+        assert_eq!(tokenizer.get_pad_id(), Some(0));
+        assert_eq!(tokenizer.get_sep_id(), Some(3));
+        assert_eq!(tokenizer.get_mask_id(), Some(4));
+        assert_eq!(tokenizer.get_mask_value(), Some("[MASK]"));
+        assert_eq!(tokenizer.get_bos_id(), None);
+        assert_eq!(tokenizer.get_eos_id(), None);
+
+        //    Special tokens read from a map, given as strings or objects
+        let special_tokens_path = directory.path().join("special_tokens_map.json");
+        std::fs::write(
+            &special_tokens_path,
+            r#"{"unk_token": "[UNK]", "pad_token": {"content": "[SEP]", "lstrip": false}}"#,
+        )?;
+        let tokenizer =
+            HFTokenizer::from_file(ModelType::Bert, &tokenizer_path, Some(&special_tokens_path))?;
+        assert_eq!(tokenizer.get_unk_id(), 1);
+        assert_eq!(tokenizer.get_pad_id(), Some(3));
+        assert_eq!(tokenizer.get_mask_id(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_files() -> anyhow::Result<()> {
+        let (directory, tokenizer_path) = tiny_tokenizer_file()?;
+
+        let invalid_path = directory.path().join("invalid.json");
+        std::fs::write(&invalid_path, "{}")?;
+        assert!(matches!(
+            HFTokenizer::from_file(ModelType::Bert, &invalid_path, None::<&Path>),
+            Err(RustBertError::TokenizerError(_))
+        ));
+
+        let missing_path = directory.path().join("missing.json");
+        assert!(matches!(
+            HFTokenizer::from_file(ModelType::Bert, &tokenizer_path, Some(&missing_path)),
+            Err(RustBertError::IOError(_))
+        ));
+        Ok(())
+    }
+}
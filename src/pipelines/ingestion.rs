@@ -0,0 +1,232 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Text ingestion helpers
+//! Text extracted from PDF files, OCR engines or web pages is usually noisy: words are hyphenated
+//! at line ends, sentences are broken over several lines, ligatures and compatibility characters
+//! are used and invisible characters (soft hyphens, zero-width spaces) are scattered through the
+//! text. This noise fragments the tokenization and measurably degrades the quality of pipelines
+//! such as NER or question answering.
+//!
+//! The `TextNormalizer` cleans up raw extracted text before it is passed to a pipeline. It does
+//! not require any layout information. The normalization steps are configured with a
+//! `TextNormalizationConfig`, so that each pipeline can use its own settings (for example keeping
+//! paragraph breaks for question answering contexts but not for short NER inputs):
+//! - unicode normalization (NFKC by default, expanding ligatures such as `ﬁ`)
+//! - removal of control and invisible characters
+//! - de-hyphenation of words split over two lines
+//! - whitespace cleanup (line breaks within paragraphs, repeated spaces)
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::ingestion::{TextNormalizationConfig, TextNormalizer};
+//! use rust_bert::pipelines::ner::NERModel;
+//!
+//! let normalizer = TextNormalizer::new(TextNormalizationConfig {
+//!     preserve_paragraphs: false,
+//!     ..Default::default()
+//! });
+//! let ner_model = NERModel::new(Default::default())?;
+//!
+//! let raw_text = "The agreement was signed in Ams-\nterdam by the Euro-\npean Commission.";
+//! let output = ner_model.predict(&normalizer.normalize_batch(&[raw_text]));
+//! # Ok(())
+//! # }
+//! ```
+
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization as _;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// # Unicode normalization form applied to the input text
+pub enum UnicodeNormalization {
+    /// No unicode normalization
+    None,
+    /// Canonical composition
+    Nfc,
+    /// Compatibility composition (ligatures, full-width and other compatibility characters are replaced by their standard equivalent)
+    Nfkc,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Configuration for TextNormalizer
+pub struct TextNormalizationConfig {
+    /// Unicode normalization form (default: `UnicodeNormalization::Nfkc`)
+    pub unicode_normalization: UnicodeNormalization,
+    /// Remove control characters, soft hyphens and zero-width characters (default: true)
+    pub remove_invisible_characters: bool,
+    /// Join words hyphenated at the end of a line, e.g. `"exam-\nple"` becomes `"example"`. The hyphen is kept if the next line starts with an upper case letter (`"Jean-\nPierre"` becomes `"Jean-Pierre"`) (default: true)
+    pub dehyphenate: bool,
+    /// Replace line breaks within paragraphs by spaces and collapse repeated spaces (default: true)
+    pub normalize_whitespace: bool,
+    /// Keep paragraph breaks (empty lines) as `"\n\n"` when normalizing whitespace. If false, paragraphs are joined with a space (default: true)
+    pub preserve_paragraphs: bool,
+}
+
+impl Default for TextNormalizationConfig {
+    fn default() -> TextNormalizationConfig {
+        TextNormalizationConfig {
+            unicode_normalization: UnicodeNormalization::Nfkc,
+            remove_invisible_characters: true,
+            dehyphenate: true,
+            normalize_whitespace: true,
+            preserve_paragraphs: true,
+        }
+    }
+}
+
+/// # TextNormalizer cleaning up raw extracted text before tokenization
+pub struct TextNormalizer {
+    config: TextNormalizationConfig,
+    hyphenation_pattern: Regex,
+    paragraph_pattern: Regex,
+    spaces_pattern: Regex,
+}
+
+impl TextNormalizer {
+    /// Build a new `TextNormalizer`
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `TextNormalizationConfig` defining the normalization steps to apply
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use rust_bert::pipelines::ingestion::TextNormalizer;
+    ///
+    /// let normalizer = TextNormalizer::new(Default::default());
+    /// let text = normalizer.normalize("The ﬁrst para-\ngraph  of\nthe document.");
+    /// ```
+    pub fn new(config: TextNormalizationConfig) -> TextNormalizer {
+        TextNormalizer {
+            config,
+            hyphenation_pattern: Regex::new(r"(\p{L})-[ \t]*\n[ \t]*(\p{L})").unwrap(),
+            paragraph_pattern: Regex::new(r"[ \t]*\n([ \t]*\n)+[ \t]*").unwrap(),
+            spaces_pattern: Regex::new(r"[ \t\n\u{a0}\u{2000}-\u{200a}\u{202f}\u{205f}\u{3000}]+")
+                .unwrap(),
+        }
+    }
+
+    /// Normalizes a text
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - Raw text to normalize
+    ///
+    /// # Returns
+    ///
+    /// * `String` normalized text
+    pub fn normalize(&self, text: &str) -> String {
+        let mut text = match self.config.unicode_normalization {
+            UnicodeNormalization::None => text.to_string(),
+            UnicodeNormalization::Nfc => text.nfc().collect::<String>(),
+            UnicodeNormalization::Nfkc => text.nfkc().collect::<String>(),
+        };
+        text = text.replace("\r\n", "\n").replace('\r', "\n");
+        if self.config.remove_invisible_characters {
+            text.retain(|character| !is_invisible(character));
+        }
+        if self.config.dehyphenate {
+            text = self
+                .hyphenation_pattern
+                .replace_all(&text, |captures: &Captures| {
+                    let next_character = &captures[2];
+                    if next_character.chars().all(char::is_lowercase) {
+                        format!("{}{}", &captures[1], next_character)
+                    } else {
+                        format!("{}-{}", &captures[1], next_character)
+                    }
+                })
+                .into_owned();
+        }
+        if self.config.normalize_whitespace {
+            text = if self.config.preserve_paragraphs {
+                self.paragraph_pattern
+                    .split(text.trim())
+                    .map(|paragraph| self.spaces_pattern.replace_all(paragraph, " "))
+                    .collect::<Vec<_>>()
+                    .join("\n\n")
+            } else {
+                self.spaces_pattern
+                    .replace_all(text.trim(), " ")
+                    .into_owned()
+            };
+        }
+        text
+    }
+
+    /// Normalizes a batch of texts
+    ///
+    /// # Arguments
+    ///
+    /// * `texts` - Raw texts to normalize
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<String>` normalized texts, that can be passed to the pipelines' prediction methods
+    pub fn normalize_batch<S>(&self, texts: &[S]) -> Vec<String>
+    where
+        S: AsRef<str>,
+    {
+        texts
+            .iter()
+            .map(|text| self.normalize(text.as_ref()))
+            .collect()
+    }
+}
+
+impl Default for TextNormalizer {
+    fn default() -> TextNormalizer {
+        TextNormalizer::new(TextNormalizationConfig::default())
+    }
+}
+
+/// Control characters (except line breaks and tabs), soft hyphens, zero-width characters and
+/// byte order marks
+fn is_invisible(character: char) -> bool {
+    match character {
+        '\n' | '\t' => false,
+        '\u{ad}' | '\u{200b}'..='\u{200d}' | '\u{2060}' | '\u{feff}' => true,
+        _ => character.is_control(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn normalize_extracted_text() {
+        let normalizer = TextNormalizer::default();
+        let raw_text =
+            "The ﬁrst para-\ngraph was ex\u{ad}tracted\r\nfrom a  PDF\u{200b} file.\n\n \n\
+        Second para\u{a0}graph, Jean-\nPierre   wrote it.  ";
+
+        assert_eq!(
+            normalizer.normalize(raw_text),
+            "The first paragraph was extracted from a PDF file.\n\n\
+            Second para graph, Jean-Pierre wrote it."
+        );
+
+        let normalizer = TextNormalizer::new(TextNormalizationConfig {
+            preserve_paragraphs: false,
+            dehyphenate: false,
+            ..Default::default()
+        });
+        assert_eq!(
+            normalizer.normalize_batch(&[raw_text]),
+            ["The first para- graph was extracted from a PDF file. \
+            Second para graph, Jean- Pierre wrote it."]
+        );
+    }
+}
@@ -0,0 +1,213 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Input encoding shared by the pipelines
+//! Builds the padded input ids, attention mask and token type ids of a batch of texts (or text
+//! pairs) for a given architecture:
+//! - special tokens are added by the tokenizer of the model (e.g. `[CLS] A [SEP] B [SEP]` for BERT,
+//! `<s> A </s></s> B </s>` for RoBERTa)
+//! - the attention mask is built from the length of each sequence (1 for tokens, 0 for padding),
+//! so that it remains valid for tokenizers whose padding token is also a regular token
+//! - token type ids are only returned for architectures with token type embeddings (see
+//! `ModelType::uses_token_type_ids`). RoBERTa-like models have a single token type: passing the
+//! segment ids of a pair would index past their token type embeddings.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::common::{ModelType, TokenizerOption};
+//! use rust_bert::pipelines::input_encoding::EncodedInputs;
+//! use tch::Device;
+//!
+//! let tokenizer =
+//!     TokenizerOption::from_file(ModelType::Bert, "vocab.txt", None, true, None, None)?;
+//! let encoded_inputs = EncodedInputs::from_text_pairs(
+//!     &tokenizer,
+//!     &[("What is the capital of France?", "Paris is the capital of France.")],
+//!     128,
+//!     Device::Cpu,
+//! )?;
+//! assert!(encoded_inputs.token_type_ids.is_some());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::common::error::RustBertError;
+use crate::pipelines::common::TokenizerOption;
+use rust_tokenizers::tokenizer::TruncationStrategy;
+use rust_tokenizers::TokenizedInput;
+use tch::{Device, Tensor};
+
+/// # Padded batch of model inputs
+pub struct EncodedInputs {
+    /// Token ids of shape (batch size, sequence length), padded with the tokenizer padding id
+    pub input_ids: Tensor,
+    /// Attention mask of shape (batch size, sequence length), 1 for tokens and 0 for padding
+    pub attention_mask: Tensor,
+    /// Token type ids of shape (batch size, sequence length) for architectures with token type
+    /// embeddings, `None` otherwise
+    pub token_type_ids: Option<Tensor>,
+}
+
+impl EncodedInputs {
+    /// Tokenizes and pads a batch of texts. Texts longer than `max_len` tokens are truncated.
+    pub fn from_texts<S>(
+        tokenizer: &TokenizerOption,
+        texts: &[S],
+        max_len: usize,
+        device: Device,
+    ) -> Result<EncodedInputs, RustBertError>
+    where
+        S: AsRef<str> + Sync,
+    {
+        let tokenized_input =
+            tokenizer.encode_list(texts, max_len, &TruncationStrategy::LongestFirst, 0);
+        EncodedInputs::from_tokenized_input(tokenizer, tokenized_input, device)
+    }
+
+    /// Tokenizes and pads a batch of text pairs. The longest sequence of pairs longer than
+    /// `max_len` tokens is truncated first.
+    pub fn from_text_pairs(
+        tokenizer: &TokenizerOption,
+        text_pairs: &[(&str, &str)],
+        max_len: usize,
+        device: Device,
+    ) -> Result<EncodedInputs, RustBertError> {
+        let tokenized_input =
+            tokenizer.encode_pair_list(text_pairs, max_len, &TruncationStrategy::LongestFirst, 0);
+        EncodedInputs::from_tokenized_input(tokenizer, tokenized_input, device)
+    }
+
+    /// Pads a batch of inputs already tokenized (with special tokens) by the tokenizer
+    pub fn from_tokenized_input(
+        tokenizer: &TokenizerOption,
+        tokenized_input: Vec<TokenizedInput>,
+        device: Device,
+    ) -> Result<EncodedInputs, RustBertError> {
+        let padded_batch = PaddedBatch::new(
+            tokenized_input,
+            tokenizer.get_pad_id().unwrap_or(0),
+            tokenizer.model_type().uses_token_type_ids(),
+        )?;
+        let to_tensor = |values: Vec<Vec<i64>>| {
+            let rows = values
+                .iter()
+                .map(|row| Tensor::of_slice(row))
+                .collect::<Vec<Tensor>>();
+            Tensor::stack(&rows, 0).to(device)
+        };
+        Ok(EncodedInputs {
+            input_ids: to_tensor(padded_batch.input_ids),
+            attention_mask: to_tensor(padded_batch.attention_mask),
+            token_type_ids: padded_batch.token_type_ids.map(to_tensor),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct PaddedBatch {
+    input_ids: Vec<Vec<i64>>,
+    attention_mask: Vec<Vec<i64>>,
+    token_type_ids: Option<Vec<Vec<i64>>>,
+}
+
+impl PaddedBatch {
+    fn new(
+        tokenized_input: Vec<TokenizedInput>,
+        pad_id: i64,
+        with_token_type_ids: bool,
+    ) -> Result<PaddedBatch, RustBertError> {
+        let max_len = tokenized_input
+            .iter()
+            .map(|input| input.token_ids.len())
+            .max()
+            .ok_or_else(|| RustBertError::ValueError("Got empty iterator as input".to_string()))?;
+
+        let mut input_ids = Vec::with_capacity(tokenized_input.len());
+        let mut attention_mask = Vec::with_capacity(tokenized_input.len());
+        let mut token_type_ids = Vec::with_capacity(tokenized_input.len());
+        for input in tokenized_input {
+            let mut mask = vec![1; input.token_ids.len()];
+            mask.resize(max_len, 0);
+            attention_mask.push(mask);
+            if with_token_type_ids {
+                let mut segment_ids = input
+                    .segment_ids
+                    .iter()
+                    .map(|&segment_id| segment_id as i64)
+                    .collect::<Vec<i64>>();
+                segment_ids.resize(max_len, 0);
+                token_type_ids.push(segment_ids);
+            }
+            let mut token_ids = input.token_ids;
+            token_ids.resize(max_len, pad_id);
+            input_ids.push(token_ids);
+        }
+        Ok(PaddedBatch {
+            input_ids,
+            attention_mask,
+            token_type_ids: if with_token_type_ids {
+                Some(token_type_ids)
+            } else {
+                None
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_tokenizers::Mask;
+
+    fn tokenized_input(token_ids: Vec<i64>, segment_ids: Vec<i8>) -> TokenizedInput {
+        let length = token_ids.len();
+        TokenizedInput {
+            token_ids,
+            segment_ids,
+            special_tokens_mask: vec![0; length],
+            overflowing_tokens: vec![],
+            num_truncated_tokens: 0,
+            token_offsets: vec![None; length],
+            reference_offsets: vec![vec![]; length],
+            mask: vec![Mask::None; length],
+        }
+    }
+
+    #[test]
+    fn input_padding() -> Result<(), RustBertError> {
+        let batch = vec![
+            tokenized_input(vec![101, 7, 102, 8, 102], vec![0, 0, 0, 1, 1]),
+            tokenized_input(vec![101, 102, 0], vec![0, 0, 1]),
+        ];
+
+        let padded_batch = PaddedBatch::new(batch.clone(), 0, true)?;
+        assert_eq!(
+            padded_batch.input_ids,
+            [vec![101, 7, 102, 8, 102], vec![101, 102, 0, 0, 0]]
+        );
+        // The last token of the second input is a regular token sharing the padding id
+        assert_eq!(
+            padded_batch.attention_mask,
+            [vec![1, 1, 1, 1, 1], vec![1, 1, 1, 0, 0]]
+        );
+        assert_eq!(
+            padded_batch.token_type_ids,
+            Some(vec![vec![0, 0, 0, 1, 1], vec![0, 0, 1, 0, 0]])
+        );
+
+        let padded_batch = PaddedBatch::new(batch, 1, false)?;
+        assert_eq!(padded_batch.input_ids[1], [101, 102, 0, 1, 1]);
+        assert_eq!(padded_batch.token_type_ids, None);
+
+        assert!(PaddedBatch::new(vec![], 0, true).is_err());
+        Ok(())
+    }
+}
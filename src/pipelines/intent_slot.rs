@@ -0,0 +1,437 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Joint intent detection and slot filling pipeline
+//! Predicts the intent of an utterance and extracts its slots (e.g. `{intent: book_flight, slots:
+//! [(fromloc, "Boston"), (toloc, "Denver")]}`) for voice-assistant style applications. Both tasks
+//! are performed from a single encoder pass, using a BERT model with an intent classification head
+//! on the pooled output and a slot filling head on the token hidden states (JointBERT architecture,
+//! see `BertForIntentSlotClassification`).
+//!
+//! The slot labels are expected to follow the BIO scheme (e.g. `O`, `B-toloc`, `I-toloc`). The
+//! slot label of a word is the label predicted for its first sub-token, and consecutive words
+//! labelled with the same slot type are merged into a single span.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::common::ModelType;
+//! use rust_bert::pipelines::intent_slot::{IntentSlotConfig, IntentSlotModel};
+//! use rust_bert::resources::LocalResource;
+//! use std::path::PathBuf;
+//!
+//! let config = IntentSlotConfig::new(
+//!     ModelType::Bert,
+//!     LocalResource::from(PathBuf::from("path/to/rust_model.ot")),
+//!     LocalResource::from(PathBuf::from("path/to/config.json")),
+//!     LocalResource::from(PathBuf::from("path/to/vocab.txt")),
+//!     None,
+//!     vec!["atis_airfare".to_string(), "atis_flight".to_string()],
+//!     vec![
+//!         "O".to_string(),
+//!         "B-fromloc".to_string(),
+//!         "I-fromloc".to_string(),
+//!         "B-toloc".to_string(),
+//!         "I-toloc".to_string(),
+//!     ],
+//!     true,
+//! );
+//! let model = IntentSlotModel::new(config)?;
+//!
+//! let output = model.predict(&["show me flights from boston to new york"]);
+//! for slot in &output[0].slots {
+//!     println!("{}: {}", slot.slot_type, slot.text);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::bert::{BertConfig, BertForIntentSlotClassification};
+use crate::common::error::RustBertError;
+use crate::pipelines::common::{ModelType, TokenizerOption};
+use crate::pipelines::sequence_classification::Label;
+use crate::resources::ResourceProvider;
+use crate::Config;
+use rust_tokenizers::tokenizer::TruncationStrategy;
+use rust_tokenizers::{Mask, Offset, TokenizedInput};
+use serde::{Deserialize, Serialize};
+use tch::nn::VarStore;
+use tch::{no_grad, Device, Kind, Tensor};
+
+#[derive(Serialize, Deserialize)]
+/// # Configuration for IntentSlotModel
+/// Contains information regarding the model to load, the intent and slot labels and device to
+/// place the model on.
+pub struct IntentSlotConfig {
+    /// Model type (only `ModelType::Bert` is currently supported)
+    pub model_type: ModelType,
+    /// Model weights resource
+    #[serde(with = "crate::common::serde_utils::resource")]
+    pub model_resource: Box<dyn ResourceProvider + Send>,
+    /// Config resource
+    #[serde(with = "crate::common::serde_utils::resource")]
+    pub config_resource: Box<dyn ResourceProvider + Send>,
+    /// Vocab resource
+    #[serde(with = "crate::common::serde_utils::resource")]
+    pub vocab_resource: Box<dyn ResourceProvider + Send>,
+    /// Merges resource
+    #[serde(default, with = "crate::common::serde_utils::optional_resource")]
+    pub merges_resource: Option<Box<dyn ResourceProvider + Send>>,
+    /// Intent labels, in the order of the intent classification head outputs
+    pub intent_labels: Vec<String>,
+    /// Slot labels (BIO scheme), in the order of the slot filling head outputs
+    pub slot_labels: Vec<String>,
+    /// Automatically lower case all input upon tokenization (assumes a lower-cased model)
+    pub lower_case: bool,
+    /// Flag indicating if the tokenizer should strip accents (normalization). Only used for BERT / ALBERT models
+    pub strip_accents: Option<bool>,
+    /// Flag indicating if the tokenizer should add a white space before each tokenized input (needed for some Roberta models)
+    pub add_prefix_space: Option<bool>,
+    /// Device to place the model on (default: CUDA/GPU when available)
+    #[serde(
+        with = "crate::common::serde_utils::device",
+        default = "crate::common::serde_utils::device::default"
+    )]
+    pub device: Device,
+}
+
+impl IntentSlotConfig {
+    /// Instantiate a new joint intent detection and slot filling configuration of the supplied type.
+    ///
+    /// # Arguments
+    ///
+    /// * `model_type` - `ModelType` indicating the model type to load (must match with the actual data to be loaded!)
+    /// * model - The `ResourceProvider` pointing to the model to load (e.g.  model.ot)
+    /// * config - The `ResourceProvider` pointing to the model configuration to load (e.g. config.json)
+    /// * vocab - The `ResourceProvider` pointing to the tokenizer's vocabulary to load (e.g.  vocab.txt/vocab.json)
+    /// * merges - An optional `ResourceProvider` pointing to the tokenizer's merge file to load (e.g.  merges.txt)
+    /// * intent_labels - Intent labels, in the order of the intent classification head outputs
+    /// * slot_labels - Slot labels (BIO scheme), in the order of the slot filling head outputs
+    /// * lower_case - A `bool` indicating whether the tokenizer should lower case all input (in case of a lower-cased model)
+    pub fn new<RM, RC, RV>(
+        model_type: ModelType,
+        model_resource: RM,
+        config_resource: RC,
+        vocab_resource: RV,
+        merges_resource: Option<RV>,
+        intent_labels: Vec<String>,
+        slot_labels: Vec<String>,
+        lower_case: bool,
+    ) -> IntentSlotConfig
+    where
+        RM: ResourceProvider + Send + 'static,
+        RC: ResourceProvider + Send + 'static,
+        RV: ResourceProvider + Send + 'static,
+    {
+        IntentSlotConfig {
+            model_type,
+            model_resource: Box::new(model_resource),
+            config_resource: Box::new(config_resource),
+            vocab_resource: Box::new(vocab_resource),
+            merges_resource: merges_resource.map(|r| Box::new(r) as Box<_>),
+            intent_labels,
+            slot_labels,
+            lower_case,
+            strip_accents: None,
+            add_prefix_space: None,
+            device: Device::cuda_if_available(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Slot extracted by an `IntentSlotModel`
+pub struct Slot {
+    /// Slot type (BIO label without its prefix)
+    pub slot_type: String,
+    /// Text of the slot value
+    pub text: String,
+    /// Average confidence score of the words forming the slot value
+    pub score: f64,
+    /// Offset of the slot value in the input text
+    pub offset: Offset,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Intent and slots predicted for an utterance
+pub struct IntentSlotOutput {
+    /// Predicted intent
+    pub intent: Label,
+    /// Slots extracted from the utterance, in order of appearance
+    pub slots: Vec<Slot>,
+}
+
+/// # IntentSlotModel for joint intent detection and slot filling
+pub struct IntentSlotModel {
+    tokenizer: TokenizerOption,
+    model: BertForIntentSlotClassification,
+    var_store: VarStore,
+    intent_labels: Vec<String>,
+    slot_labels: Vec<String>,
+    max_length: usize,
+}
+
+impl IntentSlotModel {
+    /// Build a new `IntentSlotModel`
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `IntentSlotConfig` object containing the resource references (model, vocabulary, configuration), labels and device placement (CPU/GPU)
+    pub fn new(config: IntentSlotConfig) -> Result<IntentSlotModel, RustBertError> {
+        if !matches!(config.model_type, ModelType::Bert) {
+            return Err(RustBertError::InvalidConfigurationError(format!(
+                "Joint intent detection and slot filling not implemented for {:?}!",
+                config.model_type
+            )));
+        }
+        if config.intent_labels.is_empty() | config.slot_labels.is_empty() {
+            return Err(RustBertError::InvalidConfigurationError(
+                "Intent and slot labels must be provided".to_string(),
+            ));
+        }
+        let vocab_path = config.vocab_resource.get_local_path()?;
+        let merges_path = if let Some(merges_resource) = &config.merges_resource {
+            Some(merges_resource.get_local_path()?)
+        } else {
+            None
+        };
+        let tokenizer = TokenizerOption::from_file(
+            config.model_type,
+            vocab_path.to_str().unwrap(),
+            merges_path.as_deref().map(|path| path.to_str().unwrap()),
+            config.lower_case,
+            config.strip_accents,
+            config.add_prefix_space,
+        )?;
+
+        let config_path = config.config_resource.get_local_path()?;
+        let weights_path = config.model_resource.get_local_path()?;
+        let mut var_store = VarStore::new(config.device);
+        let model_config = BertConfig::from_file(config_path);
+        let model = BertForIntentSlotClassification::new(
+            &var_store.root(),
+            &model_config,
+            config.intent_labels.len() as i64,
+            config.slot_labels.len() as i64,
+        );
+        var_store.load(weights_path)?;
+
+        Ok(IntentSlotModel {
+            tokenizer,
+            model,
+            var_store,
+            intent_labels: config.intent_labels,
+            slot_labels: config.slot_labels,
+            max_length: model_config.max_position_embeddings as usize,
+        })
+    }
+
+    /// Predict the intent and slots of utterances
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - `&[&str]` Array of utterances to process.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<IntentSlotOutput>` containing the intent and slots for each input utterance
+    pub fn predict<S>(&self, input: &[S]) -> Vec<IntentSlotOutput>
+    where
+        S: AsRef<str>,
+    {
+        if input.is_empty() {
+            return vec![];
+        }
+        let tokenized_input: Vec<TokenizedInput> = self.tokenizer.encode_list(
+            input,
+            self.max_length,
+            &TruncationStrategy::LongestFirst,
+            0,
+        );
+        let max_len = tokenized_input
+            .iter()
+            .map(|input| input.token_ids.len())
+            .max()
+            .unwrap_or(0);
+        let pad_id = self.tokenizer.get_pad_id().unwrap_or(0);
+        let (input_ids, attention_masks): (Vec<Tensor>, Vec<Tensor>) = tokenized_input
+            .iter()
+            .map(|input| {
+                let mut attention_mask = vec![1i64; input.token_ids.len()];
+                attention_mask.resize(max_len, 0);
+                let mut token_ids = input.token_ids.clone();
+                token_ids.resize(max_len, pad_id);
+                (
+                    Tensor::of_slice(&token_ids),
+                    Tensor::of_slice(&attention_mask),
+                )
+            })
+            .unzip();
+        let device = self.var_store.device();
+        let input_ids = Tensor::stack(&input_ids, 0).to(device);
+        let attention_mask = Tensor::stack(&attention_masks, 0).to(device);
+
+        let (intent_scores, slot_scores) = no_grad(|| {
+            let output = self.model.forward_t(
+                Some(&input_ids),
+                Some(&attention_mask),
+                None,
+                None,
+                None,
+                false,
+            );
+            (
+                output
+                    .intent_logits
+                    .softmax(-1, Kind::Float)
+                    .to(Device::Cpu),
+                output.slot_logits.softmax(-1, Kind::Float).to(Device::Cpu),
+            )
+        });
+        let (intent_scores, intent_ids) = intent_scores.max_dim(-1, false);
+        let (slot_scores, slot_ids) = slot_scores.max_dim(-1, false);
+
+        let mut output = Vec::with_capacity(input.len());
+        for (sentence, (text, tokens)) in input.iter().zip(tokenized_input.iter()).enumerate() {
+            let id = intent_ids.int64_value(&[sentence as i64]);
+            let intent = Label {
+                text: self.intent_labels[id as usize].clone(),
+                score: intent_scores.double_value(&[sentence as i64]),
+                id,
+                sentence,
+            };
+
+            let mut words: Vec<WordLabel> = vec![];
+            for (position, (offset, mask)) in tokens
+                .token_offsets
+                .iter()
+                .zip(tokens.mask.iter())
+                .enumerate()
+            {
+                let offset = match (offset, mask) {
+                    (Some(offset), mask) if *mask != Mask::Special => offset,
+                    _ => continue,
+                };
+                if *mask == Mask::Continuation {
+                    if let Some(word) = words.last_mut() {
+                        word.offset.end = offset.end;
+                        continue;
+                    }
+                }
+                let label_id = slot_ids.int64_value(&[sentence as i64, position as i64]);
+                words.push(WordLabel {
+                    label: self.slot_labels[label_id as usize].as_str(),
+                    score: slot_scores.double_value(&[sentence as i64, position as i64]),
+                    offset: *offset,
+                });
+            }
+            output.push(IntentSlotOutput {
+                intent,
+                slots: decode_slots(text.as_ref(), &words),
+            });
+        }
+        output
+    }
+}
+
+/// Slot label predicted for a word
+pub(crate) struct WordLabel<'a> {
+    pub(crate) label: &'a str,
+    pub(crate) score: f64,
+    pub(crate) offset: Offset,
+}
+
+/// Merges the BIO labels of consecutive words into slots. Labels without a `B-` or `I-` prefix
+/// (other than `O`) are treated as inside labels.
+pub(crate) fn decode_slots(text: &str, words: &[WordLabel]) -> Vec<Slot> {
+    let mut spans: Vec<(&str, Offset, Vec<f64>)> = vec![];
+    let mut inside = false;
+    for word in words {
+        let (is_begin, slot_type) = if word.label == "O" {
+            inside = false;
+            continue;
+        } else if let Some(slot_type) = word.label.strip_prefix("B-") {
+            (true, slot_type)
+        } else {
+            (false, word.label.strip_prefix("I-").unwrap_or(word.label))
+        };
+        match spans.last_mut() {
+            Some((current_type, offset, scores))
+                if inside & !is_begin & (*current_type == slot_type) =>
+            {
+                offset.end = word.offset.end;
+                scores.push(word.score);
+            }
+            _ => spans.push((slot_type, word.offset, vec![word.score])),
+        }
+        inside = true;
+    }
+
+    spans
+        .into_iter()
+        .map(|(slot_type, offset, scores)| Slot {
+            slot_type: slot_type.to_string(),
+            text: text
+                .chars()
+                .skip(offset.begin as usize)
+                .take((offset.end - offset.begin) as usize)
+                .collect(),
+            score: scores.iter().sum::<f64>() / scores.len() as f64,
+            offset,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bio_slot_decoding() {
+        let text = "fly from new york to paris";
+        let words = [
+            ("O", 0, 3),
+            ("O", 4, 8),
+            ("B-fromloc", 9, 12),
+            ("I-fromloc", 13, 17),
+            ("O", 18, 20),
+            ("I-toloc", 21, 26),
+        ]
+        .iter()
+        .map(|&(label, begin, end)| WordLabel {
+            label,
+            score: 0.5,
+            offset: Offset::new(begin, end),
+        })
+        .collect::<Vec<WordLabel>>();
+
+        let slots = decode_slots(text, &words);
+        assert_eq!(slots.len(), 2);
+        assert_eq!(slots[0].slot_type, "fromloc");
+        assert_eq!(slots[0].text, "new york");
+        assert_eq!(slots[0].offset, Offset::new(9, 17));
+        assert_eq!(slots[1].slot_type, "toloc");
+        assert_eq!(slots[1].text, "paris");
+        assert!((slots[1].score - 0.5).abs() < 1e-9);
+
+        let words = [("B-city", 0, 3), ("B-city", 4, 8), ("I-date", 9, 12)]
+            .iter()
+            .map(|&(label, begin, end)| WordLabel {
+                label,
+                score: 1.0,
+                offset: Offset::new(begin, end),
+            })
+            .collect::<Vec<WordLabel>>();
+        let slots = decode_slots(text, &words);
+        assert_eq!(slots.len(), 3);
+        assert_eq!(slots[1].text, "from");
+        assert_eq!(slots[2].slot_type, "date");
+    }
+}
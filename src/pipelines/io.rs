@@ -0,0 +1,534 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Batch inference on JSONL and CSV files
+//! Helpers to run a pipeline on large files of records without custom harness code:
+//! - `RecordReader` streams records from a JSONL file (one JSON object per line) or a CSV file
+//! (with a header row)
+//! - `RecordWriter` writes records to a JSONL or CSV file
+//! - `map_records` and `map_file` read the text of a field of each record, run the pipeline on
+//! batches of texts and write the records augmented with the pipeline output
+//!
+//! The pipeline is provided as a closure mapping a batch of texts to one JSON value per text, which
+//! allows using any pipeline (or `pipeline_predictor` for a `Pipeline` of the registry). In CSV
+//! files, outputs that are not strings are written as JSON. CSV files require the `csv` feature.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::io::map_file;
+//! use rust_bert::pipelines::sentiment::SentimentModel;
+//!
+//! let model = SentimentModel::new(Default::default())?;
+//! let num_records = map_file(
+//!     "reviews.jsonl",
+//!     "reviews_scored.jsonl",
+//!     "text",
+//!     "sentiment",
+//!     32,
+//!     |texts| {
+//!         Ok(model
+//!             .predict(texts)
+//!             .into_iter()
+//!             .map(|sentiment| serde_json::to_value(sentiment).unwrap())
+//!             .collect())
+//!     },
+//! )?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::pipelines::registry::Pipeline;
+use crate::RustBertError;
+use serde_json::{Map, Value};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Record read from or written to a file, mapping field names to values
+pub type Record = Map<String, Value>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// # File format of records
+pub enum RecordFormat {
+    /// One JSON object per line
+    Jsonl,
+    /// Comma-separated values with a header row
+    #[cfg(feature = "csv")]
+    Csv,
+}
+
+impl RecordFormat {
+    /// Infers the format from the extension of a file (`.csv` for CSV, `.jsonl`, `.ndjson` or
+    /// `.json` for JSONL)
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<RecordFormat, RustBertError> {
+        let path = path.as_ref();
+        match path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase())
+            .as_deref()
+        {
+            #[cfg(feature = "csv")]
+            Some("csv") => Ok(RecordFormat::Csv),
+            #[cfg(not(feature = "csv"))]
+            Some("csv") => Err(RustBertError::InvalidConfigurationError(
+                "CSV files require the `csv` feature".to_string(),
+            )),
+            Some("jsonl") | Some("ndjson") | Some("json") => Ok(RecordFormat::Jsonl),
+            _ => Err(RustBertError::InvalidConfigurationError(format!(
+                "Could not infer the record format of {}, expected a .jsonl or .csv file",
+                path.display()
+            ))),
+        }
+    }
+}
+
+/// # Streaming reader of records
+/// Iterates over the records of a JSONL or CSV input. Empty lines are skipped.
+pub struct RecordReader<R: BufRead> {
+    input: RecordInput<R>,
+}
+
+enum RecordInput<R: BufRead> {
+    Jsonl {
+        reader: R,
+        line_number: usize,
+    },
+    #[cfg(feature = "csv")]
+    Csv {
+        reader: csv::Reader<R>,
+        columns: Vec<String>,
+    },
+}
+
+impl RecordReader<BufReader<File>> {
+    /// Opens a file, inferring its format from its extension
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, RustBertError> {
+        let format = RecordFormat::from_path(&path)?;
+        RecordReader::new(BufReader::new(File::open(path)?), format)
+    }
+}
+
+impl<R: BufRead> RecordReader<R> {
+    /// Creates a new reader. For CSV inputs, the header row is read immediately.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - input to read the records from
+    /// * `format` - `RecordFormat` of the input
+    pub fn new(reader: R, format: RecordFormat) -> Result<Self, RustBertError> {
+        let input = match format {
+            RecordFormat::Jsonl => RecordInput::Jsonl {
+                reader,
+                line_number: 0,
+            },
+            #[cfg(feature = "csv")]
+            RecordFormat::Csv => {
+                let mut reader = csv::Reader::from_reader(reader);
+                let columns = reader
+                    .headers()?
+                    .iter()
+                    .map(String::from)
+                    .collect::<Vec<String>>();
+                if columns.is_empty() {
+                    return Err(RustBertError::ValueError(
+                        "Missing header row in CSV input".to_string(),
+                    ));
+                }
+                RecordInput::Csv { reader, columns }
+            }
+        };
+        Ok(RecordReader { input })
+    }
+
+    /// Returns the format of the input
+    pub fn format(&self) -> RecordFormat {
+        match self.input {
+            RecordInput::Jsonl { .. } => RecordFormat::Jsonl,
+            #[cfg(feature = "csv")]
+            RecordInput::Csv { .. } => RecordFormat::Csv,
+        }
+    }
+
+    /// Returns the columns of a CSV input (`None` for JSONL inputs)
+    pub fn columns(&self) -> Option<&[String]> {
+        match &self.input {
+            RecordInput::Jsonl { .. } => None,
+            #[cfg(feature = "csv")]
+            RecordInput::Csv { columns, .. } => Some(columns),
+        }
+    }
+
+    fn read_record(&mut self) -> Result<Option<Record>, RustBertError> {
+        match &mut self.input {
+            RecordInput::Jsonl {
+                reader,
+                line_number,
+            } => {
+                let mut line = String::new();
+                loop {
+                    line.clear();
+                    if reader.read_line(&mut line)? == 0 {
+                        return Ok(None);
+                    }
+                    *line_number += 1;
+                    if !line.trim().is_empty() {
+                        break;
+                    }
+                }
+                match serde_json::from_str::<Value>(&line) {
+                    Ok(Value::Object(record)) => Ok(Some(record)),
+                    Ok(_) => Err(RustBertError::ValueError(format!(
+                        "Line {} of JSONL input is not a JSON object",
+                        line_number
+                    ))),
+                    Err(error) => Err(RustBertError::ValueError(format!(
+                        "Invalid JSON at line {} of JSONL input: {}",
+                        line_number, error
+                    ))),
+                }
+            }
+            #[cfg(feature = "csv")]
+            RecordInput::Csv { reader, columns } => {
+                let mut fields = csv::StringRecord::new();
+                if !reader.read_record(&mut fields)? {
+                    return Ok(None);
+                }
+                Ok(Some(
+                    columns
+                        .iter()
+                        .cloned()
+                        .zip(fields.iter().map(|field| Value::String(field.to_string())))
+                        .collect(),
+                ))
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for RecordReader<R> {
+    type Item = Result<Record, RustBertError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+/// # Writer of records
+/// Writes records to a JSONL or CSV output. The columns of a CSV output are either provided with
+/// `with_columns` or taken from the first record written.
+pub struct RecordWriter<W: Write> {
+    output: RecordOutput<W>,
+}
+
+enum RecordOutput<W: Write> {
+    Jsonl(W),
+    #[cfg(feature = "csv")]
+    Csv {
+        writer: csv::Writer<W>,
+        columns: Option<Vec<String>>,
+        header_written: bool,
+    },
+}
+
+impl RecordWriter<BufWriter<File>> {
+    /// Creates (or truncates) a file, inferring its format from its extension
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, RustBertError> {
+        let format = RecordFormat::from_path(&path)?;
+        Ok(RecordWriter::new(
+            BufWriter::new(File::create(path)?),
+            format,
+        ))
+    }
+}
+
+impl<W: Write> RecordWriter<W> {
+    /// Creates a new writer
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - output to write the records to
+    /// * `format` - `RecordFormat` of the output
+    pub fn new(writer: W, format: RecordFormat) -> Self {
+        let output = match format {
+            RecordFormat::Jsonl => RecordOutput::Jsonl(writer),
+            #[cfg(feature = "csv")]
+            RecordFormat::Csv => RecordOutput::Csv {
+                writer: csv::Writer::from_writer(writer),
+                columns: None,
+                header_written: false,
+            },
+        };
+        RecordWriter { output }
+    }
+
+    /// Sets the columns of a CSV output (ignored for JSONL outputs)
+    #[cfg(feature = "csv")]
+    pub fn with_columns(mut self, columns: Vec<String>) -> Self {
+        if let RecordOutput::Csv {
+            columns: output_columns,
+            ..
+        } = &mut self.output
+        {
+            *output_columns = Some(columns);
+        }
+        self
+    }
+
+    /// Sets the columns of a CSV output without columns to the columns of the CSV input, followed
+    /// by the output field
+    #[cfg(feature = "csv")]
+    fn with_input_columns(&mut self, input_columns: &[String], output_field: &str) {
+        if let RecordOutput::Csv {
+            columns: output_columns,
+            ..
+        } = &mut self.output
+        {
+            if output_columns.is_none() {
+                let mut columns = input_columns.to_vec();
+                if !columns.iter().any(|column| column == output_field) {
+                    columns.push(output_field.to_string());
+                }
+                *output_columns = Some(columns);
+            }
+        }
+    }
+
+    /// Writes a record. Missing CSV fields are written as empty values.
+    pub fn write(&mut self, record: &Record) -> Result<(), RustBertError> {
+        match &mut self.output {
+            RecordOutput::Jsonl(writer) => {
+                serde_json::to_writer(&mut *writer, record)?;
+                writeln!(writer)?;
+            }
+            #[cfg(feature = "csv")]
+            RecordOutput::Csv {
+                writer,
+                columns,
+                header_written,
+            } => {
+                let columns = columns.get_or_insert_with(|| record.keys().cloned().collect());
+                if !*header_written {
+                    writer.write_record(columns.iter())?;
+                    *header_written = true;
+                }
+                for column in columns.iter() {
+                    match record.get(column) {
+                        None | Some(Value::Null) => writer.write_field("")?,
+                        Some(Value::String(value)) => writer.write_field(value)?,
+                        Some(value) => writer.write_field(value.to_string())?,
+                    }
+                }
+                writer.write_record(None::<&[u8]>)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Flushes the output
+    pub fn flush(&mut self) -> Result<(), RustBertError> {
+        match &mut self.output {
+            RecordOutput::Jsonl(writer) => writer.flush()?,
+            #[cfg(feature = "csv")]
+            RecordOutput::Csv { writer, .. } => writer.flush()?,
+        }
+        Ok(())
+    }
+}
+
+/// Runs a pipeline on a field of each record and writes the records augmented with the pipeline
+/// output. Records are processed by batches of `batch_size` records. Returns the number of records
+/// processed.
+///
+/// # Arguments
+///
+/// * `reader` - `RecordReader` providing the input records
+/// * `writer` - `RecordWriter` receiving the output records
+/// * `input_field` - name of the field containing the text to process (non-string values are converted to JSON strings)
+/// * `output_field` - name of the field the output is written to
+/// * `batch_size` - number of texts passed to the pipeline at once
+/// * `predict` - closure running the pipeline on a batch of texts, returning one value per text
+pub fn map_records<R, W, F>(
+    reader: RecordReader<R>,
+    writer: &mut RecordWriter<W>,
+    input_field: &str,
+    output_field: &str,
+    batch_size: usize,
+    mut predict: F,
+) -> Result<usize, RustBertError>
+where
+    R: BufRead,
+    W: Write,
+    F: FnMut(&[&str]) -> Result<Vec<Value>, RustBertError>,
+{
+    if batch_size == 0 {
+        return Err(RustBertError::InvalidConfigurationError(
+            "The batch size must be greater than 0".to_string(),
+        ));
+    }
+    #[cfg(feature = "csv")]
+    if let Some(columns) = reader.columns() {
+        writer.with_input_columns(columns, output_field);
+    }
+
+    let mut num_records = 0;
+    let mut batch: Vec<Record> = Vec::with_capacity(batch_size);
+    for record in reader {
+        batch.push(record?);
+        if batch.len() == batch_size {
+            num_records +=
+                process_batch(&mut batch, writer, input_field, output_field, &mut predict)?;
+        }
+    }
+    num_records += process_batch(&mut batch, writer, input_field, output_field, &mut predict)?;
+    writer.flush()?;
+    Ok(num_records)
+}
+
+fn process_batch<W, F>(
+    batch: &mut Vec<Record>,
+    writer: &mut RecordWriter<W>,
+    input_field: &str,
+    output_field: &str,
+    predict: &mut F,
+) -> Result<usize, RustBertError>
+where
+    W: Write,
+    F: FnMut(&[&str]) -> Result<Vec<Value>, RustBertError>,
+{
+    if batch.is_empty() {
+        return Ok(0);
+    }
+    let texts = batch
+        .iter()
+        .map(|record| match record.get(input_field) {
+            Some(Value::String(text)) => Ok(text.clone()),
+            Some(value) => Ok(value.to_string()),
+            None => Err(RustBertError::ValueError(format!(
+                "Field `{}` not found in record",
+                input_field
+            ))),
+        })
+        .collect::<Result<Vec<String>, RustBertError>>()?;
+    let texts = texts.iter().map(String::as_str).collect::<Vec<&str>>();
+    let outputs = predict(&texts)?;
+    if outputs.len() != batch.len() {
+        return Err(RustBertError::ValueError(format!(
+            "The pipeline returned {} outputs for {} inputs",
+            outputs.len(),
+            batch.len()
+        )));
+    }
+    let num_records = batch.len();
+    for (mut record, output) in batch.drain(..).zip(outputs) {
+        record.insert(output_field.to_string(), output);
+        writer.write(&record)?;
+    }
+    Ok(num_records)
+}
+
+/// Runs a pipeline on a field of each record of a JSONL or CSV file and writes the augmented
+/// records to another file (formats are inferred from the file extensions). Returns the number of
+/// records processed. See `map_records` for a description of the arguments.
+pub fn map_file<P, Q, F>(
+    input_path: P,
+    output_path: Q,
+    input_field: &str,
+    output_field: &str,
+    batch_size: usize,
+    predict: F,
+) -> Result<usize, RustBertError>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    F: FnMut(&[&str]) -> Result<Vec<Value>, RustBertError>,
+{
+    let reader = RecordReader::from_file(input_path)?;
+    let mut writer = RecordWriter::from_file(output_path)?;
+    map_records(
+        reader,
+        &mut writer,
+        input_field,
+        output_field,
+        batch_size,
+        predict,
+    )
+}
+
+/// Returns a closure running a `Pipeline` of the registry on a batch of texts, to be used with
+/// `map_records` or `map_file`. Supported by the pipelines taking a list of texts as input (see
+/// `Pipeline::predict_json`).
+pub fn pipeline_predictor(
+    pipeline: &Pipeline,
+) -> impl FnMut(&[&str]) -> Result<Vec<Value>, RustBertError> + '_ {
+    move |texts| match pipeline.predict_json(&Value::from(texts.to_vec()))? {
+        Value::Array(outputs) => Ok(outputs),
+        _ => Err(RustBertError::ValueError(format!(
+            "{:?} pipeline does not return one output per input",
+            pipeline.task()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "csv")]
+    fn map_csv_records() -> Result<(), RustBertError> {
+        let input = "id,text\n1,\"hello, \"\"world\"\"\"\n\n2,\"multi\nline\"\n3,plain\n";
+        let reader = RecordReader::new(input.as_bytes(), RecordFormat::Csv)?;
+        let mut output = vec![];
+        let mut writer = RecordWriter::new(&mut output, RecordFormat::Csv);
+        let num_records = map_records(reader, &mut writer, "text", "length", 2, |texts| {
+            Ok(texts.iter().map(|text| Value::from(text.len())).collect())
+        })?;
+        drop(writer);
+
+        assert_eq!(num_records, 3);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "id,text,length\n1,\"hello, \"\"world\"\"\",14\n2,\"multi\nline\",10\n3,plain,5\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn map_jsonl_records() -> Result<(), RustBertError> {
+        let input = "{\"text\": \"ab\", \"id\": 1}\n\n{\"text\": 3}\n";
+        let reader = RecordReader::new(input.as_bytes(), RecordFormat::Jsonl)?;
+        let mut output = vec![];
+        let mut writer = RecordWriter::new(&mut output, RecordFormat::Jsonl);
+        map_records(reader, &mut writer, "text", "output", 8, |texts| {
+            Ok(texts
+                .iter()
+                .map(|text| Value::from(text.to_uppercase()))
+                .collect())
+        })?;
+        drop(writer);
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"id\":1,\"output\":\"AB\",\"text\":\"ab\"}\n{\"output\":\"3\",\"text\":3}\n"
+        );
+
+        let reader = RecordReader::new("{\"id\": 1}".as_bytes(), RecordFormat::Jsonl)?;
+        let mut writer = RecordWriter::new(vec![], RecordFormat::Jsonl);
+        assert!(
+            map_records(reader, &mut writer, "text", "output", 8, |texts| {
+                Ok(texts.iter().map(|_| Value::Null).collect())
+            })
+            .is_err()
+        );
+        Ok(())
+    }
+}
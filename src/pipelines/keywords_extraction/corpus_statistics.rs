@@ -0,0 +1,97 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// # Document frequency statistics collected over a corpus
+/// Maintained by the `KeywordExtractionModel` in streaming mode to re-rank keywords by their
+/// informativeness at the corpus level. The statistics can be serialized to resume the processing
+/// of a feed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CorpusStatistics {
+    num_documents: usize,
+    document_frequencies: HashMap<String, usize>,
+}
+
+impl CorpusStatistics {
+    /// Creates empty corpus statistics
+    pub fn new() -> CorpusStatistics {
+        Default::default()
+    }
+
+    /// Registers a document, given as the list of its keyword candidates. Candidates occurring
+    /// several times in the document are counted once.
+    pub fn add_document<S: AsRef<str>>(&mut self, terms: impl IntoIterator<Item = S>) {
+        let terms = terms
+            .into_iter()
+            .map(|term| term.as_ref().to_string())
+            .collect::<HashSet<String>>();
+        for term in terms {
+            *self.document_frequencies.entry(term).or_insert(0) += 1;
+        }
+        self.num_documents += 1;
+    }
+
+    /// Number of documents registered
+    pub fn num_documents(&self) -> usize {
+        self.num_documents
+    }
+
+    /// Number of documents containing a term
+    pub fn document_frequency(&self, term: &str) -> usize {
+        self.document_frequencies.get(term).copied().unwrap_or(0)
+    }
+
+    /// Smoothed inverse document frequency of a term: `ln((1 + N) / (1 + df)) + 1`
+    pub fn idf(&self, term: &str) -> f32 {
+        ((1.0 + self.num_documents as f32) / (1.0 + self.document_frequency(term) as f32)).ln()
+            + 1.0
+    }
+
+    /// Inverse document frequency of a term normalized by the maximum possible value (term not
+    /// seen in the corpus), in the range (0, 1].
+    pub fn informativeness(&self, term: &str) -> f32 {
+        self.idf(term) / ((1.0 + self.num_documents as f32).ln() + 1.0)
+    }
+
+    /// Clears the statistics
+    pub fn reset(&mut self) {
+        self.num_documents = 0;
+        self.document_frequencies.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn document_frequencies() {
+        let mut corpus_statistics = CorpusStatistics::new();
+        corpus_statistics.add_document(["rust", "language", "rust"]);
+        corpus_statistics.add_document(["rust", "memory"]);
+        corpus_statistics.add_document(["python"]);
+
+        assert_eq!(corpus_statistics.num_documents(), 3);
+        assert_eq!(corpus_statistics.document_frequency("rust"), 2);
+        assert_eq!(corpus_statistics.document_frequency("memory"), 1);
+        assert_eq!(corpus_statistics.document_frequency("java"), 0);
+        assert!((corpus_statistics.informativeness("java") - 1.0).abs() < 1e-6);
+        assert!(
+            corpus_statistics.informativeness("rust") < corpus_statistics.informativeness("memory")
+        );
+
+        corpus_statistics.reset();
+        assert_eq!(corpus_statistics.num_documents(), 0);
+        assert_eq!(corpus_statistics.document_frequency("rust"), 0);
+    }
+}
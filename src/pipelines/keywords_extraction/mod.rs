@@ -1,6 +1,8 @@
+mod corpus_statistics;
 mod pipeline;
 mod scorer;
 mod stopwords;
 mod tokenizer;
 
+pub use corpus_statistics::CorpusStatistics;
 pub use pipeline::{Keyword, KeywordExtractionConfig, KeywordExtractionModel, KeywordScorerType};
@@ -20,6 +20,7 @@
 /// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 /// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 /// SOFTWARE.
+use crate::pipelines::keywords_extraction::corpus_statistics::CorpusStatistics;
 use crate::pipelines::keywords_extraction::tokenizer::StopWordsTokenizer;
 #[cfg(feature = "remote")]
 use crate::pipelines::sentence_embeddings::SentenceEmbeddingsModelType;
@@ -30,12 +31,13 @@ use crate::pipelines::sentence_embeddings::{
 use crate::{Config, RustBertError};
 use regex::Regex;
 use rust_tokenizers::Offset;
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::cmp::min;
 use std::collections::{HashMap, HashSet};
 
 /// # Keyword generated by a `KeywordExtractionModel`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Keyword {
     /// String representation of the keyword
     pub text: String,
@@ -46,6 +48,7 @@ pub struct Keyword {
 }
 
 /// # Scoring function variants for keyword ranking
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum KeywordScorerType {
     /// Cosine similarity ranker, computing score as the dot product of the normalized
     /// vector representations for the document and keywords from the sentence embedding model
@@ -64,13 +67,17 @@ pub enum KeywordScorerType {
     MaxSum,
 }
 
+#[derive(Serialize, Deserialize)]
 /// # Configuration for Keyword extraction
+/// The tokenizer stopwords and pattern are not serialized and default to `None` upon deserialization.
 pub struct KeywordExtractionConfig<'a> {
     /// `SentenceEmbeddingsConfig` defining the sentence embeddings model to use
     pub sentence_embeddings_config: SentenceEmbeddingsConfig,
     /// Optional list of tokenizer stopwords to exclude from the keywords candidate list. Default to a list of English stopwords.
+    #[serde(skip)]
     pub tokenizer_stopwords: Option<HashSet<&'a str>>,
     /// Optional tokenization regex pattern. Defaults to sequence of word characters.
+    #[serde(skip)]
     pub tokenizer_pattern: Option<Regex>,
     /// `KeywordScorerType` used to rank keywords.
     pub scorer_type: KeywordScorerType,
@@ -86,6 +93,10 @@ pub struct KeywordExtractionConfig<'a> {
     /// identify a global optimum for the ranker criterion, but are more likely to include sets that are less relevant to the
     /// input document. Larger values also have a higher computational and memory cost (N<sup>2</sup> scale)
     pub max_sum_candidates: Option<usize>,
+    /// Optional weight of the corpus-level informativeness (inverse document frequency) when re-ranking
+    /// keywords in streaming mode, defaults to 1.0. The keyword scores are multiplied by their normalized
+    /// inverse document frequency raised to this power: 0.0 disables the re-ranking.
+    pub corpus_statistics_weight: Option<f32>,
 }
 
 #[cfg(feature = "remote")]
@@ -103,6 +114,7 @@ impl Default for KeywordExtractionConfig<'_> {
             num_keywords: 5,
             diversity: None,
             max_sum_candidates: None,
+            corpus_statistics_weight: None,
         }
     }
 }
@@ -121,6 +133,8 @@ pub struct KeywordExtractionModel<'a> {
     num_keywords: usize,
     diversity: Option<f64>,
     max_sum_candidates: Option<usize>,
+    corpus_statistics: CorpusStatistics,
+    corpus_statistics_weight: f32,
 }
 
 impl<'a> KeywordExtractionModel<'a> {
@@ -175,6 +189,8 @@ impl<'a> KeywordExtractionModel<'a> {
             num_keywords: config.num_keywords,
             diversity: config.diversity,
             max_sum_candidates: config.max_sum_candidates,
+            corpus_statistics: CorpusStatistics::new(),
+            corpus_statistics_weight: config.corpus_statistics_weight.unwrap_or(1.0),
         })
     }
 
@@ -208,6 +224,74 @@ impl<'a> KeywordExtractionModel<'a> {
         S: AsRef<str> + Sync,
     {
         let words = self.tokenizer.tokenize_list(inputs, self.ngram_range);
+        self.extract_keywords(inputs, &words, None)
+    }
+
+    /// Extract keywords from a list of input texts in streaming mode. The input texts are added
+    /// to the corpus statistics (document frequencies) maintained by the model across calls, and
+    /// the keywords are re-ranked by their informativeness at the corpus level: keywords common to
+    /// many documents of the corpus are penalized. This allows processing large feeds document by
+    /// document, the ranking improving as the corpus statistics grow.
+    ///
+    /// # Arguments
+    ///
+    /// * `inputs` - slice of string-like input texts to extract keywords from
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<Vec<Keyword>>, RustBertError>` containing a list of keyword for each input text
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::keywords_extraction::KeywordExtractionModel;
+    ///
+    /// let mut keyword_extraction_model = KeywordExtractionModel::new(Default::default())?;
+    /// let feed = [
+    ///     "This is a first document to extract keywords from.",
+    ///     "Some keywords will be extracted from this document too.",
+    /// ];
+    /// for document in feed {
+    ///     let output = keyword_extraction_model.predict_streaming(&[document])?;
+    /// }
+    /// let num_documents = keyword_extraction_model
+    ///     .corpus_statistics()
+    ///     .num_documents();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn predict_streaming<S>(&mut self, inputs: &[S]) -> Result<Vec<Vec<Keyword>>, RustBertError>
+    where
+        S: AsRef<str> + Sync,
+    {
+        let words = self.tokenizer.tokenize_list(inputs, self.ngram_range);
+        for document_words in &words {
+            self.corpus_statistics.add_document(document_words.keys());
+        }
+        self.extract_keywords(inputs, &words, Some(&self.corpus_statistics))
+    }
+
+    /// Returns the corpus statistics collected in streaming mode
+    pub fn corpus_statistics(&self) -> &CorpusStatistics {
+        &self.corpus_statistics
+    }
+
+    /// Replaces the corpus statistics used in streaming mode (e.g. to resume the processing of a
+    /// feed from previously saved statistics)
+    pub fn set_corpus_statistics(&mut self, corpus_statistics: CorpusStatistics) {
+        self.corpus_statistics = corpus_statistics;
+    }
+
+    fn extract_keywords<S>(
+        &self,
+        inputs: &[S],
+        words: &[HashMap<Cow<str>, Vec<Offset>>],
+        corpus_statistics: Option<&CorpusStatistics>,
+    ) -> Result<Vec<Vec<Keyword>>, RustBertError>
+    where
+        S: AsRef<str> + Sync,
+    {
         let (flat_word_list, document_boundaries) =
             KeywordExtractionModel::flatten_word_list(&words);
 
@@ -229,7 +313,12 @@ impl<'a> KeywordExtractionModel<'a> {
             let word_embeddings = word_embeddings
                 .embeddings
                 .slice(0, start as i64, end as i64, 1);
-            let num_keywords = min(self.num_keywords, word_embeddings.size()[0] as usize);
+            // Corpus-level re-ranking of the cosine similarity ranker considers a larger candidate pool
+            let num_candidates = match (corpus_statistics, self.scorer_type) {
+                (Some(_), KeywordScorerType::CosineSimilarity) => self.num_keywords * 3,
+                _ => self.num_keywords,
+            };
+            let num_keywords = min(num_candidates, word_embeddings.size()[0] as usize);
             let local_top_word_indices = self.scorer_type.score_keywords(
                 document_embedding,
                 word_embeddings,
@@ -237,8 +326,13 @@ impl<'a> KeywordExtractionModel<'a> {
                 self.diversity,
                 self.max_sum_candidates,
             );
-            for (index, score) in local_top_word_indices {
+            for (index, mut score) in local_top_word_indices {
                 let word = flat_word_list[start + index];
+                if let Some(corpus_statistics) = corpus_statistics {
+                    score *= corpus_statistics
+                        .informativeness(word)
+                        .powf(self.corpus_statistics_weight);
+                }
                 document_keywords.push(Keyword {
                     text: word.to_string(),
                     score,
@@ -246,6 +340,7 @@ impl<'a> KeywordExtractionModel<'a> {
                 });
             }
             document_keywords.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+            document_keywords.truncate(self.num_keywords);
             output_keywords.push(document_keywords)
         }
 
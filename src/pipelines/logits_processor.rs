@@ -0,0 +1,326 @@
+// Copyright 2020 The Facebook AI Research Team Authors
+// Copyright 2020-present, the HuggingFace Inc. team.
+// Copyright 2020 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Logits processors for text generation
+//! Transformations applied to the scores of the next token at each step of the generation. The
+//! repetition penalty, n-gram repetition ban and top-k/top-p/min-p filtering of the `GenerateConfig` are
+//! implemented as `LogitsProcessor`s, and custom processors can be registered in the
+//! `logits_processors` of the `GenerateConfig` to transform the scores without modifying the
+//! generation loop.
+//!
+//! Custom processors are applied after the built-in penalties and before the generation
+//! constraints (prefix allowed tokens, minimum length, forced BOS/EOS tokens), so that the
+//! constraints always hold. The scores are the logits of the next token for greedy decoding and
+//! sampling, and their log-probabilities for beam search.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::gpt2::GPT2Generator;
+//! use rust_bert::pipelines::generation_utils::{GenerateConfig, LanguageGenerator};
+//! use rust_bert::pipelines::logits_processor::LogitsProcessor;
+//! use tch::Tensor;
+//!
+//! /// Bans the token ids below 256
+//! struct BanByteTokens;
+//!
+//! impl LogitsProcessor for BanByteTokens {
+//!     fn process(&self, _input_ids: &Tensor, scores: &mut Tensor, _current_length: i64) {
+//!         let _ = scores.narrow(1, 0, 256).fill_(f64::NEG_INFINITY);
+//!     }
+//! }
+//!
+//! let generate_config = GenerateConfig {
+//!     logits_processors: vec![Box::new(BanByteTokens)],
+//!     ..Default::default()
+//! };
+//! let model = GPT2Generator::new(generate_config)?;
+//! let output = model.generate(Some(&["The dog"]), None);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::cmp::{max, min};
+use std::collections::HashMap;
+use tch::{Device, Kind, Tensor};
+
+/// # Transformation of the next token scores
+/// Processors must be `Send + Sync` to be stored in the `GenerateConfig` of a generator.
+pub trait LogitsProcessor: Send + Sync {
+    /// Transforms the scores of the next token in place
+    ///
+    /// # Arguments
+    ///
+    /// * `input_ids` - `Tensor` of shape (*number of sequences*, *sequence_length*) with the tokens of the sequences (prompt and generated tokens)
+    /// * `scores` - `Tensor` of shape (*number of sequences*, *vocab_size*) with the scores of the next token
+    /// * `current_length` - current length of the sequences
+    fn process(&self, input_ids: &Tensor, scores: &mut Tensor, current_length: i64);
+}
+
+/// # Repetition penalty
+/// Divides (resp. multiplies) the positive (resp. negative) scores of the tokens already present
+/// in the sequence by `penalty`, as proposed by [Keskar et al.](https://arxiv.org/abs/1909.05858).
+pub struct RepetitionPenaltyLogitsProcessor {
+    /// Penalty applied to the repeated tokens. Values higher than 1 penalize repetitions.
+    pub penalty: f64,
+}
+
+impl LogitsProcessor for RepetitionPenaltyLogitsProcessor {
+    fn process(&self, input_ids: &Tensor, scores: &mut Tensor, _current_length: i64) {
+        for i in 0..scores.size()[0] {
+            for token_position in 0..input_ids.get(i).size()[0] {
+                let token = input_ids.get(i).int64_value(&[token_position]);
+                let updated_value = &scores.double_value(&[i, token]);
+                let _ = scores.get(i).index_fill_(
+                    0,
+                    &Tensor::of_slice(&[token])
+                        .to_kind(Kind::Int64)
+                        .to_device(scores.device()),
+                    if updated_value < &0f64 {
+                        updated_value * self.penalty
+                    } else {
+                        updated_value / self.penalty
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// # N-gram repetition ban
+/// Sets the score of the tokens that would repeat an n-gram of size `ngram_size` already present
+/// in the sequence to `-inf`.
+pub struct NoRepeatNGramLogitsProcessor {
+    /// Size of the n-grams that cannot be repeated
+    pub ngram_size: i64,
+}
+
+impl LogitsProcessor for NoRepeatNGramLogitsProcessor {
+    fn process(&self, input_ids: &Tensor, scores: &mut Tensor, current_length: i64) {
+        let banned_tokens = get_banned_tokens(input_ids, self.ngram_size, current_length);
+        for (batch_index, index_banned_token) in (0..banned_tokens.len() as i64).zip(banned_tokens)
+        {
+            let _ = scores.get(batch_index).index_fill_(
+                0,
+                &Tensor::of_slice(&index_banned_token).to_device(scores.device()),
+                f64::NEG_INFINITY,
+            );
+        }
+    }
+}
+
+fn get_banned_tokens(input_ids: &Tensor, no_repeat_ngram_size: i64, cur_len: i64) -> Vec<Vec<i64>> {
+    //        Ported from hugging face's transformers and fairseq (https://github.com/pytorch/fairseq/blob/master/fairseq/sequence_generator.py)
+    if cur_len + 1 < no_repeat_ngram_size {
+        vec![vec![]]
+    } else {
+        let input_ids = input_ids.to(Device::Cpu);
+        let num_hypothesis = *input_ids.size().first().unwrap();
+        let mut banned_tokens: Vec<Vec<i64>> = Vec::with_capacity(num_hypothesis as usize);
+        for hypothesis_index in 0..num_hypothesis {
+            let hypothesis_input_ids = input_ids.get(hypothesis_index);
+            let mut generated_ngram: HashMap<Vec<i64>, Vec<i64>> = HashMap::new();
+            let input: Vec<i64> = (0..hypothesis_input_ids.size1().unwrap()).collect();
+            let hypothesis_input_ids = hypothesis_input_ids
+                .iter::<i64>()
+                .unwrap()
+                .collect::<Vec<i64>>();
+            let query = &hypothesis_input_ids
+                [cur_len as usize + 1 - no_repeat_ngram_size as usize..]
+                .to_vec();
+            for ngram in input
+                .windows(no_repeat_ngram_size as usize)
+                .map(|win| (*win.first().unwrap(), *win.last().unwrap()))
+            {
+                let ngram = &hypothesis_input_ids[ngram.0 as usize..ngram.1 as usize + 1];
+                let key = ngram[..no_repeat_ngram_size as usize - 1].to_vec();
+                let value = *ngram.last().unwrap();
+                generated_ngram
+                    .entry(key)
+                    .or_insert_with(|| vec![value])
+                    .push(value);
+            }
+            let hypothesis_banned_tokens = match generated_ngram.get(query) {
+                Some(banned_tokens) => banned_tokens.clone(),
+                None => vec![],
+            };
+            banned_tokens.push(hypothesis_banned_tokens);
+        }
+        banned_tokens
+    }
+}
+
+/// # Top-k, nucleus and min-p filtering
+/// Keeps the `top_k` highest scores, the smallest set of tokens whose cumulative probability
+/// exceeds `top_p` ([Holtzman et al.](http://arxiv.org/abs/1904.09751)) and the tokens whose
+/// probability is at least `min_p` times the probability of the most likely token, setting the
+/// score of the other tokens to `-inf`. Since the min-p threshold is relative to the most likely
+/// token, it adapts to the confidence of the model and is less sensitive to the temperature than
+/// `top_p`.
+pub struct TopKTopPLogitsProcessor {
+    /// Number of highest scores kept. Values higher than 0 enable the filtering.
+    pub top_k: i64,
+    /// Cumulative probability of the tokens kept. Values lower than 1 enable the filtering.
+    pub top_p: f64,
+    /// Minimum probability of the tokens kept, relative to the most likely token. Values higher than 0 enable the filtering.
+    pub min_p: f64,
+    /// Minimum number of tokens kept by the filtering
+    pub min_tokens_to_keep: i64,
+}
+
+impl LogitsProcessor for TopKTopPLogitsProcessor {
+    fn process(&self, _input_ids: &Tensor, scores: &mut Tensor, _current_length: i64) {
+        //        Nucleus and top-k filtering introduced by Holtzman et al. (http://arxiv.org/abs/1904.09751)
+        //        Ported from https://gist.github.com/thomwolf/1a5a29f6962089e871b94cbd09daf317
+        let vocab_size = *scores.size().last().unwrap();
+        if self.top_k > 0 {
+            let top_k = vocab_size - min(max(self.top_k, self.min_tokens_to_keep), vocab_size);
+            let (_, indices_to_remove) = scores.topk(top_k, -1, false, false);
+            for index in 0..*scores.size().first().unwrap() {
+                let _ = scores.get(index).index_fill_(
+                    0,
+                    &indices_to_remove.get(index),
+                    f64::NEG_INFINITY,
+                );
+            }
+        }
+        if self.top_p < 1f64 {
+            let (sorted_logits, sorted_indices) = scores.sort(-1, true);
+            let cumulative_probabilities = sorted_logits
+                .softmax(-1, sorted_logits.kind())
+                .cumsum(-1, sorted_logits.kind());
+            let mut sorted_indices_to_remove =
+                cumulative_probabilities.ge(self.top_p).to_kind(Kind::Int64);
+            if self.min_tokens_to_keep > 1 {
+                let _ = sorted_indices_to_remove.index_fill_(
+                    1,
+                    &Tensor::arange_start(
+                        0,
+                        self.min_tokens_to_keep + 1,
+                        (Kind::Int64, scores.device()),
+                    ),
+                    0,
+                );
+            }
+            let _ = sorted_indices_to_remove.index_copy_(
+                1,
+                &Tensor::arange_start(1, vocab_size, (Kind::Int64, scores.device())),
+                &sorted_indices_to_remove
+                    .slice(1, 0, vocab_size - 1, 1)
+                    .copy(),
+            );
+            let _ = sorted_indices_to_remove.index_fill_(
+                1,
+                &Tensor::of_slice(&[0])
+                    .to_kind(Kind::Int64)
+                    .to_device(sorted_indices_to_remove.device()),
+                0,
+            );
+            let indices_to_remove = sorted_indices_to_remove
+                .scatter(1, &sorted_indices, &sorted_indices_to_remove)
+                .to_kind(Kind::Bool);
+            let _ = scores.masked_fill_(&indices_to_remove, f64::NEG_INFINITY);
+        }
+        if self.min_p > 0f64 {
+            let log_probabilities = scores.log_softmax(-1, scores.kind());
+            let (max_log_probabilities, _) = log_probabilities.max_dim(-1, true);
+            let mut threshold = max_log_probabilities + self.min_p.ln();
+            if self.min_tokens_to_keep > 1 {
+                // The threshold is lowered to the score of the min_tokens_to_keep-th token if needed
+                let num_tokens_kept = min(self.min_tokens_to_keep, vocab_size);
+                let (top_log_probabilities, _) =
+                    log_probabilities.topk(num_tokens_kept, -1, true, true);
+                let last_kept = top_log_probabilities.narrow(1, num_tokens_kept - 1, 1);
+                threshold = last_kept.where_self(&last_kept.lt_tensor(&threshold), &threshold);
+            }
+            let indices_to_remove = log_probabilities.lt_tensor(&threshold);
+            let _ = scores.masked_fill_(&indices_to_remove, f64::NEG_INFINITY);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn repetition_penalty() {
+        let input_ids = Tensor::of_slice(&[0i64, 2]).view((1, 2));
+        let mut scores = Tensor::of_slice(&[2f64, 1.0, -2.0, 1.0]).view((1, 4));
+        RepetitionPenaltyLogitsProcessor { penalty: 2.0 }.process(&input_ids, &mut scores, 2);
+        assert_eq!(Vec::<f64>::from(scores.view(-1)), [1.0, 1.0, -4.0, 1.0]);
+    }
+
+    #[test]
+    fn no_repeat_ngram() {
+        let input_ids = Tensor::of_slice(&[1i64, 2, 3, 1]).view((1, 4));
+        let mut scores = Tensor::zeros(&[1, 4], (Kind::Double, Device::Cpu));
+        NoRepeatNGramLogitsProcessor { ngram_size: 2 }.process(&input_ids, &mut scores, 4);
+        assert_eq!(
+            Vec::<f64>::from(scores.view(-1)),
+            [0.0, 0.0, f64::NEG_INFINITY, 0.0]
+        );
+    }
+
+    #[test]
+    fn top_k_filtering() {
+        let mut scores = Tensor::of_slice(&[1f64, 4.0, 3.0, 2.0]).view((1, 4));
+        TopKTopPLogitsProcessor {
+            top_k: 2,
+            top_p: 1.0,
+            min_p: 0.0,
+            min_tokens_to_keep: 1,
+        }
+        .process(
+            &Tensor::zeros(&[1, 1], (Kind::Int64, Device::Cpu)),
+            &mut scores,
+            1,
+        );
+        assert_eq!(
+            Vec::<f64>::from(scores.view(-1)),
+            [f64::NEG_INFINITY, 4.0, 3.0, f64::NEG_INFINITY]
+        );
+    }
+    #[test]
+    fn min_p_filtering() {
+        let logits = Tensor::of_slice(&[0.5f64, 0.3, 0.15, 0.05]).log();
+        let no_input_ids = Tensor::zeros(&[1, 1], (Kind::Int64, Device::Cpu));
+
+        let mut scores = logits.view((1, 4)).copy();
+        TopKTopPLogitsProcessor {
+            top_k: 0,
+            top_p: 1.0,
+            min_p: 0.2,
+            min_tokens_to_keep: 1,
+        }
+        .process(&no_input_ids, &mut scores, 1);
+        let kept: Vec<bool> = Vec::<f64>::from(scores.view(-1))
+            .iter()
+            .map(|score| score.is_finite())
+            .collect();
+        assert_eq!(kept, [true, true, true, false]);
+
+        let mut scores = logits.view((1, 4)).copy();
+        TopKTopPLogitsProcessor {
+            top_k: 0,
+            top_p: 1.0,
+            min_p: 0.9,
+            min_tokens_to_keep: 2,
+        }
+        .process(&no_input_ids, &mut scores, 1);
+        let kept: Vec<bool> = Vec::<f64>::from(scores.view(-1))
+            .iter()
+            .map(|score| score.is_finite())
+            .collect();
+        assert_eq!(kept, [true, true, false, false]);
+    }
+}
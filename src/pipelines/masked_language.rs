@@ -46,11 +46,15 @@
 //! ```
 //!
 use crate::bert::BertForMaskedLM;
+use crate::common::embeddings::load_weights_with_tied_embeddings;
 use crate::common::error::RustBertError;
+use crate::common::trace::trace_span;
 use crate::deberta::DebertaForMaskedLM;
 use crate::deberta_v2::DebertaV2ForMaskedLM;
 use crate::fnet::FNetForMaskedLM;
+use crate::pipelines::added_tokens::add_tokens_and_resize_embeddings;
 use crate::pipelines::common::{ConfigOption, ModelType, TokenizerOption};
+use crate::pipelines::perplexity::{PerplexityModel, ScoringWindow};
 use crate::resources::ResourceProvider;
 use crate::roberta::RobertaForMaskedLM;
 #[cfg(feature = "remote")]
@@ -59,12 +63,13 @@ use crate::{
     resources::RemoteResource,
 };
 use rust_tokenizers::tokenizer::TruncationStrategy;
-use rust_tokenizers::TokenizedInput;
+use rust_tokenizers::{Mask, TokenIdsWithOffsets, TokenizedInput};
+use serde::{Deserialize, Serialize};
 use std::borrow::Borrow;
 use tch::nn::VarStore;
-use tch::{nn, no_grad, Device, Tensor};
+use tch::{nn, no_grad, Device, Kind, Tensor};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 /// Output container for masked language model pipeline.
 pub struct MaskedToken {
     /// String representation of the masked word
@@ -75,18 +80,23 @@ pub struct MaskedToken {
     pub score: f64,
 }
 
+#[derive(Serialize, Deserialize)]
 /// # Configuration for MaskedLanguageModel
 /// Contains information regarding the model to load and device to place the model on.
 pub struct MaskedLanguageConfig {
     /// Model type
     pub model_type: ModelType,
     /// Model weights resource (default: pretrained BERT model on CoNLL)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub model_resource: Box<dyn ResourceProvider + Send>,
     /// Config resource (default: pretrained BERT model on CoNLL)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub config_resource: Box<dyn ResourceProvider + Send>,
     /// Vocab resource (default: pretrained BERT model on CoNLL)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub vocab_resource: Box<dyn ResourceProvider + Send>,
     /// Merges resource (default: None)
+    #[serde(default, with = "crate::common::serde_utils::optional_resource")]
     pub merges_resource: Option<Box<dyn ResourceProvider + Send>>,
     /// Automatically lower case all input upon tokenization (assumes a lower-cased model)
     pub lower_case: bool,
@@ -97,6 +107,10 @@ pub struct MaskedLanguageConfig {
     /// Token used for masking words. This is the token which the model will try to predict.
     pub mask_token: Option<String>,
     /// Device to place the model on (default: CUDA/GPU when available)
+    #[serde(
+        with = "crate::common::serde_utils::device",
+        default = "crate::common::serde_utils::device::default"
+    )]
     pub device: Device,
 }
 
@@ -287,6 +301,12 @@ impl MaskedLanguageOption {
         encoder_mask: Option<&Tensor>,
         train: bool,
     ) -> Tensor {
+        let _span = trace_span!(
+            DEBUG,
+            "forward",
+            input_shape = ?input_ids.map(Tensor::size),
+            device = ?input_ids.map(Tensor::device)
+        );
         match *self {
             Self::Bert(ref model) => {
                 model
@@ -381,15 +401,12 @@ impl MaskedLanguageModel {
     /// # }
     /// ```
     pub fn new(config: MaskedLanguageConfig) -> Result<MaskedLanguageModel, RustBertError> {
-        let config_path = config.config_resource.get_local_path()?;
         let vocab_path = config.vocab_resource.get_local_path()?;
-        let weights_path = config.model_resource.get_local_path()?;
         let merges_path = if let Some(merges_resource) = &config.merges_resource {
             Some(merges_resource.get_local_path()?)
         } else {
             None
         };
-        let device = config.device;
 
         let tokenizer = TokenizerOption::from_file(
             config.model_type,
@@ -399,6 +416,31 @@ impl MaskedLanguageModel {
             config.strip_accents,
             config.add_prefix_space,
         )?;
+        Self::new_with_tokenizer(config, tokenizer)
+    }
+
+    /// Build a new `MaskedLanguageModel` with a provided tokenizer (e.g. a HuggingFace tokenizer loaded
+    /// from a `tokenizer.json` file). The tokenizer resources of the configuration are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `MaskedLanguageConfig` object containing the resource references (model, vocabulary, configuration) and device placement (CPU/GPU)
+    /// * `tokenizer` - `TokenizerOption` tokenizer to use for masked language modelling
+    pub fn new_with_tokenizer(
+        config: MaskedLanguageConfig,
+        tokenizer: TokenizerOption,
+    ) -> Result<MaskedLanguageModel, RustBertError> {
+        let _span = trace_span!(
+            INFO,
+            "load_model",
+            pipeline = "masked_language",
+            model_type = ?config.model_type,
+            device = ?config.device
+        );
+        let config_path = config.config_resource.get_local_path()?;
+        let weights_path = config.model_resource.get_local_path()?;
+        let device = config.device;
+
         let mut var_store = VarStore::new(device);
         let model_config = ConfigOption::from_file(config.model_type, config_path);
         let max_length = model_config
@@ -408,7 +450,7 @@ impl MaskedLanguageModel {
 
         let language_encode =
             MaskedLanguageOption::new(config.model_type, &var_store.root(), &model_config)?;
-        var_store.load(weights_path)?;
+        load_weights_with_tied_embeddings(&mut var_store, weights_path)?;
         let mask_token = config.mask_token;
         Ok(MaskedLanguageModel {
             tokenizer,
@@ -419,6 +461,22 @@ impl MaskedLanguageModel {
         })
     }
 
+    /// Registers additional tokens (e.g. special markers or domain-specific terms) on the tokenizer
+    /// of the model. The added tokens are never split by the tokenizer, and the token embeddings of
+    /// the model are resized, the new embeddings being initialized with the mean embedding of the
+    /// existing vocabulary.
+    ///
+    /// # Arguments
+    ///
+    /// * `tokens` - tokens to add (tokens already part of the vocabulary are ignored)
+    pub fn with_added_tokens<S: AsRef<str>>(
+        mut self,
+        tokens: &[S],
+    ) -> Result<MaskedLanguageModel, RustBertError> {
+        self.tokenizer = add_tokens_and_resize_embeddings(self.tokenizer, &self.var_store, tokens)?;
+        Ok(self)
+    }
+
     /// Replace custom user-provided mask token by language model mask token.
     fn replace_mask_token<'a, S>(
         &self,
@@ -553,6 +611,99 @@ impl MaskedLanguageModel {
         Ok(output_tokens)
     }
 }
+impl PerplexityModel for MaskedLanguageModel {
+    fn perplexity_tokenizer(&self) -> &TokenizerOption {
+        &self.tokenizer
+    }
+
+    fn max_window_length(&self) -> usize {
+        // Leaves room for the special tokens added around the window
+        let num_special_tokens = self
+            .tokenizer
+            .build_input_with_special_tokens(
+                TokenIdsWithOffsets {
+                    ids: vec![],
+                    offsets: vec![],
+                    reference_offsets: vec![],
+                    masks: vec![],
+                },
+                None,
+            )
+            .token_ids
+            .len();
+        self.max_length.saturating_sub(num_special_tokens).max(1)
+    }
+
+    fn score_windows(
+        &self,
+        windows: &[ScoringWindow],
+        batch_size: usize,
+    ) -> Result<(f64, usize), RustBertError> {
+        let mask_token_id = self.tokenizer.get_mask_id().ok_or_else(|| {
+            RustBertError::InvalidConfigurationError(
+                "Tokenizer does not have a mask token id, required to compute the pseudo-perplexity"
+                    .into(),
+            )
+        })?;
+        let device = self.var_store.device();
+        let mut negative_log_likelihood = 0f64;
+        let mut num_tokens = 0usize;
+        for window in windows {
+            let length = window.token_ids.len();
+            let tokenized_input = self.tokenizer.build_input_with_special_tokens(
+                TokenIdsWithOffsets {
+                    ids: window.token_ids.clone(),
+                    offsets: vec![None; length],
+                    reference_offsets: vec![vec![]; length],
+                    masks: vec![Mask::None; length],
+                },
+                None,
+            );
+            let target_positions = tokenized_input
+                .special_tokens_mask
+                .iter()
+                .enumerate()
+                .filter(|(_, special_token)| **special_token == 0)
+                .map(|(position, _)| position as i64)
+                .skip(window.target_start)
+                .collect::<Vec<i64>>();
+            let input_ids = Tensor::of_slice(&tokenized_input.token_ids).to(device);
+
+            // Each target token is predicted from a copy of the window where it is masked
+            for positions in target_positions.chunks(batch_size) {
+                let rows = Tensor::arange(positions.len() as i64, (Kind::Int64, device));
+                let positions = Tensor::of_slice(positions).to(device);
+                let mut masked_input_ids = input_ids.unsqueeze(0).repeat(&[positions.size()[0], 1]);
+                let _ = masked_input_ids.index_put_(
+                    &[Some(&rows), Some(&positions)],
+                    &Tensor::of_slice(&[mask_token_id]).to(device),
+                    false,
+                );
+                let targets = input_ids.index_select(0, &positions);
+                let token_log_probabilities = no_grad(|| {
+                    self.language_encode
+                        .forward_t(
+                            Some(&masked_input_ids),
+                            None,
+                            None,
+                            None,
+                            None,
+                            None,
+                            None,
+                            false,
+                        )
+                        .index(&[Some(&rows), Some(&positions)])
+                        .log_softmax(-1, Kind::Double)
+                        .gather(1, &targets.unsqueeze(-1), false)
+                });
+                negative_log_likelihood -= f64::from(token_log_probabilities.sum(Kind::Double));
+                num_tokens += positions.size()[0] as usize;
+            }
+        }
+        Ok((negative_log_likelihood, num_tokens))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -121,7 +121,7 @@
 //! telescope — scheduled for launch in 2021 — and the European Space Agency's 2028 ARIEL program, could reveal more
 //! about exoplanets like K2-18b."];
 //!
-//! let output = model.summarize(&input);
+//! let output = model.summarize(&input)?;
 //! # Ok(())
 //! # }
 //! ```
@@ -154,7 +154,7 @@
 //!
 //! let conversation_id =
 //!     conversation_manager.create("Going to the movies tonight - any suggestions?");
-//! let output = conversation_model.generate_responses(&mut conversation_manager);
+//! let output = conversation_model.generate_responses(&mut conversation_manager)?;
 //! # Ok(())
 //! # }
 //! ```
@@ -181,7 +181,7 @@
 //!
 //! let prefix = None; // Optional prefix to append prompts with, will be excluded from the generated output
 //!
-//! let output = model.generate(&[input_context_1, input_context_2], prefix);
+//! let output = model.generate(&[input_context_1, input_context_2], prefix)?;
 //! # Ok(())
 //! # }
 //! ```
@@ -474,19 +474,57 @@
 //! # ;
 //! ```
 
+pub mod added_tokens;
+pub mod attributed_summarization;
+pub mod calibration;
 pub mod common;
 pub mod conversation;
+pub mod counterfactual;
+#[cfg(any(feature = "arrow", feature = "polars"))]
+pub mod dataframe;
+pub mod emotion;
+pub mod eval;
 pub mod generation_utils;
+pub mod grammar;
+pub mod headline_generation;
+#[cfg(feature = "hf-tokenizers")]
+pub mod hf_tokenizers;
+pub mod ingestion;
+pub mod input_encoding;
+pub mod intent_slot;
+pub mod io;
 pub mod keywords_extraction;
+pub mod logits_processor;
 pub mod masked_language;
+pub mod multi_task;
 pub mod ner;
+pub mod ner_rules;
+pub mod nested_ner;
+pub mod output_cache;
+pub mod output_filter;
+pub mod perplexity;
+pub mod pii;
 pub mod pos_tagging;
+pub mod prompt_template;
 pub mod question_answering;
+pub mod registry;
+pub mod replaced_token_detection;
+pub mod retrieval;
+pub mod saliency;
 pub mod sentence_embeddings;
+pub mod sentence_segmentation;
 pub mod sentiment;
 pub mod sequence_classification;
+pub mod sparse_embeddings;
+pub mod stopping_criteria;
+pub mod streaming;
+pub mod structured_extraction;
 pub mod summarization;
 pub mod text_generation;
+pub mod text_simplification;
+pub mod text_splitter;
 pub mod token_classification;
+pub mod tool_calling;
+pub mod toxicity;
 pub mod translation;
 pub mod zero_shot_classification;
@@ -0,0 +1,670 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Multi-task inference pipeline
+//! Runs several tasks (sequence classification, token classification and sentence embeddings)
+//! over the same input batch from a single encoder forward pass, instead of running a separate
+//! pipeline (and encoder) per task. The task heads share the encoder of a `BertForMultiTask`
+//! model, and are loaded from the same weights file:
+//! - `sequence_classifier`: sequence classification head on the pooled output
+//! - `token_classifier`: token classification head on the token hidden states (BIO labels)
+//!
+//! Sentence embeddings are obtained by mean pooling of the final hidden states (optionally
+//! normalized) and do not require additional weights. Only the tasks enabled in the
+//! `MultiTaskConfig` are computed.
+//!
+//! Additional classification heads can be loaded onto the encoder at runtime (`load_head`) from
+//! the variables under a name prefix of a weights file (e.g. `classifier` for the head of a
+//! `BertForTokenClassification` checkpoint), and unloaded without reloading the encoder weights.
+//! The heads are expected to have been trained on top of the same (frozen) encoder.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::common::ModelType;
+//! use rust_bert::pipelines::multi_task::{MultiTaskConfig, MultiTaskModel};
+//! use rust_bert::resources::LocalResource;
+//! use std::path::PathBuf;
+//!
+//! let config = MultiTaskConfig::new(
+//!     ModelType::Bert,
+//!     LocalResource::from(PathBuf::from("path/to/rust_model.ot")),
+//!     LocalResource::from(PathBuf::from("path/to/config.json")),
+//!     LocalResource::from(PathBuf::from("path/to/vocab.txt")),
+//!     None,
+//!     Some(vec!["negative".to_string(), "positive".to_string()]),
+//!     Some(vec![
+//!         "O".to_string(),
+//!         "B-PER".to_string(),
+//!         "I-PER".to_string(),
+//!         "B-LOC".to_string(),
+//!         "I-LOC".to_string(),
+//!     ]),
+//!     true,
+//!     false,
+//! );
+//! let model = MultiTaskModel::new(config)?;
+//!
+//! let output = model.predict(&["My name is Amélie. I live in Paris and I love it."]);
+//! let sentiment = output[0].classification.as_ref().unwrap();
+//! let entities = output[0].entities.as_ref().unwrap();
+//! let embedding = output[0].embedding.as_ref().unwrap();
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::bert::{BertConfig, BertForMultiTask};
+use crate::common::error::RustBertError;
+use crate::pipelines::common::{ModelType, TokenizerOption};
+use crate::pipelines::intent_slot::{decode_slots, WordLabel};
+use crate::pipelines::ner::Entity;
+use crate::pipelines::sentence_embeddings::layers::{Pooling, PoolingConfig};
+use crate::pipelines::sentence_embeddings::Embedding;
+use crate::pipelines::sequence_classification::Label;
+use crate::resources::ResourceProvider;
+use crate::Config;
+use rust_tokenizers::tokenizer::TruncationStrategy;
+use rust_tokenizers::{Mask, TokenizedInput};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tch::nn::{self, Module, VarStore};
+use tch::{no_grad, Device, Kind, Tensor};
+
+#[derive(Serialize, Deserialize)]
+/// # Configuration for MultiTaskModel
+/// Contains information regarding the model to load, the tasks to run (with their labels) and
+/// device to place the model on.
+pub struct MultiTaskConfig {
+    /// Model type (only `ModelType::Bert` is currently supported)
+    pub model_type: ModelType,
+    /// Model weights resource
+    #[serde(with = "crate::common::serde_utils::resource")]
+    pub model_resource: Box<dyn ResourceProvider + Send>,
+    /// Config resource
+    #[serde(with = "crate::common::serde_utils::resource")]
+    pub config_resource: Box<dyn ResourceProvider + Send>,
+    /// Vocab resource
+    #[serde(with = "crate::common::serde_utils::resource")]
+    pub vocab_resource: Box<dyn ResourceProvider + Send>,
+    /// Merges resource
+    #[serde(default, with = "crate::common::serde_utils::optional_resource")]
+    pub merges_resource: Option<Box<dyn ResourceProvider + Send>>,
+    /// Sequence classification labels, in the order of the sequence classification head outputs (no sequence classification if None)
+    pub classification_labels: Option<Vec<String>>,
+    /// Token classification labels (BIO scheme), in the order of the token classification head outputs (no token classification if None)
+    pub token_labels: Option<Vec<String>>,
+    /// Flag indicating if sentence embeddings (mean pooling of the final hidden states) should be returned
+    pub embeddings: bool,
+    /// Flag indicating if the sentence embeddings should be normalized (unit L2 norm)
+    #[serde(default)]
+    pub normalize_embeddings: bool,
+    /// Automatically lower case all input upon tokenization (assumes a lower-cased model)
+    pub lower_case: bool,
+    /// Flag indicating if the tokenizer should strip accents (normalization). Only used for BERT / ALBERT models
+    pub strip_accents: Option<bool>,
+    /// Flag indicating if the tokenizer should add a white space before each tokenized input (needed for some Roberta models)
+    pub add_prefix_space: Option<bool>,
+    /// Device to place the model on (default: CUDA/GPU when available)
+    #[serde(
+        with = "crate::common::serde_utils::device",
+        default = "crate::common::serde_utils::device::default"
+    )]
+    pub device: Device,
+}
+
+impl MultiTaskConfig {
+    /// Instantiate a new multi-task inference configuration of the supplied type.
+    ///
+    /// # Arguments
+    ///
+    /// * `model_type` - `ModelType` indicating the model type to load (must match with the actual data to be loaded!)
+    /// * model - The `ResourceProvider` pointing to the model to load (e.g.  model.ot)
+    /// * config - The `ResourceProvider` pointing to the model configuration to load (e.g. config.json)
+    /// * vocab - The `ResourceProvider` pointing to the tokenizer's vocabulary to load (e.g.  vocab.txt/vocab.json)
+    /// * merges - An optional `ResourceProvider` pointing to the tokenizer's merge file to load (e.g.  merges.txt)
+    /// * classification_labels - Optional sequence classification labels, in the order of the sequence classification head outputs
+    /// * token_labels - Optional token classification labels (BIO scheme), in the order of the token classification head outputs
+    /// * embeddings - A `bool` indicating whether sentence embeddings should be returned
+    /// * lower_case - A `bool` indicating whether the tokenizer should lower case all input (in case of a lower-cased model)
+    #[allow(clippy::too_many_arguments)]
+    pub fn new<RM, RC, RV>(
+        model_type: ModelType,
+        model_resource: RM,
+        config_resource: RC,
+        vocab_resource: RV,
+        merges_resource: Option<RV>,
+        classification_labels: Option<Vec<String>>,
+        token_labels: Option<Vec<String>>,
+        embeddings: bool,
+        lower_case: bool,
+    ) -> MultiTaskConfig
+    where
+        RM: ResourceProvider + Send + 'static,
+        RC: ResourceProvider + Send + 'static,
+        RV: ResourceProvider + Send + 'static,
+    {
+        MultiTaskConfig {
+            model_type,
+            model_resource: Box::new(model_resource),
+            config_resource: Box::new(config_resource),
+            vocab_resource: Box::new(vocab_resource),
+            merges_resource: merges_resource.map(|r| Box::new(r) as Box<_>),
+            classification_labels,
+            token_labels,
+            embeddings,
+            normalize_embeddings: false,
+            lower_case,
+            strip_accents: None,
+            add_prefix_space: None,
+            device: Device::cuda_if_available(),
+        }
+    }
+
+    fn validate(&self) -> Result<(), RustBertError> {
+        if !matches!(self.model_type, ModelType::Bert) {
+            return Err(RustBertError::InvalidConfigurationError(format!(
+                "Multi-task inference not implemented for {:?}!",
+                self.model_type
+            )));
+        }
+        if self
+            .classification_labels
+            .iter()
+            .chain(self.token_labels.iter())
+            .any(|labels| labels.is_empty())
+        {
+            return Err(RustBertError::InvalidConfigurationError(
+                "Labels of the enabled classification tasks must not be empty".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Outputs of all enabled tasks for an input
+pub struct MultiTaskOutput {
+    /// Predicted sequence class, if sequence classification is enabled
+    pub classification: Option<Label>,
+    /// Entities extracted by the token classification head, if token classification is enabled
+    pub entities: Option<Vec<Entity>>,
+    /// Sentence embedding, if embeddings are enabled
+    pub embedding: Option<Embedding>,
+    /// Outputs of the heads loaded at runtime (see `MultiTaskModel::load_head`), keyed by head name
+    pub heads: HashMap<String, TaskHeadOutput>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// # Type of a task head loaded at runtime
+pub enum TaskHeadType {
+    /// Sequence classification head applied to the pooled output
+    SequenceClassification,
+    /// Token classification head (BIO labels) applied to the token hidden states
+    TokenClassification,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Output of a task head loaded at runtime
+pub enum TaskHeadOutput {
+    /// Predicted sequence class of a sequence classification head
+    Classification(Label),
+    /// Entities extracted by a token classification head
+    Entities(Vec<Entity>),
+}
+
+/// Task head loaded at runtime, holding its own variables
+struct TaskHead {
+    head_type: TaskHeadType,
+    labels: Vec<String>,
+    classifier: nn::Linear,
+    var_store: VarStore,
+}
+
+/// # MultiTaskModel running several task heads from a single encoder pass
+pub struct MultiTaskModel {
+    tokenizer: TokenizerOption,
+    model: BertForMultiTask,
+    var_store: VarStore,
+    classification_labels: Option<Vec<String>>,
+    token_labels: Option<Vec<String>>,
+    pooling: Option<Pooling>,
+    normalize_embeddings: bool,
+    heads: HashMap<String, TaskHead>,
+    kind: Kind,
+    hidden_size: i64,
+    max_length: usize,
+}
+
+impl MultiTaskModel {
+    /// Build a new `MultiTaskModel`
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `MultiTaskConfig` object containing the resource references (model, vocabulary, configuration), tasks and device placement (CPU/GPU)
+    pub fn new(config: MultiTaskConfig) -> Result<MultiTaskModel, RustBertError> {
+        config.validate()?;
+        let vocab_path = config.vocab_resource.get_local_path()?;
+        let merges_path = if let Some(merges_resource) = &config.merges_resource {
+            Some(merges_resource.get_local_path()?)
+        } else {
+            None
+        };
+        let tokenizer = TokenizerOption::from_file(
+            config.model_type,
+            vocab_path.to_str().unwrap(),
+            merges_path.as_deref().map(|path| path.to_str().unwrap()),
+            config.lower_case,
+            config.strip_accents,
+            config.add_prefix_space,
+        )?;
+
+        let config_path = config.config_resource.get_local_path()?;
+        let weights_path = config.model_resource.get_local_path()?;
+        let mut var_store = VarStore::new(config.device);
+        let model_config = BertConfig::from_file(config_path);
+        let model = BertForMultiTask::new(
+            &var_store.root(),
+            &model_config,
+            config
+                .classification_labels
+                .as_ref()
+                .map(|labels| labels.len() as i64),
+            config
+                .token_labels
+                .as_ref()
+                .map(|labels| labels.len() as i64),
+        );
+        var_store.load(weights_path)?;
+
+        let pooling = if config.embeddings {
+            Some(Pooling::new(PoolingConfig {
+                word_embedding_dimension: model_config.hidden_size,
+                pooling_mode_cls_token: false,
+                pooling_mode_max_tokens: false,
+                pooling_mode_mean_tokens: true,
+                pooling_mode_mean_sqrt_len_tokens: false,
+                pooling_mode_weightedmean_tokens: false,
+                pooling_mode_lasttoken: false,
+            }))
+        } else {
+            None
+        };
+
+        Ok(MultiTaskModel {
+            tokenizer,
+            model,
+            var_store,
+            classification_labels: config.classification_labels,
+            token_labels: config.token_labels,
+            pooling,
+            normalize_embeddings: config.normalize_embeddings,
+            heads: HashMap::new(),
+            kind: Kind::Float,
+            hidden_size: model_config.hidden_size,
+            max_length: model_config.max_position_embeddings as usize,
+        })
+    }
+
+    /// Casts the encoder and head weights to half precision
+    pub fn half(&mut self) {
+        self.set_kind(Kind::Half);
+    }
+
+    /// Casts the encoder and head weights to single precision
+    pub fn float(&mut self) {
+        self.set_kind(Kind::Float);
+    }
+
+    fn set_kind(&mut self, kind: Kind) {
+        self.var_store.set_kind(kind);
+        for head in self.heads.values_mut() {
+            head.var_store.set_kind(kind);
+        }
+        self.kind = kind;
+    }
+
+    /// Load a classification head onto the encoder, without reloading the encoder weights. A head
+    /// loaded with the name of an existing head replaces it, and is cast to the precision of the
+    /// encoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the head, used as key of its outputs in `MultiTaskOutput::heads`
+    /// * `head_type` - `TaskHeadType` of the head (sequence or token classification)
+    /// * `labels` - Labels of the head, in the order of its outputs
+    /// * `weights_resource` - `ResourceProvider` pointing to the weights file containing the head
+    /// * `prefix` - Name prefix of the head variables in the weights file (e.g. `classifier`, for the `classifier.weight` and `classifier.bias` variables)
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// # use rust_bert::pipelines::common::ModelType;
+    /// # use rust_bert::pipelines::multi_task::{MultiTaskConfig, MultiTaskModel, TaskHeadType};
+    /// # use rust_bert::resources::LocalResource;
+    /// # use std::path::PathBuf;
+    /// # let config = MultiTaskConfig::new(
+    /// #     ModelType::Bert,
+    /// #     LocalResource::from(PathBuf::from("path/to/rust_model.ot")),
+    /// #     LocalResource::from(PathBuf::from("path/to/config.json")),
+    /// #     LocalResource::from(PathBuf::from("path/to/vocab.txt")),
+    /// #     None,
+    /// #     None,
+    /// #     None,
+    /// #     true,
+    /// #     false,
+    /// # );
+    /// let mut model = MultiTaskModel::new(config)?;
+    /// model.load_head(
+    ///     "sentiment",
+    ///     TaskHeadType::SequenceClassification,
+    ///     vec!["negative".to_string(), "positive".to_string()],
+    ///     &LocalResource::from(PathBuf::from("path/to/sentiment_head.ot")),
+    ///     "classifier",
+    /// )?;
+    /// let output = model.predict(&["This movie was great!"]);
+    /// let sentiment = &output[0].heads["sentiment"];
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn load_head(
+        &mut self,
+        name: &str,
+        head_type: TaskHeadType,
+        labels: Vec<String>,
+        weights_resource: &dyn ResourceProvider,
+        prefix: &str,
+    ) -> Result<(), RustBertError> {
+        if labels.is_empty() {
+            return Err(RustBertError::InvalidConfigurationError(
+                "Labels of a task head must not be empty".to_string(),
+            ));
+        }
+        let weights_path = weights_resource.get_local_path()?;
+        let mut var_store = VarStore::new(self.var_store.device());
+        let path = prefix
+            .split('.')
+            .fold(var_store.root(), |path, name| path / name);
+        let classifier = nn::linear(
+            path,
+            self.hidden_size,
+            labels.len() as i64,
+            Default::default(),
+        );
+        let missing_variables = var_store.load_partial(weights_path)?;
+        if !missing_variables.is_empty() {
+            return Err(RustBertError::InvalidConfigurationError(format!(
+                "Variables missing from the head weights: {}",
+                missing_variables.join(", ")
+            )));
+        }
+        var_store.set_kind(self.kind);
+        self.heads.insert(
+            name.to_string(),
+            TaskHead {
+                head_type,
+                labels,
+                classifier,
+                var_store,
+            },
+        );
+        Ok(())
+    }
+
+    /// Unload a head loaded at runtime, returning `false` if no head was loaded with this name
+    pub fn unload_head(&mut self, name: &str) -> bool {
+        self.heads.remove(name).is_some()
+    }
+
+    /// Returns the names of the heads loaded at runtime
+    pub fn head_names(&self) -> Vec<&str> {
+        self.heads.keys().map(String::as_str).collect()
+    }
+
+    /// Run all enabled tasks on a batch of inputs, using a single encoder forward pass
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - `&[&str]` Array of texts to process.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<MultiTaskOutput>` containing the outputs of the enabled tasks for each input
+    pub fn predict<S>(&self, input: &[S]) -> Vec<MultiTaskOutput>
+    where
+        S: AsRef<str>,
+    {
+        if input.is_empty() {
+            return vec![];
+        }
+        let tokenized_input: Vec<TokenizedInput> = self.tokenizer.encode_list(
+            input,
+            self.max_length,
+            &TruncationStrategy::LongestFirst,
+            0,
+        );
+        let max_len = tokenized_input
+            .iter()
+            .map(|input| input.token_ids.len())
+            .max()
+            .unwrap_or(0);
+        let pad_id = self.tokenizer.get_pad_id().unwrap_or(0);
+        let (input_ids, attention_masks): (Vec<Tensor>, Vec<Tensor>) = tokenized_input
+            .iter()
+            .map(|input| {
+                let mut attention_mask = vec![1i64; input.token_ids.len()];
+                attention_mask.resize(max_len, 0);
+                let mut token_ids = input.token_ids.clone();
+                token_ids.resize(max_len, pad_id);
+                (
+                    Tensor::of_slice(&token_ids),
+                    Tensor::of_slice(&attention_mask),
+                )
+            })
+            .unzip();
+        let device = self.var_store.device();
+        let input_ids = Tensor::stack(&input_ids, 0).to(device);
+        let attention_mask = Tensor::stack(&attention_masks, 0).to(device);
+
+        let (classification_scores, token_scores, embeddings, head_scores) = no_grad(|| {
+            let output = self.model.forward_t(
+                Some(&input_ids),
+                Some(&attention_mask),
+                None,
+                None,
+                None,
+                false,
+            );
+            let classification_scores = output
+                .sequence_logits
+                .map(|logits| logits.softmax(-1, Kind::Float).to(Device::Cpu));
+            let token_scores = output
+                .token_logits
+                .map(|logits| logits.softmax(-1, Kind::Float).to(Device::Cpu));
+            let head_scores = self
+                .heads
+                .iter()
+                .map(|(name, head)| {
+                    let head_input = match head.head_type {
+                        TaskHeadType::SequenceClassification => {
+                            output.pooled_output.as_ref().unwrap()
+                        }
+                        TaskHeadType::TokenClassification => &output.hidden_state,
+                    };
+                    let scores = head
+                        .classifier
+                        .forward(head_input)
+                        .softmax(-1, Kind::Float)
+                        .to(Device::Cpu)
+                        .max_dim(-1, false);
+                    (name.as_str(), head, scores)
+                })
+                .collect::<Vec<_>>();
+            let embeddings = self.pooling.as_ref().map(|pooling| {
+                let embeddings = pooling.forward(output.hidden_state, &attention_mask);
+                let embeddings = if self.normalize_embeddings {
+                    let norm = &embeddings
+                        .norm_scalaropt_dim(2, &[1], true)
+                        .clamp_min(1e-12)
+                        .expand_as(&embeddings);
+                    embeddings / norm
+                } else {
+                    embeddings
+                };
+                Vec::<Embedding>::from(embeddings.to_kind(Kind::Float).to(Device::Cpu))
+            });
+            (classification_scores, token_scores, embeddings, head_scores)
+        });
+        let classification_scores = classification_scores.map(|scores| scores.max_dim(-1, false));
+        let token_scores = token_scores.map(|scores| scores.max_dim(-1, false));
+
+        let mut output = Vec::with_capacity(input.len());
+        for (sentence, (text, tokens)) in input.iter().zip(tokenized_input.iter()).enumerate() {
+            let classification = classification_scores
+                .as_ref()
+                .zip(self.classification_labels.as_ref())
+                .map(|((scores, label_ids), labels)| {
+                    decode_label(labels, scores, label_ids, sentence)
+                });
+            let entities = token_scores.as_ref().zip(self.token_labels.as_ref()).map(
+                |((scores, label_ids), labels)| {
+                    decode_entities(text.as_ref(), tokens, labels, scores, label_ids, sentence)
+                },
+            );
+            let heads = head_scores
+                .iter()
+                .map(|(name, head, (scores, label_ids))| {
+                    let head_output = match head.head_type {
+                        TaskHeadType::SequenceClassification => TaskHeadOutput::Classification(
+                            decode_label(&head.labels, scores, label_ids, sentence),
+                        ),
+                        TaskHeadType::TokenClassification => {
+                            TaskHeadOutput::Entities(decode_entities(
+                                text.as_ref(),
+                                tokens,
+                                &head.labels,
+                                scores,
+                                label_ids,
+                                sentence,
+                            ))
+                        }
+                    };
+                    (name.to_string(), head_output)
+                })
+                .collect::<HashMap<String, TaskHeadOutput>>();
+            let embedding = embeddings
+                .as_ref()
+                .map(|embeddings| embeddings[sentence].clone());
+            output.push(MultiTaskOutput {
+                classification,
+                entities,
+                embedding,
+                heads,
+            });
+        }
+        output
+    }
+}
+
+/// Sequence label predicted for a sentence, given the scores and ids of the best labels
+fn decode_label(labels: &[String], scores: &Tensor, label_ids: &Tensor, sentence: usize) -> Label {
+    let id = label_ids.int64_value(&[sentence as i64]);
+    Label {
+        text: labels[id as usize].clone(),
+        score: scores.double_value(&[sentence as i64]),
+        id,
+        sentence,
+    }
+}
+
+/// Entities of a sentence, given the scores and ids of the best labels of its tokens. The label
+/// of a word is the label predicted for its first sub-token.
+fn decode_entities(
+    text: &str,
+    tokens: &TokenizedInput,
+    labels: &[String],
+    scores: &Tensor,
+    label_ids: &Tensor,
+    sentence: usize,
+) -> Vec<Entity> {
+    let mut words: Vec<WordLabel> = vec![];
+    for (position, (offset, mask)) in tokens
+        .token_offsets
+        .iter()
+        .zip(tokens.mask.iter())
+        .enumerate()
+    {
+        let offset = match (offset, mask) {
+            (Some(offset), mask) if *mask != Mask::Special => offset,
+            _ => continue,
+        };
+        if *mask == Mask::Continuation {
+            if let Some(word) = words.last_mut() {
+                word.offset.end = offset.end;
+                continue;
+            }
+        }
+        let label_id = label_ids.int64_value(&[sentence as i64, position as i64]);
+        words.push(WordLabel {
+            label: labels[label_id as usize].as_str(),
+            score: scores.double_value(&[sentence as i64, position as i64]),
+            offset: *offset,
+        });
+    }
+    decode_slots(text, &words)
+        .into_iter()
+        .map(|slot| Entity {
+            word: slot.text,
+            score: slot.score,
+            label: slot.slot_type,
+            offset: slot.offset,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::resources::LocalResource;
+    use std::path::PathBuf;
+
+    fn config(
+        classification_labels: Option<Vec<String>>,
+        token_labels: Option<Vec<String>>,
+        embeddings: bool,
+    ) -> MultiTaskConfig {
+        MultiTaskConfig::new(
+            ModelType::Bert,
+            LocalResource::from(PathBuf::from("model.ot")),
+            LocalResource::from(PathBuf::from("config.json")),
+            LocalResource::from(PathBuf::from("vocab.txt")),
+            None,
+            classification_labels,
+            token_labels,
+            embeddings,
+            true,
+        )
+    }
+
+    #[test]
+    fn task_validation() {
+        assert!(config(None, None, true).validate().is_ok());
+        assert!(config(Some(vec!["positive".to_string()]), None, false)
+            .validate()
+            .is_ok());
+        // Heads may be loaded at runtime onto an encoder without built-in tasks
+        assert!(config(None, None, false).validate().is_ok());
+        assert!(config(None, Some(vec![]), true).validate().is_err());
+
+        let mut roberta_config = config(None, None, true);
+        roberta_config.model_type = ModelType::Roberta;
+        assert!(roberta_config.validate().is_err());
+    }
+}
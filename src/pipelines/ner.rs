@@ -127,11 +127,14 @@
 //! Dutch| XLM_ROBERTA_NER_NL |
 
 use crate::common::error::RustBertError;
+use crate::pipelines::ner_rules::{apply_rules, AugmentedEntity, EntityRule};
 use crate::pipelines::token_classification::{
     Token, TokenClassificationConfig, TokenClassificationModel,
 };
 use rust_tokenizers::Offset;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 /// # Entity generated by a `NERModel`
@@ -146,12 +149,39 @@ pub struct Entity {
     pub offset: Offset,
 }
 
+#[derive(Debug, Clone)]
+/// # Entity borrowing its text from the input of a `NERModel`
+/// Returned by `NERModel::predict_spans`. The label is shared by all entities with the same label.
+pub struct EntitySpan<'a> {
+    /// Slice of the input text spanned by the entity
+    pub word: &'a str,
+    /// Confidence score
+    pub score: f64,
+    /// Entity label (e.g. ORG, LOC...)
+    pub label: Arc<str>,
+    /// Token offsets
+    pub offset: Offset,
+}
+
+impl<'a> EntitySpan<'a> {
+    /// Converts the entity to an owned `Entity`
+    pub fn to_entity(&self) -> Entity {
+        Entity {
+            word: self.word.to_string(),
+            score: self.score,
+            label: self.label.to_string(),
+            offset: self.offset,
+        }
+    }
+}
+
 //type alias for some backward compatibility
 type NERConfig = TokenClassificationConfig;
 
 /// # NERModel to extract named entities
 pub struct NERModel {
     token_classification_model: TokenClassificationModel,
+    rules: Vec<EntityRule>,
 }
 
 impl NERModel {
@@ -175,9 +205,22 @@ impl NERModel {
         let model = TokenClassificationModel::new(ner_config)?;
         Ok(NERModel {
             token_classification_model: model,
+            rules: Vec::new(),
         })
     }
 
+    /// Attaches an `EntityRule` to the model (e.g. a regular expression extracting identifiers),
+    /// applied to the entities returned by `predict_with_rules`. Rules are applied in the order
+    /// they were attached.
+    ///
+    /// # Arguments
+    ///
+    /// * `rule` - `EntityRule` applied to the predicted entities
+    pub fn with_rule(mut self, rule: EntityRule) -> NERModel {
+        self.rules.push(rule);
+        self
+    }
+
     /// Extract entities from a text
     ///
     /// # Arguments
@@ -284,22 +327,161 @@ impl NERModel {
         entities
     }
 
+    /// Extracts full entities (see `predict_full_entities`) and applies the rules attached to the
+    /// model with `with_rule`, adding, merging or re-typing entities. Each entity is returned with
+    /// its provenance: whether it was predicted by the model and which rules modified it.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - `&[&str]` Array of texts to extract entities from.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Vec<AugmentedEntity>>` containing the entities of each text with their provenance, by position
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::ner::NERModel;
+    /// use rust_bert::pipelines::ner_rules::EntityRule;
+    ///
+    /// let ner_model = NERModel::new(Default::default())?.with_rule(EntityRule::pattern(
+    ///     "invoice",
+    ///     r"\bINV-\d{5}\b",
+    ///     "INVOICE",
+    /// )?);
+    /// let input = ["Amy paid invoice INV-00042 in Paris."];
+    /// let output = ner_model.predict_with_rules(&input);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn predict_with_rules(&self, input: &[&str]) -> Vec<Vec<AugmentedEntity>> {
+        input
+            .iter()
+            .zip(self.predict_full_entities(input))
+            .map(|(text, entities)| apply_rules(text, entities, &self.rules))
+            .collect()
+    }
+
+    /// Extracts full entities borrowing their text from the input: the `word` of each entity is
+    /// the slice of the input text spanned by the entity, and the labels are shared between the
+    /// entities of the call. This avoids allocating a `String` for the word and label of every
+    /// entity in high-throughput batch processing.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - `&[&str]` Array of texts to extract entities from.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Vec<EntitySpan>>` containing consolidated extracted entities, borrowing from the input
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// # use rust_bert::pipelines::ner::NERModel;
+    ///
+    /// let ner_model = NERModel::new(Default::default())?;
+    /// let input = ["Asked John Smith about Acme Corp"];
+    /// let output = ner_model.predict_spans(&input);
+    /// assert_eq!(output[0][0].word, "John Smith");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn predict_spans<'a>(&self, input: &[&'a str]) -> Vec<Vec<EntitySpan<'a>>> {
+        let tokens = self.token_classification_model.predict(input, true, false);
+        let mut labels: HashMap<&str, Arc<str>> = HashMap::new();
+        tokens
+            .iter()
+            .zip(input)
+            .map(|(sequence_tokens, &text)| {
+                consolidate_entity_ranges(sequence_tokens)
+                    .into_iter()
+                    .filter_map(|entity_range| {
+                        let entity_tokens = &sequence_tokens[entity_range.start..entity_range.end];
+                        let offset = entity_offset(entity_tokens)?;
+                        Some(EntitySpan {
+                            word: char_span(text, offset)?,
+                            score: entity_tokens.iter().map(|token| token.score).product(),
+                            label: labels
+                                .entry(entity_range.label)
+                                .or_insert_with(|| Arc::from(entity_range.label))
+                                .clone(),
+                            offset,
+                        })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
     fn consolidate_entities(tokens: &[Token]) -> Vec<Entity> {
-        let mut entities: Vec<Entity> = Vec::new();
+        consolidate_entity_ranges(tokens)
+            .into_iter()
+            .filter_map(|entity_range| {
+                let entity_tokens = &tokens[entity_range.start..entity_range.end];
+                Some(Entity {
+                    word: entity_tokens
+                        .iter()
+                        .map(|token| token.text.as_str())
+                        .collect::<Vec<&str>>()
+                        .join(" "),
+                    score: entity_tokens.iter().map(|token| token.score).product(),
+                    label: entity_range.label.to_string(),
+                    offset: entity_offset(entity_tokens)?,
+                })
+            })
+            .collect()
+    }
+}
 
-        let mut entity_builder = EntityBuilder::new();
-        for (position, token) in tokens.iter().enumerate() {
-            let tag = token.get_tag();
-            let label = token.get_label();
-            if let Some(entity) = entity_builder.handle_current_tag(tag, label, position, tokens) {
-                entities.push(entity)
-            }
-        }
-        if let Some(entity) = entity_builder.flush_and_reset(tokens.len(), tokens) {
-            entities.push(entity);
+/// Returns the offset spanned by the tokens of an entity
+fn entity_offset(entity_tokens: &[Token]) -> Option<Offset> {
+    Some(Offset {
+        begin: entity_tokens.first()?.offset?.begin,
+        end: entity_tokens.last()?.offset?.end,
+    })
+}
+
+/// Slices a text with a character offset
+pub(crate) fn char_span(text: &str, offset: Offset) -> Option<&str> {
+    let mut byte_positions = text
+        .char_indices()
+        .map(|(byte_position, _)| byte_position)
+        .chain(std::iter::once(text.len()));
+    let begin = byte_positions.nth(offset.begin as usize)?;
+    let end = if offset.end > offset.begin {
+        byte_positions.nth((offset.end - offset.begin - 1) as usize)?
+    } else {
+        begin
+    };
+    text.get(begin..end)
+}
+
+/// Tokens range and label of an entity
+struct EntityRange<'a> {
+    start: usize,
+    end: usize,
+    label: &'a str,
+}
+
+fn consolidate_entity_ranges(tokens: &[Token]) -> Vec<EntityRange> {
+    let mut entities: Vec<EntityRange> = Vec::new();
+
+    let mut entity_builder = EntityBuilder::new();
+    for (position, token) in tokens.iter().enumerate() {
+        let tag = token.get_tag();
+        let label = token.get_label();
+        if let Some(entity) = entity_builder.handle_current_tag(tag, label, position) {
+            entities.push(entity)
         }
-        entities
     }
+    if let Some(entity) = entity_builder.flush_and_reset(tokens.len()) {
+        entities.push(entity);
+    }
+    entities
 }
 
 struct EntityBuilder<'a> {
@@ -318,12 +500,11 @@ impl<'a> EntityBuilder<'a> {
         tag: Tag,
         label: &'a str,
         position: usize,
-        tokens: &[Token],
-    ) -> Option<Entity> {
+    ) -> Option<EntityRange<'a>> {
         match tag {
-            Tag::Outside => self.flush_and_reset(position, tokens),
+            Tag::Outside => self.flush_and_reset(position),
             Tag::Begin | Tag::Single => {
-                let entity = self.flush_and_reset(position, tokens);
+                let entity = self.flush_and_reset(position);
                 self.start_new(position, tag, label);
                 entity
             }
@@ -333,7 +514,7 @@ impl<'a> EntityBuilder<'a> {
                         | (previous_tag == Tag::Single)
                         | (previous_label != label)
                     {
-                        let entity = self.flush_and_reset(position, tokens);
+                        let entity = self.flush_and_reset(position);
                         self.start_new(position, tag, label);
                         entity
                     } else {
@@ -347,25 +528,12 @@ impl<'a> EntityBuilder<'a> {
         }
     }
 
-    fn flush_and_reset(&mut self, position: usize, tokens: &[Token]) -> Option<Entity> {
-        let entity = if let Some((start, _, label)) = self.previous_node {
-            let entity_tokens = &tokens[start..position];
-            Some(Entity {
-                word: entity_tokens
-                    .iter()
-                    .map(|token| token.text.as_str())
-                    .collect::<Vec<&str>>()
-                    .join(" "),
-                score: entity_tokens.iter().map(|token| token.score).product(),
-                label: label.to_string(),
-                offset: Offset {
-                    begin: entity_tokens.first()?.offset?.begin,
-                    end: entity_tokens.last()?.offset?.end,
-                },
-            })
-        } else {
-            None
-        };
+    fn flush_and_reset(&mut self, position: usize) -> Option<EntityRange<'a>> {
+        let entity = self.previous_node.map(|(start, _, label)| EntityRange {
+            start,
+            end: position,
+            label,
+        });
         self.previous_node = None;
         entity
     }
@@ -416,4 +584,13 @@ mod test {
         let config = NERConfig::default();
         let _: Box<dyn Send> = Box::new(NERModel::new(config));
     }
+
+    #[test]
+    fn char_span_slicing() {
+        let text = "Asked Jürgen about Acme Corp";
+        assert_eq!(char_span(text, Offset::new(6, 12)), Some("Jürgen"));
+        assert_eq!(char_span(text, Offset::new(19, 28)), Some("Acme Corp"));
+        assert_eq!(char_span(text, Offset::new(6, 6)), Some(""));
+        assert_eq!(char_span(text, Offset::new(19, 29)), None);
+    }
 }
@@ -0,0 +1,583 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Rule-augmented named entity recognition
+//! Deterministic rules applied to the entities predicted by a `NERModel`, to extract entities the
+//! model was not trained for (identifiers, dates, product codes...) or to override its predictions
+//! with known terms. The following rules are available:
+//! - `EntityRule::pattern` adds an entity for each match of a regular expression, optionally
+//!   filtered by a validator (e.g. a checksum)
+//! - `EntityRule::gazetteer` adds an entity for each occurrence of a list of terms
+//! - `EntityRule::retype` changes the label of the entities whose text matches a regular expression
+//! - `EntityRule::merge` merges consecutive entities separated by a matching text (e.g. whitespace)
+//!
+//! Rules are applied in the order they were attached to the model, each rule working on the output
+//! of the previous ones. Entities added by a rule replace the entities they overlap, unless the rule
+//! is made non-overriding with `EntityRule::overriding(false)`. Every entity carries an
+//! `EntityProvenance` recording whether it was predicted by the model and which rules modified it.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::ner::NERModel;
+//! use rust_bert::pipelines::ner_rules::EntityRule;
+//!
+//! let ner_model = NERModel::new(Default::default())?
+//!     .with_rule(EntityRule::pattern("order-id", r"\bORD-\d{6}\b", "ORDER_ID")?)
+//!     .with_rule(EntityRule::pattern("date", r"\b\d{4}-\d{2}-\d{2}\b", "DATE")?)
+//!     .with_rule(EntityRule::gazetteer(
+//!         "customers",
+//!         &["Acme Corp", "Globex"],
+//!         "CUSTOMER",
+//!         false,
+//!     )?)
+//!     .with_rule(EntityRule::merge("persons", &["PER"], r"\s+", None)?);
+//!
+//! let input = ["Order ORD-123456 for Acme Corp was approved by John Smith on 2022-03-05."];
+//! for entity in &ner_model.predict_with_rules(&input)[0] {
+//!     println!(
+//!         "{} ({}) model: {}, rules: {:?}",
+//!         entity.entity.word,
+//!         entity.entity.label,
+//!         entity.provenance.from_model,
+//!         entity.provenance.rules
+//!     );
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::common::error::RustBertError;
+use crate::pipelines::ner::{char_span, Entity};
+use regex::{Regex, RegexBuilder};
+use rust_tokenizers::Offset;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// # Provenance of an entity returned by `NERModel::predict_with_rules`
+pub struct EntityProvenance {
+    /// True if the entity (or one of the entities merged into it) was predicted by the model
+    pub from_model: bool,
+    /// Names of the rules that added, re-typed or merged the entity, in the order they were applied
+    pub rules: Vec<String>,
+    /// Label of the entity before a rule changed it, `None` if its label was not changed
+    pub original_label: Option<String>,
+    /// True if the entity results from merging consecutive entities
+    pub merged: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Entity after the application of the rules of a `NERModel`
+pub struct AugmentedEntity {
+    /// Entity. Entities added by rules have a score of 1.0, and merged entities the lowest score of
+    /// the entities they were merged from.
+    pub entity: Entity,
+    /// Origin of the entity
+    pub provenance: EntityProvenance,
+}
+
+#[derive(Debug, Clone)]
+enum RuleAction {
+    Add {
+        pattern: Regex,
+        label: String,
+        overriding: bool,
+        validator: fn(&str) -> bool,
+    },
+    Retype {
+        from_label: Option<String>,
+        pattern: Regex,
+        label: String,
+    },
+    Merge {
+        labels: Vec<String>,
+        separator: Regex,
+        label: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone)]
+/// # Deterministic rule applied to the entities predicted by a `NERModel`
+pub struct EntityRule {
+    name: String,
+    action: RuleAction,
+}
+
+fn compile_pattern(pattern: &str) -> Result<Regex, RustBertError> {
+    Regex::new(pattern).map_err(|error| RustBertError::InvalidConfigurationError(error.to_string()))
+}
+
+fn any_text(_text: &str) -> bool {
+    true
+}
+
+fn overlap(a: Offset, b: Offset) -> bool {
+    (a.begin < b.end) & (b.begin < a.end)
+}
+
+/// Changes the label of an entity, keeping track of its original label
+fn relabel(entity: &mut AugmentedEntity, label: &str) {
+    if entity.entity.label != label {
+        let previous_label = std::mem::replace(&mut entity.entity.label, label.to_string());
+        entity
+            .provenance
+            .original_label
+            .get_or_insert(previous_label);
+    }
+}
+
+fn record_rule(provenance: &mut EntityProvenance, rule: &str) {
+    if !provenance
+        .rules
+        .iter()
+        .any(|applied_rule| applied_rule == rule)
+    {
+        provenance.rules.push(rule.to_string());
+    }
+}
+
+impl EntityRule {
+    fn add(name: &str, pattern: Regex, label: &str) -> EntityRule {
+        EntityRule {
+            name: name.to_string(),
+            action: RuleAction::Add {
+                pattern,
+                label: label.to_string(),
+                overriding: true,
+                validator: any_text,
+            },
+        }
+    }
+
+    /// Adds an entity with label `label` for each match of a regular expression
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - name of the rule, reported in the provenance of the entities
+    /// * `pattern` - regular expression matching the entities
+    /// * `label` - label of the entities
+    ///
+    /// # Returns
+    ///
+    /// * `Result<EntityRule, RustBertError>` - rule, error if the pattern is not a valid regular expression
+    pub fn pattern(name: &str, pattern: &str, label: &str) -> Result<EntityRule, RustBertError> {
+        Ok(EntityRule::add(name, compile_pattern(pattern)?, label))
+    }
+
+    /// Adds an entity with label `label` for each occurrence of one of the terms of a gazetteer.
+    /// Terms only match whole words, and the longest term is matched when terms overlap.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - name of the rule, reported in the provenance of the entities
+    /// * `terms` - terms of the gazetteer
+    /// * `label` - label of the entities
+    /// * `case_sensitive` - if false, the terms match regardless of their case
+    ///
+    /// # Returns
+    ///
+    /// * `Result<EntityRule, RustBertError>` - rule, error if the gazetteer is empty
+    pub fn gazetteer<S: AsRef<str>>(
+        name: &str,
+        terms: &[S],
+        label: &str,
+        case_sensitive: bool,
+    ) -> Result<EntityRule, RustBertError> {
+        let mut terms = terms
+            .iter()
+            .map(AsRef::as_ref)
+            .filter(|term| !term.is_empty())
+            .collect::<Vec<&str>>();
+        if terms.is_empty() {
+            return Err(RustBertError::InvalidConfigurationError(format!(
+                "The gazetteer of rule `{}` is empty",
+                name
+            )));
+        }
+        //    Alternatives are tried in order: longest terms first
+        terms.sort_by_key(|term| std::cmp::Reverse(term.chars().count()));
+        let is_word_character =
+            |character: Option<char>| character.map_or(false, |c| c.is_alphanumeric() | (c == '_'));
+        let pattern = terms
+            .iter()
+            .map(|term| {
+                format!(
+                    "{}{}{}",
+                    if is_word_character(term.chars().next()) {
+                        r"\b"
+                    } else {
+                        ""
+                    },
+                    regex::escape(term),
+                    if is_word_character(term.chars().last()) {
+                        r"\b"
+                    } else {
+                        ""
+                    }
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("|");
+        let pattern = RegexBuilder::new(&pattern)
+            .case_insensitive(!case_sensitive)
+            .build()
+            .map_err(|error| RustBertError::InvalidConfigurationError(error.to_string()))?;
+        Ok(EntityRule::add(name, pattern, label))
+    }
+
+    /// Changes the label of the entities whose text fully matches a regular expression
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - name of the rule, reported in the provenance of the entities
+    /// * `from_label` - if given, only entities with this label are re-typed
+    /// * `pattern` - regular expression the whole text of the entities must match
+    /// * `label` - new label of the entities
+    ///
+    /// # Returns
+    ///
+    /// * `Result<EntityRule, RustBertError>` - rule, error if the pattern is not a valid regular expression
+    pub fn retype(
+        name: &str,
+        from_label: Option<&str>,
+        pattern: &str,
+        label: &str,
+    ) -> Result<EntityRule, RustBertError> {
+        Ok(EntityRule {
+            name: name.to_string(),
+            action: RuleAction::Retype {
+                from_label: from_label.map(ToString::to_string),
+                pattern: compile_pattern(&format!("^(?:{})$", pattern))?,
+                label: label.to_string(),
+            },
+        })
+    }
+
+    /// Merges consecutive entities when the text between them fully matches a separator pattern,
+    /// e.g. `\s+` to merge the tokens of a name split by the model. Entities merged by the rule
+    /// can be merged again with the following entity.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - name of the rule, reported in the provenance of the entities
+    /// * `labels` - labels of the entities that can be merged (any label if empty)
+    /// * `separator` - regular expression the whole text between two entities must match
+    /// * `label` - label of the merged entities, the label of the first entity if `None`
+    ///
+    /// # Returns
+    ///
+    /// * `Result<EntityRule, RustBertError>` - rule, error if the pattern is not a valid regular expression
+    pub fn merge<S: AsRef<str>>(
+        name: &str,
+        labels: &[S],
+        separator: &str,
+        label: Option<&str>,
+    ) -> Result<EntityRule, RustBertError> {
+        Ok(EntityRule {
+            name: name.to_string(),
+            action: RuleAction::Merge {
+                labels: labels
+                    .iter()
+                    .map(|label| label.as_ref().to_string())
+                    .collect(),
+                separator: compile_pattern(&format!("^(?:{})$", separator))?,
+                label: label.map(ToString::to_string),
+            },
+        })
+    }
+
+    /// Sets whether the entities added by a `pattern` or `gazetteer` rule replace the entities they
+    /// overlap (default), or are only added where no entity was found. When overriding, an entity
+    /// spanning exactly the same text as a match is kept (with its score and provenance) and
+    /// re-labelled instead of being replaced.
+    pub fn overriding(mut self, overriding: bool) -> EntityRule {
+        if let RuleAction::Add {
+            overriding: rule_overriding,
+            ..
+        } = &mut self.action
+        {
+            *rule_overriding = overriding;
+        }
+        self
+    }
+
+    /// Filters the matches of a `pattern` or `gazetteer` rule with a validator, for example a
+    /// checksum of identifiers. Matches for which the validator returns false are discarded.
+    pub fn with_validator(mut self, validator: fn(&str) -> bool) -> EntityRule {
+        if let RuleAction::Add {
+            validator: rule_validator,
+            ..
+        } = &mut self.action
+        {
+            *rule_validator = validator;
+        }
+        self
+    }
+
+    /// Returns the name of the rule
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn apply(&self, text: &str, mut entities: Vec<AugmentedEntity>) -> Vec<AugmentedEntity> {
+        match &self.action {
+            RuleAction::Add {
+                pattern,
+                label,
+                overriding,
+                validator,
+            } => {
+                for found in pattern.find_iter(text) {
+                    if found.as_str().is_empty() || !validator(found.as_str()) {
+                        continue;
+                    }
+                    let begin = text[..found.start()].chars().count() as u32;
+                    let offset = Offset::new(begin, begin + found.as_str().chars().count() as u32);
+                    if !*overriding
+                        && entities
+                            .iter()
+                            .any(|entity| overlap(entity.entity.offset, offset))
+                    {
+                        continue;
+                    }
+                    if let Some(entity) = entities
+                        .iter_mut()
+                        .find(|entity| entity.entity.offset == offset)
+                    {
+                        relabel(entity, label);
+                        record_rule(&mut entity.provenance, &self.name);
+                        continue;
+                    }
+                    entities.retain(|entity| !overlap(entity.entity.offset, offset));
+                    entities.push(AugmentedEntity {
+                        entity: Entity {
+                            word: found.as_str().to_string(),
+                            score: 1.0,
+                            label: label.clone(),
+                            offset,
+                        },
+                        provenance: EntityProvenance {
+                            rules: vec![self.name.clone()],
+                            ..Default::default()
+                        },
+                    });
+                }
+                entities
+            }
+            RuleAction::Retype {
+                from_label,
+                pattern,
+                label,
+            } => {
+                for entity in entities.iter_mut() {
+                    if (entity.entity.label != *label)
+                        && from_label
+                            .as_ref()
+                            .map_or(true, |from_label| *from_label == entity.entity.label)
+                        && char_span(text, entity.entity.offset)
+                            .map_or(false, |span| pattern.is_match(span))
+                    {
+                        relabel(entity, label);
+                        record_rule(&mut entity.provenance, &self.name);
+                    }
+                }
+                entities
+            }
+            RuleAction::Merge {
+                labels,
+                separator,
+                label,
+            } => {
+                let mergeable = |entity: &AugmentedEntity| {
+                    labels.is_empty() || labels.contains(&entity.entity.label)
+                };
+                entities
+                    .sort_by_key(|entity| (entity.entity.offset.begin, entity.entity.offset.end));
+                let mut merged_entities: Vec<AugmentedEntity> = Vec::with_capacity(entities.len());
+                let mut previous_merged = false;
+                for entity in entities {
+                    if let Some(previous) = merged_entities.last_mut() {
+                        let is_separated = (previous.entity.offset.end
+                            <= entity.entity.offset.begin)
+                            && char_span(
+                                text,
+                                Offset::new(previous.entity.offset.end, entity.entity.offset.begin),
+                            )
+                            .map_or(false, |gap| separator.is_match(gap));
+                        if (previous_merged || mergeable(previous))
+                            && mergeable(&entity)
+                            && is_separated
+                        {
+                            self.merge_entity(text, previous, entity, label.as_deref());
+                            previous_merged = true;
+                            continue;
+                        }
+                    }
+                    merged_entities.push(entity);
+                    previous_merged = false;
+                }
+                merged_entities
+            }
+        }
+    }
+
+    fn merge_entity(
+        &self,
+        text: &str,
+        target: &mut AugmentedEntity,
+        entity: AugmentedEntity,
+        label: Option<&str>,
+    ) {
+        let offset = Offset::new(target.entity.offset.begin, entity.entity.offset.end);
+        target.entity.word = match char_span(text, offset) {
+            Some(span) => span.to_string(),
+            None => format!("{} {}", target.entity.word, entity.entity.word),
+        };
+        target.entity.score = target.entity.score.min(entity.entity.score);
+        target.entity.offset = offset;
+        if let Some(label) = label {
+            relabel(target, label);
+        }
+        target.provenance.from_model |= entity.provenance.from_model;
+        for rule in &entity.provenance.rules {
+            record_rule(&mut target.provenance, rule);
+        }
+        record_rule(&mut target.provenance, &self.name);
+        target.provenance.merged = true;
+    }
+}
+
+/// Applies rules, in order, to the entities predicted by a model for a text
+///
+/// # Arguments
+///
+/// * `text` - text the entities were extracted from
+/// * `entities` - entities predicted by the model (e.g. by `NERModel::predict_full_entities`)
+/// * `rules` - rules to apply
+///
+/// # Returns
+///
+/// * `Vec<AugmentedEntity>` entities with their provenance, by position
+pub fn apply_rules(
+    text: &str,
+    entities: Vec<Entity>,
+    rules: &[EntityRule],
+) -> Vec<AugmentedEntity> {
+    let mut entities = entities
+        .into_iter()
+        .map(|entity| AugmentedEntity {
+            entity,
+            provenance: EntityProvenance {
+                from_model: true,
+                ..Default::default()
+            },
+        })
+        .collect::<Vec<AugmentedEntity>>();
+    for rule in rules {
+        entities = rule.apply(text, entities);
+    }
+    entities.sort_by_key(|entity| (entity.entity.offset.begin, entity.entity.offset.end));
+    entities
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entity(word: &str, label: &str, score: f64, begin: u32, end: u32) -> Entity {
+        Entity {
+            word: word.to_string(),
+            score,
+            label: label.to_string(),
+            offset: Offset::new(begin, end),
+        }
+    }
+
+    #[test]
+    fn rule_application() -> Result<(), RustBertError> {
+        let text = "Order AB-1234 was shipped to John Smith on 2024-03-05 by Acme Corp.";
+        let entities = vec![
+            entity("AB", "MISC", 0.6, 6, 8),
+            entity("John", "PER", 0.9, 29, 33),
+            entity("Smith", "PER", 0.8, 34, 39),
+            entity("Acme Corp", "ORG", 0.95, 57, 66),
+        ];
+        let rules = [
+            EntityRule::pattern("product-code", r"\b[A-Z]{2}-\d{4}\b", "PRODUCT")?,
+            EntityRule::pattern("date", r"\b\d{4}-\d{2}-\d{2}\b", "DATE")?,
+            EntityRule::pattern("name", r"\bJohn Smith\b", "NAME")?.overriding(false),
+            EntityRule::gazetteer("customers", &["acme", "acme corp"], "CUSTOMER", false)?,
+            EntityRule::merge("persons", &["PER"], r"\s+", None)?,
+            EntityRule::retype("vip", Some("PER"), r"John \w+", "VIP")?,
+        ];
+        let output = apply_rules(text, entities, &rules);
+
+        let summary = output
+            .iter()
+            .map(|entity| {
+                (
+                    entity.entity.word.as_str(),
+                    entity.entity.label.as_str(),
+                    entity.entity.offset,
+                )
+            })
+            .collect::<Vec<(&str, &str, Offset)>>();
+        assert_eq!(
+            summary,
+            [
+                ("AB-1234", "PRODUCT", Offset::new(6, 13)),
+                ("John Smith", "VIP", Offset::new(29, 39)),
+                ("2024-03-05", "DATE", Offset::new(43, 53)),
+                ("Acme Corp", "CUSTOMER", Offset::new(57, 66)),
+            ]
+        );
+        assert_eq!(
+            output[0].provenance,
+            EntityProvenance {
+                from_model: false,
+                rules: vec!["product-code".to_string()],
+                original_label: None,
+                merged: false,
+            }
+        );
+        assert_eq!(
+            output[1].provenance,
+            EntityProvenance {
+                from_model: true,
+                rules: vec!["persons".to_string(), "vip".to_string()],
+                original_label: Some("PER".to_string()),
+                merged: true,
+            }
+        );
+        assert_eq!(output[1].entity.score, 0.8);
+        assert_eq!(output[3].entity.score, 0.95);
+        assert!(output[3].provenance.from_model);
+        assert_eq!(output[3].provenance.original_label.as_deref(), Some("ORG"));
+
+        assert!(EntityRule::pattern("invalid", r"(", "LABEL").is_err());
+        assert!(EntityRule::gazetteer::<&str>("empty", &[], "LABEL", true).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn rule_validator() -> Result<(), RustBertError> {
+        let rule = EntityRule::pattern("even", r"\d+", "EVEN")?
+            .with_validator(|number| number.parse::<u64>().map_or(false, |value| value % 2 == 0));
+        let output = apply_rules("7 12 15 8", vec![], &[rule]);
+        assert_eq!(
+            output
+                .iter()
+                .map(|entity| entity.entity.word.as_str())
+                .collect::<Vec<&str>>(),
+            ["12", "8"]
+        );
+        Ok(())
+    }
+}
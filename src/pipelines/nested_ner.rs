@@ -0,0 +1,572 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Nested Named Entity Recognition pipeline
+//! Extracts entities that may be contained in other entities (e.g. `California` (LOC) within
+//! `University of California` (ORG)), which cannot be represented by the flat IOB tagging of the
+//! token classification pipeline.
+//!
+//! Rather than tagging tokens, the model classifies candidate spans: every span of up to
+//! `max_span_width` words is represented by the hidden states of its first and last tokens and an
+//! embedding of its width, and classified by a feed-forward `SpanClassificationHead`. Spans are
+//! decoded greedily by decreasing score: a span is kept unless it partially overlaps (crosses) a
+//! span kept before, so that nested entities are preserved.
+//!
+//! The model is made of a transformer encoder (BERT, DistilBERT, RoBERTa, ALBERT or T5 encoder)
+//! stored at the root of the weights file, and of a span classification head stored under
+//! `span_classifier` (`span_classifier.width_embeddings`, `span_classifier.dense` and
+//! `span_classifier.classifier`). The labels are read from the `id2label` mapping of the
+//! transformer configuration and must include the `O` label for spans that are not entities.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::common::ModelType;
+//! use rust_bert::pipelines::nested_ner::{NestedNERConfig, NestedNERModel};
+//! use rust_bert::resources::LocalResource;
+//! use std::path::PathBuf;
+//!
+//! let config = NestedNERConfig::new(
+//!     ModelType::Bert,
+//!     LocalResource::from(PathBuf::from("path/to/rust_model.ot")),
+//!     LocalResource::from(PathBuf::from("path/to/config.json")),
+//!     LocalResource::from(PathBuf::from("path/to/vocab.txt")),
+//!     None,
+//!     false,
+//!     None,
+//!     None,
+//! );
+//! let model = NestedNERModel::new(config)?;
+//! let output = model.predict(&["She studied at the University of California."])?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::common::error::RustBertError;
+use crate::common::trace::trace_span;
+use crate::pipelines::common::{ConfigOption, ModelType, TokenizerOption};
+use crate::pipelines::ner::Entity;
+use crate::pipelines::sentence_embeddings::SentenceEmbeddingsOption;
+use crate::resources::ResourceProvider;
+use rust_tokenizers::tokenizer::TruncationStrategy;
+use rust_tokenizers::{Mask, Offset, TokenizedInput};
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use tch::nn::{EmbeddingConfig, VarStore};
+use tch::{nn, no_grad, Device, Kind, Tensor};
+
+#[derive(Serialize, Deserialize)]
+/// # Configuration for NestedNERModel
+/// Contains information regarding the model to load, the span enumeration and device to place the model on.
+pub struct NestedNERConfig {
+    /// Model type of the transformer encoder
+    pub model_type: ModelType,
+    /// Model weights resource (transformer encoder and span classification head)
+    #[serde(with = "crate::common::serde_utils::resource")]
+    pub model_resource: Box<dyn ResourceProvider + Send>,
+    /// Config resource of the transformer encoder, including the `id2label` mapping
+    #[serde(with = "crate::common::serde_utils::resource")]
+    pub config_resource: Box<dyn ResourceProvider + Send>,
+    /// Vocab resource
+    #[serde(with = "crate::common::serde_utils::resource")]
+    pub vocab_resource: Box<dyn ResourceProvider + Send>,
+    /// Merges resource (default: None)
+    #[serde(default, with = "crate::common::serde_utils::optional_resource")]
+    pub merges_resource: Option<Box<dyn ResourceProvider + Send>>,
+    /// Automatically lower case all input upon tokenization (assumes a lower-cased model)
+    pub lower_case: bool,
+    /// Flag indicating if the tokenizer should strip accents (normalization). Only used for BERT / ALBERT models
+    pub strip_accents: Option<bool>,
+    /// Flag indicating if the tokenizer should add a white space before each tokenized input (needed for some Roberta models)
+    pub add_prefix_space: Option<bool>,
+    /// Maximum number of words in a candidate span, also the size of the width embeddings of the head (default: 8)
+    pub max_span_width: usize,
+    /// Dimension of the span width embeddings of the head (default: 150)
+    pub span_width_embedding_size: i64,
+    /// Minimum probability of an entity span (default: 0.5)
+    pub threshold: f64,
+    /// Device to place the model on (default: CUDA/GPU when available)
+    #[serde(
+        with = "crate::common::serde_utils::device",
+        default = "crate::common::serde_utils::device::default"
+    )]
+    pub device: Device,
+}
+
+impl NestedNERConfig {
+    /// Instantiate a new nested NER configuration of the supplied type, with the default span settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `model_type` - `ModelType` indicating the model type to load (must match with the actual data to be loaded!)
+    /// * `model_resource` - The `ResourceProvider` pointing to the model to load (e.g.  model.ot)
+    /// * `config_resource` - The `ResourceProvider` pointing to the model configuration to load (e.g. config.json)
+    /// * `vocab_resource` - The `ResourceProvider` pointing to the tokenizer's vocabulary to load (e.g.  vocab.txt/vocab.json)
+    /// * `merges_resource` - An optional `ResourceProvider` pointing to the tokenizer's merge file to load (e.g.  merges.txt), needed only for Roberta.
+    /// * `lower_case` - A `bool` indicating whether the tokenizer should lower case all input (in case of a lower-cased model)
+    pub fn new<RM, RC, RV>(
+        model_type: ModelType,
+        model_resource: RM,
+        config_resource: RC,
+        vocab_resource: RV,
+        merges_resource: Option<RV>,
+        lower_case: bool,
+        strip_accents: impl Into<Option<bool>>,
+        add_prefix_space: impl Into<Option<bool>>,
+    ) -> NestedNERConfig
+    where
+        RM: ResourceProvider + Send + 'static,
+        RC: ResourceProvider + Send + 'static,
+        RV: ResourceProvider + Send + 'static,
+    {
+        NestedNERConfig {
+            model_type,
+            model_resource: Box::new(model_resource),
+            config_resource: Box::new(config_resource),
+            vocab_resource: Box::new(vocab_resource),
+            merges_resource: merges_resource.map(|r| Box::new(r) as Box<_>),
+            lower_case,
+            strip_accents: strip_accents.into(),
+            add_prefix_space: add_prefix_space.into(),
+            max_span_width: 8,
+            span_width_embedding_size: 150,
+            threshold: 0.5,
+            device: Device::cuda_if_available(),
+        }
+    }
+}
+
+/// # Span classification head
+/// Classifies spans from the concatenation of the hidden states of their first and last tokens and
+/// of an embedding of their width (in words). It is made of the following blocks:
+/// - `width_embeddings`: embeddings of the span widths
+/// - `dense`: linear layer projecting the span representation to the hidden size, followed by a ReLU activation
+/// - `classifier`: linear layer projecting the hidden representation to the labels
+pub struct SpanClassificationHead {
+    width_embeddings: nn::Embedding,
+    dense: nn::Linear,
+    classifier: nn::Linear,
+}
+
+impl SpanClassificationHead {
+    /// Build a new `SpanClassificationHead`
+    ///
+    /// # Arguments
+    ///
+    /// * `p` - Variable store path for the root of the head
+    /// * `hidden_size` - hidden size of the transformer encoder
+    /// * `max_span_width` - maximum span width (in words)
+    /// * `width_embedding_size` - dimension of the span width embeddings
+    /// * `num_labels` - number of labels, including the label for spans that are not entities
+    pub fn new<'p, P>(
+        p: P,
+        hidden_size: i64,
+        max_span_width: i64,
+        width_embedding_size: i64,
+        num_labels: i64,
+    ) -> SpanClassificationHead
+    where
+        P: Borrow<nn::Path<'p>>,
+    {
+        let p = p.borrow();
+        let width_embeddings = nn::embedding(
+            p / "width_embeddings",
+            max_span_width,
+            width_embedding_size,
+            EmbeddingConfig::default(),
+        );
+        let dense = nn::linear(
+            p / "dense",
+            2 * hidden_size + width_embedding_size,
+            hidden_size,
+            Default::default(),
+        );
+        let classifier = nn::linear(
+            p / "classifier",
+            hidden_size,
+            num_labels,
+            Default::default(),
+        );
+        SpanClassificationHead {
+            width_embeddings,
+            dense,
+            classifier,
+        }
+    }
+
+    /// Forward pass through the span classification head
+    ///
+    /// # Arguments
+    ///
+    /// * `hidden_states` - hidden states of a sequence, of shape (*sequence_length*, *hidden_size*)
+    /// * `span_starts` - positions of the first token of the spans, of shape (*num_spans*)
+    /// * `span_ends` - positions of the last token of the spans, of shape (*num_spans*)
+    /// * `span_widths` - width of the spans in words, minus one, of shape (*num_spans*)
+    ///
+    /// # Returns
+    ///
+    /// * `Tensor` of shape (*num_spans*, *num_labels*) containing the logits of each span
+    pub fn forward(
+        &self,
+        hidden_states: &Tensor,
+        span_starts: &Tensor,
+        span_ends: &Tensor,
+        span_widths: &Tensor,
+    ) -> Tensor {
+        let span_representations = Tensor::cat(
+            &[
+                hidden_states.index_select(0, span_starts),
+                hidden_states.index_select(0, span_ends),
+                span_widths.apply(&self.width_embeddings),
+            ],
+            -1,
+        );
+        span_representations
+            .apply(&self.dense)
+            .relu()
+            .apply(&self.classifier)
+    }
+}
+
+/// Candidate span of words, with the positions of its first and last tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CandidateSpan {
+    start_token: usize,
+    end_token: usize,
+    width: usize,
+}
+
+/// Returns the (first token, last token) positions of the words of a tokenized input
+fn word_boundaries(tokenized_input: &TokenizedInput) -> Vec<(usize, usize)> {
+    let mut words = vec![];
+    let mut current_word: Option<(usize, usize)> = None;
+    for (position, (mask, special_token)) in tokenized_input
+        .mask
+        .iter()
+        .zip(tokenized_input.special_tokens_mask.iter())
+        .enumerate()
+    {
+        if *special_token != 0 || tokenized_input.token_offsets[position].is_none() {
+            if let Some(word) = current_word.take() {
+                words.push(word);
+            }
+            continue;
+        }
+        let continues_word = *mask == Mask::Continuation
+            || (position > 0 && tokenized_input.mask[position - 1] == Mask::Unfinished);
+        match current_word.as_mut() {
+            Some(word) if continues_word => word.1 = position,
+            _ => {
+                if let Some(word) = current_word.take() {
+                    words.push(word);
+                }
+                current_word = Some((position, position));
+            }
+        }
+    }
+    if let Some(word) = current_word {
+        words.push(word);
+    }
+    words
+}
+
+fn candidate_spans(words: &[(usize, usize)], max_span_width: usize) -> Vec<CandidateSpan> {
+    let mut spans = vec![];
+    for start in 0..words.len() {
+        for end in start..words.len().min(start + max_span_width) {
+            spans.push(CandidateSpan {
+                start_token: words[start].0,
+                end_token: words[end].1,
+                width: end - start + 1,
+            });
+        }
+    }
+    spans
+}
+
+/// Greedily selects spans by decreasing score, discarding spans crossing a selected span.
+/// Nested spans (one containing the other) are both kept. Returns the indices of the selected
+/// spans, sorted by start position and decreasing length.
+fn resolve_nested_spans(spans: &[(usize, usize)], scores: &[f64]) -> Vec<usize> {
+    let mut order = (0..spans.len()).collect::<Vec<usize>>();
+    order.sort_by(|&first, &second| scores[second].partial_cmp(&scores[first]).unwrap());
+    let mut selected: Vec<usize> = vec![];
+    for index in order {
+        let (start, end) = spans[index];
+        let crosses_selected = selected.iter().any(|&other| {
+            let (other_start, other_end) = spans[other];
+            let overlaps = start <= other_end && other_start <= end;
+            let nested = (start <= other_start && other_end <= end)
+                || (other_start <= start && end <= other_end);
+            overlaps && (!nested || (start, end) == (other_start, other_end))
+        });
+        if !crosses_selected {
+            selected.push(index);
+        }
+    }
+    selected.sort_by_key(|&index| (spans[index].0, std::cmp::Reverse(spans[index].1)));
+    selected
+}
+
+fn get_hidden_size(config: &ConfigOption) -> Result<i64, RustBertError> {
+    match config {
+        ConfigOption::Bert(config) | ConfigOption::Roberta(config) => Ok(config.hidden_size),
+        ConfigOption::DistilBert(config) => Ok(config.dim),
+        ConfigOption::Albert(config) => Ok(config.hidden_size),
+        ConfigOption::T5(config) => Ok(config.d_model),
+        _ => Err(RustBertError::InvalidConfigurationError(
+            "Nested NER is only supported for BERT, DistilBERT, RoBERTa, ALBERT and T5 encoders"
+                .to_string(),
+        )),
+    }
+}
+
+/// # NestedNERModel to extract nested entities with a span classification head
+pub struct NestedNERModel {
+    tokenizer: TokenizerOption,
+    transformer: SentenceEmbeddingsOption,
+    span_classifier: SpanClassificationHead,
+    label_mapping: Vec<String>,
+    outside_label_index: usize,
+    var_store: VarStore,
+    max_length: usize,
+    max_span_width: usize,
+    threshold: f64,
+}
+
+impl NestedNERModel {
+    /// Build a new `NestedNERModel`
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `NestedNERConfig` object containing the resource references (model, vocabulary, configuration), span settings and device placement (CPU/GPU)
+    pub fn new(config: NestedNERConfig) -> Result<NestedNERModel, RustBertError> {
+        let _span = trace_span!(
+            INFO,
+            "load_model",
+            pipeline = "nested_ner",
+            model_type = ?config.model_type,
+            device = ?config.device
+        );
+        if config.max_span_width == 0 {
+            return Err(RustBertError::InvalidConfigurationError(
+                "The maximum span width must be greater than 0".to_string(),
+            ));
+        }
+        let vocab_path = config.vocab_resource.get_local_path()?;
+        let merges_path = if let Some(merges_resource) = &config.merges_resource {
+            Some(merges_resource.get_local_path()?)
+        } else {
+            None
+        };
+        let config_path = config.config_resource.get_local_path()?;
+        let weights_path = config.model_resource.get_local_path()?;
+
+        let tokenizer = TokenizerOption::from_file(
+            config.model_type,
+            vocab_path.to_str().unwrap(),
+            merges_path.as_deref().map(|path| path.to_str().unwrap()),
+            config.lower_case,
+            config.strip_accents,
+            config.add_prefix_space,
+        )?;
+        let model_config = ConfigOption::from_file(config.model_type, config_path);
+        let max_length = model_config.get_max_len().map_or(512, |v| v as usize);
+        let hidden_size = get_hidden_size(&model_config)?;
+
+        let label_mapping = model_config.get_label_mapping();
+        let num_labels = label_mapping.len();
+        let label_mapping = (0..num_labels as i64)
+            .map(|index| {
+                label_mapping.get(&index).cloned().ok_or_else(|| {
+                    RustBertError::InvalidConfigurationError(format!(
+                        "Label index {} missing from the id2label mapping",
+                        index
+                    ))
+                })
+            })
+            .collect::<Result<Vec<String>, RustBertError>>()?;
+        let outside_label_index = label_mapping
+            .iter()
+            .position(|label| label == "O")
+            .ok_or_else(|| {
+                RustBertError::InvalidConfigurationError(
+                    "The id2label mapping must contain an `O` label for non-entity spans"
+                        .to_string(),
+                )
+            })?;
+
+        let mut var_store = VarStore::new(config.device);
+        let transformer =
+            SentenceEmbeddingsOption::new(config.model_type, &var_store.root(), &model_config)?;
+        let span_classifier = SpanClassificationHead::new(
+            var_store.root() / "span_classifier",
+            hidden_size,
+            config.max_span_width as i64,
+            config.span_width_embedding_size,
+            num_labels as i64,
+        );
+        var_store.load(weights_path)?;
+        Ok(NestedNERModel {
+            tokenizer,
+            transformer,
+            span_classifier,
+            label_mapping,
+            outside_label_index,
+            var_store,
+            max_length,
+            max_span_width: config.max_span_width,
+            threshold: config.threshold,
+        })
+    }
+
+    /// Extract entities, possibly nested, from input texts
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - `&[&str]` Array of texts to extract entities from.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Vec<Entity>>` containing the entities of each input, sorted by position (outer entities first). Entities may overlap when nested.
+    pub fn predict<S>(&self, input: &[S]) -> Result<Vec<Vec<Entity>>, RustBertError>
+    where
+        S: AsRef<str> + Sync,
+    {
+        if input.is_empty() {
+            return Ok(vec![]);
+        }
+        let tokenized_input = self.tokenizer.encode_list(
+            input,
+            self.max_length,
+            &TruncationStrategy::LongestFirst,
+            0,
+        );
+        let max_len = tokenized_input
+            .iter()
+            .map(|input| input.token_ids.len())
+            .max()
+            .unwrap_or(0);
+        let pad_id = self.tokenizer.get_pad_id().unwrap_or(0);
+        let device = self.var_store.device();
+        let input_ids = tokenized_input
+            .iter()
+            .map(|input| {
+                let mut token_ids = input.token_ids.clone();
+                token_ids.resize(max_len, pad_id);
+                Tensor::of_slice(&token_ids)
+            })
+            .collect::<Vec<_>>();
+        let input_ids = Tensor::stack(&input_ids, 0).to(device);
+        let attention_mask = input_ids.ne(pad_id).to_kind(Kind::Int64);
+        let (hidden_states, _) = no_grad(|| self.transformer.forward(&input_ids, &attention_mask))?;
+
+        let mut output = Vec::with_capacity(input.len());
+        for (sequence_index, (text, tokenized)) in input.iter().zip(tokenized_input).enumerate() {
+            let spans = candidate_spans(&word_boundaries(&tokenized), self.max_span_width);
+            if spans.is_empty() {
+                output.push(vec![]);
+                continue;
+            }
+            let span_starts = spans
+                .iter()
+                .map(|span| span.start_token as i64)
+                .collect::<Vec<i64>>();
+            let span_ends = spans
+                .iter()
+                .map(|span| span.end_token as i64)
+                .collect::<Vec<i64>>();
+            let span_widths = spans
+                .iter()
+                .map(|span| span.width as i64 - 1)
+                .collect::<Vec<i64>>();
+            let (scores, labels) = no_grad(|| {
+                self.span_classifier
+                    .forward(
+                        &hidden_states.get(sequence_index as i64),
+                        &Tensor::of_slice(&span_starts).to(device),
+                        &Tensor::of_slice(&span_ends).to(device),
+                        &Tensor::of_slice(&span_widths).to(device),
+                    )
+                    .softmax(-1, Kind::Float)
+                    .max_dim(-1, false)
+            });
+            let scores = scores.iter::<f64>()?.collect::<Vec<f64>>();
+            let labels = labels.iter::<i64>()?.collect::<Vec<i64>>();
+
+            let entity_spans = spans
+                .iter()
+                .zip(scores.iter().zip(labels.iter()))
+                .filter(|(_, (score, label))| {
+                    **label as usize != self.outside_label_index && **score >= self.threshold
+                })
+                .map(|(span, (score, label))| (*span, *score, *label as usize))
+                .collect::<Vec<(CandidateSpan, f64, usize)>>();
+            let selected = resolve_nested_spans(
+                &entity_spans
+                    .iter()
+                    .map(|(span, _, _)| (span.start_token, span.end_token))
+                    .collect::<Vec<(usize, usize)>>(),
+                &entity_spans
+                    .iter()
+                    .map(|(_, score, _)| *score)
+                    .collect::<Vec<f64>>(),
+            );
+
+            let characters = text.as_ref().chars().collect::<Vec<char>>();
+            let entities = selected
+                .into_iter()
+                .filter_map(|index| {
+                    let (span, score, label) = entity_spans[index];
+                    let begin = tokenized.token_offsets[span.start_token]?.begin;
+                    let end = tokenized.token_offsets[span.end_token]?.end;
+                    Some(Entity {
+                        word: characters
+                            .get(begin as usize..end as usize)?
+                            .iter()
+                            .collect(),
+                        score,
+                        label: self.label_mapping[label].clone(),
+                        offset: Offset { begin, end },
+                    })
+                })
+                .collect::<Vec<Entity>>();
+            output.push(entities);
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nested_spans_resolution() {
+        // "University of California": ORG (0, 2) containing LOC (2, 2), and a crossing span (1, 3)
+        let spans = [(0, 2), (2, 2), (1, 3), (0, 2), (5, 5)];
+        let scores = [0.9, 0.8, 0.85, 0.7, 0.6];
+        let selected = resolve_nested_spans(&spans, &scores);
+        assert_eq!(selected, vec![0, 1, 4]);
+    }
+
+    #[test]
+    fn candidate_spans_enumeration() {
+        let words = [(1, 1), (2, 3), (4, 4)];
+        let spans = candidate_spans(&words, 2);
+        assert_eq!(
+            spans
+                .iter()
+                .map(|span| (span.start_token, span.end_token, span.width))
+                .collect::<Vec<_>>(),
+            vec![(1, 1, 1), (1, 3, 2), (2, 3, 1), (2, 4, 2), (4, 4, 1)]
+        );
+    }
+}
@@ -0,0 +1,291 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Cache of encoder outputs keyed by input hash
+//! Pipelines repeatedly scoring the same inputs (e.g. a cross-encoder reranking the same documents
+//! for recurring queries, or zero-shot classification of the same premise against a set of labels)
+//! can keep the outputs of the model in an `OutputCache`, so that each distinct input is only
+//! encoded once per process:
+//! - the cache key is a hash of the (unpadded) token ids and token type ids of each input of the
+//! batch. Cross-encoders encode text pairs jointly, and the cache therefore applies to recurring
+//! pairs rather than to individual texts.
+//! - only the inputs missing from the cache (de-duplicated within the batch) are forwarded through
+//! the model, and the cached outputs are stacked back in the order of the batch
+//! - the cache holds at most `capacity` outputs, the least recently used output being evicted first
+//!
+//! Outputs are stored on the device of the model. Since cached inputs are not padded to the same
+//! length as the rest of the batch, the outputs may differ from an uncached forward pass by
+//! floating point rounding.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::zero_shot_classification::ZeroShotClassificationModel;
+//!
+//! let model = ZeroShotClassificationModel::new(Default::default())?.with_output_cache(1024);
+//! let premise = ["Who are you voting for in 2020?"];
+//! let _ = model.predict(premise, ["politics", "economy"], None, 128)?;
+//! // The (premise, hypothesis) pair for "politics" is served from the cache
+//! let _ = model.predict(premise, ["politics", "sports"], None, 128)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::pipelines::input_encoding::EncodedInputs;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use tch::{Device, Tensor};
+
+/// # Bounded cache of model outputs
+/// Stores one output row per distinct encoded input. The cache is shared behind a mutex, the
+/// model forward pass being executed without holding the lock.
+pub struct OutputCache {
+    entries: Mutex<LruCache<Tensor>>,
+}
+
+impl OutputCache {
+    /// Creates a new `OutputCache` holding at most `capacity` outputs
+    pub fn new(capacity: usize) -> OutputCache {
+        OutputCache {
+            entries: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    /// Number of outputs currently cached
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Returns `true` if no output is cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all cached outputs
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear()
+    }
+
+    /// Returns the outputs of the model for a batch of inputs, only calling `forward` on the
+    /// inputs missing from the cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoded_inputs` - `EncodedInputs` padded batch of inputs
+    /// * `forward` - closure running the model on (input ids, attention mask, token type ids) and returning outputs with the batch as first dimension
+    ///
+    /// # Returns
+    ///
+    /// * `Tensor` outputs of the model for the full batch, in the order of the inputs
+    pub fn forward<F>(&self, encoded_inputs: &EncodedInputs, forward: F) -> Tensor
+    where
+        F: FnOnce(&Tensor, &Tensor, Option<&Tensor>) -> Tensor,
+    {
+        let keys = input_keys(encoded_inputs);
+        let mut outputs = {
+            let mut entries = self.entries.lock().unwrap();
+            keys.iter()
+                .map(|key| entries.get(key).map(|output| output.shallow_clone()))
+                .collect::<Vec<Option<Tensor>>>()
+        };
+
+        let mut missing_inputs: Vec<i64> = vec![];
+        let mut missing_positions: HashMap<u64, i64> = HashMap::new();
+        for (position, key) in keys.iter().enumerate() {
+            if outputs[position].is_none() && !missing_positions.contains_key(key) {
+                missing_positions.insert(*key, missing_inputs.len() as i64);
+                missing_inputs.push(position as i64);
+            }
+        }
+
+        if !missing_inputs.is_empty() {
+            let device = encoded_inputs.input_ids.device();
+            let index = Tensor::of_slice(&missing_inputs).to(device);
+            let new_outputs = forward(
+                &encoded_inputs.input_ids.index_select(0, &index),
+                &encoded_inputs.attention_mask.index_select(0, &index),
+                encoded_inputs
+                    .token_type_ids
+                    .as_ref()
+                    .map(|token_type_ids| token_type_ids.index_select(0, &index))
+                    .as_ref(),
+            )
+            .detach();
+
+            let mut entries = self.entries.lock().unwrap();
+            for (key, row) in &missing_positions {
+                entries.insert(*key, new_outputs.get(*row));
+            }
+            for (position, key) in keys.iter().enumerate() {
+                if outputs[position].is_none() {
+                    outputs[position] = Some(new_outputs.get(missing_positions[key]));
+                }
+            }
+        }
+
+        Tensor::stack(
+            &outputs
+                .into_iter()
+                .map(|output| output.unwrap())
+                .collect::<Vec<Tensor>>(),
+            0,
+        )
+    }
+}
+
+/// Hashes the unpadded token ids (and token type ids when available) of each input of the batch
+fn input_keys(encoded_inputs: &EncodedInputs) -> Vec<u64> {
+    let to_rows = |tensor: &Tensor| {
+        let tensor = tensor.to(Device::Cpu);
+        (0..tensor.size()[0])
+            .map(|row| Vec::<i64>::from(tensor.get(row)))
+            .collect::<Vec<Vec<i64>>>()
+    };
+    let input_ids = to_rows(&encoded_inputs.input_ids);
+    let attention_mask = to_rows(&encoded_inputs.attention_mask);
+    let token_type_ids = encoded_inputs.token_type_ids.as_ref().map(to_rows);
+
+    input_ids
+        .iter()
+        .zip(attention_mask.iter())
+        .enumerate()
+        .map(|(row, (input_ids, attention_mask))| {
+            let length = attention_mask.iter().filter(|&&mask| mask != 0).count();
+            hash_input(
+                &input_ids[..length],
+                token_type_ids
+                    .as_ref()
+                    .map(|token_type_ids| &token_type_ids[row][..length]),
+            )
+        })
+        .collect()
+}
+
+fn hash_input(input_ids: &[i64], token_type_ids: Option<&[i64]>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    input_ids.hash(&mut hasher);
+    token_type_ids.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Least recently used cache, evicting the entry with the oldest access when full
+struct LruCache<V> {
+    capacity: usize,
+    clock: u64,
+    entries: HashMap<u64, (V, u64)>,
+}
+
+impl<V> LruCache<V> {
+    fn new(capacity: usize) -> LruCache<V> {
+        LruCache {
+            capacity,
+            clock: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear()
+    }
+
+    fn get(&mut self, key: &u64) -> Option<&V> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.entries.get_mut(key).map(|(value, last_used)| {
+            *last_used = clock;
+            &*value
+        })
+    }
+
+    fn insert(&mut self, key: u64, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            if let Some(oldest_key) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| *key)
+            {
+                self.entries.remove(&oldest_key);
+            }
+        }
+        self.clock += 1;
+        self.entries.insert(key, (value, self.clock));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn least_recently_used_eviction() {
+        let mut cache = LruCache::new(2);
+        cache.insert(1, "a");
+        cache.insert(2, "b");
+        assert_eq!(cache.get(&1), Some(&"a"));
+        cache.insert(3, "c");
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1), Some(&"a"));
+        assert_eq!(cache.get(&3), Some(&"c"));
+
+        let mut disabled_cache = LruCache::new(0);
+        disabled_cache.insert(1, "a");
+        assert_eq!(disabled_cache.len(), 0);
+    }
+
+    #[test]
+    fn input_keys_ignore_padding() {
+        let encoded_inputs = EncodedInputs {
+            input_ids: Tensor::of_slice(&[101, 7, 102, 0, 101, 7, 102, 8, 101, 7, 102, 0])
+                .view((3, 4)),
+            attention_mask: Tensor::of_slice(&[1, 1, 1, 0, 1, 1, 1, 1, 1, 1, 1, 0]).view((3, 4)),
+            token_type_ids: None,
+        };
+        let keys = input_keys(&encoded_inputs);
+        assert_eq!(keys[0], keys[2]);
+        assert_ne!(keys[0], keys[1]);
+        assert_eq!(keys[0], hash_input(&[101, 7, 102], None));
+    }
+
+    #[test]
+    fn forward_only_missing_inputs() {
+        let cache = OutputCache::new(8);
+        let encoded_inputs = EncodedInputs {
+            input_ids: Tensor::of_slice(&[101, 7, 102, 101, 8, 102, 101, 7, 102]).view((3, 3)),
+            attention_mask: Tensor::ones(&[3, 3], (tch::Kind::Int64, Device::Cpu)),
+            token_type_ids: None,
+        };
+        let forward = |input_ids: &Tensor, _: &Tensor, _: Option<&Tensor>| {
+            input_ids.sum_dim_intlist([1].as_slice(), true, tch::Kind::Int64)
+        };
+
+        let output = cache.forward(&encoded_inputs, |input_ids, mask, token_type_ids| {
+            assert_eq!(input_ids.size(), [2, 3]);
+            forward(input_ids, mask, token_type_ids)
+        });
+        assert_eq!(Vec::<i64>::from(output.view(-1)), [210, 211, 210]);
+        assert_eq!(cache.len(), 2);
+
+        let output = cache.forward(&encoded_inputs, |_, _, _| {
+            panic!("All inputs should be cached")
+        });
+        assert_eq!(Vec::<i64>::from(output.view(-1)), [210, 211, 210]);
+    }
+}
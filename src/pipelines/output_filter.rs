@@ -0,0 +1,316 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Output filtering hooks for generation pipelines
+//! Post-generation hooks rewriting the generated texts before they are returned by the
+//! [`TextGenerationModel`](crate::pipelines::text_generation::TextGenerationModel) and
+//! [`ConversationModel`](crate::pipelines::conversation::ConversationModel) pipelines. Several
+//! filters can be attached to a pipeline, they are applied in the order they were added.
+//!
+//! The following filters are available:
+//! - `RegexRedaction` replaces the matches of regular expressions (e.g. email addresses, phone numbers)
+//! - `EntityRedaction` replaces the entities detected by a NER model (e.g. person names, locations)
+//! - `ProfanityFilter` masks the words of a block list
+//!
+//! Any closure taking and returning the batch of generated texts can also be used as a filter.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::output_filter::{
+//!     ProfanityFilter, RegexRedaction, EMAIL_PATTERN, PHONE_NUMBER_PATTERN,
+//! };
+//! use rust_bert::pipelines::text_generation::TextGenerationModel;
+//!
+//! let model = TextGenerationModel::new(Default::default())?
+//!     .with_output_filter(RegexRedaction::new(&[
+//!         (EMAIL_PATTERN, "[EMAIL]"),
+//!         (PHONE_NUMBER_PATTERN, "[PHONE]"),
+//!     ])?)
+//!     .with_output_filter(ProfanityFilter::new(&["darn", "heck"])?);
+//!
+//! let output = model.generate(&["Please contact me at"], None)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::common::error::RustBertError;
+use crate::pipelines::ner::NERModel;
+use regex::Regex;
+
+/// Regular expression matching email addresses
+pub const EMAIL_PATTERN: &str = r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}";
+
+/// Regular expression matching phone numbers (optional country code, digits separated by spaces, dots or dashes)
+pub const PHONE_NUMBER_PATTERN: &str =
+    r"(?:\+\d{1,3}[\s.-]?)?(?:\(\d{1,4}\)[\s.-]?)?\d{2,4}(?:[\s.-]?\d{2,4}){2,3}\b";
+
+/// # Post-generation hook rewriting generated texts
+pub trait OutputFilter: Send {
+    /// Rewrites a batch of generated texts
+    ///
+    /// # Arguments
+    ///
+    /// * `outputs` - Generated texts
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<String>, RustBertError>` filtered texts, in the same order
+    fn filter(&self, outputs: Vec<String>) -> Result<Vec<String>, RustBertError>;
+}
+
+impl<F> OutputFilter for F
+where
+    F: Fn(Vec<String>) -> Result<Vec<String>, RustBertError> + Send,
+{
+    fn filter(&self, outputs: Vec<String>) -> Result<Vec<String>, RustBertError> {
+        self(outputs)
+    }
+}
+
+/// # Filter replacing the matches of regular expressions
+pub struct RegexRedaction {
+    patterns: Vec<(Regex, String)>,
+}
+
+impl RegexRedaction {
+    /// Build a new `RegexRedaction` filter
+    ///
+    /// # Arguments
+    ///
+    /// * `patterns` - Pairs of regular expression and replacement text. The replacement can refer to capture groups (e.g. `$1`).
+    ///
+    /// # Returns
+    ///
+    /// * `Result<RegexRedaction, RustBertError>` filter, or an error if a pattern is not a valid regular expression
+    pub fn new<S, R>(patterns: &[(S, R)]) -> Result<RegexRedaction, RustBertError>
+    where
+        S: AsRef<str>,
+        R: AsRef<str>,
+    {
+        let patterns = patterns
+            .iter()
+            .map(|(pattern, replacement)| {
+                Ok((
+                    compile_pattern(pattern.as_ref())?,
+                    replacement.as_ref().to_string(),
+                ))
+            })
+            .collect::<Result<Vec<(Regex, String)>, RustBertError>>()?;
+        Ok(RegexRedaction { patterns })
+    }
+
+    /// Replaces the matches of the patterns in a text
+    pub fn redact(&self, text: &str) -> String {
+        self.patterns
+            .iter()
+            .fold(text.to_string(), |text, (pattern, replacement)| {
+                pattern
+                    .replace_all(&text, replacement.as_str())
+                    .into_owned()
+            })
+    }
+}
+
+impl OutputFilter for RegexRedaction {
+    fn filter(&self, outputs: Vec<String>) -> Result<Vec<String>, RustBertError> {
+        Ok(outputs.iter().map(|output| self.redact(output)).collect())
+    }
+}
+
+/// # Filter masking the words of a block list
+/// Words are matched on word boundaries, ignoring case, and each of their characters is replaced
+/// by the mask character.
+pub struct ProfanityFilter {
+    pattern: Regex,
+    mask: char,
+}
+
+impl ProfanityFilter {
+    /// Build a new `ProfanityFilter` masking words with `*`
+    ///
+    /// # Arguments
+    ///
+    /// * `words` - Words to mask
+    pub fn new<S>(words: &[S]) -> Result<ProfanityFilter, RustBertError>
+    where
+        S: AsRef<str>,
+    {
+        if words.is_empty() {
+            return Err(RustBertError::InvalidConfigurationError(
+                "The profanity filter requires at least one word".to_string(),
+            ));
+        }
+        let alternatives = words
+            .iter()
+            .map(|word| regex::escape(word.as_ref()))
+            .collect::<Vec<String>>()
+            .join("|");
+        Ok(ProfanityFilter {
+            pattern: compile_pattern(&format!(r"(?i)\b(?:{})\b", alternatives))?,
+            mask: '*',
+        })
+    }
+
+    /// Sets the character replacing the characters of the masked words
+    pub fn with_mask(mut self, mask: char) -> ProfanityFilter {
+        self.mask = mask;
+        self
+    }
+
+    /// Masks the words of the block list in a text
+    pub fn mask(&self, text: &str) -> String {
+        self.pattern
+            .replace_all(text, |captures: &regex::Captures| {
+                self.mask.to_string().repeat(captures[0].chars().count())
+            })
+            .into_owned()
+    }
+}
+
+impl OutputFilter for ProfanityFilter {
+    fn filter(&self, outputs: Vec<String>) -> Result<Vec<String>, RustBertError> {
+        Ok(outputs.iter().map(|output| self.mask(output)).collect())
+    }
+}
+
+/// # Filter replacing the entities detected by a NER model
+/// Entities are replaced by their label in brackets (e.g. `[PER]`), only the entities with one of
+/// the configured labels are replaced.
+pub struct EntityRedaction {
+    ner_model: NERModel,
+    labels: Vec<String>,
+}
+
+impl EntityRedaction {
+    /// Build a new `EntityRedaction` filter
+    ///
+    /// # Arguments
+    ///
+    /// * `ner_model` - `NERModel` detecting the entities
+    /// * `labels` - Labels of the entities to replace (e.g. `PER`, `LOC`). All entities are replaced if empty.
+    pub fn new<S>(ner_model: NERModel, labels: &[S]) -> EntityRedaction
+    where
+        S: AsRef<str>,
+    {
+        EntityRedaction {
+            ner_model,
+            labels: labels
+                .iter()
+                .map(|label| label.as_ref().to_string())
+                .collect(),
+        }
+    }
+}
+
+impl OutputFilter for EntityRedaction {
+    fn filter(&self, outputs: Vec<String>) -> Result<Vec<String>, RustBertError> {
+        let texts = outputs.iter().map(String::as_str).collect::<Vec<&str>>();
+        Ok(self
+            .ner_model
+            .predict_full_entities(&texts)
+            .into_iter()
+            .zip(texts.iter())
+            .map(|(entities, text)| {
+                let spans = entities
+                    .iter()
+                    .filter(|entity| self.labels.is_empty() || self.labels.contains(&entity.label))
+                    .map(|entity| {
+                        (
+                            entity.offset.begin as usize,
+                            entity.offset.end as usize,
+                            format!("[{}]", entity.label),
+                        )
+                    })
+                    .collect::<Vec<(usize, usize, String)>>();
+                replace_char_spans(text, spans)
+            })
+            .collect())
+    }
+}
+
+fn compile_pattern(pattern: &str) -> Result<Regex, RustBertError> {
+    Regex::new(pattern).map_err(|error| {
+        RustBertError::InvalidConfigurationError(format!("Invalid pattern {}: {}", pattern, error))
+    })
+}
+
+/// Replaces spans of characters (begin, end, replacement) of a text. Spans overlapping a previous
+/// span are ignored.
+pub(crate) fn replace_char_spans(text: &str, mut spans: Vec<(usize, usize, String)>) -> String {
+    spans.sort_by_key(|(begin, end, _)| (*begin, *end));
+    let mut output = String::with_capacity(text.len());
+    let mut position = 0;
+    let mut spans = spans.into_iter().peekable();
+    for (char_index, character) in text.chars().enumerate() {
+        while matches!(spans.peek(), Some((begin, _, _)) if *begin < position) {
+            spans.next();
+        }
+        match spans.peek() {
+            Some((begin, end, _)) if *begin == char_index && end > begin => {
+                let (_, end, replacement) = spans.next().unwrap();
+                output.push_str(&replacement);
+                position = end;
+            }
+            _ => {}
+        }
+        if char_index >= position {
+            output.push(character);
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn regex_redaction() -> Result<(), RustBertError> {
+        let filter = RegexRedaction::new(&[
+            (EMAIL_PATTERN, "[EMAIL]"),
+            (PHONE_NUMBER_PATTERN, "[PHONE]"),
+        ])?;
+        let output = filter.filter(vec![
+            "Write to jane.doe@example.com or call +1 415-555-0132.".to_string(),
+            "Nothing to hide in 2022.".to_string(),
+        ])?;
+        assert_eq!(output[0], "Write to [EMAIL] or call [PHONE].");
+        assert_eq!(output[1], "Nothing to hide in 2022.");
+        assert!(RegexRedaction::new(&[("(unclosed", "")]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn profanity_filter() -> Result<(), RustBertError> {
+        let filter = ProfanityFilter::new(&["darn", "heck"])?;
+        assert_eq!(
+            filter.mask("Darn, what the heck? Darned hecks."),
+            "****, what the ****? Darned hecks."
+        );
+        assert_eq!(filter.with_mask('#').mask("darn"), "####");
+        assert!(ProfanityFilter::new::<&str>(&[]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn char_span_replacement() {
+        let spans = vec![
+            (13, 18, "[LOC]".to_string()),
+            (0, 3, "[PER]".to_string()),
+            (14, 16, "[ORG]".to_string()),
+        ];
+        assert_eq!(
+            replace_char_spans("Zoë lives in Paris.", spans),
+            "[PER] lives in [LOC]."
+        );
+        assert_eq!(replace_char_spans("Zoë", vec![]), "Zoë");
+    }
+}
@@ -0,0 +1,239 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Perplexity evaluation
+//! Computes the perplexity of a language model over a corpus of texts, e.g. to compare models on
+//! a held-out dataset:
+//! - causal language models (`TextGenerationModel` for GPT, GPT2 and GPT-Neo) are scored with the
+//! log-likelihood of each token given the previous tokens
+//! - masked language models (`MaskedLanguageModel`) are scored with the pseudo log-likelihood of each
+//! token, predicted with the token masked and the rest of the window as context
+//!
+//! Texts longer than the model maximum length are scored with a sliding window: windows of
+//! `max_length` tokens start every `stride` tokens, and each token is scored once, within the
+//! first window containing it after the tokens of the previous window. A smaller stride provides
+//! more context for each prediction at the cost of more forward passes. Windows are batched
+//! (`batch_size`) on the model device.
+//!
+//! The corpus perplexity is the exponential of the average negative log-likelihood over all the
+//! scored tokens of the corpus.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::perplexity::{perplexity, PerplexityConfig};
+//! use rust_bert::pipelines::text_generation::TextGenerationModel;
+//!
+//! let model = TextGenerationModel::new(Default::default())?;
+//! let corpus = std::fs::read_to_string("corpus.txt")?;
+//! let output = perplexity(&model, corpus.lines(), &PerplexityConfig::default())?;
+//! println!("Perplexity: {:.2}", output.perplexity);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::pipelines::common::TokenizerOption;
+use crate::RustBertError;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Configuration for the perplexity evaluation
+pub struct PerplexityConfig {
+    /// Maximum number of tokens in a window (default: model maximum length)
+    pub max_length: Option<usize>,
+    /// Number of tokens between the start of two consecutive windows (default: 512, capped to the window length)
+    pub stride: usize,
+    /// Number of windows (causal language models) or masked sequences (masked language models) per forward pass (default: 8)
+    pub batch_size: usize,
+}
+
+impl Default for PerplexityConfig {
+    fn default() -> Self {
+        PerplexityConfig {
+            max_length: None,
+            stride: 512,
+            batch_size: 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+/// # Perplexity of a language model over a corpus
+pub struct Perplexity {
+    /// Perplexity (exponential of the average negative log-likelihood)
+    pub perplexity: f64,
+    /// Average negative log-likelihood of the scored tokens
+    pub negative_log_likelihood: f64,
+    /// Number of scored tokens
+    pub num_tokens: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// # Window of tokens to score
+pub struct ScoringWindow {
+    /// Token ids of the window (without special tokens)
+    pub token_ids: Vec<i64>,
+    /// Index of the first token to score in the window, the preceding tokens only provide context
+    pub target_start: usize,
+}
+
+/// # Language models that can be evaluated with `perplexity`
+pub trait PerplexityModel {
+    /// Returns the tokenizer of the model
+    fn perplexity_tokenizer(&self) -> &TokenizerOption;
+
+    /// Returns the maximum number of tokens (excluding special tokens) in a window
+    fn max_window_length(&self) -> usize;
+
+    /// Scores a batch of windows, returning the sum of the negative log-likelihood of the scored
+    /// tokens and the number of scored tokens
+    ///
+    /// # Arguments
+    ///
+    /// * `windows` - `ScoringWindow`s to score
+    /// * `batch_size` - maximum number of sequences per forward pass
+    fn score_windows(
+        &self,
+        windows: &[ScoringWindow],
+        batch_size: usize,
+    ) -> Result<(f64, usize), RustBertError>;
+}
+
+/// Splits a sequence of token ids in overlapping windows of at most `max_length` tokens starting
+/// every `stride` tokens. Each token is a target of exactly one window.
+pub fn sliding_windows(token_ids: &[i64], max_length: usize, stride: usize) -> Vec<ScoringWindow> {
+    let max_length = max_length.max(1);
+    let stride = stride.clamp(1, max_length);
+    let mut windows = vec![];
+    let mut previous_end = 0;
+    let mut begin = 0;
+    while begin < token_ids.len() {
+        let end = (begin + max_length).min(token_ids.len());
+        windows.push(ScoringWindow {
+            token_ids: token_ids[begin..end].to_vec(),
+            target_start: previous_end - begin,
+        });
+        if end == token_ids.len() {
+            break;
+        }
+        previous_end = end;
+        begin += stride;
+    }
+    windows
+}
+
+/// Computes the perplexity of a language model over a corpus of texts.
+///
+/// # Arguments
+///
+/// * `model` - language model implementing `PerplexityModel` (`TextGenerationModel` or `MaskedLanguageModel`)
+/// * `texts` - iterator over the texts of the corpus, consumed lazily
+/// * `config` - `PerplexityConfig` with the sliding window and batching settings
+///
+/// # Returns
+///
+/// * `Perplexity` of the model over the corpus
+pub fn perplexity<M, I, S>(
+    model: &M,
+    texts: I,
+    config: &PerplexityConfig,
+) -> Result<Perplexity, RustBertError>
+where
+    M: PerplexityModel + ?Sized,
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    if config.batch_size == 0 {
+        return Err(RustBertError::InvalidConfigurationError(
+            "The batch size must be greater than 0".to_string(),
+        ));
+    }
+    let max_length = config
+        .max_length
+        .unwrap_or_else(|| model.max_window_length())
+        .min(model.max_window_length());
+    let tokenizer = model.perplexity_tokenizer();
+
+    let mut total_negative_log_likelihood = 0f64;
+    let mut num_tokens = 0usize;
+    let mut batch = Vec::with_capacity(config.batch_size);
+    for text in texts {
+        let tokens = tokenizer.tokenize(text.as_ref());
+        let token_ids = tokenizer.convert_tokens_to_ids(&tokens);
+        for window in sliding_windows(&token_ids, max_length, config.stride) {
+            batch.push(window);
+            if batch.len() == config.batch_size {
+                let (negative_log_likelihood, count) =
+                    model.score_windows(&batch, config.batch_size)?;
+                total_negative_log_likelihood += negative_log_likelihood;
+                num_tokens += count;
+                batch.clear();
+            }
+        }
+    }
+    if !batch.is_empty() {
+        let (negative_log_likelihood, count) = model.score_windows(&batch, config.batch_size)?;
+        total_negative_log_likelihood += negative_log_likelihood;
+        num_tokens += count;
+    }
+
+    if num_tokens == 0 {
+        return Err(RustBertError::ValueError(
+            "No tokens to score in the corpus".to_string(),
+        ));
+    }
+    let negative_log_likelihood = total_negative_log_likelihood / num_tokens as f64;
+    Ok(Perplexity {
+        perplexity: negative_log_likelihood.exp(),
+        negative_log_likelihood,
+        num_tokens,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sliding_windows_score_each_token_once() {
+        let token_ids = (0..10).collect::<Vec<i64>>();
+
+        let windows = sliding_windows(&token_ids, 4, 2);
+        assert_eq!(
+            windows,
+            vec![
+                ScoringWindow {
+                    token_ids: vec![0, 1, 2, 3],
+                    target_start: 0
+                },
+                ScoringWindow {
+                    token_ids: vec![2, 3, 4, 5],
+                    target_start: 2
+                },
+                ScoringWindow {
+                    token_ids: vec![4, 5, 6, 7],
+                    target_start: 2
+                },
+                ScoringWindow {
+                    token_ids: vec![6, 7, 8, 9],
+                    target_start: 2
+                },
+            ]
+        );
+
+        let windows = sliding_windows(&token_ids, 4, 8);
+        let targets = windows
+            .iter()
+            .flat_map(|window| window.token_ids[window.target_start..].to_vec())
+            .collect::<Vec<i64>>();
+        assert_eq!(targets, token_ids);
+        assert!(sliding_windows(&[], 4, 2).is_empty());
+    }
+}
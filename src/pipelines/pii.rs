@@ -0,0 +1,549 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # PII detection and anonymization pipeline
+//! Detects personally identifiable information (PII) in texts and returns either the annotated
+//! spans or an anonymized copy of the texts. Two detectors are combined:
+//! - a token classification (NER) model detecting person names, organizations and addresses. The
+//!   labels of the model are mapped to PII types with a configurable mapping (e.g. `PER` → person).
+//! - patterns with validators for structured identifiers: email addresses, phone numbers (7 to 15
+//!   digits), credit card numbers (Luhn checksum) and IPv4 addresses (octets up to 255).
+//!
+//! When detections overlap, the longest one is kept. The NER model is optional: without it, only
+//! the structured identifiers are detected.
+//!
+//! Detected entities are either redacted (`[PERSON]`), masked (`*****`) or pseudonymized
+//! (`PERSON_1`, `PERSON_2`...), pseudonyms being consistent within a text so that the anonymized
+//! text can still be read (the same person gets the same pseudonym).
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::pii::{Anonymization, PiiConfig, PiiModel};
+//!
+//! let config = PiiConfig {
+//!     anonymization: Anonymization::Pseudonymize,
+//!     ..PiiConfig::new(Some(Default::default()))
+//! };
+//! let pii_model = PiiModel::new(config)?;
+//!
+//! let input = ["Amy lives in Paris. Contact Amy at amy@example.com or +1 415-555-0132."];
+//! let entities = pii_model.detect(&input);
+//! let anonymized = pii_model.anonymize(&input);
+//! # Ok(())
+//! # }
+//! ```
+//! Output: \
+//! ```no_run
+//! # let output =
+//! "PERSON_1 lives in ADDRESS_1. Contact PERSON_1 at EMAIL_1 or PHONE_NUMBER_1."
+//! # ;
+//! ```
+//!
+//! A `PiiModel` implements `OutputFilter` and can be attached to the text generation and
+//! conversation pipelines to anonymize the generated texts.
+
+use crate::common::error::RustBertError;
+use crate::pipelines::ner::NERModel;
+use crate::pipelines::output_filter::{
+    replace_char_spans, OutputFilter, EMAIL_PATTERN, PHONE_NUMBER_PATTERN,
+};
+use crate::pipelines::token_classification::TokenClassificationConfig;
+use regex::Regex;
+use rust_tokenizers::Offset;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const CREDIT_CARD_NUMBER_PATTERN: &str = r"\b\d(?:[ -]?\d){12,18}\b";
+
+const IP_ADDRESS_PATTERN: &str = r"\b(?:\d{1,3}\.){3}\d{1,3}\b";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// # Type of personally identifiable information
+pub enum PiiEntityType {
+    /// Person name (NER model)
+    Person,
+    /// Organization name (NER model)
+    Organization,
+    /// Address or location (NER model)
+    Address,
+    /// Email address (pattern)
+    Email,
+    /// Phone number (pattern)
+    PhoneNumber,
+    /// Credit card number (pattern)
+    CreditCardNumber,
+    /// IPv4 address (pattern)
+    IpAddress,
+}
+
+impl PiiEntityType {
+    /// All PII types
+    pub const ALL: [PiiEntityType; 7] = [
+        PiiEntityType::Person,
+        PiiEntityType::Organization,
+        PiiEntityType::Address,
+        PiiEntityType::Email,
+        PiiEntityType::PhoneNumber,
+        PiiEntityType::CreditCardNumber,
+        PiiEntityType::IpAddress,
+    ];
+
+    /// Tag used to redact and pseudonymize the entities of this type (e.g. `PERSON`)
+    pub fn tag(&self) -> &'static str {
+        match self {
+            PiiEntityType::Person => "PERSON",
+            PiiEntityType::Organization => "ORGANIZATION",
+            PiiEntityType::Address => "ADDRESS",
+            PiiEntityType::Email => "EMAIL",
+            PiiEntityType::PhoneNumber => "PHONE_NUMBER",
+            PiiEntityType::CreditCardNumber => "CREDIT_CARD_NUMBER",
+            PiiEntityType::IpAddress => "IP_ADDRESS",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// # Anonymization applied to the detected entities
+pub enum Anonymization {
+    /// Replace the entities by their type in brackets (e.g. `[PERSON]`)
+    Redact,
+    /// Replace each character of the entities by the mask character
+    Mask(char),
+    /// Replace the entities by a pseudonym numbered by type (e.g. `PERSON_1`). Identical entities of a text get the same pseudonym.
+    Pseudonymize,
+}
+
+#[derive(Serialize, Deserialize)]
+/// # Configuration for PiiModel
+pub struct PiiConfig {
+    /// Configuration of the NER model detecting person names, organizations and addresses (default: None, only structured identifiers are detected)
+    pub ner_config: Option<TokenClassificationConfig>,
+    /// Mapping from the labels of the NER model to PII types (default: `PER` → person, `ORG` → organization, `LOC` → address)
+    pub label_mapping: HashMap<String, PiiEntityType>,
+    /// Minimum score of the entities detected by the NER model (default: 0.5)
+    pub min_score: f64,
+    /// PII types to detect (default: all)
+    pub entity_types: Vec<PiiEntityType>,
+    /// Anonymization applied by `PiiModel::anonymize` (default: `Anonymization::Redact`)
+    pub anonymization: Anonymization,
+}
+
+impl PiiConfig {
+    /// Instantiate a new PII configuration with default settings
+    ///
+    /// # Arguments
+    ///
+    /// * `ner_config` - Optional `TokenClassificationConfig` of the NER model detecting person names, organizations and addresses
+    pub fn new(ner_config: Option<TokenClassificationConfig>) -> PiiConfig {
+        PiiConfig {
+            ner_config,
+            label_mapping: [
+                ("PER", PiiEntityType::Person),
+                ("ORG", PiiEntityType::Organization),
+                ("LOC", PiiEntityType::Address),
+            ]
+            .iter()
+            .map(|(label, entity_type)| (label.to_string(), *entity_type))
+            .collect(),
+            min_score: 0.5,
+            entity_types: PiiEntityType::ALL.to_vec(),
+            anonymization: Anonymization::Redact,
+        }
+    }
+}
+
+impl Default for PiiConfig {
+    fn default() -> PiiConfig {
+        PiiConfig::new(None)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// # PII entity detected by a `PiiModel`
+pub struct PiiEntity {
+    /// Type of the entity
+    pub entity_type: PiiEntityType,
+    /// Text of the entity
+    pub text: String,
+    /// Confidence score (1.0 for entities detected by patterns)
+    pub score: f64,
+    /// Character offsets of the entity in the input text
+    pub offset: Offset,
+}
+
+struct PatternDetector {
+    entity_type: PiiEntityType,
+    pattern: Regex,
+    validator: fn(&str) -> bool,
+}
+
+impl PatternDetector {
+    fn detect(&self, text: &str) -> Vec<PiiEntity> {
+        self.pattern
+            .find_iter(text)
+            .filter(|found| (self.validator)(found.as_str()))
+            .map(|found| {
+                let begin = text[..found.start()].chars().count();
+                let end = begin + found.as_str().chars().count();
+                PiiEntity {
+                    entity_type: self.entity_type,
+                    text: found.as_str().to_string(),
+                    score: 1.0,
+                    offset: Offset::new(begin as u32, end as u32),
+                }
+            })
+            .collect()
+    }
+}
+
+fn digits(text: &str) -> Vec<u32> {
+    text.chars().filter_map(|c| c.to_digit(10)).collect()
+}
+
+fn is_valid_phone_number(text: &str) -> bool {
+    (7..=15).contains(&digits(text).len())
+}
+
+fn is_valid_credit_card_number(text: &str) -> bool {
+    let digits = digits(text);
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+    // Luhn checksum: every second digit from the right is doubled
+    let checksum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(position, &digit)| match (position % 2, digit * 2) {
+            (1, doubled) if doubled > 9 => doubled - 9,
+            (1, doubled) => doubled,
+            _ => digit,
+        })
+        .sum();
+    checksum % 10 == 0
+}
+
+fn is_valid_ip_address(text: &str) -> bool {
+    text.split('.')
+        .all(|octet| octet.parse::<u32>().map_or(false, |value| value <= 255))
+}
+
+fn any_text(_text: &str) -> bool {
+    true
+}
+
+/// Sorts entities by position and removes the entities overlapping a longer (or earlier) entity
+fn resolve_overlaps(mut entities: Vec<PiiEntity>) -> Vec<PiiEntity> {
+    entities.sort_by(|a, b| {
+        let length = |entity: &PiiEntity| entity.offset.end - entity.offset.begin;
+        length(b)
+            .cmp(&length(a))
+            .then(a.offset.begin.cmp(&b.offset.begin))
+    });
+    let mut kept: Vec<PiiEntity> = Vec::with_capacity(entities.len());
+    for entity in entities {
+        if kept.iter().all(|kept_entity| {
+            entity.offset.end <= kept_entity.offset.begin
+                || entity.offset.begin >= kept_entity.offset.end
+        }) {
+            kept.push(entity);
+        }
+    }
+    kept.sort_by_key(|entity| entity.offset.begin);
+    kept
+}
+
+/// Replaces the entities of a text according to the anonymization strategy
+fn anonymize_text(text: &str, entities: &[PiiEntity], anonymization: Anonymization) -> String {
+    let mut pseudonyms: HashMap<(PiiEntityType, &str), String> = HashMap::new();
+    let mut type_counts: HashMap<PiiEntityType, usize> = HashMap::new();
+    let spans = entities
+        .iter()
+        .map(|entity| {
+            let replacement = match anonymization {
+                Anonymization::Redact => format!("[{}]", entity.entity_type.tag()),
+                Anonymization::Mask(mask) => mask
+                    .to_string()
+                    .repeat((entity.offset.end - entity.offset.begin) as usize),
+                Anonymization::Pseudonymize => pseudonyms
+                    .entry((entity.entity_type, entity.text.as_str()))
+                    .or_insert_with(|| {
+                        let count = type_counts.entry(entity.entity_type).or_insert(0);
+                        *count += 1;
+                        format!("{}_{}", entity.entity_type.tag(), count)
+                    })
+                    .clone(),
+            };
+            (
+                entity.offset.begin as usize,
+                entity.offset.end as usize,
+                replacement,
+            )
+        })
+        .collect::<Vec<(usize, usize, String)>>();
+    replace_char_spans(text, spans)
+}
+
+/// # PiiModel detecting and anonymizing personally identifiable information
+pub struct PiiModel {
+    ner_model: Option<NERModel>,
+    label_mapping: HashMap<String, PiiEntityType>,
+    min_score: f64,
+    entity_types: Vec<PiiEntityType>,
+    pattern_detectors: Vec<PatternDetector>,
+    anonymization: Anonymization,
+}
+
+impl PiiModel {
+    /// Build a new `PiiModel`
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `PiiConfig` object containing the NER model configuration, the PII types to detect and the anonymization strategy
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::pii::{PiiConfig, PiiModel};
+    ///
+    /// let pii_model = PiiModel::new(PiiConfig::new(Some(Default::default())))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new(config: PiiConfig) -> Result<PiiModel, RustBertError> {
+        if !(0.0..=1.0).contains(&config.min_score) {
+            return Err(RustBertError::InvalidConfigurationError(format!(
+                "min_score must be between 0 and 1, got {}",
+                config.min_score
+            )));
+        }
+        if config.entity_types.is_empty() {
+            return Err(RustBertError::InvalidConfigurationError(
+                "At least one PII type must be detected".to_string(),
+            ));
+        }
+        let pattern_detectors = [
+            (
+                PiiEntityType::Email,
+                EMAIL_PATTERN,
+                any_text as fn(&str) -> bool,
+            ),
+            (
+                PiiEntityType::CreditCardNumber,
+                CREDIT_CARD_NUMBER_PATTERN,
+                is_valid_credit_card_number,
+            ),
+            (
+                PiiEntityType::IpAddress,
+                IP_ADDRESS_PATTERN,
+                is_valid_ip_address,
+            ),
+            (
+                PiiEntityType::PhoneNumber,
+                PHONE_NUMBER_PATTERN,
+                is_valid_phone_number,
+            ),
+        ]
+        .iter()
+        .filter(|(entity_type, _, _)| config.entity_types.contains(entity_type))
+        .map(|(entity_type, pattern, validator)| {
+            Ok(PatternDetector {
+                entity_type: *entity_type,
+                pattern: Regex::new(pattern)
+                    .map_err(|error| RustBertError::InvalidConfigurationError(error.to_string()))?,
+                validator: *validator,
+            })
+        })
+        .collect::<Result<Vec<PatternDetector>, RustBertError>>()?;
+        let ner_model = config.ner_config.map(NERModel::new).transpose()?;
+        Ok(PiiModel {
+            ner_model,
+            label_mapping: config.label_mapping,
+            min_score: config.min_score,
+            entity_types: config.entity_types,
+            pattern_detectors,
+            anonymization: config.anonymization,
+        })
+    }
+
+    /// Detects the PII entities of texts
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - `&[&str]` Array of texts to analyze.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Vec<PiiEntity>>` non-overlapping entities of each text, by position
+    pub fn detect<S>(&self, input: &[S]) -> Vec<Vec<PiiEntity>>
+    where
+        S: AsRef<str>,
+    {
+        let texts = input.iter().map(AsRef::as_ref).collect::<Vec<&str>>();
+        let mut entities = texts
+            .iter()
+            .map(|text| {
+                self.pattern_detectors
+                    .iter()
+                    .flat_map(|detector| detector.detect(text))
+                    .collect::<Vec<PiiEntity>>()
+            })
+            .collect::<Vec<Vec<PiiEntity>>>();
+
+        if let Some(ner_model) = &self.ner_model {
+            for (text_entities, ner_entities) in entities
+                .iter_mut()
+                .zip(ner_model.predict_full_entities(&texts))
+            {
+                text_entities.extend(ner_entities.into_iter().filter_map(|entity| {
+                    let entity_type = *self.label_mapping.get(&entity.label)?;
+                    if entity.score < self.min_score || !self.entity_types.contains(&entity_type) {
+                        return None;
+                    }
+                    Some(PiiEntity {
+                        entity_type,
+                        text: entity.word,
+                        score: entity.score,
+                        offset: entity.offset,
+                    })
+                }));
+            }
+        }
+        entities.into_iter().map(resolve_overlaps).collect()
+    }
+
+    /// Returns anonymized copies of texts, following the anonymization strategy of the configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - `&[&str]` Array of texts to anonymize.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<String>` anonymized texts
+    pub fn anonymize<S>(&self, input: &[S]) -> Vec<String>
+    where
+        S: AsRef<str>,
+    {
+        input
+            .iter()
+            .zip(self.detect(input))
+            .map(|(text, entities)| anonymize_text(text.as_ref(), &entities, self.anonymization))
+            .collect()
+    }
+}
+
+impl OutputFilter for PiiModel {
+    fn filter(&self, outputs: Vec<String>) -> Result<Vec<String>, RustBertError> {
+        Ok(self.anonymize(&outputs))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pattern_validators() {
+        assert!(is_valid_credit_card_number("4111 1111 1111 1111"));
+        assert!(!is_valid_credit_card_number("4111 1111 1111 1112"));
+        assert!(is_valid_ip_address("192.168.0.1"));
+        assert!(!is_valid_ip_address("192.168.0.256"));
+        assert!(is_valid_phone_number("+1 415-555-0132"));
+        assert!(!is_valid_phone_number("12-34"));
+    }
+
+    #[test]
+    fn pattern_detection() -> Result<(), RustBertError> {
+        let pii_model = PiiModel::new(PiiConfig::default())?;
+        let input = [
+            "Reach Zoë at zoe@example.com, +1 415-555-0132 or 192.168.0.1.",
+            "Card 4111-1111-1111-1111 expires in 2025, invoice 1234 5678 9012 3456.",
+        ];
+        let entities = pii_model.detect(&input);
+
+        let types = |entities: &[PiiEntity]| {
+            entities
+                .iter()
+                .map(|entity| (entity.entity_type, entity.text.clone()))
+                .collect::<Vec<(PiiEntityType, String)>>()
+        };
+        assert_eq!(
+            types(&entities[0]),
+            [
+                (PiiEntityType::Email, "zoe@example.com".to_string()),
+                (PiiEntityType::PhoneNumber, "+1 415-555-0132".to_string()),
+                (PiiEntityType::IpAddress, "192.168.0.1".to_string()),
+            ]
+        );
+        assert_eq!(entities[0][0].offset, Offset::new(13, 28));
+        assert_eq!(
+            types(&entities[1]),
+            [(
+                PiiEntityType::CreditCardNumber,
+                "4111-1111-1111-1111".to_string()
+            )]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn anonymization() {
+        let text = "Amy met Bob. Amy left.";
+        let entity = |text: &str, begin: u32| PiiEntity {
+            entity_type: PiiEntityType::Person,
+            text: text.to_string(),
+            score: 0.9,
+            offset: Offset::new(begin, begin + text.chars().count() as u32),
+        };
+        let entities = [entity("Amy", 0), entity("Bob", 8), entity("Amy", 13)];
+        assert_eq!(
+            anonymize_text(text, &entities, Anonymization::Redact),
+            "[PERSON] met [PERSON]. [PERSON] left."
+        );
+        assert_eq!(
+            anonymize_text(text, &entities, Anonymization::Mask('*')),
+            "*** met ***. *** left."
+        );
+        assert_eq!(
+            anonymize_text(text, &entities, Anonymization::Pseudonymize),
+            "PERSON_1 met PERSON_2. PERSON_1 left."
+        );
+    }
+
+    #[test]
+    fn overlap_resolution() {
+        let entity = |entity_type: PiiEntityType, begin: u32, end: u32| PiiEntity {
+            entity_type,
+            text: String::new(),
+            score: 1.0,
+            offset: Offset::new(begin, end),
+        };
+        let entities = resolve_overlaps(vec![
+            entity(PiiEntityType::Address, 10, 30),
+            entity(PiiEntityType::Person, 0, 5),
+            entity(PiiEntityType::Person, 12, 18),
+            entity(PiiEntityType::Email, 30, 40),
+        ]);
+        assert_eq!(
+            entities
+                .iter()
+                .map(|entity| entity.entity_type)
+                .collect::<Vec<PiiEntityType>>(),
+            [
+                PiiEntityType::Person,
+                PiiEntityType::Address,
+                PiiEntityType::Email
+            ]
+        );
+    }
+}
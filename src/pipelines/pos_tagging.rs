@@ -109,6 +109,8 @@ pub struct POSTag {
 }
 
 //type alias for some backward compatibility
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct POSConfig {
     token_classification_config: TokenClassificationConfig,
 }
@@ -136,6 +138,7 @@ impl Default for POSConfig {
                 device: Device::cuda_if_available(),
                 label_aggregation_function: LabelAggregationOption::First,
                 batch_size: 64,
+                window_overlap: None,
             },
         }
     }
@@ -147,6 +150,14 @@ impl From<POSConfig> for TokenClassificationConfig {
     }
 }
 
+impl From<TokenClassificationConfig> for POSConfig {
+    fn from(token_classification_config: TokenClassificationConfig) -> Self {
+        POSConfig {
+            token_classification_config,
+        }
+    }
+}
+
 /// # POSModel to extract Part of Speech tags
 pub struct POSModel {
     token_classification_model: TokenClassificationModel,
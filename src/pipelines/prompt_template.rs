@@ -0,0 +1,512 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Prompt templates for generation pipelines
+//! Utilities to build the prompts passed to the text generation and conversation pipelines:
+//! - `PromptTemplate`: template with named placeholders (e.g. `Translate to French: {text}`), literal braces being escaped by doubling them (`{{` and `}}`)
+//! - `FewShotPromptTemplate`: prompt made of an optional prefix, a list of examples rendered with an example template and a suffix containing the actual query
+//! - `ChatFormat`: formats a list of `ChatMessage` following the chat format expected by a model (e.g. DialoGPT turns separated by end of sequence tokens, ChatML or Llama 2)
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::prompt_template::{FewShotPromptTemplate, PromptTemplate};
+//! use rust_bert::pipelines::text_generation::TextGenerationModel;
+//!
+//! let template = FewShotPromptTemplate::new(
+//!     PromptTemplate::new("Review: {review}\nSentiment: {sentiment}")?,
+//!     PromptTemplate::new("Review: {review}\nSentiment:")?,
+//! )
+//! .with_prefix(PromptTemplate::new("Classify the sentiment of movie reviews.")?)
+//! .with_example(&[("review", "A masterpiece."), ("sentiment", "positive")])
+//! .with_example(&[("review", "Two hours I will never get back."), ("sentiment", "negative")]);
+//!
+//! let prompt = template.render(&[("review", "The actors were brilliant.")])?;
+//! let model = TextGenerationModel::new(Default::default())?;
+//! let output = model.generate(&[prompt.as_str()], None)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::RustBertError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TemplatePart {
+    Text(String),
+    Placeholder(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// # Template with named placeholders
+/// Placeholders are identified by their name between braces (e.g. `{context}`) and can be used
+/// multiple times in a template.
+pub struct PromptTemplate {
+    parts: Vec<TemplatePart>,
+}
+
+impl PromptTemplate {
+    /// Parses a new `PromptTemplate`, returning an error if a placeholder is not closed or empty,
+    /// or if a closing brace is not escaped.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - template text, with placeholders between braces
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::prompt_template::PromptTemplate;
+    ///
+    /// let template = PromptTemplate::new("Question: {question}\nAnswer:")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new(template: &str) -> Result<PromptTemplate, RustBertError> {
+        let mut parts = vec![];
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(character) = chars.next() {
+            match character {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(character) => name.push(character),
+                            None => {
+                                return Err(RustBertError::ValueError(format!(
+                                    "Placeholder `{{{}` is not closed in template",
+                                    name
+                                )));
+                            }
+                        }
+                    }
+                    let name = name.trim();
+                    if name.is_empty() || name.contains('{') {
+                        return Err(RustBertError::ValueError(format!(
+                            "Invalid placeholder `{{{}}}` in template",
+                            name
+                        )));
+                    }
+                    if !text.is_empty() {
+                        parts.push(TemplatePart::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(TemplatePart::Placeholder(name.to_string()));
+                }
+                '}' => {
+                    return Err(RustBertError::ValueError(
+                        "Unmatched `}` in template, literal braces should be escaped as `}}`"
+                            .to_string(),
+                    ));
+                }
+                _ => text.push(character),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(TemplatePart::Text(text));
+        }
+        Ok(PromptTemplate { parts })
+    }
+
+    /// Returns the names of the placeholders of the template (in order of first appearance)
+    pub fn placeholders(&self) -> Vec<&str> {
+        let mut placeholders: Vec<&str> = vec![];
+        for part in self.parts.iter() {
+            if let TemplatePart::Placeholder(name) = part {
+                if !placeholders.contains(&name.as_str()) {
+                    placeholders.push(name);
+                }
+            }
+        }
+        placeholders
+    }
+
+    /// Renders the template, replacing the placeholders by their value. Returns an error if a value
+    /// is missing for a placeholder (values not used by the template are ignored).
+    ///
+    /// # Arguments
+    ///
+    /// * `values` - pairs of placeholder names and values
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::prompt_template::PromptTemplate;
+    ///
+    /// let template = PromptTemplate::new("Question: {question}\nAnswer:")?;
+    /// let prompt = template.render(&[("question", "Where does Amy live?")])?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn render<K, V>(&self, values: &[(K, V)]) -> Result<String, RustBertError>
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let values = values
+            .iter()
+            .map(|(name, value)| (name.as_ref(), value.as_ref()))
+            .collect::<HashMap<&str, &str>>();
+        let mut output = String::new();
+        for part in self.parts.iter() {
+            match part {
+                TemplatePart::Text(text) => output.push_str(text),
+                TemplatePart::Placeholder(name) => {
+                    output.push_str(values.get(name.as_str()).ok_or_else(|| {
+                        RustBertError::ValueError(format!(
+                            "No value provided for placeholder `{}`",
+                            name
+                        ))
+                    })?)
+                }
+            }
+        }
+        Ok(output)
+    }
+}
+
+#[derive(Debug, Clone)]
+/// # Few-shot prompt template
+/// Prompt made of an optional prefix (e.g. task instructions), examples rendered with the example
+/// template and a suffix (typically the query, following the format of the examples). The parts
+/// of the prompt are separated by `example_separator` (defaults to an empty line).
+pub struct FewShotPromptTemplate {
+    /// Optional prefix of the prompt
+    pub prefix: Option<PromptTemplate>,
+    /// Template used to render each example
+    pub example_template: PromptTemplate,
+    /// Values of the example template placeholders for each example
+    pub examples: Vec<Vec<(String, String)>>,
+    /// Suffix of the prompt
+    pub suffix: PromptTemplate,
+    /// Separator between the prefix, examples and suffix
+    pub example_separator: String,
+}
+
+impl FewShotPromptTemplate {
+    /// Creates a new `FewShotPromptTemplate` without prefix and examples
+    ///
+    /// # Arguments
+    ///
+    /// * `example_template` - `PromptTemplate` used to render each example
+    /// * `suffix` - `PromptTemplate` rendered after the examples
+    pub fn new(example_template: PromptTemplate, suffix: PromptTemplate) -> FewShotPromptTemplate {
+        FewShotPromptTemplate {
+            prefix: None,
+            example_template,
+            examples: vec![],
+            suffix,
+            example_separator: "\n\n".to_string(),
+        }
+    }
+
+    /// Sets the prefix of the prompt
+    pub fn with_prefix(mut self, prefix: PromptTemplate) -> Self {
+        self.prefix = Some(prefix);
+        self
+    }
+
+    /// Adds an example, given as pairs of example template placeholder names and values
+    pub fn with_example<K, V>(mut self, values: &[(K, V)]) -> Self
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        self.examples.push(
+            values
+                .iter()
+                .map(|(name, value)| (name.as_ref().to_string(), value.as_ref().to_string()))
+                .collect(),
+        );
+        self
+    }
+
+    /// Sets the separator between the prefix, examples and suffix
+    pub fn with_separator(mut self, separator: &str) -> Self {
+        self.example_separator = separator.to_string();
+        self
+    }
+
+    /// Renders the prompt. The values provided are used for the prefix and suffix placeholders.
+    ///
+    /// # Arguments
+    ///
+    /// * `values` - pairs of placeholder names and values
+    pub fn render<K, V>(&self, values: &[(K, V)]) -> Result<String, RustBertError>
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let mut parts = Vec::with_capacity(self.examples.len() + 2);
+        if let Some(prefix) = &self.prefix {
+            parts.push(prefix.render(values)?);
+        }
+        for example in self.examples.iter() {
+            parts.push(self.example_template.render(example)?);
+        }
+        parts.push(self.suffix.render(values)?);
+        Ok(parts.join(&self.example_separator))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+/// # Author of a chat message
+pub enum ChatRole {
+    System,
+    User,
+    Assistant,
+    /// Result of a tool call, returned to the model
+    Tool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+/// # Chat message
+pub struct ChatMessage {
+    /// Author of the message
+    pub role: ChatRole,
+    /// Content of the message
+    pub content: String,
+}
+
+impl ChatMessage {
+    /// Creates a new `ChatMessage`
+    pub fn new(role: ChatRole, content: impl Into<String>) -> ChatMessage {
+        ChatMessage {
+            role,
+            content: content.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+/// # Chat format expected by a model
+pub enum ChatFormat {
+    /// DialoGPT format: turns separated by the `<|endoftext|>` token (the system prompt is used as
+    /// first turn)
+    DialoGPT,
+    /// ChatML format: `<|im_start|>{role}\n{content}<|im_end|>` for each message
+    ChatML,
+    /// Llama 2 chat format: `[INST]` blocks, with the system prompt in a `<<SYS>>` block of the
+    /// first instruction
+    Llama2,
+    /// Plain text format: `{Role}: {content}` lines
+    Plain,
+    /// Custom format, each message being rendered with the template of its role (with a `content`
+    /// placeholder, tool results using the user template). The generation prompt is appended after
+    /// the messages.
+    Custom {
+        system_template: String,
+        user_template: String,
+        assistant_template: String,
+        generation_prompt: String,
+    },
+}
+
+impl ChatFormat {
+    /// Formats chat messages into a prompt
+    ///
+    /// # Arguments
+    ///
+    /// * `messages` - `ChatMessage`s to format, in chronological order
+    /// * `add_generation_prompt` - if `true`, appends the prefix of an assistant message so that the model generates the next answer
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::prompt_template::{ChatFormat, ChatMessage, ChatRole};
+    ///
+    /// let messages = [
+    ///     ChatMessage::new(ChatRole::System, "You are a helpful assistant."),
+    ///     ChatMessage::new(ChatRole::User, "What is the capital of France?"),
+    /// ];
+    /// let prompt = ChatFormat::ChatML.render(&messages, true)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn render(
+        &self,
+        messages: &[ChatMessage],
+        add_generation_prompt: bool,
+    ) -> Result<String, RustBertError> {
+        let mut output = String::new();
+        match self {
+            ChatFormat::DialoGPT => {
+                for message in messages {
+                    output.push_str(&message.content);
+                    output.push_str("<|endoftext|>");
+                }
+            }
+            ChatFormat::ChatML => {
+                for message in messages {
+                    output.push_str(&format!(
+                        "<|im_start|>{}\n{}<|im_end|>\n",
+                        role_name(message.role),
+                        message.content
+                    ));
+                }
+                if add_generation_prompt {
+                    output.push_str("<|im_start|>assistant\n");
+                }
+            }
+            ChatFormat::Llama2 => {
+                let mut system_prompt = None;
+                for message in messages {
+                    match message.role {
+                        ChatRole::System => system_prompt = Some(message.content.as_str()),
+                        ChatRole::User | ChatRole::Tool => {
+                            output.push_str("<s>[INST] ");
+                            if let Some(system_prompt) = system_prompt.take() {
+                                output
+                                    .push_str(&format!("<<SYS>>\n{}\n<</SYS>>\n\n", system_prompt));
+                            }
+                            output.push_str(&format!("{} [/INST]", message.content.trim()));
+                        }
+                        ChatRole::Assistant => {
+                            output.push_str(&format!(" {} </s>", message.content.trim()))
+                        }
+                    }
+                }
+            }
+            ChatFormat::Plain => {
+                for message in messages {
+                    output.push_str(&format!(
+                        "{}: {}\n",
+                        capitalize(role_name(message.role)),
+                        message.content
+                    ));
+                }
+                if add_generation_prompt {
+                    output.push_str("Assistant:");
+                }
+            }
+            ChatFormat::Custom {
+                system_template,
+                user_template,
+                assistant_template,
+                generation_prompt,
+            } => {
+                let system_template = PromptTemplate::new(system_template)?;
+                let user_template = PromptTemplate::new(user_template)?;
+                let assistant_template = PromptTemplate::new(assistant_template)?;
+                for message in messages {
+                    let template = match message.role {
+                        ChatRole::System => &system_template,
+                        ChatRole::User | ChatRole::Tool => &user_template,
+                        ChatRole::Assistant => &assistant_template,
+                    };
+                    output.push_str(&template.render(&[("content", message.content.as_str())])?);
+                }
+                if add_generation_prompt {
+                    output.push_str(generation_prompt);
+                }
+            }
+        }
+        Ok(output)
+    }
+}
+
+fn role_name(role: ChatRole) -> &'static str {
+    match role {
+        ChatRole::System => "system",
+        ChatRole::User => "user",
+        ChatRole::Assistant => "assistant",
+        ChatRole::Tool => "tool",
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn prompt_template_render() -> Result<(), RustBertError> {
+        let template = PromptTemplate::new("{{literal}} {name}: {value} ({name})")?;
+        assert_eq!(template.placeholders(), ["name", "value"]);
+        assert_eq!(
+            template.render(&[("name", "a"), ("value", "1"), ("unused", "x")])?,
+            "{literal} a: 1 (a)"
+        );
+        assert!(template.render(&[("name", "a")]).is_err());
+        assert!(PromptTemplate::new("{name").is_err());
+        assert!(PromptTemplate::new("name}").is_err());
+        assert!(PromptTemplate::new("{}").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn few_shot_prompt_template_render() -> Result<(), RustBertError> {
+        let template = FewShotPromptTemplate::new(
+            PromptTemplate::new("Q: {question}\nA: {answer}")?,
+            PromptTemplate::new("Q: {question}\nA:")?,
+        )
+        .with_prefix(PromptTemplate::new("Answer the {topic} questions.")?)
+        .with_example(&[("question", "1+1?"), ("answer", "2")])
+        .with_separator("\n");
+        assert_eq!(
+            template.render(&[("topic", "math"), ("question", "2+2?")])?,
+            "Answer the math questions.\nQ: 1+1?\nA: 2\nQ: 2+2?\nA:"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn chat_format_render() -> Result<(), RustBertError> {
+        let messages = [
+            ChatMessage::new(ChatRole::System, "Be brief."),
+            ChatMessage::new(ChatRole::User, "Hi"),
+            ChatMessage::new(ChatRole::Assistant, "Hello"),
+            ChatMessage::new(ChatRole::User, "Bye"),
+        ];
+        assert_eq!(
+            ChatFormat::DialoGPT.render(&messages, true)?,
+            "Be brief.<|endoftext|>Hi<|endoftext|>Hello<|endoftext|>Bye<|endoftext|>"
+        );
+        assert_eq!(
+            ChatFormat::ChatML.render(&messages[1..2], true)?,
+            "<|im_start|>user\nHi<|im_end|>\n<|im_start|>assistant\n"
+        );
+        assert_eq!(
+            ChatFormat::Llama2.render(&messages, true)?,
+            "<s>[INST] <<SYS>>\nBe brief.\n<</SYS>>\n\nHi [/INST] Hello </s><s>[INST] Bye [/INST]"
+        );
+        assert_eq!(
+            ChatFormat::Plain.render(&messages[1..], true)?,
+            "User: Hi\nAssistant: Hello\nUser: Bye\nAssistant:"
+        );
+        let tool_message = [ChatMessage::new(ChatRole::Tool, "{\"temperature\": 21}")];
+        assert_eq!(
+            ChatFormat::ChatML.render(&tool_message, false)?,
+            "<|im_start|>tool\n{\"temperature\": 21}<|im_end|>\n"
+        );
+        Ok(())
+    }
+}
@@ -46,11 +46,13 @@
 use crate::albert::AlbertForQuestionAnswering;
 use crate::bert::BertForQuestionAnswering;
 use crate::common::error::RustBertError;
+use crate::common::trace::trace_span;
 use crate::deberta::DebertaForQuestionAnswering;
 use crate::distilbert::DistilBertForQuestionAnswering;
 use crate::fnet::FNetForQuestionAnswering;
 use crate::longformer::LongformerForQuestionAnswering;
 use crate::mobilebert::MobileBertForQuestionAnswering;
+use crate::pipelines::added_tokens::add_tokens_and_resize_embeddings;
 use crate::pipelines::common::{ConfigOption, ModelType, TokenizerOption};
 use crate::reformer::ReformerForQuestionAnswering;
 use crate::resources::ResourceProvider;
@@ -124,18 +126,27 @@ fn remove_duplicates<T: PartialEq + Clone>(vector: &mut Vec<T>) -> &mut Vec<T> {
     vector
 }
 
+#[derive(Serialize, Deserialize)]
 /// # Configuration for question answering
 /// Contains information regarding the model to load and device to place the model on.
 pub struct QuestionAnsweringConfig {
     /// Model weights resource (default: pretrained DistilBERT model on SQuAD)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub model_resource: Box<dyn ResourceProvider + Send>,
     /// Config resource (default: pretrained DistilBERT model on SQuAD)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub config_resource: Box<dyn ResourceProvider + Send>,
     /// Vocab resource (default: pretrained DistilBERT model on SQuAD)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub vocab_resource: Box<dyn ResourceProvider + Send>,
     /// Merges resource (default: None)
+    #[serde(default, with = "crate::common::serde_utils::optional_resource")]
     pub merges_resource: Option<Box<dyn ResourceProvider + Send>>,
     /// Device to place the model on (default: CUDA/GPU when available)
+    #[serde(
+        with = "crate::common::serde_utils::device",
+        default = "crate::common::serde_utils::device::default"
+    )]
     pub device: Device,
     /// Model type
     pub model_type: ModelType,
@@ -488,6 +499,12 @@ impl QuestionAnsweringOption {
         input_embeds: Option<&Tensor>,
         train: bool,
     ) -> (Tensor, Tensor) {
+        let _span = trace_span!(
+            DEBUG,
+            "forward",
+            input_shape = ?input_ids.map(Tensor::size),
+            device = ?input_ids.map(Tensor::device)
+        );
         match *self {
             Self::Bert(ref model) => {
                 let outputs = model.forward_t(input_ids, mask, None, None, input_embeds, train);
@@ -585,16 +602,13 @@ impl QuestionAnsweringModel {
     pub fn new(
         question_answering_config: QuestionAnsweringConfig,
     ) -> Result<QuestionAnsweringModel, RustBertError> {
-        let config_path = question_answering_config.config_resource.get_local_path()?;
         let vocab_path = question_answering_config.vocab_resource.get_local_path()?;
-        let weights_path = question_answering_config.model_resource.get_local_path()?;
         let merges_path = if let Some(merges_resource) = &question_answering_config.merges_resource
         {
             Some(merges_resource.get_local_path()?)
         } else {
             None
         };
-        let device = question_answering_config.device;
 
         let tokenizer = TokenizerOption::from_file(
             question_answering_config.model_type,
@@ -604,6 +618,31 @@ impl QuestionAnsweringModel {
             question_answering_config.strip_accents,
             question_answering_config.add_prefix_space,
         )?;
+        Self::new_with_tokenizer(question_answering_config, tokenizer)
+    }
+
+    /// Build a new `QuestionAnsweringModel` with a provided tokenizer (e.g. a HuggingFace tokenizer loaded
+    /// from a `tokenizer.json` file). The tokenizer resources of the configuration are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `question_answering_config` - `QuestionAnsweringConfig` object containing the resource references (model, vocabulary, configuration) and device placement (CPU/GPU)
+    /// * `tokenizer` - `TokenizerOption` tokenizer to use for question answering
+    pub fn new_with_tokenizer(
+        question_answering_config: QuestionAnsweringConfig,
+        tokenizer: TokenizerOption,
+    ) -> Result<QuestionAnsweringModel, RustBertError> {
+        let _span = trace_span!(
+            INFO,
+            "load_model",
+            pipeline = "question_answering",
+            model_type = ?question_answering_config.model_type,
+            device = ?question_answering_config.device
+        );
+        let config_path = question_answering_config.config_resource.get_local_path()?;
+        let weights_path = question_answering_config.model_resource.get_local_path()?;
+        let device = question_answering_config.device;
+
         let pad_idx = tokenizer
             .get_pad_id()
             .expect("The Tokenizer used for Question Answering should contain a PAD id");
@@ -653,6 +692,27 @@ impl QuestionAnsweringModel {
         })
     }
 
+    /// Registers additional tokens (e.g. special markers or domain-specific terms) on the tokenizer
+    /// of the model. The added tokens are never split by the tokenizer, and the token embeddings of
+    /// the model are resized, the new embeddings being initialized with the mean embedding of the
+    /// existing vocabulary.
+    ///
+    /// # Arguments
+    ///
+    /// * `tokens` - tokens to add (tokens already part of the vocabulary are ignored)
+    pub fn with_added_tokens<S: AsRef<str>>(
+        mut self,
+        tokens: &[S],
+    ) -> Result<QuestionAnsweringModel, RustBertError> {
+        self.tokenizer = add_tokens_and_resize_embeddings(self.tokenizer, &self.var_store, tokens)?;
+        Ok(self)
+    }
+
+    /// Returns the tokenizer of the model (e.g. to split long contexts with a `TextSplitter`)
+    pub fn get_tokenizer(&self) -> &TokenizerOption {
+        &self.tokenizer
+    }
+
     /// Perform extractive question answering given a list of `QaInputs`
     ///
     /// # Arguments
@@ -696,15 +756,41 @@ impl QuestionAnsweringModel {
         top_k: i64,
         batch_size: usize,
     ) -> Vec<Vec<Answer>> {
-        let mut features: Vec<QaFeature> = qa_inputs
+        // Identical (question, context) pairs are processed once, and questions or contexts shared
+        // by several inputs (e.g. many questions over a single context) are only tokenized once.
+        let mut unique_inputs: Vec<&QaInput> = vec![];
+        let mut input_to_unique_index: Vec<usize> = Vec::with_capacity(qa_inputs.len());
+        let mut unique_input_indices: HashMap<(&str, &str), usize> = HashMap::new();
+        for qa_input in qa_inputs {
+            let unique_index = *unique_input_indices
+                .entry((qa_input.question.as_str(), qa_input.context.as_str()))
+                .or_insert_with(|| {
+                    unique_inputs.push(qa_input);
+                    unique_inputs.len() - 1
+                });
+            input_to_unique_index.push(unique_index);
+        }
+
+        let mut encoded_queries: HashMap<&str, TokenIdsWithOffsets> = HashMap::new();
+        let mut encoded_contexts: HashMap<&str, TokenIdsWithOffsets> = HashMap::new();
+        for qa_input in unique_inputs.iter() {
+            encoded_queries
+                .entry(qa_input.question.as_str())
+                .or_insert_with(|| self.encode_query(&qa_input.question, self.max_query_length));
+            encoded_contexts
+                .entry(qa_input.context.as_str())
+                .or_insert_with(|| self.encode_context(&qa_input.context));
+        }
+
+        let mut features: Vec<QaFeature> = unique_inputs
             .iter()
             .enumerate()
             .flat_map(|(example_index, qa_example)| {
                 self.generate_features(
-                    qa_example,
+                    &encoded_queries[qa_example.question.as_str()],
+                    &encoded_contexts[qa_example.context.as_str()],
                     self.max_seq_len,
                     self.doc_stride,
-                    self.max_query_length,
                     example_index as i64,
                 )
             })
@@ -738,7 +824,7 @@ impl QuestionAnsweringModel {
 
                 for (example_id, max_feature_id) in example_index_to_feature_end_position {
                     let mut answers: Vec<Answer> = vec![];
-                    let example = &qa_inputs[example_id];
+                    let example = unique_inputs[example_id];
                     for feature_idx in feature_id_start..max_feature_id {
                         let feature = &batch_features[feature_idx as usize];
                         let p_mask = (Tensor::of_slice(&feature.p_mask) - 1)
@@ -785,16 +871,21 @@ impl QuestionAnsweringModel {
             });
             start = end;
         }
-        let mut all_answers = vec![];
-        for example_id in 0..qa_inputs.len() {
-            if let Some(answers) = example_top_k_answers_map.get_mut(&example_id) {
-                remove_duplicates(answers).sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
-                all_answers.push(answers[..min(answers.len(), top_k as usize)].to_vec());
-            } else {
-                all_answers.push(vec![]);
-            }
-        }
-        all_answers
+        let unique_answers = (0..unique_inputs.len())
+            .map(|example_id| {
+                if let Some(answers) = example_top_k_answers_map.get_mut(&example_id) {
+                    remove_duplicates(answers)
+                        .sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+                    answers[..min(answers.len(), top_k as usize)].to_vec()
+                } else {
+                    vec![]
+                }
+            })
+            .collect::<Vec<Vec<Answer>>>();
+        input_to_unique_index
+            .into_iter()
+            .map(|unique_index| unique_answers[unique_index].clone())
+            .collect()
     }
 
     fn decode(&self, start: &Tensor, end: &Tensor, top_k: i64) -> (Vec<i64>, Vec<i64>, Vec<f64>) {
@@ -824,26 +915,40 @@ impl QuestionAnsweringModel {
         (start, end, scores)
     }
 
-    fn generate_features(
-        &self,
-        qa_example: &QaInput,
-        max_seq_length: usize,
-        doc_stride: usize,
-        max_query_length: usize,
-        example_index: i64,
-    ) -> Vec<QaFeature> {
-        let mut encoded_query = self.tokenizer.tokenize_with_offsets(&qa_example.question);
+    fn encode_query(&self, question: &str, max_query_length: usize) -> TokenIdsWithOffsets {
+        let mut encoded_query = self.tokenizer.tokenize_with_offsets(question);
         encoded_query.tokens.truncate(max_query_length);
         encoded_query.offsets.truncate(max_query_length);
         encoded_query.reference_offsets.truncate(max_query_length);
         encoded_query.masks.truncate(max_query_length);
-        let encoded_query = TokenIdsWithOffsets {
+        TokenIdsWithOffsets {
             ids: self.tokenizer.convert_tokens_to_ids(&encoded_query.tokens),
             offsets: encoded_query.offsets,
             reference_offsets: encoded_query.reference_offsets,
             masks: encoded_query.masks,
-        };
+        }
+    }
+
+    fn encode_context(&self, context: &str) -> TokenIdsWithOffsets {
+        let tokenized_context = self.tokenizer.tokenize_with_offsets(context);
+        TokenIdsWithOffsets {
+            ids: self
+                .tokenizer
+                .convert_tokens_to_ids(&tokenized_context.tokens),
+            offsets: tokenized_context.offsets,
+            reference_offsets: tokenized_context.reference_offsets,
+            masks: tokenized_context.masks,
+        }
+    }
 
+    fn generate_features(
+        &self,
+        encoded_query: &TokenIdsWithOffsets,
+        encoded_context: &TokenIdsWithOffsets,
+        max_seq_length: usize,
+        doc_stride: usize,
+        example_index: i64,
+    ) -> Vec<QaFeature> {
         let sequence_pair_added_tokens = self
             .tokenizer
             .build_input_with_special_tokens(
@@ -865,15 +970,6 @@ impl QuestionAnsweringModel {
 
         let mut spans: Vec<QaFeature> = vec![];
 
-        let tokenized_context = self.tokenizer.tokenize_with_offsets(&qa_example.context);
-        let encoded_context = TokenIdsWithOffsets {
-            ids: self
-                .tokenizer
-                .convert_tokens_to_ids(&tokenized_context.tokens),
-            offsets: tokenized_context.offsets,
-            reference_offsets: tokenized_context.reference_offsets,
-            masks: tokenized_context.masks,
-        };
         let max_context_length =
             max_seq_length - sequence_pair_added_tokens - encoded_query.ids.len();
 
@@ -0,0 +1,1003 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Task-based pipeline factory
+//! Creates the pipeline matching a task and a model specification at runtime, allowing applications
+//! to select the task and model from configuration files rather than from their source code.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::distilbert::{
+//!     DistilBertConfigResources, DistilBertModelResources, DistilBertVocabResources,
+//! };
+//! use rust_bert::pipelines::common::ModelType;
+//! use rust_bert::pipelines::registry::{ModelSpec, Pipeline, TaskType};
+//! use rust_bert::resources::RemoteResource;
+//!
+//! let mut model_spec = ModelSpec::new(
+//!     ModelType::DistilBert,
+//!     RemoteResource::from_pretrained(DistilBertModelResources::DISTIL_BERT_SST2),
+//!     RemoteResource::from_pretrained(DistilBertConfigResources::DISTIL_BERT_SST2),
+//!     RemoteResource::from_pretrained(DistilBertVocabResources::DISTIL_BERT_SST2),
+//!     None,
+//! );
+//! model_spec.lower_case = true;
+//!
+//! let pipeline = Pipeline::new(TaskType::Sentiment, model_spec)?;
+//! if let Pipeline::Sentiment(model) = &pipeline {
+//!     let output = model.predict(&["This is a great movie!"]);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Pipelines can also be described declaratively in a single JSON (or YAML, with the `yaml` feature)
+//! file containing the task, model resources, device, precision and task-specific parameters (see
+//! `PipelineConfig`), and loaded with `Pipeline::from_config_file("pipeline.json")`, allowing
+//! deployments to change models without recompiling the application.
+//!
+//! The model type does not need to be known in advance: `ModelSpec::auto` reads it from the
+//! `model_type` (or `architectures`) field of the model configuration, and
+//! `Pipeline::from_local_dir` loads any supported checkpoint from a directory, identifying its task
+//! from the model architecture when none is given.
+//!
+//! Translation, sentence embeddings and keywords extraction are not part of the registry as their
+//! models are created from dedicated builders (`TranslationModelBuilder`, `SentenceEmbeddingsBuilder`).
+
+use crate::common::error::RustBertError;
+use crate::pipelines::common::ModelType;
+use crate::pipelines::conversation::{ConversationConfig, ConversationManager, ConversationModel};
+use crate::pipelines::masked_language::{MaskedLanguageConfig, MaskedLanguageModel};
+use crate::pipelines::ner::NERModel;
+use crate::pipelines::pos_tagging::POSModel;
+use crate::pipelines::question_answering::{
+    QaInput, QuestionAnsweringConfig, QuestionAnsweringModel,
+};
+use crate::pipelines::sentiment::SentimentModel;
+use crate::pipelines::sequence_classification::{
+    SequenceClassificationConfig, SequenceClassificationModel,
+};
+use crate::pipelines::summarization::{SummarizationConfig, SummarizationModel};
+use crate::pipelines::text_generation::{TextGenerationConfig, TextGenerationModel};
+use crate::pipelines::token_classification::{
+    LabelAggregationOption, TokenClassificationConfig, TokenClassificationModel,
+};
+use crate::pipelines::zero_shot_classification::{
+    ZeroShotClassificationConfig, ZeroShotClassificationModel,
+};
+use crate::resources::{LocalResource, ResourceProvider};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+use tch::Device;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+/// # Identifies the task performed by a pipeline
+pub enum TaskType {
+    SequenceClassification,
+    Sentiment,
+    TokenClassification,
+    #[serde(alias = "ner")]
+    NER,
+    POSTagging,
+    QuestionAnswering,
+    ZeroShotClassification,
+    Summarization,
+    TextGeneration,
+    Conversation,
+    MaskedLanguage,
+}
+
+#[derive(Serialize, Deserialize)]
+/// # Model specification used to create a pipeline from the registry
+/// Contains the resources and tokenizer settings shared by all pipelines. Task-specific settings
+/// (e.g. generation parameters) are set to the defaults of the corresponding pipeline configuration.
+pub struct ModelSpec {
+    /// Model type
+    pub model_type: ModelType,
+    /// Model weights resource
+    #[serde(with = "crate::common::serde_utils::resource")]
+    pub model_resource: Box<dyn ResourceProvider + Send>,
+    /// Config resource
+    #[serde(with = "crate::common::serde_utils::resource")]
+    pub config_resource: Box<dyn ResourceProvider + Send>,
+    /// Vocab resource
+    #[serde(with = "crate::common::serde_utils::resource")]
+    pub vocab_resource: Box<dyn ResourceProvider + Send>,
+    /// Merges resource (default: None)
+    #[serde(default, with = "crate::common::serde_utils::optional_resource")]
+    pub merges_resource: Option<Box<dyn ResourceProvider + Send>>,
+    /// Automatically lower case all input upon tokenization (assumes a lower-cased model, default: false)
+    #[serde(default)]
+    pub lower_case: bool,
+    /// Flag indicating if the tokenizer should strip accents (normalization). Only used for BERT / ALBERT models
+    pub strip_accents: Option<bool>,
+    /// Flag indicating if the tokenizer should add a white space before each tokenized input (needed for some Roberta models)
+    pub add_prefix_space: Option<bool>,
+    /// Device to place the model on (default: CUDA/GPU when available)
+    #[serde(
+        with = "crate::common::serde_utils::device",
+        default = "crate::common::serde_utils::device::default"
+    )]
+    pub device: Device,
+}
+
+impl ModelSpec {
+    /// Instantiate a new model specification of the supplied type.
+    ///
+    /// # Arguments
+    ///
+    /// * `model_type` - `ModelType` indicating the model type to load (must match with the actual data to be loaded!)
+    /// * model_resource - The `ResourceProvider` pointing to the model to load (e.g.  model.ot)
+    /// * config_resource - The `ResourceProvider` pointing to the model configuration to load (e.g. config.json)
+    /// * vocab_resource - The `ResourceProvider` pointing to the tokenizer's vocabulary to load (e.g.  vocab.txt/vocab.json)
+    /// * merges_resource - An optional `ResourceProvider` pointing to the tokenizer's merge file or SentencePiece model to load (e.g.  merges.txt).
+    pub fn new<RM, RC, RV>(
+        model_type: ModelType,
+        model_resource: RM,
+        config_resource: RC,
+        vocab_resource: RV,
+        merges_resource: Option<RV>,
+    ) -> ModelSpec
+    where
+        RM: ResourceProvider + Send + 'static,
+        RC: ResourceProvider + Send + 'static,
+        RV: ResourceProvider + Send + 'static,
+    {
+        ModelSpec {
+            model_type,
+            model_resource: Box::new(model_resource),
+            config_resource: Box::new(config_resource),
+            vocab_resource: Box::new(vocab_resource),
+            merges_resource: merges_resource.map(|r| Box::new(r) as Box<_>),
+            lower_case: false,
+            strip_accents: None,
+            add_prefix_space: None,
+            device: Device::cuda_if_available(),
+        }
+    }
+
+    /// Instantiate a new model specification, identifying the model type from the `model_type`
+    /// (or `architectures`) field of the model configuration file (see `detect_model_type`).
+    ///
+    /// # Arguments
+    ///
+    /// * model_resource - The `ResourceProvider` pointing to the model to load (e.g.  model.ot)
+    /// * config_resource - The `ResourceProvider` pointing to the model configuration to load (e.g. config.json)
+    /// * vocab_resource - The `ResourceProvider` pointing to the tokenizer's vocabulary to load (e.g.  vocab.txt/vocab.json)
+    /// * merges_resource - An optional `ResourceProvider` pointing to the tokenizer's merge file or SentencePiece model to load (e.g.  merges.txt).
+    pub fn auto<RM, RC, RV>(
+        model_resource: RM,
+        config_resource: RC,
+        vocab_resource: RV,
+        merges_resource: Option<RV>,
+    ) -> Result<ModelSpec, RustBertError>
+    where
+        RM: ResourceProvider + Send + 'static,
+        RC: ResourceProvider + Send + 'static,
+        RV: ResourceProvider + Send + 'static,
+    {
+        let config = read_json(config_resource.get_local_path()?)?;
+        Ok(ModelSpec::new(
+            detect_model_type(&config)?,
+            model_resource,
+            config_resource,
+            vocab_resource,
+            merges_resource,
+        ))
+    }
+
+    /// Instantiate a new model specification from a directory containing a converted checkpoint:
+    /// - `rust_model.ot` and `config.json`, the model type being read from the configuration
+    /// - the tokenizer vocabulary (`vocab.txt`, `vocab.json`, `spiece.model` or
+    /// `sentencepiece.bpe.model`) and optional merges (`merges.txt` or `source.spm`)
+    /// - an optional `tokenizer_config.json`, providing the `do_lower_case`, `strip_accents` and
+    /// `add_prefix_space` tokenizer settings
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - directory containing the model files
+    pub fn from_local_dir<P: AsRef<Path>>(path: P) -> Result<ModelSpec, RustBertError> {
+        let path = path.as_ref();
+        let find_file = |file_names: &[&str]| {
+            file_names
+                .iter()
+                .map(|file_name| path.join(file_name))
+                .find(|file_path| file_path.is_file())
+        };
+        let missing_file = |description: &str| {
+            RustBertError::InvalidConfigurationError(format!(
+                "No {} found in {}",
+                description,
+                path.display()
+            ))
+        };
+        let model_path =
+            find_file(&["rust_model.ot"]).ok_or_else(|| missing_file("model weights"))?;
+        let config_path =
+            find_file(&["config.json"]).ok_or_else(|| missing_file("configuration"))?;
+        let vocab_path = find_file(&[
+            "vocab.txt",
+            "vocab.json",
+            "spiece.model",
+            "sentencepiece.bpe.model",
+        ])
+        .ok_or_else(|| missing_file("tokenizer vocabulary"))?;
+        let merges_path = find_file(&["merges.txt", "source.spm"]);
+
+        let mut model_spec = ModelSpec::auto(
+            LocalResource::from(model_path),
+            LocalResource::from(config_path),
+            LocalResource::from(vocab_path),
+            merges_path.map(LocalResource::from),
+        )?;
+        if let Some(tokenizer_config_path) = find_file(&["tokenizer_config.json"]) {
+            let tokenizer_config = read_json(tokenizer_config_path)?;
+            let get_flag = |key: &str| tokenizer_config.get(key).and_then(Value::as_bool);
+            model_spec.lower_case = get_flag("do_lower_case").unwrap_or(false);
+            model_spec.strip_accents = get_flag("strip_accents");
+            model_spec.add_prefix_space = get_flag("add_prefix_space");
+        }
+        Ok(model_spec)
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+/// # Floating point precision of the model weights
+pub enum Precision {
+    /// Single precision (default)
+    #[serde(alias = "fp32", alias = "float")]
+    Float32,
+    /// Half precision, supported by the summarization, text generation and conversation pipelines
+    #[serde(alias = "fp16", alias = "half")]
+    Float16,
+}
+
+impl Default for Precision {
+    fn default() -> Self {
+        Precision::Float32
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+/// # Declarative configuration of a pipeline
+/// Describes a whole pipeline (task, model resources, device, precision and task-specific
+/// parameters) so that it can be loaded from a single configuration file, for example:
+///
+/// ```json
+/// {
+///   "task": "Summarization",
+///   "model": {
+///     "model_type": "Bart",
+///     "model_resource": {"local": {"local_path": "bart/rust_model.ot"}},
+///     "config_resource": {"local": {"local_path": "bart/config.json"}},
+///     "vocab_resource": {"local": {"local_path": "bart/vocab.json"}},
+///     "merges_resource": {"local": {"local_path": "bart/merges.txt"}},
+///     "device": "cuda:0"
+///   },
+///   "dtype": "float16",
+///   "parameters": {"num_beams": 4, "max_length": 64}
+/// }
+/// ```
+///
+/// The `parameters` override the fields of the task configuration (e.g. `SummarizationConfig`
+/// or `SequenceClassificationConfig`) created from the model specification. Unknown parameters
+/// are rejected.
+pub struct PipelineConfig {
+    /// Task performed by the pipeline
+    pub task: TaskType,
+    /// Model resources, tokenizer settings and device
+    pub model: ModelSpec,
+    /// Precision of the model weights (default: `float32`)
+    #[serde(default)]
+    pub dtype: Precision,
+    /// Task-specific settings overriding the defaults of the task configuration
+    #[serde(default)]
+    pub parameters: Map<String, Value>,
+}
+
+impl PipelineConfig {
+    /// Reads a pipeline configuration from a JSON or YAML file. Files with a `.yaml` or `.yml`
+    /// extension are parsed as YAML (requires the `yaml` feature), other files as JSON.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - path to the configuration file
+    ///
+    /// # Returns
+    ///
+    /// * `PipelineConfig` read from the file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<PipelineConfig, RustBertError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("yaml") | Some("yml") => PipelineConfig::from_yaml_str(&content),
+            _ => PipelineConfig::from_json_str(&content),
+        }
+    }
+
+    /// Parses a pipeline configuration from a JSON string
+    pub fn from_json_str(content: &str) -> Result<PipelineConfig, RustBertError> {
+        serde_json::from_str(content).map_err(|error| {
+            RustBertError::InvalidConfigurationError(format!(
+                "Invalid pipeline configuration: {}",
+                error
+            ))
+        })
+    }
+
+    /// Parses a pipeline configuration from a YAML string (requires the `yaml` feature)
+    pub fn from_yaml_str(content: &str) -> Result<PipelineConfig, RustBertError> {
+        #[cfg(feature = "yaml")]
+        {
+            serde_yaml::from_str(content).map_err(|error| {
+                RustBertError::InvalidConfigurationError(format!(
+                    "Invalid pipeline configuration: {}",
+                    error
+                ))
+            })
+        }
+        #[cfg(not(feature = "yaml"))]
+        {
+            let _ = content;
+            Err(RustBertError::InvalidConfigurationError(
+                "YAML pipeline configurations require the `yaml` feature".to_string(),
+            ))
+        }
+    }
+}
+
+/// # Pipeline created by the registry, holding the concrete model for the requested task
+pub enum Pipeline {
+    /// Sequence classification pipeline
+    SequenceClassification(SequenceClassificationModel),
+    /// Sentiment analysis pipeline
+    Sentiment(SentimentModel),
+    /// Token classification pipeline
+    TokenClassification(TokenClassificationModel),
+    /// Named entity recognition pipeline
+    NER(NERModel),
+    /// Part of speech tagging pipeline
+    POSTagging(POSModel),
+    /// Extractive question answering pipeline
+    QuestionAnswering(QuestionAnsweringModel),
+    /// Zero-shot classification pipeline
+    ZeroShotClassification(ZeroShotClassificationModel),
+    /// Summarization pipeline
+    Summarization(SummarizationModel),
+    /// Text generation pipeline
+    TextGeneration(TextGenerationModel),
+    /// Multi-turn conversation pipeline
+    Conversation(ConversationModel),
+    /// Masked language model pipeline
+    MaskedLanguage(MaskedLanguageModel),
+}
+
+impl Pipeline {
+    /// Build the pipeline for a task from a model specification
+    ///
+    /// # Arguments
+    ///
+    /// * `task` - `TaskType` to perform
+    /// * `model` - `ModelSpec` with the resources of the model and tokenizer settings
+    ///
+    /// # Returns
+    ///
+    /// * `Pipeline` holding the model for the requested task
+    pub fn new(task: TaskType, model: ModelSpec) -> Result<Pipeline, RustBertError> {
+        Pipeline::build(task, model, &Map::new())
+    }
+
+    /// Build the pipeline described by a `PipelineConfig`, overriding the task-specific settings
+    /// with the configuration parameters and casting the model weights to the requested precision.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `PipelineConfig` describing the task, model, precision and parameters
+    ///
+    /// # Returns
+    ///
+    /// * `Pipeline` holding the model for the configured task
+    pub fn from_config(config: PipelineConfig) -> Result<Pipeline, RustBertError> {
+        let mut pipeline = Pipeline::build(config.task, config.model, &config.parameters)?;
+        if config.dtype == Precision::Float16 {
+            match &mut pipeline {
+                Pipeline::Summarization(model) => model.half(),
+                Pipeline::TextGeneration(model) => model.half(),
+                Pipeline::Conversation(model) => model.half(),
+                _ => {
+                    return Err(RustBertError::InvalidConfigurationError(format!(
+                        "Half precision is not supported for the {:?} pipeline",
+                        config.task
+                    )));
+                }
+            }
+        }
+        Ok(pipeline)
+    }
+
+    /// Build the pipeline described by a JSON (or YAML, with the `yaml` feature) configuration
+    /// file. See `PipelineConfig` for the file format.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - path to the configuration file (`.yaml` and `.yml` files are parsed as YAML, other files as JSON)
+    ///
+    /// # Returns
+    ///
+    /// * `Pipeline` holding the model for the configured task
+    pub fn from_config_file<P: AsRef<Path>>(path: P) -> Result<Pipeline, RustBertError> {
+        Pipeline::from_config(PipelineConfig::from_file(path)?)
+    }
+
+    /// Build a pipeline from a directory containing a converted checkpoint (see
+    /// `ModelSpec::from_local_dir`), without specifying its architecture. The model type is read
+    /// from the checkpoint configuration and, if no task is given, the task is identified from the
+    /// `architectures` of the configuration (see `detect_task`).
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - directory containing the model files
+    /// * `task` - `Option<TaskType>` to perform, detected from the configuration if `None`
+    ///
+    /// # Returns
+    ///
+    /// * `Pipeline` holding the model for the requested (or detected) task
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::registry::Pipeline;
+    /// use serde_json::json;
+    ///
+    /// let pipeline = Pipeline::from_local_dir("path/to/checkpoint", None)?;
+    /// println!("Loaded a {:?} pipeline", pipeline.task());
+    /// let output = pipeline.predict_json(&json!(["This is a great movie!"]))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_local_dir<P: AsRef<Path>>(
+        path: P,
+        task: Option<TaskType>,
+    ) -> Result<Pipeline, RustBertError> {
+        let model_spec = ModelSpec::from_local_dir(path)?;
+        let task = match task {
+            Some(task) => task,
+            None => {
+                let config = read_json(model_spec.config_resource.get_local_path()?)?;
+                detect_task(&config).ok_or_else(|| {
+                    RustBertError::InvalidConfigurationError(format!(
+                        "Could not identify the task from the model architectures {}, \
+                         the task should be provided",
+                        config.get("architectures").unwrap_or(&Value::Null)
+                    ))
+                })?
+            }
+        };
+        Pipeline::new(task, model_spec)
+    }
+
+    fn build(
+        task: TaskType,
+        model: ModelSpec,
+        parameters: &Map<String, Value>,
+    ) -> Result<Pipeline, RustBertError> {
+        let device = model.device;
+        Ok(match task {
+            TaskType::SequenceClassification => {
+                let mut config = sequence_classification_config(model);
+                config.device = device;
+                let config = with_parameters(task, config, parameters)?;
+                Pipeline::SequenceClassification(SequenceClassificationModel::new(config)?)
+            }
+            TaskType::Sentiment => {
+                let mut config = sequence_classification_config(model);
+                config.device = device;
+                let config = with_parameters(task, config, parameters)?;
+                Pipeline::Sentiment(SentimentModel::new(config)?)
+            }
+            TaskType::TokenClassification => {
+                let mut config = token_classification_config(model);
+                config.device = device;
+                let config = with_parameters(task, config, parameters)?;
+                Pipeline::TokenClassification(TokenClassificationModel::new(config)?)
+            }
+            TaskType::NER => {
+                let mut config = token_classification_config(model);
+                config.device = device;
+                let config = with_parameters(task, config, parameters)?;
+                Pipeline::NER(NERModel::new(config)?)
+            }
+            TaskType::POSTagging => {
+                let mut config = token_classification_config(model);
+                config.device = device;
+                let config = with_parameters(task, config, parameters)?;
+                Pipeline::POSTagging(POSModel::new(config.into())?)
+            }
+            TaskType::QuestionAnswering => {
+                let mut config = QuestionAnsweringConfig::new(
+                    model.model_type,
+                    model.model_resource,
+                    model.config_resource,
+                    model.vocab_resource,
+                    model.merges_resource,
+                    model.lower_case,
+                    model.strip_accents,
+                    model.add_prefix_space,
+                );
+                config.device = device;
+                let config = with_parameters(task, config, parameters)?;
+                Pipeline::QuestionAnswering(QuestionAnsweringModel::new(config)?)
+            }
+            TaskType::ZeroShotClassification => {
+                let mut config = ZeroShotClassificationConfig::new(
+                    model.model_type,
+                    model.model_resource,
+                    model.config_resource,
+                    model.vocab_resource,
+                    model.merges_resource,
+                    model.lower_case,
+                    model.strip_accents,
+                    model.add_prefix_space,
+                );
+                config.device = device;
+                let config = with_parameters(task, config, parameters)?;
+                Pipeline::ZeroShotClassification(ZeroShotClassificationModel::new(config)?)
+            }
+            TaskType::Summarization => {
+                let mut config = SummarizationConfig::new(
+                    model.model_type,
+                    model.model_resource,
+                    model.config_resource,
+                    model.vocab_resource,
+                    model.merges_resource,
+                );
+                config.device = device;
+                let config = with_parameters(task, config, parameters)?;
+                Pipeline::Summarization(SummarizationModel::new(config)?)
+            }
+            TaskType::TextGeneration => {
+                let mut config = TextGenerationConfig::new(
+                    model.model_type,
+                    model.model_resource,
+                    model.config_resource,
+                    model.vocab_resource,
+                    model.merges_resource,
+                );
+                config.device = device;
+                let config = with_parameters(task, config, parameters)?;
+                Pipeline::TextGeneration(TextGenerationModel::new(config)?)
+            }
+            TaskType::Conversation => {
+                let mut config = ConversationConfig::new(
+                    model.model_type,
+                    model.model_resource,
+                    model.config_resource,
+                    model.vocab_resource,
+                    model.merges_resource,
+                );
+                config.device = device;
+                let config = with_parameters(task, config, parameters)?;
+                Pipeline::Conversation(ConversationModel::new(config)?)
+            }
+            TaskType::MaskedLanguage => {
+                let mut config = MaskedLanguageConfig::new(
+                    model.model_type,
+                    model.model_resource,
+                    model.config_resource,
+                    model.vocab_resource,
+                    model.merges_resource,
+                    model.lower_case,
+                    model.strip_accents,
+                    model.add_prefix_space,
+                    None::<String>,
+                );
+                config.device = device;
+                let config = with_parameters(task, config, parameters)?;
+                Pipeline::MaskedLanguage(MaskedLanguageModel::new(config)?)
+            }
+        })
+    }
+
+    /// Build the pipeline for a task using the default pretrained model of the task (e.g. DistilBERT
+    /// fine-tuned on SST-2 for sentiment analysis)
+    ///
+    /// # Arguments
+    ///
+    /// * `task` - `TaskType` to perform
+    ///
+    /// # Returns
+    ///
+    /// * `Pipeline` holding the default model for the requested task
+    #[cfg(feature = "remote")]
+    pub fn new_default(task: TaskType) -> Result<Pipeline, RustBertError> {
+        Ok(match task {
+            TaskType::SequenceClassification => Pipeline::SequenceClassification(
+                SequenceClassificationModel::new(Default::default())?,
+            ),
+            TaskType::Sentiment => Pipeline::Sentiment(SentimentModel::new(Default::default())?),
+            TaskType::TokenClassification => {
+                Pipeline::TokenClassification(TokenClassificationModel::new(Default::default())?)
+            }
+            TaskType::NER => Pipeline::NER(NERModel::new(Default::default())?),
+            TaskType::POSTagging => Pipeline::POSTagging(POSModel::new(Default::default())?),
+            TaskType::QuestionAnswering => {
+                Pipeline::QuestionAnswering(QuestionAnsweringModel::new(Default::default())?)
+            }
+            TaskType::ZeroShotClassification => Pipeline::ZeroShotClassification(
+                ZeroShotClassificationModel::new(Default::default())?,
+            ),
+            TaskType::Summarization => {
+                Pipeline::Summarization(SummarizationModel::new(Default::default())?)
+            }
+            TaskType::TextGeneration => {
+                Pipeline::TextGeneration(TextGenerationModel::new(Default::default())?)
+            }
+            TaskType::Conversation => {
+                Pipeline::Conversation(ConversationModel::new(Default::default())?)
+            }
+            TaskType::MaskedLanguage => {
+                Pipeline::MaskedLanguage(MaskedLanguageModel::new(Default::default())?)
+            }
+        })
+    }
+
+    /// Returns the `TaskType` performed by this pipeline
+    pub fn task(&self) -> TaskType {
+        match self {
+            Self::SequenceClassification(_) => TaskType::SequenceClassification,
+            Self::Sentiment(_) => TaskType::Sentiment,
+            Self::TokenClassification(_) => TaskType::TokenClassification,
+            Self::NER(_) => TaskType::NER,
+            Self::POSTagging(_) => TaskType::POSTagging,
+            Self::QuestionAnswering(_) => TaskType::QuestionAnswering,
+            Self::ZeroShotClassification(_) => TaskType::ZeroShotClassification,
+            Self::Summarization(_) => TaskType::Summarization,
+            Self::TextGeneration(_) => TaskType::TextGeneration,
+            Self::Conversation(_) => TaskType::Conversation,
+            Self::MaskedLanguage(_) => TaskType::MaskedLanguage,
+        }
+    }
+
+    /// Runs the pipeline on JSON inputs and returns JSON outputs, providing a uniform interface to
+    /// all tasks (e.g. for bindings or services). The expected inputs are:
+    /// - `QuestionAnswering`: an array of `{"question": ..., "context": ...}` objects
+    /// - `ZeroShotClassification`: an object `{"inputs": [...], "labels": [...]}`
+    /// - all other tasks: an array of input texts. Each conversation input starts a new conversation.
+    ///
+    /// The outputs are the serialized outputs of the underlying pipeline (e.g. `Vec<Sentiment>`
+    /// for sentiment analysis, `Vec<String>` for summarization).
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - `&Value` JSON inputs for the task
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Value, RustBertError>` JSON outputs of the pipeline
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// # use rust_bert::pipelines::registry::{ModelSpec, Pipeline, TaskType};
+    /// # let model_spec: ModelSpec = unimplemented!();
+    /// use serde_json::json;
+    ///
+    /// let pipeline = Pipeline::new(TaskType::Sentiment, model_spec)?;
+    /// let output = pipeline.predict_json(&json!(["This is a great movie!"]))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn predict_json(&self, input: &Value) -> Result<Value, RustBertError> {
+        Ok(match self {
+            Self::QuestionAnswering(model) => {
+                let qa_inputs = Vec::<QaInput>::deserialize(input)?;
+                serde_json::to_value(model.predict(&qa_inputs, 1, 32))?
+            }
+            Self::ZeroShotClassification(model) => {
+                #[derive(Deserialize)]
+                struct ZeroShotInput {
+                    inputs: Vec<String>,
+                    labels: Vec<String>,
+                }
+                let zero_shot_input = ZeroShotInput::deserialize(input)?;
+                let inputs = as_str_slice(&zero_shot_input.inputs);
+                let labels = as_str_slice(&zero_shot_input.labels);
+                serde_json::to_value(model.predict(&inputs, &labels, None, 128)?)?
+            }
+            _ => {
+                let texts = Vec::<String>::deserialize(input)?;
+                let inputs = as_str_slice(&texts);
+                match self {
+                    Self::SequenceClassification(model) => {
+                        serde_json::to_value(model.predict(&inputs))?
+                    }
+                    Self::Sentiment(model) => serde_json::to_value(model.predict(&inputs))?,
+                    Self::TokenClassification(model) => {
+                        serde_json::to_value(model.predict(&inputs, true, false))?
+                    }
+                    Self::NER(model) => serde_json::to_value(model.predict(&inputs))?,
+                    Self::POSTagging(model) => serde_json::to_value(model.predict(&inputs))?,
+                    Self::MaskedLanguage(model) => serde_json::to_value(model.predict(&inputs)?)?,
+                    Self::Summarization(model) => serde_json::to_value(model.summarize(&inputs)?)?,
+                    Self::TextGeneration(model) => {
+                        serde_json::to_value(model.generate(&inputs, None)?)?
+                    }
+                    Self::Conversation(model) => {
+                        let mut conversation_manager = ConversationManager::new();
+                        let conversation_ids = inputs
+                            .iter()
+                            .map(|text| conversation_manager.create(text))
+                            .collect::<Vec<_>>();
+                        let responses = model.generate_responses(&mut conversation_manager)?;
+                        let responses = conversation_ids
+                            .iter()
+                            .map(|id| responses.get(id).copied().unwrap_or_default())
+                            .collect::<Vec<&str>>();
+                        serde_json::to_value(responses)?
+                    }
+                    Self::QuestionAnswering(_) | Self::ZeroShotClassification(_) => unreachable!(),
+                }
+            }
+        })
+    }
+}
+
+/// Model types identified by the `model_type` field of Hugging Face configuration files
+/// (underscores being replaced by hyphens)
+const CONFIG_MODEL_TYPES: [(&str, ModelType); 23] = [
+    ("bart", ModelType::Bart),
+    ("bert", ModelType::Bert),
+    ("distilbert", ModelType::DistilBert),
+    ("deberta", ModelType::Deberta),
+    ("deberta-v2", ModelType::DebertaV2),
+    ("roberta", ModelType::Roberta),
+    ("xlm-roberta", ModelType::XLMRoberta),
+    ("electra", ModelType::Electra),
+    ("marian", ModelType::Marian),
+    ("mobilebert", ModelType::MobileBert),
+    ("t5", ModelType::T5),
+    ("albert", ModelType::Albert),
+    ("xlnet", ModelType::XLNet),
+    ("gpt2", ModelType::GPT2),
+    ("openai-gpt", ModelType::OpenAiGpt),
+    ("reformer", ModelType::Reformer),
+    ("prophetnet", ModelType::ProphetNet),
+    ("longformer", ModelType::Longformer),
+    ("pegasus", ModelType::Pegasus),
+    ("gpt-neo", ModelType::GPTNeo),
+    ("mbart", ModelType::MBart),
+    ("m2m-100", ModelType::M2M100),
+    ("fnet", ModelType::FNet),
+];
+
+/// Prefixes of the model classes listed in the `architectures` field of Hugging Face
+/// configuration files. Prefixes sharing a common start are listed from the longest.
+const ARCHITECTURE_MODEL_TYPES: [(&str, ModelType); 23] = [
+    ("XLMRoberta", ModelType::XLMRoberta),
+    ("Roberta", ModelType::Roberta),
+    ("DistilBert", ModelType::DistilBert),
+    ("MobileBert", ModelType::MobileBert),
+    ("DebertaV2", ModelType::DebertaV2),
+    ("Deberta", ModelType::Deberta),
+    ("Bert", ModelType::Bert),
+    ("Albert", ModelType::Albert),
+    ("Electra", ModelType::Electra),
+    ("Marian", ModelType::Marian),
+    ("T5", ModelType::T5),
+    ("XLNet", ModelType::XLNet),
+    ("GPT2", ModelType::GPT2),
+    ("GPTNeo", ModelType::GPTNeo),
+    ("OpenAIGPT", ModelType::OpenAiGpt),
+    ("Reformer", ModelType::Reformer),
+    ("ProphetNet", ModelType::ProphetNet),
+    ("Longformer", ModelType::Longformer),
+    ("Pegasus", ModelType::Pegasus),
+    ("MBart", ModelType::MBart),
+    ("M2M100", ModelType::M2M100),
+    ("FNet", ModelType::FNet),
+    ("Bart", ModelType::Bart),
+];
+
+/// Suffixes of the model classes listed in the `architectures` field of Hugging Face
+/// configuration files, identifying the task of the checkpoint
+const ARCHITECTURE_TASKS: [(&str, TaskType); 7] = [
+    (
+        "ForSequenceClassification",
+        TaskType::SequenceClassification,
+    ),
+    ("ForTokenClassification", TaskType::TokenClassification),
+    ("ForQuestionAnswering", TaskType::QuestionAnswering),
+    ("ForMaskedLM", TaskType::MaskedLanguage),
+    ("ForConditionalGeneration", TaskType::Summarization),
+    ("ForCausalLM", TaskType::TextGeneration),
+    ("LMHeadModel", TaskType::TextGeneration),
+];
+
+/// Identifies the model type of a Hugging Face model configuration (`config.json`), from its
+/// `model_type` field or, if missing, from the model classes listed in its `architectures`.
+///
+/// # Arguments
+///
+/// * `config` - `&Value` JSON model configuration
+///
+/// # Returns
+///
+/// * `Result<ModelType, RustBertError>` model type, or an error for unsupported architectures
+pub fn detect_model_type(config: &Value) -> Result<ModelType, RustBertError> {
+    let from_model_type = config
+        .get("model_type")
+        .and_then(Value::as_str)
+        .and_then(|model_type| {
+            let model_type = model_type.to_lowercase().replace('_', "-");
+            CONFIG_MODEL_TYPES
+                .iter()
+                .find(|(name, _)| *name == model_type)
+                .map(|(_, model_type)| *model_type)
+        });
+    let from_architectures = || {
+        architectures(config).find_map(|architecture| {
+            ARCHITECTURE_MODEL_TYPES
+                .iter()
+                .find(|(prefix, _)| architecture.starts_with(prefix))
+                .map(|(_, model_type)| *model_type)
+        })
+    };
+    from_model_type.or_else(from_architectures).ok_or_else(|| {
+        RustBertError::InvalidConfigurationError(format!(
+            "Could not identify a supported model type from the configuration \
+             (model_type: {}, architectures: {})",
+            config.get("model_type").unwrap_or(&Value::Null),
+            config.get("architectures").unwrap_or(&Value::Null)
+        ))
+    })
+}
+
+/// Identifies the task of a Hugging Face model configuration (`config.json`) from the model
+/// classes listed in its `architectures` (e.g. `BertForTokenClassification`). Sequence-to-sequence
+/// language models (`ForConditionalGeneration`) are mapped to summarization.
+///
+/// # Arguments
+///
+/// * `config` - `&Value` JSON model configuration
+///
+/// # Returns
+///
+/// * `Option<TaskType>` task of the checkpoint, `None` if it cannot be identified
+pub fn detect_task(config: &Value) -> Option<TaskType> {
+    architectures(config).find_map(|architecture| {
+        ARCHITECTURE_TASKS
+            .iter()
+            .find(|(suffix, _)| architecture.ends_with(suffix))
+            .map(|(_, task)| *task)
+    })
+}
+
+fn architectures(config: &Value) -> impl Iterator<Item = &str> {
+    config
+        .get("architectures")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+}
+
+fn read_json<P: AsRef<Path>>(path: P) -> Result<Value, RustBertError> {
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+/// Overrides the fields of a task configuration with the provided parameters, going through the
+/// serialized representation of the configuration.
+fn with_parameters<C>(
+    task: TaskType,
+    config: C,
+    parameters: &Map<String, Value>,
+) -> Result<C, RustBertError>
+where
+    C: Serialize + DeserializeOwned,
+{
+    if parameters.is_empty() {
+        return Ok(config);
+    }
+    let mut config = serde_json::to_value(config)?;
+    let fields = config.as_object_mut().ok_or_else(|| {
+        RustBertError::InvalidConfigurationError(format!(
+            "Configuration of the {:?} pipeline cannot be overridden",
+            task
+        ))
+    })?;
+    for (name, value) in parameters {
+        match fields.get_mut(name) {
+            Some(field) => *field = value.clone(),
+            None => {
+                return Err(RustBertError::InvalidConfigurationError(format!(
+                    "Unknown parameter `{}` for the {:?} pipeline",
+                    name, task
+                )));
+            }
+        }
+    }
+    serde_json::from_value(config).map_err(|error| {
+        RustBertError::InvalidConfigurationError(format!(
+            "Invalid parameters for the {:?} pipeline: {}",
+            task, error
+        ))
+    })
+}
+
+fn as_str_slice(values: &[String]) -> Vec<&str> {
+    values.iter().map(String::as_str).collect()
+}
+
+fn sequence_classification_config(model: ModelSpec) -> SequenceClassificationConfig {
+    SequenceClassificationConfig::new(
+        model.model_type,
+        model.model_resource,
+        model.config_resource,
+        model.vocab_resource,
+        model.merges_resource,
+        model.lower_case,
+        model.strip_accents,
+        model.add_prefix_space,
+    )
+}
+
+fn token_classification_config(model: ModelSpec) -> TokenClassificationConfig {
+    TokenClassificationConfig::new(
+        model.model_type,
+        model.model_resource,
+        model.config_resource,
+        model.vocab_resource,
+        model.merges_resource,
+        model.lower_case,
+        model.strip_accents,
+        model.add_prefix_space,
+        LabelAggregationOption::First,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn architecture_detection() -> Result<(), RustBertError> {
+        let config = json!({"model_type": "xlm-roberta", "architectures": ["XLMRobertaForTokenClassification"]});
+        assert_eq!(detect_model_type(&config)?, ModelType::XLMRoberta);
+        assert_eq!(detect_task(&config), Some(TaskType::TokenClassification));
+
+        let config = json!({"model_type": "gpt_neo"});
+        assert_eq!(detect_model_type(&config)?, ModelType::GPTNeo);
+        assert_eq!(detect_task(&config), None);
+
+        let config = json!({"architectures": ["DistilBertForSequenceClassification"]});
+        assert_eq!(detect_model_type(&config)?, ModelType::DistilBert);
+        assert_eq!(detect_task(&config), Some(TaskType::SequenceClassification));
+
+        let config = json!({"architectures": ["BartForConditionalGeneration"]});
+        assert_eq!(detect_model_type(&config)?, ModelType::Bart);
+        assert_eq!(detect_task(&config), Some(TaskType::Summarization));
+
+        let config = json!({"architectures": ["GPT2LMHeadModel"]});
+        assert_eq!(detect_model_type(&config)?, ModelType::GPT2);
+        assert_eq!(detect_task(&config), Some(TaskType::TextGeneration));
+
+        let config = json!({"model_type": "llama", "architectures": ["LlamaForCausalLM"]});
+        assert!(detect_model_type(&config).is_err());
+        Ok(())
+    }
+}
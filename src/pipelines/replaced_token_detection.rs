@@ -0,0 +1,328 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Replaced token detection pipeline
+//! Flags the tokens of an input text that are likely to be wrong, using the discriminator of an
+//! ELECTRA model. The discriminator is pre-trained to detect the tokens of a sentence replaced by
+//! a small generator model, and therefore assigns a high score to tokens that do not fit their
+//! context (typos, wrong words, corrupted text). This can be used for lightweight proofreading or
+//! to filter low-quality samples out of a dataset.
+//!
+//! Each token of the input is returned with its probability of having been replaced, and tokens
+//! with a probability above the configured threshold are flagged. The maximum token probability
+//! of a sequence is reported as an overall score, lower values indicating more acceptable texts.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::replaced_token_detection::ReplacedTokenDetectionModel;
+//!
+//! let model = ReplacedTokenDetectionModel::new(Default::default())?;
+//! let input = ["One Two Three Ten Five Six Seven Eight"];
+//! let output = model.predict(&input)?;
+//! for token in output[0].flagged_tokens() {
+//!     println!("{} ({:.3})", token.text, token.score);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::common::error::RustBertError;
+use crate::common::trace::trace_span;
+use crate::electra::{ElectraConfig, ElectraDiscriminator};
+use crate::pipelines::common::{ModelType, TokenizerOption};
+use crate::pipelines::input_encoding::EncodedInputs;
+use crate::resources::ResourceProvider;
+use crate::Config;
+#[cfg(feature = "remote")]
+use crate::{
+    electra::{ElectraConfigResources, ElectraModelResources, ElectraVocabResources},
+    resources::RemoteResource,
+};
+use rust_tokenizers::tokenizer::TruncationStrategy;
+use rust_tokenizers::Offset;
+use serde::{Deserialize, Serialize};
+use tch::nn::VarStore;
+use tch::{no_grad, Device};
+
+#[derive(Serialize, Deserialize)]
+/// # Configuration for ReplacedTokenDetectionModel
+/// Contains information regarding the ELECTRA discriminator to load and device to place the model on.
+pub struct ReplacedTokenDetectionConfig {
+    /// Model weights resource (default: pretrained ELECTRA base discriminator)
+    #[serde(with = "crate::common::serde_utils::resource")]
+    pub model_resource: Box<dyn ResourceProvider + Send>,
+    /// Config resource (default: pretrained ELECTRA base discriminator)
+    #[serde(with = "crate::common::serde_utils::resource")]
+    pub config_resource: Box<dyn ResourceProvider + Send>,
+    /// Vocab resource (default: pretrained ELECTRA base discriminator)
+    #[serde(with = "crate::common::serde_utils::resource")]
+    pub vocab_resource: Box<dyn ResourceProvider + Send>,
+    /// Automatically lower case all input upon tokenization (assumes a lower-cased model)
+    pub lower_case: bool,
+    /// Flag indicating if the tokenizer should strip accents (normalization)
+    pub strip_accents: Option<bool>,
+    /// Probability above which a token is flagged as replaced (default: 0.5)
+    pub threshold: f64,
+    /// Device to place the model on (default: CUDA/GPU when available)
+    #[serde(
+        with = "crate::common::serde_utils::device",
+        default = "crate::common::serde_utils::device::default"
+    )]
+    pub device: Device,
+}
+
+impl ReplacedTokenDetectionConfig {
+    /// Instantiate a new replaced token detection configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `model_resource` - The `ResourceProvider` pointing to the discriminator weights to load (e.g.  model.ot)
+    /// * `config_resource` - The `ResourceProvider` pointing to the model configuration to load (e.g. config.json)
+    /// * `vocab_resource` - The `ResourceProvider` pointing to the tokenizer's vocabulary to load (e.g.  vocab.txt)
+    /// * `lower_case` - A `bool` indicating whether the tokenizer should lower case all input (in case of a lower-cased model)
+    /// * `threshold` - Probability above which a token is flagged as replaced
+    pub fn new<RM, RC, RV>(
+        model_resource: RM,
+        config_resource: RC,
+        vocab_resource: RV,
+        lower_case: bool,
+        strip_accents: impl Into<Option<bool>>,
+        threshold: f64,
+    ) -> ReplacedTokenDetectionConfig
+    where
+        RM: ResourceProvider + Send + 'static,
+        RC: ResourceProvider + Send + 'static,
+        RV: ResourceProvider + Send + 'static,
+    {
+        ReplacedTokenDetectionConfig {
+            model_resource: Box::new(model_resource),
+            config_resource: Box::new(config_resource),
+            vocab_resource: Box::new(vocab_resource),
+            lower_case,
+            strip_accents: strip_accents.into(),
+            threshold,
+            device: Device::cuda_if_available(),
+        }
+    }
+}
+
+#[cfg(feature = "remote")]
+impl Default for ReplacedTokenDetectionConfig {
+    /// Provides the ELECTRA base discriminator
+    fn default() -> ReplacedTokenDetectionConfig {
+        ReplacedTokenDetectionConfig::new(
+            RemoteResource::from_pretrained(ElectraModelResources::BASE_DISCRIMINATOR),
+            RemoteResource::from_pretrained(ElectraConfigResources::BASE_DISCRIMINATOR),
+            RemoteResource::from_pretrained(ElectraVocabResources::BASE_DISCRIMINATOR),
+            true,
+            None,
+            0.5,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Token scored by the discriminator
+pub struct DetectedToken {
+    /// Text of the token in the input (or decoded token if it cannot be mapped to the input)
+    pub text: String,
+    /// Vocabulary index of the token
+    pub id: i64,
+    /// Probability of the token to have been replaced (i.e. to be wrong in its context)
+    pub score: f64,
+    /// Flag indicating if the score is above the configured threshold
+    pub replaced: bool,
+    /// Token offsets in the input text (character positions)
+    pub offset: Option<Offset>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Output of the replaced token detection for an input text
+pub struct ReplacedTokenDetection {
+    /// Scored tokens of the input, excluding special tokens
+    pub tokens: Vec<DetectedToken>,
+    /// Maximum replaced probability over the tokens of the input (lower is more acceptable)
+    pub score: f64,
+}
+
+impl ReplacedTokenDetection {
+    /// Returns the tokens flagged as replaced
+    pub fn flagged_tokens(&self) -> impl Iterator<Item = &DetectedToken> {
+        self.tokens.iter().filter(|token| token.replaced)
+    }
+
+    /// Returns `true` if no token of the input was flagged as replaced
+    pub fn is_acceptable(&self) -> bool {
+        !self.tokens.iter().any(|token| token.replaced)
+    }
+}
+
+/// # ReplacedTokenDetectionModel to flag likely-wrong tokens with an ELECTRA discriminator
+pub struct ReplacedTokenDetectionModel {
+    tokenizer: TokenizerOption,
+    discriminator: ElectraDiscriminator,
+    var_store: VarStore,
+    max_length: usize,
+    threshold: f64,
+}
+
+impl ReplacedTokenDetectionModel {
+    /// Build a new `ReplacedTokenDetectionModel`
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `ReplacedTokenDetectionConfig` object containing the resource references (model, vocabulary, configuration), threshold and device placement (CPU/GPU)
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::replaced_token_detection::ReplacedTokenDetectionModel;
+    ///
+    /// let model = ReplacedTokenDetectionModel::new(Default::default())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new(
+        config: ReplacedTokenDetectionConfig,
+    ) -> Result<ReplacedTokenDetectionModel, RustBertError> {
+        let _span = trace_span!(
+            INFO,
+            "load_model",
+            pipeline = "replaced_token_detection",
+            device = ?config.device
+        );
+        let vocab_path = config.vocab_resource.get_local_path()?;
+        let config_path = config.config_resource.get_local_path()?;
+        let weights_path = config.model_resource.get_local_path()?;
+
+        let tokenizer = TokenizerOption::from_file(
+            ModelType::Electra,
+            vocab_path.to_str().unwrap(),
+            None,
+            config.lower_case,
+            config.strip_accents,
+            None,
+        )?;
+        let model_config = ElectraConfig::from_file(config_path);
+        let max_length = model_config.max_position_embeddings as usize;
+
+        let mut var_store = VarStore::new(config.device);
+        let discriminator = ElectraDiscriminator::new(&var_store.root(), &model_config);
+        var_store.load(weights_path)?;
+        Ok(ReplacedTokenDetectionModel {
+            tokenizer,
+            discriminator,
+            var_store,
+            max_length,
+            threshold: config.threshold,
+        })
+    }
+
+    /// Scores the tokens of input texts
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - `&[&str]` Array of texts to check. Texts longer than the model maximum length are truncated.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<ReplacedTokenDetection>` containing the scored tokens of each input text
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::replaced_token_detection::ReplacedTokenDetectionModel;
+    ///
+    /// let model = ReplacedTokenDetectionModel::new(Default::default())?;
+    /// let input = [
+    ///     "The quick brown fox jumps over the lazy dog.",
+    ///     "The quick brown fox cooks over the lazy dog.",
+    /// ];
+    /// let output = model.predict(&input)?;
+    /// for detection in output {
+    ///     println!("{:.3} {}", detection.score, detection.is_acceptable());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn predict<S>(&self, input: &[S]) -> Result<Vec<ReplacedTokenDetection>, RustBertError>
+    where
+        S: AsRef<str> + Sync,
+    {
+        if input.is_empty() {
+            return Ok(vec![]);
+        }
+        let tokenized_input = self.tokenizer.encode_list(
+            input,
+            self.max_length,
+            &TruncationStrategy::LongestFirst,
+            0,
+        );
+        let encoded_inputs = EncodedInputs::from_tokenized_input(
+            &self.tokenizer,
+            tokenized_input.clone(),
+            self.var_store.device(),
+        )?;
+        let max_len = encoded_inputs.input_ids.size()[1];
+
+        let probabilities = no_grad(|| {
+            self.discriminator
+                .forward_t(
+                    Some(&encoded_inputs.input_ids),
+                    Some(&encoded_inputs.attention_mask),
+                    encoded_inputs.token_type_ids.as_ref(),
+                    None,
+                    None,
+                    false,
+                )
+                .probabilities
+                // The discriminator head squeezes its output, restore the batch dimensions
+                .view([input.len() as i64, max_len])
+        });
+
+        let mut output = Vec::with_capacity(input.len());
+        for (sequence_index, (text, tokenized)) in input.iter().zip(tokenized_input).enumerate() {
+            let sequence_probabilities = probabilities
+                .get(sequence_index as i64)
+                .iter::<f64>()?
+                .collect::<Vec<f64>>();
+            let characters = text.as_ref().chars().collect::<Vec<char>>();
+            let tokens = tokenized
+                .token_ids
+                .iter()
+                .zip(tokenized.token_offsets.iter())
+                .zip(tokenized.special_tokens_mask.iter())
+                .zip(sequence_probabilities)
+                .filter(|(((_, _), special_token), _)| **special_token == 0)
+                .map(|(((id, offset), _), score)| {
+                    let text = offset
+                        .and_then(|offset| {
+                            characters.get(offset.begin as usize..offset.end as usize)
+                        })
+                        .map(|characters| characters.iter().collect::<String>())
+                        .unwrap_or_else(|| self.tokenizer.decode(&[*id], false, true));
+                    DetectedToken {
+                        text,
+                        id: *id,
+                        score,
+                        replaced: score > self.threshold,
+                        offset: *offset,
+                    }
+                })
+                .collect::<Vec<DetectedToken>>();
+            let score = tokens.iter().map(|token| token.score).fold(0f64, f64::max);
+            output.push(ReplacedTokenDetection { tokens, score });
+        }
+        Ok(output)
+    }
+}
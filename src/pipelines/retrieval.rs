@@ -0,0 +1,595 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Two-stage retrieval pipeline
+//! Retrieves the documents of a corpus that are the most relevant to queries, combining:
+//! - a bi-encoder (`SentenceEmbeddingsModel`): queries and documents are encoded independently,
+//! allowing to index large corpora and retrieve candidates efficiently from an `EmbeddingIndex`
+//! - a cross-encoder (`SequenceClassificationModel` with a single output, e.g. MS MARCO
+//! cross-encoders): each (query, candidate) pair is scored jointly, which is more accurate but too
+//! expensive to run over the full corpus
+//!
+//! The candidates retrieved by the bi-encoder are reranked by the cross-encoder in a single
+//! `search` call. The pairs are scored in batches, and the final score of each document is
+//! obtained from the bi-encoder and cross-encoder scores according to the `ScoreFusion` strategy.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::common::ModelType;
+//! use rust_bert::pipelines::retrieval::{RetrievalConfig, RetrievalModel};
+//! use rust_bert::pipelines::sentence_embeddings::{
+//!     SentenceEmbeddingsBuilder, SentenceEmbeddingsModelType,
+//! };
+//! use rust_bert::pipelines::sequence_classification::{
+//!     SequenceClassificationConfig, SequenceClassificationModel,
+//! };
+//! use rust_bert::resources::LocalResource;
+//! use std::path::PathBuf;
+//!
+//! let bi_encoder = SentenceEmbeddingsBuilder::remote(SentenceEmbeddingsModelType::AllMiniLmL6V2)
+//!     .create_model()?;
+//! let cross_encoder = SequenceClassificationModel::new(SequenceClassificationConfig::new(
+//!     ModelType::Bert,
+//!     LocalResource::from(PathBuf::from("path/to/rust_model.ot")),
+//!     LocalResource::from(PathBuf::from("path/to/config.json")),
+//!     LocalResource::from(PathBuf::from("path/to/vocab.txt")),
+//!     None,
+//!     true,
+//!     None,
+//!     None,
+//! ))?;
+//!
+//! let mut model = RetrievalModel::new(bi_encoder, cross_encoder, RetrievalConfig::default());
+//! model.add_documents(&[
+//!     "Paris is the capital and most populous city of France.",
+//!     "The Eiffel Tower was completed in 1889.",
+//!     "Berlin is the capital of Germany.",
+//! ])?;
+//! let output = model.search(&["When was the Eiffel Tower built?"], 2)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The same models mine hard negatives for the training of bi-encoders with
+//! `mine_hard_negatives`: for each (query, positive) pair, the documents of the index retrieved by
+//! the bi-encoder are scored by the cross-encoder, and the highest scored documents that are not
+//! likely to be unlabeled positives are returned as (query, positive, negative) training triples.
+
+use crate::common::error::RustBertError;
+use crate::pipelines::sentence_embeddings::{EmbeddingIndex, SentenceEmbeddingsModel};
+use crate::pipelines::sequence_classification::SequenceClassificationModel;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+/// # Strategy combining the bi-encoder and cross-encoder scores of the candidates
+pub enum ScoreFusion {
+    /// The cross-encoder score is used as final score
+    CrossEncoder,
+    /// Weighted sum of the bi-encoder and cross-encoder scores, min-max normalized over the
+    /// candidates of a query. The cross-encoder score has a weight of `1 - bi_encoder_weight`.
+    Weighted { bi_encoder_weight: f64 },
+    /// Reciprocal rank fusion: sum of `1 / (k + rank)` for the bi-encoder and cross-encoder
+    /// rankings (ranks starting at 1)
+    ReciprocalRank { k: f64 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Configuration for RetrievalModel
+pub struct RetrievalConfig {
+    /// Number of candidates retrieved by the bi-encoder and reranked by the cross-encoder for each query (default: 50)
+    pub num_candidates: usize,
+    /// Maximum number of (query, candidate) pairs scored by the cross-encoder in a single forward pass (default: 32)
+    pub batch_size: usize,
+    /// Strategy combining the bi-encoder and cross-encoder scores (default: `ScoreFusion::CrossEncoder`)
+    pub score_fusion: ScoreFusion,
+}
+
+impl Default for RetrievalConfig {
+    fn default() -> RetrievalConfig {
+        RetrievalConfig {
+            num_candidates: 50,
+            batch_size: 32,
+            score_fusion: ScoreFusion::CrossEncoder,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// # Document retrieved for a query
+pub struct RetrievalHit {
+    /// Position of the document in the index
+    pub corpus_id: usize,
+    /// Text of the document
+    pub text: String,
+    /// Final score of the document, combining the bi-encoder and cross-encoder scores
+    pub score: f64,
+    /// Cosine similarity between the query and document embeddings
+    pub bi_encoder_score: f32,
+    /// Relevance score of the (query, document) pair given by the cross-encoder
+    pub cross_encoder_score: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Configuration for the mining of hard negatives
+pub struct HardNegativeMiningConfig {
+    /// Number of candidates retrieved by the bi-encoder for each query (default: 30)
+    pub num_candidates: usize,
+    /// Maximum number of negatives kept for each (query, positive) pair (default: 5)
+    pub num_negatives: usize,
+    /// Number of top-ranked bi-encoder candidates skipped, as the closest documents are often unlabeled positives (default: 0)
+    pub skip_top: usize,
+    /// Candidates with a cross-encoder score above the score of the positive minus this margin are
+    /// discarded as likely unlabeled positives. Set to `None` to keep all candidates (default: `Some(0.0)`)
+    pub false_negative_margin: Option<f64>,
+}
+
+impl Default for HardNegativeMiningConfig {
+    fn default() -> HardNegativeMiningConfig {
+        HardNegativeMiningConfig {
+            num_candidates: 30,
+            num_negatives: 5,
+            skip_top: 0,
+            false_negative_margin: Some(0.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// # (query, positive, negative) training triple
+/// The cross-encoder scores can be used as soft labels (e.g. margin MSE distillation).
+pub struct TrainingTriple {
+    /// Query
+    pub query: String,
+    /// Document relevant to the query
+    pub positive: String,
+    /// Hard negative document retrieved for the query
+    pub negative: String,
+    /// Cross-encoder score of the (query, positive) pair
+    pub positive_score: f64,
+    /// Cross-encoder score of the (query, negative) pair
+    pub negative_score: f64,
+}
+
+/// # RetrievalModel retrieving documents with a bi-encoder and reranking them with a cross-encoder
+pub struct RetrievalModel {
+    bi_encoder: SentenceEmbeddingsModel,
+    cross_encoder: SequenceClassificationModel,
+    index: EmbeddingIndex,
+    config: RetrievalConfig,
+}
+
+impl RetrievalModel {
+    /// Build a new `RetrievalModel` with an empty index
+    ///
+    /// # Arguments
+    ///
+    /// * `bi_encoder` - `SentenceEmbeddingsModel` encoding the documents and queries
+    /// * `cross_encoder` - `SequenceClassificationModel` with a single output scoring (query, document) pairs
+    /// * `config` - `RetrievalConfig` defining the number of candidates, batch size and score fusion
+    pub fn new(
+        bi_encoder: SentenceEmbeddingsModel,
+        cross_encoder: SequenceClassificationModel,
+        config: RetrievalConfig,
+    ) -> RetrievalModel {
+        RetrievalModel {
+            bi_encoder,
+            cross_encoder,
+            index: EmbeddingIndex::new(),
+            config,
+        }
+    }
+
+    /// Replaces the index of the model by a pre-built index (e.g. deserialized from disk). The
+    /// index must have been built with the bi-encoder of the model.
+    pub fn with_index(mut self, index: EmbeddingIndex) -> RetrievalModel {
+        self.index = index;
+        self
+    }
+
+    /// Returns the index of the model
+    pub fn index(&self) -> &EmbeddingIndex {
+        &self.index
+    }
+
+    /// Encodes documents with the bi-encoder and adds them to the index
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - `&[&str]` Array of documents to index
+    pub fn add_documents<S>(&mut self, documents: &[S]) -> Result<(), RustBertError>
+    where
+        S: AsRef<str> + Sync,
+    {
+        self.index.add(&self.bi_encoder, documents)
+    }
+
+    /// Retrieves the documents most relevant to queries
+    ///
+    /// # Arguments
+    ///
+    /// * `queries` - `&[&str]` Array of queries
+    /// * `top_k` - Maximum number of documents returned for each query
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<Vec<RetrievalHit>>, RustBertError>` documents retrieved for each query, by decreasing final score
+    pub fn search<S>(
+        &self,
+        queries: &[S],
+        top_k: usize,
+    ) -> Result<Vec<Vec<RetrievalHit>>, RustBertError>
+    where
+        S: AsRef<str> + Sync,
+    {
+        if self.config.batch_size == 0 {
+            return Err(RustBertError::InvalidConfigurationError(
+                "batch_size must be strictly greater than 0".to_string(),
+            ));
+        }
+        let candidates = self.index.search(
+            &self.bi_encoder,
+            queries,
+            self.config.num_candidates.max(top_k),
+        )?;
+
+        let pairs = queries
+            .iter()
+            .zip(candidates.iter())
+            .flat_map(|(query, hits)| {
+                hits.iter()
+                    .map(move |hit| (query.as_ref(), hit.text.as_str()))
+            })
+            .collect::<Vec<(&str, &str)>>();
+        let mut cross_encoder_scores = Vec::with_capacity(pairs.len());
+        for batch in pairs.chunks(self.config.batch_size) {
+            cross_encoder_scores.extend(self.cross_encoder.predict_pair_scores(batch)?);
+        }
+
+        let mut cross_encoder_scores = cross_encoder_scores.into_iter();
+        Ok(candidates
+            .into_iter()
+            .map(|hits| {
+                let cross_encoder_scores = cross_encoder_scores
+                    .by_ref()
+                    .take(hits.len())
+                    .collect::<Vec<f64>>();
+                let bi_encoder_scores = hits.iter().map(|hit| hit.score).collect::<Vec<f32>>();
+                let scores = fuse_scores(
+                    self.config.score_fusion,
+                    &bi_encoder_scores,
+                    &cross_encoder_scores,
+                );
+                let mut output = hits
+                    .into_iter()
+                    .zip(cross_encoder_scores)
+                    .zip(scores)
+                    .map(|((hit, cross_encoder_score), score)| RetrievalHit {
+                        corpus_id: hit.corpus_id,
+                        text: hit.text,
+                        score,
+                        bi_encoder_score: hit.score,
+                        cross_encoder_score,
+                    })
+                    .collect::<Vec<RetrievalHit>>();
+                output.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+                output.truncate(top_k);
+                output
+            })
+            .collect())
+    }
+
+    /// Mines hard negatives from the documents of the index for (query, positive) pairs. The
+    /// documents retrieved by the bi-encoder (excluding the positives of the query) are scored by
+    /// the cross-encoder, and the highest scored candidates are kept as negatives, discarding the
+    /// candidates scored too close to the positive (likely unlabeled positives).
+    ///
+    /// # Arguments
+    ///
+    /// * `pairs` - `&[(&str, &str)]` Array of (query, positive document) pairs. Queries with several positives can appear in several pairs.
+    /// * `config` - `HardNegativeMiningConfig` setting the number of candidates and negatives and the false negative filtering
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<TrainingTriple>, RustBertError>` training triples, grouped by input pair
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// # use rust_bert::pipelines::retrieval::{HardNegativeMiningConfig, RetrievalConfig, RetrievalModel};
+    /// # use rust_bert::pipelines::sentence_embeddings::{SentenceEmbeddingsBuilder, SentenceEmbeddingsModelType};
+    /// # use rust_bert::pipelines::sequence_classification::SequenceClassificationModel;
+    /// # let bi_encoder = SentenceEmbeddingsBuilder::remote(SentenceEmbeddingsModelType::AllMiniLmL6V2).create_model()?;
+    /// # let cross_encoder = SequenceClassificationModel::new(Default::default())?;
+    /// let mut model = RetrievalModel::new(bi_encoder, cross_encoder, RetrievalConfig::default());
+    /// model.add_documents(&[
+    ///     "The Eiffel Tower was completed in 1889.",
+    ///     "The Eiffel Tower is 330 metres tall.",
+    ///     "The Statue of Liberty was dedicated in 1886.",
+    /// ])?;
+    /// let triples = model.mine_hard_negatives(
+    ///     &[(
+    ///         "When was the Eiffel Tower built?",
+    ///         "The Eiffel Tower was completed in 1889.",
+    ///     )],
+    ///     &HardNegativeMiningConfig::default(),
+    /// )?;
+    /// let jsonl = triples
+    ///     .iter()
+    ///     .map(serde_json::to_string)
+    ///     .collect::<Result<Vec<String>, _>>()?
+    ///     .join("\n");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn mine_hard_negatives<S, T>(
+        &self,
+        pairs: &[(S, T)],
+        config: &HardNegativeMiningConfig,
+    ) -> Result<Vec<TrainingTriple>, RustBertError>
+    where
+        S: AsRef<str> + Sync,
+        T: AsRef<str>,
+    {
+        if self.config.batch_size == 0 {
+            return Err(RustBertError::InvalidConfigurationError(
+                "batch_size must be strictly greater than 0".to_string(),
+            ));
+        }
+        if config.num_negatives == 0 {
+            return Err(RustBertError::InvalidConfigurationError(
+                "num_negatives must be strictly greater than 0".to_string(),
+            ));
+        }
+        let mut positives: HashMap<&str, HashSet<&str>> = HashMap::new();
+        for (query, positive) in pairs {
+            positives
+                .entry(query.as_ref())
+                .or_default()
+                .insert(positive.as_ref());
+        }
+        let queries = pairs
+            .iter()
+            .map(|(query, _)| query.as_ref())
+            .collect::<Vec<&str>>();
+        let candidates = self
+            .index
+            .search(
+                &self.bi_encoder,
+                &queries,
+                config.num_candidates + config.skip_top,
+            )?
+            .into_iter()
+            .zip(queries.iter())
+            .map(|(hits, query)| {
+                let hits = hits
+                    .into_iter()
+                    .map(|hit| hit.text)
+                    .collect::<Vec<String>>();
+                candidate_negatives(hits, &positives[query], config.skip_top)
+            })
+            .collect::<Vec<Vec<String>>>();
+
+        // Each pair is scored first, followed by its candidates
+        let scored_pairs = pairs
+            .iter()
+            .zip(candidates.iter())
+            .flat_map(|((query, positive), candidates)| {
+                std::iter::once((query.as_ref(), positive.as_ref())).chain(
+                    candidates
+                        .iter()
+                        .map(move |candidate| (query.as_ref(), candidate.as_str())),
+                )
+            })
+            .collect::<Vec<(&str, &str)>>();
+        let mut scores = Vec::with_capacity(scored_pairs.len());
+        for batch in scored_pairs.chunks(self.config.batch_size) {
+            scores.extend(self.cross_encoder.predict_pair_scores(batch)?);
+        }
+
+        let mut scores = scores.into_iter();
+        let mut triples = vec![];
+        for ((query, positive), candidates) in pairs.iter().zip(candidates) {
+            let positive_score = scores.next().unwrap();
+            let scored_candidates = candidates
+                .into_iter()
+                .zip(scores.by_ref())
+                .collect::<Vec<(String, f64)>>();
+            triples.extend(
+                select_negatives(scored_candidates, positive_score, config)
+                    .into_iter()
+                    .map(|(negative, negative_score)| TrainingTriple {
+                        query: query.as_ref().to_string(),
+                        positive: positive.as_ref().to_string(),
+                        negative,
+                        positive_score,
+                        negative_score,
+                    }),
+            );
+        }
+        Ok(triples)
+    }
+}
+
+/// Removes the positives of a query from its bi-encoder candidates and skips the top-ranked
+/// remaining candidates
+fn candidate_negatives(
+    candidates: Vec<String>,
+    positives: &HashSet<&str>,
+    skip_top: usize,
+) -> Vec<String> {
+    candidates
+        .into_iter()
+        .filter(|candidate| !positives.contains(candidate.as_str()))
+        .skip(skip_top)
+        .collect()
+}
+
+/// Selects the hard negatives of a pair among candidates scored by the cross-encoder, by
+/// decreasing score, discarding likely false negatives
+fn select_negatives(
+    mut scored_candidates: Vec<(String, f64)>,
+    positive_score: f64,
+    config: &HardNegativeMiningConfig,
+) -> Vec<(String, f64)> {
+    scored_candidates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+    scored_candidates
+        .into_iter()
+        .filter(|(_, score)| match config.false_negative_margin {
+            Some(margin) => *score < positive_score - margin,
+            None => true,
+        })
+        .take(config.num_negatives)
+        .collect()
+}
+
+/// Combines the bi-encoder and cross-encoder scores of the candidates of a query
+fn fuse_scores(
+    score_fusion: ScoreFusion,
+    bi_encoder_scores: &[f32],
+    cross_encoder_scores: &[f64],
+) -> Vec<f64> {
+    let bi_encoder_scores = bi_encoder_scores
+        .iter()
+        .map(|&score| score as f64)
+        .collect::<Vec<f64>>();
+    match score_fusion {
+        ScoreFusion::CrossEncoder => cross_encoder_scores.to_vec(),
+        ScoreFusion::Weighted { bi_encoder_weight } => min_max_normalize(&bi_encoder_scores)
+            .into_iter()
+            .zip(min_max_normalize(cross_encoder_scores))
+            .map(|(bi_encoder_score, cross_encoder_score)| {
+                bi_encoder_weight * bi_encoder_score
+                    + (1.0 - bi_encoder_weight) * cross_encoder_score
+            })
+            .collect(),
+        ScoreFusion::ReciprocalRank { k } => ranks(&bi_encoder_scores)
+            .into_iter()
+            .zip(ranks(cross_encoder_scores))
+            .map(|(bi_encoder_rank, cross_encoder_rank)| {
+                1.0 / (k + bi_encoder_rank as f64) + 1.0 / (k + cross_encoder_rank as f64)
+            })
+            .collect(),
+    }
+}
+
+fn min_max_normalize(scores: &[f64]) -> Vec<f64> {
+    let min = scores.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = scores.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    scores
+        .iter()
+        .map(|&score| {
+            if max > min {
+                (score - min) / (max - min)
+            } else {
+                1.0
+            }
+        })
+        .collect()
+}
+
+/// 1-based rank of each score, by decreasing score
+fn ranks(scores: &[f64]) -> Vec<usize> {
+    let mut order = (0..scores.len()).collect::<Vec<usize>>();
+    order.sort_by(|&a, &b| scores[b].partial_cmp(&scores[a]).unwrap());
+    let mut ranks = vec![0; scores.len()];
+    for (rank, index) in order.into_iter().enumerate() {
+        ranks[index] = rank + 1;
+    }
+    ranks
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn score_fusion() {
+        let bi_encoder_scores = [0.9, 0.8, 0.5];
+        let cross_encoder_scores = [-2.0, 4.0, 1.0];
+
+        assert_eq!(
+            fuse_scores(
+                ScoreFusion::CrossEncoder,
+                &bi_encoder_scores,
+                &cross_encoder_scores
+            ),
+            cross_encoder_scores
+        );
+
+        let weighted = fuse_scores(
+            ScoreFusion::Weighted {
+                bi_encoder_weight: 0.5,
+            },
+            &bi_encoder_scores,
+            &cross_encoder_scores,
+        );
+        let expected = [0.5, 0.5 * 0.75 + 0.5, 0.25];
+        for (score, expected_score) in weighted.iter().zip(expected.iter()) {
+            assert!((score - expected_score).abs() < 1e-6);
+        }
+
+        assert_eq!(ranks(&[0.1, 0.3, 0.2]), [3, 1, 2]);
+        let reciprocal_rank = fuse_scores(
+            ScoreFusion::ReciprocalRank { k: 60.0 },
+            &bi_encoder_scores,
+            &cross_encoder_scores,
+        );
+        assert!(reciprocal_rank[1] > reciprocal_rank[0]);
+        assert!(reciprocal_rank[0] > reciprocal_rank[2]);
+        assert!((reciprocal_rank[0] - (1.0 / 61.0 + 1.0 / 63.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn hard_negative_selection() {
+        let positives = ["Paris is the capital of France."]
+            .iter()
+            .cloned()
+            .collect::<HashSet<&str>>();
+        let candidates = [
+            "Paris is the capital of France.",
+            "Paris is the most populous city of France.",
+            "Lyon is a city of France.",
+            "Berlin is the capital of Germany.",
+        ]
+        .iter()
+        .map(|candidate| candidate.to_string())
+        .collect::<Vec<String>>();
+        assert_eq!(
+            candidate_negatives(candidates.clone(), &positives, 1),
+            candidates[2..]
+        );
+
+        let scored_candidates = vec![
+            (candidates[1].clone(), 7.5),
+            (candidates[2].clone(), 1.0),
+            (candidates[3].clone(), 2.0),
+        ];
+        let config = HardNegativeMiningConfig {
+            num_negatives: 2,
+            false_negative_margin: Some(1.0),
+            ..Default::default()
+        };
+        let negatives = select_negatives(scored_candidates.clone(), 8.0, &config);
+        assert_eq!(
+            negatives,
+            [(candidates[3].clone(), 2.0), (candidates[2].clone(), 1.0)]
+        );
+        let config = HardNegativeMiningConfig {
+            num_negatives: 1,
+            false_negative_margin: None,
+            ..Default::default()
+        };
+        let negatives = select_negatives(scored_candidates, 8.0, &config);
+        assert_eq!(negatives, [(candidates[1].clone(), 7.5)]);
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Saliency of the input tokens of classification pipelines
+//! Attributes the prediction of a classifier to the tokens of its input, to display which parts
+//! of a document a label was predicted from. The following attribution methods are available:
+//! - `SaliencyMethod::GradientTimesInput` multiplies the input embeddings of each token by the
+//!   gradient of the logit of the predicted label with respect to these embeddings. The scores are
+//!   signed: positive scores support the predicted label, negative scores oppose it.
+//! - `SaliencyMethod::AttentionRollout` ([Abnar & Zuidema, 2020][rollout]) propagates the
+//!   attention weights (averaged over the heads, mixed with the residual connections) through the
+//!   layers of the model and returns the attention of the first (classification) token to each
+//!   token of the input. The scores are positive and do not depend on the predicted label. The
+//!   model configuration must enable `output_attentions`.
+//!
+//! The scores of an input are normalized so that their absolute values sum to 1.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::saliency::SaliencyMethod;
+//! use rust_bert::pipelines::sequence_classification::SequenceClassificationModel;
+//!
+//! let model = SequenceClassificationModel::new(Default::default())?;
+//! let saliency = model.saliency(
+//!     &["The plot was dull but the acting was superb."],
+//!     SaliencyMethod::GradientTimesInput,
+//! )?;
+//! for token in &saliency[0].tokens {
+//!     println!("{}: {:.3}", token.token, token.score);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [rollout]: https://arxiv.org/abs/2005.00928
+
+use rust_tokenizers::Offset;
+use serde::{Deserialize, Serialize};
+use tch::{Kind, Tensor};
+
+use crate::pipelines::sequence_classification::Label;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// # Method used to attribute a prediction to the input tokens
+pub enum SaliencyMethod {
+    /// Input embeddings multiplied by the gradient of the predicted logit (signed scores)
+    GradientTimesInput,
+    /// Attention of the classification token propagated through the layers (positive scores)
+    AttentionRollout,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// # Importance of an input token for a prediction
+pub struct TokenSaliency {
+    /// Token, as produced by the tokenizer
+    pub token: String,
+    /// Position of the token in the input text (characters), `None` for special tokens
+    pub offset: Option<Offset>,
+    /// Importance of the token (the absolute values of the scores of an input sum to 1)
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Saliency of the tokens of an input for the predicted label
+pub struct Saliency {
+    /// Label predicted for the input
+    pub label: Label,
+    /// Importance of each token of the (tokenized) input
+    pub tokens: Vec<TokenSaliency>,
+}
+
+/// Gradient x input scores, reduced over the embedding dimension and normalized.
+///
+/// # Arguments
+///
+/// * `input_embeddings` - input embeddings of shape (*sequence_length*, *embedding_dim*)
+/// * `gradients` - gradients of the target logit with respect to the input embeddings, of the same shape
+///
+/// # Returns
+///
+/// * `Tensor` of shape (*sequence_length*) with the signed token scores
+pub(crate) fn gradient_times_input(input_embeddings: &Tensor, gradients: &Tensor) -> Tensor {
+    let scores = (input_embeddings * gradients)
+        .sum_dim_intlist(&[-1], false, Kind::Float)
+        .to_kind(Kind::Float);
+    normalize_scores(&scores)
+}
+
+/// Attention rollout from the first token of the input to all tokens.
+///
+/// # Arguments
+///
+/// * `attentions` - attention weights of each layer, of shape (*num_heads*, *sequence_length*, *sequence_length*)
+///
+/// # Returns
+///
+/// * `Tensor` of shape (*sequence_length*) with the positive token scores
+pub(crate) fn attention_rollout(attentions: &[Tensor]) -> Tensor {
+    let mut rollout: Option<Tensor> = None;
+    for attention in attentions {
+        let attention = attention
+            .to_kind(Kind::Float)
+            .mean_dim(&[0], false, Kind::Float);
+        let sequence_length = attention.size()[0];
+        // Residual connections: the output of a layer mixes the attention output with its input
+        let attention =
+            (attention + Tensor::eye(sequence_length, (Kind::Float, attentions[0].device()))) / 2.0;
+        let attention = &attention / attention.sum_dim_intlist(&[-1], true, Kind::Float);
+        rollout = Some(match rollout {
+            Some(rollout) => attention.matmul(&rollout),
+            None => attention,
+        });
+    }
+    match rollout {
+        Some(rollout) => normalize_scores(&rollout.get(0)),
+        None => Tensor::of_slice::<f32>(&[]),
+    }
+}
+
+fn normalize_scores(scores: &Tensor) -> Tensor {
+    scores / scores.abs().sum(Kind::Float).clamp_min(1e-12)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tch::Device;
+
+    #[test]
+    fn token_scores() {
+        let embeddings = Tensor::of_slice(&[1f32, 2.0, 0.0, 1.0, -1.0, 0.0]).view((3, 2));
+        let gradients = Tensor::of_slice(&[1f32, 1.0, 5.0, 0.0, 1.0, 0.0]).view((3, 2));
+        let scores = Vec::<f32>::from(gradient_times_input(&embeddings, &gradients));
+        let expected = [0.75, 0.0, -0.25];
+        for (score, expected) in scores.iter().zip(expected.iter()) {
+            assert!((score - expected).abs() < 1e-6);
+        }
+
+        //    Full attention to the second token, then uniform attention over the 2 tokens
+        let second = Tensor::of_slice(&[0f32, 1.0, 0.0, 1.0]).view((1, 2, 2));
+        let uniform = Tensor::full(&[2, 2, 2], 0.5, (Kind::Float, Device::Cpu));
+        let scores = Vec::<f32>::from(attention_rollout(&[second, uniform]));
+        //    With residuals, layer 1: [[0.5, 0.5], [0, 1]], layer 2: [[0.75, 0.25], [0.25, 0.75]]
+        let expected = [0.375, 0.625];
+        for (score, expected) in scores.iter().zip(expected.iter()) {
+            assert!((score - expected).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! # Embedding arithmetic
+//!
+//! Gradient-free utilities combining embeddings, to explore and debug the embedding space of a
+//! model: averaging, linear and spherical interpolation, analogies (`b - a + c`) and projections
+//! onto (or removal of) a direction, for example a "formality" direction computed as the
+//! difference between the embeddings of a formal and of a casual sentence.
+//!
+//! The input embeddings of a `SentenceEmbeddingsModel` can also be inspected at the vocabulary
+//! level: `SentenceEmbeddingsModel::token_embeddings` returns the rows of the (input) embedding
+//! matrix of tokens and `SentenceEmbeddingsModel::nearest_tokens` retrieves the tokens whose
+//! embeddings are the closest to arbitrary vectors, for example the result of an analogy:
+//!
+//! ```no_run
+//! use rust_bert::pipelines::sentence_embeddings::arithmetic::analogy;
+//! use rust_bert::pipelines::sentence_embeddings::SentenceEmbeddingsBuilder;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let model = SentenceEmbeddingsBuilder::local("local/path/to/all-MiniLM-L12-v2").create_model()?;
+//!
+//! let embeddings = model.token_embeddings(&["man", "king", "woman"])?;
+//! let query = analogy(&embeddings[0], &embeddings[1], &embeddings[2])?;
+//! for neighbour in &model.nearest_tokens(&[query], 5)?[0] {
+//!     println!("{} ({:.3})", neighbour.token, neighbour.score);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The token embeddings live in the input space of the transformer: sentence embeddings (computed
+//! after the transformer layers, the pooling and the optional dense layer) are generally not
+//! comparable to them and only give a rough indication of the related tokens.
+
+use serde::{Deserialize, Serialize};
+
+use crate::pipelines::sentence_embeddings::Embedding;
+use crate::RustBertError;
+
+/// Vocabulary token close to a query embedding, returned by `SentenceEmbeddingsModel::nearest_tokens`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenNeighbour {
+    /// Id of the token in the vocabulary
+    pub token_id: i64,
+    /// Decoded token
+    pub token: String,
+    /// Cosine similarity between the query and the token embedding
+    pub score: f32,
+}
+
+/// Averages embeddings of the same dimension
+///
+/// # Arguments
+///
+/// * `embeddings` - embeddings to average
+///
+/// # Returns
+///
+/// * `Result<Embedding, RustBertError>` - mean of the embeddings, error if no embedding is given or if their dimensions differ
+pub fn mean<E: AsRef<[f32]>>(embeddings: &[E]) -> Result<Embedding, RustBertError> {
+    weighted_mean(embeddings, &vec![1.0; embeddings.len()])
+}
+
+/// Averages embeddings of the same dimension, weighting each embedding
+///
+/// # Arguments
+///
+/// * `embeddings` - embeddings to average
+/// * `weights` - weight of each embedding (the weights are normalized and must not sum to 0)
+///
+/// # Returns
+///
+/// * `Result<Embedding, RustBertError>` - weighted mean of the embeddings
+pub fn weighted_mean<E: AsRef<[f32]>>(
+    embeddings: &[E],
+    weights: &[f32],
+) -> Result<Embedding, RustBertError> {
+    if embeddings.len() != weights.len() {
+        return Err(RustBertError::ValueError(format!(
+            "Got {} weights for {} embeddings",
+            weights.len(),
+            embeddings.len()
+        )));
+    }
+    let dimension = match embeddings.first() {
+        Some(embedding) => embedding.as_ref().len(),
+        None => {
+            return Err(RustBertError::ValueError(
+                "Cannot average an empty set of embeddings".to_string(),
+            ));
+        }
+    };
+    let total_weight: f32 = weights.iter().sum();
+    if total_weight.abs() < f32::EPSILON {
+        return Err(RustBertError::ValueError(
+            "The weights of the embeddings sum to 0".to_string(),
+        ));
+    }
+    let mut output = vec![0f32; dimension];
+    for (embedding, weight) in embeddings.iter().zip(weights.iter()) {
+        let embedding = embedding.as_ref();
+        check_dimensions(&output, embedding)?;
+        output
+            .iter_mut()
+            .zip(embedding.iter())
+            .for_each(|(output, value)| *output += weight * value / total_weight);
+    }
+    Ok(output)
+}
+
+/// Linear interpolation between two embeddings: `(1 - t) * a + t * b`
+///
+/// # Arguments
+///
+/// * `a` - embedding returned for `t = 0`
+/// * `b` - embedding returned for `t = 1`
+/// * `t` - interpolation factor (values outside of [0, 1] extrapolate)
+pub fn interpolate(a: &[f32], b: &[f32], t: f32) -> Result<Embedding, RustBertError> {
+    check_dimensions(a, b)?;
+    Ok(a.iter()
+        .zip(b.iter())
+        .map(|(a, b)| (1.0 - t) * a + t * b)
+        .collect())
+}
+
+/// Spherical linear interpolation between two embeddings, following the arc between their
+/// directions. The norm of the output is interpolated linearly between the norms of `a` and `b`,
+/// so that interpolating normalized embeddings returns normalized embeddings. Falls back to the
+/// linear interpolation when the embeddings are (anti-)colinear.
+///
+/// # Arguments
+///
+/// * `a` - embedding returned for `t = 0`
+/// * `b` - embedding returned for `t = 1`
+/// * `t` - interpolation factor between 0 and 1
+pub fn slerp(a: &[f32], b: &[f32], t: f32) -> Result<Embedding, RustBertError> {
+    check_dimensions(a, b)?;
+    let (norm_a, norm_b) = (norm(a), norm(b));
+    if norm_a < f32::EPSILON || norm_b < f32::EPSILON {
+        return interpolate(a, b, t);
+    }
+    let cosine = (dot_product(a, b) / (norm_a * norm_b)).clamp(-1.0, 1.0);
+    let angle = cosine.acos();
+    if angle.sin().abs() < 1e-6 {
+        return interpolate(a, b, t);
+    }
+    let weight_a = ((1.0 - t) * angle).sin() / angle.sin() / norm_a;
+    let weight_b = (t * angle).sin() / angle.sin() / norm_b;
+    let output_norm = (1.0 - t) * norm_a + t * norm_b;
+    Ok(a.iter()
+        .zip(b.iter())
+        .map(|(a, b)| (weight_a * a + weight_b * b) * output_norm)
+        .collect())
+}
+
+/// Analogy `b - a + c` ("`a` is to `b` what `c` is to ?"), e.g. `king - man + woman`
+pub fn analogy(a: &[f32], b: &[f32], c: &[f32]) -> Result<Embedding, RustBertError> {
+    check_dimensions(a, b)?;
+    check_dimensions(a, c)?;
+    Ok(a.iter()
+        .zip(b.iter())
+        .zip(c.iter())
+        .map(|((a, b), c)| b - a + c)
+        .collect())
+}
+
+/// Projects an embedding onto a direction, returning the component of the embedding along the
+/// direction
+pub fn project(embedding: &[f32], direction: &[f32]) -> Result<Embedding, RustBertError> {
+    check_dimensions(embedding, direction)?;
+    let squared_norm = dot_product(direction, direction);
+    if squared_norm < f32::EPSILON {
+        return Err(RustBertError::ValueError(
+            "Cannot project onto a null direction".to_string(),
+        ));
+    }
+    let scale = dot_product(embedding, direction) / squared_norm;
+    Ok(direction.iter().map(|value| scale * value).collect())
+}
+
+/// Removes the component of an embedding along a direction (projection onto the orthogonal
+/// complement of the direction), e.g. to neutralize a topic or style direction
+pub fn reject(embedding: &[f32], direction: &[f32]) -> Result<Embedding, RustBertError> {
+    let projection = project(embedding, direction)?;
+    Ok(embedding
+        .iter()
+        .zip(projection.iter())
+        .map(|(value, projection)| value - projection)
+        .collect())
+}
+
+/// Cosine similarity between two embeddings (0 if one of them is null)
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> Result<f32, RustBertError> {
+    check_dimensions(a, b)?;
+    Ok(dot_product(a, b) / (norm(a) * norm(b)).max(1e-12))
+}
+
+fn check_dimensions(a: &[f32], b: &[f32]) -> Result<(), RustBertError> {
+    if a.len() != b.len() {
+        return Err(RustBertError::ValueError(format!(
+            "Embedding dimensions {} and {} do not match",
+            a.len(),
+            b.len()
+        )));
+    }
+    Ok(())
+}
+
+fn dot_product(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+}
+
+fn norm(embedding: &[f32]) -> f32 {
+    dot_product(embedding, embedding).sqrt()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (actual, expected) in actual.iter().zip(expected.iter()) {
+            assert!((actual - expected).abs() < 1e-5, "{:?}", actual);
+        }
+    }
+
+    #[test]
+    fn embedding_arithmetic() -> Result<(), RustBertError> {
+        let (x, y) = (vec![1.0, 0.0], vec![0.0, 2.0]);
+        assert_close(&mean(&[&x, &y])?, &[0.5, 1.0]);
+        assert_close(&weighted_mean(&[&x, &y], &[3.0, 1.0])?, &[0.75, 0.5]);
+        assert_close(&interpolate(&x, &y, 0.25)?, &[0.75, 0.5]);
+        assert_close(&analogy(&x, &y, &[1.0, 1.0])?, &[0.0, 3.0]);
+
+        //    Half-way along the arc between orthogonal unit vectors
+        let diagonal = slerp(&x, &[0.0, 1.0], 0.5)?;
+        assert_close(&diagonal, &[0.5f32.sqrt(), 0.5f32.sqrt()]);
+        assert_close(&slerp(&x, &y, 1.0)?, &y);
+
+        let embedding = [3.0, 4.0];
+        assert_close(&project(&embedding, &y)?, &[0.0, 4.0]);
+        assert_close(&reject(&embedding, &y)?, &[3.0, 0.0]);
+        assert!((cosine_similarity(&embedding, &x)? - 0.6).abs() < 1e-6);
+
+        assert!(mean::<Embedding>(&[]).is_err());
+        assert!(mean(&[vec![1.0], vec![1.0, 2.0]]).is_err());
+        assert!(weighted_mean(&[&x, &y], &[1.0, -1.0]).is_err());
+        assert!(project(&embedding, &[0.0, 0.0]).is_err());
+        assert!(analogy(&x, &y, &[1.0]).is_err());
+        Ok(())
+    }
+}
@@ -26,34 +26,49 @@ use crate::{
     t5::{T5ConfigResources, T5ModelResources, T5VocabResources},
 };
 
+#[derive(Serialize, Deserialize)]
 /// # Configuration for sentence embeddings
 ///
 /// Contains information regarding the transformer model to load, the optional extra
 /// layers, and device to place the model on.
 pub struct SentenceEmbeddingsConfig {
     /// Modules configuration resource, contains layers definition
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub modules_config_resource: Box<dyn ResourceProvider + Send>,
     /// Transformer model type
     pub transformer_type: ModelType,
     /// Transformer model configuration resource
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub transformer_config_resource: Box<dyn ResourceProvider + Send>,
     /// Transformer weights resource
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub transformer_weights_resource: Box<dyn ResourceProvider + Send>,
     /// Pooling layer configuration resource
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub pooling_config_resource: Box<dyn ResourceProvider + Send>,
     /// Optional dense layer configuration resource
+    #[serde(default, with = "crate::common::serde_utils::optional_resource")]
     pub dense_config_resource: Option<Box<dyn ResourceProvider + Send>>,
     /// Optional dense layer weights resource
+    #[serde(default, with = "crate::common::serde_utils::optional_resource")]
     pub dense_weights_resource: Option<Box<dyn ResourceProvider + Send>>,
     /// Sentence BERT specific configuration resource
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub sentence_bert_config_resource: Box<dyn ResourceProvider + Send>,
     /// Transformer's tokenizer configuration resource
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub tokenizer_config_resource: Box<dyn ResourceProvider + Send>,
     /// Transformer's tokenizer vocab resource
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub tokenizer_vocab_resource: Box<dyn ResourceProvider + Send>,
     /// Optional transformer's tokenizer merges resource
+    #[serde(default, with = "crate::common::serde_utils::optional_resource")]
     pub tokenizer_merges_resource: Option<Box<dyn ResourceProvider + Send>>,
     /// Device to place the transformer model on
+    #[serde(
+        with = "crate::common::serde_utils::device",
+        default = "crate::common::serde_utils::device::default"
+    )]
     pub device: Device,
 }
 
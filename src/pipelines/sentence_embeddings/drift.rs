@@ -0,0 +1,363 @@
+//! # Embedding drift monitoring
+//!
+//! Retrieval systems assume that the embeddings of the queries and documents they receive follow
+//! the distribution of the embeddings they were indexed (or fine-tuned) with. The `DriftMonitor`
+//! tracks the centroid and norm statistics of the embeddings computed by a model over windows of
+//! embeddings, and compares each completed window to a reference distribution (by default, the
+//! first window observed). A window is flagged as drifted when its centroid or its mean norm moves
+//! away from the reference by more than the configured thresholds, which can be used to trigger
+//! a re-indexing or a re-training of the model.
+//!
+//! A monitor can be attached to a `SentenceEmbeddingsModel`, observing all embeddings computed by
+//! the model:
+//!
+//! ```no_run
+//! use rust_bert::pipelines::sentence_embeddings::drift::{DriftMonitor, DriftMonitorConfig};
+//! use rust_bert::pipelines::sentence_embeddings::{
+//!     SentenceEmbeddingsBuilder, SentenceEmbeddingsModelType,
+//! };
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let monitor = DriftMonitor::new(DriftMonitorConfig {
+//!     window_size: 500,
+//!     ..Default::default()
+//! })?;
+//! let model = SentenceEmbeddingsBuilder::remote(SentenceEmbeddingsModelType::AllMiniLmL12V2)
+//!     .create_model()?
+//!     .with_drift_monitor(monitor);
+//!
+//! let embeddings = model.encode(&["This is an example sentence"])?;
+//! if let Some(report) = model.drift_report() {
+//!     if report.drifted {
+//!         println!("Embedding drift detected: {:?}", report);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::RustBertError;
+
+/// # Configuration for the embedding drift monitor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DriftMonitorConfig {
+    /// Number of embeddings in a monitoring window, compared to the reference once complete (default: 1000)
+    pub window_size: usize,
+    /// Cosine distance between the window and reference centroids above which the window is flagged as drifted (default: 0.1)
+    pub centroid_threshold: f64,
+    /// Relative change of the mean embedding norm above which the window is flagged as drifted (default: 0.1)
+    pub norm_threshold: f64,
+}
+
+impl Default for DriftMonitorConfig {
+    fn default() -> DriftMonitorConfig {
+        DriftMonitorConfig {
+            window_size: 1000,
+            centroid_threshold: 0.1,
+            norm_threshold: 0.1,
+        }
+    }
+}
+
+/// # Running statistics of a set of embeddings
+/// Serializable, allowing to persist the reference distribution of a corpus (e.g. along with its
+/// index) and to monitor a model against it after a restart.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EmbeddingStatistics {
+    count: usize,
+    centroid_sum: Vec<f64>,
+    norm_sum: f64,
+    squared_norm_sum: f64,
+}
+
+impl EmbeddingStatistics {
+    /// Creates empty statistics
+    pub fn new() -> EmbeddingStatistics {
+        EmbeddingStatistics::default()
+    }
+
+    /// Computes the statistics of a set of embeddings
+    pub fn from_embeddings<E>(embeddings: &[E]) -> Result<EmbeddingStatistics, RustBertError>
+    where
+        E: AsRef<[f32]>,
+    {
+        let mut statistics = EmbeddingStatistics::new();
+        statistics.update(embeddings)?;
+        Ok(statistics)
+    }
+
+    /// Adds embeddings to the statistics. Returns an error if their dimension does not match the
+    /// dimension of the embeddings already observed.
+    pub fn update<E>(&mut self, embeddings: &[E]) -> Result<(), RustBertError>
+    where
+        E: AsRef<[f32]>,
+    {
+        for embedding in embeddings.iter().map(AsRef::as_ref) {
+            if self.count == 0 {
+                self.centroid_sum = vec![0f64; embedding.len()];
+            } else if embedding.len() != self.centroid_sum.len() {
+                return Err(RustBertError::ValueError(format!(
+                    "Embedding dimension {} does not match the monitored dimension {}",
+                    embedding.len(),
+                    self.centroid_sum.len()
+                )));
+            }
+            let squared_norm = embedding
+                .iter()
+                .map(|&value| (value as f64).powi(2))
+                .sum::<f64>();
+            for (sum, &value) in self.centroid_sum.iter_mut().zip(embedding.iter()) {
+                *sum += value as f64;
+            }
+            self.norm_sum += squared_norm.sqrt();
+            self.squared_norm_sum += squared_norm;
+            self.count += 1;
+        }
+        Ok(())
+    }
+
+    /// Number of embeddings observed
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Mean of the embeddings observed (empty if no embedding was observed)
+    pub fn centroid(&self) -> Vec<f64> {
+        self.centroid_sum
+            .iter()
+            .map(|sum| sum / self.count as f64)
+            .collect()
+    }
+
+    /// Mean L2 norm of the embeddings observed
+    pub fn mean_norm(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.norm_sum / self.count as f64
+        }
+    }
+
+    /// Standard deviation of the L2 norm of the embeddings observed
+    pub fn norm_std(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            let mean_norm = self.mean_norm();
+            (self.squared_norm_sum / self.count as f64 - mean_norm * mean_norm)
+                .max(0.0)
+                .sqrt()
+        }
+    }
+}
+
+/// # Comparison of a window of embeddings to the reference distribution
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DriftReport {
+    /// Cosine distance between the centroids of the window and of the reference
+    pub centroid_distance: f64,
+    /// Relative change of the mean embedding norm of the window compared to the reference
+    pub norm_shift: f64,
+    /// Flag indicating if the centroid distance or the norm shift exceed their threshold
+    pub drifted: bool,
+    /// Statistics of the embeddings of the window
+    pub window: EmbeddingStatistics,
+}
+
+/// # Monitor of the distribution of embeddings over time
+pub struct DriftMonitor {
+    config: DriftMonitorConfig,
+    reference: Option<EmbeddingStatistics>,
+    window: EmbeddingStatistics,
+    last_report: Option<DriftReport>,
+    num_drifted_windows: usize,
+}
+
+impl DriftMonitor {
+    /// Creates a new monitor, using the first window of embeddings observed as reference
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `DriftMonitorConfig` setting the window size and the drift thresholds
+    pub fn new(config: DriftMonitorConfig) -> Result<DriftMonitor, RustBertError> {
+        if config.window_size == 0 {
+            return Err(RustBertError::InvalidConfigurationError(
+                "window_size must be strictly greater than 0".to_string(),
+            ));
+        }
+        if (config.centroid_threshold < 0.0) | (config.norm_threshold < 0.0) {
+            return Err(RustBertError::InvalidConfigurationError(
+                "Drift thresholds must be positive".to_string(),
+            ));
+        }
+        Ok(DriftMonitor {
+            config,
+            reference: None,
+            window: EmbeddingStatistics::new(),
+            last_report: None,
+            num_drifted_windows: 0,
+        })
+    }
+
+    /// Sets the reference distribution the windows are compared to (e.g. the statistics of the
+    /// indexed corpus), instead of the first window observed
+    ///
+    /// # Arguments
+    ///
+    /// * `reference` - `EmbeddingStatistics` of the reference distribution
+    pub fn with_reference(mut self, reference: EmbeddingStatistics) -> DriftMonitor {
+        self.reference = Some(reference).filter(|reference| reference.count() > 0);
+        self
+    }
+
+    /// Adds embeddings to the current window. Windows are compared to the reference as soon as
+    /// they are complete, the reports of the windows completed by these embeddings are returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `embeddings` - embeddings computed by the monitored model
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<DriftReport>` reports of the windows completed (empty while the current window is not complete)
+    pub fn observe<E>(&mut self, embeddings: &[E]) -> Result<Vec<DriftReport>, RustBertError>
+    where
+        E: AsRef<[f32]>,
+    {
+        let mut reports = vec![];
+        let mut remaining = embeddings;
+        while !remaining.is_empty() {
+            let split = remaining
+                .len()
+                .min(self.config.window_size - self.window.count());
+            let (batch, rest) = remaining.split_at(split);
+            self.window.update(batch)?;
+            remaining = rest;
+            if self.window.count() == self.config.window_size {
+                let window = std::mem::take(&mut self.window);
+                match &self.reference {
+                    Some(reference) => {
+                        let report = self.compare(reference, window)?;
+                        if report.drifted {
+                            self.num_drifted_windows += 1;
+                        }
+                        self.last_report = Some(report.clone());
+                        reports.push(report);
+                    }
+                    None => self.reference = Some(window),
+                }
+            }
+        }
+        Ok(reports)
+    }
+
+    fn compare(
+        &self,
+        reference: &EmbeddingStatistics,
+        window: EmbeddingStatistics,
+    ) -> Result<DriftReport, RustBertError> {
+        let reference_centroid = reference.centroid();
+        let window_centroid = window.centroid();
+        if reference_centroid.len() != window_centroid.len() {
+            return Err(RustBertError::ValueError(format!(
+                "Embedding dimension {} does not match the reference dimension {}",
+                window_centroid.len(),
+                reference_centroid.len()
+            )));
+        }
+        let centroid_distance = 1.0 - cosine_similarity(&reference_centroid, &window_centroid);
+        let norm_shift =
+            (window.mean_norm() - reference.mean_norm()).abs() / reference.mean_norm().max(1e-12);
+        Ok(DriftReport {
+            centroid_distance,
+            norm_shift,
+            drifted: (centroid_distance > self.config.centroid_threshold)
+                | (norm_shift > self.config.norm_threshold),
+            window,
+        })
+    }
+
+    /// Returns the reference distribution, if set or once the first window is complete
+    pub fn reference(&self) -> Option<&EmbeddingStatistics> {
+        self.reference.as_ref()
+    }
+
+    /// Returns the report of the last window compared to the reference
+    pub fn last_report(&self) -> Option<&DriftReport> {
+        self.last_report.as_ref()
+    }
+
+    /// Number of windows flagged as drifted since the creation of the monitor
+    pub fn num_drifted_windows(&self) -> usize {
+        self.num_drifted_windows
+    }
+}
+
+fn cosine_similarity(a: &[f64], b: &[f64]) -> f64 {
+    let dot_product = a.iter().zip(b.iter()).map(|(a, b)| a * b).sum::<f64>();
+    let norm_a = a.iter().map(|a| a * a).sum::<f64>().sqrt();
+    let norm_b = b.iter().map(|b| b * b).sum::<f64>().sqrt();
+    dot_product / (norm_a * norm_b).max(1e-12)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn embedding_statistics() -> Result<(), RustBertError> {
+        let statistics = EmbeddingStatistics::from_embeddings(&[vec![3.0, 4.0], vec![1.0, 0.0]])?;
+        assert_eq!(statistics.count(), 2);
+        assert_eq!(statistics.centroid(), vec![2.0, 2.0]);
+        assert!((statistics.mean_norm() - 3.0).abs() < 1e-9);
+        assert!((statistics.norm_std() - 2.0).abs() < 1e-9);
+
+        let mut statistics = statistics;
+        assert!(statistics.update(&[vec![1.0, 0.0, 0.0]]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn drift_detection() -> Result<(), RustBertError> {
+        let mut monitor = DriftMonitor::new(DriftMonitorConfig {
+            window_size: 2,
+            ..Default::default()
+        })?;
+
+        // The first window sets the reference
+        let reports = monitor.observe(&[vec![1.0, 0.1], vec![1.0, -0.1]])?;
+        assert!(reports.is_empty());
+        assert_eq!(monitor.reference().unwrap().count(), 2);
+
+        // Similar windows do not drift, a window may be completed across calls
+        assert!(monitor.observe(&[vec![1.0, 0.05]])?.is_empty());
+        let reports = monitor.observe(&[vec![1.0, -0.05], vec![0.0, 1.0], vec![0.1, 1.0]])?;
+        assert_eq!(reports.len(), 2);
+        assert!(!reports[0].drifted);
+        assert!(reports[0].centroid_distance < 1e-9);
+        assert!(reports[1].drifted);
+        assert!(reports[1].centroid_distance > 0.9);
+        assert_eq!(monitor.num_drifted_windows(), 1);
+        assert!(monitor.last_report().unwrap().drifted);
+
+        // Norm shifts are flagged for identical directions
+        let mut monitor = DriftMonitor::new(DriftMonitorConfig {
+            window_size: 1,
+            ..Default::default()
+        })?
+        .with_reference(EmbeddingStatistics::from_embeddings(&[vec![1.0, 0.0]])?);
+        let reports = monitor.observe(&[vec![1.05, 0.0], vec![2.0, 0.0]])?;
+        assert!(!reports[0].drifted);
+        assert!(reports[1].drifted);
+        assert!((reports[1].norm_shift - 1.0).abs() < 1e-9);
+
+        assert!(DriftMonitor::new(DriftMonitorConfig {
+            window_size: 0,
+            ..Default::default()
+        })
+        .is_err());
+        Ok(())
+    }
+}
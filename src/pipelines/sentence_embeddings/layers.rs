@@ -12,13 +12,23 @@ pub struct PoolingConfig {
     /// Dimensions for the word embeddings
     pub word_embedding_dimension: i64,
     /// Use the first token (CLS token) as text representations
+    #[serde(default)]
     pub pooling_mode_cls_token: bool,
     /// Use max in each dimension over all tokens
+    #[serde(default)]
     pub pooling_mode_max_tokens: bool,
     /// Perform mean-pooling
+    #[serde(default)]
     pub pooling_mode_mean_tokens: bool,
     /// Perform mean-pooling, but devide by sqrt(input_length)
+    #[serde(default)]
     pub pooling_mode_mean_sqrt_len_tokens: bool,
+    /// Perform position-weighted mean pooling (later tokens having a higher weight)
+    #[serde(default)]
+    pub pooling_mode_weightedmean_tokens: bool,
+    /// Use the last (non-padding) token as text representation
+    #[serde(default)]
+    pub pooling_mode_lasttoken: bool,
 }
 
 impl Config for PoolingConfig {}
@@ -48,13 +58,13 @@ impl Pooling {
             let input_mask_expanded = attention_mask.unsqueeze(-1).expand_as(&token_embeddings);
             // Set padding tokens to large negative value
             token_embeddings = token_embeddings.masked_fill_(&input_mask_expanded.eq(0), -1e9);
-            let max_over_time = token_embeddings.max_dim(1, true).0;
+            let max_over_time = token_embeddings.max_dim(1, false).0;
             output_vectors.push(max_over_time);
         }
 
         if self.conf.pooling_mode_mean_tokens || self.conf.pooling_mode_mean_sqrt_len_tokens {
             let input_mask_expanded = attention_mask.unsqueeze(-1).expand_as(&token_embeddings);
-            let sum_embeddings = (token_embeddings * &input_mask_expanded).sum_dim_intlist(
+            let sum_embeddings = (&token_embeddings * &input_mask_expanded).sum_dim_intlist(
                 [1].as_slice(),
                 false,
                 Kind::Float,
@@ -70,6 +80,36 @@ impl Pooling {
             }
         }
 
+        if self.conf.pooling_mode_weightedmean_tokens {
+            let sequence_length = token_embeddings.size()[1];
+            // Weights are the (1-based) positions of the tokens, padding tokens being ignored
+            let weights = (Tensor::arange(sequence_length, (Kind::Float, attention_mask.device()))
+                + 1.0)
+                .unsqueeze(0)
+                * attention_mask.to_kind(Kind::Float);
+            let weights = weights.unsqueeze(-1).expand_as(&token_embeddings);
+            let sum_embeddings =
+                (&token_embeddings * &weights).sum_dim_intlist([1].as_slice(), false, Kind::Float);
+            let sum_weights = weights
+                .sum_dim_intlist([1].as_slice(), false, Kind::Float)
+                .clamp_min(10e-9);
+            output_vectors.push(sum_embeddings / sum_weights);
+        }
+
+        if self.conf.pooling_mode_lasttoken {
+            let last_token_index =
+                (attention_mask.sum_dim_intlist([1].as_slice(), false, Kind::Int64) - 1)
+                    .clamp_min(0);
+            let last_token_index = last_token_index
+                .view((-1, 1, 1))
+                .expand(&[-1, 1, *token_embeddings.size().last().unwrap()], true);
+            output_vectors.push(
+                token_embeddings
+                    .gather(1, &last_token_index, false)
+                    .squeeze_dim(1),
+            );
+        }
+
         Tensor::cat(&output_vectors, 1)
     }
 }
@@ -27,12 +27,31 @@
 //! # Ok(())
 //! # }
 //! ```
+//!
+//! Embeddings can be indexed and searched with an [`EmbeddingIndex`](search::EmbeddingIndex),
+//! including across languages for multilingual models with aligned embedding spaces such as
+//! LaBSE (see the [`search`](search) module).
+//!
+//! Documents longer than the maximum sequence length of the model can be encoded with
+//! `SentenceEmbeddingsModel::encode_documents`: documents are split in chunks that are encoded
+//! separately and pooled into a single embedding (see `DocumentEmbeddingsConfig`).
+//!
+//! The distribution of the embeddings computed by a model can be monitored over time with a
+//! [`DriftMonitor`](drift::DriftMonitor), flagging drifts of the inputs (see the [`drift`](drift)
+//! module).
+//!
+//! Embeddings can be averaged, interpolated and projected, and compared to the embeddings of the
+//! vocabulary tokens with `SentenceEmbeddingsModel::nearest_tokens` (see the
+//! [`arithmetic`](arithmetic) module).
 
+pub mod arithmetic;
 pub mod builder;
 mod config;
+pub mod drift;
 pub mod layers;
 mod pipeline;
 mod resources;
+pub mod search;
 
 pub use builder::SentenceEmbeddingsBuilder;
 pub use config::{
@@ -41,6 +60,7 @@ pub use config::{
     SentenceEmbeddingsTokenizerConfig,
 };
 pub use pipeline::{
+    DocumentEmbeddingsConfig, DocumentPooling, SentenceEmbeddingsEncodeOptions,
     SentenceEmbeddingsModel, SentenceEmbeddingsModelOuput, SentenceEmbeddingsOption,
     SentenceEmbeddingsTokenizerOuput,
 };
@@ -51,6 +71,7 @@ pub use resources::{
     SentenceEmbeddingsModulesConfigResources, SentenceEmbeddingsPoolingConfigResources,
     SentenceEmbeddingsTokenizerConfigResources,
 };
+pub use search::{EmbeddingIndex, SearchHit};
 
 /// Length = sequence length
 pub type Attention = Vec<f32>;
@@ -1,19 +1,28 @@
 use std::borrow::Borrow;
 use std::convert::TryInto;
+use std::sync::Mutex;
+use std::time::Instant;
 
 use rust_tokenizers::tokenizer::TruncationStrategy;
-use tch::{nn, Tensor};
+use rust_tokenizers::TokenIdsWithOffsets;
+use serde::{Deserialize, Serialize};
+use tch::{nn, Device, Kind, Tensor};
 
 use crate::albert::AlbertForSentenceEmbeddings;
 use crate::bert::BertForSentenceEmbeddings;
+use crate::common::metrics;
+use crate::common::trace::trace_span;
 use crate::distilbert::DistilBertForSentenceEmbeddings;
 use crate::pipelines::common::{ConfigOption, ModelType, TokenizerOption};
+use crate::pipelines::sentence_embeddings::arithmetic::TokenNeighbour;
+use crate::pipelines::sentence_embeddings::drift::{DriftMonitor, DriftReport};
 use crate::pipelines::sentence_embeddings::layers::{Dense, DenseConfig, Pooling, PoolingConfig};
 use crate::pipelines::sentence_embeddings::{
     AttentionHead, AttentionLayer, AttentionOutput, Embedding, SentenceEmbeddingsConfig,
     SentenceEmbeddingsModulesConfig, SentenceEmbeddingsSentenceBertConfig,
     SentenceEmbeddingsTokenizerConfig,
 };
+use crate::pipelines::text_splitter::{TextSplitter, TextSplitterConfig};
 use crate::roberta::RobertaForSentenceEmbeddings;
 use crate::t5::T5ForSentenceEmbeddings;
 use crate::{Config, RustBertError};
@@ -80,6 +89,12 @@ impl SentenceEmbeddingsOption {
         tokens_ids: &Tensor,
         tokens_masks: &Tensor,
     ) -> Result<(Tensor, Option<Vec<Tensor>>), RustBertError> {
+        let _span = trace_span!(
+            DEBUG,
+            "forward",
+            input_shape = ?tokens_ids.size(),
+            device = ?tokens_ids.device()
+        );
         match self {
             Self::Bert(transformer) => transformer
                 .forward_t(
@@ -168,6 +183,7 @@ pub struct SentenceEmbeddingsModel {
     pooling_layer: Pooling,
     dense_layer: Option<Dense>,
     normalize_embeddings: bool,
+    drift_monitor: Option<Mutex<DriftMonitor>>,
 }
 
 impl SentenceEmbeddingsModel {
@@ -177,6 +193,13 @@ impl SentenceEmbeddingsModel {
     ///
     /// * `config` - `SentenceEmbeddingsConfig` object containing the resource references (model, vocabulary, configuration) and device placement (CPU/GPU)
     pub fn new(config: SentenceEmbeddingsConfig) -> Result<Self, RustBertError> {
+        let _span = trace_span!(
+            INFO,
+            "load_model",
+            pipeline = "sentence_embeddings",
+            model_type = ?config.transformer_type,
+            device = ?config.device
+        );
         let SentenceEmbeddingsConfig {
             modules_config_resource,
             sentence_bert_config_resource,
@@ -268,9 +291,34 @@ impl SentenceEmbeddingsModel {
             pooling_layer,
             dense_layer,
             normalize_embeddings,
+            drift_monitor: None,
         })
     }
 
+    /// Attaches a drift monitor observing all embeddings computed by the model (see the
+    /// [`drift`](crate::pipelines::sentence_embeddings::drift) module)
+    ///
+    /// # Arguments
+    ///
+    /// * `drift_monitor` - `DriftMonitor` tracking the distribution of the embeddings
+    pub fn with_drift_monitor(mut self, drift_monitor: DriftMonitor) -> Self {
+        self.drift_monitor = Some(Mutex::new(drift_monitor));
+        self
+    }
+
+    /// Returns the report of the last window of embeddings compared to the reference distribution
+    /// by the drift monitor, if a monitor is attached and a window was completed
+    pub fn drift_report(&self) -> Option<DriftReport> {
+        self.drift_monitor
+            .as_ref()
+            .and_then(|drift_monitor| drift_monitor.lock().unwrap().last_report().cloned())
+    }
+
+    /// Returns the tokenizer of the model (e.g. to split long documents with a `TextSplitter`)
+    pub fn get_tokenizer(&self) -> &TokenizerOption {
+        &self.tokenizer
+    }
+
     /// Sets the tokenizer's truncation strategy
     pub fn set_tokenizer_truncation(&mut self, truncation_strategy: TruncationStrategy) {
         self.tokenizer_truncation_strategy = truncation_strategy;
@@ -335,6 +383,108 @@ impl SentenceEmbeddingsModel {
     where
         S: AsRef<str> + Sync,
     {
+        self.encode_as_tensor_with_options(inputs, Default::default())
+    }
+
+    /// Computes sentence embeddings, outputs `Tensor`. The inputs are encoded by batches on the
+    /// device of the model, and the embeddings are returned on the device and with the kind
+    /// requested in the options (e.g. encoding on GPU and returning half precision embeddings on CPU).
+    ///
+    /// # Arguments
+    ///
+    /// * `inputs` - slice of string-like input texts to encode
+    /// * `options` - `SentenceEmbeddingsEncodeOptions` setting the batch size, output device and output kind
+    ///
+    /// # Returns
+    ///
+    /// * `SentenceEmbeddingsModelOuput` containing the embeddings. Attentions are only returned if
+    /// the inputs fit in a single batch (their sequence length differs between batches).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::sentence_embeddings::{
+    ///     SentenceEmbeddingsBuilder, SentenceEmbeddingsEncodeOptions, SentenceEmbeddingsModelType,
+    /// };
+    /// use tch::{Device, Kind};
+    ///
+    /// let model = SentenceEmbeddingsBuilder::remote(SentenceEmbeddingsModelType::AllMiniLmL12V2)
+    ///     .with_device(Device::cuda_if_available())
+    ///     .create_model()?;
+    /// let options = SentenceEmbeddingsEncodeOptions {
+    ///     batch_size: Some(64),
+    ///     output_device: Some(Device::Cpu),
+    ///     output_kind: Some(Kind::Half),
+    /// };
+    /// let embeddings = model
+    ///     .encode_as_tensor_with_options(&["This is an example sentence"], options)?
+    ///     .embeddings;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn encode_as_tensor_with_options<S>(
+        &self,
+        inputs: &[S],
+        options: SentenceEmbeddingsEncodeOptions,
+    ) -> Result<SentenceEmbeddingsModelOuput, RustBertError>
+    where
+        S: AsRef<str> + Sync,
+    {
+        if options.batch_size == Some(0) {
+            return Err(RustBertError::ValueError(
+                "The batch size must be strictly greater than 0".to_string(),
+            ));
+        }
+        let to_output = |embeddings: Tensor| {
+            let embeddings = match options.output_kind {
+                Some(kind) => embeddings.to_kind(kind),
+                None => embeddings,
+            };
+            match options.output_device {
+                Some(device) => embeddings.to_device(device),
+                None => embeddings,
+            }
+        };
+        match options.batch_size {
+            Some(batch_size) if batch_size < inputs.len() => {
+                let mut embeddings =
+                    Vec::with_capacity((inputs.len() + batch_size - 1) / batch_size);
+                for batch in inputs.chunks(batch_size) {
+                    embeddings.push(to_output(self.encode_batch(batch)?.embeddings));
+                }
+                Ok(SentenceEmbeddingsModelOuput {
+                    embeddings: Tensor::cat(&embeddings, 0),
+                    all_attentions: None,
+                })
+            }
+            _ => {
+                let SentenceEmbeddingsModelOuput {
+                    embeddings,
+                    all_attentions,
+                } = self.encode_batch(inputs)?;
+                let all_attentions = match options.output_device {
+                    Some(device) => all_attentions.map(|attentions| {
+                        attentions
+                            .iter()
+                            .map(|attention| attention.to_device(device))
+                            .collect()
+                    }),
+                    None => all_attentions,
+                };
+                Ok(SentenceEmbeddingsModelOuput {
+                    embeddings: to_output(embeddings),
+                    all_attentions,
+                })
+            }
+        }
+    }
+
+    fn encode_batch<S>(&self, inputs: &[S]) -> Result<SentenceEmbeddingsModelOuput, RustBertError>
+    where
+        S: AsRef<str> + Sync,
+    {
+        let start_time = Instant::now();
         let SentenceEmbeddingsTokenizerOuput {
             tokens_ids,
             tokens_masks,
@@ -362,6 +512,21 @@ impl SentenceEmbeddingsModel {
             maybe_linear
         };
 
+        if let Some(drift_monitor) = &self.drift_monitor {
+            let embeddings = Vec::<Embedding>::from(maybe_normalized.to_kind(Kind::Float));
+            drift_monitor.lock().unwrap().observe(&embeddings)?;
+        }
+
+        if let Some(recorder) = metrics::recorder() {
+            recorder.record_request("sentence_embeddings", inputs.len());
+            recorder.record_tokens(
+                "sentence_embeddings",
+                tokens_ids.numel(),
+                0,
+                start_time.elapsed(),
+            );
+        }
+
         Ok(SentenceEmbeddingsModelOuput {
             embeddings: maybe_normalized,
             all_attentions,
@@ -377,6 +542,137 @@ impl SentenceEmbeddingsModel {
         Ok(Vec::from(embeddings))
     }
 
+    /// Computes embeddings of documents longer than the maximum sequence length of the model. Each
+    /// document is split in chunks fitting the model, the chunks are encoded and their embeddings
+    /// are pooled into a single embedding per document. Normalized models return normalized
+    /// document embeddings.
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - slice of string-like documents to encode
+    /// * `config` - `DocumentEmbeddingsConfig` setting the chunking strategy and the pooling of the chunk embeddings
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Embedding>` containing one embedding per document
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::sentence_embeddings::{
+    ///     DocumentEmbeddingsConfig, DocumentPooling, SentenceEmbeddingsBuilder,
+    ///     SentenceEmbeddingsModelType,
+    /// };
+    ///
+    /// let model = SentenceEmbeddingsBuilder::remote(SentenceEmbeddingsModelType::AllMiniLmL12V2)
+    ///     .create_model()?;
+    /// let config = DocumentEmbeddingsConfig {
+    ///     pooling: DocumentPooling::WeightedByLength,
+    ///     ..Default::default()
+    /// };
+    /// let embeddings = model.encode_documents(&["A very long document..."], &config)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn encode_documents<S>(
+        &self,
+        documents: &[S],
+        config: &DocumentEmbeddingsConfig,
+    ) -> Result<Vec<Embedding>, RustBertError>
+    where
+        S: AsRef<str> + Sync,
+    {
+        let num_special_tokens = self
+            .tokenizer
+            .build_input_with_special_tokens(
+                TokenIdsWithOffsets {
+                    ids: vec![],
+                    offsets: vec![],
+                    reference_offsets: vec![],
+                    masks: vec![],
+                },
+                None,
+            )
+            .token_ids
+            .len();
+        let max_chunk_size = self
+            .sentence_bert_config
+            .max_seq_length
+            .saturating_sub(num_special_tokens);
+        let text_splitter_config = match &config.text_splitter_config {
+            Some(text_splitter_config) => {
+                if text_splitter_config.chunk_size > max_chunk_size {
+                    return Err(RustBertError::InvalidConfigurationError(format!(
+                        "The chunk size ({}) exceeds the maximum number of tokens of the model ({})",
+                        text_splitter_config.chunk_size, max_chunk_size
+                    )));
+                }
+                text_splitter_config.clone()
+            }
+            None => TextSplitterConfig {
+                chunk_size: max_chunk_size,
+                chunk_overlap: max_chunk_size / 8,
+                ..Default::default()
+            },
+        };
+        let text_splitter = TextSplitter::new(text_splitter_config)?;
+
+        let mut chunk_texts = vec![];
+        let mut chunk_weights = vec![];
+        let mut chunks_per_document = Vec::with_capacity(documents.len());
+        for (document, chunks) in documents
+            .iter()
+            .zip(text_splitter.split(&self.tokenizer, documents))
+        {
+            if chunks.is_empty() {
+                // Empty documents are encoded as a single (empty) chunk
+                chunk_texts.push(document.as_ref().to_string());
+                chunk_weights.push(1.0);
+                chunks_per_document.push(1);
+            } else {
+                chunks_per_document.push(chunks.len());
+                for chunk in chunks {
+                    chunk_weights.push(chunk.num_tokens.max(1) as f32);
+                    chunk_texts.push(chunk.text);
+                }
+            }
+        }
+
+        let chunk_embeddings = self
+            .encode_as_tensor_with_options(
+                &chunk_texts,
+                SentenceEmbeddingsEncodeOptions {
+                    batch_size: config.batch_size,
+                    output_device: Some(Device::Cpu),
+                    output_kind: Some(Kind::Float),
+                },
+            )?
+            .embeddings;
+        let chunk_weights = Tensor::of_slice(&chunk_weights);
+
+        let mut start = 0;
+        let document_embeddings = chunks_per_document
+            .iter()
+            .map(|&num_chunks| {
+                let embeddings = chunk_embeddings.narrow(0, start, num_chunks as i64);
+                let weights = chunk_weights.narrow(0, start, num_chunks as i64);
+                start += num_chunks as i64;
+                config.pooling.pool(&embeddings, &weights)
+            })
+            .collect::<Vec<Tensor>>();
+        let document_embeddings = Tensor::stack(&document_embeddings, 0);
+        let document_embeddings = if self.normalize_embeddings {
+            let norm = document_embeddings
+                .norm_scalaropt_dim(2, &[1], true)
+                .clamp_min(1e-12);
+            document_embeddings / norm
+        } else {
+            document_embeddings
+        };
+        Ok(Vec::from(document_embeddings))
+    }
+
     fn nb_layers(&self) -> usize {
         use SentenceEmbeddingsOption::*;
         match (&self.transformer, &self.transformer_config) {
@@ -448,6 +744,121 @@ impl SentenceEmbeddingsModel {
 
         Ok((embeddings, attention_outputs))
     }
+
+    /// Returns the input embedding matrix of the transformer (one row per token of the vocabulary)
+    fn word_embeddings(&self) -> Result<Tensor, RustBertError> {
+        let variable_name = match self.transformer {
+            SentenceEmbeddingsOption::T5(_) => "shared.weight",
+            _ => "embeddings.word_embeddings.weight",
+        };
+        self.var_store
+            .variables()
+            .remove(variable_name)
+            .ok_or_else(|| {
+                RustBertError::InvalidConfigurationError(format!(
+                    "Could not find the word embeddings `{}` in the model weights",
+                    variable_name
+                ))
+            })
+    }
+
+    /// Returns the input embeddings of tokens of the vocabulary (rows of the embedding matrix of
+    /// the transformer). See the [`arithmetic`](crate::pipelines::sentence_embeddings::arithmetic)
+    /// module to combine them.
+    ///
+    /// # Arguments
+    ///
+    /// * `tokens` - tokens of the vocabulary (e.g. `king` or `##ing` for WordPiece vocabularies, `Ġking` for byte-level BPE vocabularies)
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<Embedding>, RustBertError>` - one embedding per token, error if a token is not part of the vocabulary
+    pub fn token_embeddings<S>(&self, tokens: &[S]) -> Result<Vec<Embedding>, RustBertError>
+    where
+        S: AsRef<str>,
+    {
+        let token_ids = self.tokenizer.convert_tokens_to_ids(tokens);
+        let unk_id = self.tokenizer.get_unk_id();
+        let unk_token = self.tokenizer.decode(&[unk_id], false, false);
+        for (token, &token_id) in tokens.iter().zip(token_ids.iter()) {
+            let token = token.as_ref();
+            if token_id == unk_id && token != unk_token {
+                return Err(RustBertError::ValueError(format!(
+                    "Token `{}` is not part of the vocabulary",
+                    token
+                )));
+            }
+        }
+        let word_embeddings = self.word_embeddings()?;
+        let token_ids = Tensor::of_slice(&token_ids).to(word_embeddings.device());
+        let embeddings = tch::no_grad(|| word_embeddings.index_select(0, &token_ids));
+        Ok(Vec::from(embeddings.to_kind(Kind::Float).to(Device::Cpu)))
+    }
+
+    /// Retrieves the tokens of the vocabulary whose input embeddings are the closest to query
+    /// embeddings (cosine similarity). The queries must have the dimension of the input
+    /// embeddings of the transformer, for example token embeddings combined with the
+    /// [`arithmetic`](crate::pipelines::sentence_embeddings::arithmetic) utilities.
+    ///
+    /// # Arguments
+    ///
+    /// * `embeddings` - query embeddings
+    /// * `top_k` - maximum number of tokens returned for each query
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<Vec<TokenNeighbour>>, RustBertError>` - tokens closest to each query, by decreasing similarity
+    pub fn nearest_tokens(
+        &self,
+        embeddings: &[Embedding],
+        top_k: usize,
+    ) -> Result<Vec<Vec<TokenNeighbour>>, RustBertError> {
+        let word_embeddings = self.word_embeddings()?;
+        let (vocab_size, dimension) = word_embeddings.size2()?;
+        if let Some(embedding) = embeddings
+            .iter()
+            .find(|embedding| embedding.len() as i64 != dimension)
+        {
+            return Err(RustBertError::ValueError(format!(
+                "Query dimension {} does not match the token embeddings dimension {}",
+                embedding.len(),
+                dimension
+            )));
+        }
+        if embeddings.is_empty() || top_k == 0 {
+            return Ok(vec![vec![]; embeddings.len()]);
+        }
+
+        let (scores, token_ids) = tch::no_grad(|| {
+            let word_embeddings = word_embeddings.to_kind(Kind::Float);
+            let queries = Tensor::of_slice(&embeddings.concat())
+                .view((embeddings.len() as i64, dimension))
+                .to(word_embeddings.device());
+            let normalize = |tensor: &Tensor| {
+                tensor / tensor.norm_scalaropt_dim(2, &[-1], true).clamp_min(1e-12)
+            };
+            normalize(&queries)
+                .matmul(&normalize(&word_embeddings).transpose(0, 1))
+                .topk((top_k as i64).min(vocab_size), -1, true, true)
+        });
+        let scores = Vec::<Vec<f32>>::from(scores.to(Device::Cpu));
+        let token_ids = Vec::<Vec<i64>>::from(token_ids.to(Device::Cpu));
+        Ok(scores
+            .into_iter()
+            .zip(token_ids)
+            .map(|(scores, token_ids)| {
+                scores
+                    .into_iter()
+                    .zip(token_ids)
+                    .map(|(score, token_id)| TokenNeighbour {
+                        token_id,
+                        token: self.tokenizer.decode(&[token_id], false, false),
+                        score,
+                    })
+                    .collect()
+            })
+            .collect())
+    }
 }
 
 /// Container for the SentenceEmbeddings tokenizer output.
@@ -456,6 +867,67 @@ pub struct SentenceEmbeddingsTokenizerOuput {
     pub tokens_masks: Vec<Tensor>,
 }
 
+#[derive(Debug, Clone, Copy, Default)]
+/// # Options for the computation of sentence embeddings
+pub struct SentenceEmbeddingsEncodeOptions {
+    /// Maximum number of inputs encoded in a single forward pass (default: all inputs in a single batch)
+    pub batch_size: Option<usize>,
+    /// Device to return the embeddings on (default: device of the model)
+    pub output_device: Option<Device>,
+    /// Kind of the returned embeddings (default: kind of the model output)
+    pub output_kind: Option<Kind>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// # Pooling of the chunk embeddings of a document
+pub enum DocumentPooling {
+    /// Average of the chunk embeddings
+    Mean,
+    /// Average of the chunk embeddings weighted by the number of tokens of the chunks
+    WeightedByLength,
+    /// Element-wise maximum of the chunk embeddings
+    Max,
+}
+
+impl DocumentPooling {
+    fn pool(&self, embeddings: &Tensor, weights: &Tensor) -> Tensor {
+        match self {
+            Self::Mean => embeddings.mean_dim(&[0], false, Kind::Float),
+            Self::WeightedByLength => {
+                (embeddings * weights.unsqueeze(1)).sum_dim_intlist(
+                    [0].as_slice(),
+                    false,
+                    Kind::Float,
+                ) / weights.sum(Kind::Float)
+            }
+            Self::Max => embeddings.max_dim(0, false).0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Configuration for the computation of document embeddings
+pub struct DocumentEmbeddingsConfig {
+    /// Chunking strategy (chunk size, overlap and snapping to sentence boundaries). The chunk size
+    /// must fit the maximum sequence length of the model (default: chunks filling the maximum
+    /// sequence length with an overlap of 1/8th, snapped to sentence boundaries)
+    pub text_splitter_config: Option<TextSplitterConfig>,
+    /// Pooling of the chunk embeddings (default: `DocumentPooling::Mean`)
+    pub pooling: DocumentPooling,
+    /// Maximum number of chunks encoded in a single forward pass (default: all chunks in a single batch)
+    pub batch_size: Option<usize>,
+}
+
+impl Default for DocumentEmbeddingsConfig {
+    fn default() -> DocumentEmbeddingsConfig {
+        DocumentEmbeddingsConfig {
+            text_splitter_config: None,
+            pooling: DocumentPooling::Mean,
+            batch_size: None,
+        }
+    }
+}
+
 /// Container for the SentenceEmbeddings model output.
 pub struct SentenceEmbeddingsModelOuput {
     pub embeddings: Tensor,
@@ -0,0 +1,248 @@
+//! # Semantic search over sentence embeddings
+//!
+//! The `EmbeddingIndex` stores the embeddings of a corpus and retrieves the entries closest to
+//! queries (cosine similarity). Embeddings are normalized when added to the index, so that any
+//! sentence embeddings model can be used.
+//!
+//! ## Cross-lingual search
+//!
+//! Multilingual models with aligned embedding spaces (e.g. [LaBSE][labse] or
+//! `distiluse-base-multilingual-cased`) map translations of a sentence to close embeddings. An
+//! index built from English documents can therefore be queried in any language supported by the
+//! model, without translating the queries or the corpus:
+//!
+//! ```no_run
+//! use rust_bert::pipelines::sentence_embeddings::{EmbeddingIndex, SentenceEmbeddingsBuilder};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let model = SentenceEmbeddingsBuilder::local("resources/LaBSE")
+//!     .with_device(tch::Device::cuda_if_available())
+//!     .create_model()?;
+//!
+//! let corpus = [
+//!     "The cat sits on the mat.",
+//!     "The new movie is awesome.",
+//!     "Interest rates were raised by the central bank.",
+//! ];
+//! let index = EmbeddingIndex::build(&model, &corpus)?;
+//!
+//! let queries = ["Le film est génial.", "Die Zentralbank hat die Zinsen erhöht."];
+//! for (query, hits) in queries.iter().zip(index.search(&model, &queries, 1)?) {
+//!     println!("{} -> {} ({:.3})", query, hits[0].text, hits[0].score);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [labse]: https://huggingface.co/sentence-transformers/LaBSE
+
+use serde::{Deserialize, Serialize};
+
+use crate::pipelines::sentence_embeddings::{Embedding, SentenceEmbeddingsModel};
+use crate::RustBertError;
+
+/// Search result returned by an `EmbeddingIndex`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchHit {
+    /// Position of the entry in the index
+    pub corpus_id: usize,
+    /// Text of the entry
+    pub text: String,
+    /// Cosine similarity between the query and the entry
+    pub score: f32,
+}
+
+/// In-memory index of normalized sentence embeddings, searched exhaustively
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EmbeddingIndex {
+    texts: Vec<String>,
+    embeddings: Vec<Embedding>,
+}
+
+impl EmbeddingIndex {
+    /// Creates an empty index
+    pub fn new() -> EmbeddingIndex {
+        EmbeddingIndex::default()
+    }
+
+    /// Creates an index from a corpus, encoded with a sentence embeddings model
+    pub fn build<S>(
+        model: &SentenceEmbeddingsModel,
+        texts: &[S],
+    ) -> Result<EmbeddingIndex, RustBertError>
+    where
+        S: AsRef<str> + Sync,
+    {
+        let mut index = EmbeddingIndex::new();
+        index.add(model, texts)?;
+        Ok(index)
+    }
+
+    /// Encodes texts with a sentence embeddings model and adds them to the index
+    pub fn add<S>(
+        &mut self,
+        model: &SentenceEmbeddingsModel,
+        texts: &[S],
+    ) -> Result<(), RustBertError>
+    where
+        S: AsRef<str> + Sync,
+    {
+        let embeddings = model.encode(texts)?;
+        self.add_embeddings(texts, embeddings)
+    }
+
+    /// Adds texts with pre-computed embeddings to the index
+    pub fn add_embeddings<S>(
+        &mut self,
+        texts: &[S],
+        embeddings: Vec<Embedding>,
+    ) -> Result<(), RustBertError>
+    where
+        S: AsRef<str>,
+    {
+        if texts.len() != embeddings.len() {
+            return Err(RustBertError::ValueError(format!(
+                "Got {} texts for {} embeddings",
+                texts.len(),
+                embeddings.len()
+            )));
+        }
+        let dimension = self
+            .embeddings
+            .first()
+            .or_else(|| embeddings.first())
+            .map(Vec::len);
+        if let Some(embedding) = embeddings
+            .iter()
+            .find(|embedding| Some(embedding.len()) != dimension)
+        {
+            return Err(RustBertError::ValueError(format!(
+                "Embedding dimension {} does not match the index dimension {}",
+                embedding.len(),
+                dimension.unwrap_or_default()
+            )));
+        }
+        self.texts
+            .extend(texts.iter().map(|text| text.as_ref().to_string()));
+        self.embeddings
+            .extend(embeddings.into_iter().map(normalize));
+        Ok(())
+    }
+
+    /// Number of entries in the index
+    pub fn len(&self) -> usize {
+        self.texts.len()
+    }
+
+    /// Returns true if the index has no entries
+    pub fn is_empty(&self) -> bool {
+        self.texts.is_empty()
+    }
+
+    /// Returns the text of an entry
+    pub fn text(&self, corpus_id: usize) -> Option<&str> {
+        self.texts.get(corpus_id).map(String::as_str)
+    }
+
+    /// Returns the (normalized) embedding of an entry
+    pub fn embedding(&self, corpus_id: usize) -> Option<&[f32]> {
+        self.embeddings.get(corpus_id).map(Vec::as_slice)
+    }
+
+    /// Encodes queries with a sentence embeddings model and retrieves the closest entries
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - `SentenceEmbeddingsModel` used to build the index
+    /// * `queries` - `&[&str]` Array of queries
+    /// * `top_k` - Maximum number of entries returned for each query
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<Vec<SearchHit>>, RustBertError>` entries closest to each query, by decreasing similarity
+    pub fn search<S>(
+        &self,
+        model: &SentenceEmbeddingsModel,
+        queries: &[S],
+        top_k: usize,
+    ) -> Result<Vec<Vec<SearchHit>>, RustBertError>
+    where
+        S: AsRef<str> + Sync,
+    {
+        Ok(self.search_embeddings(&model.encode(queries)?, top_k))
+    }
+
+    /// Retrieves the closest entries to query embeddings
+    pub fn search_embeddings(
+        &self,
+        query_embeddings: &[Embedding],
+        top_k: usize,
+    ) -> Vec<Vec<SearchHit>> {
+        query_embeddings
+            .iter()
+            .map(|query_embedding| {
+                let query_embedding = normalize(query_embedding.clone());
+                let mut scores = self
+                    .embeddings
+                    .iter()
+                    .map(|embedding| dot_product(&query_embedding, embedding))
+                    .enumerate()
+                    .collect::<Vec<(usize, f32)>>();
+                scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+                scores
+                    .into_iter()
+                    .take(top_k)
+                    .map(|(corpus_id, score)| SearchHit {
+                        corpus_id,
+                        text: self.texts[corpus_id].clone(),
+                        score,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+fn dot_product(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+}
+
+fn normalize(mut embedding: Embedding) -> Embedding {
+    let norm = dot_product(&embedding, &embedding).sqrt().max(1e-12);
+    embedding.iter_mut().for_each(|value| *value /= norm);
+    embedding
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn embedding_index_search() -> Result<(), RustBertError> {
+        let mut index = EmbeddingIndex::new();
+        index.add_embeddings(
+            &["north", "east", "north-east"],
+            vec![vec![0.0, 2.0], vec![3.0, 0.0], vec![1.0, 1.0]],
+        )?;
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.embedding(0), Some([0.0, 1.0].as_slice()));
+
+        let hits = index.search_embeddings(&[vec![0.1, 1.0], vec![1.0, 0.0]], 2);
+        assert_eq!(hits.len(), 2);
+        assert_eq!(
+            hits[0]
+                .iter()
+                .map(|hit| hit.text.as_str())
+                .collect::<Vec<&str>>(),
+            ["north", "north-east"]
+        );
+        assert_eq!(hits[1][0].corpus_id, 1);
+        assert!((hits[1][0].score - 1.0).abs() < 1e-6);
+
+        assert!(index
+            .add_embeddings(&["up"], vec![vec![0.0, 0.0, 1.0]])
+            .is_err());
+        assert!(index.add_embeddings(&["up"], vec![]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,405 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Sentence segmentation pipeline
+//! Splits texts into sentences, returning each sentence with its character offsets in the input
+//! text. This provides a consistent segmentation for pipelines working on long documents (e.g.
+//! summarization or extractive pipelines) that need to process or reference individual sentences.
+//!
+//! The segmentation is rule-based by default: sentences end with a terminal punctuation mark
+//! (`.`, `!`, `?`, `…` and their CJK equivalents) followed by a whitespace, or at paragraph breaks.
+//! Periods following a known abbreviation (e.g. `Dr.`), an initial or preceding a lower-cased
+//! word are not considered as sentence boundaries.
+//!
+//! A token classification model predicting sentence boundaries can optionally be attached to the
+//! segmenter: the boundaries predicted by the model are combined with the rule-based boundaries,
+//! allowing to split texts with missing or unusual punctuation.
+//!
+//! ```no_run
+//! use rust_bert::pipelines::sentence_segmentation::SentenceSegmenter;
+//!
+//! let segmenter = SentenceSegmenter::new(Default::default());
+//! let input = ["Dr. Smith lives in Paris. He works at the U.N. since 2010! Does he like it?"];
+//! let output = segmenter.segment(&input);
+//! ```
+//! Output: \
+//! ```no_run
+//! # use rust_bert::pipelines::sentence_segmentation::Sentence;
+//! # use rust_tokenizers::Offset;
+//! # let output =
+//! [[
+//!     Sentence {
+//!         text: String::from("Dr. Smith lives in Paris."),
+//!         offset: Offset { begin: 0, end: 25 },
+//!     },
+//!     Sentence {
+//!         text: String::from("He works at the U.N. since 2010!"),
+//!         offset: Offset { begin: 26, end: 58 },
+//!     },
+//!     Sentence {
+//!         text: String::from("Does he like it?"),
+//!         offset: Offset { begin: 59, end: 75 },
+//!     },
+//! ]]
+//! # ;
+//! ```
+
+use crate::pipelines::token_classification::TokenClassificationModel;
+use rust_tokenizers::Offset;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+
+const DEFAULT_ABBREVIATIONS: [&str; 40] = [
+    "mr", "mrs", "ms", "dr", "prof", "sr", "jr", "st", "mt", "rev", "gen", "gov", "sen", "rep",
+    "vs", "e.g", "i.e", "cf", "inc", "ltd", "co", "corp", "dept", "no", "nos", "fig", "al",
+    "approx", "est", "jan", "feb", "mar", "apr", "jun", "jul", "aug", "sep", "sept", "oct", "nov",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Configuration for SentenceSegmenter
+/// Contains the rules used to detect sentence boundaries and the settings of the optional
+/// boundary detection model.
+pub struct SentenceSegmentationConfig {
+    /// Lower-cased abbreviations (without their final period) after which a period does not end a
+    /// sentence (default: common English abbreviations such as `dr`, `e.g` or `inc`)
+    pub abbreviations: HashSet<String>,
+    /// Flag indicating if every line break ends a sentence. Paragraph breaks (empty lines) always
+    /// end a sentence (default: false)
+    pub split_on_line_breaks: bool,
+    /// Label of the tokens ending a sentence for the boundary detection model (default: `EOS`)
+    pub boundary_label: String,
+    /// Minimum score for a boundary predicted by the model to be used (default: 0.5)
+    pub boundary_threshold: f64,
+}
+
+impl Default for SentenceSegmentationConfig {
+    fn default() -> SentenceSegmentationConfig {
+        SentenceSegmentationConfig {
+            abbreviations: DEFAULT_ABBREVIATIONS
+                .iter()
+                .map(|abbreviation| abbreviation.to_string())
+                .collect(),
+            split_on_line_breaks: false,
+            boundary_label: "EOS".to_string(),
+            boundary_threshold: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// # Sentence extracted by a `SentenceSegmenter`
+pub struct Sentence {
+    /// Text of the sentence, without surrounding whitespaces
+    pub text: String,
+    /// Character offsets of the sentence in the input text
+    pub offset: Offset,
+}
+
+/// # SentenceSegmenter to split texts into sentences
+/// Rule-based sentence splitter, optionally assisted by a token classification model predicting
+/// sentence boundaries.
+pub struct SentenceSegmenter {
+    config: SentenceSegmentationConfig,
+    boundary_model: Option<TokenClassificationModel>,
+}
+
+impl SentenceSegmenter {
+    /// Build a new rule-based `SentenceSegmenter`
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `SentenceSegmentationConfig` object containing the segmentation rules
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use rust_bert::pipelines::sentence_segmentation::SentenceSegmenter;
+    ///
+    /// let segmenter = SentenceSegmenter::new(Default::default());
+    /// ```
+    pub fn new(config: SentenceSegmentationConfig) -> SentenceSegmenter {
+        SentenceSegmenter {
+            config,
+            boundary_model: None,
+        }
+    }
+
+    /// Build a new `SentenceSegmenter` assisted by a sentence boundary detection model. The model
+    /// should label the last word of sentences with the `boundary_label` of the configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `SentenceSegmentationConfig` object containing the segmentation rules
+    /// * `boundary_model` - `TokenClassificationModel` predicting sentence boundaries
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::sentence_segmentation::{
+    ///     SentenceSegmentationConfig, SentenceSegmenter,
+    /// };
+    /// use rust_bert::pipelines::token_classification::TokenClassificationModel;
+    ///
+    /// let boundary_model = TokenClassificationModel::new(Default::default())?;
+    /// let config = SentenceSegmentationConfig {
+    ///     boundary_label: "B-EOS".to_string(),
+    ///     ..Default::default()
+    /// };
+    /// let segmenter = SentenceSegmenter::new_with_boundary_model(config, boundary_model);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new_with_boundary_model(
+        config: SentenceSegmentationConfig,
+        boundary_model: TokenClassificationModel,
+    ) -> SentenceSegmenter {
+        SentenceSegmenter {
+            config,
+            boundary_model: Some(boundary_model),
+        }
+    }
+
+    /// Split texts into sentences
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - `&[&str]` Array of texts to segment.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Vec<Sentence>>` containing the sentences of each input text, with their character offsets
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use rust_bert::pipelines::sentence_segmentation::SentenceSegmenter;
+    ///
+    /// let segmenter = SentenceSegmenter::new(Default::default());
+    /// let input = ["This is a first sentence. This is a second sentence."];
+    /// let output = segmenter.segment(&input);
+    /// ```
+    pub fn segment<S>(&self, input: &[S]) -> Vec<Vec<Sentence>>
+    where
+        S: AsRef<str>,
+    {
+        let mut model_boundaries = vec![BTreeSet::new(); input.len()];
+        if let Some(boundary_model) = &self.boundary_model {
+            for token in boundary_model.predict(input, true, false).iter().flatten() {
+                if (token.label == self.config.boundary_label)
+                    & (token.score >= self.config.boundary_threshold)
+                {
+                    if let Some(offset) = token.offset {
+                        model_boundaries[token.sentence].insert(offset.end as usize);
+                    }
+                }
+            }
+        }
+
+        input
+            .iter()
+            .zip(model_boundaries)
+            .map(|(text, model_boundaries)| {
+                let chars = text.as_ref().chars().collect::<Vec<char>>();
+                let mut boundaries = self.rule_based_boundaries(&chars);
+                boundaries.extend(model_boundaries);
+                boundaries.insert(chars.len());
+                split_at_boundaries(&chars, &boundaries)
+            })
+            .collect()
+    }
+
+    fn rule_based_boundaries(&self, chars: &[char]) -> BTreeSet<usize> {
+        let mut boundaries = BTreeSet::new();
+        let mut position = 0;
+        while position < chars.len() {
+            let character = chars[position];
+            if character == '\n' {
+                let next_line_start = chars[position + 1..]
+                    .iter()
+                    .position(|c| !matches!(c, ' ' | '\t' | '\r'))
+                    .map(|offset| position + 1 + offset);
+                let paragraph_break = matches!(next_line_start, Some(next) if chars[next] == '\n');
+                if self.config.split_on_line_breaks | paragraph_break {
+                    boundaries.insert(position);
+                }
+                position += 1;
+                continue;
+            }
+            if !is_terminal_punctuation(character) {
+                position += 1;
+                continue;
+            }
+
+            let mut end = position + 1;
+            while end < chars.len()
+                && (is_terminal_punctuation(chars[end]) | is_closing_punctuation(chars[end]))
+            {
+                end += 1;
+            }
+            let followed_by_space = end == chars.len() || chars[end].is_whitespace();
+            let is_boundary = if is_cjk_terminal_punctuation(character) {
+                true
+            } else if !followed_by_space
+                || matches!(next_word_start(chars, end), Some(c) if c.is_lowercase())
+            {
+                false
+            } else if (character == '.') & (end == position + 1) {
+                self.period_ends_sentence(chars, position)
+            } else {
+                true
+            };
+            if is_boundary {
+                boundaries.insert(end);
+            }
+            position = end;
+        }
+        boundaries
+    }
+
+    fn period_ends_sentence(&self, chars: &[char], position: usize) -> bool {
+        let word_start = chars[..position]
+            .iter()
+            .rposition(|c| !(c.is_alphanumeric() | (*c == '.')))
+            .map_or(0, |index| index + 1);
+        let word = chars[word_start..position]
+            .iter()
+            .collect::<String>()
+            .to_lowercase();
+        if self.config.abbreviations.contains(&word) {
+            return false;
+        }
+
+        // Initials (e.g. `J. R. R. Tolkien`) followed by another word
+        let is_initial = matches!(chars[word_start..position], [c] if c.is_uppercase());
+        !is_initial | next_word_start(chars, position + 1).is_none()
+    }
+}
+
+fn next_word_start(chars: &[char], position: usize) -> Option<char> {
+    chars[position..]
+        .iter()
+        .find(|c| !c.is_whitespace() & !is_opening_punctuation(**c))
+        .copied()
+}
+
+fn split_at_boundaries(chars: &[char], boundaries: &BTreeSet<usize>) -> Vec<Sentence> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    for &boundary in boundaries {
+        let boundary = boundary.min(chars.len());
+        if boundary <= start {
+            continue;
+        }
+        let span = &chars[start..boundary];
+        if let (Some(first), Some(last)) = (
+            span.iter().position(|c| !c.is_whitespace()),
+            span.iter().rposition(|c| !c.is_whitespace()),
+        ) {
+            sentences.push(Sentence {
+                text: span[first..=last].iter().collect(),
+                offset: Offset {
+                    begin: (start + first) as u32,
+                    end: (start + last + 1) as u32,
+                },
+            });
+        }
+        start = boundary;
+    }
+    sentences
+}
+
+fn is_terminal_punctuation(character: char) -> bool {
+    matches!(character, '.' | '!' | '?' | '…' | '‼' | '⁇' | '⁈' | '⁉')
+        | is_cjk_terminal_punctuation(character)
+}
+
+fn is_cjk_terminal_punctuation(character: char) -> bool {
+    matches!(character, '。' | '！' | '？' | '｡')
+}
+
+fn is_closing_punctuation(character: char) -> bool {
+    matches!(
+        character,
+        '"' | '\'' | ')' | ']' | '}' | '»' | '”' | '’' | '」' | '』' | '）'
+    )
+}
+
+fn is_opening_punctuation(character: char) -> bool {
+    matches!(
+        character,
+        '"' | '\'' | '(' | '[' | '{' | '«' | '“' | '‘' | '「' | '『' | '（'
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn segment(text: &str) -> Vec<String> {
+        SentenceSegmenter::new(Default::default()).segment(&[text])[0]
+            .iter()
+            .map(|sentence| sentence.text.clone())
+            .collect()
+    }
+
+    #[test]
+    fn split_sentences_with_offsets() {
+        let text = "Dr. Smith lives in Paris. He works at the U.N. since 2010! Does he like it?";
+        let sentences = SentenceSegmenter::new(Default::default()).segment(&[text]);
+
+        assert_eq!(sentences[0].len(), 3);
+        for sentence in &sentences[0] {
+            let span = text
+                .chars()
+                .skip(sentence.offset.begin as usize)
+                .take((sentence.offset.end - sentence.offset.begin) as usize)
+                .collect::<String>();
+            assert_eq!(span, sentence.text);
+        }
+        assert_eq!(sentences[0][0].text, "Dr. Smith lives in Paris.");
+        assert_eq!(sentences[0][1].text, "He works at the U.N. since 2010!");
+        assert_eq!(sentences[0][2].offset, Offset { begin: 59, end: 75 });
+    }
+
+    #[test]
+    fn punctuation_rules() {
+        assert_eq!(
+            segment("Pi is approx. 3.14. J. R. R. Tolkien wrote books... \"Really?\" she asked."),
+            vec![
+                "Pi is approx. 3.14.",
+                "J. R. R. Tolkien wrote books...",
+                "\"Really?\" she asked."
+            ]
+        );
+        assert_eq!(
+            segment("今天天气很好。我们去公园吧！"),
+            vec!["今天天气很好。", "我们去公园吧！"]
+        );
+        assert_eq!(
+            segment("A title\n\nFirst paragraph\nstill going.  "),
+            vec!["A title", "First paragraph\nstill going."]
+        );
+        assert!(segment(" \n ").is_empty());
+    }
+
+    #[test]
+    fn line_breaks() {
+        let config = SentenceSegmentationConfig {
+            split_on_line_breaks: true,
+            ..Default::default()
+        };
+        let sentences = SentenceSegmenter::new(config).segment(&["- first item\n- second item"]);
+        assert_eq!(sentences[0].len(), 2);
+        assert_eq!(sentences[0][1].text, "- second item");
+        assert_eq!(sentences[0][1].offset, Offset { begin: 13, end: 26 });
+    }
+}
@@ -55,6 +55,7 @@
 //! ```
 
 use crate::common::error::RustBertError;
+use crate::pipelines::calibration::Calibrator;
 use crate::pipelines::sequence_classification::{
     SequenceClassificationConfig, SequenceClassificationModel,
 };
@@ -107,6 +108,21 @@ impl SentimentModel {
         })
     }
 
+    /// Sets a calibrator converting the logits of the classifier to calibrated probabilities
+    ///
+    /// # Arguments
+    ///
+    /// * `calibrator` - `Calibrator` fitted on a validation set
+    pub fn with_calibrator<C>(mut self, calibrator: C) -> SentimentModel
+    where
+        C: Calibrator + 'static,
+    {
+        self.sequence_classification_model = self
+            .sequence_classification_model
+            .with_calibrator(calibrator);
+        self
+    }
+
     /// Extract sentiment form an array of text inputs
     ///
     /// # Arguments
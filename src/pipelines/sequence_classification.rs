@@ -61,20 +61,32 @@ use crate::albert::AlbertForSequenceClassification;
 use crate::bart::BartForSequenceClassification;
 use crate::bert::BertForSequenceClassification;
 use crate::common::error::RustBertError;
+use crate::common::trace::trace_span;
 use crate::deberta::DebertaForSequenceClassification;
 use crate::distilbert::DistilBertModelClassifier;
 use crate::fnet::FNetForSequenceClassification;
 use crate::longformer::LongformerForSequenceClassification;
 use crate::mobilebert::MobileBertForSequenceClassification;
+use crate::pipelines::added_tokens::add_tokens_and_resize_embeddings;
+use crate::pipelines::calibration::Calibrator;
 use crate::pipelines::common::{ConfigOption, ModelType, TokenizerOption};
+use crate::pipelines::counterfactual::{
+    kl_divergence, total_variation, Counterfactual, CounterfactualOutput, CounterfactualReport,
+};
+use crate::pipelines::input_encoding::EncodedInputs;
+use crate::pipelines::output_cache::OutputCache;
+use crate::pipelines::saliency::{
+    attention_rollout, gradient_times_input, Saliency, SaliencyMethod, TokenSaliency,
+};
 use crate::reformer::ReformerForSequenceClassification;
 use crate::resources::ResourceProvider;
 use crate::roberta::RobertaForSequenceClassification;
 use crate::xlnet::XLNetForSequenceClassification;
 use rust_tokenizers::tokenizer::TruncationStrategy;
-use rust_tokenizers::TokenizedInput;
+use rust_tokenizers::{TokenIdsWithOffsets, TokenizedInput};
 use serde::{Deserialize, Serialize};
 use std::borrow::Borrow;
+use std::cmp::min;
 use std::collections::HashMap;
 use tch::nn::VarStore;
 use tch::{nn, no_grad, Device, Kind, Tensor};
@@ -100,18 +112,92 @@ pub struct Label {
     pub sentence: usize,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// # Normalization applied to the classification logits to obtain label scores
+pub enum ScoreNormalization {
+    /// Softmax over all labels (mutually exclusive labels, scores sum to 1)
+    Softmax,
+    /// Independent sigmoid for each label (multi-label classification)
+    Sigmoid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// # Strategy used to combine the logits of the windows generated for a single input
+pub enum WindowAggregation {
+    /// Average the logits over all windows
+    Mean,
+    /// Keep the maximum logit for each label over all windows
+    Max,
+    /// Majority vote of the windows predictions, the score reflects the share of windows voting for a label
+    Vote,
+}
+
+impl WindowAggregation {
+    fn aggregate(&self, window_logits: &Tensor, multilabel: bool) -> Tensor {
+        match self {
+            Self::Mean => window_logits.mean_dim(&[0], false, Kind::Float),
+            Self::Max => window_logits.max_dim(0, false).0,
+            Self::Vote => {
+                let votes = if multilabel {
+                    window_logits.ge(0.0).to_kind(Kind::Float)
+                } else {
+                    window_logits
+                        .argmax(-1, false)
+                        .one_hot(window_logits.size()[1])
+                        .to_kind(Kind::Float)
+                };
+                let vote_share = votes
+                    .mean_dim(&[0], false, Kind::Float)
+                    .clamp(1e-6, 1.0 - 1e-6);
+                // Convert the vote share back to logits so that the softmax (or sigmoid for
+                // multi-label classification) returns the share of windows voting for each label
+                if multilabel {
+                    (&vote_share / (1.0 - &vote_share)).log()
+                } else {
+                    vote_share.log()
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+/// # Sliding window settings for inputs longer than the model maximum length
+/// Inputs exceeding the maximum position embeddings of the model are split in overlapping windows
+/// that are classified independently. The window logits are then aggregated into a single prediction.
+pub struct SlidingWindowConfig {
+    /// Number of overlapping tokens between consecutive windows
+    pub stride: usize,
+    /// Strategy used to aggregate the logits of the windows
+    pub aggregation: WindowAggregation,
+}
+
+impl Default for SlidingWindowConfig {
+    fn default() -> SlidingWindowConfig {
+        SlidingWindowConfig {
+            stride: 128,
+            aggregation: WindowAggregation::Mean,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 /// # Configuration for SequenceClassificationModel
 /// Contains information regarding the model to load and device to place the model on.
 pub struct SequenceClassificationConfig {
     /// Model type
     pub model_type: ModelType,
     /// Model weights resource (default: pretrained BERT model on CoNLL)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub model_resource: Box<dyn ResourceProvider + Send>,
     /// Config resource (default: pretrained BERT model on CoNLL)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub config_resource: Box<dyn ResourceProvider + Send>,
     /// Vocab resource (default: pretrained BERT model on CoNLL)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub vocab_resource: Box<dyn ResourceProvider + Send>,
     /// Merges resource (default: None)
+    #[serde(default, with = "crate::common::serde_utils::optional_resource")]
     pub merges_resource: Option<Box<dyn ResourceProvider + Send>>,
     /// Automatically lower case all input upon tokenization (assumes a lower-cased model)
     pub lower_case: bool,
@@ -120,7 +206,13 @@ pub struct SequenceClassificationConfig {
     /// Flag indicating if the tokenizer should add a white space before each tokenized input (needed for some Roberta models)
     pub add_prefix_space: Option<bool>,
     /// Device to place the model on (default: CUDA/GPU when available)
+    #[serde(
+        with = "crate::common::serde_utils::device",
+        default = "crate::common::serde_utils::device::default"
+    )]
     pub device: Device,
+    /// Optional sliding window settings for long inputs (default: None, inputs are truncated to the model maximum length)
+    pub sliding_window: Option<SlidingWindowConfig>,
 }
 
 impl SequenceClassificationConfig {
@@ -159,6 +251,7 @@ impl SequenceClassificationConfig {
             strip_accents: strip_accents.into(),
             add_prefix_space: add_prefix_space.into(),
             device: Device::cuda_if_available(),
+            sliding_window: None,
         }
     }
 }
@@ -408,8 +501,39 @@ impl SequenceClassificationOption {
         input_embeds: Option<&Tensor>,
         train: bool,
     ) -> Tensor {
+        self.forward_t_with_attentions(
+            input_ids,
+            mask,
+            token_type_ids,
+            position_ids,
+            input_embeds,
+            train,
+        )
+        .0
+    }
+
+    /// Interface method to forward_t() of the particular models, also returning the attention
+    /// weights of each layer, of shape (*batch size*, *number of heads*, *sequence length*,
+    /// *sequence length*). The attention weights are only returned if the configuration of the
+    /// model enables `output_attentions`, for the BERT-like models (BERT, DeBERTa (v2),
+    /// DistilBERT, MobileBERT, RoBERTa, XLM-RoBERTa and ALBERT).
+    pub fn forward_t_with_attentions(
+        &self,
+        input_ids: Option<&Tensor>,
+        mask: Option<&Tensor>,
+        token_type_ids: Option<&Tensor>,
+        position_ids: Option<&Tensor>,
+        input_embeds: Option<&Tensor>,
+        train: bool,
+    ) -> (Tensor, Option<Vec<Tensor>>) {
+        let _span = trace_span!(
+            DEBUG,
+            "forward",
+            input_shape = ?input_ids.map(Tensor::size),
+            device = ?input_ids.map(Tensor::device)
+        );
         match *self {
-            Self::Bart(ref model) => {
+            Self::Bart(ref model) => (
                 model
                     .forward_t(
                         input_ids.expect("`input_ids` must be provided for BART models"),
@@ -419,22 +543,22 @@ impl SequenceClassificationOption {
                         None,
                         train,
                     )
-                    .decoder_output
-            }
+                    .decoder_output,
+                None,
+            ),
             Self::Bert(ref model) => {
-                model
-                    .forward_t(
-                        input_ids,
-                        mask,
-                        token_type_ids,
-                        position_ids,
-                        input_embeds,
-                        train,
-                    )
-                    .logits
+                let output = model.forward_t(
+                    input_ids,
+                    mask,
+                    token_type_ids,
+                    position_ids,
+                    input_embeds,
+                    train,
+                );
+                (output.logits, output.all_attentions)
             }
             Self::Deberta(ref model) => {
-                model
+                let output = model
                     .forward_t(
                         input_ids,
                         mask,
@@ -443,11 +567,11 @@ impl SequenceClassificationOption {
                         input_embeds,
                         train,
                     )
-                    .expect("Error in Deberta forward_t")
-                    .logits
+                    .expect("Error in Deberta forward_t");
+                (output.logits, output.all_attentions)
             }
             Self::DebertaV2(ref model) => {
-                model
+                let output = model
                     .forward_t(
                         input_ids,
                         mask,
@@ -456,46 +580,54 @@ impl SequenceClassificationOption {
                         input_embeds,
                         train,
                     )
-                    .expect("Error in Deberta V2 forward_t")
-                    .logits
+                    .expect("Error in Deberta V2 forward_t");
+                (output.logits, output.all_attentions)
             }
             Self::DistilBert(ref model) => {
-                model
+                let output = model
                     .forward_t(input_ids, mask, input_embeds, train)
-                    .expect("Error in distilbert forward_t")
-                    .logits
+                    .expect("Error in distilbert forward_t");
+                (output.logits, output.all_attentions)
             }
             Self::MobileBert(ref model) => {
-                model
+                let output = model
                     .forward_t(input_ids, None, None, input_embeds, mask, train)
-                    .expect("Error in mobilebert forward_t")
-                    .logits
+                    .expect("Error in mobilebert forward_t");
+                (output.logits, output.all_attentions)
             }
             Self::Roberta(ref model) | Self::XLMRoberta(ref model) => {
-                model
-                    .forward_t(
-                        input_ids,
-                        mask,
-                        token_type_ids,
-                        position_ids,
-                        input_embeds,
-                        train,
-                    )
-                    .logits
+                let output = model.forward_t(
+                    input_ids,
+                    mask,
+                    token_type_ids,
+                    position_ids,
+                    input_embeds,
+                    train,
+                );
+                (output.logits, output.all_attentions)
             }
             Self::Albert(ref model) => {
-                model
-                    .forward_t(
-                        input_ids,
-                        mask,
-                        token_type_ids,
-                        position_ids,
-                        input_embeds,
-                        train,
-                    )
-                    .logits
+                let output = model.forward_t(
+                    input_ids,
+                    mask,
+                    token_type_ids,
+                    position_ids,
+                    input_embeds,
+                    train,
+                );
+                // Layers sharing their weights (inner groups) are averaged
+                let all_attentions = output.all_attentions.map(|attentions| {
+                    attentions
+                        .into_iter()
+                        .map(|tensors| {
+                            let num_inner_groups = tensors.len() as f64;
+                            tensors.into_iter().sum::<Tensor>() / num_inner_groups
+                        })
+                        .collect()
+                });
+                (output.logits, all_attentions)
             }
-            Self::XLNet(ref model) => {
+            Self::XLNet(ref model) => (
                 model
                     .forward_t(
                         input_ids,
@@ -507,15 +639,17 @@ impl SequenceClassificationOption {
                         input_embeds,
                         train,
                     )
-                    .logits
-            }
-            Self::Reformer(ref model) => {
+                    .logits,
+                None,
+            ),
+            Self::Reformer(ref model) => (
                 model
                     .forward_t(input_ids, None, None, mask, None, train)
                     .expect("Error in Reformer forward pass.")
-                    .logits
-            }
-            Self::Longformer(ref model) => {
+                    .logits,
+                None,
+            ),
+            Self::Longformer(ref model) => (
                 model
                     .forward_t(
                         input_ids,
@@ -527,14 +661,16 @@ impl SequenceClassificationOption {
                         train,
                     )
                     .expect("Error in Longformer forward pass.")
-                    .logits
-            }
-            Self::FNet(ref model) => {
+                    .logits,
+                None,
+            ),
+            Self::FNet(ref model) => (
                 model
                     .forward_t(input_ids, token_type_ids, position_ids, input_embeds, train)
                     .expect("Error in FNet forward pass.")
-                    .logits
-            }
+                    .logits,
+                None,
+            ),
         }
     }
 }
@@ -546,6 +682,9 @@ pub struct SequenceClassificationModel {
     label_mapping: HashMap<i64, String>,
     var_store: VarStore,
     max_length: usize,
+    sliding_window: Option<SlidingWindowConfig>,
+    calibrator: Option<Box<dyn Calibrator>>,
+    output_cache: Option<OutputCache>,
 }
 
 impl SequenceClassificationModel {
@@ -568,15 +707,12 @@ impl SequenceClassificationModel {
     pub fn new(
         config: SequenceClassificationConfig,
     ) -> Result<SequenceClassificationModel, RustBertError> {
-        let config_path = config.config_resource.get_local_path()?;
         let vocab_path = config.vocab_resource.get_local_path()?;
-        let weights_path = config.model_resource.get_local_path()?;
         let merges_path = if let Some(merges_resource) = &config.merges_resource {
             Some(merges_resource.get_local_path()?)
         } else {
             None
         };
-        let device = config.device;
 
         let tokenizer = TokenizerOption::from_file(
             config.model_type,
@@ -586,6 +722,52 @@ impl SequenceClassificationModel {
             config.strip_accents,
             config.add_prefix_space,
         )?;
+        Self::new_with_tokenizer(config, tokenizer)
+    }
+
+    /// Build a new `SequenceClassificationModel` with a provided tokenizer (e.g. a HuggingFace
+    /// tokenizer loaded from a `tokenizer.json` file). The tokenizer resources of the configuration
+    /// are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `SequenceClassificationConfig` object containing the resource references (model, vocabulary, configuration) and device placement (CPU/GPU)
+    /// * `tokenizer` - `TokenizerOption` tokenizer to use for sequence classification
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::common::{ModelType, TokenizerOption};
+    /// use rust_bert::pipelines::sequence_classification::SequenceClassificationModel;
+    ///
+    /// let tokenizer = TokenizerOption::from_file(
+    ///     ModelType::DistilBert,
+    ///     "path/to/vocab.txt",
+    ///     None,
+    ///     true,
+    ///     None,
+    ///     None,
+    /// )?;
+    /// let model = SequenceClassificationModel::new_with_tokenizer(Default::default(), tokenizer)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new_with_tokenizer(
+        config: SequenceClassificationConfig,
+        tokenizer: TokenizerOption,
+    ) -> Result<SequenceClassificationModel, RustBertError> {
+        let _span = trace_span!(
+            INFO,
+            "load_model",
+            pipeline = "sequence_classification",
+            model_type = ?config.model_type,
+            device = ?config.device
+        );
+        let config_path = config.config_resource.get_local_path()?;
+        let weights_path = config.model_resource.get_local_path()?;
+        let device = config.device;
+
         let mut var_store = VarStore::new(device);
         let model_config = ConfigOption::from_file(config.model_type, config_path);
         let max_length = model_config
@@ -595,6 +777,14 @@ impl SequenceClassificationModel {
         let sequence_classifier =
             SequenceClassificationOption::new(config.model_type, &var_store.root(), &model_config)?;
         let label_mapping = model_config.get_label_mapping().clone();
+        if let Some(sliding_window) = &config.sliding_window {
+            if sliding_window.stride >= max_length / 2 {
+                return Err(RustBertError::InvalidConfigurationError(format!(
+                    "The sliding window stride ({}) should be lower than half of the model maximum length ({})",
+                    sliding_window.stride, max_length
+                )));
+            }
+        }
         var_store.load(weights_path)?;
         Ok(SequenceClassificationModel {
             tokenizer,
@@ -602,19 +792,133 @@ impl SequenceClassificationModel {
             label_mapping,
             var_store,
             max_length,
+            sliding_window: config.sliding_window,
+            calibrator: None,
+            output_cache: None,
         })
     }
 
-    fn prepare_for_model<'a, S>(&self, input: S) -> Tensor
+    /// Registers additional tokens (e.g. special markers or domain-specific terms) on the tokenizer
+    /// of the model. The added tokens are never split by the tokenizer, and the token embeddings of
+    /// the model are resized, the new embeddings being initialized with the mean embedding of the
+    /// existing vocabulary.
+    ///
+    /// # Arguments
+    ///
+    /// * `tokens` - tokens to add (tokens already part of the vocabulary are ignored)
+    pub fn with_added_tokens<S: AsRef<str>>(
+        mut self,
+        tokens: &[S],
+    ) -> Result<SequenceClassificationModel, RustBertError> {
+        self.tokenizer = add_tokens_and_resize_embeddings(self.tokenizer, &self.var_store, tokens)?;
+        Ok(self)
+    }
+
+    /// Sets a calibrator converting the logits of the model to calibrated probabilities. The
+    /// calibrator replaces the softmax (or sigmoid) normalization of all prediction methods.
+    ///
+    /// # Arguments
+    ///
+    /// * `calibrator` - `Calibrator` fitted on a validation set, e.g. a `TemperatureScaling` fitted on the output of `predict_logits`
+    pub fn with_calibrator<C>(mut self, calibrator: C) -> SequenceClassificationModel
+    where
+        C: Calibrator + 'static,
+    {
+        self.calibrator = Some(Box::new(calibrator));
+        self
+    }
+
+    /// Enables a cache of the pair scores keyed by the hash of the encoded text pairs, so that
+    /// pairs scored repeatedly by `predict_pair_scores` (e.g. cross-encoder reranking of the same
+    /// documents for recurring queries) are only encoded once. See `OutputCache` for details.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - maximum number of text pair outputs kept in the cache
+    pub fn with_output_cache(mut self, capacity: usize) -> SequenceClassificationModel {
+        self.output_cache = Some(OutputCache::new(capacity));
+        self
+    }
+
+    fn normalize(&self, logits: &Tensor, normalization: ScoreNormalization) -> Tensor {
+        match (&self.calibrator, normalization) {
+            (Some(calibrator), _) => calibrator.calibrate(logits, normalization),
+            (None, ScoreNormalization::Softmax) => logits.softmax(-1, Kind::Float),
+            (None, ScoreNormalization::Sigmoid) => logits.sigmoid(),
+        }
+    }
+
+    fn encode_windows(&self, input: &[&str], stride: usize) -> (Vec<TokenizedInput>, Vec<usize>) {
+        let num_added_tokens = self
+            .tokenizer
+            .build_input_with_special_tokens(
+                TokenIdsWithOffsets {
+                    ids: vec![],
+                    offsets: vec![],
+                    reference_offsets: vec![],
+                    masks: vec![],
+                },
+                None,
+            )
+            .token_ids
+            .len();
+        let window_length = self.max_length.saturating_sub(num_added_tokens);
+        let window_step = window_length.saturating_sub(stride).max(1);
+
+        let mut windows = vec![];
+        let mut windows_per_input = Vec::with_capacity(input.len());
+        for text in input {
+            let tokens = self.tokenizer.tokenize_with_offsets(text);
+            let ids = self.tokenizer.convert_tokens_to_ids(&tokens.tokens);
+            let spans = window_spans(ids.len(), window_length, window_step);
+            windows_per_input.push(spans.len());
+            for (start_token, end_token) in spans {
+                let window = TokenIdsWithOffsets {
+                    ids: ids[start_token..end_token].to_vec(),
+                    offsets: tokens.offsets[start_token..end_token].to_vec(),
+                    reference_offsets: tokens.reference_offsets[start_token..end_token].to_vec(),
+                    masks: tokens.masks[start_token..end_token].to_vec(),
+                };
+                windows.push(self.tokenizer.build_input_with_special_tokens(window, None));
+            }
+        }
+        (windows, windows_per_input)
+    }
+
+    fn aggregate_windows(
+        &self,
+        logits: Tensor,
+        windows_per_input: &[usize],
+        multilabel: bool,
+    ) -> Tensor {
+        match &self.sliding_window {
+            Some(sliding_window) => aggregate_window_logits(
+                &logits,
+                windows_per_input,
+                sliding_window.aggregation,
+                multilabel,
+            ),
+            None => logits,
+        }
+    }
+
+    fn prepare_for_model<'a, S>(&self, input: S) -> (Tensor, Tensor, Vec<usize>)
     where
         S: AsRef<[&'a str]>,
     {
-        let tokenized_input: Vec<TokenizedInput> = self.tokenizer.encode_list(
-            input.as_ref(),
-            self.max_length,
-            &TruncationStrategy::LongestFirst,
-            0,
-        );
+        let (tokenized_input, windows_per_input) = match &self.sliding_window {
+            Some(sliding_window) => self.encode_windows(input.as_ref(), sliding_window.stride),
+            None => {
+                let tokenized_input: Vec<TokenizedInput> = self.tokenizer.encode_list(
+                    input.as_ref(),
+                    self.max_length,
+                    &TruncationStrategy::LongestFirst,
+                    0,
+                );
+                let windows_per_input = vec![1; tokenized_input.len()];
+                (tokenized_input, windows_per_input)
+            }
+        };
         let max_len = tokenized_input
             .iter()
             .map(|input| input.token_ids.len())
@@ -624,14 +928,24 @@ impl SequenceClassificationModel {
             .tokenizer
             .get_pad_id()
             .expect("The Tokenizer used for sequence classification should contain a PAD id");
-        let tokenized_input_tensors: Vec<tch::Tensor> = tokenized_input
-            .into_iter()
-            .map(|mut input| {
-                input.token_ids.resize(max_len, pad_id);
-                Tensor::of_slice(&(input.token_ids))
-            })
-            .collect::<Vec<_>>();
-        Tensor::stack(tokenized_input_tensors.as_slice(), 0).to(self.var_store.device())
+        let (tokenized_input_tensors, attention_masks): (Vec<Tensor>, Vec<Tensor>) =
+            tokenized_input
+                .into_iter()
+                .map(|mut input| {
+                    let mut attention_mask = vec![1; input.token_ids.len()];
+                    attention_mask.resize(max_len, 0);
+                    input.token_ids.resize(max_len, pad_id);
+                    (
+                        Tensor::of_slice(&(input.token_ids)),
+                        Tensor::of_slice(&attention_mask),
+                    )
+                })
+                .unzip();
+        (
+            Tensor::stack(tokenized_input_tensors.as_slice(), 0).to(self.var_store.device()),
+            Tensor::stack(attention_masks.as_slice(), 0).to(self.var_store.device()),
+            windows_per_input,
+        )
     }
 
     /// Classify texts
@@ -664,17 +978,21 @@ impl SequenceClassificationModel {
     where
         S: AsRef<[&'a str]>,
     {
-        let input_tensor = self.prepare_for_model(input.as_ref());
+        let (input_tensor, attention_mask, windows_per_input) =
+            self.prepare_for_model(input.as_ref());
         let output = no_grad(|| {
             let output = self.sequence_classifier.forward_t(
                 Some(&input_tensor),
-                None,
+                Some(&attention_mask),
                 None,
                 None,
                 None,
                 false,
             );
-            output.softmax(-1, Kind::Float).detach().to(Device::Cpu)
+            let output = self.aggregate_windows(output, &windows_per_input, false);
+            self.normalize(&output, ScoreNormalization::Softmax)
+                .detach()
+                .to(Device::Cpu)
         });
         let label_indices = output.as_ref().argmax(-1, true).squeeze_dim(1);
         let scores = output
@@ -701,6 +1019,80 @@ impl SequenceClassificationModel {
         labels
     }
 
+    /// Classify texts, returning the scores for all labels
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - `&[&str]` Array of texts to classify.
+    /// * `normalization` - `ScoreNormalization` applied to the logits (softmax for mutually exclusive labels, sigmoid for multi-label classification)
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Vec<Label>>` containing, for each input text, a label for every class of the model sorted by decreasing score
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// # use rust_bert::pipelines::sequence_classification::{SequenceClassificationModel, ScoreNormalization};
+    ///
+    /// let sequence_classification_model =  SequenceClassificationModel::new(Default::default())?;
+    /// let input = [
+    ///     "Probably my all-time favorite movie, a story of selflessness, sacrifice and dedication to a noble cause, but it's not preachy or boring.",
+    ///     "This film tried to be too many things all at once: stinging political satire, Hollywood blockbuster, sappy romantic comedy, family values promo...",
+    /// ];
+    /// let output = sequence_classification_model.predict_full(&input, ScoreNormalization::Softmax);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn predict_full<'a, S>(
+        &self,
+        input: S,
+        normalization: ScoreNormalization,
+    ) -> Vec<Vec<Label>>
+    where
+        S: AsRef<[&'a str]>,
+    {
+        let (input_tensor, attention_mask, windows_per_input) =
+            self.prepare_for_model(input.as_ref());
+        let output = no_grad(|| {
+            let output = self.sequence_classifier.forward_t(
+                Some(&input_tensor),
+                Some(&attention_mask),
+                None,
+                None,
+                None,
+                false,
+            );
+            let multilabel = normalization == ScoreNormalization::Sigmoid;
+            let output = self.aggregate_windows(output, &windows_per_input, multilabel);
+            self.normalize(&output, normalization)
+                .detach()
+                .to(Device::Cpu)
+        });
+
+        let (num_sentences, num_labels) = (output.size()[0], output.size()[1]);
+        let mut labels: Vec<Vec<Label>> = Vec::with_capacity(num_sentences as usize);
+        for sentence_idx in 0..num_sentences {
+            let scores = output
+                .get(sentence_idx)
+                .iter::<f64>()
+                .unwrap()
+                .collect::<Vec<f64>>();
+            let mut sequence_labels = (0..num_labels)
+                .map(|id| Label {
+                    text: self.label_mapping.get(&id).unwrap().clone(),
+                    score: scores[id as usize],
+                    id,
+                    sentence: sentence_idx as usize,
+                })
+                .collect::<Vec<Label>>();
+            sequence_labels.sort_by(|a, b| b.score.total_cmp(&a.score));
+            labels.push(sequence_labels);
+        }
+        labels
+    }
+
     /// Multi-label classification of texts
     ///
     /// # Arguments
@@ -733,17 +1125,20 @@ impl SequenceClassificationModel {
         input: &[&str],
         threshold: f64,
     ) -> Result<Vec<Vec<Label>>, RustBertError> {
-        let input_tensor = self.prepare_for_model(input);
+        let (input_tensor, attention_mask, windows_per_input) = self.prepare_for_model(input);
         let output = no_grad(|| {
             let output = self.sequence_classifier.forward_t(
                 Some(&input_tensor),
-                None,
+                Some(&attention_mask),
                 None,
                 None,
                 None,
                 false,
             );
-            output.sigmoid().detach().to(Device::Cpu)
+            let output = self.aggregate_windows(output, &windows_per_input, true);
+            self.normalize(&output, ScoreNormalization::Sigmoid)
+                .detach()
+                .to(Device::Cpu)
         });
         let label_indices = output.as_ref().ge(threshold).nonzero();
 
@@ -776,6 +1171,456 @@ impl SequenceClassificationModel {
         }
         Ok(labels)
     }
+
+    /// Returns the raw classification logits of texts, before normalization and calibration. The
+    /// logits of a labeled validation set are used to fit a `Calibrator`.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - `&[&str]` Array of texts to classify.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Vec<f64>>` logits of each label (by label id) for each input text
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// # use rust_bert::pipelines::sequence_classification::SequenceClassificationModel;
+    ///
+    /// let sequence_classification_model = SequenceClassificationModel::new(Default::default())?;
+    /// let input = ["This movie was fantastic!", "I will never watch it again."];
+    /// let logits = sequence_classification_model.predict_logits(&input);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn predict_logits<'a, S>(&self, input: S) -> Vec<Vec<f64>>
+    where
+        S: AsRef<[&'a str]>,
+    {
+        let (input_tensor, attention_mask, windows_per_input) =
+            self.prepare_for_model(input.as_ref());
+        let output = no_grad(|| {
+            let output = self.sequence_classifier.forward_t(
+                Some(&input_tensor),
+                Some(&attention_mask),
+                None,
+                None,
+                None,
+                false,
+            );
+            self.aggregate_windows(output, &windows_per_input, false)
+                .to_kind(Kind::Double)
+                .detach()
+                .to(Device::Cpu)
+        });
+        (0..output.size()[0])
+            .map(|sentence_idx| Vec::<f64>::from(output.get(sentence_idx)))
+            .collect()
+    }
+
+    /// Scores text pairs with a regression head (single output), e.g. for cross-encoders or
+    /// reference-free translation quality estimation models
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - `&[(&str, &str)]` Array of text pairs to score.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<f64>, RustBertError>` raw regression output for each text pair
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// # use rust_bert::pipelines::sequence_classification::SequenceClassificationModel;
+    ///
+    /// let sequence_classification_model = SequenceClassificationModel::new(Default::default())?;
+    /// let input = [("The cat sat on the mat.", "Le chat était assis sur le tapis.")];
+    /// let output = sequence_classification_model.predict_pair_scores(&input)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn predict_pair_scores(&self, input: &[(&str, &str)]) -> Result<Vec<f64>, RustBertError> {
+        if self.label_mapping.len() != 1 {
+            return Err(RustBertError::InvalidConfigurationError(format!(
+                "Pair scoring requires a regression head with a single output, got {} labels",
+                self.label_mapping.len()
+            )));
+        }
+        let encoded_inputs = EncodedInputs::from_text_pairs(
+            &self.tokenizer,
+            input,
+            self.max_length,
+            self.var_store.device(),
+        )?;
+
+        let output = no_grad(|| {
+            let forward =
+                |input_ids: &Tensor, attention_mask: &Tensor, token_type_ids: Option<&Tensor>| {
+                    self.sequence_classifier.forward_t(
+                        Some(input_ids),
+                        Some(attention_mask),
+                        token_type_ids,
+                        None,
+                        None,
+                        false,
+                    )
+                };
+            match &self.output_cache {
+                Some(output_cache) => output_cache.forward(&encoded_inputs, forward),
+                None => forward(
+                    &encoded_inputs.input_ids,
+                    &encoded_inputs.attention_mask,
+                    encoded_inputs.token_type_ids.as_ref(),
+                ),
+            }
+            .select(1, 0)
+            .to_kind(Kind::Double)
+            .to(Device::Cpu)
+        });
+        Ok(Vec::<f64>::from(output))
+    }
+
+    /// Returns the input embedding matrix of the model, used to compute gradients with respect to
+    /// the input embeddings
+    fn word_embeddings(&self) -> Result<Tensor, RustBertError> {
+        let unsupported_error = || {
+            RustBertError::InvalidConfigurationError(format!(
+                "Gradient x input saliency is not supported for {:?} models",
+                self.sequence_classifier.model_type()
+            ))
+        };
+        if matches!(
+            self.sequence_classifier,
+            SequenceClassificationOption::Bart(_) | SequenceClassificationOption::Reformer(_)
+        ) {
+            return Err(unsupported_error());
+        }
+        let mut word_embeddings = self
+            .var_store
+            .variables()
+            .into_iter()
+            .filter(|(name, _)| name.ends_with("embeddings.word_embeddings.weight"));
+        match (word_embeddings.next(), word_embeddings.next()) {
+            (Some((_, word_embeddings)), None) => Ok(word_embeddings),
+            _ => Err(unsupported_error()),
+        }
+    }
+
+    /// Computes the importance of each token of the inputs for the label predicted by the model
+    /// (see the [`saliency`](crate::pipelines::saliency) module). Inputs are truncated to the
+    /// maximum length of the model.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - `&[&str]` Array of texts to classify.
+    /// * `method` - `SaliencyMethod` used to attribute the predictions to the tokens. Attention rollout requires a model configuration enabling `output_attentions`
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<Saliency>, RustBertError>` containing, for each input text, the predicted label and the scores of its tokens
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// # use rust_bert::pipelines::sequence_classification::SequenceClassificationModel;
+    /// use rust_bert::pipelines::saliency::SaliencyMethod;
+    ///
+    /// let sequence_classification_model = SequenceClassificationModel::new(Default::default())?;
+    /// let input = ["The plot was dull but the acting was superb."];
+    /// let output =
+    ///     sequence_classification_model.saliency(&input, SaliencyMethod::GradientTimesInput)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn saliency<'a, S>(
+        &self,
+        input: S,
+        method: SaliencyMethod,
+    ) -> Result<Vec<Saliency>, RustBertError>
+    where
+        S: AsRef<[&'a str]>,
+    {
+        let word_embeddings = match method {
+            SaliencyMethod::GradientTimesInput => Some(self.word_embeddings()?),
+            SaliencyMethod::AttentionRollout => None,
+        };
+        let tokenized_input = self.tokenizer.encode_list(
+            input.as_ref(),
+            self.max_length,
+            &TruncationStrategy::LongestFirst,
+            0,
+        );
+
+        let mut output = Vec::with_capacity(tokenized_input.len());
+        for (sentence, tokenized_input) in tokenized_input.into_iter().enumerate() {
+            let input_ids =
+                Tensor::of_slice(&tokenized_input.token_ids).to(self.var_store.device());
+            // Inputs are processed one by one: no padding token dilutes the scores
+            let input_embeds = word_embeddings.as_ref().map(|word_embeddings| {
+                no_grad(|| word_embeddings.index_select(0, &input_ids))
+                    .unsqueeze(0)
+                    .set_requires_grad(true)
+            });
+            let (logits, all_attentions) = match &input_embeds {
+                Some(input_embeds) => self.sequence_classifier.forward_t_with_attentions(
+                    None,
+                    None,
+                    None,
+                    None,
+                    Some(input_embeds),
+                    false,
+                ),
+                None => no_grad(|| {
+                    self.sequence_classifier.forward_t_with_attentions(
+                        Some(&input_ids.unsqueeze(0)),
+                        None,
+                        None,
+                        None,
+                        None,
+                        false,
+                    )
+                }),
+            };
+            let probabilities = no_grad(|| {
+                self.normalize(&logits.detach(), ScoreNormalization::Softmax)
+                    .get(0)
+                    .to(Device::Cpu)
+            });
+            let label_id = i64::from(probabilities.argmax(-1, false));
+
+            let scores = match input_embeds {
+                Some(input_embeds) => {
+                    let gradients = Tensor::run_backward(
+                        &[logits.get(0).get(label_id)],
+                        &[&input_embeds],
+                        false,
+                        false,
+                    );
+                    gradient_times_input(&input_embeds.get(0).detach(), &gradients[0].get(0))
+                }
+                None => {
+                    let all_attentions = all_attentions.ok_or_else(|| {
+                        RustBertError::InvalidConfigurationError(
+                            "Attention rollout requires a model returning its attention weights \
+                            (`output_attentions` enabled in the model configuration)"
+                                .to_string(),
+                        )
+                    })?;
+                    let attentions = all_attentions
+                        .iter()
+                        .map(|attention| attention.get(0))
+                        .collect::<Vec<Tensor>>();
+                    attention_rollout(&attentions)
+                }
+            };
+            let scores = Vec::<f32>::from(scores.to(Device::Cpu));
+
+            let tokens = tokenized_input
+                .token_ids
+                .iter()
+                .zip(tokenized_input.token_offsets.iter())
+                .zip(scores.iter())
+                .map(|((&token_id, &offset), &score)| TokenSaliency {
+                    token: self.tokenizer.decode(&[token_id], false, false),
+                    offset,
+                    score: score as f64,
+                })
+                .collect();
+            output.push(Saliency {
+                label: Label {
+                    text: self.label_mapping.get(&label_id).unwrap().clone(),
+                    score: f64::from(probabilities.get(label_id)),
+                    id: label_id,
+                    sentence,
+                },
+                tokens,
+            });
+        }
+        Ok(output)
+    }
+
+    /// Classifies a text and counterfactual variants of it, where spans of the text are masked,
+    /// removed or substituted, and reports the changes of the output distribution (see the
+    /// [`counterfactual`](crate::pipelines::counterfactual) module). The texts are classified in
+    /// batches and truncated to the maximum length of the model.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - original text
+    /// * `counterfactuals` - `&[Counterfactual]` edits of the original text to probe
+    /// * `batch_size` - maximum number of texts classified at once
+    ///
+    /// # Returns
+    ///
+    /// * `Result<CounterfactualReport, RustBertError>` - prediction for the original text and for each counterfactual, error if a counterfactual is invalid for the text
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// # use rust_bert::pipelines::sequence_classification::SequenceClassificationModel;
+    /// use rust_bert::pipelines::counterfactual::Counterfactual;
+    ///
+    /// let sequence_classification_model = SequenceClassificationModel::new(Default::default())?;
+    /// let text = "The service was slow but the food was great.";
+    /// let report = sequence_classification_model.probe_counterfactuals(
+    ///     text,
+    ///     &[Counterfactual::substitute(16, 20, "fast"), Counterfactual::mask(38, 43)],
+    ///     8,
+    /// )?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn probe_counterfactuals(
+        &self,
+        text: &str,
+        counterfactuals: &[Counterfactual],
+        batch_size: usize,
+    ) -> Result<CounterfactualReport, RustBertError> {
+        let mask_token = self
+            .tokenizer
+            .get_mask_id()
+            .map(|mask_id| self.tokenizer.decode(&[mask_id], false, false));
+        let mut texts = Vec::with_capacity(counterfactuals.len() + 1);
+        texts.push(text.to_string());
+        for counterfactual in counterfactuals {
+            texts.push(counterfactual.apply(text, mask_token.as_deref())?);
+        }
+
+        let mut all_scores: Vec<Vec<f64>> = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(batch_size.max(1)) {
+            let encoded_inputs = EncodedInputs::from_texts(
+                &self.tokenizer,
+                batch,
+                self.max_length,
+                self.var_store.device(),
+            )?;
+            let output = no_grad(|| {
+                let logits = self.sequence_classifier.forward_t(
+                    Some(&encoded_inputs.input_ids),
+                    Some(&encoded_inputs.attention_mask),
+                    encoded_inputs.token_type_ids.as_ref(),
+                    None,
+                    None,
+                    false,
+                );
+                self.normalize(&logits, ScoreNormalization::Softmax)
+                    .to_kind(Kind::Double)
+                    .to(Device::Cpu)
+            });
+            all_scores
+                .extend((0..output.size()[0]).map(|index| Vec::<f64>::from(output.get(index))));
+        }
+
+        let top_label = |scores: &[f64]| {
+            let (id, score) = scores
+                .iter()
+                .enumerate()
+                .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+                .map(|(id, score)| (id as i64, *score))
+                .unwrap();
+            Label {
+                text: self.label_mapping.get(&id).unwrap().clone(),
+                score,
+                id,
+                sentence: 0,
+            }
+        };
+        let mut all_scores = all_scores.into_iter();
+        let scores = all_scores.next().unwrap();
+        let label = top_label(&scores);
+        let counterfactuals = texts
+            .into_iter()
+            .skip(1)
+            .zip(all_scores)
+            .map(|(text, counterfactual_scores)| {
+                let counterfactual_label = top_label(&counterfactual_scores);
+                CounterfactualOutput {
+                    text,
+                    label_changed: counterfactual_label.id != label.id,
+                    label: counterfactual_label,
+                    score_changes: counterfactual_scores
+                        .iter()
+                        .zip(scores.iter())
+                        .map(|(counterfactual_score, score)| counterfactual_score - score)
+                        .collect(),
+                    total_variation: total_variation(&scores, &counterfactual_scores),
+                    kl_divergence: kl_divergence(&scores, &counterfactual_scores),
+                    scores: counterfactual_scores,
+                }
+            })
+            .collect();
+        Ok(CounterfactualReport {
+            label,
+            scores,
+            counterfactuals,
+        })
+    }
+}
+
+/// Returns the labels with a score above their threshold, using the label specific threshold when
+/// available and the default threshold otherwise
+pub(crate) fn labels_above_threshold(
+    scores: &[Label],
+    default_threshold: f64,
+    thresholds: &HashMap<String, f64>,
+) -> Vec<Label> {
+    scores
+        .iter()
+        .filter(|label| {
+            label.score
+                >= *thresholds
+                    .get(label.text.as_str())
+                    .unwrap_or(&default_threshold)
+        })
+        .cloned()
+        .collect()
+}
+
+/// Start and end token positions of the windows covering a sequence of `num_tokens` tokens. A
+/// sequence shorter than the window length (including an empty sequence) is covered by a single
+/// window, and the last window ends with the sequence.
+fn window_spans(
+    num_tokens: usize,
+    window_length: usize,
+    window_step: usize,
+) -> Vec<(usize, usize)> {
+    let mut spans = vec![];
+    let mut start_token = 0;
+    loop {
+        let end_token = min(start_token.saturating_add(window_length), num_tokens);
+        spans.push((start_token, end_token));
+        if end_token == num_tokens {
+            break;
+        }
+        start_token += window_step;
+    }
+    spans
+}
+
+/// Aggregates the logits of consecutive windows (`windows_per_input` windows for each input) into
+/// a single row of logits per input
+fn aggregate_window_logits(
+    logits: &Tensor,
+    windows_per_input: &[usize],
+    aggregation: WindowAggregation,
+    multilabel: bool,
+) -> Tensor {
+    let mut start = 0;
+    let aggregated_logits = windows_per_input
+        .iter()
+        .map(|&num_windows| {
+            let window_logits = logits.narrow(0, start, num_windows as i64);
+            start += num_windows as i64;
+            aggregation.aggregate(&window_logits, multilabel)
+        })
+        .collect::<Vec<Tensor>>();
+    Tensor::stack(&aggregated_logits, 0)
 }
 
 #[cfg(test)]
@@ -788,4 +1633,55 @@ mod test {
         let config = SequenceClassificationConfig::default();
         let _: Box<dyn Send> = Box::new(SequenceClassificationModel::new(config));
     }
+
+    #[test]
+    fn window_spans_shorter_than_window() {
+        assert_eq!(window_spans(5, 8, 6), vec![(0, 5)]);
+        assert_eq!(window_spans(8, 8, 6), vec![(0, 8)]);
+        assert_eq!(window_spans(0, 8, 6), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn window_spans_exact_multiple_of_step() {
+        //    Window of 8 tokens with a stride of 2: windows start every 6 tokens
+        assert_eq!(window_spans(20, 8, 6), vec![(0, 8), (6, 14), (12, 20)]);
+    }
+
+    #[test]
+    fn window_spans_trailing_partial_window() {
+        assert_eq!(window_spans(16, 8, 6), vec![(0, 8), (6, 14), (12, 16)]);
+        assert_eq!(window_spans(9, 8, 6), vec![(0, 8), (6, 9)]);
+    }
+
+    #[test]
+    fn aggregated_windows_one_row_per_input() {
+        let windows_per_input = [1, 3, 2];
+        let logits = Tensor::of_slice(&[
+            1.0f32, 0.0, //
+            0.0, 1.0, 0.5, 0.5, 2.0, 0.0, //
+            0.0, 3.0, 1.0, 1.0,
+        ])
+        .view((6, 2));
+        for &aggregation in [
+            WindowAggregation::Mean,
+            WindowAggregation::Max,
+            WindowAggregation::Vote,
+        ]
+        .iter()
+        {
+            for &multilabel in [false, true].iter() {
+                let aggregated =
+                    aggregate_window_logits(&logits, &windows_per_input, aggregation, multilabel);
+                assert_eq!(aggregated.size(), vec![3, 2]);
+            }
+        }
+        let mean =
+            aggregate_window_logits(&logits, &windows_per_input, WindowAggregation::Mean, false);
+        let mean = Vec::<f32>::from(mean.view(-1));
+        let expected = [1.0, 0.0, 2.5 / 3.0, 1.5 / 3.0, 0.5, 2.0];
+        assert!(mean
+            .iter()
+            .zip(expected.iter())
+            .all(|(value, expected)| (value - expected).abs() < 1e-6));
+    }
 }
@@ -0,0 +1,335 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Sparse lexical embeddings pipeline (SPLADE)
+//! Computes sparse, vocabulary-sized representations of texts from the masked language modelling
+//! head of a model, as proposed in [SPLADE: Sparse Lexical and Expansion Model for First Stage Ranking](https://arxiv.org/abs/2107.05720).
+//! The weight of each vocabulary term is obtained by log-saturation of the masked language model
+//! logits (`log(1 + relu(logits))`) followed by a max pooling over the input tokens. Only the
+//! terms with a positive weight are returned as (term id, weight) pairs, which can be stored in an
+//! inverted index for retrieval. The relevance of a document for a query is given by the dot
+//! product of their sparse embeddings.
+//!
+//! The pipeline complements the dense [`sentence_embeddings`](crate::pipelines::sentence_embeddings)
+//! pipeline and supports the models available for masked language modelling (BERT, RoBERTa,
+//! DeBERTa, FNet...) fine-tuned with the SPLADE objective.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::common::ModelType;
+//! use rust_bert::pipelines::masked_language::MaskedLanguageConfig;
+//! use rust_bert::pipelines::sparse_embeddings::{SparseEmbeddingsConfig, SparseEmbeddingsModel};
+//! use rust_bert::resources::LocalResource;
+//! use std::path::PathBuf;
+//!
+//! let masked_language_config = MaskedLanguageConfig::new(
+//!     ModelType::Bert,
+//!     LocalResource::from(PathBuf::from("path/to/splade/rust_model.ot")),
+//!     LocalResource::from(PathBuf::from("path/to/splade/config.json")),
+//!     LocalResource::from(PathBuf::from("path/to/splade/vocab.txt")),
+//!     None,
+//!     true,
+//!     None,
+//!     None,
+//!     None,
+//! );
+//! let model = SparseEmbeddingsModel::new(SparseEmbeddingsConfig::new(masked_language_config))?;
+//!
+//! let embeddings = model.encode(&[
+//!     "What is the capital of France?",
+//!     "Paris is the capital and most populous city of France.",
+//! ])?;
+//! let score = embeddings[0].dot(&embeddings[1]);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::common::embeddings::load_weights_with_tied_embeddings;
+use crate::common::error::RustBertError;
+use crate::pipelines::common::{ConfigOption, TokenizerOption};
+use crate::pipelines::masked_language::{MaskedLanguageConfig, MaskedLanguageOption};
+use rust_tokenizers::tokenizer::TruncationStrategy;
+use rust_tokenizers::TokenizedInput;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tch::nn::VarStore;
+use tch::{no_grad, Device, Kind, Tensor};
+
+#[derive(Serialize, Deserialize)]
+/// # Configuration for SparseEmbeddingsModel
+/// Contains the masked language model configuration and the sparsification settings.
+pub struct SparseEmbeddingsConfig {
+    /// `MaskedLanguageConfig` of the model producing the term weights (the `mask_token` is ignored)
+    pub masked_language_config: MaskedLanguageConfig,
+    /// Optional maximum number of terms returned for each input, keeping the terms with the highest weight (default: None, all terms with a positive weight are returned)
+    pub max_terms: Option<usize>,
+    /// Minimum weight for a term to be returned (default: 0.0)
+    pub min_weight: f32,
+}
+
+impl SparseEmbeddingsConfig {
+    /// Instantiate a new sparse embeddings configuration returning all terms with a positive weight
+    ///
+    /// # Arguments
+    ///
+    /// * `masked_language_config` - `MaskedLanguageConfig` of the model producing the term weights
+    pub fn new(masked_language_config: MaskedLanguageConfig) -> SparseEmbeddingsConfig {
+        SparseEmbeddingsConfig {
+            masked_language_config,
+            max_terms: None,
+            min_weight: 0.0,
+        }
+    }
+}
+
+impl From<MaskedLanguageConfig> for SparseEmbeddingsConfig {
+    fn from(masked_language_config: MaskedLanguageConfig) -> Self {
+        SparseEmbeddingsConfig::new(masked_language_config)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// # Vocabulary term of a sparse embedding
+pub struct SparseTerm {
+    /// Vocabulary index of the term
+    pub id: i64,
+    /// String representation of the term
+    pub text: String,
+    /// Weight of the term
+    pub weight: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// # Sparse embedding generated by a `SparseEmbeddingsModel`
+pub struct SparseEmbedding {
+    /// Terms with a non-zero weight, sorted by decreasing weight
+    pub terms: Vec<SparseTerm>,
+}
+
+impl SparseEmbedding {
+    /// Dot product with another sparse embedding (relevance score of a document for a query)
+    pub fn dot(&self, other: &SparseEmbedding) -> f32 {
+        let weights = self
+            .terms
+            .iter()
+            .map(|term| (term.id, term.weight))
+            .collect::<HashMap<i64, f32>>();
+        other
+            .terms
+            .iter()
+            .filter_map(|term| weights.get(&term.id).map(|weight| weight * term.weight))
+            .sum()
+    }
+}
+
+/// # SparseEmbeddingsModel to compute SPLADE sparse lexical representations
+pub struct SparseEmbeddingsModel {
+    tokenizer: TokenizerOption,
+    language_model: MaskedLanguageOption,
+    var_store: VarStore,
+    max_length: usize,
+    max_terms: Option<usize>,
+    min_weight: f32,
+}
+
+impl SparseEmbeddingsModel {
+    /// Build a new `SparseEmbeddingsModel`
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `SparseEmbeddingsConfig` object containing the resource references (model, vocabulary, configuration), sparsification settings and device placement (CPU/GPU)
+    pub fn new(config: SparseEmbeddingsConfig) -> Result<SparseEmbeddingsModel, RustBertError> {
+        if config.max_terms == Some(0) {
+            return Err(RustBertError::InvalidConfigurationError(
+                "max_terms must be strictly greater than 0".to_string(),
+            ));
+        }
+        let masked_language_config = config.masked_language_config;
+        let vocab_path = masked_language_config.vocab_resource.get_local_path()?;
+        let merges_path = if let Some(merges_resource) = &masked_language_config.merges_resource {
+            Some(merges_resource.get_local_path()?)
+        } else {
+            None
+        };
+        let tokenizer = TokenizerOption::from_file(
+            masked_language_config.model_type,
+            vocab_path.to_str().unwrap(),
+            merges_path.as_deref().map(|path| path.to_str().unwrap()),
+            masked_language_config.lower_case,
+            masked_language_config.strip_accents,
+            masked_language_config.add_prefix_space,
+        )?;
+
+        let config_path = masked_language_config.config_resource.get_local_path()?;
+        let weights_path = masked_language_config.model_resource.get_local_path()?;
+        let mut var_store = VarStore::new(masked_language_config.device);
+        let model_config = ConfigOption::from_file(masked_language_config.model_type, config_path);
+        let max_length = model_config
+            .get_max_len()
+            .map(|v| v as usize)
+            .unwrap_or(usize::MAX);
+        let language_model = MaskedLanguageOption::new(
+            masked_language_config.model_type,
+            &var_store.root(),
+            &model_config,
+        )?;
+        load_weights_with_tied_embeddings(&mut var_store, weights_path)?;
+
+        Ok(SparseEmbeddingsModel {
+            tokenizer,
+            language_model,
+            var_store,
+            max_length,
+            max_terms: config.max_terms,
+            min_weight: config.min_weight,
+        })
+    }
+
+    /// Returns the tokenizer of the model (e.g. to map term ids of an inverted index back to tokens)
+    pub fn get_tokenizer(&self) -> &TokenizerOption {
+        &self.tokenizer
+    }
+
+    /// Computes the term weights, outputs a `Tensor` of shape (batch size, vocabulary size)
+    pub fn encode_as_tensor<S>(&self, inputs: &[S]) -> Tensor
+    where
+        S: AsRef<str>,
+    {
+        let tokenized_input: Vec<TokenizedInput> = self.tokenizer.encode_list(
+            inputs,
+            self.max_length,
+            &TruncationStrategy::LongestFirst,
+            0,
+        );
+        let max_len = tokenized_input
+            .iter()
+            .map(|input| input.token_ids.len())
+            .max()
+            .unwrap_or(0);
+        let pad_id = self.tokenizer.get_pad_id().unwrap_or(0);
+        let (input_ids, attention_masks): (Vec<Tensor>, Vec<Tensor>) = tokenized_input
+            .into_iter()
+            .map(|input| {
+                let mut attention_mask = vec![1i64; input.token_ids.len()];
+                attention_mask.resize(max_len, 0);
+                let mut token_ids = input.token_ids;
+                token_ids.resize(max_len, pad_id);
+                (
+                    Tensor::of_slice(&token_ids),
+                    Tensor::of_slice(&attention_mask),
+                )
+            })
+            .unzip();
+        let device = self.var_store.device();
+        let input_ids = Tensor::stack(&input_ids, 0).to(device);
+        let attention_mask = Tensor::stack(&attention_masks, 0).to(device);
+
+        no_grad(|| {
+            let logits = self.language_model.forward_t(
+                Some(&input_ids),
+                Some(&attention_mask),
+                None,
+                None,
+                None,
+                None,
+                None,
+                false,
+            );
+            (logits.relu().log1p() * attention_mask.unsqueeze(-1).to_kind(logits.kind()))
+                .max_dim(1, false)
+                .0
+                .to_kind(Kind::Float)
+        })
+    }
+
+    /// Computes sparse embeddings
+    ///
+    /// # Arguments
+    ///
+    /// * `inputs` - slice of string-like input texts to encode
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<SparseEmbedding>` containing the terms with a non-zero weight for each input
+    pub fn encode<S>(&self, inputs: &[S]) -> Result<Vec<SparseEmbedding>, RustBertError>
+    where
+        S: AsRef<str>,
+    {
+        if inputs.is_empty() {
+            return Ok(vec![]);
+        }
+        let term_weights = self.encode_as_tensor(inputs).to(Device::Cpu);
+        let mut output = Vec::with_capacity(inputs.len());
+        for input_index in 0..inputs.len() as i64 {
+            let weights = term_weights.get(input_index);
+            let term_ids = weights
+                .gt(self.min_weight.max(0.0) as f64)
+                .nonzero()
+                .squeeze_dim(1);
+            let mut weights = weights.index_select(0, &term_ids);
+            let mut term_ids = term_ids;
+            if let Some(max_terms) = self.max_terms {
+                if (max_terms as i64) < weights.size()[0] {
+                    let (top_weights, top_indices) = weights.topk(max_terms as i64, 0, true, true);
+                    term_ids = term_ids.index_select(0, &top_indices);
+                    weights = top_weights;
+                }
+            }
+            let mut terms = term_ids
+                .iter::<i64>()?
+                .zip(weights.iter::<f64>()?)
+                .map(|(id, weight)| SparseTerm {
+                    id,
+                    text: self.tokenizer.decode(&[id], false, true),
+                    weight: weight as f32,
+                })
+                .collect::<Vec<SparseTerm>>();
+            terms.sort_by(|a, b| b.weight.partial_cmp(&a.weight).unwrap());
+            output.push(SparseEmbedding { terms });
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sparse_embedding(terms: &[(i64, f32)]) -> SparseEmbedding {
+        SparseEmbedding {
+            terms: terms
+                .iter()
+                .map(|&(id, weight)| SparseTerm {
+                    id,
+                    text: id.to_string(),
+                    weight,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn sparse_dot_product() {
+        let query = sparse_embedding(&[(1, 2.0), (5, 0.5), (7, 1.0)]);
+        let document = sparse_embedding(&[(5, 2.0), (1, 1.5), (3, 4.0)]);
+
+        assert!((query.dot(&document) - 4.0).abs() < 1e-6);
+        assert!((document.dot(&query) - 4.0).abs() < 1e-6);
+        assert_eq!(query.dot(&sparse_embedding(&[(3, 1.0)])), 0.0);
+    }
+
+    #[test]
+    #[ignore] // no need to run, compilation is enough to verify it is Send
+    fn test() {
+        let config = SparseEmbeddingsConfig::new(MaskedLanguageConfig::default());
+        let _: Box<dyn Send> = Box::new(SparseEmbeddingsModel::new(config));
+    }
+}
@@ -0,0 +1,170 @@
+// Copyright 2020 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Stopping criteria for text generation
+//! Conditions evaluated after each decoding step, ending the generation of the call once one of
+//! them is met. The sequences generated so far are returned and flagged as `truncated`, in the
+//! same way as when the `max_time` or `max_total_new_tokens` budget of the `GenerateOptions` is
+//! exhausted. Criteria are registered in the `stopping_criteria` of the `GenerateConfig`, and apply
+//! to all the generation calls of the model:
+//! - `MaxTimeCriteria`: bounds the wall-clock duration of each generation call
+//! - `MaxNewTokensCriteria`: bounds the number of new tokens generated for each sequence
+//! - `DecodedTextCriteria`: user closure over the text generated so far
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::gpt2::GPT2Generator;
+//! use rust_bert::pipelines::generation_utils::{GenerateConfig, LanguageGenerator};
+//! use rust_bert::pipelines::stopping_criteria::{DecodedTextCriteria, MaxTimeCriteria};
+//! use std::time::Duration;
+//!
+//! let generate_config = GenerateConfig {
+//!     stopping_criteria: vec![
+//!         Box::new(MaxTimeCriteria::new(Duration::from_millis(500))),
+//!         Box::new(DecodedTextCriteria::new(|texts: &[String]| {
+//!             texts.iter().all(|text| text.contains('\n'))
+//!         })),
+//!     ],
+//!     ..Default::default()
+//! };
+//! let model = GPT2Generator::new(generate_config)?;
+//! let output = model.generate(Some(&["The dog"]), None);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::pipelines::common::TokenizerOption;
+use std::time::Duration;
+use tch::Tensor;
+
+/// # State of the generation after a decoding step
+pub struct StoppingState<'a> {
+    /// Token ids of the sequences (prompt and generated tokens) of shape (*number of sequences*, *current_length*).
+    /// For beam search, the sequences are the beams of each input.
+    pub input_ids: &'a Tensor,
+    /// Length of the prompt (or of the decoder start tokens for encoder-decoder models)
+    pub prompt_length: i64,
+    /// Current length of the sequences
+    pub current_length: i64,
+    /// Time elapsed since the start of the generation call
+    pub elapsed: Duration,
+    tokenizer: &'a TokenizerOption,
+}
+
+impl<'a> StoppingState<'a> {
+    pub(crate) fn new(
+        input_ids: &'a Tensor,
+        prompt_length: i64,
+        current_length: i64,
+        elapsed: Duration,
+        tokenizer: &'a TokenizerOption,
+    ) -> StoppingState<'a> {
+        StoppingState {
+            input_ids,
+            prompt_length,
+            current_length,
+            elapsed,
+            tokenizer,
+        }
+    }
+
+    /// Number of new tokens generated for each sequence
+    pub fn num_new_tokens(&self) -> i64 {
+        self.current_length - self.prompt_length
+    }
+
+    /// Decodes the text generated so far (excluding the prompt) for each sequence
+    pub fn generated_texts(&self) -> Vec<String> {
+        let generated_ids = self
+            .input_ids
+            .narrow(1, self.prompt_length, self.num_new_tokens());
+        (0..generated_ids.size()[0])
+            .map(|sequence_index| {
+                let token_ids = Vec::<i64>::from(generated_ids.get(sequence_index));
+                self.tokenizer.decode(&token_ids, true, true)
+            })
+            .collect()
+    }
+}
+
+/// # Condition ending the generation
+/// Criteria must be `Send + Sync` to be stored in the `GenerateConfig` of a generator.
+pub trait StoppingCriteria: Send + Sync {
+    /// Returns `true` if the generation should stop after the current decoding step
+    fn should_stop(&self, state: &StoppingState) -> bool;
+}
+
+/// # Maximum wall-clock duration of each generation call
+pub struct MaxTimeCriteria {
+    max_time: Duration,
+}
+
+impl MaxTimeCriteria {
+    /// Creates a new `MaxTimeCriteria` stopping the generation once `max_time` has elapsed
+    pub fn new(max_time: Duration) -> MaxTimeCriteria {
+        MaxTimeCriteria { max_time }
+    }
+}
+
+impl StoppingCriteria for MaxTimeCriteria {
+    fn should_stop(&self, state: &StoppingState) -> bool {
+        state.elapsed >= self.max_time
+    }
+}
+
+/// # Maximum number of new tokens generated for each sequence
+pub struct MaxNewTokensCriteria {
+    max_new_tokens: i64,
+}
+
+impl MaxNewTokensCriteria {
+    /// Creates a new `MaxNewTokensCriteria` stopping the generation once `max_new_tokens` tokens
+    /// were generated for each sequence
+    pub fn new(max_new_tokens: i64) -> MaxNewTokensCriteria {
+        MaxNewTokensCriteria { max_new_tokens }
+    }
+}
+
+impl StoppingCriteria for MaxNewTokensCriteria {
+    fn should_stop(&self, state: &StoppingState) -> bool {
+        state.num_new_tokens() >= self.max_new_tokens
+    }
+}
+
+/// # User predicate over the generated texts
+/// The predicate is called after each decoding step with the text generated so far for each
+/// sequence (see `StoppingState::generated_texts`). Decoding the sequences at each step has a cost
+/// that grows with the length of the generated text.
+pub struct DecodedTextCriteria<F>
+where
+    F: Fn(&[String]) -> bool + Send + Sync,
+{
+    predicate: F,
+}
+
+impl<F> DecodedTextCriteria<F>
+where
+    F: Fn(&[String]) -> bool + Send + Sync,
+{
+    /// Creates a new `DecodedTextCriteria` stopping the generation once `predicate` returns `true`
+    pub fn new(predicate: F) -> DecodedTextCriteria<F> {
+        DecodedTextCriteria { predicate }
+    }
+}
+
+impl<F> StoppingCriteria for DecodedTextCriteria<F>
+where
+    F: Fn(&[String]) -> bool + Send + Sync,
+{
+    fn should_stop(&self, state: &StoppingState) -> bool {
+        (self.predicate)(&state.generated_texts())
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Utilities for streaming generated text
+//! Decoding each generated token on its own does not produce valid text fragments for byte-level
+//! BPE tokenizers (e.g. GPT2 or RoBERTa): a multi-byte character (emoji, CJK text) may be split
+//! across several tokens, and decoding an incomplete character yields replacement characters
+//! (`�`). SentencePiece tokenizers also drop the leading space of a token decoded on its own.
+//!
+//! The `IncrementalDecoder` accumulates the generated token ids and decodes them within a sliding
+//! window of previous tokens, emitting a text fragment only once it forms valid text. The
+//! concatenation of the fragments matches the decoding of the full sequence.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::common::{ModelType, TokenizerOption};
+//! use rust_bert::pipelines::streaming::IncrementalDecoder;
+//!
+//! let tokenizer = TokenizerOption::from_file(
+//!     ModelType::GPT2,
+//!     "path/to/vocab.json",
+//!     Some("path/to/merges.txt"),
+//!     false,
+//!     None,
+//!     None,
+//! )?;
+//! let mut decoder = IncrementalDecoder::new(&tokenizer, true);
+//! for token_id in [10163, 8582, 236, 231] {
+//!     if let Some(text) = decoder.push(token_id) {
+//!         print!("{}", text);
+//!     }
+//! }
+//! if let Some(text) = decoder.finish() {
+//!     print!("{}", text);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::pipelines::common::TokenizerOption;
+
+const REPLACEMENT_CHARACTER: char = '\u{FFFD}';
+
+/// # Incremental decoder emitting valid text fragments from a stream of token ids
+pub struct IncrementalDecoder<'a> {
+    tokenizer: &'a TokenizerOption,
+    skip_special_tokens: bool,
+    token_ids: Vec<i64>,
+    prefix_offset: usize,
+    read_offset: usize,
+}
+
+impl<'a> IncrementalDecoder<'a> {
+    /// Creates a new incremental decoder
+    ///
+    /// # Arguments
+    ///
+    /// * `tokenizer` - `TokenizerOption` used to decode the tokens
+    /// * `skip_special_tokens` - flag indicating if special tokens should be removed from the output
+    pub fn new(tokenizer: &'a TokenizerOption, skip_special_tokens: bool) -> Self {
+        IncrementalDecoder {
+            tokenizer,
+            skip_special_tokens,
+            token_ids: vec![],
+            prefix_offset: 0,
+            read_offset: 0,
+        }
+    }
+
+    /// Sets the token ids preceding the streamed tokens (e.g. the prompt). They are not emitted but
+    /// provide the context needed to decode the first streamed tokens (e.g. leading spaces).
+    ///
+    /// # Arguments
+    ///
+    /// * `context_ids` - token ids preceding the streamed tokens
+    pub fn with_context(mut self, context_ids: &[i64]) -> Self {
+        self.token_ids = context_ids.to_vec();
+        self.read_offset = self.token_ids.len();
+        // A few context tokens are enough to decode the next token consistently
+        self.prefix_offset = self.read_offset.saturating_sub(5);
+        self
+    }
+
+    /// Returns all token ids received so far (including the context)
+    pub fn token_ids(&self) -> &[i64] {
+        &self.token_ids
+    }
+
+    /// Adds a token id and returns the new text fragment, if it forms valid text. Returns `None`
+    /// while the new tokens only contain an incomplete character or whitespace trimmed by the
+    /// tokenizer: the text is emitted with the following tokens.
+    ///
+    /// # Arguments
+    ///
+    /// * `token_id` - next generated token id
+    pub fn push(&mut self, token_id: i64) -> Option<String> {
+        self.token_ids.push(token_id);
+        let prefix_text = self.decode(self.prefix_offset, self.read_offset);
+        let new_text = self.decode(self.prefix_offset, self.token_ids.len());
+        if new_text.len() > prefix_text.len() && !new_text.ends_with(REPLACEMENT_CHARACTER) {
+            let fragment = new_text
+                .strip_prefix(prefix_text.as_str())
+                .or_else(|| new_text.get(prefix_text.len()..))?
+                .to_string();
+            self.prefix_offset = self.read_offset;
+            self.read_offset = self.token_ids.len();
+            Some(fragment)
+        } else {
+            None
+        }
+    }
+
+    /// Adds a sequence of token ids and returns the text fragments they form (possibly empty)
+    ///
+    /// # Arguments
+    ///
+    /// * `token_ids` - next generated token ids
+    pub fn push_all(&mut self, token_ids: &[i64]) -> String {
+        token_ids
+            .iter()
+            .filter_map(|token_id| self.push(*token_id))
+            .collect()
+    }
+
+    /// Returns the text not emitted yet at the end of the stream (e.g. an incomplete character,
+    /// decoded with replacement characters), if any.
+    pub fn finish(&mut self) -> Option<String> {
+        let prefix_text = self.decode(self.prefix_offset, self.read_offset);
+        let new_text = self.decode(self.prefix_offset, self.token_ids.len());
+        self.prefix_offset = self.read_offset;
+        self.read_offset = self.token_ids.len();
+        if new_text.len() > prefix_text.len() {
+            new_text
+                .strip_prefix(prefix_text.as_str())
+                .or_else(|| new_text.get(prefix_text.len()..))
+                .map(str::to_string)
+        } else {
+            None
+        }
+    }
+
+    fn decode(&self, start: usize, end: usize) -> String {
+        if start >= end {
+            return String::new();
+        }
+        self.tokenizer
+            .decode(&self.token_ids[start..end], self.skip_special_tokens, false)
+    }
+}
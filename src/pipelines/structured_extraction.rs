@@ -0,0 +1,518 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Structured output extraction pipeline
+//! Extracts a typed Rust value from free text using a text generation model. The expected output
+//! is described by a JSON schema, provided either directly (`JsonSchema`) or by the extracted type
+//! itself (implementing `ExtractionSchema`, for example from a schema derive). The model is
+//! prompted with the schema and the text, the generation is primed with the opening brace of a
+//! JSON object and stopped at the matching closing brace. The generated object is validated
+//! against the schema and deserialized with serde.
+//!
+//! Outputs that can not be parsed, do not match the schema or can not be deserialized are
+//! reported with a typed `StructuredExtractionError`. The extraction can be attempted several times
+//! before failing (useful with sampling enabled).
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::structured_extraction::{
+//!     ExtractionSchema, StructuredExtractionConfig, StructuredExtractionModel,
+//! };
+//! use serde::Deserialize;
+//! use serde_json::json;
+//!
+//! #[derive(Debug, Deserialize)]
+//! struct Person {
+//!     name: String,
+//!     age: Option<u32>,
+//! }
+//!
+//! impl ExtractionSchema for Person {
+//!     fn json_schema() -> serde_json::Value {
+//!         json!({
+//!             "type": "object",
+//!             "properties": {
+//!                 "name": {"type": "string"},
+//!                 "age": {"type": ["integer", "null"]}
+//!             },
+//!             "required": ["name"]
+//!         })
+//!     }
+//! }
+//!
+//! let model = StructuredExtractionModel::new(StructuredExtractionConfig::new(Default::default()))?;
+//! let people = model.extract_typed::<Person, _>(&["Amy, 32, moved to Paris last year."])?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::common::error::RustBertError;
+use crate::pipelines::prompt_template::PromptTemplate;
+use crate::pipelines::text_generation::{TextGenerationConfig, TextGenerationModel};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+/// Default prompt template, with `{schema}` and `{text}` placeholders
+pub const DEFAULT_EXTRACTION_PROMPT: &str =
+    "Extract the information from the text as a JSON object \
+following this JSON schema.\nSchema: {schema}\nText: {text}\nJSON:";
+
+#[derive(Error, Debug)]
+/// # Error returned by the `StructuredExtractionModel`
+pub enum StructuredExtractionError {
+    /// Error raised by the generation model
+    #[error("Generation error: {0}")]
+    GenerationError(#[from] RustBertError),
+
+    /// The generated output does not contain a complete JSON object
+    #[error("No complete JSON object generated: {output}")]
+    MissingJsonObject { output: String },
+
+    /// The generated object is not valid JSON
+    #[error("Invalid JSON generated ({error}): {output}")]
+    InvalidJson { output: String, error: String },
+
+    /// The generated object does not match the schema
+    #[error("Generated JSON does not match the schema ({}): {output}", errors.join("; "))]
+    SchemaViolation { output: String, errors: Vec<String> },
+
+    /// The generated object matches the schema but can not be deserialized into the target type
+    #[error("Generated JSON can not be deserialized ({error}): {output}")]
+    DeserializationError { output: String, error: String },
+}
+
+/// # Type providing the JSON schema of its serialized form
+/// Implemented by the types extracted with `StructuredExtractionModel::extract_typed`, manually or
+/// from the output of a schema derive.
+pub trait ExtractionSchema {
+    /// JSON schema of the type
+    fn json_schema() -> Value;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// # JSON schema describing the extracted values
+/// The following keywords are validated: `type` (single type or list of types), `enum`,
+/// `properties`, `required`, `additionalProperties` (boolean) and `items`. Other keywords are
+/// passed to the model as part of the prompt but not validated.
+pub struct JsonSchema(Value);
+
+impl JsonSchema {
+    /// Creates a new `JsonSchema`, returning an error if the schema is not a JSON object
+    ///
+    /// # Arguments
+    ///
+    /// * `schema` - JSON schema
+    pub fn new(schema: Value) -> Result<JsonSchema, RustBertError> {
+        if !schema.is_object() {
+            return Err(RustBertError::InvalidConfigurationError(format!(
+                "JSON schema must be an object, got {}",
+                schema
+            )));
+        }
+        Ok(JsonSchema(schema))
+    }
+
+    /// Returns the schema as a JSON value
+    pub fn as_value(&self) -> &Value {
+        &self.0
+    }
+
+    /// Validates a value against the schema
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), Vec<String>>` list of the violations of the schema, identified by their path in the value
+    pub fn validate(&self, value: &Value) -> Result<(), Vec<String>> {
+        let mut errors = vec![];
+        validate_value(&self.0, value, "$", &mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+fn validate_value(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    if let Some(expected_type) = schema.get("type") {
+        let types = match expected_type {
+            Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+            Value::String(value_type) => vec![value_type.as_str()],
+            _ => vec![],
+        };
+        if !types.is_empty() && !types.iter().any(|&value_type| has_type(value, value_type)) {
+            errors.push(format!(
+                "{}: expected {}, got {}",
+                path,
+                types.join(" or "),
+                value
+            ));
+            return;
+        }
+    }
+    if let Some(Value::Array(allowed_values)) = schema.get("enum") {
+        if !allowed_values.contains(value) {
+            errors.push(format!("{}: {} is not an allowed value", path, value));
+        }
+    }
+    match value {
+        Value::Object(object) => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for field in required.iter().filter_map(Value::as_str) {
+                    if !object.contains_key(field) {
+                        errors.push(format!("{}: missing required field {}", path, field));
+                    }
+                }
+            }
+            for (field, field_value) in object {
+                match properties.and_then(|properties| properties.get(field)) {
+                    Some(field_schema) => validate_value(
+                        field_schema,
+                        field_value,
+                        &format!("{}.{}", path, field),
+                        errors,
+                    ),
+                    None => {
+                        if let Some(Value::Bool(false)) = schema.get("additionalProperties") {
+                            errors.push(format!("{}: unexpected field {}", path, field));
+                        }
+                    }
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(items_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    validate_value(items_schema, item, &format!("{}[{}]", path, index), errors);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn has_type(value: &Value, value_type: &str) -> bool {
+    match value_type {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.is_i64()
+                || value.is_u64()
+                || matches!(value.as_f64(), Some(number) if number.fract() == 0.0)
+        }
+        _ => false,
+    }
+}
+
+/// Returns the first complete JSON object of a text, if any
+pub(crate) fn first_json_object(text: &str) -> Option<&str> {
+    let start = text.find('{')?;
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (position, character) in text[start..].char_indices() {
+        if in_string {
+            match character {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match character {
+            '"' => in_string = true,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&text[start..start + position + 1]);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[derive(Serialize, Deserialize)]
+/// # Configuration for StructuredExtractionModel
+pub struct StructuredExtractionConfig {
+    /// `TextGenerationConfig` of the model generating the JSON objects
+    pub text_generation_config: TextGenerationConfig,
+    /// Prompt template with `{schema}` and `{text}` placeholders (default: `DEFAULT_EXTRACTION_PROMPT`)
+    pub prompt_template: String,
+    /// Number of generation attempts before returning an error for an input (default: 1)
+    pub max_attempts: usize,
+}
+
+impl StructuredExtractionConfig {
+    /// Instantiate a new structured extraction configuration using the default prompt
+    ///
+    /// # Arguments
+    ///
+    /// * `text_generation_config` - `TextGenerationConfig` of the model generating the JSON objects
+    pub fn new(text_generation_config: TextGenerationConfig) -> StructuredExtractionConfig {
+        StructuredExtractionConfig {
+            text_generation_config,
+            prompt_template: DEFAULT_EXTRACTION_PROMPT.to_string(),
+            max_attempts: 1,
+        }
+    }
+}
+
+impl From<TextGenerationConfig> for StructuredExtractionConfig {
+    fn from(text_generation_config: TextGenerationConfig) -> Self {
+        StructuredExtractionConfig::new(text_generation_config)
+    }
+}
+
+/// # StructuredExtractionModel extracting typed values from free text
+pub struct StructuredExtractionModel {
+    text_generation_model: TextGenerationModel,
+    prompt_template: PromptTemplate,
+    max_attempts: usize,
+}
+
+impl StructuredExtractionModel {
+    /// Build a new `StructuredExtractionModel`
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `StructuredExtractionConfig` object containing the generation model configuration, prompt template and number of attempts
+    pub fn new(
+        config: StructuredExtractionConfig,
+    ) -> Result<StructuredExtractionModel, RustBertError> {
+        let prompt_template = PromptTemplate::new(&config.prompt_template)?;
+        let mut placeholders = prompt_template.placeholders();
+        placeholders.sort_unstable();
+        if placeholders != ["schema", "text"] {
+            return Err(RustBertError::InvalidConfigurationError(format!(
+                "The extraction prompt must contain the {{schema}} and {{text}} placeholders only, got {:?}",
+                placeholders
+            )));
+        }
+        if config.max_attempts == 0 {
+            return Err(RustBertError::InvalidConfigurationError(
+                "max_attempts must be strictly greater than 0".to_string(),
+            ));
+        }
+        let text_generation_model = TextGenerationModel::new(config.text_generation_config)?;
+        Ok(StructuredExtractionModel {
+            text_generation_model,
+            prompt_template,
+            max_attempts: config.max_attempts,
+        })
+    }
+
+    /// Extracts JSON values matching a schema from texts
+    ///
+    /// # Arguments
+    ///
+    /// * `texts` - `&[&str]` Array of texts to extract values from
+    /// * `schema` - `JsonSchema` of the values to extract
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Result<Value, StructuredExtractionError>>` Extracted value or extraction error for each input text
+    pub fn extract_values<S>(
+        &self,
+        texts: &[S],
+        schema: &JsonSchema,
+    ) -> Result<Vec<Result<Value, StructuredExtractionError>>, RustBertError>
+    where
+        S: AsRef<str> + Sync,
+    {
+        let schema_string = schema.as_value().to_string();
+        let mut output = Vec::with_capacity(texts.len());
+        for text in texts {
+            let prompt = self
+                .prompt_template
+                .render(&[("schema", schema_string.as_str()), ("text", text.as_ref())])?;
+            let mut result = Err(StructuredExtractionError::MissingJsonObject {
+                output: String::new(),
+            });
+            for _ in 0..self.max_attempts {
+                // The generation is primed with the opening brace of the object
+                let generated = self
+                    .text_generation_model
+                    .generate(&["{"], prompt.as_str())?
+                    .pop()
+                    .unwrap_or_default();
+                result = parse_output(&generated, schema);
+                if result.is_ok() {
+                    break;
+                }
+            }
+            output.push(result);
+        }
+        Ok(output)
+    }
+
+    /// Extracts values of a type from texts, using the provided schema
+    ///
+    /// # Arguments
+    ///
+    /// * `texts` - `&[&str]` Array of texts to extract values from
+    /// * `schema` - `JsonSchema` of the serialized form of `T`
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Result<T, StructuredExtractionError>>` Extracted value or extraction error for each input text
+    pub fn extract<T, S>(
+        &self,
+        texts: &[S],
+        schema: &JsonSchema,
+    ) -> Result<Vec<Result<T, StructuredExtractionError>>, RustBertError>
+    where
+        T: DeserializeOwned,
+        S: AsRef<str> + Sync,
+    {
+        Ok(self
+            .extract_values(texts, schema)?
+            .into_iter()
+            .map(|value| {
+                let value = value?;
+                serde_json::from_value(value.clone()).map_err(|error| {
+                    StructuredExtractionError::DeserializationError {
+                        output: value.to_string(),
+                        error: error.to_string(),
+                    }
+                })
+            })
+            .collect())
+    }
+
+    /// Extracts values of a type providing its own schema from texts
+    ///
+    /// # Arguments
+    ///
+    /// * `texts` - `&[&str]` Array of texts to extract values from
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Result<T, StructuredExtractionError>>` Extracted value or extraction error for each input text
+    pub fn extract_typed<T, S>(
+        &self,
+        texts: &[S],
+    ) -> Result<Vec<Result<T, StructuredExtractionError>>, RustBertError>
+    where
+        T: DeserializeOwned + ExtractionSchema,
+        S: AsRef<str> + Sync,
+    {
+        self.extract(texts, &JsonSchema::new(T::json_schema())?)
+    }
+}
+
+/// Parses and validates the first JSON object of a generated text
+fn parse_output(generated: &str, schema: &JsonSchema) -> Result<Value, StructuredExtractionError> {
+    let json_object = first_json_object(generated).ok_or_else(|| {
+        StructuredExtractionError::MissingJsonObject {
+            output: generated.to_string(),
+        }
+    })?;
+    let value: Value = serde_json::from_str(json_object).map_err(|error| {
+        StructuredExtractionError::InvalidJson {
+            output: json_object.to_string(),
+            error: error.to_string(),
+        }
+    })?;
+    schema
+        .validate(&value)
+        .map_err(|errors| StructuredExtractionError::SchemaViolation {
+            output: json_object.to_string(),
+            errors,
+        })?;
+    Ok(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn json_object_extraction() {
+        assert_eq!(
+            first_json_object(r#"{"name": "Amy {}", "tags": {"a": "\"}"}} trailing text"#),
+            Some(r#"{"name": "Amy {}", "tags": {"a": "\"}"}}"#)
+        );
+        assert_eq!(first_json_object(r#"{"name": "Amy""#), None);
+        assert_eq!(first_json_object("no object"), None);
+    }
+
+    #[test]
+    fn schema_validation() {
+        let schema = JsonSchema::new(json!({
+            "type": "object",
+            "properties": {
+                "name": {"type": "string"},
+                "age": {"type": ["integer", "null"]},
+                "role": {"enum": ["admin", "user"]},
+                "tags": {"type": "array", "items": {"type": "string"}}
+            },
+            "required": ["name"],
+            "additionalProperties": false
+        }))
+        .unwrap();
+
+        assert!(schema
+            .validate(&json!({"name": "Amy", "age": 32, "tags": ["a"]}))
+            .is_ok());
+        assert!(schema
+            .validate(&json!({"name": "Amy", "age": null}))
+            .is_ok());
+
+        let errors = schema
+            .validate(&json!({"age": 3.5, "role": "guest", "tags": ["a", 1], "city": "Paris"}))
+            .unwrap_err();
+        assert_eq!(errors.len(), 5);
+        assert!(errors.contains(&"$: missing required field name".to_string()));
+        assert!(errors.contains(&"$.tags[1]: expected string, got 1".to_string()));
+        assert!(errors.contains(&"$: unexpected field city".to_string()));
+
+        assert!(JsonSchema::new(json!("string")).is_err());
+    }
+
+    #[test]
+    fn output_parsing() {
+        let schema = JsonSchema::new(json!({
+            "type": "object",
+            "properties": {"name": {"type": "string"}},
+            "required": ["name"]
+        }))
+        .unwrap();
+
+        assert_eq!(
+            parse_output(r#"{"name": "Amy"} and more"#, &schema).unwrap(),
+            json!({"name": "Amy"})
+        );
+        assert!(matches!(
+            parse_output(r#"{"name": "Amy""#, &schema),
+            Err(StructuredExtractionError::MissingJsonObject { .. })
+        ));
+        assert!(matches!(
+            parse_output(r#"{"name": Amy}"#, &schema),
+            Err(StructuredExtractionError::InvalidJson { .. })
+        ));
+        assert!(matches!(
+            parse_output(r#"{"name": 1}"#, &schema),
+            Err(StructuredExtractionError::SchemaViolation { .. })
+        ));
+    }
+}
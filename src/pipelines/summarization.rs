@@ -66,9 +66,10 @@ use tch::Device;
 
 use crate::bart::BartGenerator;
 use crate::common::error::RustBertError;
+use crate::common::trace::trace_span;
 use crate::pegasus::PegasusConditionalGenerator;
-use crate::pipelines::common::ModelType;
-use crate::pipelines::generation_utils::{GenerateConfig, LanguageGenerator};
+use crate::pipelines::common::{ModelType, TokenizerOption};
+use crate::pipelines::generation_utils::{GenerateConfig, LanguageGenerator, TruncationSide};
 use crate::prophetnet::ProphetNetConditionalGenerator;
 use crate::resources::ResourceProvider;
 use crate::t5::T5Generator;
@@ -78,7 +79,11 @@ use crate::{
     bart::{BartConfigResources, BartMergesResources, BartModelResources, BartVocabResources},
     resources::RemoteResource,
 };
+use rust_tokenizers::tokenizer::TruncationStrategy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
+#[derive(Serialize, Deserialize)]
 /// # Configuration for text summarization
 /// Contains information regarding the model to load, mirrors the GenerationConfig, with a
 /// different set of default parameters and sets the device to place the model on.
@@ -86,12 +91,16 @@ pub struct SummarizationConfig {
     /// Model type
     pub model_type: ModelType,
     /// Model weights resource (default: pretrained BART model on CNN-DM)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub model_resource: Box<dyn ResourceProvider + Send>,
     /// Config resource (default: pretrained BART model on CNN-DM)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub config_resource: Box<dyn ResourceProvider + Send>,
     /// Vocab resource (default: pretrained BART model on CNN-DM)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub vocab_resource: Box<dyn ResourceProvider + Send>,
     /// Merges resource (default: pretrained BART model on CNN-DM)
+    #[serde(default, with = "crate::common::serde_utils::optional_resource")]
     pub merges_resource: Option<Box<dyn ResourceProvider + Send>>,
     /// Minimum sequence length (default: 0)
     pub min_length: i64,
@@ -122,6 +131,10 @@ pub struct SummarizationConfig {
     /// Diversity penalty for diverse beam search. High values will enforce more difference between beam groups (default: 5.5)
     pub diversity_penalty: Option<f64>,
     /// Device to place the model on (default: CUDA/GPU when available)
+    #[serde(
+        with = "crate::common::serde_utils::device",
+        default = "crate::common::serde_utils::device::default"
+    )]
     pub device: Device,
 }
 
@@ -202,12 +215,29 @@ impl From<SummarizationConfig> for GenerateConfig {
             temperature: config.temperature,
             top_k: config.top_k,
             top_p: config.top_p,
+            min_p: 0.0,
             repetition_penalty: config.repetition_penalty,
             length_penalty: config.length_penalty,
             no_repeat_ngram_size: config.no_repeat_ngram_size,
             num_return_sequences: config.num_return_sequences,
             num_beam_groups: config.num_beam_groups,
             diversity_penalty: config.diversity_penalty,
+            pad_token_id: None,
+            max_prompt_length: None,
+            prompt_truncation_side: TruncationSide::Right,
+            prompt_truncation_strategy: TruncationStrategy::LongestFirst,
+            dola_layers: None,
+            attention_sink: None,
+            stop_sequences: Vec::new(),
+            seed: None,
+            bad_word_ids: Vec::new(),
+            bad_words: Vec::new(),
+            forced_bos_token_id: None,
+            forced_eos_token_id: None,
+            logit_bias: HashMap::new(),
+            logits_processors: Vec::new(),
+            stopping_criteria: Vec::new(),
+            layer_placement: HashMap::new(),
             device: config.device,
         }
     }
@@ -227,6 +257,13 @@ pub enum SummarizationOption {
 
 impl SummarizationOption {
     pub fn new(config: SummarizationConfig) -> Result<Self, RustBertError> {
+        let _span = trace_span!(
+            INFO,
+            "load_model",
+            pipeline = "summarization",
+            model_type = ?config.model_type,
+            device = ?config.device
+        );
         match config.model_type {
             ModelType::Bart => Ok(SummarizationOption::Bart(BartGenerator::new(
                 config.into(),
@@ -255,33 +292,63 @@ impl SummarizationOption {
         }
     }
 
+    /// Interface method to access tokenizer
+    pub fn get_tokenizer(&self) -> &TokenizerOption {
+        match self {
+            Self::Bart(model_ref) => model_ref.get_tokenizer(),
+            Self::T5(model_ref) => model_ref.get_tokenizer(),
+            Self::ProphetNet(model_ref) => model_ref.get_tokenizer(),
+            Self::Pegasus(model_ref) => model_ref.get_tokenizer(),
+        }
+    }
+
+    /// Casts the model weights to half precision
+    pub fn half(&mut self) {
+        match self {
+            Self::Bart(model_ref) => model_ref.half(),
+            Self::T5(model_ref) => model_ref.half(),
+            Self::ProphetNet(model_ref) => model_ref.half(),
+            Self::Pegasus(model_ref) => model_ref.half(),
+        }
+    }
+
+    /// Casts the model weights to single precision
+    pub fn float(&mut self) {
+        match self {
+            Self::Bart(model_ref) => model_ref.float(),
+            Self::T5(model_ref) => model_ref.float(),
+            Self::ProphetNet(model_ref) => model_ref.float(),
+            Self::Pegasus(model_ref) => model_ref.float(),
+        }
+    }
+
     /// Interface method to generate() of the particular models.
-    pub fn generate<S>(&self, prompt_texts: Option<&[S]>) -> Vec<String>
+    pub fn generate<S>(&self, prompt_texts: Option<&[S]>) -> Result<Vec<String>, RustBertError>
     where
         S: AsRef<str> + Sync,
     {
-        match *self {
+        Ok(match *self {
             Self::Bart(ref model) => model
-                .generate(prompt_texts, None)
+                .generate(prompt_texts, None)?
                 .into_iter()
                 .map(|output| output.text)
                 .collect(),
             Self::T5(ref model) => model
-                .generate(prompt_texts, None)
+                .generate(prompt_texts, None)?
                 .into_iter()
                 .map(|output| output.text)
                 .collect(),
             Self::ProphetNet(ref model) => model
-                .generate(prompt_texts, None)
+                .generate(prompt_texts, None)?
                 .into_iter()
                 .map(|output| output.text)
                 .collect(),
             Self::Pegasus(ref model) => model
-                .generate(prompt_texts, None)
+                .generate(prompt_texts, None)?
                 .into_iter()
                 .map(|output| output.text)
                 .collect(),
-        }
+        })
     }
 }
 
@@ -320,6 +387,21 @@ impl SummarizationModel {
         Ok(SummarizationModel { model, prefix })
     }
 
+    /// Returns the tokenizer of the summarization model (e.g. to split long documents with a `TextSplitter`)
+    pub fn get_tokenizer(&self) -> &TokenizerOption {
+        self.model.get_tokenizer()
+    }
+
+    /// Casts the model weights to half precision
+    pub fn half(&mut self) {
+        self.model.half();
+    }
+
+    /// Casts the model weights to single precision
+    pub fn float(&mut self) {
+        self.model.float();
+    }
+
     /// Summarize texts provided
     ///
     /// # Arguments
@@ -327,7 +409,7 @@ impl SummarizationModel {
     /// * `input` - `&[&str]` Array of texts to summarize.
     ///
     /// # Returns
-    /// * `Vec<String>` Summarized texts
+    /// * `Result<Vec<String>, RustBertError>` Summarized texts
     ///
     /// # Example
     ///
@@ -359,12 +441,12 @@ impl SummarizationModel {
     /// telescope — scheduled for launch in 2021 — and the European Space Agency's 2028 ARIEL program, could reveal more
     /// about exoplanets like K2-18b."];
     ///
-    /// let output = model.summarize(&input);
+    /// let output = model.summarize(&input)?;
     /// # Ok(())
     /// # }
     /// ```
     /// (New sample credits: [WikiNews](https://en.wikinews.org/wiki/Astronomers_find_water_vapour_in_atmosphere_of_exoplanet_K2-18b))
-    pub fn summarize<S>(&self, texts: &[S]) -> Vec<String>
+    pub fn summarize<S>(&self, texts: &[S]) -> Result<Vec<String>, RustBertError>
     where
         S: AsRef<str> + Sync,
     {
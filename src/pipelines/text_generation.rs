@@ -31,15 +31,22 @@
 //!
 //! Customized text generation models models can be loaded by overwriting the resources in the configuration.
 //! The dependencies will be downloaded to the user's home directory, e.g. under ~/.cache/.rustbert/gpt2
-use tch::Device;
+use tch::{no_grad, Device, Kind, Tensor};
 
 use crate::common::error::RustBertError;
+use crate::common::placement::LayerPlacement;
+use crate::common::trace::trace_span;
 use crate::gpt2::GPT2Generator;
 use crate::gpt_neo::GptNeoGenerator;
 use crate::openai_gpt::OpenAIGenerator;
 use crate::pipelines::common::{ModelType, TokenizerOption};
 use crate::pipelines::generation_utils::private_generation_utils::PrivateLanguageGenerator;
-use crate::pipelines::generation_utils::{GenerateConfig, GenerateOptions, LanguageGenerator};
+use crate::pipelines::generation_utils::{
+    Cache, GenerateConfig, GenerateOptions, LMHeadModel, LanguageGenerator, TruncationSide,
+};
+use crate::pipelines::output_filter::OutputFilter;
+use crate::pipelines::perplexity::{PerplexityModel, ScoringWindow};
+use crate::pipelines::toxicity::SafetyFilter;
 use crate::reformer::ReformerGenerator;
 use crate::resources::ResourceProvider;
 use crate::xlnet::XLNetGenerator;
@@ -49,7 +56,11 @@ use crate::{
     gpt2::{Gpt2ConfigResources, Gpt2MergesResources, Gpt2ModelResources, Gpt2VocabResources},
     resources::RemoteResource,
 };
+use rust_tokenizers::tokenizer::TruncationStrategy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
+#[derive(Serialize, Deserialize)]
 /// # Configuration for text generation
 /// Contains information regarding the model to load, mirrors the GenerateConfig, with a
 /// different set of default parameters and sets the device to place the model on.
@@ -57,12 +68,16 @@ pub struct TextGenerationConfig {
     /// Model type
     pub model_type: ModelType,
     /// Model weights resource (default: pretrained BART model on CNN-DM)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub model_resource: Box<dyn ResourceProvider + Send>,
     /// Config resource (default: pretrained BART model on CNN-DM)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub config_resource: Box<dyn ResourceProvider + Send>,
     /// Vocab resource (default: pretrained BART model on CNN-DM)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub vocab_resource: Box<dyn ResourceProvider + Send>,
     /// Merges resource (default: pretrained BART model on CNN-DM)
+    #[serde(default, with = "crate::common::serde_utils::optional_resource")]
     pub merges_resource: Option<Box<dyn ResourceProvider + Send>>,
     /// Minimum sequence length (default: 0)
     pub min_length: i64,
@@ -80,6 +95,9 @@ pub struct TextGenerationConfig {
     pub top_k: i64,
     /// Top_p value for [Nucleus sampling, Holtzman et al.](http://arxiv.org/abs/1904.09751). Keep top tokens until cumulative probability reaches top_p (default: 0.9)
     pub top_p: f64,
+    /// Min_p value for sampling tokens. Tokens with a probability lower than `min_p` times the probability of the most likely token are removed. Value higher than 0 will enable the feature (default: 0.0)
+    #[serde(default)]
+    pub min_p: f64,
     /// Repetition penalty (mostly useful for CTRL decoders). Values higher than 1 will penalize tokens that have been already generated. (default: 1.0)
     pub repetition_penalty: f64,
     /// Exponential penalty based on the length of the hypotheses generated (default: 1.0)
@@ -92,7 +110,30 @@ pub struct TextGenerationConfig {
     pub num_beam_groups: Option<i64>,
     /// Diversity penalty for diverse beam search. High values will enforce more difference between beam groups (default: 5.5)
     pub diversity_penalty: Option<f64>,
+    /// Padding token id used to batch prompts of different lengths (default: None, using the padding token of the model, or its first end of sequence token for models without padding token such as GPT2)
+    #[serde(default)]
+    pub pad_token_id: Option<i64>,
+    /// Maximum number of tokens of the prompts (default: None, prompts are truncated to `max_length`)
+    #[serde(default)]
+    pub max_prompt_length: Option<i64>,
+    /// Side of the prompts to truncate when exceeding the maximum prompt length. Use `Left` to keep the most recent context of chat-style prompts (default: right)
+    #[serde(default)]
+    pub prompt_truncation_side: TruncationSide,
+    /// Truncation strategy for prompts exceeding the maximum prompt length. `DoNotTruncate` keeps the full prompts (default: `LongestFirst`)
+    #[serde(
+        with = "crate::common::serde_utils::truncation_strategy",
+        default = "crate::common::serde_utils::truncation_strategy::default"
+    )]
+    pub prompt_truncation_strategy: TruncationStrategy,
+    /// Device and precision of groups of layers, identified by the prefix of the names of their weights (e.g. `transformer.wte`), overriding
+    /// the `device` of the model. Only supported by GPT2 (default: empty, all layers are placed on `device`)
+    #[serde(default)]
+    pub layer_placement: HashMap<String, LayerPlacement>,
     /// Device to place the model on (default: CUDA/GPU when available)
+    #[serde(
+        with = "crate::common::serde_utils::device",
+        default = "crate::common::serde_utils::device::default"
+    )]
     pub device: Device,
 }
 
@@ -132,12 +173,18 @@ impl TextGenerationConfig {
             temperature: 1.0,
             top_k: 0,
             top_p: 0.9,
+            min_p: 0.0,
             repetition_penalty: 1.0,
             length_penalty: 1.0,
             no_repeat_ngram_size: 0,
             num_return_sequences: 1,
             num_beam_groups: None,
             diversity_penalty: None,
+            pad_token_id: None,
+            max_prompt_length: None,
+            prompt_truncation_side: TruncationSide::Right,
+            prompt_truncation_strategy: TruncationStrategy::LongestFirst,
+            layer_placement: HashMap::new(),
             device: Device::cuda_if_available(),
         }
     }
@@ -173,12 +220,29 @@ impl From<TextGenerationConfig> for GenerateConfig {
             temperature: config.temperature,
             top_k: config.top_k,
             top_p: config.top_p,
+            min_p: config.min_p,
             repetition_penalty: config.repetition_penalty,
             length_penalty: config.length_penalty,
             no_repeat_ngram_size: config.no_repeat_ngram_size,
             num_return_sequences: config.num_return_sequences,
             num_beam_groups: config.num_beam_groups,
             diversity_penalty: config.diversity_penalty,
+            pad_token_id: config.pad_token_id,
+            max_prompt_length: config.max_prompt_length,
+            prompt_truncation_side: config.prompt_truncation_side,
+            prompt_truncation_strategy: config.prompt_truncation_strategy,
+            dola_layers: None,
+            attention_sink: None,
+            stop_sequences: Vec::new(),
+            seed: None,
+            bad_word_ids: Vec::new(),
+            bad_words: Vec::new(),
+            forced_bos_token_id: None,
+            forced_eos_token_id: None,
+            logit_bias: HashMap::new(),
+            logits_processors: Vec::new(),
+            stopping_criteria: Vec::new(),
+            layer_placement: config.layer_placement,
             device: config.device,
         }
     }
@@ -200,6 +264,13 @@ pub enum TextGenerationOption {
 
 impl TextGenerationOption {
     pub fn new(config: TextGenerationConfig) -> Result<Self, RustBertError> {
+        let _span = trace_span!(
+            INFO,
+            "load_model",
+            pipeline = "text_generation",
+            model_type = ?config.model_type,
+            device = ?config.device
+        );
         match config.model_type {
             ModelType::GPT2 => Ok(TextGenerationOption::GPT2(GPT2Generator::new(
                 config.into(),
@@ -223,6 +294,41 @@ impl TextGenerationOption {
         }
     }
 
+    /// Builds a new `TextGenerationOption` with a provided tokenizer
+    pub fn new_with_tokenizer(
+        config: TextGenerationConfig,
+        tokenizer: TokenizerOption,
+    ) -> Result<Self, RustBertError> {
+        let _span = trace_span!(
+            INFO,
+            "load_model",
+            pipeline = "text_generation",
+            model_type = ?config.model_type,
+            device = ?config.device
+        );
+        match config.model_type {
+            ModelType::GPT2 => Ok(TextGenerationOption::GPT2(
+                GPT2Generator::new_with_tokenizer(config.into(), tokenizer)?,
+            )),
+            ModelType::OpenAiGpt => Ok(TextGenerationOption::GPT(
+                OpenAIGenerator::new_with_tokenizer(config.into(), tokenizer)?,
+            )),
+            ModelType::XLNet => Ok(TextGenerationOption::XLNet(
+                XLNetGenerator::new_with_tokenizer(config.into(), tokenizer)?,
+            )),
+            ModelType::Reformer => Ok(TextGenerationOption::Reformer(
+                ReformerGenerator::new_with_tokenizer(config.into(), tokenizer)?,
+            )),
+            ModelType::GPTNeo => Ok(TextGenerationOption::GPTNeo(
+                GptNeoGenerator::new_with_tokenizer(config.into(), tokenizer)?,
+            )),
+            _ => Err(RustBertError::InvalidConfigurationError(format!(
+                "Text generation not implemented for {:?}!",
+                config.model_type
+            ))),
+        }
+    }
+
     /// Returns the `ModelType` for this TextGenerationOption
     pub fn model_type(&self) -> ModelType {
         match *self {
@@ -245,13 +351,86 @@ impl TextGenerationOption {
         }
     }
 
+    /// Interface method to forward_t() of the particular models, returning the language model
+    /// logits for the input tokens. Not supported for XLNet and Reformer models.
+    pub fn forward_t(
+        &self,
+        input_ids: &Tensor,
+        attention_mask: Option<&Tensor>,
+    ) -> Result<Tensor, RustBertError> {
+        let output = match self {
+            Self::GPT(model_ref) => model_ref.get_model().forward_t(
+                Some(input_ids),
+                Cache::None,
+                attention_mask,
+                None,
+                None,
+                None,
+                None,
+                None,
+                false,
+            )?,
+            Self::GPT2(model_ref) => model_ref.get_model().forward_t(
+                Some(input_ids),
+                Cache::None,
+                attention_mask,
+                None,
+                None,
+                None,
+                None,
+                None,
+                false,
+            )?,
+            Self::GPTNeo(model_ref) => model_ref.get_model().forward_t(
+                Some(input_ids),
+                Cache::None,
+                attention_mask,
+                None,
+                None,
+                None,
+                None,
+                None,
+                false,
+            )?,
+            Self::XLNet(_) | Self::Reformer(_) => {
+                return Err(RustBertError::InvalidConfigurationError(format!(
+                    "Forward pass not supported for {:?} text generation models",
+                    self.model_type()
+                )));
+            }
+        };
+        Ok(output.lm_logits)
+    }
+
+    /// Returns the maximum number of positions supported by the model
+    pub fn get_max_positions_embeddings(&self) -> i64 {
+        match self {
+            Self::GPT(model_ref) => model_ref.get_max_positions_embeddings(),
+            Self::GPT2(model_ref) => model_ref.get_max_positions_embeddings(),
+            Self::GPTNeo(model_ref) => model_ref.get_max_positions_embeddings(),
+            Self::XLNet(model_ref) => model_ref.get_max_positions_embeddings(),
+            Self::Reformer(model_ref) => model_ref.get_max_positions_embeddings(),
+        }
+    }
+
+    /// Returns the device of the model
+    pub fn device(&self) -> Device {
+        match self {
+            Self::GPT(model_ref) => model_ref.get_var_store().device(),
+            Self::GPT2(model_ref) => model_ref.get_var_store().device(),
+            Self::GPTNeo(model_ref) => model_ref.get_var_store().device(),
+            Self::XLNet(model_ref) => model_ref.get_var_store().device(),
+            Self::Reformer(model_ref) => model_ref.get_var_store().device(),
+        }
+    }
+
     /// Interface method to generate() of the particular models.
     pub fn generate_indices<S>(
         &self,
         prompt_texts: Option<&[S]>,
         min_length: Option<i64>,
         max_length: Option<i64>,
-    ) -> Vec<Vec<i64>>
+    ) -> Result<Vec<Vec<i64>>, RustBertError>
     where
         S: AsRef<str> + Sync,
     {
@@ -260,33 +439,46 @@ impl TextGenerationOption {
             max_length,
             ..Default::default()
         });
-        match *self {
+        self.generate_indices_with_options(prompt_texts, generate_options)
+    }
+
+    /// Interface method to generate() of the particular models, with generation options
+    /// overriding the configuration of the model for this call.
+    pub fn generate_indices_with_options<S>(
+        &self,
+        prompt_texts: Option<&[S]>,
+        generate_options: Option<GenerateOptions>,
+    ) -> Result<Vec<Vec<i64>>, RustBertError>
+    where
+        S: AsRef<str> + Sync,
+    {
+        Ok(match *self {
             Self::GPT(ref model) => model
-                .generate_indices(prompt_texts, generate_options)
+                .generate_indices(prompt_texts, generate_options)?
                 .into_iter()
                 .map(|output| output.indices)
                 .collect(),
             Self::GPT2(ref model) => model
-                .generate_indices(prompt_texts, generate_options)
+                .generate_indices(prompt_texts, generate_options)?
                 .into_iter()
                 .map(|output| output.indices)
                 .collect(),
             Self::GPTNeo(ref model) => model
-                .generate_indices(prompt_texts, generate_options)
+                .generate_indices(prompt_texts, generate_options)?
                 .into_iter()
                 .map(|output| output.indices)
                 .collect(),
             Self::XLNet(ref model) => model
-                .generate_indices(prompt_texts, generate_options)
+                .generate_indices(prompt_texts, generate_options)?
                 .into_iter()
                 .map(|output| output.indices)
                 .collect(),
             Self::Reformer(ref model) => model
-                .generate_indices(prompt_texts, generate_options)
+                .generate_indices(prompt_texts, generate_options)?
                 .into_iter()
                 .map(|output| output.indices)
                 .collect(),
-        }
+        })
     }
 
     pub fn half(&mut self) {
@@ -327,6 +519,8 @@ pub struct TextGenerationModel {
     prefix_length: Option<i64>,
     min_length: i64,
     max_length: Option<i64>,
+    safety_filter: Option<SafetyFilter>,
+    output_filters: Vec<Box<dyn OutputFilter>>,
 }
 
 impl TextGenerationModel {
@@ -351,7 +545,55 @@ impl TextGenerationModel {
     pub fn new(
         generation_config: TextGenerationConfig,
     ) -> Result<TextGenerationModel, RustBertError> {
-        let prefix = match generation_config.model_type {
+        let min_length = generation_config.min_length;
+        let max_length = generation_config.max_length;
+        let model = TextGenerationOption::new(generation_config)?;
+        Ok(Self::from_generation_option(model, min_length, max_length))
+    }
+
+    /// Build a new `TextGenerationModel` with a provided tokenizer (e.g. a HuggingFace tokenizer
+    /// loaded from a `tokenizer.json` file). The tokenizer resources of the configuration are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `generation_config` - `GenerateConfig` object containing the resource references (model, vocabulary, configuration), generation options and device placement (CPU/GPU)
+    /// * `tokenizer` - `TokenizerOption` tokenizer to use for text generation
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::common::{ModelType, TokenizerOption};
+    /// use rust_bert::pipelines::text_generation::TextGenerationModel;
+    ///
+    /// let tokenizer = TokenizerOption::from_file(
+    ///     ModelType::GPT2,
+    ///     "path/to/vocab.json",
+    ///     Some("path/to/merges.txt"),
+    ///     false,
+    ///     None,
+    ///     None,
+    /// )?;
+    /// let generation_model = TextGenerationModel::new_with_tokenizer(Default::default(), tokenizer)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new_with_tokenizer(
+        generation_config: TextGenerationConfig,
+        tokenizer: TokenizerOption,
+    ) -> Result<TextGenerationModel, RustBertError> {
+        let min_length = generation_config.min_length;
+        let max_length = generation_config.max_length;
+        let model = TextGenerationOption::new_with_tokenizer(generation_config, tokenizer)?;
+        Ok(Self::from_generation_option(model, min_length, max_length))
+    }
+
+    fn from_generation_option(
+        model: TextGenerationOption,
+        min_length: i64,
+        max_length: Option<i64>,
+    ) -> TextGenerationModel {
+        let prefix = match model.model_type() {
             ModelType::XLNet => Some(
                 "In 1991, the remains of Russian Tsar Nicholas II and his family \
 (except for Alexei and Maria) are discovered. \
@@ -368,20 +610,45 @@ with people, even a bishop, begging for his blessing. <eod> </s> <eos>"
             _ => None,
         };
 
-        let min_length = generation_config.min_length;
-        let max_length = generation_config.max_length;
-        let model = TextGenerationOption::new(generation_config)?;
         let prefix_length = prefix
             .as_ref()
             .map(|prefix| model.get_tokenizer().tokenize(prefix).len() as i64);
 
-        Ok(TextGenerationModel {
+        TextGenerationModel {
             model,
             prefix,
             prefix_length,
             min_length,
             max_length,
-        })
+            safety_filter: None,
+            output_filters: vec![],
+        }
+    }
+
+    /// Attaches a `SafetyFilter` to the model: generated texts classified as toxic are replaced or
+    /// regenerated according to the action of the filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `safety_filter` - `SafetyFilter` screening the generated texts
+    pub fn with_safety_filter(mut self, safety_filter: SafetyFilter) -> TextGenerationModel {
+        self.safety_filter = Some(safety_filter);
+        self
+    }
+
+    /// Attaches an `OutputFilter` to the model (e.g. a `RegexRedaction`), rewriting the generated
+    /// texts before they are returned. Filters are applied in the order they were attached, after
+    /// the safety filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `output_filter` - `OutputFilter` applied to the generated texts
+    pub fn with_output_filter<F>(mut self, output_filter: F) -> TextGenerationModel
+    where
+        F: OutputFilter + 'static,
+    {
+        self.output_filters.push(Box::new(output_filter));
+        self
     }
 
     pub fn half(&mut self) {
@@ -396,6 +663,17 @@ with people, even a bishop, begging for his blessing. <eod> </s> <eos>"
         self.model.set_device(device);
     }
 
+    /// Get a reference to the model tokenizer.
+    pub fn get_tokenizer(&self) -> &TokenizerOption {
+        self.model.get_tokenizer()
+    }
+
+    /// Indicates whether a safety filter or output filters are applied to the generated texts,
+    /// which can then only be returned once fully generated.
+    pub(crate) fn has_output_filters(&self) -> bool {
+        self.safety_filter.is_some() || !self.output_filters.is_empty()
+    }
+
     /// Generate texts from provided prompts
     ///
     /// # Arguments
@@ -404,7 +682,7 @@ with people, even a bishop, begging for his blessing. <eod> </s> <eos>"
     /// * `prefix` - `impl Into<Option<&'a str>>`: Optional string to pass as a prefix for generation. Will be excluded from generated sequences.
     ///
     /// # Returns
-    /// * `Vec<String>` Generated texts
+    /// * `Result<Vec<String>, RustBertError>` Generated texts
     ///
     /// # Example
     ///
@@ -418,15 +696,99 @@ with people, even a bishop, begging for his blessing. <eod> </s> <eos>"
     /// let input = ["The dog", "The cat was"];
     /// let prefix = None;
     ///
-    /// let output = model.generate(&input, prefix);
+    /// let output = model.generate(&input, prefix)?;
     /// # Ok(())
     /// # }
     /// ```
-    pub fn generate<'a, S>(&self, texts: &[S], prefix: impl Into<Option<&'a str>>) -> Vec<String>
+    pub fn generate<'a, S>(
+        &self,
+        texts: &[S],
+        prefix: impl Into<Option<&'a str>>,
+    ) -> Result<Vec<String>, RustBertError>
     where
         S: AsRef<str> + Sync,
     {
-        let (prefix, prefix_length) = match (prefix.into(), &self.prefix) {
+        self.generate_with_options(texts, prefix, None)
+    }
+
+    /// Generate texts from provided prompts, with generation options (e.g. sampling parameters,
+    /// number of beams or maximum length) overriding the configuration of the pipeline for this
+    /// call. This allows serving several decoding presets with a single loaded model.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - `&[&str]` Array of texts to summarize.
+    /// * `prefix` - `impl Into<Option<&'a str>>`: Optional string to pass as a prefix for generation. Will be excluded from generated sequences.
+    /// * `generate_options` - `Option<GenerateOptions>` Optional set of generate options. Options left to `None` default to the pipeline configuration. The minimum and maximum lengths exclude the prefix.
+    ///
+    /// # Returns
+    /// * `Result<Vec<String>, RustBertError>` Generated texts
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::generation_utils::GenerateOptions;
+    /// use rust_bert::pipelines::text_generation::TextGenerationModel;
+    ///
+    /// let model = TextGenerationModel::new(Default::default())?;
+    ///
+    /// let input = ["The dog", "The cat was"];
+    /// let creative_options = GenerateOptions {
+    ///     do_sample: Some(true),
+    ///     num_beams: Some(1),
+    ///     temperature: Some(1.3),
+    ///     top_p: Some(0.95),
+    ///     ..Default::default()
+    /// };
+    /// let greedy_options = GenerateOptions {
+    ///     do_sample: Some(false),
+    ///     num_beams: Some(1),
+    ///     max_length: Some(32),
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let creative_output = model.generate_with_options(&input, None, Some(creative_options))?;
+    /// let greedy_output = model.generate_with_options(&input, None, Some(greedy_options))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn generate_with_options<'a, S>(
+        &self,
+        texts: &[S],
+        prefix: impl Into<Option<&'a str>>,
+        generate_options: Option<GenerateOptions>,
+    ) -> Result<Vec<String>, RustBertError>
+    where
+        S: AsRef<str> + Sync,
+    {
+        let prefix = prefix.into();
+        let output = self.generate_unfiltered(texts, prefix, generate_options)?;
+        let output = match &self.safety_filter {
+            Some(safety_filter) => safety_filter.filter(output, |indices| {
+                let texts = indices
+                    .iter()
+                    .map(|&index| texts[index].as_ref())
+                    .collect::<Vec<&str>>();
+                self.generate_unfiltered(&texts, prefix, generate_options)
+            })?,
+            None => output,
+        };
+        self.output_filters
+            .iter()
+            .try_fold(output, |output, output_filter| output_filter.filter(output))
+    }
+
+    fn generate_unfiltered<S>(
+        &self,
+        texts: &[S],
+        prefix: Option<&str>,
+        generate_options: Option<GenerateOptions>,
+    ) -> Result<Vec<String>, RustBertError>
+    where
+        S: AsRef<str> + Sync,
+    {
+        let (prefix, prefix_length) = match (prefix, &self.prefix) {
             (Some(query_prefix), _) => (
                 Some(query_prefix),
                 Some(self.model.get_tokenizer().tokenize(query_prefix).len() as i64),
@@ -435,20 +797,38 @@ with people, even a bishop, begging for his blessing. <eod> </s> <eos>"
             (None, None) => (None, None),
         };
         let generated_indices = match (prefix, prefix_length) {
-            (None, _) => self.model.generate_indices(Some(texts), None, None),
+            (None, _) => self
+                .model
+                .generate_indices_with_options(Some(texts), generate_options)?,
             (Some(prefix), Some(prefix_length)) => {
                 let texts = texts
                     .as_ref()
                     .iter()
                     .map(|text| format!("{} {}", prefix, text.as_ref()))
                     .collect::<Vec<String>>();
-                self.model.generate_indices(
+                let generate_options = generate_options.unwrap_or_default();
+                let min_length = generate_options.min_length.unwrap_or(self.min_length);
+                // A maximum number of new tokens is not affected by the prefix
+                let max_length =
+                    match (generate_options.max_length, generate_options.max_new_tokens) {
+                        (Some(max_length), _) => Some(max_length),
+                        (None, Some(_)) => None,
+                        (None, None) => self.max_length,
+                    };
+                self.model.generate_indices_with_options(
                     Some(&texts),
-                    Some(self.min_length + prefix_length),
-                    self.max_length.map(|max_length| max_length + prefix_length),
-                )
+                    Some(GenerateOptions {
+                        min_length: Some(min_length + prefix_length),
+                        max_length: max_length.map(|max_length| max_length + prefix_length),
+                        ..generate_options
+                    }),
+                )?
+            }
+            _ => {
+                return Err(RustBertError::ValueError(
+                    "Prefix length not defined but prefix provided!".to_string(),
+                ));
             }
-            _ => panic!("Prefix length not defined but prefix provided!"),
         };
 
         let mut output = Vec::with_capacity(generated_indices.len());
@@ -459,7 +839,7 @@ with people, even a bishop, begging for his blessing. <eod> </s> <eos>"
                 true,
             ));
         }
-        output
+        Ok(output)
     }
 }
 
@@ -474,3 +854,75 @@ mod test {
         let _: Box<dyn Send> = Box::new(TextGenerationModel::new(config));
     }
 }
+
+impl PerplexityModel for TextGenerationModel {
+    fn perplexity_tokenizer(&self) -> &TokenizerOption {
+        self.model.get_tokenizer()
+    }
+
+    fn max_window_length(&self) -> usize {
+        self.model.get_max_positions_embeddings() as usize
+    }
+
+    fn score_windows(
+        &self,
+        windows: &[ScoringWindow],
+        batch_size: usize,
+    ) -> Result<(f64, usize), RustBertError> {
+        let pad_id = self.model.get_tokenizer().get_pad_id().unwrap_or(0);
+        let device = self.model.device();
+        let mut negative_log_likelihood = 0f64;
+        let mut num_tokens = 0usize;
+        for windows in windows.chunks(batch_size) {
+            let max_length = windows
+                .iter()
+                .map(|window| window.token_ids.len())
+                .max()
+                .unwrap_or(0);
+            if max_length < 2 {
+                continue;
+            }
+            let mut input_ids = Vec::with_capacity(windows.len() * max_length);
+            let mut attention_mask = Vec::with_capacity(windows.len() * max_length);
+            // Token at position p is predicted from the logits at position p - 1
+            let mut target_mask = Vec::with_capacity(windows.len() * (max_length - 1));
+            for window in windows {
+                let length = window.token_ids.len();
+                input_ids.extend_from_slice(&window.token_ids);
+                input_ids.extend(vec![pad_id; max_length - length]);
+                attention_mask.extend(vec![1i64; length]);
+                attention_mask.extend(vec![0i64; max_length - length]);
+                target_mask.extend((1..max_length).map(|position| {
+                    if position >= window.target_start.max(1) && position < length {
+                        num_tokens += 1;
+                        1f64
+                    } else {
+                        0f64
+                    }
+                }));
+            }
+            let shape = [windows.len() as i64, max_length as i64];
+            let input_ids = Tensor::of_slice(&input_ids).view(shape).to(device);
+            let attention_mask = Tensor::of_slice(&attention_mask).view(shape).to(device);
+            let target_mask = Tensor::of_slice(&target_mask)
+                .view([windows.len() as i64, max_length as i64 - 1])
+                .to(device);
+
+            let token_log_probabilities = no_grad(|| -> Result<Tensor, RustBertError> {
+                let logits = self.model.forward_t(&input_ids, Some(&attention_mask))?;
+                Ok(logits
+                    .narrow(1, 0, max_length as i64 - 1)
+                    .log_softmax(-1, Kind::Double)
+                    .gather(
+                        2,
+                        &input_ids.narrow(1, 1, max_length as i64 - 1).unsqueeze(-1),
+                        false,
+                    )
+                    .squeeze_dim(-1))
+            })?;
+            negative_log_likelihood -=
+                f64::from((token_log_probabilities * target_mask).sum(Kind::Double));
+        }
+        Ok((negative_log_likelihood, num_tokens))
+    }
+}
@@ -0,0 +1,402 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Text simplification pipeline
+//! Rewrites texts into simpler language with a sequence to sequence model (BART, T5, ProphetNet,
+//! Pegasus) trained with control tokens, as proposed in [Controllable Sentence Simplification (ACCESS)](https://arxiv.org/abs/1910.02677).
+//! The control tokens (`<NbChars_x> <LevSim_x> <WordRank_x> <DepTreeDepth_x>`) prepended to the
+//! input set the target compression, amount of paraphrasing, lexical complexity and syntactic
+//! complexity of the output. `ReadingLevel` presets map a target reading level to control values.
+//!
+//! Each simplification is returned with a word-level edit alignment to the source text, listing
+//! the spans of the source that were kept, deleted, inserted or replaced.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::common::ModelType;
+//! use rust_bert::pipelines::summarization::SummarizationConfig;
+//! use rust_bert::pipelines::text_simplification::{
+//!     ReadingLevel, TextSimplificationConfig, TextSimplificationModel,
+//! };
+//! use rust_bert::resources::LocalResource;
+//! use std::path::PathBuf;
+//!
+//! let generation_config = SummarizationConfig::new(
+//!     ModelType::Bart,
+//!     LocalResource::from(PathBuf::from("path/to/rust_model.ot")),
+//!     LocalResource::from(PathBuf::from("path/to/config.json")),
+//!     LocalResource::from(PathBuf::from("path/to/vocab.json")),
+//!     Some(LocalResource::from(PathBuf::from("path/to/merges.txt"))),
+//! );
+//! let model = TextSimplificationModel::new(TextSimplificationConfig::new(generation_config))?;
+//!
+//! let input = ["The incumbent was re-elected by an overwhelming majority of the electorate."];
+//! let output = model.simplify(&input, ReadingLevel::Elementary)?;
+//! for edit in &output[0].edits {
+//!     println!("{:?} {:?} -> {:?}", edit.operation, edit.source, edit.target);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::common::error::RustBertError;
+use crate::pipelines::summarization::{SummarizationConfig, SummarizationOption};
+use rust_tokenizers::Offset;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+/// # Control values for the simplification (ratios between the output and the source)
+pub struct SimplificationControls {
+    /// Character length ratio (compression)
+    pub length_ratio: f32,
+    /// Levenshtein similarity (amount of paraphrasing, lower values allow more rewriting)
+    pub levenshtein_similarity: f32,
+    /// Word rank ratio (lexical complexity, lower values favour more frequent words)
+    pub word_rank_ratio: f32,
+    /// Dependency tree depth ratio (syntactic complexity)
+    pub dependency_tree_depth_ratio: f32,
+}
+
+impl SimplificationControls {
+    /// Returns the control tokens prepended to the input
+    pub fn control_prefix(&self) -> String {
+        format!(
+            "<NbChars_{:.2}> <LevSim_{:.2}> <WordRank_{:.2}> <DepTreeDepth_{:.2}> ",
+            self.length_ratio,
+            self.levenshtein_similarity,
+            self.word_rank_ratio,
+            self.dependency_tree_depth_ratio
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+/// # Target reading level of the simplification
+pub enum ReadingLevel {
+    /// Short sentences and frequent words, aggressive rewriting
+    Elementary,
+    /// Moderate simplification
+    Intermediate,
+    /// Light simplification, close to the source
+    Advanced,
+    /// User-provided control values
+    Custom(SimplificationControls),
+}
+
+impl ReadingLevel {
+    /// Returns the control values for the reading level
+    pub fn controls(&self) -> SimplificationControls {
+        match self {
+            ReadingLevel::Elementary => SimplificationControls {
+                length_ratio: 0.75,
+                levenshtein_similarity: 0.6,
+                word_rank_ratio: 0.65,
+                dependency_tree_depth_ratio: 0.6,
+            },
+            ReadingLevel::Intermediate => SimplificationControls {
+                length_ratio: 0.9,
+                levenshtein_similarity: 0.75,
+                word_rank_ratio: 0.75,
+                dependency_tree_depth_ratio: 0.8,
+            },
+            ReadingLevel::Advanced => SimplificationControls {
+                length_ratio: 0.95,
+                levenshtein_similarity: 0.85,
+                word_rank_ratio: 0.9,
+                dependency_tree_depth_ratio: 0.95,
+            },
+            ReadingLevel::Custom(controls) => *controls,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+/// # Configuration for TextSimplificationModel
+pub struct TextSimplificationConfig {
+    /// `SummarizationConfig` defining the sequence to sequence simplification model and its generation settings
+    pub generation_config: SummarizationConfig,
+    /// Flag indicating if the control tokens should be prepended to the inputs (default: true). Disable for models trained without control tokens.
+    pub use_control_tokens: bool,
+}
+
+impl TextSimplificationConfig {
+    /// Instantiate a new text simplification configuration. The length settings of the generation
+    /// configuration are relaxed (no minimum length), as simplifications are usually about as long
+    /// as their source.
+    ///
+    /// # Arguments
+    ///
+    /// * `generation_config` - `SummarizationConfig` defining the sequence to sequence simplification model
+    pub fn new(generation_config: SummarizationConfig) -> TextSimplificationConfig {
+        TextSimplificationConfig {
+            generation_config: SummarizationConfig {
+                min_length: 0,
+                max_length: Some(256),
+                no_repeat_ngram_size: 0,
+                ..generation_config
+            },
+            use_control_tokens: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// # Edit operation between the source and the simplified text
+pub enum EditOperation {
+    /// Words kept from the source
+    Keep,
+    /// Source words removed from the simplification
+    Delete,
+    /// Words added by the simplification
+    Insert,
+    /// Source words replaced by other words
+    Replace,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// # Aligned span between the source and the simplified text
+pub struct Edit {
+    /// Edit operation
+    pub operation: EditOperation,
+    /// Character offsets of the span in the source text (None for insertions)
+    pub source: Option<Offset>,
+    /// Character offsets of the span in the simplified text (None for deletions)
+    pub target: Option<Offset>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// # Simplification generated by a `TextSimplificationModel`
+pub struct Simplification {
+    /// Simplified text
+    pub text: String,
+    /// Word-level alignment of the simplified text to the source text, in order
+    pub edits: Vec<Edit>,
+}
+
+/// # TextSimplificationModel to rewrite texts into simpler language
+pub struct TextSimplificationModel {
+    model: SummarizationOption,
+    use_control_tokens: bool,
+}
+
+impl TextSimplificationModel {
+    /// Build a new `TextSimplificationModel`
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `TextSimplificationConfig` object containing the resource references (model, vocabulary, configuration), generation options and device placement (CPU/GPU)
+    pub fn new(config: TextSimplificationConfig) -> Result<TextSimplificationModel, RustBertError> {
+        let model = SummarizationOption::new(config.generation_config)?;
+        Ok(TextSimplificationModel {
+            model,
+            use_control_tokens: config.use_control_tokens,
+        })
+    }
+
+    /// Simplify texts
+    ///
+    /// # Arguments
+    ///
+    /// * `texts` - `&[&str]` Array of texts to simplify.
+    /// * `reading_level` - `ReadingLevel` target reading level of the simplification
+    ///
+    /// # Returns
+    /// * `Result<Vec<Simplification>, RustBertError>` Simplified texts with their alignment to the source
+    pub fn simplify<S>(
+        &self,
+        texts: &[S],
+        reading_level: ReadingLevel,
+    ) -> Result<Vec<Simplification>, RustBertError>
+    where
+        S: AsRef<str> + Sync,
+    {
+        let simplified_texts = if self.use_control_tokens {
+            let control_prefix = reading_level.controls().control_prefix();
+            let prompts = texts
+                .iter()
+                .map(|text| format!("{}{}", control_prefix, text.as_ref()))
+                .collect::<Vec<String>>();
+            self.model.generate(Some(&prompts))?
+        } else {
+            self.model.generate(Some(texts))?
+        };
+        Ok(texts
+            .iter()
+            .zip(simplified_texts)
+            .map(|(source, text)| {
+                let text = text.trim().to_string();
+                let edits = align_words(source.as_ref(), &text);
+                Simplification { text, edits }
+            })
+            .collect())
+    }
+}
+
+/// Splits a text on whitespace, returning the words with their character offsets
+fn words_with_offsets(text: &str) -> Vec<(&str, Offset)> {
+    let mut words = Vec::new();
+    let mut word_start: Option<(usize, usize)> = None;
+    let mut char_position = 0;
+    for (byte_position, character) in text.char_indices() {
+        match (character.is_whitespace(), word_start) {
+            (true, Some((start_byte, start_char))) => {
+                words.push((
+                    &text[start_byte..byte_position],
+                    Offset::new(start_char as u32, char_position as u32),
+                ));
+                word_start = None;
+            }
+            (false, None) => word_start = Some((byte_position, char_position)),
+            _ => {}
+        }
+        char_position += 1;
+    }
+    if let Some((start_byte, start_char)) = word_start {
+        words.push((
+            &text[start_byte..],
+            Offset::new(start_char as u32, char_position as u32),
+        ));
+    }
+    words
+}
+
+fn merge_offsets(offsets: &[Offset]) -> Option<Offset> {
+    match (offsets.first(), offsets.last()) {
+        (Some(first), Some(last)) => Some(Offset::new(first.begin, last.end)),
+        _ => None,
+    }
+}
+
+/// Aligns the words of the target to the words of the source (longest common subsequence), grouping
+/// consecutive words with the same operation into a single edit
+fn align_words(source: &str, target: &str) -> Vec<Edit> {
+    let source_words = words_with_offsets(source);
+    let target_words = words_with_offsets(target);
+    let (num_source, num_target) = (source_words.len(), target_words.len());
+
+    let mut lcs_lengths = vec![vec![0usize; num_target + 1]; num_source + 1];
+    for i in (0..num_source).rev() {
+        for j in (0..num_target).rev() {
+            lcs_lengths[i][j] = if source_words[i].0 == target_words[j].0 {
+                lcs_lengths[i + 1][j + 1] + 1
+            } else {
+                lcs_lengths[i + 1][j].max(lcs_lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut deleted, mut inserted, mut kept_source, mut kept_target) =
+        (vec![], vec![], vec![], vec![]);
+    let flush_changes =
+        |edits: &mut Vec<Edit>, deleted: &mut Vec<Offset>, inserted: &mut Vec<Offset>| {
+            let operation = match (deleted.is_empty(), inserted.is_empty()) {
+                (true, true) => return,
+                (false, true) => EditOperation::Delete,
+                (true, false) => EditOperation::Insert,
+                (false, false) => EditOperation::Replace,
+            };
+            edits.push(Edit {
+                operation,
+                source: merge_offsets(deleted),
+                target: merge_offsets(inserted),
+            });
+            deleted.clear();
+            inserted.clear();
+        };
+    let flush_kept =
+        |edits: &mut Vec<Edit>, kept_source: &mut Vec<Offset>, kept_target: &mut Vec<Offset>| {
+            if !kept_source.is_empty() {
+                edits.push(Edit {
+                    operation: EditOperation::Keep,
+                    source: merge_offsets(kept_source),
+                    target: merge_offsets(kept_target),
+                });
+                kept_source.clear();
+                kept_target.clear();
+            }
+        };
+
+    let (mut i, mut j) = (0, 0);
+    while (i < num_source) | (j < num_target) {
+        if (i < num_source) && (j < num_target) && (source_words[i].0 == target_words[j].0) {
+            flush_changes(&mut edits, &mut deleted, &mut inserted);
+            kept_source.push(source_words[i].1);
+            kept_target.push(target_words[j].1);
+            i += 1;
+            j += 1;
+        } else {
+            flush_kept(&mut edits, &mut kept_source, &mut kept_target);
+            if (j == num_target)
+                || ((i < num_source) && (lcs_lengths[i + 1][j] >= lcs_lengths[i][j + 1]))
+            {
+                deleted.push(source_words[i].1);
+                i += 1;
+            } else {
+                inserted.push(target_words[j].1);
+                j += 1;
+            }
+        }
+    }
+    flush_changes(&mut edits, &mut deleted, &mut inserted);
+    flush_kept(&mut edits, &mut kept_source, &mut kept_target);
+    edits
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn control_prefix() {
+        assert_eq!(
+            ReadingLevel::Elementary.controls().control_prefix(),
+            "<NbChars_0.75> <LevSim_0.60> <WordRank_0.65> <DepTreeDepth_0.60> "
+        );
+    }
+
+    #[test]
+    fn word_alignment() {
+        let source = "The incumbent was re-elected by an overwhelming majority.";
+        let target = "The president was elected again by a large majority.";
+        let edits = align_words(source, target);
+
+        let operations = edits
+            .iter()
+            .map(|edit| edit.operation)
+            .collect::<Vec<EditOperation>>();
+        assert_eq!(
+            operations,
+            vec![
+                EditOperation::Keep,
+                EditOperation::Replace,
+                EditOperation::Keep,
+                EditOperation::Replace,
+                EditOperation::Keep,
+                EditOperation::Replace,
+                EditOperation::Keep,
+            ]
+        );
+        assert_eq!(edits[1].source, Some(Offset::new(4, 13)));
+        assert_eq!(edits[1].target, Some(Offset::new(4, 13)));
+        assert_eq!(edits[3].source, Some(Offset::new(18, 28)));
+        assert_eq!(edits[3].target, Some(Offset::new(18, 31)));
+        assert_eq!(edits[5].source, Some(Offset::new(32, 47)));
+        assert_eq!(edits[5].target, Some(Offset::new(35, 42)));
+        assert_eq!(edits[6].source, Some(Offset::new(48, 57)));
+
+        let edits = align_words("A short sentence", "A sentence");
+        assert_eq!(edits[1].operation, EditOperation::Delete);
+        assert_eq!(edits[1].source, Some(Offset::new(2, 7)));
+        assert_eq!(edits[1].target, None);
+        assert!(align_words("", "").is_empty());
+    }
+}
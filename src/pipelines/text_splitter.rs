@@ -0,0 +1,326 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Text splitter
+//! Splits long documents into chunks fitting a token budget, so that they can be processed by
+//! pipelines with a limited input length (question answering, summarization, sentence embeddings).
+//! Chunk sizes are measured with the tokenizer of the pipeline processing the chunks, and
+//! consecutive chunks may overlap to preserve the context at their boundaries.
+//!
+//! By default, chunks are snapped to sentence boundaries (as detected by the
+//! [`SentenceSegmenter`](crate::pipelines::sentence_segmentation::SentenceSegmenter)): a chunk
+//! contains as many full sentences as fit in the token budget, and only sentences longer than the
+//! budget are split between tokens.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::summarization::SummarizationModel;
+//! use rust_bert::pipelines::text_splitter::{TextSplitter, TextSplitterConfig};
+//!
+//! let summarization_model = SummarizationModel::new(Default::default())?;
+//! let text_splitter = TextSplitter::new(TextSplitterConfig {
+//!     chunk_size: 512,
+//!     chunk_overlap: 64,
+//!     ..Default::default()
+//! })?;
+//!
+//! let document = "A very long document...";
+//! let chunks = text_splitter.split(summarization_model.get_tokenizer(), &[document]);
+//! let chunk_texts = chunks[0]
+//!     .iter()
+//!     .map(|chunk| chunk.text.as_str())
+//!     .collect::<Vec<&str>>();
+//! let summaries = summarization_model.summarize(&chunk_texts)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::common::error::RustBertError;
+use crate::pipelines::common::TokenizerOption;
+use crate::pipelines::sentence_segmentation::{
+    Sentence, SentenceSegmentationConfig, SentenceSegmenter,
+};
+use rust_tokenizers::Offset;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Configuration for TextSplitter
+pub struct TextSplitterConfig {
+    /// Maximum number of tokens of a chunk, excluding special tokens (default: 256)
+    pub chunk_size: usize,
+    /// Maximum number of tokens shared by consecutive chunks (default: 32)
+    pub chunk_overlap: usize,
+    /// Flag indicating if chunks should start and end at sentence boundaries when possible (default: true)
+    pub snap_to_sentences: bool,
+    /// Sentence segmentation rules used when snapping chunks to sentence boundaries
+    pub sentence_segmentation_config: SentenceSegmentationConfig,
+}
+
+impl Default for TextSplitterConfig {
+    fn default() -> TextSplitterConfig {
+        TextSplitterConfig {
+            chunk_size: 256,
+            chunk_overlap: 32,
+            snap_to_sentences: true,
+            sentence_segmentation_config: SentenceSegmentationConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// # Chunk of text generated by a `TextSplitter`
+pub struct TextChunk {
+    /// Text of the chunk
+    pub text: String,
+    /// Character offsets of the chunk in the input text
+    pub offset: Offset,
+    /// Number of tokens of the chunk (excluding special tokens)
+    pub num_tokens: usize,
+}
+
+/// Contiguous span of the input text that is not split further (a sentence or a token)
+struct TextUnit {
+    begin: usize,
+    end: usize,
+    num_tokens: usize,
+}
+
+/// # TextSplitter to chunk documents by token count
+pub struct TextSplitter {
+    config: TextSplitterConfig,
+    sentence_segmenter: SentenceSegmenter,
+}
+
+impl TextSplitter {
+    /// Build a new `TextSplitter`
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `TextSplitterConfig` object containing the chunk size and overlap
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::text_splitter::TextSplitter;
+    ///
+    /// let text_splitter = TextSplitter::new(Default::default())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new(config: TextSplitterConfig) -> Result<TextSplitter, RustBertError> {
+        if config.chunk_size == 0 {
+            return Err(RustBertError::InvalidConfigurationError(
+                "chunk_size must be strictly greater than 0".to_string(),
+            ));
+        }
+        if config.chunk_overlap >= config.chunk_size {
+            return Err(RustBertError::InvalidConfigurationError(
+                "chunk_overlap must be lower than chunk_size".to_string(),
+            ));
+        }
+        let sentence_segmenter =
+            SentenceSegmenter::new(config.sentence_segmentation_config.clone());
+        Ok(TextSplitter {
+            config,
+            sentence_segmenter,
+        })
+    }
+
+    /// Split texts into chunks fitting the token budget
+    ///
+    /// # Arguments
+    ///
+    /// * `tokenizer` - `TokenizerOption` used to count the tokens of the chunks (usually the tokenizer of the pipeline processing the chunks)
+    /// * `input` - `&[&str]` Array of texts to split.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Vec<TextChunk>>` containing the chunks of each input text, with their character offsets
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::question_answering::QuestionAnsweringModel;
+    /// use rust_bert::pipelines::text_splitter::TextSplitter;
+    ///
+    /// let qa_model = QuestionAnsweringModel::new(Default::default())?;
+    /// let text_splitter = TextSplitter::new(Default::default())?;
+    /// let chunks = text_splitter.split(qa_model.get_tokenizer(), &["A very long context..."]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn split<S>(&self, tokenizer: &TokenizerOption, input: &[S]) -> Vec<Vec<TextChunk>>
+    where
+        S: AsRef<str>,
+    {
+        let sentences = if self.config.snap_to_sentences {
+            Some(self.sentence_segmenter.segment(input))
+        } else {
+            None
+        };
+        input
+            .iter()
+            .enumerate()
+            .map(|(text_index, text)| {
+                let text = text.as_ref();
+                let token_offsets = tokenizer
+                    .tokenize_with_offsets(text)
+                    .offsets
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<Offset>>();
+                let units = match &sentences {
+                    Some(sentences) => self.sentence_units(&sentences[text_index], &token_offsets),
+                    None => token_units(&token_offsets),
+                };
+                self.merge_units(text, &units)
+            })
+            .collect()
+    }
+
+    /// Creates a unit per sentence, falling back to token units for sentences exceeding the chunk size
+    fn sentence_units(&self, sentences: &[Sentence], token_offsets: &[Offset]) -> Vec<TextUnit> {
+        let mut units = Vec::new();
+        let mut token_position = 0;
+        for sentence in sentences {
+            let first_token = token_position;
+            while token_position < token_offsets.len()
+                && token_offsets[token_position].begin < sentence.offset.end
+            {
+                token_position += 1;
+            }
+            let sentence_tokens = &token_offsets[first_token..token_position];
+            if sentence_tokens.len() > self.config.chunk_size {
+                units.extend(token_units(sentence_tokens));
+            } else if !sentence_tokens.is_empty() {
+                units.push(TextUnit {
+                    begin: (sentence.offset.begin.min(sentence_tokens[0].begin)) as usize,
+                    end: (sentence.offset.end.max(sentence_tokens.last().unwrap().end)) as usize,
+                    num_tokens: sentence_tokens.len(),
+                });
+            }
+        }
+        units
+    }
+
+    /// Greedily merges consecutive units into chunks, starting each new chunk with the last units
+    /// of the previous chunk fitting in the overlap budget
+    fn merge_units(&self, text: &str, units: &[TextUnit]) -> Vec<TextChunk> {
+        let chars = text.chars().collect::<Vec<char>>();
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        while start < units.len() {
+            let mut end = start;
+            let mut num_tokens = 0;
+            while end < units.len()
+                && ((end == start) | (num_tokens + units[end].num_tokens <= self.config.chunk_size))
+            {
+                num_tokens += units[end].num_tokens;
+                end += 1;
+            }
+            let (begin_char, end_char) = (units[start].begin, units[end - 1].end.min(chars.len()));
+            chunks.push(TextChunk {
+                text: chars[begin_char..end_char].iter().collect(),
+                offset: Offset {
+                    begin: begin_char as u32,
+                    end: end_char as u32,
+                },
+                num_tokens,
+            });
+            if end == units.len() {
+                break;
+            }
+
+            let mut next_start = end;
+            let mut overlap = 0;
+            while (next_start > start + 1)
+                && (overlap + units[next_start - 1].num_tokens <= self.config.chunk_overlap)
+            {
+                overlap += units[next_start - 1].num_tokens;
+                next_start -= 1;
+            }
+            start = next_start;
+        }
+        chunks
+    }
+}
+
+fn token_units(token_offsets: &[Offset]) -> Vec<TextUnit> {
+    token_offsets
+        .iter()
+        .map(|offset| TextUnit {
+            begin: offset.begin as usize,
+            end: offset.end as usize,
+            num_tokens: 1,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn units(token_counts: &[usize]) -> Vec<TextUnit> {
+        let mut position = 0;
+        token_counts
+            .iter()
+            .map(|&num_tokens| {
+                let unit = TextUnit {
+                    begin: position,
+                    end: position + 2,
+                    num_tokens,
+                };
+                position += 3;
+                unit
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merge_units_with_overlap() {
+        let text_splitter = TextSplitter::new(TextSplitterConfig {
+            chunk_size: 4,
+            chunk_overlap: 1,
+            ..Default::default()
+        })
+        .unwrap();
+        let text = "aa bb cc dd ee ff";
+
+        let chunks = text_splitter.merge_units(text, &units(&[1, 1, 1, 1, 1, 1]));
+        let chunk_texts = chunks
+            .iter()
+            .map(|chunk| chunk.text.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(chunk_texts, vec!["aa bb cc dd", "dd ee ff"]);
+        assert_eq!(chunks[1].offset, Offset { begin: 9, end: 17 });
+        assert_eq!(chunks[1].num_tokens, 3);
+
+        //    Units exceeding the overlap are not repeated, units exceeding the chunk size are kept whole
+        let chunks = text_splitter.merge_units(text, &units(&[3, 2, 5, 1]));
+        let chunk_texts = chunks
+            .iter()
+            .map(|chunk| chunk.text.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(chunk_texts, vec!["aa", "bb", "cc", "dd"]);
+    }
+
+    #[test]
+    fn invalid_configuration() {
+        assert!(TextSplitter::new(TextSplitterConfig {
+            chunk_size: 8,
+            chunk_overlap: 8,
+            ..Default::default()
+        })
+        .is_err());
+    }
+}
@@ -114,13 +114,17 @@
 use crate::albert::AlbertForTokenClassification;
 use crate::bert::BertForTokenClassification;
 use crate::common::error::RustBertError;
+use crate::common::trace::trace_span;
 use crate::deberta::DebertaForTokenClassification;
 use crate::distilbert::DistilBertForTokenClassification;
 use crate::electra::ElectraForTokenClassification;
 use crate::fnet::FNetForTokenClassification;
 use crate::longformer::LongformerForTokenClassification;
 use crate::mobilebert::MobileBertForTokenClassification;
+use crate::pipelines::added_tokens::add_tokens_and_resize_embeddings;
+use crate::pipelines::calibration::Calibrator;
 use crate::pipelines::common::{ConfigOption, ModelType, TokenizerOption};
+use crate::pipelines::sequence_classification::{Label, ScoreNormalization};
 use crate::resources::ResourceProvider;
 use crate::roberta::RobertaForTokenClassification;
 use crate::xlnet::XLNetForTokenClassification;
@@ -128,7 +132,6 @@ use ordered_float::OrderedFloat;
 use rust_tokenizers::tokenizer::Tokenizer;
 use rust_tokenizers::{
     ConsolidatableTokens, ConsolidatedTokenIterator, Mask, Offset, TokenIdsWithOffsets, TokenTrait,
-    TokenizedInput,
 };
 use serde::{Deserialize, Serialize};
 use std::borrow::Borrow;
@@ -167,6 +170,15 @@ pub struct Token {
     pub mask: Mask,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Token with the scores of all labels, generated by `TokenClassificationModel::predict_full`
+pub struct TokenScores {
+    /// Token with its most likely label
+    pub token: Token,
+    /// Scores of every label of the model for the token, sorted by decreasing score
+    pub labels: Vec<Label>,
+}
+
 impl TokenTrait for Token {
     fn offset(&self) -> Option<Offset> {
         self.offset
@@ -205,6 +217,8 @@ type LabelAggregationFunction = Box<fn(&[Token]) -> (i64, String)>;
 
 /// # Enum defining the label aggregation method for sub tokens
 /// Defines the behaviour for labels aggregation if the consolidation of sub-tokens is enabled.
+/// The `Custom` variant cannot be serialized.
+#[derive(Serialize, Deserialize)]
 pub enum LabelAggregationOption {
     /// The label of the first sub token is assigned to the entire token
     First,
@@ -213,21 +227,27 @@ pub enum LabelAggregationOption {
     /// The most frequent sub- token is  assigned to the entire token
     Mode,
     /// The user can provide a function mapping a `&Vec<Token>` to a `(i64, String)` tuple corresponding to the label index, label String to return
+    #[serde(skip)]
     Custom(LabelAggregationFunction),
 }
 
+#[derive(Serialize, Deserialize)]
 /// # Configuration for TokenClassificationModel
 /// Contains information regarding the model to load and device to place the model on.
 pub struct TokenClassificationConfig {
     /// Model type
     pub model_type: ModelType,
     /// Model weights resource (default: pretrained BERT model on CoNLL)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub model_resource: Box<dyn ResourceProvider + Send>,
     /// Config resource (default: pretrained BERT model on CoNLL)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub config_resource: Box<dyn ResourceProvider + Send>,
     /// Vocab resource (default: pretrained BERT model on CoNLL)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub vocab_resource: Box<dyn ResourceProvider + Send>,
     /// Merges resource (default: pretrained BERT model on CoNLL)
+    #[serde(default, with = "crate::common::serde_utils::optional_resource")]
     pub merges_resource: Option<Box<dyn ResourceProvider + Send>>,
     /// Automatically lower case all input upon tokenization (assumes a lower-cased model)
     pub lower_case: bool,
@@ -236,11 +256,19 @@ pub struct TokenClassificationConfig {
     /// Flag indicating if the tokenizer should add a white space before each tokenized input (needed for some Roberta models)
     pub add_prefix_space: Option<bool>,
     /// Device to place the model on (default: CUDA/GPU when available)
+    #[serde(
+        with = "crate::common::serde_utils::device",
+        default = "crate::common::serde_utils::device::default"
+    )]
     pub device: Device,
     /// Sub-tokens aggregation method (default: `LabelAggregationOption::First`)
     pub label_aggregation_function: LabelAggregationOption,
     /// Batch size for predictions
     pub batch_size: usize,
+    /// Number of tokens shared by consecutive windows when processing inputs longer than the
+    /// maximum length of the model (default: None, using a quarter of the maximum length)
+    #[serde(default)]
+    pub window_overlap: Option<usize>,
 }
 
 impl TokenClassificationConfig {
@@ -282,6 +310,7 @@ impl TokenClassificationConfig {
             device: Device::cuda_if_available(),
             label_aggregation_function,
             batch_size: 64,
+            window_overlap: None,
         }
     }
 }
@@ -517,6 +546,12 @@ impl TokenClassificationOption {
         input_embeds: Option<&Tensor>,
         train: bool,
     ) -> Tensor {
+        let _span = trace_span!(
+            DEBUG,
+            "forward",
+            input_shape = ?input_ids.map(Tensor::size),
+            device = ?input_ids.map(Tensor::device)
+        );
         match *self {
             Self::Bert(ref model) => {
                 model
@@ -650,7 +685,9 @@ pub struct TokenClassificationModel {
     var_store: VarStore,
     label_aggregation_function: LabelAggregationOption,
     max_length: usize,
+    window_overlap: usize,
     batch_size: usize,
+    calibrator: Option<Box<dyn Calibrator>>,
 }
 
 impl TokenClassificationModel {
@@ -673,16 +710,12 @@ impl TokenClassificationModel {
     pub fn new(
         config: TokenClassificationConfig,
     ) -> Result<TokenClassificationModel, RustBertError> {
-        let config_path = config.config_resource.get_local_path()?;
         let vocab_path = config.vocab_resource.get_local_path()?;
-        let weights_path = config.model_resource.get_local_path()?;
         let merges_path = if let Some(merges_resource) = &config.merges_resource {
             Some(merges_resource.get_local_path()?)
         } else {
             None
         };
-        let device = config.device;
-        let label_aggregation_function = config.label_aggregation_function;
 
         let tokenizer = TokenizerOption::from_file(
             config.model_type,
@@ -692,6 +725,32 @@ impl TokenClassificationModel {
             config.strip_accents,
             config.add_prefix_space,
         )?;
+        Self::new_with_tokenizer(config, tokenizer)
+    }
+
+    /// Build a new `TokenClassificationModel` with a provided tokenizer (e.g. a HuggingFace tokenizer loaded
+    /// from a `tokenizer.json` file). The tokenizer resources of the configuration are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `TokenClassificationConfig` object containing the resource references (model, vocabulary, configuration) and device placement (CPU/GPU)
+    /// * `tokenizer` - `TokenizerOption` tokenizer to use for token classification
+    pub fn new_with_tokenizer(
+        config: TokenClassificationConfig,
+        tokenizer: TokenizerOption,
+    ) -> Result<TokenClassificationModel, RustBertError> {
+        let _span = trace_span!(
+            INFO,
+            "load_model",
+            pipeline = "token_classification",
+            model_type = ?config.model_type,
+            device = ?config.device
+        );
+        let config_path = config.config_resource.get_local_path()?;
+        let weights_path = config.model_resource.get_local_path()?;
+        let device = config.device;
+        let label_aggregation_function = config.label_aggregation_function;
+
         let mut var_store = VarStore::new(device);
         let model_config = ConfigOption::from_file(config.model_type, config_path);
         let max_length = model_config
@@ -702,6 +761,14 @@ impl TokenClassificationModel {
             TokenClassificationOption::new(config.model_type, &var_store.root(), &model_config)?;
         let label_mapping = model_config.get_label_mapping().clone();
         let batch_size = config.batch_size;
+        let window_overlap = config.window_overlap.unwrap_or(max_length / 4);
+        let max_content_length = max_length.saturating_sub(num_added_tokens(&tokenizer));
+        if 2 * window_overlap > max_content_length {
+            return Err(RustBertError::InvalidConfigurationError(format!(
+                "window_overlap ({}) must be at most half of the maximum number of input tokens of the model ({})",
+                window_overlap, max_content_length
+            )));
+        }
         var_store.load(weights_path)?;
         Ok(TokenClassificationModel {
             tokenizer,
@@ -710,10 +777,42 @@ impl TokenClassificationModel {
             var_store,
             label_aggregation_function,
             max_length,
+            window_overlap,
             batch_size,
+            calibrator: None,
         })
     }
 
+    /// Registers additional tokens (e.g. special markers or domain-specific terms) on the tokenizer
+    /// of the model. The added tokens are never split by the tokenizer, and the token embeddings of
+    /// the model are resized, the new embeddings being initialized with the mean embedding of the
+    /// existing vocabulary.
+    ///
+    /// # Arguments
+    ///
+    /// * `tokens` - tokens to add (tokens already part of the vocabulary are ignored)
+    pub fn with_added_tokens<S: AsRef<str>>(
+        mut self,
+        tokens: &[S],
+    ) -> Result<TokenClassificationModel, RustBertError> {
+        self.tokenizer = add_tokens_and_resize_embeddings(self.tokenizer, &self.var_store, tokens)?;
+        Ok(self)
+    }
+
+    /// Sets a calibrator converting the token logits of the model to calibrated probabilities
+    /// (replacing the softmax normalization)
+    ///
+    /// # Arguments
+    ///
+    /// * `calibrator` - `Calibrator` fitted on a validation set, e.g. a `TemperatureScaling`
+    pub fn with_calibrator<C>(mut self, calibrator: C) -> TokenClassificationModel
+    where
+        C: Calibrator + 'static,
+    {
+        self.calibrator = Some(Box::new(calibrator));
+        self
+    }
+
     fn generate_features<S>(&self, input: S, example_index: usize) -> Vec<InputFeature>
     where
         S: AsRef<str>,
@@ -728,29 +827,16 @@ impl TokenClassificationModel {
             masks: tokenized_input.masks,
         };
 
-        let sequence_added_tokens = self
-            .tokenizer
-            .build_input_with_special_tokens(
-                TokenIdsWithOffsets {
-                    ids: vec![],
-                    offsets: vec![],
-                    reference_offsets: vec![],
-                    masks: vec![],
-                },
-                None,
-            )
-            .token_ids
-            .len();
-
-        let max_content_length = self.max_length - sequence_added_tokens;
-        let doc_stride = self.max_length / 4;
+        let max_content_length = self.max_length - num_added_tokens(&self.tokenizer);
+        let windows = token_windows(
+            encoded_input.ids.len(),
+            max_content_length,
+            self.window_overlap,
+        );
+        let boundaries = window_boundaries(&windows, &encoded_input.masks);
 
-        let mut spans: Vec<InputFeature> = vec![];
-        let mut start_token = 0_usize;
-        let total_length = encoded_input.ids.len();
-
-        while (spans.len() * doc_stride as usize) < encoded_input.ids.len() {
-            let end_token = min(start_token + max_content_length, total_length);
+        let mut spans: Vec<InputFeature> = Vec::with_capacity(windows.len());
+        for (window_index, &(start_token, end_token)) in windows.iter().enumerate() {
             let sub_encoded_input = TokenIdsWithOffsets {
                 ids: encoded_input.ids[start_token..end_token].to_vec(),
                 offsets: encoded_input.offsets[start_token..end_token].to_vec(),
@@ -762,75 +848,44 @@ impl TokenClassificationModel {
                 .tokenizer
                 .build_input_with_special_tokens(sub_encoded_input, None);
 
-            let reference_feature = self.get_reference_feature_flag(
-                start_token,
-                end_token,
-                total_length,
-                doc_stride,
-                &encoded_span,
-            );
+            // Each token is labelled by a single window: the window owning the token position
+            let owned_start = if window_index > 0 {
+                boundaries[window_index - 1]
+            } else {
+                0
+            };
+            let owned_end = boundaries.get(window_index).cloned().unwrap_or(end_token);
+            let is_first_window = window_index == 0;
+            let is_last_window = window_index == windows.len() - 1;
+            let leading_special_tokens = encoded_span
+                .mask
+                .iter()
+                .take_while(|&&mask| mask == Mask::Special)
+                .count();
+            let reference_feature = (0..encoded_span.token_ids.len())
+                .map(|position| {
+                    if position < leading_special_tokens {
+                        is_first_window
+                    } else if position >= leading_special_tokens + end_token - start_token {
+                        is_last_window
+                    } else {
+                        let token_position = start_token + position - leading_special_tokens;
+                        (owned_start..owned_end).contains(&token_position)
+                    }
+                })
+                .collect();
 
-            let feature = InputFeature {
+            spans.push(InputFeature {
                 input_ids: encoded_span.token_ids,
                 offsets: encoded_span.token_offsets,
                 mask: encoded_span.mask,
                 reference_feature,
                 example_index,
-            };
-            spans.push(feature);
-            if end_token == encoded_input.ids.len() {
-                break;
-            }
-            start_token = end_token - doc_stride;
+            });
         }
         spans
     }
 
-    fn get_reference_feature_flag(
-        &self,
-        start_token: usize,
-        end_token: usize,
-        total_length: usize,
-        doc_stride: usize,
-        encoded_span: &TokenizedInput,
-    ) -> Vec<bool> {
-        // set halfway through the doc_stride to be false if the feature is not the first/last
-        let start_cutoff = if start_token > 0 {
-            let leading_special_tokens = {
-                let mut counter = 0;
-                let mut masks = encoded_span.mask.iter();
-                while masks.next().unwrap_or(&Mask::None) == &Mask::Special {
-                    counter += 1;
-                }
-                counter
-            };
-            doc_stride / 2 + leading_special_tokens
-        } else {
-            0
-        };
-        let end_cutoff = if end_token < total_length {
-            let trailing_special_tokens = {
-                let mut counter = 0;
-                let mut masks = encoded_span.mask.iter().rev();
-                while masks.next().unwrap_or(&Mask::None) == &Mask::Special {
-                    counter += 1;
-                }
-                counter
-            };
-            encoded_span.token_ids.len() - doc_stride / 2 - trailing_special_tokens
-        } else {
-            encoded_span.token_ids.len()
-        };
-        let mut reference_feature = vec![true; encoded_span.token_ids.len()];
-        reference_feature[..start_cutoff]
-            .iter_mut()
-            .for_each(|v| *v = false);
-        reference_feature[end_cutoff..]
-            .iter_mut()
-            .for_each(|v| *v = false);
-        reference_feature
-    }
-
     /// Classify tokens in a text sequence
     ///
     /// # Arguments
@@ -866,6 +921,86 @@ impl TokenClassificationModel {
     ) -> Vec<Vec<Token>>
     where
         S: AsRef<str>,
+    {
+        let mut tokens = self.classify_tokens(
+            input,
+            return_special,
+            ScoreNormalization::Softmax,
+            |token, _| token,
+        );
+        if consolidate_sub_tokens {
+            self.consolidate_tokens(&mut tokens, &self.label_aggregation_function);
+        }
+        tokens
+    }
+
+    /// Classify tokens in a text sequence, returning the scores of all labels for each token.
+    /// Sub-tokens are not consolidated.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - `&[&str]` Array of texts to extract entities from.
+    /// * `return_special` - bool flag indicating if labels for special tokens should be returned
+    /// * `normalization` - `ScoreNormalization` applied to the logits (softmax for mutually exclusive labels, sigmoid for multi-label classification)
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Vec<TokenScores>>` containing, for each input, the tokens with their most likely label and the scores of every label sorted by decreasing score
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// # use rust_bert::pipelines::sequence_classification::ScoreNormalization;
+    /// # use rust_bert::pipelines::token_classification::TokenClassificationModel;
+    ///
+    /// let ner_model = TokenClassificationModel::new(Default::default())?;
+    /// let input = ["My name is Amy. I live in Paris."];
+    /// let output = ner_model.predict_full(&input, false, ScoreNormalization::Softmax);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn predict_full<S>(
+        &self,
+        input: &[S],
+        return_special: bool,
+        normalization: ScoreNormalization,
+    ) -> Vec<Vec<TokenScores>>
+    where
+        S: AsRef<str>,
+    {
+        self.classify_tokens(input, return_special, normalization, |token, scores| {
+            let mut labels = Vec::<f64>::from(scores.to_kind(Kind::Double).to(Device::Cpu))
+                .into_iter()
+                .enumerate()
+                .map(|(id, score)| Label {
+                    text: self
+                        .label_mapping
+                        .get(&(id as i64))
+                        .expect("Index out of vocabulary bounds.")
+                        .to_owned(),
+                    score,
+                    id: id as i64,
+                    sentence: token.sentence,
+                })
+                .collect::<Vec<Label>>();
+            labels.sort_by(|a, b| b.score.total_cmp(&a.score));
+            TokenScores { token, labels }
+        })
+    }
+
+    /// Runs the model on the features of the inputs by batches, building an output for each token
+    /// from the decoded token and the normalized scores of all labels at its position
+    fn classify_tokens<S, T, F>(
+        &self,
+        input: &[S],
+        return_special: bool,
+        normalization: ScoreNormalization,
+        build_output: F,
+    ) -> Vec<Vec<T>>
+    where
+        S: AsRef<str>,
+        F: Fn(Token, &Tensor) -> T,
     {
         let mut features: Vec<InputFeature> = input
             .iter()
@@ -873,7 +1008,7 @@ impl TokenClassificationModel {
             .flat_map(|(example_index, example)| self.generate_features(example, example_index))
             .collect();
 
-        let mut example_tokens_map: Vec<Vec<Token>> = vec![Vec::new(); input.len()];
+        let mut example_tokens_map: Vec<Vec<T>> = (0..input.len()).map(|_| Vec::new()).collect();
         let mut start = 0usize;
         let len_features = features.len();
 
@@ -891,14 +1026,20 @@ impl TokenClassificationModel {
                     None,
                     false,
                 );
-                let score = output.exp()
-                    / output
-                        .exp()
-                        .sum_dim_intlist([-1].as_slice(), true, Kind::Float);
+                let score = match (&self.calibrator, normalization) {
+                    (Some(calibrator), _) => calibrator.calibrate(&output, normalization),
+                    (None, ScoreNormalization::Softmax) => {
+                        output.exp()
+                            / output
+                                .exp()
+                                .sum_dim_intlist([-1].as_slice(), true, Kind::Float)
+                    }
+                    (None, ScoreNormalization::Sigmoid) => output.sigmoid(),
+                };
                 let label_indices = score.argmax(-1, true);
                 for sentence_idx in 0..label_indices.size()[0] {
                     let labels = label_indices.get(sentence_idx);
-                    let feature = &features[sentence_idx as usize];
+                    let feature = &features[start + sentence_idx as usize];
                     let sentence_reference_flag = &feature.reference_feature;
                     let original_chars = input[feature.example_index]
                         .as_ref()
@@ -930,18 +1071,15 @@ impl TokenClassificationModel {
                                 word_idx,
                             )
                         };
-                        example_tokens_map[feature.example_index].push(token);
+                        let token_scores = score.get(sentence_idx).get(position_idx as i64);
+                        example_tokens_map[feature.example_index]
+                            .push(build_output(token, &token_scores));
                     }
                 }
             });
             start = end;
         }
-        let mut tokens = example_tokens_map;
-
-        if consolidate_sub_tokens {
-            self.consolidate_tokens(&mut tokens, &self.label_aggregation_function);
-        }
-        tokens
+        example_tokens_map
     }
 
     fn pad_features(&self, features: &mut [InputFeature]) -> (Tensor, Tensor) {
@@ -1016,6 +1154,13 @@ impl TokenClassificationModel {
                 TokenizerOption::XLNet(ref tokenizer) => {
                     Tokenizer::decode(tokenizer, &[token_id], false, false)
                 }
+                #[cfg(feature = "hf-tokenizers")]
+                TokenizerOption::HFTokenizer(ref tokenizer) => {
+                    tokenizer.decode(&[token_id], false, false)
+                }
+                TokenizerOption::AddedTokens(ref tokenizer) => {
+                    tokenizer.decode(&[token_id], false, false)
+                }
                 _ => panic!(
                     "Token classification not implemented for {:?}!",
                     self.tokenizer.model_type()
@@ -1148,3 +1293,79 @@ impl TokenClassificationModel {
         }
     }
 }
+
+/// Returns the number of special tokens added by the tokenizer to a single input
+fn num_added_tokens(tokenizer: &TokenizerOption) -> usize {
+    tokenizer
+        .build_input_with_special_tokens(
+            TokenIdsWithOffsets {
+                ids: vec![],
+                offsets: vec![],
+                reference_offsets: vec![],
+                masks: vec![],
+            },
+            None,
+        )
+        .token_ids
+        .len()
+}
+
+/// Returns the windows `(start, end)` of `window_length` tokens covering an input of
+/// `total_length` tokens, consecutive windows sharing `overlap` tokens
+fn token_windows(total_length: usize, window_length: usize, overlap: usize) -> Vec<(usize, usize)> {
+    let mut windows = vec![];
+    let mut start = 0;
+    while start < total_length {
+        let end = min(start + window_length, total_length);
+        windows.push((start, end));
+        if end == total_length {
+            break;
+        }
+        start = end - overlap;
+    }
+    windows
+}
+
+/// Returns the boundaries between consecutive windows: the tokens before a boundary are labelled
+/// by the first window and the tokens after it by the second one. Boundaries are set in the middle
+/// of the overlap, where both windows have the most context, and aligned on the start of a word so
+/// that all sub-tokens of a word are labelled by the same window.
+fn window_boundaries(windows: &[(usize, usize)], masks: &[Mask]) -> Vec<usize> {
+    windows
+        .windows(2)
+        .map(|consecutive_windows| {
+            let (overlap_start, overlap_end) = (consecutive_windows[1].0, consecutive_windows[0].1);
+            let middle = (overlap_start + overlap_end) / 2;
+            (middle..overlap_end)
+                .chain((overlap_start + 1..middle).rev())
+                .find(|&position| masks[position] != Mask::Continuation)
+                .unwrap_or(middle)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn long_input_windows() {
+        assert!(token_windows(0, 8, 2).is_empty());
+        assert_eq!(token_windows(5, 8, 2), [(0, 5)]);
+        assert_eq!(token_windows(20, 8, 2), [(0, 8), (6, 14), (12, 20)]);
+
+        let windows = token_windows(20, 8, 4);
+        assert_eq!(windows, [(0, 8), (4, 12), (8, 16), (12, 20)]);
+        let mut masks = vec![Mask::None; 20];
+        assert_eq!(window_boundaries(&windows, &masks), [6, 10, 14]);
+
+        // Boundaries are moved to the start of the next word, or of the previous one
+        masks[6] = Mask::Continuation;
+        masks[10] = Mask::Continuation;
+        masks[11] = Mask::Continuation;
+        masks[14] = Mask::Continuation;
+        masks[15] = Mask::Continuation;
+        masks[13] = Mask::Continuation;
+        assert_eq!(window_boundaries(&windows, &masks), [7, 9, 14]);
+    }
+}
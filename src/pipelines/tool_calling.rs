@@ -0,0 +1,481 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Tool calling chat pipeline
+//! Chat with a local text generation model able to call tools (functions) defined by the
+//! application. Each tool is described by a name, a description and a JSON schema of its
+//! parameters (`ToolDefinition`). The tools are described to the model in the system prompt and
+//! the model answers either with a message or with a tool call of the form
+//! `<tool_call>{"name": ..., "arguments": {...}}</tool_call>`.
+//!
+//! The decoding is constrained according to the `ToolChoice`: when a tool call is required, the
+//! assistant answer is primed with the beginning of the tool call (including the tool name if a
+//! specific tool is requested) and the generation is cut at the end of the JSON object. Generated
+//! calls are parsed into a `ToolCall`, the tool name and arguments being validated against the tool
+//! definitions. The result of the tool is passed back to the model as a `ChatRole::Tool` message,
+//! allowing to build agents on local models.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::prompt_template::{ChatFormat, ChatMessage, ChatRole};
+//! use rust_bert::pipelines::tool_calling::{
+//!     ToolCall, ToolCallingConfig, ToolCallingModel, ToolChoice, ToolDefinition,
+//! };
+//! use serde_json::json;
+//!
+//! let model = ToolCallingModel::new(ToolCallingConfig::new(
+//!     Default::default(),
+//!     ChatFormat::ChatML,
+//! ))?;
+//! let tools = [ToolDefinition::new(
+//!     "get_weather",
+//!     "Returns the current weather in a city",
+//!     json!({
+//!         "type": "object",
+//!         "properties": {"city": {"type": "string"}},
+//!         "required": ["city"]
+//!     }),
+//! )?];
+//!
+//! let mut messages = vec![ChatMessage::new(ChatRole::User, "How warm is it in Paris?")];
+//! let response = model.chat(&messages, &tools, &ToolChoice::Auto)?;
+//! if let ToolCall::Function { .. } = &response {
+//!     messages.push(response.to_message());
+//!     messages.push(ChatMessage::new(ChatRole::Tool, "{\"temperature\": 21}"));
+//!     let answer = model.chat(&messages, &tools, &ToolChoice::None)?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::common::error::RustBertError;
+use crate::pipelines::prompt_template::{ChatFormat, ChatMessage, ChatRole, PromptTemplate};
+use crate::pipelines::structured_extraction::{first_json_object, JsonSchema};
+use crate::pipelines::text_generation::{TextGenerationConfig, TextGenerationModel};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+
+/// Default description of the tools added to the system prompt, with a `{tools}` placeholder
+pub const DEFAULT_TOOLS_PROMPT: &str = "You have access to the following tools:\n{tools}\n\
+To call a tool, answer with <tool_call>{{\"name\": <tool name>, \"arguments\": <arguments object>}}\
+</tool_call>";
+
+const TOOL_CALL_START: &str = "<tool_call>";
+const TOOL_CALL_END: &str = "</tool_call>";
+
+#[derive(Error, Debug)]
+/// # Error returned by the `ToolCallingModel`
+pub enum ToolCallError {
+    /// Error raised by the generation model
+    #[error("Generation error: {0}")]
+    GenerationError(#[from] RustBertError),
+
+    /// The model called a tool that is not defined
+    #[error("Unknown tool called: {name}")]
+    UnknownTool { name: String },
+
+    /// The generated tool call is not well-formed
+    #[error("Invalid tool call ({error}): {output}")]
+    InvalidToolCall { output: String, error: String },
+
+    /// The arguments of the tool call do not match the parameters schema of the tool
+    #[error("Invalid arguments for tool {name} ({}): {output}", errors.join("; "))]
+    InvalidArguments {
+        name: String,
+        output: String,
+        errors: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// # Definition of a tool that can be called by the model
+pub struct ToolDefinition {
+    /// Name of the tool
+    pub name: String,
+    /// Description of the tool, used by the model to decide when to call it
+    pub description: String,
+    /// JSON schema of the arguments of the tool
+    pub parameters: JsonSchema,
+}
+
+impl ToolDefinition {
+    /// Creates a new `ToolDefinition`
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - name of the tool
+    /// * `description` - description of the tool
+    /// * `parameters` - JSON schema of the arguments of the tool
+    pub fn new(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        parameters: Value,
+    ) -> Result<ToolDefinition, RustBertError> {
+        Ok(ToolDefinition {
+            name: name.into(),
+            description: description.into(),
+            parameters: JsonSchema::new(parameters)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// # Constraint on the tool calls of the model
+pub enum ToolChoice {
+    /// The model answers with a message or calls a tool
+    Auto,
+    /// The model must call one of the tools
+    Required,
+    /// The model must call the tool with the given name
+    Tool(String),
+    /// The tools are not provided to the model, which answers with a message
+    None,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// # Response of the model
+pub enum ToolCall {
+    /// Call of a tool, with arguments matching the parameters schema of the tool
+    Function { name: String, arguments: Value },
+    /// Message answered without calling a tool
+    Message(String),
+}
+
+impl ToolCall {
+    /// Returns the response as an assistant `ChatMessage`, to be added to the chat history
+    pub fn to_message(&self) -> ChatMessage {
+        match self {
+            ToolCall::Function { name, arguments } => ChatMessage::new(
+                ChatRole::Assistant,
+                format!(
+                    "{}{{\"name\": {}, \"arguments\": {}}}{}",
+                    TOOL_CALL_START,
+                    json!(name),
+                    arguments,
+                    TOOL_CALL_END
+                ),
+            ),
+            ToolCall::Message(content) => ChatMessage::new(ChatRole::Assistant, content.as_str()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+/// # Configuration for ToolCallingModel
+pub struct ToolCallingConfig {
+    /// `TextGenerationConfig` of the chat model
+    pub text_generation_config: TextGenerationConfig,
+    /// Chat format expected by the model
+    pub chat_format: ChatFormat,
+    /// Description of the tools added to the system prompt, with a `{tools}` placeholder (default: `DEFAULT_TOOLS_PROMPT`)
+    pub tools_prompt: String,
+}
+
+impl ToolCallingConfig {
+    /// Instantiate a new tool calling configuration using the default tools prompt
+    ///
+    /// # Arguments
+    ///
+    /// * `text_generation_config` - `TextGenerationConfig` of the chat model
+    /// * `chat_format` - `ChatFormat` expected by the model
+    pub fn new(
+        text_generation_config: TextGenerationConfig,
+        chat_format: ChatFormat,
+    ) -> ToolCallingConfig {
+        ToolCallingConfig {
+            text_generation_config,
+            chat_format,
+            tools_prompt: DEFAULT_TOOLS_PROMPT.to_string(),
+        }
+    }
+}
+
+/// # ToolCallingModel to chat with a model calling tools
+pub struct ToolCallingModel {
+    text_generation_model: TextGenerationModel,
+    chat_format: ChatFormat,
+    tools_prompt: PromptTemplate,
+}
+
+impl ToolCallingModel {
+    /// Build a new `ToolCallingModel`
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `ToolCallingConfig` object containing the chat model configuration, chat format and tools prompt
+    pub fn new(config: ToolCallingConfig) -> Result<ToolCallingModel, RustBertError> {
+        let tools_prompt = PromptTemplate::new(&config.tools_prompt)?;
+        if tools_prompt.placeholders() != ["tools"] {
+            return Err(RustBertError::InvalidConfigurationError(format!(
+                "The tools prompt must contain the {{tools}} placeholder only, got {:?}",
+                tools_prompt.placeholders()
+            )));
+        }
+        let text_generation_model = TextGenerationModel::new(config.text_generation_config)?;
+        Ok(ToolCallingModel {
+            text_generation_model,
+            chat_format: config.chat_format,
+            tools_prompt,
+        })
+    }
+
+    /// Generates the next assistant response of a chat, which may be a tool call
+    ///
+    /// # Arguments
+    ///
+    /// * `messages` - `ChatMessage`s of the chat, in chronological order
+    /// * `tools` - `ToolDefinition`s of the tools available to the model
+    /// * `tool_choice` - `ToolChoice` constraining the tool calls of the model
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ToolCall, ToolCallError>` tool call or message generated by the model
+    pub fn chat(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolDefinition],
+        tool_choice: &ToolChoice,
+    ) -> Result<ToolCall, ToolCallError> {
+        let tools: &[ToolDefinition] = match tool_choice {
+            ToolChoice::None => &[],
+            _ => tools,
+        };
+        let (prompt, priming) = build_prompt(
+            &self.chat_format,
+            &self.tools_prompt,
+            messages,
+            tools,
+            tool_choice,
+        )?;
+        let generated = self
+            .text_generation_model
+            .generate(&[priming.as_str()], prompt.as_str())?
+            .pop()
+            .unwrap_or_default();
+        parse_response(
+            &generated,
+            tools,
+            matches!(tool_choice, ToolChoice::Required | ToolChoice::Tool(_)),
+        )
+    }
+}
+
+/// Builds the chat prompt and the beginning of the assistant response forced by the tool choice
+fn build_prompt(
+    chat_format: &ChatFormat,
+    tools_prompt: &PromptTemplate,
+    messages: &[ChatMessage],
+    tools: &[ToolDefinition],
+    tool_choice: &ToolChoice,
+) -> Result<(String, String), ToolCallError> {
+    if tools.is_empty() {
+        return match tool_choice {
+            ToolChoice::Required | ToolChoice::Tool(_) => Err(RustBertError::ValueError(
+                "A tool call is required but no tool is defined".to_string(),
+            )
+            .into()),
+            _ => Ok((chat_format.render(messages, true)?, String::new())),
+        };
+    }
+    let tools_description = tools
+        .iter()
+        .map(serde_json::to_string)
+        .collect::<Result<Vec<String>, _>>()
+        .map_err(|error| RustBertError::ValueError(error.to_string()))?
+        .join("\n");
+    let tools_description = tools_prompt.render(&[("tools", tools_description)])?;
+
+    let mut messages = messages.to_vec();
+    match messages.first_mut() {
+        Some(message) if message.role == ChatRole::System => {
+            message.content = format!("{}\n\n{}", message.content, tools_description)
+        }
+        _ => messages.insert(0, ChatMessage::new(ChatRole::System, tools_description)),
+    }
+    let priming = match tool_choice {
+        ToolChoice::Auto | ToolChoice::None => String::new(),
+        ToolChoice::Required => format!("{}{{\"name\": \"", TOOL_CALL_START),
+        ToolChoice::Tool(name) => {
+            if !tools.iter().any(|tool| &tool.name == name) {
+                return Err(ToolCallError::UnknownTool { name: name.clone() });
+            }
+            format!(
+                "{}{{\"name\": {}, \"arguments\": ",
+                TOOL_CALL_START,
+                json!(name)
+            )
+        }
+    };
+    Ok((chat_format.render(&messages, true)?, priming))
+}
+
+/// Parses the generated response into a `ToolCall`, validating the tool name and arguments
+fn parse_response(
+    generated: &str,
+    tools: &[ToolDefinition],
+    call_required: bool,
+) -> Result<ToolCall, ToolCallError> {
+    let call_text = match generated.find(TOOL_CALL_START) {
+        Some(position) => &generated[position + TOOL_CALL_START.len()..],
+        None => generated.trim_start(),
+    };
+    if tools.is_empty() || !(call_required || call_text.starts_with('{')) {
+        return Ok(ToolCall::Message(generated.trim().to_string()));
+    }
+    let invalid_call = |error: &str| ToolCallError::InvalidToolCall {
+        output: generated.to_string(),
+        error: error.to_string(),
+    };
+    let call_object =
+        first_json_object(call_text).ok_or_else(|| invalid_call("no complete JSON object"))?;
+    let call: Value =
+        serde_json::from_str(call_object).map_err(|error| invalid_call(&error.to_string()))?;
+    let name = call
+        .get("name")
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid_call("missing tool name"))?;
+    let arguments = call.get("arguments").cloned().unwrap_or_else(|| json!({}));
+    let tool =
+        tools
+            .iter()
+            .find(|tool| tool.name == name)
+            .ok_or_else(|| ToolCallError::UnknownTool {
+                name: name.to_string(),
+            })?;
+    tool.parameters
+        .validate(&arguments)
+        .map_err(|errors| ToolCallError::InvalidArguments {
+            name: name.to_string(),
+            output: call_object.to_string(),
+            errors,
+        })?;
+    Ok(ToolCall::Function {
+        name: name.to_string(),
+        arguments,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn weather_tool() -> ToolDefinition {
+        ToolDefinition::new(
+            "get_weather",
+            "Returns the weather",
+            json!({
+                "type": "object",
+                "properties": {"city": {"type": "string"}},
+                "required": ["city"]
+            }),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn tool_call_prompt() -> Result<(), ToolCallError> {
+        let tools_prompt = PromptTemplate::new("Tools:\n{tools}")?;
+        let tools = [ToolDefinition::new(
+            "get_weather",
+            "Returns the weather",
+            json!({"type": "object"}),
+        )?];
+        let messages = [
+            ChatMessage::new(ChatRole::System, "Be brief."),
+            ChatMessage::new(ChatRole::User, "Weather in Paris?"),
+        ];
+
+        let (prompt, priming) = build_prompt(
+            &ChatFormat::Plain,
+            &tools_prompt,
+            &messages,
+            &tools,
+            &ToolChoice::Tool("get_weather".to_string()),
+        )?;
+        assert_eq!(
+            prompt,
+            "System: Be brief.\n\nTools:\n{\"name\":\"get_weather\",\"description\":\
+            \"Returns the weather\",\"parameters\":{\"type\":\"object\"}}\n\
+            User: Weather in Paris?\nAssistant:"
+        );
+        assert_eq!(
+            priming,
+            "<tool_call>{\"name\": \"get_weather\", \"arguments\": "
+        );
+
+        let (prompt, priming) = build_prompt(
+            &ChatFormat::Plain,
+            &tools_prompt,
+            &messages[1..],
+            &[],
+            &ToolChoice::Auto,
+        )?;
+        assert_eq!(prompt, "User: Weather in Paris?\nAssistant:");
+        assert!(priming.is_empty());
+
+        assert!(matches!(
+            build_prompt(
+                &ChatFormat::Plain,
+                &tools_prompt,
+                &messages,
+                &tools,
+                &ToolChoice::Tool("get_time".to_string()),
+            ),
+            Err(ToolCallError::UnknownTool { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn tool_call_parsing() -> Result<(), ToolCallError> {
+        let tools = [weather_tool()];
+
+        let call = parse_response(
+            "<tool_call>{\"name\": \"get_weather\", \"arguments\": {\"city\": \"Paris\"}}\
+            </tool_call> trailing",
+            &tools,
+            false,
+        )?;
+        assert_eq!(
+            call,
+            ToolCall::Function {
+                name: "get_weather".to_string(),
+                arguments: json!({"city": "Paris"}),
+            }
+        );
+        assert_eq!(
+            call.to_message().content,
+            "<tool_call>{\"name\": \"get_weather\", \"arguments\": {\"city\":\"Paris\"}}</tool_call>"
+        );
+        assert_eq!(
+            parse_response(" It is sunny. ", &tools, false)?,
+            ToolCall::Message("It is sunny.".to_string())
+        );
+        assert!(matches!(
+            parse_response("{\"name\": \"get_time\"}", &tools, false),
+            Err(ToolCallError::UnknownTool { .. })
+        ));
+        assert!(matches!(
+            parse_response(
+                "{\"name\": \"get_weather\", \"arguments\": {}}",
+                &tools,
+                true
+            ),
+            Err(ToolCallError::InvalidArguments { .. })
+        ));
+        assert!(matches!(
+            parse_response("{\"name\": \"get_weather\", \"arguments\": {", &tools, true),
+            Err(ToolCallError::InvalidToolCall { .. })
+        ));
+        Ok(())
+    }
+}
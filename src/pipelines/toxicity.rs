@@ -0,0 +1,341 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Toxicity / content safety classification pipeline
+//! Multi-label classification of texts into toxicity categories (e.g. `toxic`, `obscene`,
+//! `threat`, `insult`...). Each category is scored independently (sigmoid) and flagged when its
+//! score exceeds the threshold configured for the category. Inputs longer than the model maximum
+//! length are split in overlapping windows, keeping the maximum score of each category over the
+//! windows so that toxic content located anywhere in the input gets flagged.
+//!
+//! The categories are read from the label mapping of the sequence classification model: any
+//! multi-label toxicity classifier supported by the `SequenceClassificationModel` can be used
+//! (for example a BERT model fine-tuned on the Jigsaw toxic comments dataset).
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::pipelines::common::ModelType;
+//! use rust_bert::pipelines::sequence_classification::SequenceClassificationConfig;
+//! use rust_bert::pipelines::toxicity::{ToxicityConfig, ToxicityModel};
+//! use rust_bert::resources::LocalResource;
+//! use std::path::PathBuf;
+//!
+//! let sequence_classification_config = SequenceClassificationConfig::new(
+//!     ModelType::Bert,
+//!     LocalResource::from(PathBuf::from("path/to/rust_model.ot")),
+//!     LocalResource::from(PathBuf::from("path/to/config.json")),
+//!     LocalResource::from(PathBuf::from("path/to/vocab.txt")),
+//!     None,
+//!     true,
+//!     None,
+//!     None,
+//! );
+//! let mut config = ToxicityConfig::new(sequence_classification_config);
+//! config.thresholds.insert("threat".to_string(), 0.3);
+//!
+//! let toxicity_model = ToxicityModel::new(config)?;
+//! let output = toxicity_model.predict(&["Have a nice day!", "I will find you."]);
+//! for prediction in output {
+//!     println!("{} {:?}", prediction.is_toxic(), prediction.flagged);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A `SafetyFilter` wrapping a `ToxicityModel` can be attached to a
+//! [`TextGenerationModel`](crate::pipelines::text_generation::TextGenerationModel) to replace or
+//! regenerate unsafe outputs automatically.
+
+use crate::common::error::RustBertError;
+use crate::pipelines::calibration::Calibrator;
+use crate::pipelines::sequence_classification::{
+    labels_above_threshold, Label, ScoreNormalization, SequenceClassificationConfig,
+    SequenceClassificationModel, SlidingWindowConfig, WindowAggregation,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Serialize, Deserialize)]
+/// # Configuration for ToxicityModel
+/// Contains the sequence classification model configuration and the flagging thresholds.
+pub struct ToxicityConfig {
+    /// `SequenceClassificationConfig` of the multi-label toxicity classifier
+    pub sequence_classification_config: SequenceClassificationConfig,
+    /// Threshold above which a category is flagged, if no category specific threshold is given (default: 0.5)
+    pub default_threshold: f64,
+    /// Category specific thresholds, indexed by label name
+    pub thresholds: HashMap<String, f64>,
+    /// Maximum number of inputs processed by the model in a single forward pass (default: 32)
+    pub batch_size: usize,
+}
+
+impl ToxicityConfig {
+    /// Instantiate a new toxicity configuration from a sequence classification configuration.
+    /// If no sliding window is set in the sequence classification configuration, long inputs are
+    /// windowed with a stride of 128 tokens and the maximum score of each category is kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `sequence_classification_config` - `SequenceClassificationConfig` of the multi-label toxicity classifier
+    pub fn new(mut sequence_classification_config: SequenceClassificationConfig) -> ToxicityConfig {
+        if sequence_classification_config.sliding_window.is_none() {
+            sequence_classification_config.sliding_window = Some(SlidingWindowConfig {
+                stride: 128,
+                aggregation: WindowAggregation::Max,
+            });
+        }
+        ToxicityConfig {
+            sequence_classification_config,
+            default_threshold: 0.5,
+            thresholds: HashMap::new(),
+            batch_size: 32,
+        }
+    }
+}
+
+impl From<SequenceClassificationConfig> for ToxicityConfig {
+    fn from(sequence_classification_config: SequenceClassificationConfig) -> Self {
+        ToxicityConfig::new(sequence_classification_config)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Toxicity prediction for a single input
+pub struct ToxicityPrediction {
+    /// Scores of all toxicity categories, sorted by decreasing score
+    pub scores: Vec<Label>,
+    /// Categories with a score above their threshold, sorted by decreasing score
+    pub flagged: Vec<Label>,
+}
+
+impl ToxicityPrediction {
+    /// Indicates if at least one toxicity category was flagged
+    pub fn is_toxic(&self) -> bool {
+        !self.flagged.is_empty()
+    }
+}
+
+/// # ToxicityModel for multi-label content safety classification
+pub struct ToxicityModel {
+    sequence_classification_model: SequenceClassificationModel,
+    default_threshold: f64,
+    thresholds: HashMap<String, f64>,
+    batch_size: usize,
+}
+
+impl ToxicityModel {
+    /// Build a new `ToxicityModel`
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `ToxicityConfig` object containing the classifier resources and the flagging thresholds
+    pub fn new(config: ToxicityConfig) -> Result<ToxicityModel, RustBertError> {
+        for (label, threshold) in config
+            .thresholds
+            .iter()
+            .map(|(label, threshold)| (label.as_str(), threshold))
+            .chain(std::iter::once(("default", &config.default_threshold)))
+        {
+            if !(0.0..=1.0).contains(threshold) {
+                return Err(RustBertError::InvalidConfigurationError(format!(
+                    "Toxicity threshold for {} must be between 0 and 1, got {}",
+                    label, threshold
+                )));
+            }
+        }
+        if config.batch_size == 0 {
+            return Err(RustBertError::InvalidConfigurationError(
+                "batch_size must be strictly greater than 0".to_string(),
+            ));
+        }
+        let sequence_classification_model =
+            SequenceClassificationModel::new(config.sequence_classification_config)?;
+        Ok(ToxicityModel {
+            sequence_classification_model,
+            default_threshold: config.default_threshold,
+            thresholds: config.thresholds,
+            batch_size: config.batch_size,
+        })
+    }
+
+    /// Sets a calibrator converting the logits of the classifier to calibrated probabilities,
+    /// so that the flagging thresholds can be set on trustworthy probabilities
+    ///
+    /// # Arguments
+    ///
+    /// * `calibrator` - `Calibrator` fitted on a validation set
+    pub fn with_calibrator<C>(mut self, calibrator: C) -> ToxicityModel
+    where
+        C: Calibrator + 'static,
+    {
+        self.sequence_classification_model = self
+            .sequence_classification_model
+            .with_calibrator(calibrator);
+        self
+    }
+
+    /// Classify texts into toxicity categories
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - `&[&str]` Array of texts to classify.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<ToxicityPrediction>` containing the category scores and flagged categories for each input text
+    pub fn predict<S>(&self, input: &[S]) -> Vec<ToxicityPrediction>
+    where
+        S: AsRef<str>,
+    {
+        let input = input.iter().map(AsRef::as_ref).collect::<Vec<&str>>();
+        let mut output = Vec::with_capacity(input.len());
+        for batch in input.chunks(self.batch_size) {
+            for mut scores in self
+                .sequence_classification_model
+                .predict_full(batch, ScoreNormalization::Sigmoid)
+            {
+                let sentence = output.len();
+                for label in scores.iter_mut() {
+                    label.sentence = sentence;
+                }
+                let flagged =
+                    labels_above_threshold(&scores, self.default_threshold, &self.thresholds);
+                output.push(ToxicityPrediction { scores, flagged });
+            }
+        }
+        output
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Action taken by a `SafetyFilter` on unsafe generated outputs
+pub enum UnsafeOutputAction {
+    /// Replace the unsafe output by the replacement text
+    Replace(String),
+    /// Generate a new output, up to `max_attempts` times, before replacing the output by the
+    /// `fallback` text. Regeneration is only useful with sampling enabled, as greedy and beam
+    /// search decoding are deterministic.
+    Regenerate {
+        max_attempts: usize,
+        fallback: String,
+    },
+}
+
+/// # SafetyFilter screening generated outputs with a `ToxicityModel`
+pub struct SafetyFilter {
+    toxicity_model: ToxicityModel,
+    action: UnsafeOutputAction,
+}
+
+impl SafetyFilter {
+    /// Build a new `SafetyFilter`
+    ///
+    /// # Arguments
+    ///
+    /// * `toxicity_model` - `ToxicityModel` used to screen the outputs
+    /// * `action` - `UnsafeOutputAction` taken on unsafe outputs
+    pub fn new(toxicity_model: ToxicityModel, action: UnsafeOutputAction) -> SafetyFilter {
+        SafetyFilter {
+            toxicity_model,
+            action,
+        }
+    }
+
+    /// Screen generated outputs, replacing or regenerating the unsafe ones
+    ///
+    /// # Arguments
+    ///
+    /// * `outputs` - Generated outputs to screen
+    /// * `regenerate` - Closure generating new outputs for the provided output indices (only called for `UnsafeOutputAction::Regenerate`)
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<String>` safe outputs
+    pub fn filter<F>(
+        &self,
+        mut outputs: Vec<String>,
+        mut regenerate: F,
+    ) -> Result<Vec<String>, RustBertError>
+    where
+        F: FnMut(&[usize]) -> Result<Vec<String>, RustBertError>,
+    {
+        let mut unsafe_indices = self.unsafe_indices(&outputs, 0..outputs.len());
+        let (max_attempts, replacement) = match &self.action {
+            UnsafeOutputAction::Replace(replacement) => (0, replacement),
+            UnsafeOutputAction::Regenerate {
+                max_attempts,
+                fallback,
+            } => (*max_attempts, fallback),
+        };
+        let mut attempts = 0;
+        while !unsafe_indices.is_empty() & (attempts < max_attempts) {
+            let regenerated = regenerate(&unsafe_indices)?;
+            for (&index, output) in unsafe_indices.iter().zip(regenerated) {
+                outputs[index] = output;
+            }
+            unsafe_indices = self.unsafe_indices(&outputs, unsafe_indices.into_iter());
+            attempts += 1;
+        }
+        for index in unsafe_indices {
+            outputs[index] = replacement.clone();
+        }
+        Ok(outputs)
+    }
+
+    fn unsafe_indices(
+        &self,
+        outputs: &[String],
+        indices: impl Iterator<Item = usize>,
+    ) -> Vec<usize> {
+        let indices = indices.collect::<Vec<usize>>();
+        let texts = indices
+            .iter()
+            .map(|&index| outputs[index].as_str())
+            .collect::<Vec<&str>>();
+        indices
+            .into_iter()
+            .zip(self.toxicity_model.predict(&texts))
+            .filter(|(_, prediction)| prediction.is_toxic())
+            .map(|(index, _)| index)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn flag_labels_with_category_thresholds() {
+        let scores = [("toxic", 0.6), ("threat", 0.35), ("insult", 0.2)]
+            .iter()
+            .enumerate()
+            .map(|(id, (text, score))| Label {
+                text: text.to_string(),
+                score: *score,
+                id: id as i64,
+                sentence: 0,
+            })
+            .collect::<Vec<Label>>();
+
+        let flagged = labels_above_threshold(&scores, 0.5, &HashMap::new());
+        assert_eq!(flagged.len(), 1);
+        assert_eq!(flagged[0].text, "toxic");
+
+        let thresholds = [("threat".to_string(), 0.3), ("toxic".to_string(), 0.7)]
+            .iter()
+            .cloned()
+            .collect::<HashMap<String, f64>>();
+        let flagged = labels_above_threshold(&scores, 0.5, &thresholds);
+        assert_eq!(flagged.len(), 1);
+        assert_eq!(flagged[0].text, "threat");
+    }
+}
@@ -87,9 +87,13 @@
 //! }
 //! ```
 
+mod quality_estimation;
 mod translation_builder;
 mod translation_pipeline;
 
-pub use translation_pipeline::{Language, TranslationConfig, TranslationModel, TranslationOption};
+pub use quality_estimation::{QualityEstimationConfig, QualityEstimationModel};
+pub use translation_pipeline::{
+    Language, TranslationConfig, TranslationHypothesis, TranslationModel, TranslationOption,
+};
 
 pub use translation_builder::TranslationModelBuilder;
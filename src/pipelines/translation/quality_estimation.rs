@@ -0,0 +1,117 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::error::RustBertError;
+use crate::pipelines::sequence_classification::{
+    SequenceClassificationConfig, SequenceClassificationModel,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+/// # Configuration for QualityEstimationModel
+pub struct QualityEstimationConfig {
+    /// `SequenceClassificationConfig` of the quality estimation model. The model must have a regression head (single output) scoring (source, translation) pairs, e.g. MonoTransQuest models.
+    pub sequence_classification_config: SequenceClassificationConfig,
+    /// Maximum number of pairs scored by the model in a single forward pass (default: 32)
+    pub batch_size: usize,
+}
+
+impl QualityEstimationConfig {
+    /// Instantiate a new quality estimation configuration from a sequence classification configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `sequence_classification_config` - `SequenceClassificationConfig` of the quality estimation model
+    pub fn new(
+        sequence_classification_config: SequenceClassificationConfig,
+    ) -> QualityEstimationConfig {
+        QualityEstimationConfig {
+            sequence_classification_config,
+            batch_size: 32,
+        }
+    }
+}
+
+impl From<SequenceClassificationConfig> for QualityEstimationConfig {
+    fn from(sequence_classification_config: SequenceClassificationConfig) -> Self {
+        QualityEstimationConfig::new(sequence_classification_config)
+    }
+}
+
+/// # Reference-free translation quality estimation model
+/// Scores the quality of translations from the source text and the translation only (no
+/// reference translation needed), higher scores indicating better translations.
+pub struct QualityEstimationModel {
+    sequence_classification_model: SequenceClassificationModel,
+    batch_size: usize,
+}
+
+impl QualityEstimationModel {
+    /// Build a new `QualityEstimationModel`
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `QualityEstimationConfig` object containing the quality estimation model resources
+    pub fn new(config: QualityEstimationConfig) -> Result<QualityEstimationModel, RustBertError> {
+        if config.batch_size == 0 {
+            return Err(RustBertError::InvalidConfigurationError(
+                "batch_size must be strictly greater than 0".to_string(),
+            ));
+        }
+        let sequence_classification_model =
+            SequenceClassificationModel::new(config.sequence_classification_config)?;
+        Ok(QualityEstimationModel {
+            sequence_classification_model,
+            batch_size: config.batch_size,
+        })
+    }
+
+    /// Estimates the quality of translations
+    ///
+    /// # Arguments
+    ///
+    /// * `sources` - `&[&str]` Array of source texts
+    /// * `translations` - `&[&str]` Array of translations, aligned with the source texts
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<f64>, RustBertError>` quality score of each translation
+    pub fn estimate<S, T>(
+        &self,
+        sources: &[S],
+        translations: &[T],
+    ) -> Result<Vec<f64>, RustBertError>
+    where
+        S: AsRef<str>,
+        T: AsRef<str>,
+    {
+        if sources.len() != translations.len() {
+            return Err(RustBertError::ValueError(format!(
+                "Got {} source texts for {} translations",
+                sources.len(),
+                translations.len()
+            )));
+        }
+        let pairs = sources
+            .iter()
+            .zip(translations.iter())
+            .map(|(source, translation)| (source.as_ref(), translation.as_ref()))
+            .collect::<Vec<(&str, &str)>>();
+        let mut scores = Vec::with_capacity(pairs.len());
+        for batch in pairs.chunks(self.batch_size) {
+            scores.extend(
+                self.sequence_classification_model
+                    .predict_pair_scores(batch)?,
+            );
+        }
+        Ok(scores)
+    }
+}
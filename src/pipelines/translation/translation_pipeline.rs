@@ -14,16 +14,21 @@
 use tch::Device;
 
 use crate::common::error::RustBertError;
+use crate::common::trace::trace_span;
 use crate::m2m_100::M2M100Generator;
 use crate::marian::MarianGenerator;
 use crate::mbart::MBartGenerator;
 use crate::pipelines::common::ModelType;
 use crate::pipelines::generation_utils::private_generation_utils::PrivateLanguageGenerator;
-use crate::pipelines::generation_utils::{GenerateConfig, GenerateOptions, LanguageGenerator};
+use crate::pipelines::generation_utils::{
+    GenerateConfig, GenerateOptions, GeneratedTextOutput, LanguageGenerator, TruncationSide,
+};
+use crate::pipelines::translation::QualityEstimationModel;
 use crate::resources::ResourceProvider;
 use crate::t5::T5Generator;
+use rust_tokenizers::tokenizer::TruncationStrategy;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::fmt::{Debug, Display};
 
@@ -367,6 +372,7 @@ impl Language {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 /// # Configuration for text translation
 /// Contains information regarding the model to load, mirrors the GenerationConfig, with a
 /// different set of default parameters and sets the device to place the model on.
@@ -374,12 +380,16 @@ pub struct TranslationConfig {
     /// Model type used for translation
     pub model_type: ModelType,
     /// Model weights resource
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub model_resource: Box<dyn ResourceProvider + Send>,
     /// Config resource
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub config_resource: Box<dyn ResourceProvider + Send>,
     /// Vocab resource
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub vocab_resource: Box<dyn ResourceProvider + Send>,
     /// Merges resource
+    #[serde(default, with = "crate::common::serde_utils::optional_resource")]
     pub merges_resource: Option<Box<dyn ResourceProvider + Send>>,
     /// Supported source languages
     pub source_languages: HashSet<Language>,
@@ -410,6 +420,10 @@ pub struct TranslationConfig {
     /// Number of sequences to return for each prompt text (default: 1)
     pub num_return_sequences: i64,
     /// Device to place the model on (default: CUDA/GPU when available)
+    #[serde(
+        with = "crate::common::serde_utils::device",
+        default = "crate::common::serde_utils::device::default"
+    )]
     pub device: Device,
     /// Number of beam groups for diverse beam generation. If provided and higher than 1, will split the beams into beam subgroups leading to more diverse generation.
     pub num_beam_groups: Option<i64>,
@@ -520,12 +534,29 @@ impl From<TranslationConfig> for GenerateConfig {
             temperature: config.temperature,
             top_k: config.top_k,
             top_p: config.top_p,
+            min_p: 0.0,
             repetition_penalty: config.repetition_penalty,
             length_penalty: config.length_penalty,
             no_repeat_ngram_size: config.no_repeat_ngram_size,
             num_return_sequences: config.num_return_sequences,
             num_beam_groups: config.num_beam_groups,
             diversity_penalty: config.diversity_penalty,
+            pad_token_id: None,
+            max_prompt_length: None,
+            prompt_truncation_side: TruncationSide::Right,
+            prompt_truncation_strategy: TruncationStrategy::LongestFirst,
+            dola_layers: None,
+            attention_sink: None,
+            stop_sequences: Vec::new(),
+            seed: None,
+            bad_word_ids: Vec::new(),
+            bad_words: Vec::new(),
+            forced_bos_token_id: None,
+            forced_eos_token_id: None,
+            logit_bias: HashMap::new(),
+            logits_processors: Vec::new(),
+            stopping_criteria: Vec::new(),
+            layer_placement: HashMap::new(),
             device: config.device,
         }
     }
@@ -731,52 +762,58 @@ impl TranslationOption {
         &self,
         prompt_texts: Option<&[S]>,
         forced_bos_token_id: Option<i64>,
-    ) -> Vec<String>
+    ) -> Result<Vec<String>, RustBertError>
+    where
+        S: AsRef<str> + Sync,
+    {
+        let generate_options = GenerateOptions {
+            forced_bos_token_id,
+            ..Default::default()
+        };
+        Ok(self
+            .generate_with_options(prompt_texts, generate_options)?
+            .into_iter()
+            .map(|output| output.text)
+            .collect())
+    }
+
+    /// Interface method to generate() of the particular models, with generation options
+    /// overriding the configuration of the model.
+    pub fn generate_with_options<S>(
+        &self,
+        prompt_texts: Option<&[S]>,
+        generate_options: GenerateOptions,
+    ) -> Result<Vec<GeneratedTextOutput>, RustBertError>
     where
         S: AsRef<str> + Sync,
     {
         match *self {
-            Self::Marian(ref model) => model
-                .generate(prompt_texts, None)
-                .into_iter()
-                .map(|output| output.text)
-                .collect(),
-            Self::T5(ref model) => model
-                .generate(prompt_texts, None)
-                .into_iter()
-                .map(|output| output.text)
-                .collect(),
-            Self::MBart(ref model) => {
-                let generate_options = GenerateOptions {
-                    forced_bos_token_id,
-                    ..Default::default()
-                };
-                model
-                    .generate(prompt_texts, Some(generate_options))
-                    .into_iter()
-                    .map(|output| output.text)
-                    .collect()
-            }
-            Self::M2M100(ref model) => {
-                let generate_options = GenerateOptions {
-                    forced_bos_token_id,
-                    ..Default::default()
-                };
-                model
-                    .generate(prompt_texts, Some(generate_options))
-                    .into_iter()
-                    .map(|output| output.text)
-                    .collect()
-            }
+            Self::Marian(ref model) => model.generate(prompt_texts, Some(generate_options)),
+            Self::T5(ref model) => model.generate(prompt_texts, Some(generate_options)),
+            Self::MBart(ref model) => model.generate(prompt_texts, Some(generate_options)),
+            Self::M2M100(ref model) => model.generate(prompt_texts, Some(generate_options)),
         }
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Translation hypothesis returned by `TranslationModel::translate_n_best`
+pub struct TranslationHypothesis {
+    /// Translated text
+    pub text: String,
+    /// Log-likelihood score of the translation under the translation model
+    pub score: Option<f64>,
+    /// Reference-free quality estimation score, if a `QualityEstimationModel` is attached to the translation model
+    pub quality_score: Option<f64>,
+}
+
 /// # TranslationModel to perform translation
 pub struct TranslationModel {
     model: TranslationOption,
     supported_source_languages: HashSet<Language>,
     supported_target_languages: HashSet<Language>,
+    num_beams: i64,
+    quality_estimation_model: Option<QualityEstimationModel>,
 }
 
 impl TranslationModel {
@@ -822,8 +859,16 @@ impl TranslationModel {
     /// # }
     /// ```
     pub fn new(translation_config: TranslationConfig) -> Result<TranslationModel, RustBertError> {
+        let _span = trace_span!(
+            INFO,
+            "load_model",
+            pipeline = "translation",
+            model_type = ?translation_config.model_type,
+            device = ?translation_config.device
+        );
         let supported_source_languages = translation_config.source_languages.clone();
         let supported_target_languages = translation_config.target_languages.clone();
+        let num_beams = translation_config.num_beams;
 
         let model = TranslationOption::new(translation_config)?;
 
@@ -831,9 +876,25 @@ impl TranslationModel {
             model,
             supported_source_languages,
             supported_target_languages,
+            num_beams,
+            quality_estimation_model: None,
         })
     }
 
+    /// Attaches a reference-free `QualityEstimationModel` to the translation model: the hypotheses
+    /// returned by `translate_n_best` are scored with the quality estimation model.
+    ///
+    /// # Arguments
+    ///
+    /// * `quality_estimation_model` - `QualityEstimationModel` scoring (source, translation) pairs
+    pub fn with_quality_estimation(
+        mut self,
+        quality_estimation_model: QualityEstimationModel,
+    ) -> TranslationModel {
+        self.quality_estimation_model = Some(quality_estimation_model);
+        self
+    }
+
     /// Translates texts provided
     ///
     /// # Arguments
@@ -898,7 +959,7 @@ impl TranslationModel {
             &self.supported_target_languages,
         )?;
 
-        Ok(match prefix {
+        match prefix {
             Some(value) => {
                 let texts = texts
                     .iter()
@@ -907,7 +968,129 @@ impl TranslationModel {
                 self.model.generate(Some(&texts), forced_bos_token_id)
             }
             None => self.model.generate(Some(texts), forced_bos_token_id),
-        })
+        }
+    }
+
+    /// Translates texts provided, returning the n-best beam search hypotheses for each text. If a
+    /// `QualityEstimationModel` is attached to the model, the hypotheses are scored with it, allowing
+    /// to route low-confidence translations to human review.
+    ///
+    /// # Arguments
+    /// * `input` - `&[&str]` Array of texts to translate.
+    /// * `source_language` - Optional source language
+    /// * `target_language` - Optional target language
+    /// * `num_hypotheses` - Number of hypotheses to return for each text. The beam search uses at least as many beams.
+    ///
+    /// # Returns
+    /// * `Vec<Vec<TranslationHypothesis>>` Translation hypotheses for each text, by decreasing translation model score
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::common::ModelType;
+    /// use rust_bert::pipelines::sequence_classification::SequenceClassificationConfig;
+    /// use rust_bert::pipelines::translation::{
+    ///     Language, QualityEstimationConfig, QualityEstimationModel, TranslationModelBuilder,
+    /// };
+    /// use rust_bert::resources::LocalResource;
+    /// use std::path::PathBuf;
+    ///
+    /// let quality_estimation_model = QualityEstimationModel::new(QualityEstimationConfig::new(
+    ///     SequenceClassificationConfig::new(
+    ///         ModelType::XLMRoberta,
+    ///         LocalResource::from(PathBuf::from("path/to/rust_model.ot")),
+    ///         LocalResource::from(PathBuf::from("path/to/config.json")),
+    ///         LocalResource::from(PathBuf::from("path/to/sentencepiece.bpe.model")),
+    ///         None,
+    ///         false,
+    ///         None,
+    ///         None,
+    ///     ),
+    /// ))?;
+    /// let model = TranslationModelBuilder::new()
+    ///     .with_source_languages(vec![Language::English])
+    ///     .with_target_languages(vec![Language::French])
+    ///     .create_model()?
+    ///     .with_quality_estimation(quality_estimation_model);
+    ///
+    /// let output = model.translate_n_best(
+    ///     &["This is a sentence to be translated"],
+    ///     Language::English,
+    ///     Language::French,
+    ///     3,
+    /// )?;
+    /// for hypothesis in &output[0] {
+    ///     println!("{} ({:?})", hypothesis.text, hypothesis.quality_score);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn translate_n_best<S>(
+        &self,
+        texts: &[S],
+        source_language: impl Into<Option<Language>>,
+        target_language: impl Into<Option<Language>>,
+        num_hypotheses: usize,
+    ) -> Result<Vec<Vec<TranslationHypothesis>>, RustBertError>
+    where
+        S: AsRef<str> + Sync,
+    {
+        if num_hypotheses == 0 {
+            return Err(RustBertError::ValueError(
+                "num_hypotheses must be strictly greater than 0".to_string(),
+            ));
+        }
+        let (prefix, forced_bos_token_id) = self.model.validate_and_get_prefix_and_forced_bos_id(
+            source_language.into().as_ref(),
+            target_language.into().as_ref(),
+            &self.supported_source_languages,
+            &self.supported_target_languages,
+        )?;
+        let prompts = texts
+            .iter()
+            .map(|text| format!("{}{}", prefix.as_deref().unwrap_or(""), text.as_ref()))
+            .collect::<Vec<String>>();
+        let generate_options = GenerateOptions {
+            num_return_sequences: Some(num_hypotheses as i64),
+            num_beams: Some(self.num_beams.max(num_hypotheses as i64)),
+            forced_bos_token_id,
+            output_scores: true,
+            ..Default::default()
+        };
+        let outputs = self
+            .model
+            .generate_with_options(Some(&prompts), generate_options)?;
+
+        let quality_scores = match &self.quality_estimation_model {
+            Some(quality_estimation_model) => {
+                let sources = texts
+                    .iter()
+                    .flat_map(|text| std::iter::repeat(text.as_ref()).take(num_hypotheses))
+                    .collect::<Vec<&str>>();
+                let translations = outputs
+                    .iter()
+                    .map(|output| output.text.as_str())
+                    .collect::<Vec<&str>>();
+                Some(quality_estimation_model.estimate(&sources, &translations)?)
+            }
+            None => None,
+        };
+
+        let mut hypotheses = outputs
+            .into_iter()
+            .enumerate()
+            .map(|(index, output)| TranslationHypothesis {
+                text: output.text,
+                score: output.score,
+                quality_score: quality_scores.as_ref().map(|scores| scores[index]),
+            })
+            .peekable();
+        let mut output = Vec::with_capacity(texts.len());
+        while hypotheses.peek().is_some() {
+            output.push(hypotheses.by_ref().take(num_hypotheses).collect());
+        }
+        Ok(output)
     }
 }
 
@@ -101,21 +101,23 @@
 use crate::albert::AlbertForSequenceClassification;
 use crate::bart::BartForSequenceClassification;
 use crate::bert::BertForSequenceClassification;
+use crate::common::trace::trace_span;
 use crate::deberta::DebertaForSequenceClassification;
 use crate::distilbert::DistilBertModelClassifier;
 use crate::longformer::LongformerForSequenceClassification;
 use crate::mobilebert::MobileBertForSequenceClassification;
+use crate::pipelines::added_tokens::add_tokens_and_resize_embeddings;
 use crate::pipelines::common::{ConfigOption, ModelType, TokenizerOption};
-use crate::pipelines::sequence_classification::Label;
+use crate::pipelines::input_encoding::EncodedInputs;
+use crate::pipelines::output_cache::OutputCache;
+use crate::pipelines::sequence_classification::{Label, ScoreNormalization};
 use crate::resources::ResourceProvider;
 use crate::roberta::RobertaForSequenceClassification;
 use crate::xlnet::XLNetForSequenceClassification;
 use crate::RustBertError;
-use rust_tokenizers::tokenizer::TruncationStrategy;
-use rust_tokenizers::TokenizedInput;
 use std::borrow::Borrow;
 use std::ops::Deref;
-use tch::kind::Kind::{Bool, Float};
+use tch::kind::Kind::Float;
 use tch::nn::VarStore;
 use tch::{nn, no_grad, Device, Tensor};
 
@@ -124,19 +126,25 @@ use crate::{
     bart::{BartConfigResources, BartMergesResources, BartModelResources, BartVocabResources},
     resources::RemoteResource,
 };
+use serde::{Deserialize, Serialize};
 
+#[derive(Serialize, Deserialize)]
 /// # Configuration for ZeroShotClassificationModel
 /// Contains information regarding the model to load and device to place the model on.
 pub struct ZeroShotClassificationConfig {
     /// Model type
     pub model_type: ModelType,
     /// Model weights resource (default: pretrained BERT model on CoNLL)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub model_resource: Box<dyn ResourceProvider + Send>,
     /// Config resource (default: pretrained BERT model on CoNLL)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub config_resource: Box<dyn ResourceProvider + Send>,
     /// Vocab resource (default: pretrained BERT model on CoNLL)
+    #[serde(with = "crate::common::serde_utils::resource")]
     pub vocab_resource: Box<dyn ResourceProvider + Send>,
     /// Merges resource (default: None)
+    #[serde(default, with = "crate::common::serde_utils::optional_resource")]
     pub merges_resource: Option<Box<dyn ResourceProvider + Send>>,
     /// Automatically lower case all input upon tokenization (assumes a lower-cased model)
     pub lower_case: bool,
@@ -145,6 +153,10 @@ pub struct ZeroShotClassificationConfig {
     /// Flag indicating if the tokenizer should add a white space before each tokenized input (needed for some Roberta models)
     pub add_prefix_space: Option<bool>,
     /// Device to place the model on (default: CUDA/GPU when available)
+    #[serde(
+        with = "crate::common::serde_utils::device",
+        default = "crate::common::serde_utils::device::default"
+    )]
     pub device: Device,
 }
 
@@ -402,6 +414,12 @@ impl ZeroShotClassificationOption {
         input_embeds: Option<&Tensor>,
         train: bool,
     ) -> Tensor {
+        let _span = trace_span!(
+            DEBUG,
+            "forward",
+            input_shape = ?input_ids.map(Tensor::size),
+            device = ?input_ids.map(Tensor::device)
+        );
         match *self {
             Self::Bart(ref model) => {
                 model
@@ -532,6 +550,7 @@ pub struct ZeroShotClassificationModel {
     tokenizer: TokenizerOption,
     zero_shot_classifier: ZeroShotClassificationOption,
     var_store: VarStore,
+    output_cache: Option<OutputCache>,
 }
 
 impl ZeroShotClassificationModel {
@@ -554,15 +573,12 @@ impl ZeroShotClassificationModel {
     pub fn new(
         config: ZeroShotClassificationConfig,
     ) -> Result<ZeroShotClassificationModel, RustBertError> {
-        let config_path = config.config_resource.get_local_path()?;
         let vocab_path = config.vocab_resource.get_local_path()?;
-        let weights_path = config.model_resource.get_local_path()?;
         let merges_path = if let Some(merges_resource) = &config.merges_resource {
             Some(merges_resource.get_local_path()?)
         } else {
             None
         };
-        let device = config.device;
 
         let tokenizer = TokenizerOption::from_file(
             config.model_type,
@@ -572,6 +588,31 @@ impl ZeroShotClassificationModel {
             config.strip_accents,
             config.add_prefix_space,
         )?;
+        Self::new_with_tokenizer(config, tokenizer)
+    }
+
+    /// Build a new `ZeroShotClassificationModel` with a provided tokenizer (e.g. a HuggingFace tokenizer loaded
+    /// from a `tokenizer.json` file). The tokenizer resources of the configuration are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `ZeroShotClassificationConfig` object containing the resource references (model, vocabulary, configuration) and device placement (CPU/GPU)
+    /// * `tokenizer` - `TokenizerOption` tokenizer to use for zero-shot classification
+    pub fn new_with_tokenizer(
+        config: ZeroShotClassificationConfig,
+        tokenizer: TokenizerOption,
+    ) -> Result<ZeroShotClassificationModel, RustBertError> {
+        let _span = trace_span!(
+            INFO,
+            "load_model",
+            pipeline = "zero_shot_classification",
+            model_type = ?config.model_type,
+            device = ?config.device
+        );
+        let config_path = config.config_resource.get_local_path()?;
+        let weights_path = config.model_resource.get_local_path()?;
+        let device = config.device;
+
         let mut var_store = VarStore::new(device);
         let model_config = ConfigOption::from_file(config.model_type, config_path);
         let zero_shot_classifier =
@@ -581,16 +622,67 @@ impl ZeroShotClassificationModel {
             tokenizer,
             zero_shot_classifier,
             var_store,
+            output_cache: None,
         })
     }
 
+    /// Registers additional tokens (e.g. special markers or domain-specific terms) on the tokenizer
+    /// of the model. The added tokens are never split by the tokenizer, and the token embeddings of
+    /// the model are resized, the new embeddings being initialized with the mean embedding of the
+    /// existing vocabulary.
+    ///
+    /// # Arguments
+    ///
+    /// * `tokens` - tokens to add (tokens already part of the vocabulary are ignored)
+    pub fn with_added_tokens<S: AsRef<str>>(
+        mut self,
+        tokens: &[S],
+    ) -> Result<ZeroShotClassificationModel, RustBertError> {
+        self.tokenizer = add_tokens_and_resize_embeddings(self.tokenizer, &self.var_store, tokens)?;
+        Ok(self)
+    }
+
+    /// Enables a cache of the model outputs keyed by the hash of the encoded (premise, hypothesis)
+    /// pairs, so that pairs classified repeatedly (e.g. the same premise against a recurring set of
+    /// labels) are only encoded once. See `OutputCache` for details.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - maximum number of (premise, hypothesis) outputs kept in the cache
+    pub fn with_output_cache(mut self, capacity: usize) -> ZeroShotClassificationModel {
+        self.output_cache = Some(OutputCache::new(capacity));
+        self
+    }
+
+    fn forward_encoded(&self, encoded_inputs: &EncodedInputs) -> Tensor {
+        let forward =
+            |input_ids: &Tensor, attention_mask: &Tensor, token_type_ids: Option<&Tensor>| {
+                self.zero_shot_classifier.forward_t(
+                    Some(input_ids),
+                    Some(attention_mask),
+                    token_type_ids,
+                    None,
+                    None,
+                    false,
+                )
+            };
+        match &self.output_cache {
+            Some(output_cache) => output_cache.forward(encoded_inputs, forward),
+            None => forward(
+                &encoded_inputs.input_ids,
+                &encoded_inputs.attention_mask,
+                encoded_inputs.token_type_ids.as_ref(),
+            ),
+        }
+    }
+
     fn prepare_for_model<'a, S, T>(
         &self,
         inputs: S,
         labels: T,
         template: Option<ZeroShotTemplate>,
         max_len: usize,
-    ) -> Result<(Tensor, Tensor), RustBertError>
+    ) -> Result<EncodedInputs, RustBertError>
     where
         S: AsRef<[&'a str]>,
         T: AsRef<[&'a str]>,
@@ -618,41 +710,12 @@ impl ZeroShotClassificationModel {
             })
             .collect::<Vec<(&str, &str)>>();
 
-        let tokenized_input: Vec<TokenizedInput> = self.tokenizer.encode_pair_list(
-            text_pair_list.as_ref(),
+        EncodedInputs::from_text_pairs(
+            &self.tokenizer,
+            &text_pair_list,
             max_len,
-            &TruncationStrategy::LongestFirst,
-            0,
-        );
-        let max_len = tokenized_input
-            .iter()
-            .map(|input| input.token_ids.len())
-            .max()
-            .ok_or_else(|| RustBertError::ValueError("Got empty iterator as input".to_string()))?;
-
-        let pad_id = self
-            .tokenizer
-            .get_pad_id()
-            .expect("The Tokenizer used for sequence classification should contain a PAD id");
-        let tokenized_input_tensors = tokenized_input
-            .into_iter()
-            .map(|mut input| {
-                input.token_ids.resize(max_len, pad_id);
-                Tensor::of_slice(&(input.token_ids))
-            })
-            .collect::<Vec<_>>();
-
-        let tokenized_input_tensors =
-            Tensor::stack(tokenized_input_tensors.as_slice(), 0).to(self.var_store.device());
-
-        let mask = tokenized_input_tensors
-            .ne(self
-                .tokenizer
-                .get_pad_id()
-                .expect("The Tokenizer used for zero shot classification should contain a PAD id"))
-            .to_kind(Bool);
-
-        Ok((tokenized_input_tensors, mask))
+            self.var_store.device(),
+        )
     }
 
     /// Zero shot classification with 1 (and exactly 1) true label.
@@ -721,18 +784,11 @@ impl ZeroShotClassificationModel {
         T: AsRef<[&'a str]>,
     {
         let num_inputs = inputs.as_ref().len();
-        let (input_tensor, mask) =
+        let encoded_inputs =
             self.prepare_for_model(inputs.as_ref(), labels.as_ref(), template, max_length)?;
 
         let output = no_grad(|| {
-            let output = self.zero_shot_classifier.forward_t(
-                Some(&input_tensor),
-                Some(&mask),
-                None,
-                None,
-                None,
-                false,
-            );
+            let output = self.forward_encoded(&encoded_inputs);
             output.view((num_inputs as i64, labels.as_ref().len() as i64, -1i64))
         });
 
@@ -863,18 +919,11 @@ impl ZeroShotClassificationModel {
         T: AsRef<[&'a str]>,
     {
         let num_inputs = inputs.as_ref().len();
-        let (input_tensor, mask) =
+        let encoded_inputs =
             self.prepare_for_model(inputs.as_ref(), labels.as_ref(), template, max_length)?;
 
         let output = no_grad(|| {
-            let output = self.zero_shot_classifier.forward_t(
-                Some(&input_tensor),
-                Some(&mask),
-                None,
-                None,
-                None,
-                false,
-            );
+            let output = self.forward_encoded(&encoded_inputs);
             output.view((num_inputs as i64, labels.as_ref().len() as i64, -1i64))
         });
         let scores = output.slice(-1, 0, 3, 2).softmax(-1, Float).select(-1, -1);
@@ -901,6 +950,88 @@ impl ZeroShotClassificationModel {
         }
         Ok(output_labels)
     }
+
+    /// Zero shot classification returning the scores of all candidate labels
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - `&[&str]` Array of texts to classify.
+    /// * `labels` - `&[&str]` Possible labels for the inputs.
+    /// * `template` - `Option<Box<dyn Fn(&str) -> String>>` closure to build label propositions. If None, will default to `"This example is about {}."`.
+    /// * `max_length` -`usize` Maximum sequence length for the inputs. If needed, the input sequence will be truncated before the label template.
+    /// * `normalization` - `ScoreNormalization` of the entailment scores: `Softmax` normalizes them over the candidate labels (exactly one true label, as `predict`), `Sigmoid` scores each label independently from its entailment and contradiction logits (any number of true labels, as `predict_multilabel`)
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<Vec<Label>>, RustBertError>` containing, for each input text, every candidate label sorted by decreasing score
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::sequence_classification::ScoreNormalization;
+    /// use rust_bert::pipelines::zero_shot_classification::ZeroShotClassificationModel;
+    ///
+    /// let sequence_classification_model = ZeroShotClassificationModel::new(Default::default())?;
+    ///
+    /// let input_sentence = "Who are you voting for in 2020?";
+    /// let candidate_labels = &["politics", "public health", "economics", "sports"];
+    ///
+    /// let output = sequence_classification_model.predict_full(
+    ///     &[input_sentence],
+    ///     candidate_labels,
+    ///     None,
+    ///     128,
+    ///     ScoreNormalization::Softmax,
+    /// )?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn predict_full<'a, S, T>(
+        &self,
+        inputs: S,
+        labels: T,
+        template: Option<ZeroShotTemplate>,
+        max_length: usize,
+        normalization: ScoreNormalization,
+    ) -> Result<Vec<Vec<Label>>, RustBertError>
+    where
+        S: AsRef<[&'a str]>,
+        T: AsRef<[&'a str]>,
+    {
+        let num_inputs = inputs.as_ref().len();
+        let encoded_inputs =
+            self.prepare_for_model(inputs.as_ref(), labels.as_ref(), template, max_length)?;
+
+        let output = no_grad(|| {
+            let output = self.forward_encoded(&encoded_inputs);
+            output.view((num_inputs as i64, labels.as_ref().len() as i64, -1i64))
+        });
+        let scores = match normalization {
+            ScoreNormalization::Softmax => output.softmax(1, Float).select(-1, -1),
+            ScoreNormalization::Sigmoid => {
+                output.slice(-1, 0, 3, 2).softmax(-1, Float).select(-1, -1)
+            }
+        };
+
+        let mut output_labels = Vec::with_capacity(num_inputs);
+        for sentence_idx in 0..num_inputs {
+            let mut sentence_labels = scores
+                .select(0, sentence_idx as i64)
+                .iter::<f64>()?
+                .enumerate()
+                .map(|(label_index, score)| Label {
+                    text: labels.as_ref()[label_index].to_string(),
+                    score,
+                    id: label_index as i64,
+                    sentence: sentence_idx,
+                })
+                .collect::<Vec<Label>>();
+            sentence_labels.sort_by(|a, b| b.score.total_cmp(&a.score));
+            output_labels.push(sentence_labels);
+        }
+        Ok(output_labels)
+    }
 }
 #[cfg(test)]
 mod test {
@@ -13,14 +13,17 @@
 use std::borrow::Borrow;
 use std::collections::HashMap;
 
-use rust_tokenizers::tokenizer::{ProphetNetTokenizer, TruncationStrategy};
+use rust_tokenizers::tokenizer::ProphetNetTokenizer;
 use rust_tokenizers::vocab::{ProphetNetVocab, Vocab};
 use serde::{Deserialize, Serialize};
 use tch::{nn, Kind, Tensor};
 
+use crate::common::embeddings::load_weights_with_tied_embeddings;
+use crate::common::loading::check_device;
+use crate::common::placement::check_layer_placement_unsupported;
 use crate::pipelines::common::{ModelType, TokenizerOption};
 use crate::pipelines::generation_utils::private_generation_utils::{
-    PreparedInput, PrivateLanguageGenerator,
+    pad_prompt_ids, PreparedInput, PrivateLanguageGenerator,
 };
 use crate::pipelines::generation_utils::{
     Cache, GenerateConfig, LMHeadModel, LMModelOutput, LanguageGenerator,
@@ -631,6 +634,7 @@ impl LMHeadModel for ProphetNetForConditionalGeneration {
         Ok(LMModelOutput {
             lm_logits: base_model_output.logits,
             cache: Cache::ProphetNetCache(base_model_output.next_decoder_cache),
+            all_hidden_states: None,
         })
     }
 }
@@ -962,11 +966,13 @@ impl ProphetNetConditionalGenerator {
         let weights_path = generate_config.model_resource.get_local_path()?;
         let device = generate_config.device;
 
-        generate_config.validate();
+        generate_config.validate()?;
+        check_layer_placement_unsupported(&generate_config.layer_placement, "ProphetNet")?;
+        check_device(device)?;
         let mut var_store = nn::VarStore::new(device);
-        let config = ProphetNetConfig::from_file(config_path);
+        let config = ProphetNetConfig::try_from_file(config_path)?;
         let model = ProphetNetForConditionalGeneration::new(&var_store.root(), &config)?;
-        var_store.load(weights_path)?;
+        load_weights_with_tied_embeddings(&mut var_store, weights_path)?;
 
         let bos_token_id = Some(config.bos_token_id);
         let eos_token_ids = Some(vec![config.eos_token_id]);
@@ -1077,24 +1083,11 @@ impl
         prompt_text: &[S],
         max_len: Option<i64>,
         pad_token_id: Option<i64>,
-    ) -> Tensor
+    ) -> (Tensor, Tensor)
     where
         S: AsRef<str> + Sync,
     {
-        let tokens = self._get_tokenizer().encode_list(
-            prompt_text,
-            max_len
-                .map(|max_len| max_len as usize)
-                .unwrap_or(usize::MAX),
-            &TruncationStrategy::LongestFirst,
-            0,
-        );
-        let token_ids = tokens
-            .into_iter()
-            .map(|tokenized_input| tokenized_input.token_ids)
-            .collect::<Vec<Vec<i64>>>();
-
-        let max_len = token_ids.iter().map(|input| input.len()).max().unwrap();
+        let token_ids = self.tokenize_prompt_text(prompt_text, max_len, true);
 
         let pad_token = match pad_token_id {
             Some(value) => value,
@@ -1103,17 +1096,7 @@ impl
                 .convert_tokens_to_ids(&[ProphetNetVocab::unknown_value()])[0],
         };
 
-        let token_ids = token_ids
-            .into_iter()
-            .map(|mut input| {
-                let temp = vec![pad_token; max_len - input.len()];
-                input.extend(temp);
-                input
-            })
-            .map(|tokens| Tensor::of_slice(&tokens).to(self.get_var_store().device()))
-            .collect::<Vec<Tensor>>();
-
-        Tensor::stack(&token_ids, 0)
+        pad_prompt_ids(token_ids, pad_token, false, self.get_var_store().device())
     }
 
     fn reorder_cache(
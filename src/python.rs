@@ -0,0 +1,335 @@
+//! # Python bindings
+//! Exposes the pipelines as Python classes using [PyO3](https://pyo3.rs) (enabled with the `python`
+//! feature). Each class wraps a [`Pipeline`] and exchanges native Python objects (strings, lists,
+//! dictionaries) with the caller, following the input and output formats of [`Pipeline::predict_json`].
+//!
+//! The extension module can be built with [maturin](https://github.com/PyO3/maturin)
+//! (`maturin build --release --features python`) or directly with
+//! `cargo rustc --release --lib --features python,pyo3/extension-module --crate-type cdylib`,
+//! renaming the resulting library to `rust_bert.so` (`rust_bert.pyd` on Windows).
+//!
+//! Models are created from an optional model specification (see [`ModelSpec`]), provided as a
+//! dictionary or a JSON string. The default pretrained model of the task is used if no
+//! specification is given (requires the `remote` feature).
+//!
+//! ```python
+//! import rust_bert
+//!
+//! sentiment_model = rust_bert.SentimentModel()
+//! output = sentiment_model.predict(["This is a great movie!", "What a waste of time."])
+//!
+//! ner_model = rust_bert.Pipeline("NER", {
+//!     "model_type": "Bert",
+//!     "model_resource": {"local": {"local_path": "path/to/rust_model.ot"}},
+//!     "config_resource": {"local": {"local_path": "path/to/config.json"}},
+//!     "vocab_resource": {"local": {"local_path": "path/to/vocab.txt"}},
+//!     "device": "cpu",
+//! })
+//! entities = ner_model.predict(["My name is Amy. I live in Paris."])
+//! ```
+
+use crate::pipelines::registry::{ModelSpec, Pipeline, TaskType};
+use crate::RustBertError;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyString;
+use serde_json::Value;
+
+impl From<RustBertError> for PyErr {
+    fn from(error: RustBertError) -> Self {
+        match error {
+            RustBertError::ValueError(_) | RustBertError::InvalidConfigurationError(_) => {
+                PyValueError::new_err(error.to_string())
+            }
+            _ => PyRuntimeError::new_err(error.to_string()),
+        }
+    }
+}
+
+/// Converts a Python object to a JSON value using the Python `json` module
+fn to_json(py: Python, object: &PyAny) -> PyResult<Value> {
+    let serialized: String = py
+        .import("json")?
+        .call_method1("dumps", (object,))?
+        .extract()?;
+    Ok(serde_json::from_str(&serialized).map_err(RustBertError::from)?)
+}
+
+/// Converts a JSON value to a Python object using the Python `json` module
+fn from_json(py: Python, value: &Value) -> PyResult<PyObject> {
+    Ok(py
+        .import("json")?
+        .call_method1("loads", (value.to_string(),))?
+        .into())
+}
+
+fn create_pipeline(py: Python, task: TaskType, model_spec: Option<&PyAny>) -> PyResult<Pipeline> {
+    let pipeline = match model_spec {
+        Some(model_spec) => {
+            let model_spec: ModelSpec = if let Ok(model_spec) = model_spec.downcast::<PyString>() {
+                serde_json::from_str(model_spec.to_str()?).map_err(RustBertError::from)?
+            } else {
+                serde_json::from_value(to_json(py, model_spec)?).map_err(RustBertError::from)?
+            };
+            Pipeline::new(task, model_spec)?
+        }
+        #[cfg(feature = "remote")]
+        None => Pipeline::new_default(task)?,
+        #[cfg(not(feature = "remote"))]
+        None => {
+            return Err(PyValueError::new_err(
+                "A model specification is required when the remote feature is disabled",
+            ));
+        }
+    };
+    Ok(pipeline)
+}
+
+fn predict(py: Python, pipeline: &Pipeline, inputs: &PyAny) -> PyResult<PyObject> {
+    let inputs = to_json(py, inputs)?;
+    let outputs = pipeline.predict_json(&inputs)?;
+    from_json(py, &outputs)
+}
+
+/// # Generic pipeline
+/// Pipeline created from a task name (e.g. `"Sentiment"`, `"NER"`, `"Summarization"`) and an
+/// optional model specification.
+#[pyclass(name = "Pipeline", unsendable)]
+pub struct PyPipeline {
+    pipeline: Pipeline,
+}
+
+#[pymethods]
+impl PyPipeline {
+    #[new]
+    #[args(model_spec = "None")]
+    fn new(py: Python, task: &str, model_spec: Option<&PyAny>) -> PyResult<Self> {
+        let task: TaskType =
+            serde_json::from_value(Value::String(task.to_string())).map_err(RustBertError::from)?;
+        Ok(PyPipeline {
+            pipeline: create_pipeline(py, task, model_spec)?,
+        })
+    }
+
+    /// Runs the pipeline on the inputs and returns the outputs as Python objects
+    fn predict(&self, py: Python, inputs: &PyAny) -> PyResult<PyObject> {
+        predict(py, &self.pipeline, inputs)
+    }
+
+    /// Task performed by the pipeline
+    #[getter]
+    fn task(&self) -> String {
+        format!("{:?}", self.pipeline.task())
+    }
+}
+
+macro_rules! task_pipeline {
+    ($(#[$doc:meta])* $struct_name:ident, $py_name:literal, $task:expr) => {
+        $(#[$doc])*
+        #[pyclass(name = $py_name, unsendable)]
+        pub struct $struct_name {
+            pipeline: Pipeline,
+        }
+
+        #[pymethods]
+        impl $struct_name {
+            #[new]
+            #[args(model_spec = "None")]
+            fn new(py: Python, model_spec: Option<&PyAny>) -> PyResult<Self> {
+                Ok($struct_name {
+                    pipeline: create_pipeline(py, $task, model_spec)?,
+                })
+            }
+
+            /// Runs the pipeline on the inputs and returns the outputs as Python objects
+            fn predict(&self, py: Python, inputs: &PyAny) -> PyResult<PyObject> {
+                predict(py, &self.pipeline, inputs)
+            }
+        }
+    };
+}
+
+task_pipeline!(
+    /// Sequence classification model, taking a list of strings as input
+    PySequenceClassificationModel,
+    "SequenceClassificationModel",
+    TaskType::SequenceClassification
+);
+task_pipeline!(
+    /// Sentiment analysis model, taking a list of strings as input
+    PySentimentModel,
+    "SentimentModel",
+    TaskType::Sentiment
+);
+task_pipeline!(
+    /// Token classification model, taking a list of strings as input
+    PyTokenClassificationModel,
+    "TokenClassificationModel",
+    TaskType::TokenClassification
+);
+task_pipeline!(
+    /// Named entity recognition model, taking a list of strings as input
+    PyNERModel,
+    "NERModel",
+    TaskType::NER
+);
+task_pipeline!(
+    /// Part of speech tagging model, taking a list of strings as input
+    PyPOSModel,
+    "POSModel",
+    TaskType::POSTagging
+);
+task_pipeline!(
+    /// Extractive question answering model, taking a list of `{"question": ..., "context": ...}`
+    /// dictionaries as input
+    PyQuestionAnsweringModel,
+    "QuestionAnsweringModel",
+    TaskType::QuestionAnswering
+);
+task_pipeline!(
+    /// Zero-shot classification model, taking a `{"inputs": [...], "labels": [...]}` dictionary as
+    /// input
+    PyZeroShotClassificationModel,
+    "ZeroShotClassificationModel",
+    TaskType::ZeroShotClassification
+);
+task_pipeline!(
+    /// Summarization model, taking a list of strings as input
+    PySummarizationModel,
+    "SummarizationModel",
+    TaskType::Summarization
+);
+task_pipeline!(
+    /// Text generation model, taking a list of prompts as input
+    PyTextGenerationModel,
+    "TextGenerationModel",
+    TaskType::TextGeneration
+);
+task_pipeline!(
+    /// Conversation model, taking a list of user inputs (each starting a new conversation) as input
+    PyConversationModel,
+    "ConversationModel",
+    TaskType::Conversation
+);
+task_pipeline!(
+    /// Masked language model, taking a list of strings with masked tokens as input
+    PyMaskedLanguageModel,
+    "MaskedLanguageModel",
+    TaskType::MaskedLanguage
+);
+
+/// Python module exposing the pipeline classes
+#[pymodule]
+fn rust_bert(_py: Python, module: &PyModule) -> PyResult<()> {
+    module.add_class::<PyPipeline>()?;
+    module.add_class::<PySequenceClassificationModel>()?;
+    module.add_class::<PySentimentModel>()?;
+    module.add_class::<PyTokenClassificationModel>()?;
+    module.add_class::<PyNERModel>()?;
+    module.add_class::<PyPOSModel>()?;
+    module.add_class::<PyQuestionAnsweringModel>()?;
+    module.add_class::<PyZeroShotClassificationModel>()?;
+    module.add_class::<PySummarizationModel>()?;
+    module.add_class::<PyTextGenerationModel>()?;
+    module.add_class::<PyConversationModel>()?;
+    module.add_class::<PyMaskedLanguageModel>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pyo3::types::{PyDict, PyList};
+
+    fn with_module<F: FnOnce(Python, &PyModule) -> PyResult<()>>(f: F) -> PyResult<()> {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "rust_bert")?;
+            rust_bert(py, module)?;
+            f(py, module)
+        })
+    }
+
+    #[test]
+    fn module_exposes_pipeline_classes() -> PyResult<()> {
+        with_module(|_py, module| {
+            for class_name in [
+                "Pipeline",
+                "SequenceClassificationModel",
+                "SentimentModel",
+                "TokenClassificationModel",
+                "NERModel",
+                "POSModel",
+                "QuestionAnsweringModel",
+                "ZeroShotClassificationModel",
+                "SummarizationModel",
+                "TextGenerationModel",
+                "ConversationModel",
+                "MaskedLanguageModel",
+            ]
+            .iter()
+            {
+                assert!(module.hasattr(*class_name)?, "missing class {}", class_name);
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn json_conversion_round_trip() -> PyResult<()> {
+        with_module(|py, _module| {
+            let inputs = PyDict::new(py);
+            inputs.set_item("inputs", PyList::new(py, &["Who are you?"]))?;
+            inputs.set_item("labels", PyList::new(py, &["politics", "sports"]))?;
+
+            let value = to_json(py, inputs)?;
+            assert_eq!(
+                value,
+                serde_json::json!({"inputs": ["Who are you?"], "labels": ["politics", "sports"]})
+            );
+            let object = from_json(py, &value)?;
+            assert!(object.as_ref(py).eq(inputs)?);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn invalid_arguments_raise_value_errors() -> PyResult<()> {
+        with_module(|py, module| {
+            let pipeline_class = module.getattr("Pipeline")?;
+
+            let error = pipeline_class.call1(("NotATask",)).unwrap_err();
+            assert!(error.is_instance_of::<PyValueError>(py));
+
+            //    Model specifications are validated before any model is loaded
+            let model_spec = PyDict::new(py);
+            model_spec.set_item("model_type", "Bert")?;
+            let error = pipeline_class.call1(("Sentiment", model_spec)).unwrap_err();
+            assert!(error.is_instance_of::<PyValueError>(py));
+            let error = module
+                .getattr("SentimentModel")?
+                .call1(("{\"model_type\": ",))
+                .unwrap_err();
+            assert!(error.is_instance_of::<PyValueError>(py));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn rust_errors_map_to_python_exceptions() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let error: PyErr = RustBertError::ValueError("invalid input".to_string()).into();
+            assert!(error.is_instance_of::<PyValueError>(py));
+            let error: PyErr =
+                RustBertError::InvalidConfigurationError("invalid setting".to_string()).into();
+            assert!(error.is_instance_of::<PyValueError>(py));
+            let error: PyErr = RustBertError::TokenizerError("invalid vocab".to_string()).into();
+            assert!(error.is_instance_of::<PyRuntimeError>(py));
+            assert_eq!(
+                error.value(py).to_string(),
+                "Tokenizer error: invalid vocab"
+            );
+        });
+    }
+}
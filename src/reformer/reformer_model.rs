@@ -22,7 +22,11 @@ use tch::{nn, Device, Kind, Tensor};
 
 use crate::common::activations::Activation;
 use crate::common::dropout::Dropout;
-use crate::common::embeddings::get_shape_and_device_from_ids_embeddings_pair;
+use crate::common::embeddings::{
+    get_shape_and_device_from_ids_embeddings_pair, load_weights_with_tied_embeddings,
+};
+use crate::common::loading::check_device;
+use crate::common::placement::check_layer_placement_unsupported;
 use crate::pipelines::common::{ModelType, TokenizerOption};
 use crate::pipelines::generation_utils::private_generation_utils::{
     PreparedInput, PrivateLanguageGenerator,
@@ -683,6 +687,7 @@ impl LMHeadModel for ReformerModelWithLMHead {
         Ok(LMModelOutput {
             lm_logits: output.logits,
             cache: Cache::ReformerCache(output.next_cache),
+            all_hidden_states: None,
         })
     }
 }
@@ -1088,11 +1093,13 @@ impl ReformerGenerator {
         let weights_path = generate_config.model_resource.get_local_path()?;
         let device = generate_config.device;
 
-        generate_config.validate();
+        generate_config.validate()?;
+        check_layer_placement_unsupported(&generate_config.layer_placement, "Reformer")?;
+        check_device(device)?;
         let mut var_store = nn::VarStore::new(device);
-        let config = ReformerConfig::from_file(config_path);
+        let config = ReformerConfig::try_from_file(config_path)?;
         let model = ReformerModelWithLMHead::new(&var_store.root(), &config)?;
-        var_store.load(weights_path)?;
+        load_weights_with_tied_embeddings(&mut var_store, weights_path)?;
 
         let bos_token_id = tokenizer.get_bos_id();
         let eos_token_ids = tokenizer.get_eos_id().map(|id| vec![id]);
@@ -0,0 +1,1194 @@
+//! # OpenAI-compatible REST server
+//! HTTP server built on [axum](https://github.com/tokio-rs/axum) (enabled with the `rest` feature),
+//! exposing the generation pipelines with the request and response formats of the OpenAI API. This
+//! allows existing OpenAI client applications to use a locally hosted model by changing their base
+//! URL. The following endpoints are available:
+//! - `POST /v1/completions`: text completion backed by a `TextGenerationModel`
+//! - `POST /v1/chat/completions`: chat completion backed by a `ConversationModel`. An optional
+//! `system` message is used as the system prompt of the conversation, and the remaining messages
+//! must alternate between `user` and `assistant`, ending with a `user` message.
+//! - `GET /v1/models`: lists the model served
+//!
+//! Both completion endpoints support streaming with server-sent events (`"stream": true`). The text
+//! is sent while it is generated, as soon as the new tokens form valid text (see
+//! `IncrementalDecoder`), followed by a final chunk for each choice and the `[DONE]` message.
+//! Streamed text completions do not use beam search, and streamed chat completions require a
+//! conversation model configured without beam search. Models applying output filters only send the
+//! filtered text once the generation completes.
+//!
+//! The `max_tokens`, `temperature`, `top_p`, `n` and `stop` fields of the requests override the
+//! generation settings of the pipeline configurations (chat completions support neither stop
+//! sequences nor more than one choice). Requests setting other fields of the OpenAI API (e.g.
+//! `logprobs`) are rejected.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! # let runtime = tokio::runtime::Runtime::new()?;
+//! # runtime.block_on(async {
+//! use rust_bert::rest::OpenAIServer;
+//!
+//! let server = OpenAIServer::new(
+//!     "gpt2",
+//!     Some(Default::default()),
+//!     Some(Default::default()),
+//! )?;
+//! server.serve("127.0.0.1:8000".parse()?).await?;
+//! # Ok::<(), anyhow::Error>(())
+//! # })
+//! # }
+//! ```
+
+use crate::pipelines::conversation::{
+    ConversationConfig, ConversationGenerationSettings, ConversationManager, ConversationModel,
+};
+use crate::pipelines::generation_utils::GenerateOptions;
+use crate::pipelines::streaming::IncrementalDecoder;
+use crate::pipelines::text_generation::{TextGenerationConfig, TextGenerationModel};
+use crate::RustBertError;
+use axum::extract::Extension;
+use axum::http::StatusCode;
+use axum::response::sse::{Event, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// # Message of a chat completion request
+pub struct ChatMessage {
+    /// Author of the message (`system`, `user` or `assistant`)
+    pub role: String,
+    /// Content of the message
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+/// # Prompt of a completion request, either a single string or a batch of strings
+pub enum Prompt {
+    /// Single prompt
+    Single(String),
+    /// Batch of prompts
+    Batch(Vec<String>),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+/// # Stop sequences of a request, either a single string or a list of strings
+pub enum StopSequences {
+    /// Single stop sequence
+    Single(String),
+    /// List of stop sequences
+    Multiple(Vec<String>),
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+/// # Generation settings of a request
+/// Settings left to `None` default to the configuration of the pipeline.
+pub struct GenerationParameters {
+    /// Maximum number of tokens generated
+    pub max_tokens: Option<i64>,
+    /// Sampling temperature. A temperature of 0 selects the most likely tokens (greedy decoding)
+    pub temperature: Option<f64>,
+    /// Top_p value for nucleus sampling
+    pub top_p: Option<f64>,
+    /// Number of choices generated for each prompt (default: 1)
+    pub n: Option<i64>,
+    /// Stop sequences ending the generation
+    pub stop: Option<StopSequences>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+/// # Completion request (`/v1/completions`)
+pub struct CompletionRequest {
+    /// Name of the model (must match the name of the model served if provided)
+    pub model: Option<String>,
+    /// Prompt(s) to complete
+    pub prompt: Prompt,
+    /// Stream the completion with server-sent events
+    #[serde(default)]
+    pub stream: bool,
+    /// Generation settings of the request
+    #[serde(flatten)]
+    pub parameters: GenerationParameters,
+    /// Other fields of the request, which are not supported and rejected unless `null`
+    #[serde(flatten)]
+    pub unsupported: HashMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+/// # Chat completion request (`/v1/chat/completions`)
+pub struct ChatCompletionRequest {
+    /// Name of the model (must match the name of the model served if provided)
+    pub model: Option<String>,
+    /// Messages of the conversation
+    pub messages: Vec<ChatMessage>,
+    /// Stream the completion with server-sent events
+    #[serde(default)]
+    pub stream: bool,
+    /// Generation settings of the request
+    #[serde(flatten)]
+    pub parameters: GenerationParameters,
+    /// Other fields of the request, which are not supported and rejected unless `null`
+    #[serde(flatten)]
+    pub unsupported: HashMap<String, Value>,
+}
+
+impl GenerationParameters {
+    fn stop_sequences(&self) -> Vec<String> {
+        match &self.stop {
+            Some(StopSequences::Single(stop_sequence)) => vec![stop_sequence.clone()],
+            Some(StopSequences::Multiple(stop_sequences)) => stop_sequences.clone(),
+            None => vec![],
+        }
+    }
+
+    fn num_choices(&self) -> i64 {
+        self.n.unwrap_or(1)
+    }
+
+    /// Returns the sampling flag: a temperature of 0 selects greedy decoding, while other
+    /// temperatures or a top_p value enable sampling
+    fn do_sample(&self) -> Option<bool> {
+        match (self.temperature, self.top_p) {
+            (Some(temperature), _) if temperature == 0.0 => Some(false),
+            (Some(_), _) | (_, Some(_)) => Some(true),
+            (None, None) => None,
+        }
+    }
+
+    fn generate_options<'a>(&self, stop_sequences: &'a [String]) -> GenerateOptions<'a> {
+        GenerateOptions {
+            max_new_tokens: self.max_tokens,
+            do_sample: self.do_sample(),
+            temperature: self.temperature.filter(|temperature| *temperature != 0.0),
+            top_p: self.top_p,
+            num_return_sequences: Some(self.num_choices()),
+            stop_sequences: if stop_sequences.is_empty() {
+                None
+            } else {
+                Some(stop_sequences)
+            },
+            ..Default::default()
+        }
+    }
+
+    fn conversation_settings(&self) -> ConversationGenerationSettings {
+        ConversationGenerationSettings {
+            do_sample: self.do_sample(),
+            temperature: self.temperature.filter(|temperature| *temperature != 0.0),
+            top_p: self.top_p,
+            max_new_tokens: self.max_tokens,
+            ..Default::default()
+        }
+    }
+
+    fn validate(&self) -> Result<(), ApiError> {
+        if self.num_choices() < 1 {
+            return Err(ApiError::invalid_request("`n` must be at least 1"));
+        }
+        if matches!(self.max_tokens, Some(max_tokens) if max_tokens < 1) {
+            return Err(ApiError::invalid_request("`max_tokens` must be at least 1"));
+        }
+        Ok(())
+    }
+}
+
+/// Rejects the requests setting fields that are not supported by the server
+fn reject_unsupported(unsupported: &HashMap<String, Value>) -> Result<(), ApiError> {
+    let mut fields = unsupported
+        .iter()
+        .filter(|(_, value)| !value.is_null())
+        .map(|(field, _)| field.as_str())
+        .collect::<Vec<&str>>();
+    if fields.is_empty() {
+        Ok(())
+    } else {
+        fields.sort_unstable();
+        Err(ApiError::invalid_request(format!(
+            "Unsupported request fields: {}",
+            fields.join(", ")
+        )))
+    }
+}
+
+/// Sender of the text fragments of a streamed generation, with the index of their choice
+type FragmentSender = UnboundedSender<(usize, String)>;
+
+enum Job {
+    Completion {
+        prompts: Vec<String>,
+        parameters: GenerationParameters,
+        stream: Option<FragmentSender>,
+        sender: oneshot::Sender<Result<Vec<String>, RustBertError>>,
+    },
+    Chat {
+        messages: Vec<ChatMessage>,
+        parameters: GenerationParameters,
+        stream: Option<FragmentSender>,
+        sender: oneshot::Sender<Result<String, RustBertError>>,
+    },
+}
+
+struct ServerState {
+    model_name: String,
+    sender: Mutex<Sender<Job>>,
+    completions: bool,
+    chat: bool,
+}
+
+/// # OpenAI-compatible server
+/// Holds the text generation and conversation models served. The models run on a dedicated thread
+/// and process the requests sequentially.
+pub struct OpenAIServer {
+    state: Arc<ServerState>,
+}
+
+impl OpenAIServer {
+    /// Loads the models and creates a new server. At least one of the configurations must be provided.
+    ///
+    /// # Arguments
+    ///
+    /// * `model_name` - name of the model, returned in the responses and validated against the `model` field of requests
+    /// * `text_generation_config` - optional `TextGenerationConfig` of the model used for `/v1/completions`
+    /// * `conversation_config` - optional `ConversationConfig` of the model used for `/v1/chat/completions`
+    pub fn new(
+        model_name: impl Into<String>,
+        text_generation_config: Option<TextGenerationConfig>,
+        conversation_config: Option<ConversationConfig>,
+    ) -> Result<OpenAIServer, RustBertError> {
+        if text_generation_config.is_none() && conversation_config.is_none() {
+            return Err(RustBertError::InvalidConfigurationError(
+                "At least one of the text generation or conversation configurations must be provided"
+                    .to_string(),
+            ));
+        }
+        let completions = text_generation_config.is_some();
+        let chat = conversation_config.is_some();
+
+        let (sender, receiver) = mpsc::channel();
+        let (load_sender, load_receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let models = text_generation_config
+                .map(TextGenerationModel::new)
+                .transpose()
+                .and_then(|text_generation_model| {
+                    Ok((
+                        text_generation_model,
+                        conversation_config
+                            .map(ConversationModel::new)
+                            .transpose()?,
+                    ))
+                });
+            match models {
+                Ok((text_generation_model, conversation_model)) => {
+                    let _ = load_sender.send(Ok(()));
+                    run(
+                        text_generation_model.as_ref(),
+                        conversation_model.as_ref(),
+                        receiver,
+                    );
+                }
+                Err(error) => {
+                    let _ = load_sender.send(Err(error));
+                }
+            }
+        });
+        load_receiver.recv().map_err(|_| {
+            RustBertError::ValueError("Model worker stopped while loading the models".to_string())
+        })??;
+
+        Ok(OpenAIServer {
+            state: Arc::new(ServerState {
+                model_name: model_name.into(),
+                sender: Mutex::new(sender),
+                completions,
+                chat,
+            }),
+        })
+    }
+
+    /// Returns the router of the server, allowing to nest it in a custom axum application
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/v1/models", get(list_models))
+            .route("/v1/completions", post(completions))
+            .route("/v1/chat/completions", post(chat_completions))
+            .layer(Extension(self.state))
+    }
+
+    /// Serves the models on the given address until the server is shut down
+    pub async fn serve(self, address: SocketAddr) -> Result<(), RustBertError> {
+        axum::Server::bind(&address)
+            .serve(self.router().into_make_service())
+            .await
+            .map_err(|error| RustBertError::IOError(error.to_string()))
+    }
+}
+
+fn run(
+    text_generation_model: Option<&TextGenerationModel>,
+    conversation_model: Option<&ConversationModel>,
+    receiver: Receiver<Job>,
+) {
+    while let Ok(job) = receiver.recv() {
+        match job {
+            Job::Completion {
+                prompts,
+                parameters,
+                stream,
+                sender,
+            } => {
+                let output = text_generation_model
+                    .ok_or_else(|| {
+                        RustBertError::ValueError("No text generation model served".to_string())
+                    })
+                    .and_then(|model| complete(model, &prompts, &parameters, stream.as_ref()));
+                // The stream ends once its sender is dropped, before the output is sent
+                drop(stream);
+                let _ = sender.send(output);
+            }
+            Job::Chat {
+                messages,
+                parameters,
+                stream,
+                sender,
+            } => {
+                let output = conversation_model
+                    .ok_or_else(|| {
+                        RustBertError::ValueError("No conversation model served".to_string())
+                    })
+                    .and_then(|model| chat(model, &messages, &parameters, stream.as_ref()));
+                drop(stream);
+                let _ = sender.send(output);
+            }
+        }
+    }
+}
+
+/// Returns the completions of the prompts, stripping the prompt from the generated text. Streamed
+/// completions send the text fragments of each choice while they are generated.
+fn complete(
+    model: &TextGenerationModel,
+    prompts: &[String],
+    parameters: &GenerationParameters,
+    stream: Option<&FragmentSender>,
+) -> Result<Vec<String>, RustBertError> {
+    if prompts.is_empty() {
+        return Ok(vec![]);
+    }
+    let stop_sequences = parameters.stop_sequences();
+    let sequences_per_prompt = parameters.num_choices() as usize;
+    let token_stream = stream.filter(|_| !model.has_output_filters());
+
+    // Each choice is decoded following the tokens of its prompt, preserving its leading space
+    let tokenizer = model.get_tokenizer();
+    let decoders = RefCell::new(match token_stream {
+        Some(_) => prompts
+            .iter()
+            .flat_map(|prompt| {
+                let prompt_ids = tokenizer.convert_tokens_to_ids(&tokenizer.tokenize(prompt));
+                (0..sequences_per_prompt).map(move |_| {
+                    IncrementalDecoder::new(tokenizer, true).with_context(&prompt_ids)
+                })
+            })
+            .collect::<Vec<IncrementalDecoder>>(),
+        None => vec![],
+    });
+    let send_token = |index: usize, token_id: i64| {
+        if let (Some(stream), Some(decoder)) = (token_stream, decoders.borrow_mut().get_mut(index))
+        {
+            if let Some(text) = decoder.push(token_id) {
+                let _ = stream.send((index, text));
+            }
+        }
+    };
+    let mut generate_options = parameters.generate_options(&stop_sequences);
+    if token_stream.is_some() {
+        generate_options.num_beams = Some(1);
+        generate_options.token_callback_fn = Some(&send_token);
+    }
+
+    let outputs = model.generate_with_options(prompts, None, Some(generate_options))?;
+    let completions = outputs
+        .into_iter()
+        .enumerate()
+        .map(|(index, output)| {
+            let prompt_index = (index / sequences_per_prompt).min(prompts.len() - 1);
+            let prompt = prompts[prompt_index].as_str();
+            output.strip_prefix(prompt).unwrap_or(&output).to_string()
+        })
+        .collect::<Vec<String>>();
+
+    match (stream, token_stream) {
+        (_, Some(stream)) => {
+            for (index, decoder) in decoders.borrow_mut().iter_mut().enumerate() {
+                if let Some(text) = decoder.finish() {
+                    let _ = stream.send((index, text));
+                }
+            }
+        }
+        // Filtered completions are only sent once complete
+        (Some(stream), None) => {
+            for (index, completion) in completions.iter().enumerate() {
+                let _ = stream.send((index, completion.clone()));
+            }
+        }
+        (None, None) => {}
+    }
+    Ok(completions)
+}
+
+/// Splits the messages of a chat request into the system prompts, the previous turns and the last
+/// user input
+fn split_messages(messages: &[ChatMessage]) -> Result<(Vec<&str>, Vec<&str>, &str), RustBertError> {
+    let mut system_prompts = vec![];
+    let mut turns = vec![];
+    for message in messages {
+        match message.role.as_str() {
+            "system" => system_prompts.push(message.content.as_str()),
+            "user" | "assistant" => turns.push(message),
+            role => {
+                return Err(RustBertError::ValueError(format!(
+                    "Unsupported message role: {}",
+                    role
+                )));
+            }
+        }
+    }
+    let user_input = match turns.pop() {
+        Some(message) if message.role == "user" => message.content.as_str(),
+        _ => {
+            return Err(RustBertError::ValueError(
+                "The last message must be a user message".to_string(),
+            ));
+        }
+    };
+    let history = turns
+        .iter()
+        .map(|message| message.content.as_str())
+        .collect::<Vec<&str>>();
+    Ok((system_prompts, history, user_input))
+}
+
+fn chat(
+    model: &ConversationModel,
+    messages: &[ChatMessage],
+    parameters: &GenerationParameters,
+    stream: Option<&FragmentSender>,
+) -> Result<String, RustBertError> {
+    let (system_prompts, history, user_input) = split_messages(messages)?;
+
+    let mut conversation_manager = ConversationManager::new();
+    let conversation_id = conversation_manager.create_empty();
+    let conversation = conversation_manager.get(&conversation_id).unwrap();
+    if !system_prompts.is_empty() {
+        conversation.set_system_prompt(&system_prompts.join("\n"));
+    }
+    if !history.is_empty() {
+        let encoded_history = model.encode_prompts(&history);
+        conversation.load_from_history(&history, &encoded_history);
+    }
+    conversation.add_user_input(user_input)?;
+    conversation.set_generation_settings(parameters.conversation_settings());
+
+    let response = match stream {
+        Some(stream) if !model.has_output_filters() => {
+            let decoder = RefCell::new(IncrementalDecoder::new(model.get_tokenizer(), true));
+            let send_token = |_: usize, token_id: i64| {
+                if let Some(text) = decoder.borrow_mut().push(token_id) {
+                    let _ = stream.send((0, text));
+                }
+            };
+            let responses =
+                model.generate_responses_with_callback(&mut conversation_manager, &send_token)?;
+            if let Some(text) = decoder.borrow_mut().finish() {
+                let _ = stream.send((0, text));
+            }
+            responses
+                .get(&conversation_id)
+                .map(|response| response.to_string())
+        }
+        _ => {
+            let responses = model.generate_responses(&mut conversation_manager)?;
+            let response = responses
+                .get(&conversation_id)
+                .map(|response| response.to_string());
+            if let (Some(stream), Some(response)) = (stream, &response) {
+                let _ = stream.send((0, response.clone()));
+            }
+            response
+        }
+    };
+    Ok(response.unwrap_or_default())
+}
+
+/// Error returned with the OpenAI error format
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn invalid_request(message: impl Into<String>) -> ApiError {
+        ApiError {
+            status: StatusCode::BAD_REQUEST,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<RustBertError> for ApiError {
+    fn from(error: RustBertError) -> Self {
+        let status = match error {
+            RustBertError::ValueError(_) | RustBertError::InvalidConfigurationError(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError {
+            status,
+            message: error.to_string(),
+        }
+    }
+}
+
+impl ApiError {
+    fn body(&self) -> Value {
+        let error_type = if self.status == StatusCode::BAD_REQUEST {
+            "invalid_request_error"
+        } else {
+            "server_error"
+        };
+        json!({
+            "error": {"message": self.message, "type": error_type, "param": null, "code": null}
+        })
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = self.body();
+        (self.status, Json(body)).into_response()
+    }
+}
+
+impl ServerState {
+    fn validate_model(&self, model: &Option<String>) -> Result<(), ApiError> {
+        match model {
+            Some(model) if model != &self.model_name => Err(ApiError {
+                status: StatusCode::NOT_FOUND,
+                message: format!("The model {} does not exist", model),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn submit(&self, job: Job) -> Result<(), ApiError> {
+        self.sender.lock().unwrap().send(job).map_err(|_| {
+            RustBertError::ValueError("Model worker is not running".to_string()).into()
+        })
+    }
+}
+
+async fn receive<T>(receiver: oneshot::Receiver<Result<T, RustBertError>>) -> Result<T, ApiError> {
+    Ok(receiver.await.map_err(|_| {
+        RustBertError::ValueError("Model worker stopped before completing the request".to_string())
+    })??)
+}
+
+fn created_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// Builds the server-sent events stream of a generation: the `first_chunks`, a chunk for each text
+/// fragment received from the model worker, the final chunks built from the output of the worker
+/// (or the error it returned) and the `[DONE]` message.
+fn event_stream<T, C, F>(
+    first_chunks: Vec<Value>,
+    mut fragments: UnboundedReceiver<(usize, String)>,
+    receiver: oneshot::Receiver<Result<T, RustBertError>>,
+    chunk: C,
+    final_chunks: F,
+) -> Response
+where
+    T: Send + 'static,
+    C: Fn(usize, String) -> Value + Send + 'static,
+    F: FnOnce(T) -> Vec<Value> + Send + 'static,
+{
+    let (sender, events) = unbounded_channel();
+    tokio::spawn(async move {
+        let send = |chunk: Value| {
+            sender.send(Ok::<Event, Infallible>(
+                Event::default().data(chunk.to_string()),
+            ))
+        };
+        for first_chunk in first_chunks {
+            let _ = send(first_chunk);
+        }
+        while let Some((index, text)) = fragments.recv().await {
+            let _ = send(chunk(index, text));
+        }
+        match receive(receiver).await {
+            Ok(output) => {
+                for final_chunk in final_chunks(output) {
+                    let _ = send(final_chunk);
+                }
+            }
+            Err(error) => {
+                let _ = send(error.body());
+            }
+        }
+        let _ = sender.send(Ok(Event::default().data("[DONE]")));
+    });
+    Sse::new(UnboundedReceiverStream::new(events)).into_response()
+}
+
+async fn list_models(Extension(state): Extension<Arc<ServerState>>) -> Json<Value> {
+    Json(json!({
+        "object": "list",
+        "data": [{
+            "id": state.model_name,
+            "object": "model",
+            "created": 0,
+            "owned_by": "rust-bert"
+        }]
+    }))
+}
+
+async fn completions(
+    Extension(state): Extension<Arc<ServerState>>,
+    Json(request): Json<CompletionRequest>,
+) -> Result<Response, ApiError> {
+    state.validate_model(&request.model)?;
+    if !state.completions {
+        return Err(ApiError::invalid_request(
+            "Completions are not supported by this server",
+        ));
+    }
+    reject_unsupported(&request.unsupported)?;
+    request.parameters.validate()?;
+    let prompts = match request.prompt {
+        Prompt::Single(prompt) => vec![prompt],
+        Prompt::Batch(prompts) => prompts,
+    };
+    let (sender, receiver) = oneshot::channel();
+    let id = format!("cmpl-{}", Uuid::new_v4().simple());
+    let created = created_timestamp();
+
+    if request.stream {
+        let (stream, fragments) = unbounded_channel();
+        state.submit(Job::Completion {
+            prompts,
+            parameters: request.parameters,
+            stream: Some(stream),
+            sender,
+        })?;
+        let model_name = state.model_name.clone();
+        let chunk = move |index: usize, text: String, finish_reason: Option<&str>| {
+            json!({
+                "id": id, "object": "text_completion", "created": created, "model": model_name,
+                "choices": [{"text": text, "index": index, "logprobs": null, "finish_reason": finish_reason}]
+            })
+        };
+        let final_chunk = chunk.clone();
+        Ok(event_stream(
+            vec![],
+            fragments,
+            receiver,
+            move |index, text| chunk(index, text, None),
+            move |completions: Vec<String>| {
+                (0..completions.len())
+                    .map(|index| final_chunk(index, String::new(), Some("stop")))
+                    .collect()
+            },
+        ))
+    } else {
+        state.submit(Job::Completion {
+            prompts,
+            parameters: request.parameters,
+            stream: None,
+            sender,
+        })?;
+        let completions = receive(receiver).await?;
+        let choices = completions
+            .into_iter()
+            .enumerate()
+            .map(|(index, text)| {
+                json!({"text": text, "index": index, "logprobs": null, "finish_reason": "stop"})
+            })
+            .collect::<Vec<Value>>();
+        Ok(Json(json!({
+            "id": id, "object": "text_completion", "created": created, "model": state.model_name,
+            "choices": choices
+        }))
+        .into_response())
+    }
+}
+
+async fn chat_completions(
+    Extension(state): Extension<Arc<ServerState>>,
+    Json(request): Json<ChatCompletionRequest>,
+) -> Result<Response, ApiError> {
+    state.validate_model(&request.model)?;
+    if !state.chat {
+        return Err(ApiError::invalid_request(
+            "Chat completions are not supported by this server",
+        ));
+    }
+    reject_unsupported(&request.unsupported)?;
+    request.parameters.validate()?;
+    if request.parameters.num_choices() != 1 {
+        return Err(ApiError::invalid_request(
+            "Chat completions only support a single choice (`n` = 1)",
+        ));
+    }
+    if request.parameters.stop.is_some() {
+        return Err(ApiError::invalid_request(
+            "Stop sequences (`stop`) are not supported by chat completions",
+        ));
+    }
+    let (sender, receiver) = oneshot::channel();
+    let id = format!("chatcmpl-{}", Uuid::new_v4().simple());
+    let created = created_timestamp();
+
+    if request.stream {
+        let (stream, fragments) = unbounded_channel();
+        state.submit(Job::Chat {
+            messages: request.messages,
+            parameters: request.parameters,
+            stream: Some(stream),
+            sender,
+        })?;
+        let model_name = state.model_name.clone();
+        let chunk = move |delta: Value, finish_reason: Option<&str>| {
+            json!({
+                "id": id, "object": "chat.completion.chunk", "created": created, "model": model_name,
+                "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}]
+            })
+        };
+        let final_chunk = chunk.clone();
+        Ok(event_stream(
+            vec![chunk(json!({"role": "assistant", "content": ""}), None)],
+            fragments,
+            receiver,
+            move |_, text| chunk(json!({ "content": text }), None),
+            move |_: String| vec![final_chunk(json!({}), Some("stop"))],
+        ))
+    } else {
+        state.submit(Job::Chat {
+            messages: request.messages,
+            parameters: request.parameters,
+            stream: None,
+            sender,
+        })?;
+        let response = receive(receiver).await?;
+        Ok(Json(json!({
+            "id": id, "object": "chat.completion", "created": created, "model": state.model_name,
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": response},
+                "finish_reason": "stop"
+            }]
+        }))
+        .into_response())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::body::HttpBody;
+
+    /// Server state answering the jobs without models: completions append `" completed"` to the
+    /// prompts (`n` times) and chat completions echo the last user input. Streamed outputs are sent
+    /// in two fragments.
+    fn test_state(completions: bool, chat: bool) -> Arc<ServerState> {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            while let Ok(job) = receiver.recv() {
+                match job {
+                    Job::Completion {
+                        prompts,
+                        parameters,
+                        stream,
+                        sender,
+                    } => {
+                        let prompts = prompts
+                            .iter()
+                            .flat_map(|prompt| {
+                                std::iter::repeat(prompt).take(parameters.num_choices() as usize)
+                            })
+                            .collect::<Vec<&String>>();
+                        if let Some(stream) = stream {
+                            for (index, prompt) in prompts.iter().enumerate() {
+                                let _ = stream.send((index, prompt.to_string()));
+                                let _ = stream.send((index, " completed".to_string()));
+                            }
+                        }
+                        let _ = sender.send(Ok(prompts
+                            .iter()
+                            .map(|prompt| format!("{} completed", prompt))
+                            .collect()));
+                    }
+                    Job::Chat {
+                        messages,
+                        stream,
+                        sender,
+                        ..
+                    } => {
+                        let output = split_messages(&messages)
+                            .map(|(_, _, user_input)| format!("echo: {}", user_input));
+                        if let (Some(stream), Ok(_)) = (stream, &output) {
+                            let _ = stream.send((0, "echo: ".to_string()));
+                            let _ = stream.send((0, messages.last().unwrap().content.clone()));
+                        }
+                        let _ = sender.send(output);
+                    }
+                }
+            }
+        });
+        Arc::new(ServerState {
+            model_name: "tiny".to_string(),
+            sender: Mutex::new(sender),
+            completions,
+            chat,
+        })
+    }
+
+    async fn into_parts(response: Response) -> (StatusCode, String) {
+        let status = response.status();
+        let mut body = response.into_body();
+        let mut bytes = vec![];
+        while let Some(chunk) = body.data().await {
+            bytes.extend_from_slice(&chunk.unwrap());
+        }
+        (status, String::from_utf8(bytes).unwrap())
+    }
+
+    async fn into_json(response: Result<Response, ApiError>) -> (StatusCode, Value) {
+        let response = response.unwrap_or_else(IntoResponse::into_response);
+        let (status, body) = into_parts(response).await;
+        (status, serde_json::from_str(&body).unwrap())
+    }
+
+    fn completion_request(request: Value) -> Json<CompletionRequest> {
+        Json(serde_json::from_value(request).unwrap())
+    }
+
+    fn chat_request(request: Value) -> Json<ChatCompletionRequest> {
+        Json(serde_json::from_value(request).unwrap())
+    }
+
+    #[tokio::test]
+    async fn completion_requests() {
+        let state = test_state(true, false);
+
+        let (status, body) = into_json(
+            completions(
+                Extension(state.clone()),
+                completion_request(json!({"model": "tiny", "prompt": ["a", "b"]})),
+            )
+            .await,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["object"], "text_completion");
+        assert_eq!(body["model"], "tiny");
+        assert_eq!(body["choices"][0]["text"], "a completed");
+        assert_eq!(body["choices"][1]["text"], "b completed");
+        assert_eq!(body["choices"][1]["index"], 1);
+
+        let (status, body) = into_json(
+            completions(
+                Extension(state.clone()),
+                completion_request(json!({"model": "gpt2", "prompt": "a"})),
+            )
+            .await,
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["message"], "The model gpt2 does not exist");
+
+        // Chat completions are rejected when no conversation model is served
+        let (status, body) = into_json(
+            chat_completions(
+                Extension(state),
+                chat_request(json!({"messages": [{"role": "user", "content": "hi"}]})),
+            )
+            .await,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["type"], "invalid_request_error");
+    }
+
+    /// Returns the data of the server-sent events of a response
+    async fn into_events(response: Response) -> Vec<String> {
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+        let (status, body) = into_parts(response).await;
+        assert_eq!(status, StatusCode::OK);
+        body.lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn parse_chunks(events: &[String]) -> Vec<Value> {
+        events
+            .iter()
+            .map(|event| serde_json::from_str(event).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn streamed_completion_request() {
+        let state = test_state(true, false);
+        let response = completions(
+            Extension(state),
+            completion_request(json!({"prompt": "a", "stream": true, "n": 2})),
+        )
+        .await
+        .unwrap_or_else(IntoResponse::into_response);
+
+        let events = into_events(response).await;
+        assert_eq!(events.len(), 7);
+        assert_eq!(events[6], "[DONE]");
+        let chunks = parse_chunks(&events[..6]);
+        // One chunk per fragment of each choice, followed by the final chunk of each choice
+        let texts = chunks[..4]
+            .iter()
+            .map(|chunk| {
+                (
+                    chunk["choices"][0]["index"].as_i64().unwrap(),
+                    chunk["choices"][0]["text"].as_str().unwrap(),
+                )
+            })
+            .collect::<Vec<(i64, &str)>>();
+        assert_eq!(
+            texts,
+            [(0, "a"), (0, " completed"), (1, "a"), (1, " completed")]
+        );
+        assert!(chunks[..4]
+            .iter()
+            .all(|chunk| chunk["choices"][0]["finish_reason"].is_null()));
+        assert_eq!(chunks[4]["choices"][0]["index"], 0);
+        assert_eq!(chunks[5]["choices"][0]["index"], 1);
+        assert_eq!(chunks[5]["choices"][0]["finish_reason"], "stop");
+        assert_eq!(chunks[0]["id"], chunks[5]["id"]);
+    }
+
+    #[tokio::test]
+    async fn streamed_chat_completion_request() {
+        let state = test_state(false, true);
+        let response = chat_completions(
+            Extension(state.clone()),
+            chat_request(json!({
+                "messages": [{"role": "user", "content": "hi"}],
+                "stream": true
+            })),
+        )
+        .await
+        .unwrap_or_else(IntoResponse::into_response);
+
+        let events = into_events(response).await;
+        assert_eq!(events.len(), 5);
+        assert_eq!(events[4], "[DONE]");
+        let chunks = parse_chunks(&events[..4]);
+        assert_eq!(chunks[0]["object"], "chat.completion.chunk");
+        assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+        assert_eq!(chunks[1]["choices"][0]["delta"]["content"], "echo: ");
+        assert_eq!(chunks[2]["choices"][0]["delta"]["content"], "hi");
+        assert_eq!(chunks[3]["choices"][0]["delta"], json!({}));
+        assert_eq!(chunks[3]["choices"][0]["finish_reason"], "stop");
+
+        // Generation errors are sent as an error event once the stream started
+        let response = chat_completions(
+            Extension(state),
+            chat_request(json!({
+                "messages": [{"role": "assistant", "content": "hello"}],
+                "stream": true
+            })),
+        )
+        .await
+        .unwrap_or_else(IntoResponse::into_response);
+        let events = into_events(response).await;
+        assert_eq!(events.len(), 3);
+        let error: Value = serde_json::from_str(&events[1]).unwrap();
+        assert_eq!(error["error"]["type"], "invalid_request_error");
+        assert_eq!(events[2], "[DONE]");
+    }
+
+    #[tokio::test]
+    async fn unsupported_request_fields() {
+        let state = test_state(true, true);
+
+        let (status, body) = into_json(
+            completions(
+                Extension(state.clone()),
+                completion_request(json!({
+                    "prompt": "a",
+                    "logprobs": 5,
+                    "echo": true,
+                    "user": null,
+                    "max_tokens": 16
+                })),
+            )
+            .await,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body["error"]["message"],
+            "Unsupported request fields: echo, logprobs"
+        );
+
+        let (status, _) = into_json(
+            completions(
+                Extension(state.clone()),
+                completion_request(json!({"prompt": "a", "n": 0})),
+            )
+            .await,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        for request in [
+            json!({"messages": [{"role": "user", "content": "hi"}], "n": 2}),
+            json!({"messages": [{"role": "user", "content": "hi"}], "stop": "\n"}),
+        ] {
+            let (status, _) =
+                into_json(chat_completions(Extension(state.clone()), chat_request(request)).await)
+                    .await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[test]
+    fn generation_parameters() {
+        let request: CompletionRequest = serde_json::from_value(json!({
+            "prompt": "a",
+            "max_tokens": 16,
+            "temperature": 0.7,
+            "top_p": 0.9,
+            "n": 3,
+            "stop": ["\n", "."]
+        }))
+        .unwrap();
+        assert!(request.unsupported.is_empty());
+        let stop_sequences = request.parameters.stop_sequences();
+        assert_eq!(stop_sequences, ["\n", "."]);
+        let options = request.parameters.generate_options(&stop_sequences);
+        assert_eq!(options.max_new_tokens, Some(16));
+        assert_eq!(options.do_sample, Some(true));
+        assert_eq!(options.temperature, Some(0.7));
+        assert_eq!(options.top_p, Some(0.9));
+        assert_eq!(options.num_return_sequences, Some(3));
+        assert_eq!(options.stop_sequences, Some(&stop_sequences[..]));
+
+        // A temperature of 0 selects greedy decoding
+        let parameters = GenerationParameters {
+            temperature: Some(0.0),
+            stop: Some(StopSequences::Single("\n".to_string())),
+            ..Default::default()
+        };
+        let stop_sequences = parameters.stop_sequences();
+        assert_eq!(stop_sequences, ["\n"]);
+        let options = parameters.generate_options(&stop_sequences);
+        assert_eq!(options.do_sample, Some(false));
+        assert_eq!(options.temperature, None);
+        assert_eq!(options.num_return_sequences, Some(1));
+        let settings = parameters.conversation_settings();
+        assert_eq!(settings.do_sample, Some(false));
+        assert_eq!(settings.temperature, None);
+
+        let options = GenerationParameters::default().generate_options(&[]);
+        assert_eq!(options.do_sample, None);
+        assert_eq!(options.max_new_tokens, None);
+        assert_eq!(options.stop_sequences, None);
+    }
+
+    #[tokio::test]
+    async fn chat_completion_requests() {
+        let state = test_state(false, true);
+
+        let (status, body) = into_json(
+            chat_completions(
+                Extension(state.clone()),
+                chat_request(json!({"messages": [
+                    {"role": "system", "content": "be brief"},
+                    {"role": "user", "content": "hi"},
+                    {"role": "assistant", "content": "hello"},
+                    {"role": "user", "content": "how are you?"}
+                ]})),
+            )
+            .await,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["object"], "chat.completion");
+        assert_eq!(body["choices"][0]["message"]["role"], "assistant");
+        assert_eq!(
+            body["choices"][0]["message"]["content"],
+            "echo: how are you?"
+        );
+
+        // Invalid conversations are reported as invalid requests
+        let (status, body) = into_json(
+            chat_completions(
+                Extension(state),
+                chat_request(json!({"messages": [{"role": "assistant", "content": "hello"}]})),
+            )
+            .await,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body["error"]["message"],
+            "Value error: The last message must be a user message"
+        );
+    }
+
+    #[tokio::test]
+    async fn models_list() {
+        let Json(body) = list_models(Extension(test_state(true, true))).await;
+        assert_eq!(body["data"][0]["id"], "tiny");
+    }
+
+    #[test]
+    fn chat_messages_split() {
+        let message = |role: &str, content: &str| ChatMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+        };
+        let messages = vec![
+            message("system", "be brief"),
+            message("user", "hi"),
+            message("assistant", "hello"),
+            message("system", "be polite"),
+            message("user", "how are you?"),
+        ];
+        let (system_prompts, history, user_input) = split_messages(&messages).unwrap();
+        assert_eq!(system_prompts, ["be brief", "be polite"]);
+        assert_eq!(history, ["hi", "hello"]);
+        assert_eq!(user_input, "how are you?");
+
+        assert!(split_messages(&[]).is_err());
+        assert!(split_messages(&[message("tool", "{}"), message("user", "hi")]).is_err());
+    }
+}
@@ -0,0 +1,525 @@
+// Copyright 2019-present Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Command line interface running the pipelines on inputs read from files or the standard input
+//! (one input per line) and writing the outputs as JSON lines. Example usage:
+//!
+//! ```text
+//! rust-bert generate --model gpt2 --prompt "The dog"
+//! rust-bert embed --model all-mini-lm-l6-v2 --input sentences.txt --output embeddings.jsonl
+//! cat sentences.txt | rust-bert ner
+//! rust-bert zero-shot --labels politics --labels sports --input headlines.txt
+//! ```
+
+extern crate clap;
+extern crate tch;
+
+use clap::{ArgEnum, Args, Parser, Subcommand};
+use rust_bert::gpt2::{
+    Gpt2ConfigResources, Gpt2MergesResources, Gpt2ModelResources, Gpt2VocabResources,
+};
+use rust_bert::pipelines::common::ModelType;
+use rust_bert::pipelines::registry::{ModelSpec, Pipeline, TaskType};
+use rust_bert::pipelines::sentence_embeddings::{
+    SentenceEmbeddingsBuilder, SentenceEmbeddingsModel, SentenceEmbeddingsModelType,
+};
+use rust_bert::resources::RemoteResource;
+use rust_bert::RustBertError;
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use tch::Device;
+
+#[derive(Parser)]
+#[clap(
+    name = "rust-bert",
+    version,
+    about = "Run rust-bert pipelines from the command line"
+)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate text from prompts
+    Generate {
+        /// Pretrained GPT-2 model (ignored if a model specification is provided)
+        #[clap(long, arg_enum, default_value = "gpt2")]
+        model: Gpt2Model,
+        /// Prompt to complete (can be repeated). Prompts are read from the input if not provided
+        #[clap(long)]
+        prompt: Vec<String>,
+        #[clap(flatten)]
+        args: CommonArgs,
+    },
+    /// Compute sentence embeddings
+    Embed {
+        /// Pretrained sentence embeddings model
+        #[clap(long, arg_enum, default_value = "all-mini-lm-l12-v2")]
+        model: EmbeddingsModel,
+        /// Directory of a local sentence embeddings model (overrides `--model`)
+        #[clap(long)]
+        model_dir: Option<PathBuf>,
+        #[clap(flatten)]
+        args: CommonArgs,
+    },
+    /// Extract named entities
+    Ner(CommonArgs),
+    /// Tag the parts of speech
+    Pos(CommonArgs),
+    /// Predict the sentiment
+    Sentiment(CommonArgs),
+    /// Classify sequences
+    Classify(CommonArgs),
+    /// Classify sequences with a zero-shot classification model
+    ZeroShot {
+        /// Candidate label (can be repeated)
+        #[clap(long, required = true)]
+        labels: Vec<String>,
+        #[clap(flatten)]
+        args: CommonArgs,
+    },
+    /// Answer questions (inputs are JSON lines with a `question` and a `context`)
+    Qa(CommonArgs),
+    /// Summarize documents
+    Summarize(CommonArgs),
+    /// Generate a response to each input with a conversation model
+    Converse(CommonArgs),
+    /// Predict the masked tokens
+    FillMask(CommonArgs),
+}
+
+#[derive(Args)]
+struct CommonArgs {
+    /// Input file with one input per line (standard input if not provided)
+    #[clap(long, short)]
+    input: Option<PathBuf>,
+    /// Output file for the JSON lines (standard output if not provided)
+    #[clap(long, short)]
+    output: Option<PathBuf>,
+    /// JSON model specification (see `ModelSpec`) used instead of the default model of the task
+    #[clap(long)]
+    model_spec: Option<PathBuf>,
+    /// Device to run the model on (`auto`, `cpu`, `cuda` or `cuda:N`)
+    #[clap(long, default_value = "auto")]
+    device: String,
+    /// Number of inputs processed at once
+    #[clap(long, default_value = "16")]
+    batch_size: usize,
+}
+
+#[derive(Clone, Copy, ArgEnum)]
+enum Gpt2Model {
+    Gpt2,
+    Distilgpt2,
+    Gpt2Medium,
+    Gpt2Large,
+    Gpt2Xl,
+}
+
+#[derive(Clone, Copy, ArgEnum)]
+enum EmbeddingsModel {
+    DistiluseBaseMultilingualCased,
+    BertBaseNliMeanTokens,
+    AllMiniLmL12V2,
+    AllMiniLmL6V2,
+    AllDistilrobertaV1,
+    ParaphraseAlbertSmallV2,
+    SentenceT5Base,
+}
+
+impl From<EmbeddingsModel> for SentenceEmbeddingsModelType {
+    fn from(model: EmbeddingsModel) -> Self {
+        match model {
+            EmbeddingsModel::DistiluseBaseMultilingualCased => {
+                SentenceEmbeddingsModelType::DistiluseBaseMultilingualCased
+            }
+            EmbeddingsModel::BertBaseNliMeanTokens => {
+                SentenceEmbeddingsModelType::BertBaseNliMeanTokens
+            }
+            EmbeddingsModel::AllMiniLmL12V2 => SentenceEmbeddingsModelType::AllMiniLmL12V2,
+            EmbeddingsModel::AllMiniLmL6V2 => SentenceEmbeddingsModelType::AllMiniLmL6V2,
+            EmbeddingsModel::AllDistilrobertaV1 => SentenceEmbeddingsModelType::AllDistilrobertaV1,
+            EmbeddingsModel::ParaphraseAlbertSmallV2 => {
+                SentenceEmbeddingsModelType::ParaphraseAlbertSmallV2
+            }
+            EmbeddingsModel::SentenceT5Base => SentenceEmbeddingsModelType::SentenceT5Base,
+        }
+    }
+}
+
+fn gpt2_model_spec(model: Gpt2Model) -> ModelSpec {
+    let (model_resource, config_resource, vocab_resource, merges_resource) = match model {
+        Gpt2Model::Gpt2 => (
+            Gpt2ModelResources::GPT2,
+            Gpt2ConfigResources::GPT2,
+            Gpt2VocabResources::GPT2,
+            Gpt2MergesResources::GPT2,
+        ),
+        Gpt2Model::Distilgpt2 => (
+            Gpt2ModelResources::DISTIL_GPT2,
+            Gpt2ConfigResources::DISTIL_GPT2,
+            Gpt2VocabResources::DISTIL_GPT2,
+            Gpt2MergesResources::DISTIL_GPT2,
+        ),
+        Gpt2Model::Gpt2Medium => (
+            Gpt2ModelResources::GPT2_MEDIUM,
+            Gpt2ConfigResources::GPT2_MEDIUM,
+            Gpt2VocabResources::GPT2_MEDIUM,
+            Gpt2MergesResources::GPT2_MEDIUM,
+        ),
+        Gpt2Model::Gpt2Large => (
+            Gpt2ModelResources::GPT2_LARGE,
+            Gpt2ConfigResources::GPT2_LARGE,
+            Gpt2VocabResources::GPT2_LARGE,
+            Gpt2MergesResources::GPT2_LARGE,
+        ),
+        Gpt2Model::Gpt2Xl => (
+            Gpt2ModelResources::GPT2_XL,
+            Gpt2ConfigResources::GPT2_XL,
+            Gpt2VocabResources::GPT2_XL,
+            Gpt2MergesResources::GPT2_XL,
+        ),
+    };
+    ModelSpec::new(
+        ModelType::GPT2,
+        RemoteResource::from_pretrained(model_resource),
+        RemoteResource::from_pretrained(config_resource),
+        RemoteResource::from_pretrained(vocab_resource),
+        Some(RemoteResource::from_pretrained(merges_resource)),
+    )
+}
+
+fn parse_device(device: &str) -> Result<Device, RustBertError> {
+    match device {
+        "auto" => Ok(Device::cuda_if_available()),
+        "cpu" => Ok(Device::Cpu),
+        "cuda" => Ok(Device::Cuda(0)),
+        _ => device
+            .strip_prefix("cuda:")
+            .and_then(|index| index.parse().ok())
+            .map(Device::Cuda)
+            .ok_or_else(|| RustBertError::ValueError(format!("Invalid device: {}", device))),
+    }
+}
+
+fn read_model_spec(path: &PathBuf) -> Result<ModelSpec, RustBertError> {
+    Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+}
+
+/// Creates the pipeline for a task from the model specification if provided, or from the default model
+fn create_pipeline(
+    task: TaskType,
+    args: &CommonArgs,
+    default_spec: Option<ModelSpec>,
+) -> Result<Pipeline, RustBertError> {
+    let device = parse_device(&args.device)?;
+    let model_spec = match &args.model_spec {
+        Some(path) => Some(read_model_spec(path)?),
+        None => default_spec,
+    };
+    match model_spec {
+        Some(mut model_spec) => {
+            model_spec.device = device;
+            Pipeline::new(task, model_spec)
+        }
+        None => {
+            if device != Device::cuda_if_available() {
+                return Err(RustBertError::InvalidConfigurationError(
+                    "The device of the default models cannot be changed, provide a model specification instead"
+                        .to_string(),
+                ));
+            }
+            Pipeline::new_default(task)
+        }
+    }
+}
+
+fn read_inputs(input: &Option<PathBuf>) -> Result<Vec<String>, RustBertError> {
+    let reader: Box<dyn BufRead> = match input {
+        Some(path) => Box::new(BufReader::new(File::open(path)?)),
+        None => Box::new(BufReader::new(io::stdin())),
+    };
+    let mut inputs = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            inputs.push(line);
+        }
+    }
+    Ok(inputs)
+}
+
+fn output_writer(output: &Option<PathBuf>) -> Result<Box<dyn Write>, RustBertError> {
+    Ok(match output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    })
+}
+
+fn write_line(writer: &mut dyn Write, input: &Value, output: &Value) -> Result<(), RustBertError> {
+    writeln!(writer, "{}", json!({"input": input, "output": output}))?;
+    Ok(())
+}
+
+/// Runs a pipeline on batches of inputs, writing a JSON line per input
+fn run_pipeline(
+    pipeline: &Pipeline,
+    inputs: Vec<Value>,
+    args: &CommonArgs,
+    labels: Option<&[String]>,
+) -> Result<(), RustBertError> {
+    let mut writer = output_writer(&args.output)?;
+    for batch in inputs.chunks(args.batch_size.max(1)) {
+        let batch_input = match labels {
+            Some(labels) => json!({"inputs": batch, "labels": labels}),
+            None => json!(batch),
+        };
+        let outputs = match pipeline.predict_json(&batch_input)? {
+            Value::Array(outputs) => outputs,
+            output => vec![output],
+        };
+        if outputs.len() == batch.len() {
+            for (input, output) in batch.iter().zip(outputs.iter()) {
+                write_line(writer.as_mut(), input, output)?;
+            }
+        } else {
+            // Pipelines returning multiple outputs per input (e.g. several generated sequences)
+            let outputs_per_input = (outputs.len() / batch.len()).max(1);
+            for (input, output) in batch.iter().zip(outputs.chunks(outputs_per_input)) {
+                write_line(writer.as_mut(), input, &json!(output))?;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn run_embeddings(
+    model: &SentenceEmbeddingsModel,
+    inputs: Vec<String>,
+    args: &CommonArgs,
+) -> Result<(), RustBertError> {
+    let mut writer = output_writer(&args.output)?;
+    for batch in inputs.chunks(args.batch_size.max(1)) {
+        let embeddings = model.encode(batch)?;
+        for (input, embedding) in batch.iter().zip(embeddings.iter()) {
+            write_line(writer.as_mut(), &json!(input), &json!(embedding))?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn text_inputs(args: &CommonArgs) -> Result<Vec<Value>, RustBertError> {
+    Ok(read_inputs(&args.input)?
+        .into_iter()
+        .map(Value::String)
+        .collect())
+}
+
+fn run_task(task: TaskType, args: &CommonArgs) -> Result<(), RustBertError> {
+    let inputs = match task {
+        TaskType::QuestionAnswering => read_inputs(&args.input)?
+            .iter()
+            .map(|line| serde_json::from_str(line))
+            .collect::<Result<Vec<Value>, _>>()?,
+        _ => text_inputs(args)?,
+    };
+    let pipeline = create_pipeline(task, args, None)?;
+    run_pipeline(&pipeline, inputs, args, None)
+}
+
+fn main() -> Result<(), RustBertError> {
+    let cli = Cli::parse();
+    match cli.command {
+        Command::Generate {
+            model,
+            prompt,
+            args,
+        } => {
+            let inputs = if prompt.is_empty() {
+                text_inputs(&args)?
+            } else {
+                prompt.into_iter().map(Value::String).collect()
+            };
+            let pipeline = create_pipeline(
+                TaskType::TextGeneration,
+                &args,
+                Some(gpt2_model_spec(model)),
+            )?;
+            run_pipeline(&pipeline, inputs, &args, None)
+        }
+        Command::Embed {
+            model,
+            model_dir,
+            args,
+        } => {
+            let device = parse_device(&args.device)?;
+            let model = match model_dir {
+                Some(model_dir) => SentenceEmbeddingsBuilder::local(model_dir)
+                    .with_device(device)
+                    .create_model()?,
+                None => SentenceEmbeddingsBuilder::remote(model.into())
+                    .with_device(device)
+                    .create_model()?,
+            };
+            run_embeddings(&model, read_inputs(&args.input)?, &args)
+        }
+        Command::ZeroShot { labels, args } => {
+            let inputs = text_inputs(&args)?;
+            let pipeline = create_pipeline(TaskType::ZeroShotClassification, &args, None)?;
+            run_pipeline(&pipeline, inputs, &args, Some(&labels))
+        }
+        Command::Ner(args) => run_task(TaskType::NER, &args),
+        Command::Pos(args) => run_task(TaskType::POSTagging, &args),
+        Command::Sentiment(args) => run_task(TaskType::Sentiment, &args),
+        Command::Classify(args) => run_task(TaskType::SequenceClassification, &args),
+        Command::Qa(args) => run_task(TaskType::QuestionAnswering, &args),
+        Command::Summarize(args) => run_task(TaskType::Summarization, &args),
+        Command::Converse(args) => run_task(TaskType::Conversation, &args),
+        Command::FillMask(args) => run_task(TaskType::MaskedLanguage, &args),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clap::{CommandFactory, ErrorKind};
+
+    fn parse(args: &[&str]) -> Result<Command, clap::Error> {
+        Cli::try_parse_from(["rust-bert"].iter().chain(args.iter()).copied()).map(|cli| cli.command)
+    }
+
+    #[test]
+    fn cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn generate_arguments() {
+        match parse(&["generate", "--prompt", "The dog", "--prompt", "The cat"]).unwrap() {
+            Command::Generate {
+                model,
+                prompt,
+                args,
+            } => {
+                assert!(matches!(model, Gpt2Model::Gpt2));
+                assert_eq!(prompt, ["The dog", "The cat"]);
+                assert_eq!(args.input, None);
+                assert_eq!(args.output, None);
+                assert_eq!(args.model_spec, None);
+                assert_eq!(args.device, "auto");
+                assert_eq!(args.batch_size, 16);
+            }
+            _ => panic!("expected a generate command"),
+        }
+
+        match parse(&[
+            "generate",
+            "--model",
+            "distilgpt2",
+            "-i",
+            "prompts.txt",
+            "-o",
+            "outputs.jsonl",
+            "--device",
+            "cuda:1",
+            "--batch-size",
+            "4",
+        ])
+        .unwrap()
+        {
+            Command::Generate {
+                model,
+                prompt,
+                args,
+            } => {
+                assert!(matches!(model, Gpt2Model::Distilgpt2));
+                assert!(prompt.is_empty());
+                assert_eq!(args.input, Some(PathBuf::from("prompts.txt")));
+                assert_eq!(args.output, Some(PathBuf::from("outputs.jsonl")));
+                assert_eq!(args.device, "cuda:1");
+                assert_eq!(args.batch_size, 4);
+            }
+            _ => panic!("expected a generate command"),
+        }
+    }
+
+    #[test]
+    fn task_arguments() {
+        match parse(&[
+            "embed",
+            "--model",
+            "all-mini-lm-l6-v2",
+            "--model-dir",
+            "model",
+        ])
+        .unwrap()
+        {
+            Command::Embed {
+                model, model_dir, ..
+            } => {
+                assert!(matches!(model, EmbeddingsModel::AllMiniLmL6V2));
+                assert_eq!(model_dir, Some(PathBuf::from("model")));
+            }
+            _ => panic!("expected an embed command"),
+        }
+
+        match parse(&["zero-shot", "--labels", "politics", "--labels", "sports"]).unwrap() {
+            Command::ZeroShot { labels, .. } => assert_eq!(labels, ["politics", "sports"]),
+            _ => panic!("expected a zero-shot command"),
+        }
+
+        match parse(&["ner", "--model-spec", "spec.json", "--input", "texts.txt"]).unwrap() {
+            Command::Ner(args) => {
+                assert_eq!(args.model_spec, Some(PathBuf::from("spec.json")));
+                assert_eq!(args.input, Some(PathBuf::from("texts.txt")));
+            }
+            _ => panic!("expected a ner command"),
+        }
+        assert!(matches!(
+            parse(&["fill-mask"]).unwrap(),
+            Command::FillMask(_)
+        ));
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        let error_kind = |args: &[&str]| parse(args).err().map(|error| error.kind());
+
+        assert_eq!(
+            error_kind(&["zero-shot"]),
+            Some(ErrorKind::MissingRequiredArgument)
+        );
+        assert_eq!(
+            error_kind(&["generate", "--model", "gpt3"]),
+            Some(ErrorKind::InvalidValue)
+        );
+        assert_eq!(
+            error_kind(&["sentiment", "--batch-size", "many"]),
+            Some(ErrorKind::ValueValidation)
+        );
+        assert!(error_kind(&["translate"]).is_some());
+    }
+
+    #[test]
+    fn device_parsing() {
+        assert_eq!(parse_device("cpu").unwrap(), Device::Cpu);
+        assert_eq!(parse_device("cuda").unwrap(), Device::Cuda(0));
+        assert_eq!(parse_device("cuda:2").unwrap(), Device::Cuda(2));
+        assert!(parse_device("gpu").is_err());
+        assert!(parse_device("cuda:first").is_err());
+    }
+}
@@ -0,0 +1,378 @@
+//! # gRPC model serving
+//! Multi-task inference server built on [tonic](https://github.com/hyperium/tonic) (enabled with the
+//! `serve` feature). Models are registered under a name with a task and model specification (see
+//! [`ModelSpec`]) and are exposed by the `RustBert` service defined in `proto/rust_bert.proto`:
+//! - `Classify`: sequence classification, sentiment analysis and zero-shot classification
+//! - `ExtractEntities`: named entity recognition, token classification and part of speech tagging
+//! - `AnswerQuestions`: extractive question answering
+//! - `Summarize` and `Generate` (streaming): summarization, text generation and conversation
+//! - `Predict`: generic prediction exchanging JSON strings (see [`Pipeline::predict_json`](crate::pipelines::registry::Pipeline::predict_json))
+//!
+//! Each model runs on a dedicated thread. Concurrent requests received within `batch_timeout` are
+//! batched together (up to `max_batch_size` inputs) and processed in a single forward pass. The
+//! order in which queued requests are batched is set by the [`SchedulingPolicy`]: shortest job
+//! first avoids short interactive requests waiting behind long document summarizations on a shared
+//! model, and the priority policy uses the `priority` field of the requests. The estimated cost of
+//! a batch can be limited with `max_batch_cost`, and `max_queue_delay` prevents the starvation of
+//! long or low priority requests.
+//!
+//! Compiling the protobuf definitions requires `protoc` to be installed.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! # let runtime = tokio::runtime::Runtime::new()?;
+//! # runtime.block_on(async {
+//! use rust_bert::pipelines::common::ModelType;
+//! use rust_bert::pipelines::registry::{ModelSpec, TaskType};
+//! use rust_bert::resources::LocalResource;
+//! use rust_bert::serve::{BatchConfig, RustBertService};
+//! use std::path::PathBuf;
+//!
+//! let model_spec = ModelSpec::new(
+//!     ModelType::DistilBert,
+//!     LocalResource::from(PathBuf::from("path/to/rust_model.ot")),
+//!     LocalResource::from(PathBuf::from("path/to/config.json")),
+//!     LocalResource::from(PathBuf::from("path/to/vocab.txt")),
+//!     None::<LocalResource>,
+//! );
+//! let mut service = RustBertService::new(BatchConfig::default());
+//! service.add_model("sentiment", TaskType::Sentiment, model_spec)?;
+//! service.serve("0.0.0.0:50051".parse()?).await?;
+//! # Ok::<(), anyhow::Error>(())
+//! # })
+//! # }
+//! ```
+
+mod worker;
+
+pub use worker::{BatchConfig, SchedulingPolicy};
+
+use crate::pipelines::registry::{ModelSpec, TaskType};
+use crate::RustBertError;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+use worker::ModelWorker;
+
+/// Types generated from the protobuf definitions of the service
+pub mod proto {
+    tonic::include_proto!("rust_bert");
+}
+
+use proto::rust_bert_server::{RustBert, RustBertServer};
+use proto::{
+    Answer, Answers, ClassificationRequest, ClassificationResponse, Entities, EntitiesResponse,
+    Entity, GeneratedText, JsonRequest, JsonResponse, Label, ListModelsRequest, ListModelsResponse,
+    ModelInfo, QuestionAnsweringRequest, QuestionAnsweringResponse, TextRequest, TextResponse,
+};
+
+impl From<RustBertError> for Status {
+    fn from(error: RustBertError) -> Self {
+        match error {
+            RustBertError::ValueError(_) | RustBertError::InvalidConfigurationError(_) => {
+                Status::invalid_argument(error.to_string())
+            }
+            _ => Status::internal(error.to_string()),
+        }
+    }
+}
+
+/// # Multi-task inference service
+/// Holds the models served, identified by their name.
+pub struct RustBertService {
+    models: HashMap<String, Arc<ModelWorker>>,
+    batch_config: BatchConfig,
+}
+
+impl RustBertService {
+    /// Creates a new service without models
+    ///
+    /// # Arguments
+    ///
+    /// * `batch_config` - `BatchConfig` used for the models registered on the service
+    pub fn new(batch_config: BatchConfig) -> RustBertService {
+        RustBertService {
+            models: HashMap::new(),
+            batch_config,
+        }
+    }
+
+    /// Loads a model and registers it under the given name, replacing any model with the same name
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - name identifying the model in requests
+    /// * `task` - `TaskType` performed by the model
+    /// * `model_spec` - `ModelSpec` of the model to load
+    pub fn add_model(
+        &mut self,
+        name: impl Into<String>,
+        task: TaskType,
+        model_spec: ModelSpec,
+    ) -> Result<&mut Self, RustBertError> {
+        let worker = ModelWorker::spawn(task, model_spec, self.batch_config)?;
+        self.models.insert(name.into(), Arc::new(worker));
+        Ok(self)
+    }
+
+    /// Wraps the service in a tonic server, allowing to add it to a custom `tonic::transport::Server`
+    pub fn into_server(self) -> RustBertServer<RustBertService> {
+        RustBertServer::new(self)
+    }
+
+    /// Serves the models on the given address until the server is shut down
+    pub async fn serve(self, address: SocketAddr) -> Result<(), tonic::transport::Error> {
+        tonic::transport::Server::builder()
+            .add_service(self.into_server())
+            .serve(address)
+            .await
+    }
+
+    fn get_model(&self, name: &str, tasks: &[TaskType]) -> Result<Arc<ModelWorker>, Status> {
+        let model = self
+            .models
+            .get(name)
+            .ok_or_else(|| Status::not_found(format!("Model {} is not registered", name)))?;
+        if !tasks.is_empty() && !tasks.contains(&model.task()) {
+            return Err(Status::invalid_argument(format!(
+                "Model {} ({:?}) does not support this request",
+                name,
+                model.task()
+            )));
+        }
+        Ok(model.clone())
+    }
+}
+
+fn parse_output<T: for<'de> Deserialize<'de>>(output: Value) -> Result<T, Status> {
+    serde_json::from_value(output)
+        .map_err(|error| Status::internal(format!("Unexpected model output: {}", error)))
+}
+
+#[derive(Deserialize)]
+struct LabelOutput {
+    #[serde(alias = "polarity")]
+    text: String,
+    score: f64,
+    #[serde(default)]
+    id: i64,
+}
+
+#[derive(Deserialize)]
+struct OffsetOutput {
+    begin: u32,
+    end: u32,
+}
+
+#[derive(Deserialize)]
+struct EntityOutput {
+    #[serde(alias = "text")]
+    word: String,
+    score: f64,
+    label: String,
+    #[serde(default)]
+    offset: Option<OffsetOutput>,
+}
+
+#[derive(Deserialize)]
+struct AnswerOutput {
+    score: f64,
+    start: u64,
+    end: u64,
+    answer: String,
+}
+
+const TEXT_TO_TEXT_TASKS: [TaskType; 3] = [
+    TaskType::Summarization,
+    TaskType::TextGeneration,
+    TaskType::Conversation,
+];
+
+#[tonic::async_trait]
+impl RustBert for RustBertService {
+    async fn list_models(
+        &self,
+        _request: Request<ListModelsRequest>,
+    ) -> Result<Response<ListModelsResponse>, Status> {
+        let mut models = self
+            .models
+            .iter()
+            .map(|(name, model)| ModelInfo {
+                name: name.clone(),
+                task: format!("{:?}", model.task()),
+            })
+            .collect::<Vec<ModelInfo>>();
+        models.sort_by(|model_a, model_b| model_a.name.cmp(&model_b.name));
+        Ok(Response::new(ListModelsResponse { models }))
+    }
+
+    async fn classify(
+        &self,
+        request: Request<ClassificationRequest>,
+    ) -> Result<Response<ClassificationResponse>, Status> {
+        let request = request.into_inner();
+        let model = self.get_model(
+            &request.model,
+            &[
+                TaskType::SequenceClassification,
+                TaskType::Sentiment,
+                TaskType::ZeroShotClassification,
+            ],
+        )?;
+        let input = if model.task() == TaskType::ZeroShotClassification {
+            json!({"inputs": request.inputs, "labels": request.candidate_labels})
+        } else {
+            json!(request.inputs)
+        };
+        let labels: Vec<LabelOutput> = parse_output(model.predict(input, request.priority).await?)?;
+        let labels = labels
+            .into_iter()
+            .map(|label| Label {
+                text: label.text,
+                score: label.score,
+                id: label.id,
+            })
+            .collect();
+        Ok(Response::new(ClassificationResponse { labels }))
+    }
+
+    async fn extract_entities(
+        &self,
+        request: Request<TextRequest>,
+    ) -> Result<Response<EntitiesResponse>, Status> {
+        let request = request.into_inner();
+        let model = self.get_model(
+            &request.model,
+            &[
+                TaskType::NER,
+                TaskType::TokenClassification,
+                TaskType::POSTagging,
+            ],
+        )?;
+        let entities: Vec<Vec<EntityOutput>> = parse_output(
+            model
+                .predict(json!(request.inputs), request.priority)
+                .await?,
+        )?;
+        let results = entities
+            .into_iter()
+            .map(|entities| Entities {
+                entities: entities
+                    .into_iter()
+                    .map(|entity| Entity {
+                        word: entity.word,
+                        score: entity.score,
+                        label: entity.label,
+                        begin: entity.offset.as_ref().map(|offset| offset.begin),
+                        end: entity.offset.as_ref().map(|offset| offset.end),
+                    })
+                    .collect(),
+            })
+            .collect();
+        Ok(Response::new(EntitiesResponse { results }))
+    }
+
+    async fn answer_questions(
+        &self,
+        request: Request<QuestionAnsweringRequest>,
+    ) -> Result<Response<QuestionAnsweringResponse>, Status> {
+        let request = request.into_inner();
+        let model = self.get_model(&request.model, &[TaskType::QuestionAnswering])?;
+        let input = request
+            .inputs
+            .into_iter()
+            .map(|input| json!({"question": input.question, "context": input.context}))
+            .collect::<Vec<Value>>();
+        let answers: Vec<Vec<AnswerOutput>> =
+            parse_output(model.predict(json!(input), request.priority).await?)?;
+        let results = answers
+            .into_iter()
+            .map(|answers| Answers {
+                answers: answers
+                    .into_iter()
+                    .map(|answer| Answer {
+                        score: answer.score,
+                        start: answer.start,
+                        end: answer.end,
+                        answer: answer.answer,
+                    })
+                    .collect(),
+            })
+            .collect();
+        Ok(Response::new(QuestionAnsweringResponse { results }))
+    }
+
+    async fn summarize(
+        &self,
+        request: Request<TextRequest>,
+    ) -> Result<Response<TextResponse>, Status> {
+        let request = request.into_inner();
+        let model = self.get_model(&request.model, &TEXT_TO_TEXT_TASKS)?;
+        let outputs: Vec<String> = parse_output(
+            model
+                .predict(json!(request.inputs), request.priority)
+                .await?,
+        )?;
+        Ok(Response::new(TextResponse { outputs }))
+    }
+
+    type GenerateStream = ReceiverStream<Result<GeneratedText, Status>>;
+
+    async fn generate(
+        &self,
+        request: Request<TextRequest>,
+    ) -> Result<Response<Self::GenerateStream>, Status> {
+        let request = request.into_inner();
+        let model = self.get_model(&request.model, &TEXT_TO_TEXT_TASKS)?;
+        let priority = request.priority;
+        // Inputs are submitted individually so that they can be batched with other requests and
+        // streamed back as soon as they are processed
+        let receivers = request
+            .inputs
+            .into_iter()
+            .map(|input| model.submit(json!([input]), priority))
+            .collect::<Result<Vec<_>, RustBertError>>()?;
+
+        let (sender, stream_receiver) = mpsc::channel(receivers.len().max(1));
+        tokio::spawn(async move {
+            for (index, receiver) in receivers.into_iter().enumerate() {
+                let output = match receiver.await {
+                    Ok(Ok(output)) => {
+                        parse_output::<Vec<String>>(output).map(|outputs| GeneratedText {
+                            index: index as u32,
+                            text: outputs.into_iter().next().unwrap_or_default(),
+                        })
+                    }
+                    Ok(Err(error)) => Err(error.into()),
+                    Err(_) => Err(Status::internal(
+                        "Model worker stopped before completing the request",
+                    )),
+                };
+                let is_error = output.is_err();
+                if sender.send(output).await.is_err() || is_error {
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(stream_receiver)))
+    }
+
+    async fn predict(
+        &self,
+        request: Request<JsonRequest>,
+    ) -> Result<Response<JsonResponse>, Status> {
+        let request = request.into_inner();
+        let model = self.get_model(&request.model, &[])?;
+        let input: Value =
+            serde_json::from_str(&request.input_json).map_err(RustBertError::from)?;
+        let output = model.predict(input, request.priority).await?;
+        Ok(Response::new(JsonResponse {
+            output_json: output.to_string(),
+        }))
+    }
+}
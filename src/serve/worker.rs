@@ -0,0 +1,476 @@
+use crate::pipelines::registry::{ModelSpec, Pipeline, TaskType};
+use crate::RustBertError;
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+/// # Order in which queued requests are scheduled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedulingPolicy {
+    /// Requests are processed in their order of arrival
+    Fifo,
+    /// Requests with the smallest estimated cost (input length) are processed first, so that short
+    /// interactive requests are not stuck behind long documents
+    ShortestJobFirst,
+    /// Requests with the highest priority are processed first, ties are broken by order of arrival
+    Priority,
+}
+
+/// # Configuration of the request batching
+#[derive(Debug, Clone, Copy)]
+pub struct BatchConfig {
+    /// Maximum number of inputs processed in a single forward pass (a larger request is processed as a single batch)
+    pub max_batch_size: usize,
+    /// Maximum duration to wait for additional requests after receiving the first request of a batch
+    pub batch_timeout: Duration,
+    /// Order in which the queued requests are added to the batches (default: `SchedulingPolicy::Fifo`)
+    pub scheduling_policy: SchedulingPolicy,
+    /// Optional budget for the estimated cost (number of input characters) of a batch. Requests
+    /// exceeding the remaining budget are deferred to a later batch, a request exceeding the budget
+    /// on its own is processed alone (default: None)
+    pub max_batch_cost: Option<usize>,
+    /// Optional maximum queuing delay after which a request is scheduled before any other request,
+    /// preventing the starvation of long or low priority requests (default: None)
+    pub max_queue_delay: Option<Duration>,
+}
+
+impl Default for BatchConfig {
+    fn default() -> BatchConfig {
+        BatchConfig {
+            max_batch_size: 16,
+            batch_timeout: Duration::from_millis(10),
+            scheduling_policy: SchedulingPolicy::Fifo,
+            max_batch_cost: None,
+            max_queue_delay: None,
+        }
+    }
+}
+
+struct Job {
+    input: Value,
+    sender: oneshot::Sender<Result<Value, RustBertError>>,
+    priority: i32,
+    cost: usize,
+    submitted: Instant,
+}
+
+/// Handle to a pipeline running on a dedicated worker thread
+pub(crate) struct ModelWorker {
+    task: TaskType,
+    sender: Mutex<Sender<Job>>,
+}
+
+impl ModelWorker {
+    /// Loads the pipeline on a new worker thread, blocking until the model is loaded
+    pub(crate) fn spawn(
+        task: TaskType,
+        model_spec: ModelSpec,
+        batch_config: BatchConfig,
+    ) -> Result<ModelWorker, RustBertError> {
+        let (sender, receiver) = mpsc::channel();
+        let (load_sender, load_receiver) = mpsc::channel();
+        thread::spawn(move || match Pipeline::new(task, model_spec) {
+            Ok(pipeline) => {
+                let _ = load_sender.send(Ok(()));
+                run(|input| pipeline.predict_json(input), receiver, batch_config);
+            }
+            Err(error) => {
+                let _ = load_sender.send(Err(error));
+            }
+        });
+        load_receiver.recv().map_err(|_| {
+            RustBertError::ValueError("Model worker stopped while loading the model".to_string())
+        })??;
+        Ok(ModelWorker {
+            task,
+            sender: Mutex::new(sender),
+        })
+    }
+
+    pub(crate) fn task(&self) -> TaskType {
+        self.task
+    }
+
+    /// Submits an input to the worker, returning a receiver for the pipeline output. Requests with
+    /// a higher priority are scheduled first with `SchedulingPolicy::Priority`.
+    pub(crate) fn submit(
+        &self,
+        input: Value,
+        priority: i32,
+    ) -> Result<oneshot::Receiver<Result<Value, RustBertError>>, RustBertError> {
+        let (sender, receiver) = oneshot::channel();
+        let cost = input_cost(&input);
+        self.sender
+            .lock()
+            .unwrap()
+            .send(Job {
+                input,
+                sender,
+                priority,
+                cost,
+                submitted: Instant::now(),
+            })
+            .map_err(|_| RustBertError::ValueError("Model worker is not running".to_string()))?;
+        Ok(receiver)
+    }
+
+    /// Runs the pipeline on an input, batched with concurrent requests
+    pub(crate) async fn predict(
+        &self,
+        input: Value,
+        priority: i32,
+    ) -> Result<Value, RustBertError> {
+        self.submit(input, priority)?.await.map_err(|_| {
+            RustBertError::ValueError(
+                "Model worker stopped before completing the request".to_string(),
+            )
+        })?
+    }
+}
+
+/// Processes the jobs received by the worker by batches, running `predict` (the pipeline JSON
+/// interface) on the batched inputs
+fn run<F>(predict: F, receiver: Receiver<Job>, batch_config: BatchConfig)
+where
+    F: Fn(&Value) -> Result<Value, RustBertError>,
+{
+    let mut queue: Vec<Job> = vec![];
+    loop {
+        if queue.is_empty() {
+            match receiver.recv() {
+                Ok(job) => queue.push(job),
+                Err(_) => break,
+            }
+        }
+        queue.extend(receiver.try_iter());
+        let mut queued_size = queue
+            .iter()
+            .map(|job| input_size(&job.input))
+            .sum::<usize>();
+        let deadline = Instant::now() + batch_config.batch_timeout;
+        while queued_size < batch_config.max_batch_size {
+            match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(job) => {
+                    queued_size += input_size(&job.input);
+                    queue.push(job);
+                }
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        let jobs = schedule(&mut queue, &batch_config, Instant::now());
+        process_jobs(&predict, jobs);
+    }
+}
+
+/// Removes the jobs of the next batch from the queue, following the scheduling policy
+fn schedule(queue: &mut Vec<Job>, batch_config: &BatchConfig, now: Instant) -> Vec<Job> {
+    let is_overdue = |job: &Job| match batch_config.max_queue_delay {
+        Some(max_queue_delay) => now.saturating_duration_since(job.submitted) >= max_queue_delay,
+        None => false,
+    };
+    let mut order = (0..queue.len()).collect::<Vec<usize>>();
+    // The queue is in order of arrival and the sort is stable: ties are broken by arrival
+    match batch_config.scheduling_policy {
+        SchedulingPolicy::Fifo => {}
+        SchedulingPolicy::ShortestJobFirst => {
+            order.sort_by_key(|&index| (!is_overdue(&queue[index]), queue[index].cost))
+        }
+        SchedulingPolicy::Priority => {
+            order.sort_by_key(|&index| (!is_overdue(&queue[index]), Reverse(queue[index].priority)))
+        }
+    }
+
+    let mut selected = vec![false; queue.len()];
+    let (mut batch_size, mut batch_cost) = (0, 0);
+    for index in order {
+        if batch_size >= batch_config.max_batch_size {
+            break;
+        }
+        let cost = queue[index].cost;
+        if let Some(max_batch_cost) = batch_config.max_batch_cost {
+            if (batch_size > 0) & (batch_cost + cost > max_batch_cost) {
+                continue;
+            }
+        }
+        selected[index] = true;
+        batch_size += input_size(&queue[index].input);
+        batch_cost += cost;
+    }
+
+    let mut jobs = Vec::with_capacity(queue.len());
+    let mut remaining = Vec::with_capacity(queue.len());
+    for (job, selected) in queue.drain(..).zip(selected) {
+        if selected {
+            jobs.push(job);
+        } else {
+            remaining.push(job);
+        }
+    }
+    *queue = remaining;
+    jobs
+}
+
+fn input_size(input: &Value) -> usize {
+    input.as_array().map_or(1, Vec::len)
+}
+
+/// Estimated processing cost of an input: number of characters of the texts it contains
+fn input_cost(input: &Value) -> usize {
+    match input {
+        Value::String(text) => text.chars().count(),
+        Value::Array(values) => values.iter().map(input_cost).sum(),
+        Value::Object(map) => map.values().map(input_cost).sum(),
+        _ => 0,
+    }
+}
+
+/// Inputs provided as arrays are concatenated and processed in a single forward pass, other inputs
+/// (e.g. zero-shot classification) are processed individually.
+fn process_jobs<F>(predict: &F, jobs: Vec<Job>)
+where
+    F: Fn(&Value) -> Result<Value, RustBertError>,
+{
+    let (batched_jobs, single_jobs): (Vec<Job>, Vec<Job>) =
+        jobs.into_iter().partition(|job| job.input.is_array());
+
+    for job in single_jobs {
+        let _ = job.sender.send(predict(&job.input));
+    }
+
+    match batched_jobs.len() {
+        0 => {}
+        1 => {
+            let job = batched_jobs.into_iter().next().unwrap();
+            let _ = job.sender.send(predict(&job.input));
+        }
+        _ => {
+            let sizes = batched_jobs
+                .iter()
+                .map(|job| input_size(&job.input))
+                .collect::<Vec<usize>>();
+            let inputs = batched_jobs
+                .iter()
+                .flat_map(|job| job.input.as_array().unwrap().iter().cloned())
+                .collect::<Vec<Value>>();
+            match predict(&Value::Array(inputs)) {
+                Ok(Value::Array(mut outputs)) if outputs.len() == sizes.iter().sum::<usize>() => {
+                    let mut offset = 0;
+                    for (job, size) in batched_jobs.into_iter().zip(sizes) {
+                        let mut job_outputs = outputs.drain(..size).collect::<Vec<Value>>();
+                        job_outputs
+                            .iter_mut()
+                            .for_each(|output| shift_sentence_indices(output, offset));
+                        offset += size;
+                        let _ = job.sender.send(Ok(Value::Array(job_outputs)));
+                    }
+                }
+                // A single invalid request should not fail the other requests of the batch
+                _ => {
+                    for job in batched_jobs {
+                        let _ = job.sender.send(predict(&job.input));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Outputs referring to the index of their input (`sentence` field) are re-indexed relative to
+/// their request
+fn shift_sentence_indices(output: &mut Value, offset: usize) {
+    match output {
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| shift_sentence_indices(value, offset)),
+        Value::Object(map) => {
+            if let Some(Value::Number(sentence)) = map.get("sentence") {
+                if let Some(sentence) = sentence.as_u64() {
+                    map.insert(
+                        "sentence".to_string(),
+                        Value::from(sentence.saturating_sub(offset as u64)),
+                    );
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn job(text: &str, priority: i32, submitted: Instant) -> Job {
+        Job {
+            input: json!([text]),
+            sender: oneshot::channel().0,
+            priority,
+            cost: text.len(),
+            submitted,
+        }
+    }
+
+    fn texts(jobs: &[Job]) -> Vec<&str> {
+        jobs.iter()
+            .map(|job| job.input[0].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn scheduling_policies() {
+        let now = Instant::now();
+        let queue = || {
+            vec![
+                job("a long document to summarize", 0, now),
+                job("short", 0, now),
+                job("medium text", 2, now),
+                job("tiny", 1, now),
+            ]
+        };
+        let mut batch_config = BatchConfig {
+            max_batch_size: 2,
+            ..Default::default()
+        };
+
+        let mut fifo_queue = queue();
+        let batch = schedule(&mut fifo_queue, &batch_config, now);
+        assert_eq!(texts(&batch), ["a long document to summarize", "short"]);
+        assert_eq!(texts(&fifo_queue), ["medium text", "tiny"]);
+
+        batch_config.scheduling_policy = SchedulingPolicy::ShortestJobFirst;
+        let mut sjf_queue = queue();
+        let batch = schedule(&mut sjf_queue, &batch_config, now);
+        assert_eq!(texts(&batch), ["short", "tiny"]);
+
+        batch_config.scheduling_policy = SchedulingPolicy::Priority;
+        let mut priority_queue = queue();
+        let batch = schedule(&mut priority_queue, &batch_config, now);
+        assert_eq!(texts(&batch), ["medium text", "tiny"]);
+
+        // Requests exceeding the remaining budget are deferred, overdue requests go first
+        batch_config.max_batch_size = 4;
+        batch_config.max_batch_cost = Some(16);
+        batch_config.max_queue_delay = Some(Duration::from_secs(1));
+        let mut budget_queue = queue();
+        budget_queue[0].submitted = now - Duration::from_secs(2);
+        let batch = schedule(&mut budget_queue, &batch_config, now);
+        assert_eq!(texts(&batch), ["a long document to summarize"]);
+        let batch = schedule(&mut budget_queue, &batch_config, now);
+        assert_eq!(texts(&batch), ["medium text", "tiny"]);
+        assert_eq!(texts(&budget_queue), ["short"]);
+    }
+
+    /// Runs the worker loop on a thread with a predictor echoing each text with its index in the
+    /// batch (rejecting batches containing `"invalid"`), recording the batches it receives
+    fn spawn_echo_worker(
+        batch_config: BatchConfig,
+    ) -> (ModelWorker, Arc<Mutex<Vec<Value>>>, thread::JoinHandle<()>) {
+        let (sender, receiver) = mpsc::channel();
+        let batches = Arc::new(Mutex::new(vec![]));
+        let recorded_batches = batches.clone();
+        let predict = move |input: &Value| {
+            recorded_batches.lock().unwrap().push(input.clone());
+            match input {
+                Value::Array(texts) if texts.contains(&json!("invalid")) => {
+                    Err(RustBertError::ValueError("invalid input".to_string()))
+                }
+                Value::Array(texts) => Ok(Value::Array(
+                    texts
+                        .iter()
+                        .enumerate()
+                        .map(|(sentence, text)| json!({"text": text, "sentence": sentence}))
+                        .collect(),
+                )),
+                _ => Ok(json!({ "single": input })),
+            }
+        };
+        let worker = ModelWorker {
+            task: TaskType::NER,
+            sender: Mutex::new(sender),
+        };
+        // The worker loop starts after the jobs are queued so that they are batched together
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            run(predict, receiver, batch_config)
+        });
+        (worker, batches, handle)
+    }
+
+    #[test]
+    fn worker_batches_concurrent_requests() -> Result<(), RustBertError> {
+        let batch_config = BatchConfig {
+            max_batch_size: 8,
+            batch_timeout: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let (worker, batches, handle) = spawn_echo_worker(batch_config);
+
+        let first = worker.submit(json!(["a", "b"]), 0)?;
+        let second = worker.submit(json!(["c"]), 0)?;
+        let zero_shot = worker.submit(json!({"inputs": ["d"], "labels": ["e"]}), 0)?;
+
+        assert_eq!(
+            first.blocking_recv().unwrap()?,
+            json!([{"text": "a", "sentence": 0}, {"text": "b", "sentence": 1}])
+        );
+        // Sentence indices are relative to the request
+        assert_eq!(
+            second.blocking_recv().unwrap()?,
+            json!([{"text": "c", "sentence": 0}])
+        );
+        assert_eq!(
+            zero_shot.blocking_recv().unwrap()?,
+            json!({"single": {"inputs": ["d"], "labels": ["e"]}})
+        );
+        // Array inputs are concatenated in a single forward pass, other inputs run individually
+        assert_eq!(
+            *batches.lock().unwrap(),
+            vec![
+                json!({"inputs": ["d"], "labels": ["e"]}),
+                json!(["a", "b", "c"])
+            ]
+        );
+
+        drop(worker);
+        handle.join().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn worker_isolates_failing_requests() -> Result<(), RustBertError> {
+        let batch_config = BatchConfig {
+            max_batch_size: 8,
+            batch_timeout: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let (worker, batches, handle) = spawn_echo_worker(batch_config);
+
+        let valid = worker.submit(json!(["a"]), 0)?;
+        let invalid = worker.submit(json!(["invalid"]), 0)?;
+
+        // The failing batch is processed again request by request
+        assert_eq!(
+            valid.blocking_recv().unwrap()?,
+            json!([{"text": "a", "sentence": 0}])
+        );
+        assert!(matches!(
+            invalid.blocking_recv().unwrap(),
+            Err(RustBertError::ValueError(_))
+        ));
+        assert_eq!(
+            *batches.lock().unwrap(),
+            vec![json!(["a", "invalid"]), json!(["a"]), json!(["invalid"])]
+        );
+
+        drop(worker);
+        handle.join().unwrap();
+        Ok(())
+    }
+}
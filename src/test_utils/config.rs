@@ -0,0 +1,389 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::albert::AlbertConfig;
+use crate::bart::BartConfig;
+use crate::bert::BertConfig;
+use crate::deberta::DebertaConfig;
+use crate::deberta_v2::DebertaV2Config;
+use crate::distilbert::DistilBertConfig;
+use crate::electra::ElectraConfig;
+use crate::fnet::FNetConfig;
+use crate::gpt2::Gpt2Config;
+use crate::gpt_neo::GptNeoConfig;
+use crate::longformer::LongformerConfig;
+use crate::mbart::MBartConfig;
+use crate::mobilebert::MobileBertConfig;
+use crate::pipelines::common::ModelType;
+use crate::prophetnet::ProphetNetConfig;
+use crate::reformer::ReformerConfig;
+use crate::t5::T5Config;
+use crate::test_utils::{TinyModelConfig, TinyModelHead};
+use crate::xlnet::XLNetConfig;
+use crate::{Activation, RustBertError};
+use serde_json::Value;
+use std::collections::HashMap;
+
+const NUM_LAYERS: i64 = 2;
+const HIDDEN_SIZE: i64 = 32;
+const NUM_ATTENTION_HEADS: i64 = 4;
+const INTERMEDIATE_SIZE: i64 = 64;
+/// Size of the factorized embeddings (ALBERT, ELECTRA and MobileBERT)
+const EMBEDDING_SIZE: i64 = 16;
+/// Attention window of Longformer and chunk length of the Reformer attention layers
+const ATTENTION_CHUNK_LENGTH: i64 = 8;
+
+/// Creates the configuration of a tiny model of `config.model_type`, with the special token ids of
+/// the vocabulary
+pub(crate) fn model_config(
+    config: &TinyModelConfig,
+    vocab: &HashMap<String, i64>,
+    vocab_size: i64,
+) -> Result<Value, RustBertError> {
+    let token_id = |token: &str| {
+        vocab.get(token).copied().ok_or_else(|| {
+            RustBertError::InvalidConfigurationError(format!(
+                "Token {} not found in the vocabulary",
+                token
+            ))
+        })
+    };
+    let max_position_embeddings = config.max_position_embeddings;
+    let (id2label, label2id) = label_mappings(&config.labels);
+
+    let model_config = match config.model_type {
+        ModelType::Bert | ModelType::Roberta | ModelType::XLMRoberta => {
+            serde_json::to_value(BertConfig {
+                hidden_size: HIDDEN_SIZE,
+                intermediate_size: INTERMEDIATE_SIZE,
+                max_position_embeddings,
+                num_attention_heads: NUM_ATTENTION_HEADS,
+                num_hidden_layers: NUM_LAYERS,
+                vocab_size,
+                id2label,
+                label2id,
+                ..Default::default()
+            })?
+        }
+        ModelType::DistilBert => serde_json::to_value(DistilBertConfig {
+            dim: HIDDEN_SIZE,
+            hidden_dim: INTERMEDIATE_SIZE,
+            max_position_embeddings,
+            n_heads: NUM_ATTENTION_HEADS,
+            n_layers: NUM_LAYERS,
+            vocab_size,
+            id2label,
+            label2id,
+            ..Default::default()
+        })?,
+        ModelType::Deberta => serde_json::to_value(DebertaConfig {
+            hidden_size: HIDDEN_SIZE,
+            intermediate_size: INTERMEDIATE_SIZE,
+            max_position_embeddings,
+            num_attention_heads: NUM_ATTENTION_HEADS,
+            num_hidden_layers: NUM_LAYERS,
+            vocab_size,
+            pooler_hidden_size: Some(HIDDEN_SIZE),
+            pad_token_id: Some(token_id("[PAD]")?),
+            id2label,
+            label2id,
+            ..Default::default()
+        })?,
+        ModelType::DebertaV2 => serde_json::to_value(DebertaV2Config {
+            hidden_size: HIDDEN_SIZE,
+            intermediate_size: INTERMEDIATE_SIZE,
+            max_position_embeddings,
+            num_attention_heads: NUM_ATTENTION_HEADS,
+            num_hidden_layers: NUM_LAYERS,
+            vocab_size,
+            pooler_hidden_size: Some(HIDDEN_SIZE),
+            pad_token_id: Some(token_id("[PAD]")?),
+            id2label,
+            label2id,
+            ..Default::default()
+        })?,
+        ModelType::Electra => serde_json::to_value(ElectraConfig {
+            embedding_size: EMBEDDING_SIZE,
+            hidden_size: HIDDEN_SIZE,
+            intermediate_size: INTERMEDIATE_SIZE,
+            max_position_embeddings,
+            num_attention_heads: NUM_ATTENTION_HEADS,
+            num_hidden_layers: NUM_LAYERS,
+            vocab_size,
+            pad_token_id: token_id("[PAD]")?,
+            id2label,
+            label2id,
+            ..Default::default()
+        })?,
+        ModelType::MobileBert => serde_json::to_value(MobileBertConfig {
+            hidden_size: HIDDEN_SIZE,
+            intermediate_size: INTERMEDIATE_SIZE,
+            max_position_embeddings,
+            num_attention_heads: NUM_ATTENTION_HEADS,
+            num_hidden_layers: NUM_LAYERS,
+            vocab_size,
+            embedding_size: EMBEDDING_SIZE,
+            pad_token_idx: Some(token_id("[PAD]")?),
+            intra_bottleneck_size: Some(EMBEDDING_SIZE),
+            num_feedforward_networks: Some(2),
+            id2label,
+            label2id,
+            ..Default::default()
+        })?,
+        ModelType::Albert => serde_json::to_value(AlbertConfig {
+            bos_token_id: token_id("[CLS]")?,
+            eos_token_id: token_id("[SEP]")?,
+            embedding_size: EMBEDDING_SIZE,
+            hidden_size: HIDDEN_SIZE,
+            intermediate_size: INTERMEDIATE_SIZE,
+            max_position_embeddings,
+            num_attention_heads: NUM_ATTENTION_HEADS,
+            num_hidden_layers: NUM_LAYERS,
+            pad_token_id: token_id("<pad>")?,
+            vocab_size,
+            id2label,
+            label2id,
+            ..Default::default()
+        })?,
+        ModelType::FNet => serde_json::to_value(FNetConfig {
+            vocab_size,
+            hidden_size: HIDDEN_SIZE,
+            num_hidden_layers: NUM_LAYERS,
+            intermediate_size: INTERMEDIATE_SIZE,
+            max_position_embeddings,
+            pad_token_id: Some(token_id("<pad>")?),
+            bos_token_id: Some(token_id("[CLS]")?),
+            eos_token_id: Some(token_id("[SEP]")?),
+            id2label,
+            label2id,
+            ..Default::default()
+        })?,
+        ModelType::Longformer => serde_json::to_value(LongformerConfig {
+            attention_window: vec![ATTENTION_CHUNK_LENGTH; NUM_LAYERS as usize],
+            hidden_size: HIDDEN_SIZE,
+            intermediate_size: INTERMEDIATE_SIZE,
+            max_position_embeddings,
+            num_attention_heads: NUM_ATTENTION_HEADS,
+            num_hidden_layers: NUM_LAYERS,
+            vocab_size,
+            sep_token_id: token_id("</s>")?,
+            pad_token_id: Some(token_id("<pad>")?),
+            id2label,
+            label2id,
+            ..Default::default()
+        })?,
+        ModelType::XLNet => serde_json::to_value(XLNetConfig {
+            vocab_size,
+            d_model: HIDDEN_SIZE,
+            n_layer: NUM_LAYERS,
+            d_head: HIDDEN_SIZE / NUM_ATTENTION_HEADS,
+            n_head: NUM_ATTENTION_HEADS,
+            d_inner: INTERMEDIATE_SIZE,
+            bos_token_id: token_id("<s>")?,
+            eos_token_id: token_id("</s>")?,
+            pad_token_id: token_id("<pad>")?,
+            id2label,
+            label2id,
+            ..Default::default()
+        })?,
+        ModelType::Reformer => {
+            // The axial position embeddings factorize the positions in a (8, N / 8) grid
+            if max_position_embeddings % 8 != 0 {
+                return Err(RustBertError::InvalidConfigurationError(format!(
+                    "The number of positions of a Reformer model must be a multiple of 8, got {}",
+                    max_position_embeddings
+                )));
+            }
+            serde_json::to_value(ReformerConfig {
+                attention_head_size: HIDDEN_SIZE / NUM_ATTENTION_HEADS,
+                // A local attention layer followed by a LSH attention layer
+                attn_layers: ReformerConfig::default().attn_layers[..NUM_LAYERS as usize].to_vec(),
+                axial_pos_embds_dim: vec![HIDDEN_SIZE / 2, HIDDEN_SIZE / 2],
+                axial_pos_shape: vec![8, max_position_embeddings / 8],
+                eos_token_id: token_id("</s>")?,
+                pad_token_id: token_id("<unk>")?,
+                feed_forward_size: INTERMEDIATE_SIZE,
+                hidden_size: HIDDEN_SIZE,
+                is_decoder: config.head == TinyModelHead::Generation,
+                max_position_embeddings,
+                vocab_size,
+                num_attention_heads: NUM_ATTENTION_HEADS,
+                num_buckets: Value::from(4),
+                local_attn_chunk_length: Some(ATTENTION_CHUNK_LENGTH),
+                lsh_attn_chunk_length: Some(ATTENTION_CHUNK_LENGTH),
+                num_hidden_layers: NUM_LAYERS,
+                id2label,
+                label2id,
+                ..Default::default()
+            })?
+        }
+        ModelType::GPT2 | ModelType::OpenAiGpt => serde_json::to_value(Gpt2Config {
+            n_ctx: max_position_embeddings,
+            n_embd: HIDDEN_SIZE,
+            n_head: NUM_ATTENTION_HEADS,
+            n_layer: NUM_LAYERS,
+            n_positions: max_position_embeddings,
+            vocab_size,
+            ..Default::default()
+        })?,
+        ModelType::GPTNeo => {
+            let default_config = GptNeoConfig::default();
+            serde_json::to_value(GptNeoConfig {
+                // Alternating global and local attention layers
+                attention_layers: default_config.attention_layers[..NUM_LAYERS as usize].to_vec(),
+                attention_types: vec![(
+                    default_config.attention_types[0].0.clone(),
+                    NUM_LAYERS / 2,
+                )],
+                intermediate_size: Some(INTERMEDIATE_SIZE),
+                bos_token_id: token_id("<|endoftext|>")?,
+                eos_token_id: token_id("<|endoftext|>")?,
+                vocab_size,
+                num_layers: NUM_LAYERS,
+                num_heads: NUM_ATTENTION_HEADS,
+                hidden_size: HIDDEN_SIZE,
+                window_size: ATTENTION_CHUNK_LENGTH,
+                max_position_embeddings,
+                ..default_config
+            })?
+        }
+        ModelType::Bart | ModelType::Marian => {
+            let pad_token_id = token_id("<pad>")?;
+            let bart_config = BartConfig {
+                d_model: HIDDEN_SIZE,
+                decoder_attention_heads: NUM_ATTENTION_HEADS,
+                decoder_ffn_dim: INTERMEDIATE_SIZE,
+                decoder_layers: NUM_LAYERS,
+                encoder_attention_heads: NUM_ATTENTION_HEADS,
+                encoder_ffn_dim: INTERMEDIATE_SIZE,
+                encoder_layers: NUM_LAYERS,
+                max_position_embeddings,
+                num_hidden_layers: NUM_LAYERS,
+                vocab_size,
+                pad_token_id: Some(pad_token_id),
+                eos_token_id: Some(token_id("</s>")?),
+                id2label,
+                label2id,
+                ..Default::default()
+            };
+            let bart_config = if config.model_type == ModelType::Marian {
+                // Marian uses sinusoidal position embeddings, does not normalize the embeddings
+                // and starts decoding with the padding token
+                BartConfig {
+                    activation_function: Some(Activation::swish),
+                    bos_token_id: None,
+                    decoder_start_token_id: Some(pad_token_id),
+                    normalize_embedding: Some(false),
+                    scale_embedding: Some(true),
+                    static_position_embeddings: Some(true),
+                    ..bart_config
+                }
+            } else {
+                BartConfig {
+                    bos_token_id: Some(token_id("<s>")?),
+                    decoder_start_token_id: Some(token_id("</s>")?),
+                    ..bart_config
+                }
+            };
+            serde_json::to_value(bart_config)?
+        }
+        ModelType::MBart | ModelType::M2M100 | ModelType::Pegasus => {
+            let eos_token_id = token_id("</s>")?;
+            // Pegasus has no beginning of sequence token and starts decoding with the padding
+            // token, MBart and M2M100 start decoding with the end of sequence token
+            let (bos_token_id, decoder_start_token_id) = if config.model_type == ModelType::Pegasus
+            {
+                let pad_token_id = token_id("<pad>")?;
+                (pad_token_id, pad_token_id)
+            } else {
+                (token_id("<s>")?, eos_token_id)
+            };
+            serde_json::to_value(MBartConfig {
+                vocab_size,
+                max_position_embeddings,
+                encoder_layers: NUM_LAYERS,
+                encoder_attention_heads: NUM_ATTENTION_HEADS,
+                encoder_ffn_dim: INTERMEDIATE_SIZE,
+                decoder_layers: NUM_LAYERS,
+                decoder_ffn_dim: INTERMEDIATE_SIZE,
+                decoder_attention_heads: NUM_ATTENTION_HEADS,
+                d_model: HIDDEN_SIZE,
+                bos_token_id: Some(bos_token_id),
+                eos_token_id: Some(eos_token_id),
+                pad_token_id: Some(token_id("<pad>")?),
+                forced_eos_token_id: Some(eos_token_id),
+                decoder_start_token_id: Some(decoder_start_token_id),
+                id2label,
+                label2id,
+                ..Default::default()
+            })?
+        }
+        ModelType::ProphetNet => {
+            let sep_token_id = token_id("[SEP]")?;
+            serde_json::to_value(ProphetNetConfig {
+                decoder_ffn_dim: INTERMEDIATE_SIZE,
+                decoder_start_token_id: sep_token_id,
+                encoder_ffn_dim: INTERMEDIATE_SIZE,
+                hidden_size: HIDDEN_SIZE,
+                max_position_embeddings,
+                bos_token_id: sep_token_id,
+                eos_token_id: sep_token_id,
+                id2label,
+                label2id,
+                num_decoder_attention_heads: NUM_ATTENTION_HEADS,
+                num_decoder_layers: NUM_LAYERS,
+                num_encoder_attention_heads: NUM_ATTENTION_HEADS,
+                num_encoder_layers: NUM_LAYERS,
+                pad_token_id: token_id("[PAD]")?,
+                vocab_size,
+                ..Default::default()
+            })?
+        }
+        ModelType::T5 => {
+            // The task specific parameters of the T5 configuration are private
+            let mut t5_config = T5Config::default();
+            t5_config.d_model = HIDDEN_SIZE;
+            t5_config.d_ff = INTERMEDIATE_SIZE;
+            t5_config.d_kv = HIDDEN_SIZE / NUM_ATTENTION_HEADS;
+            t5_config.num_heads = NUM_ATTENTION_HEADS;
+            t5_config.num_layers = NUM_LAYERS;
+            t5_config.vocab_size = vocab_size;
+            t5_config.pad_token_id = Some(token_id("<pad>")?);
+            t5_config.eos_token_id = Some(token_id("</s>")?);
+            serde_json::to_value(t5_config)?
+        }
+    };
+    Ok(model_config)
+}
+
+fn label_mappings(
+    labels: &[String],
+) -> (Option<HashMap<i64, String>>, Option<HashMap<String, i64>>) {
+    if labels.is_empty() {
+        return (None, None);
+    }
+    (
+        Some(
+            labels
+                .iter()
+                .enumerate()
+                .map(|(id, label)| (id as i64, label.clone()))
+                .collect(),
+        ),
+        Some(
+            labels
+                .iter()
+                .enumerate()
+                .map(|(id, label)| (label.clone(), id as i64))
+                .collect(),
+        ),
+    )
+}
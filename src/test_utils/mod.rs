@@ -0,0 +1,339 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Tiny randomly-initialized models for testing
+//! Builds models of any `ModelType` with 2 layers, a small hidden size and a small synthetic
+//! vocabulary, initialized from a fixed seed and saved to a temporary directory (enabled with the
+//! `test-utils` feature). These models allow testing pipelines and generation settings without
+//! downloading pretrained checkpoints: their outputs are meaningless but deterministic for a given
+//! seed.
+//!
+//! `TinyModel::new` writes the configuration, the weights of the requested `TinyModelHead` and the
+//! vocabulary files expected by the tokenizer of the model type (WordPiece, byte-level BPE or
+//! SentencePiece), containing the lower-case letters, digits and punctuation and a few common
+//! words. The files are exposed as `LocalResource`s and removed when the `TinyModel` is dropped.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rust_bert::gpt2::GPT2Generator;
+//! use rust_bert::pipelines::common::ModelType;
+//! use rust_bert::pipelines::generation_utils::{GenerateConfig, LanguageGenerator};
+//! use rust_bert::test_utils::{TinyModel, TinyModelConfig, TinyModelHead};
+//!
+//! let model = TinyModel::new(TinyModelConfig::new(
+//!     ModelType::GPT2,
+//!     TinyModelHead::Generation,
+//! ))?;
+//! let generator = GPT2Generator::new(GenerateConfig {
+//!     model_resource: model.model_resource(),
+//!     config_resource: model.config_resource(),
+//!     vocab_resource: model.vocab_resource(),
+//!     merges_resource: model.merges_resource(),
+//!     max_length: Some(16),
+//!     ..Default::default()
+//! })?;
+//! let output = generator.generate(Some(&["the dog is"]), None)?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod config;
+mod vocab;
+
+use crate::pipelines::common::{ConfigOption, ModelType};
+use crate::pipelines::masked_language::MaskedLanguageOption;
+use crate::pipelines::question_answering::QuestionAnsweringOption;
+use crate::pipelines::sequence_classification::SequenceClassificationOption;
+use crate::pipelines::token_classification::TokenClassificationOption;
+use crate::resources::{LocalResource, ResourceProvider};
+use crate::test_utils::config::model_config;
+use crate::test_utils::vocab::{vocab_values, write_vocab};
+use crate::RustBertError;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tch::{nn, Device};
+use tempfile::TempDir;
+
+/// The libtorch random number generator is global to the process while tests run concurrently:
+/// seeded sections are serialized to remain reproducible
+static SEED_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// # Head of a tiny model
+pub enum TinyModelHead {
+    /// Language model head used by the text generation pipelines (causal language model, or
+    /// conditional generation for encoder-decoder architectures)
+    Generation,
+    /// Extractive question answering head
+    QuestionAnswering,
+    /// Sequence classification head (requires labels)
+    SequenceClassification,
+    /// Token classification head (requires labels)
+    TokenClassification,
+    /// Masked language model head
+    MaskedLanguageModel,
+}
+
+/// # Configuration of a tiny model
+pub struct TinyModelConfig {
+    /// Model type
+    pub model_type: ModelType,
+    /// Head of the model
+    pub head: TinyModelHead,
+    /// Seed of the libtorch random number generator used to initialize the weights
+    pub seed: i64,
+    /// Labels of the classification heads, stored as the `id2label` and `label2id` mappings of the
+    /// configuration
+    pub labels: Vec<String>,
+    /// Maximum number of positions (must be a multiple of 8 for Reformer, ignored by T5 and XLNet
+    /// that use relative positions)
+    pub max_position_embeddings: i64,
+}
+
+impl TinyModelConfig {
+    /// Creates the configuration of a tiny model with a seed of 42, no labels and 128 positions.
+    ///
+    /// # Arguments
+    ///
+    /// * `model_type` - `ModelType` of the model
+    /// * `head` - `TinyModelHead` of the model
+    pub fn new(model_type: ModelType, head: TinyModelHead) -> TinyModelConfig {
+        TinyModelConfig {
+            model_type,
+            head,
+            seed: 42,
+            labels: vec![],
+            max_position_embeddings: 128,
+        }
+    }
+
+    /// Sets the labels of the classification heads
+    pub fn with_labels<S: AsRef<str>>(mut self, labels: &[S]) -> TinyModelConfig {
+        self.labels = labels
+            .iter()
+            .map(|label| label.as_ref().to_string())
+            .collect();
+        self
+    }
+}
+
+/// # Files of a tiny model, deleted when dropped
+pub struct TinyModel {
+    _directory: TempDir,
+    config_path: PathBuf,
+    weights_path: PathBuf,
+    vocab_path: PathBuf,
+    merges_path: Option<PathBuf>,
+    /// Model type
+    pub model_type: ModelType,
+    /// Size of the vocabulary (the token ids are lower than this value)
+    pub vocab_size: i64,
+}
+
+impl TinyModel {
+    /// Builds a tiny model and writes its configuration, weights and vocabulary files to a
+    /// temporary directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `TinyModelConfig` describing the model to build
+    ///
+    /// Returns an `InvalidConfigurationError` if the model type does not support the head, or if a
+    /// classification head is requested without labels.
+    pub fn new(config: TinyModelConfig) -> Result<TinyModel, RustBertError> {
+        if config.labels.is_empty()
+            && matches!(
+                config.head,
+                TinyModelHead::SequenceClassification | TinyModelHead::TokenClassification
+            )
+        {
+            return Err(RustBertError::InvalidConfigurationError(format!(
+                "Labels are required to build a {:?} head",
+                config.head
+            )));
+        }
+
+        let directory = tempfile::tempdir()?;
+        let vocab_files = write_vocab(config.model_type, directory.path())?;
+        let vocab = vocab_values(config.model_type, &vocab_files)?;
+        let vocab_size = vocab.values().max().map_or(0, |max_id| max_id + 1);
+
+        let config_path = directory.path().join("config.json");
+        fs::write(
+            &config_path,
+            serde_json::to_string(&model_config(&config, &vocab, vocab_size)?)?,
+        )?;
+        let config_option = ConfigOption::from_file(config.model_type, &config_path);
+
+        let var_store = with_seed(config.seed, || {
+            let var_store = nn::VarStore::new(Device::Cpu);
+            build_head(
+                config.model_type,
+                config.head,
+                var_store.root(),
+                &config_option,
+            )?;
+            Ok::<_, RustBertError>(var_store)
+        })?;
+        let weights_path = directory.path().join("model.ot");
+        var_store.save(&weights_path)?;
+
+        Ok(TinyModel {
+            _directory: directory,
+            config_path,
+            weights_path,
+            vocab_path: vocab_files.vocab_path,
+            merges_path: vocab_files.merges_path,
+            model_type: config.model_type,
+            vocab_size,
+        })
+    }
+
+    /// Returns the configuration file as a resource
+    pub fn config_resource(&self) -> Box<dyn ResourceProvider + Send> {
+        local_resource(&self.config_path)
+    }
+
+    /// Returns the weights file as a resource
+    pub fn model_resource(&self) -> Box<dyn ResourceProvider + Send> {
+        local_resource(&self.weights_path)
+    }
+
+    /// Returns the vocabulary file as a resource (a SentencePiece model for the SentencePiece
+    /// tokenizers, a JSON vocabulary for Marian and M2M100)
+    pub fn vocab_resource(&self) -> Box<dyn ResourceProvider + Send> {
+        local_resource(&self.vocab_path)
+    }
+
+    /// Returns the merges file as a resource for the BPE tokenizers (the SentencePiece model for
+    /// Marian and M2M100), `None` otherwise
+    pub fn merges_resource(&self) -> Option<Box<dyn ResourceProvider + Send>> {
+        self.merges_path.as_deref().map(local_resource)
+    }
+}
+
+/// Runs `f` after seeding the libtorch random number generator, no other seeded section running
+/// concurrently
+pub fn with_seed<T>(seed: i64, f: impl FnOnce() -> T) -> T {
+    let _guard = SEED_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    tch::manual_seed(seed);
+    f()
+}
+
+fn local_resource(path: &Path) -> Box<dyn ResourceProvider + Send> {
+    Box::new(LocalResource {
+        local_path: path.to_path_buf(),
+    })
+}
+
+/// Creates the variables of the model head in the variable store of `p`, with the same paths as
+/// the pipelines and generators loading them
+fn build_head(
+    model_type: ModelType,
+    head: TinyModelHead,
+    p: nn::Path,
+    config: &ConfigOption,
+) -> Result<(), RustBertError> {
+    match head {
+        TinyModelHead::Generation => build_generation_head(model_type, p, config)?,
+        TinyModelHead::QuestionAnswering => {
+            let _ = QuestionAnsweringOption::new(model_type, p, config)?;
+        }
+        TinyModelHead::SequenceClassification => {
+            let _ = SequenceClassificationOption::new(model_type, p, config)?;
+        }
+        TinyModelHead::TokenClassification => {
+            let _ = TokenClassificationOption::new(model_type, p, config)?;
+        }
+        TinyModelHead::MaskedLanguageModel => {
+            let _ = MaskedLanguageOption::new(model_type, p, config)?;
+        }
+    }
+    Ok(())
+}
+
+fn build_generation_head(
+    model_type: ModelType,
+    p: nn::Path,
+    config: &ConfigOption,
+) -> Result<(), RustBertError> {
+    match config {
+        ConfigOption::GPT2(config) => {
+            let _ = crate::gpt2::GPT2LMHeadModel::new(p, config);
+        }
+        ConfigOption::GPTNeo(config) => {
+            let _ = crate::gpt_neo::GptNeoForCausalLM::new(p, config)?;
+        }
+        ConfigOption::OpenAiGpt(config) => {
+            let _ = crate::openai_gpt::OpenAIGPTLMHeadModel::new(p, config);
+        }
+        ConfigOption::XLNet(config) => {
+            let _ = crate::xlnet::XLNetLMHeadModel::new(p, config);
+        }
+        ConfigOption::Reformer(config) => {
+            let _ = crate::reformer::ReformerModelWithLMHead::new(p, config)?;
+        }
+        ConfigOption::Bart(config) => {
+            let _ = crate::bart::BartForConditionalGeneration::new(p, config);
+        }
+        ConfigOption::Marian(config) => {
+            let _ = crate::marian::MarianForConditionalGeneration::new(p, config);
+        }
+        ConfigOption::MBart(config) => {
+            let _ = crate::mbart::MBartForConditionalGeneration::new(p, config);
+        }
+        ConfigOption::M2M100(config) => {
+            let _ = crate::m2m_100::M2M100ForConditionalGeneration::new(p, config);
+        }
+        ConfigOption::Pegasus(config) => {
+            let _ = crate::pegasus::PegasusForConditionalGeneration::new(p, config);
+        }
+        ConfigOption::ProphetNet(config) => {
+            let _ = crate::prophetnet::ProphetNetForConditionalGeneration::new(p, config)?;
+        }
+        ConfigOption::T5(config) => {
+            let _ = crate::t5::T5ForConditionalGeneration::new(p, config);
+        }
+        _ => {
+            return Err(RustBertError::InvalidConfigurationError(format!(
+                "Text generation not implemented for {:?}!",
+                model_type
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn missing_labels() {
+        let config = TinyModelConfig::new(ModelType::Bert, TinyModelHead::SequenceClassification);
+        assert!(matches!(
+            TinyModel::new(config),
+            Err(RustBertError::InvalidConfigurationError(_))
+        ));
+    }
+
+    #[test]
+    fn unsupported_head() {
+        let config = TinyModelConfig::new(ModelType::Bert, TinyModelHead::Generation);
+        assert!(matches!(
+            TinyModel::new(config),
+            Err(RustBertError::InvalidConfigurationError(_))
+        ));
+    }
+}
@@ -0,0 +1,401 @@
+// Copyright 2022 Guillaume Becquin
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//     http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::pipelines::common::ModelType;
+use crate::RustBertError;
+use rust_tokenizers::vocab::{
+    AlbertVocab, BertVocab, DeBERTaV2Vocab, DeBERTaVocab, FNetVocab, Gpt2Vocab, M2M100Vocab,
+    MBart50Vocab, MarianVocab, OpenAiGptVocab, PegasusVocab, ProphetNetVocab, ReformerVocab,
+    RobertaVocab, T5Vocab, Vocab, XLMRobertaVocab, XLNetVocab,
+};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Words added to the synthetic vocabularies, on top of the characters (or bytes) needed to
+/// tokenize any lower-case input
+const WORDS: [&str; 16] = [
+    "the", "a", "is", "of", "and", "to", "in", "it", "was", "rust", "language", "dog", "cat",
+    "what", "where", "who",
+];
+
+/// Marker of the beginning of a word in the SentencePiece vocabularies
+const WORD_START: char = '\u{2581}';
+
+/// Vocabulary files of a tiny model
+pub(crate) struct VocabFiles {
+    pub(crate) vocab_path: PathBuf,
+    pub(crate) merges_path: Option<PathBuf>,
+}
+
+/// Writes the vocabulary files expected by the tokenizer of `model_type` to `directory`
+pub(crate) fn write_vocab(
+    model_type: ModelType,
+    directory: &Path,
+) -> Result<VocabFiles, RustBertError> {
+    match model_type {
+        ModelType::Bert | ModelType::DistilBert | ModelType::Electra | ModelType::MobileBert => {
+            write_word_piece_vocab(directory, &["[PAD]", "[UNK]", "[CLS]", "[SEP]", "[MASK]"])
+        }
+        ModelType::ProphetNet => write_word_piece_vocab(
+            directory,
+            &["[PAD]", "[UNK]", "[CLS]", "[SEP]", "[MASK]", "[X_SEP]"],
+        ),
+        ModelType::GPT2 | ModelType::GPTNeo => {
+            write_byte_level_vocab(directory, &["<|endoftext|>"])
+        }
+        ModelType::Bart | ModelType::Roberta | ModelType::Longformer => {
+            write_byte_level_vocab(directory, &["<s>", "<pad>", "</s>", "<unk>", "<mask>"])
+        }
+        ModelType::Deberta => {
+            write_byte_level_vocab(directory, &["[PAD]", "[CLS]", "[SEP]", "[UNK]", "[MASK]"])
+        }
+        ModelType::OpenAiGpt => write_openai_gpt_vocab(directory),
+        ModelType::T5 => write_sentence_piece_model(directory, &["<pad>", "</s>", "<unk>"]),
+        // The first 3 pieces are replaced by the special tokens of the vocabulary
+        ModelType::XLMRoberta | ModelType::MBart => {
+            write_sentence_piece_model(directory, &["<unk>", "<s>", "</s>"])
+        }
+        ModelType::Albert | ModelType::FNet => {
+            write_sentence_piece_model(directory, &["<pad>", "<unk>", "[CLS]", "[SEP]", "[MASK]"])
+        }
+        ModelType::XLNet => write_sentence_piece_model(
+            directory,
+            &[
+                "<unk>", "<s>", "</s>", "<cls>", "<sep>", "<pad>", "<mask>", "<eod>", "<eop>",
+            ],
+        ),
+        ModelType::Reformer => write_sentence_piece_model(directory, &["<unk>", "<s>", "</s>"]),
+        ModelType::Pegasus => write_sentence_piece_model(directory, &["<pad>", "</s>", "<unk>"]),
+        ModelType::DebertaV2 => {
+            write_sentence_piece_model(directory, &["[PAD]", "[CLS]", "[SEP]", "[UNK]", "[MASK]"])
+        }
+        ModelType::Marian => write_marian_vocab(directory, &["</s>", "<unk>", "<pad>"]),
+        ModelType::M2M100 => write_marian_vocab(directory, &["<s>", "<pad>", "</s>", "<unk>"]),
+    }
+}
+
+/// Loads the vocabulary written for `model_type`, including the special tokens added by the
+/// tokenizer (e.g. the language codes of MBart and M2M100)
+pub(crate) fn vocab_values(
+    model_type: ModelType,
+    vocab_files: &VocabFiles,
+) -> Result<HashMap<String, i64>, RustBertError> {
+    let vocab_path = vocab_files.vocab_path.to_string_lossy();
+    let vocab_path = vocab_path.as_ref();
+    match model_type {
+        ModelType::Bert | ModelType::DistilBert | ModelType::Electra | ModelType::MobileBert => {
+            load_values::<BertVocab>(vocab_path)
+        }
+        ModelType::ProphetNet => load_values::<ProphetNetVocab>(vocab_path),
+        ModelType::GPT2 | ModelType::GPTNeo => load_values::<Gpt2Vocab>(vocab_path),
+        ModelType::Bart | ModelType::Roberta | ModelType::Longformer => {
+            load_values::<RobertaVocab>(vocab_path)
+        }
+        ModelType::Deberta => load_values::<DeBERTaVocab>(vocab_path),
+        ModelType::OpenAiGpt => load_values::<OpenAiGptVocab>(vocab_path),
+        ModelType::T5 => load_values::<T5Vocab>(vocab_path),
+        ModelType::XLMRoberta => load_values::<XLMRobertaVocab>(vocab_path),
+        ModelType::MBart => load_values::<MBart50Vocab>(vocab_path),
+        ModelType::Albert => load_values::<AlbertVocab>(vocab_path),
+        ModelType::FNet => load_values::<FNetVocab>(vocab_path),
+        ModelType::XLNet => load_values::<XLNetVocab>(vocab_path),
+        ModelType::Reformer => load_values::<ReformerVocab>(vocab_path),
+        ModelType::Pegasus => load_values::<PegasusVocab>(vocab_path),
+        ModelType::DebertaV2 => load_values::<DeBERTaV2Vocab>(vocab_path),
+        ModelType::Marian => load_values::<MarianVocab>(vocab_path),
+        ModelType::M2M100 => load_values::<M2M100Vocab>(vocab_path),
+    }
+}
+
+fn load_values<V: Vocab>(vocab_path: &str) -> Result<HashMap<String, i64>, RustBertError> {
+    Ok(V::from_file(vocab_path)?.values().clone())
+}
+
+/// Lower-case letters and digits
+fn alphanumeric_characters() -> Vec<char> {
+    ('a'..='z').chain('0'..='9').collect()
+}
+
+fn ascii_punctuation() -> Vec<char> {
+    (0x21u8..=0x7E)
+        .map(char::from)
+        .filter(|character| character.is_ascii_punctuation())
+        .collect()
+}
+
+fn to_tokens(tokens: &[&str]) -> Vec<String> {
+    tokens.iter().map(|token| token.to_string()).collect()
+}
+
+/// Assigns consecutive ids to the tokens, keeping the first id of duplicated tokens
+fn to_vocab(tokens: Vec<String>) -> HashMap<String, i64> {
+    let mut vocab = HashMap::new();
+    for token in tokens {
+        let next_id = vocab.len() as i64;
+        vocab.entry(token).or_insert(next_id);
+    }
+    vocab
+}
+
+fn deduplicate(values: &mut Vec<String>) {
+    let mut seen_values = HashSet::new();
+    values.retain(|value| seen_values.insert(value.clone()));
+}
+
+/// Adds the merges building `symbols` from left to right, and the intermediate tokens they create
+fn add_merges(symbols: &[String], tokens: &mut Vec<String>, merges: &mut Vec<String>) {
+    let mut prefix = symbols[0].clone();
+    for symbol in &symbols[1..] {
+        merges.push(format!("{} {}", prefix, symbol));
+        prefix.push_str(symbol);
+        tokens.push(prefix.clone());
+    }
+}
+
+fn write_json_vocab(
+    directory: &Path,
+    vocab: &HashMap<String, i64>,
+) -> Result<PathBuf, RustBertError> {
+    let vocab_path = directory.join("vocab.json");
+    fs::write(&vocab_path, serde_json::to_string(vocab)?)?;
+    Ok(vocab_path)
+}
+
+fn write_merges(directory: &Path, merges: &[String]) -> Result<PathBuf, RustBertError> {
+    let merges_path = directory.join("merges.txt");
+    fs::write(
+        &merges_path,
+        format!("#version: 0.2\n{}\n", merges.join("\n")),
+    )?;
+    Ok(merges_path)
+}
+
+/// Vocabulary made of the special tokens, the lower-case letters and digits (also as
+/// continuation pieces), the ASCII punctuation and the `WORDS`
+fn write_word_piece_vocab(
+    directory: &Path,
+    special_tokens: &[&str],
+) -> Result<VocabFiles, RustBertError> {
+    let mut tokens = to_tokens(special_tokens);
+    let characters = alphanumeric_characters();
+    tokens.extend(characters.iter().map(|character| character.to_string()));
+    tokens.extend(
+        characters
+            .iter()
+            .map(|character| format!("##{}", character)),
+    );
+    tokens.extend(
+        ascii_punctuation()
+            .iter()
+            .map(|character| character.to_string()),
+    );
+    tokens.extend(
+        WORDS
+            .iter()
+            .filter(|word| word.len() > 1)
+            .map(|word| word.to_string()),
+    );
+
+    let vocab_path = directory.join("vocab.txt");
+    fs::write(&vocab_path, tokens.join("\n"))?;
+    Ok(VocabFiles {
+        vocab_path,
+        merges_path: None,
+    })
+}
+
+/// Characters used by byte-level BPE tokenizers to represent each of the 256 bytes (printable
+/// characters represent themselves, the other bytes are shifted above 255)
+fn byte_level_alphabet() -> Vec<String> {
+    let mut shift = 0;
+    (0u32..256)
+        .map(|byte| {
+            let printable = (0x21..=0x7E).contains(&byte)
+                || (0xA1..=0xAC).contains(&byte)
+                || (0xAE..=0xFF).contains(&byte);
+            let code_point = if printable {
+                byte
+            } else {
+                shift += 1;
+                255 + shift
+            };
+            char::from_u32(code_point).unwrap().to_string()
+        })
+        .collect()
+}
+
+/// Vocabulary made of the special tokens, the 256 bytes and the (space-prefixed) `WORDS`, with the
+/// merges building these words character by character
+fn write_byte_level_vocab(
+    directory: &Path,
+    special_tokens: &[&str],
+) -> Result<VocabFiles, RustBertError> {
+    let mut tokens = to_tokens(special_tokens);
+    tokens.extend(byte_level_alphabet());
+    let mut merges = vec![];
+    for word in WORDS.iter() {
+        let symbols: Vec<String> = "Ġ"
+            .chars()
+            .chain(word.chars())
+            .map(|character| character.to_string())
+            .collect();
+        add_merges(&symbols, &mut tokens, &mut merges);
+    }
+    deduplicate(&mut merges);
+
+    Ok(VocabFiles {
+        vocab_path: write_json_vocab(directory, &to_vocab(tokens))?,
+        merges_path: Some(write_merges(directory, &merges)?),
+    })
+}
+
+/// Vocabulary of the OpenAI GPT tokenizer, whose BPE marks the last character of each word with
+/// `</w>`: the characters (also as word endings) and the `WORDS`, with their merges
+fn write_openai_gpt_vocab(directory: &Path) -> Result<VocabFiles, RustBertError> {
+    let mut tokens = to_tokens(&["<unk>"]);
+    for character in alphanumeric_characters()
+        .into_iter()
+        .chain(ascii_punctuation())
+    {
+        tokens.push(character.to_string());
+        tokens.push(format!("{}</w>", character));
+    }
+    let mut merges = vec![];
+    for word in WORDS.iter().filter(|word| word.len() > 1) {
+        let mut symbols: Vec<String> = word
+            .chars()
+            .map(|character| character.to_string())
+            .collect();
+        symbols.last_mut().unwrap().push_str("</w>");
+        add_merges(&symbols, &mut tokens, &mut merges);
+    }
+    deduplicate(&mut merges);
+
+    Ok(VocabFiles {
+        vocab_path: write_json_vocab(directory, &to_vocab(tokens))?,
+        merges_path: Some(write_merges(directory, &merges)?),
+    })
+}
+
+/// SentencePiece pieces: the special tokens, the word start marker, the characters and the
+/// prefixes of the (word start prefixed) `WORDS`. The prefixes allow the BPE-based SentencePiece
+/// tokenizers to build the words by merging adjacent pieces.
+fn sentence_pieces(special_tokens: &[&str]) -> Vec<String> {
+    let mut pieces = to_tokens(special_tokens);
+    pieces.push(WORD_START.to_string());
+    pieces.extend(
+        alphanumeric_characters()
+            .into_iter()
+            .chain(ascii_punctuation())
+            .map(|character| character.to_string()),
+    );
+    for word in WORDS.iter() {
+        let mut prefix = WORD_START.to_string();
+        for character in word.chars() {
+            prefix.push(character);
+            pieces.push(prefix.clone());
+        }
+    }
+    deduplicate(&mut pieces);
+    pieces
+}
+
+/// Serializes the pieces as a SentencePiece `ModelProto` protobuf message (only the pieces are
+/// read by the tokenizers). The special tokens have a score of 0 and the other pieces a score of
+/// -1, so that the unigram tokenizers use as few pieces as possible.
+fn sentence_piece_model(pieces: &[String], num_special_tokens: usize) -> Vec<u8> {
+    let mut model = vec![];
+    for (index, piece) in pieces.iter().enumerate() {
+        let score: f32 = if index < num_special_tokens {
+            0.0
+        } else {
+            -1.0
+        };
+        // SentencePiece message: `piece` (field 1, string) and `score` (field 2, float)
+        let mut message = vec![0x0A];
+        write_varint(&mut message, piece.len());
+        message.extend_from_slice(piece.as_bytes());
+        message.push(0x15);
+        message.extend_from_slice(&score.to_le_bytes());
+        // ModelProto message: repeated `pieces` (field 1, message)
+        model.push(0x0A);
+        write_varint(&mut model, message.len());
+        model.extend(message);
+    }
+    model
+}
+
+fn write_varint(buffer: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        buffer.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+fn write_sentence_piece_model(
+    directory: &Path,
+    special_tokens: &[&str],
+) -> Result<VocabFiles, RustBertError> {
+    let pieces = sentence_pieces(special_tokens);
+    let vocab_path = directory.join("spiece.model");
+    fs::write(
+        &vocab_path,
+        sentence_piece_model(&pieces, special_tokens.len()),
+    )?;
+    Ok(VocabFiles {
+        vocab_path,
+        merges_path: None,
+    })
+}
+
+/// Marian and M2M100 tokenize with a SentencePiece model (passed as the merges file) and map the
+/// pieces to ids with a JSON vocabulary
+fn write_marian_vocab(
+    directory: &Path,
+    special_tokens: &[&str],
+) -> Result<VocabFiles, RustBertError> {
+    let pieces = sentence_pieces(special_tokens);
+    let spm_path = directory.join("spiece.model");
+    fs::write(
+        &spm_path,
+        sentence_piece_model(&pieces, special_tokens.len()),
+    )?;
+    Ok(VocabFiles {
+        vocab_path: write_json_vocab(directory, &to_vocab(pieces))?,
+        merges_path: Some(spm_path),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn varint_encoding() {
+        let mut buffer = vec![];
+        write_varint(&mut buffer, 1);
+        write_varint(&mut buffer, 300);
+        assert_eq!(buffer, vec![0x01, 0xAC, 0x02]);
+    }
+
+    #[test]
+    fn sentence_piece_vocabularies() -> anyhow::Result<()> {
+        let directory = tempfile::tempdir()?;
+        let vocab_files = write_vocab(ModelType::T5, directory.path())?;
+        let vocab = vocab_values(ModelType::T5, &vocab_files)?;
+        assert_eq!(vocab["<pad>"], 0);
+        assert_eq!(vocab["</s>"], 1);
+        assert!(vocab.contains_key("\u{2581}language"));
+        Ok(())
+    }
+}
@@ -14,6 +14,9 @@
 
 use crate::common::activations::Activation;
 use crate::common::dropout::Dropout;
+use crate::common::embeddings::load_weights_with_tied_embeddings;
+use crate::common::loading::check_device;
+use crate::common::placement::check_layer_placement_unsupported;
 use crate::common::summary::{SequenceSummary, SummaryConfig, SummaryType};
 use crate::pipelines::common::{ModelType, TokenizerOption};
 use crate::pipelines::generation_utils::private_generation_utils::{
@@ -787,6 +790,7 @@ impl XLNetLMHeadModel {
         Ok(LMModelOutput {
             lm_logits,
             cache: Cache::XLNetCache(base_model_output.next_cache),
+            all_hidden_states: None,
         })
     }
 }
@@ -1644,12 +1648,14 @@ impl XLNetGenerator {
         let weights_path = generate_config.model_resource.get_local_path()?;
         let device = generate_config.device;
 
-        generate_config.validate();
+        generate_config.validate()?;
+        check_layer_placement_unsupported(&generate_config.layer_placement, "XLNet")?;
+        check_device(device)?;
         let mut var_store = nn::VarStore::new(device);
 
-        let config = XLNetConfig::from_file(config_path);
+        let config = XLNetConfig::try_from_file(config_path)?;
         let model = XLNetLMHeadModel::new(&var_store.root(), &config);
-        var_store.load(weights_path)?;
+        load_weights_with_tied_embeddings(&mut var_store, weights_path)?;
 
         let bos_token_id = Some(config.bos_token_id);
         let eos_token_ids = Some(vec![config.eos_token_id]);
@@ -2,6 +2,12 @@ use rust_bert::bart::{
     BartConfig, BartConfigResources, BartMergesResources, BartModel, BartModelResources,
     BartVocabResources,
 };
+use rust_bert::pipelines::attributed_summarization::{
+    AttributedSummarizationConfig, AttributedSummarizationModel,
+};
+use rust_bert::pipelines::headline_generation::{
+    HeadlineGenerationConfig, HeadlineGenerationModel,
+};
 use rust_bert::pipelines::summarization::{SummarizationConfig, SummarizationModel};
 use rust_bert::pipelines::zero_shot_classification::{
     ZeroShotClassificationConfig, ZeroShotClassificationModel,
@@ -126,7 +132,7 @@ telescope — scheduled for launch in 2021 — and the European Space Agency's 2
 about exoplanets like K2-18b."];
 
     //    Credits: WikiNews, CC BY 2.5 license (https://en.wikinews.org/wiki/Astronomers_find_water_vapour_in_atmosphere_of_exoplanet_K2-18b)
-    let output = model.summarize(&input);
+    let output = model.summarize(&input)?;
 
     assert_eq!(output.len(), 1);
     assert_eq!(output[0], " K2-18b is not too hot and not too cold for liquid water to exist. \
@@ -136,6 +142,104 @@ about exoplanets like K2-18b."];
     Ok(())
 }
 
+#[test]
+fn bart_attributed_summarization() -> anyhow::Result<()> {
+    let summarization_config = SummarizationConfig {
+        model_resource: Box::new(RemoteResource::from_pretrained(
+            BartModelResources::DISTILBART_CNN_6_6,
+        )),
+        config_resource: Box::new(RemoteResource::from_pretrained(
+            BartConfigResources::DISTILBART_CNN_6_6,
+        )),
+        vocab_resource: Box::new(RemoteResource::from_pretrained(
+            BartVocabResources::DISTILBART_CNN_6_6,
+        )),
+        merges_resource: Some(Box::new(RemoteResource::from_pretrained(
+            BartMergesResources::DISTILBART_CNN_6_6,
+        ))),
+        num_beams: 1,
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let model = AttributedSummarizationModel::new(AttributedSummarizationConfig {
+        summarization_config,
+        num_sources: 1,
+        ..Default::default()
+    })?;
+
+    let input = ["In findings published Tuesday in Cornell University's arXiv by a team of scientists \
+from the University of Montreal and a separate report published Wednesday in Nature Astronomy by a team \
+from University College London (UCL), the presence of water vapour was confirmed in the atmosphere of K2-18b, \
+a planet circling a star in the constellation Leo. K2-18b was first identified in 2015 by the Kepler space telescope. \
+It is about 110 light-years from Earth and larger but less dense."];
+    let summaries = [
+        "Water vapour was found in the atmosphere of the planet K2-18b. \
+The stock market fell sharply on Monday.",
+    ];
+
+    let output = model.attribute(&input, &summaries)?;
+
+    assert_eq!(output.len(), 1);
+    assert_eq!(output[0].len(), 2);
+    assert!(output[0][0].is_supported());
+    assert_eq!(output[0][0].sources.len(), 1);
+    assert!(output[0][0].sources[0]
+        .sentence
+        .text
+        .contains("the presence of water vapour was confirmed"));
+    assert_eq!(output[0][0].sources[0].sentence.offset.begin, 0);
+    assert!(!output[0][1].is_supported());
+
+    Ok(())
+}
+
+#[test]
+fn bart_headline_generation() -> anyhow::Result<()> {
+    let summarization_config = SummarizationConfig {
+        model_resource: Box::new(RemoteResource::from_pretrained(
+            BartModelResources::DISTILBART_CNN_6_6,
+        )),
+        config_resource: Box::new(RemoteResource::from_pretrained(
+            BartConfigResources::DISTILBART_CNN_6_6,
+        )),
+        vocab_resource: Box::new(RemoteResource::from_pretrained(
+            BartVocabResources::DISTILBART_CNN_6_6,
+        )),
+        merges_resource: Some(Box::new(RemoteResource::from_pretrained(
+            BartMergesResources::DISTILBART_CNN_6_6,
+        ))),
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let model = HeadlineGenerationModel::new(HeadlineGenerationConfig {
+        summarization_config,
+        max_new_tokens: 16,
+        ..Default::default()
+    })?;
+
+    let input = [
+        "In findings published Tuesday in Cornell University's arXiv by a team of scientists \
+from the University of Montreal and a separate report published Wednesday in Nature Astronomy by a team \
+from University College London (UCL), the presence of water vapour was confirmed in the atmosphere of K2-18b, \
+a planet circling a star in the constellation Leo. This is the first such discovery in a planet in its star's \
+habitable zone — not too hot and not too cold for liquid water to exist.",
+        "Rust is a multi-paradigm, general-purpose programming language. Rust emphasizes performance, \
+type safety, and concurrency. Rust enforces memory safety without requiring the use of a garbage collector.",
+    ];
+
+    let output = model.generate(&input)?;
+
+    assert_eq!(output.len(), 2);
+    for headline in output {
+        assert!(!headline.is_empty());
+        assert!(!headline.contains('\n'));
+        assert!(!headline.ends_with('.'));
+        assert!(headline.split_whitespace().count() <= 16);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn bart_summarization_beam_search() -> anyhow::Result<()> {
     let config_resource = Box::new(RemoteResource::from_pretrained(
@@ -187,7 +291,7 @@ telescope — scheduled for launch in 2021 — and the European Space Agency's 2
 about exoplanets like K2-18b."];
 
     //    Credits: WikiNews, CC BY 2.5 license (https://en.wikinews.org/wiki/Astronomers_find_water_vapour_in_atmosphere_of_exoplanet_K2-18b)
-    let output = model.summarize(&input);
+    let output = model.summarize(&input)?;
 
     assert_eq!(output.len(), 1);
     assert_eq!(output[0], " K2-18b, a planet circling a star in the constellation Leo, is not too hot and not too cold for liquid water to exist. \
@@ -230,6 +334,44 @@ fn bart_zero_shot_classification() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+#[cfg_attr(not(feature = "all-tests"), ignore)]
+fn bart_zero_shot_classification_output_cache() -> anyhow::Result<()> {
+    //    Set-up model
+    let zero_shot_config = ZeroShotClassificationConfig {
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let sequence_classification_model =
+        ZeroShotClassificationModel::new(zero_shot_config)?.with_output_cache(16);
+
+    let input_sentence = "Who are you voting for in 2020?";
+    let input_sequence_2 = "The prime minister has announced a stimulus package which was widely criticized by the opposition.";
+    let candidate_labels = &["politics", "public health", "economy", "sports"];
+
+    let first_output = sequence_classification_model.predict_multilabel(
+        [input_sentence, input_sequence_2],
+        candidate_labels,
+        None,
+        128,
+    )?;
+    // All (premise, hypothesis) pairs are served from the cache
+    let second_output = sequence_classification_model.predict_multilabel(
+        [input_sequence_2, input_sentence],
+        candidate_labels,
+        None,
+        128,
+    )?;
+
+    for (first, second) in first_output[0].iter().zip(second_output[1].iter()) {
+        assert_eq!(first.text, second.text);
+        assert!((first.score - second.score).abs() < 1e-6);
+    }
+    assert_eq!(first_output[0][0].text, "politics");
+    assert!((first_output[0][0].score - 0.9805).abs() < 1e-4);
+    Ok(())
+}
+
 #[test]
 #[cfg_attr(not(feature = "all-tests"), ignore)]
 fn bart_zero_shot_classification_try_error() -> anyhow::Result<()> {
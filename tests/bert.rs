@@ -12,6 +12,7 @@ use rust_bert::pipelines::ner::NERModel;
 use rust_bert::pipelines::question_answering::{
     QaInput, QuestionAnsweringConfig, QuestionAnsweringModel,
 };
+use rust_bert::pipelines::token_classification::TokenClassificationConfig;
 use rust_bert::resources::{RemoteResource, ResourceProvider};
 use rust_bert::Config;
 use rust_tokenizers::tokenizer::{BertTokenizer, MultiThreadedTokenizer, TruncationStrategy};
@@ -447,6 +448,73 @@ fn bert_pre_trained_ner_full_entities() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn bert_pre_trained_ner_entity_spans() -> anyhow::Result<()> {
+    //    Set-up model
+    let ner_model = NERModel::new(Default::default())?;
+
+    //    Define input
+    let input = ["Asked John Smith about Acme Corp", "Let's go to New York!"];
+
+    //    Run model
+    let full_entities = ner_model.predict_full_entities(&input);
+    let output = ner_model.predict_spans(&input);
+
+    assert_eq!(output.len(), 2);
+    assert_eq!(output[0][0].word, "John Smith");
+    assert_eq!(&*output[0][0].label, "PER");
+    assert_eq!(output[1][0].word, "New York");
+    for (spans, entities) in output.iter().zip(full_entities.iter()) {
+        assert_eq!(spans.len(), entities.len());
+        for (span, entity) in spans.iter().zip(entities.iter()) {
+            assert_eq!(span.to_entity().label, entity.label);
+            assert_eq!(span.offset, entity.offset);
+            assert!((span.score - entity.score).abs() < 1e-9);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn bert_pre_trained_ner_long_input() -> anyhow::Result<()> {
+    //    Set-up model
+    let ner_model = NERModel::new(TokenClassificationConfig {
+        window_overlap: Some(64),
+        batch_size: 2,
+        ..Default::default()
+    })?;
+
+    //    Define input longer than the maximum length of the model (512 tokens)
+    let input = ["My name is Amy. I live in Paris. ".repeat(100)];
+
+    //    Run model
+    let output = ner_model.predict(&input);
+
+    // Entities are found over the entire input, without duplicates in the window overlaps
+    assert_eq!(output[0].len(), 200);
+    for (entity_index, entity) in output[0].iter().enumerate() {
+        let (word, label) = if entity_index % 2 == 0 {
+            ("Amy", "I-PER")
+        } else {
+            ("Paris", "I-LOC")
+        };
+        assert_eq!(entity.word, word);
+        assert_eq!(entity.label, label);
+    }
+    assert!(output[0]
+        .windows(2)
+        .all(|entities| entities[0].offset.end <= entities[1].offset.begin));
+
+    assert!(NERModel::new(TokenClassificationConfig {
+        window_overlap: Some(400),
+        ..Default::default()
+    })
+    .is_err());
+
+    Ok(())
+}
+
 #[test]
 fn bert_question_answering() -> anyhow::Result<()> {
     //    Set-up question answering model
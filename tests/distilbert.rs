@@ -3,8 +3,15 @@ use rust_bert::distilbert::{
     DistilBertForTokenClassification, DistilBertModelMaskedLM, DistilBertModelResources,
     DistilBertVocabResources,
 };
+use rust_bert::pipelines::calibration::{Calibrator, TemperatureScaling};
+use rust_bert::pipelines::common::{ModelType, TokenizerOption};
+use rust_bert::pipelines::eval::evaluate_sequence_classification;
 use rust_bert::pipelines::question_answering::{QaInput, QuestionAnsweringModel};
+use rust_bert::pipelines::registry::{ModelSpec, Pipeline, PipelineConfig, Precision, TaskType};
 use rust_bert::pipelines::sentiment::{SentimentModel, SentimentPolarity};
+use rust_bert::pipelines::sequence_classification::{
+    ScoreNormalization, SequenceClassificationModel,
+};
 use rust_bert::resources::{RemoteResource, ResourceProvider};
 use rust_bert::Config;
 use rust_tokenizers::tokenizer::{BertTokenizer, MultiThreadedTokenizer, TruncationStrategy};
@@ -276,3 +283,138 @@ fn distilbert_question_answering() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn distilbert_sequence_classification_added_tokens() -> anyhow::Result<()> {
+    //    Set-up tokenizer with additional tokens
+    let vocab_resource = RemoteResource::from_pretrained(DistilBertVocabResources::DISTIL_BERT);
+    let tokenizer = TokenizerOption::from_file(
+        ModelType::DistilBert,
+        vocab_resource.get_local_path()?.to_str().unwrap(),
+        None,
+        true,
+        None,
+        None,
+    )?
+    .with_added_tokens(&["<product>", "<brand>", "[SEP]"], 30522);
+
+    let tokens = tokenizer.tokenize("This <product> from <brand> is great!");
+    assert_eq!(
+        tokens,
+        ["this", "<product>", "from", "<brand>", "is", "great", "!"]
+    );
+    assert_eq!(
+        tokenizer.convert_tokens_to_ids(&["<product>", "<brand>", "[SEP]"]),
+        [30522, 30523, 102]
+    );
+    assert_eq!(
+        tokenizer.decode(&[2023, 30522, 2003, 2307], true, true),
+        "this <product> is great"
+    );
+
+    //    Set-up classifier with additional tokens
+    let model = SequenceClassificationModel::new(Default::default())?
+        .with_added_tokens(&["<product>", "<brand>"])?;
+
+    let output = model.predict(["This <product> from <brand> is great!"]);
+    assert_eq!(output.len(), 1);
+    assert_eq!(output[0].text, "POSITIVE");
+
+    Ok(())
+}
+
+#[test]
+fn distilbert_sentiment_from_config_file() -> anyhow::Result<()> {
+    //    Write pipeline configuration file
+    let model_spec = ModelSpec::new(
+        ModelType::DistilBert,
+        RemoteResource::from_pretrained(DistilBertModelResources::DISTIL_BERT_SST2),
+        RemoteResource::from_pretrained(DistilBertConfigResources::DISTIL_BERT_SST2),
+        RemoteResource::from_pretrained(DistilBertVocabResources::DISTIL_BERT_SST2),
+        None,
+    );
+    let mut config = serde_json::to_value(PipelineConfig {
+        task: TaskType::Sentiment,
+        model: model_spec,
+        dtype: Precision::Float32,
+        parameters: Default::default(),
+    })?;
+    config["model"]["device"] = "cpu".into();
+    config["parameters"]["lower_case"] = true.into();
+    let config_file = tempfile::Builder::new().suffix(".json").tempfile()?;
+    serde_json::to_writer(config_file.as_file(), &config)?;
+
+    //    Set-up pipeline
+    let pipeline = Pipeline::from_config_file(config_file.path())?;
+    let output = pipeline.predict_json(&serde_json::json!(["This is a great movie!"]))?;
+    assert_eq!(output[0]["polarity"], "Positive");
+
+    //    Unknown parameters are rejected
+    config["parameters"]["unknown_parameter"] = 1.into();
+    let config: PipelineConfig = serde_json::from_value(config)?;
+    assert!(Pipeline::from_config(config).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn distilbert_sequence_classification_evaluation() -> anyhow::Result<()> {
+    //    Set-up classifier
+    let model = SequenceClassificationModel::new(Default::default())?;
+
+    let dataset = [
+        ("Probably my all-time favorite movie, a story of selflessness, sacrifice and dedication to a noble cause.", "POSITIVE"),
+        ("This film tried to be too many things all at once and failed at all of them.", "NEGATIVE"),
+        ("If you like original gut wrenching laughter you will like this movie.", "POSITIVE"),
+    ];
+    let confusion_matrix = evaluate_sequence_classification(&model, &dataset, 2)?;
+
+    assert_eq!(confusion_matrix.total(), 3);
+    assert_eq!(confusion_matrix.count("POSITIVE", "POSITIVE"), 2);
+    assert_eq!(confusion_matrix.count("NEGATIVE", "NEGATIVE"), 1);
+    let report = confusion_matrix.report();
+    assert!((report.accuracy - 1.0).abs() < 1e-9);
+    assert!((report.macro_average.f1 - 1.0).abs() < 1e-9);
+
+    Ok(())
+}
+
+#[test]
+fn distilbert_sequence_classification_calibration() -> anyhow::Result<()> {
+    //    Set-up classifier
+    let model = SequenceClassificationModel::new(Default::default())?;
+
+    let validation_texts = [
+        "Probably my all-time favorite movie, a story of selflessness, sacrifice and dedication to a noble cause.",
+        "This film tried to be too many things all at once and failed at all of them.",
+        "If you like original gut wrenching laughter you will like this movie.",
+        "It was not as bad as I expected.",
+    ];
+    let validation_labels = [1, 0, 1, 1];
+    let logits = model.predict_logits(&validation_texts);
+    assert_eq!(logits.len(), 4);
+    assert_eq!(logits[0].len(), 2);
+
+    let calibrator =
+        TemperatureScaling::fit(&logits, &validation_labels, ScoreNormalization::Softmax)?;
+    let calibrated_scores = calibrator.calibrate_scores(&logits, ScoreNormalization::Softmax);
+    let calibrated_tensor = calibrator.calibrate(
+        &Tensor::of_slice(&logits.concat()).view([4, 2]),
+        ScoreNormalization::Softmax,
+    );
+    assert!((calibrated_tensor.double_value(&[0, 1]) - calibrated_scores[0][1]).abs() < 1e-4);
+
+    let uncalibrated_output = model.predict(&validation_texts);
+    let model = model.with_calibrator(calibrator);
+    let calibrated_output = model.predict(&validation_texts);
+    for (uncalibrated, calibrated) in uncalibrated_output.iter().zip(calibrated_output.iter()) {
+        assert_eq!(uncalibrated.id, calibrated.id);
+        assert!(
+            (calibrated.score - calibrated_scores[calibrated.sentence][calibrated.id as usize])
+                .abs()
+                < 1e-4
+        );
+    }
+
+    Ok(())
+}
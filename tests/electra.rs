@@ -2,6 +2,9 @@ use rust_bert::electra::{
     ElectraConfig, ElectraConfigResources, ElectraDiscriminator, ElectraForMaskedLM,
     ElectraModelResources, ElectraVocabResources,
 };
+use rust_bert::pipelines::replaced_token_detection::{
+    ReplacedTokenDetectionConfig, ReplacedTokenDetectionModel,
+};
 use rust_bert::resources::{RemoteResource, ResourceProvider};
 use rust_bert::Config;
 use rust_tokenizers::tokenizer::{BertTokenizer, MultiThreadedTokenizer, TruncationStrategy};
@@ -157,3 +160,35 @@ fn electra_discriminator() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn electra_replaced_token_detection() -> anyhow::Result<()> {
+    //    Set-up model
+    let config = ReplacedTokenDetectionConfig {
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let model = ReplacedTokenDetectionModel::new(config)?;
+
+    //    Define input
+    let input = [
+        "One Two Three Ten Five Six Seven Eight",
+        "One Two Three Four Five",
+    ];
+
+    //    Run model
+    let output = model.predict(&input)?;
+
+    assert_eq!(output.len(), 2);
+    assert_eq!(output[0].tokens.len(), 8);
+    let flagged = output[0].flagged_tokens().collect::<Vec<_>>();
+    assert_eq!(flagged.len(), 1);
+    assert_eq!(flagged[0].text, "Ten");
+    assert!((flagged[0].score - 0.9489).abs() < 1e-3);
+    assert!((output[0].score - flagged[0].score).abs() < 1e-9);
+    assert!(!output[0].is_acceptable());
+    assert_eq!(output[1].tokens.len(), 5);
+    assert!(output[1].is_acceptable());
+
+    Ok(())
+}
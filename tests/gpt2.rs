@@ -2,17 +2,25 @@ use rust_bert::gpt2::{
     GPT2Generator, GPT2LMHeadModel, Gpt2Config, Gpt2ConfigResources, Gpt2MergesResources,
     Gpt2ModelResources, Gpt2VocabResources,
 };
-use rust_bert::pipelines::common::ModelType;
+use rust_bert::pipelines::common::{ModelType, TokenizerOption};
 use rust_bert::pipelines::conversation::{
-    ConversationConfig, ConversationManager, ConversationModel,
+    ConversationConfig, ConversationGenerationSettings, ConversationManager, ConversationModel,
 };
 use rust_bert::pipelines::generation_utils::{
-    Cache, GenerateConfig, GenerateOptions, LMHeadModel, LanguageGenerator,
+    AttentionSinkConfig, Cache, GenerateConfig, GenerateOptions, LMHeadModel, LanguageGenerator,
+    TruncationSide,
 };
+use rust_bert::pipelines::logits_processor::LogitsProcessor;
+use rust_bert::pipelines::output_filter::{ProfanityFilter, RegexRedaction};
+use rust_bert::pipelines::perplexity::{perplexity, PerplexityConfig, PerplexityModel};
+use rust_bert::pipelines::stopping_criteria::{DecodedTextCriteria, MaxNewTokensCriteria};
+use rust_bert::pipelines::streaming::IncrementalDecoder;
 use rust_bert::pipelines::text_generation::{TextGenerationConfig, TextGenerationModel};
 use rust_bert::resources::{RemoteResource, ResourceProvider};
-use rust_bert::Config;
+use rust_bert::{set_deterministic, Config, RustBertError};
 use rust_tokenizers::tokenizer::{Gpt2Tokenizer, Tokenizer, TruncationStrategy};
+use std::collections::HashMap;
+use std::time::Duration;
 use tch::{nn, Device, Tensor};
 
 #[test]
@@ -131,7 +139,7 @@ fn gpt2_generation_greedy() -> anyhow::Result<()> {
     let model = TextGenerationModel::new(generate_config)?;
 
     let input_context = "The cat";
-    let output = model.generate(&[input_context], None);
+    let output = model.generate(&[input_context], None)?;
 
     assert_eq!(output.len(), 1);
     assert_eq!(output[0], "The cat was found in a field near the town of Keflavik, about 30 miles (48 kilometers) south-east of Moscow.\n\n\n");
@@ -139,6 +147,132 @@ fn gpt2_generation_greedy() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn gpt2_generation_per_call_options() -> anyhow::Result<()> {
+    //    Resources definition
+    let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
+    let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
+    let merges_resource = Box::new(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2));
+    let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
+
+    let generate_config = TextGenerationConfig {
+        model_type: ModelType::GPT2,
+        model_resource,
+        config_resource,
+        vocab_resource,
+        merges_resource: Some(merges_resource),
+        max_length: Some(40),
+        do_sample: false,
+        num_beams: 1,
+        temperature: 1.1,
+        repetition_penalty: 1.1,
+        ..Default::default()
+    };
+    let model = TextGenerationModel::new(generate_config)?;
+
+    let input_context = "The cat";
+    let output = model.generate(&[input_context], None)?;
+    let default_output = model.generate_with_options(&[input_context], None, None)?;
+    assert_eq!(output, default_output);
+
+    // Options override the pipeline configuration for a single call
+    let generate_options = GenerateOptions {
+        max_length: Some(10),
+        ..Default::default()
+    };
+    let short_output =
+        model.generate_with_options(&[input_context], None, Some(generate_options))?;
+    assert!(output[0].starts_with(short_output[0].as_str()));
+    assert!(short_output[0].len() < output[0].len());
+
+    let generate_options = GenerateOptions {
+        num_beams: Some(3),
+        num_return_sequences: Some(2),
+        ..Default::default()
+    };
+    let beam_output =
+        model.generate_with_options(&[input_context], None, Some(generate_options))?;
+    assert_eq!(beam_output.len(), 2);
+    assert_eq!(model.generate(&[input_context], None)?, output);
+
+    Ok(())
+}
+
+#[test]
+fn gpt2_generation_output_filters() -> anyhow::Result<()> {
+    //    Resources definition
+    let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
+    let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
+    let merges_resource = Box::new(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2));
+    let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
+
+    let generate_config = TextGenerationConfig {
+        model_type: ModelType::GPT2,
+        model_resource,
+        config_resource,
+        vocab_resource,
+        merges_resource: Some(merges_resource),
+        max_length: Some(40),
+        do_sample: false,
+        num_beams: 1,
+        temperature: 1.1,
+        repetition_penalty: 1.1,
+        ..Default::default()
+    };
+    let model = TextGenerationModel::new(generate_config)?
+        .with_output_filter(RegexRedaction::new(&[("Keflavik", "[LOC]")])?)
+        .with_output_filter(ProfanityFilter::new(&["cat"])?)
+        .with_output_filter(
+            |outputs: Vec<String>| -> Result<Vec<String>, RustBertError> {
+                Ok(outputs
+                    .into_iter()
+                    .map(|output| output.trim_end().to_string())
+                    .collect())
+            },
+        );
+
+    let output = model.generate(&["The cat"], None)?;
+
+    assert_eq!(output.len(), 1);
+    assert_eq!(output[0], "The *** was found in a field near the town of [LOC], about 30 miles (48 kilometers) south-east of Moscow.");
+
+    Ok(())
+}
+
+#[test]
+fn gpt2_generation_sampling_deterministic() -> anyhow::Result<()> {
+    //    Resources definition
+    let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
+    let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
+    let merges_resource = Box::new(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2));
+    let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
+
+    let generate_config = TextGenerationConfig {
+        model_type: ModelType::GPT2,
+        model_resource,
+        config_resource,
+        vocab_resource,
+        merges_resource: Some(merges_resource),
+        max_length: Some(20),
+        do_sample: true,
+        num_beams: 1,
+        top_k: 50,
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let model = TextGenerationModel::new(generate_config)?;
+    set_deterministic(42);
+
+    let input_context = "The cat";
+    let output_1 = model.generate(&[input_context], None)?;
+    let output_2 = model.generate(&[input_context], None)?;
+
+    assert_eq!(output_1.len(), 1);
+    assert_eq!(output_1, output_2);
+
+    Ok(())
+}
+
 #[test]
 fn gpt2_generation_beam_search() -> anyhow::Result<()> {
     //    Resources definition
@@ -164,7 +298,7 @@ fn gpt2_generation_beam_search() -> anyhow::Result<()> {
     let model = TextGenerationModel::new(generate_config)?;
 
     let input_context = "The dog";
-    let output = model.generate(&[input_context], None);
+    let output = model.generate(&[input_context], None)?;
 
     assert_eq!(output.len(), 3);
     assert_eq!(
@@ -209,7 +343,7 @@ fn gpt2_generation_beam_search_multiple_prompts_without_padding() -> anyhow::Res
 
     let input_context_1 = "The dog";
     let input_context_2 = "The cat";
-    let output = model.generate(&[input_context_1, input_context_2], None);
+    let output = model.generate(&[input_context_1, input_context_2], None)?;
 
     assert_eq!(output.len(), 6);
     assert_eq!(
@@ -265,7 +399,7 @@ fn gpt2_generation_beam_search_multiple_prompts_with_padding() -> anyhow::Result
 
     let input_context_1 = "The dog";
     let input_context_2 = "The cat was";
-    let output = model.generate(&[input_context_1, input_context_2], None);
+    let output = model.generate(&[input_context_1, input_context_2], None)?;
 
     assert_eq!(output.len(), 6);
     assert_eq!(
@@ -323,7 +457,7 @@ fn gpt2_diverse_beam_search_multiple_prompts_with_padding() -> anyhow::Result<()
 
     let input_context_1 = "It was a nice and";
     let input_context_2 = "Language models can generate";
-    let output = model.generate(&[input_context_1, input_context_2], None);
+    let output = model.generate(&[input_context_1, input_context_2], None)?;
 
     assert_eq!(output.len(), 6);
     assert_eq!(
@@ -354,6 +488,40 @@ fn gpt2_diverse_beam_search_multiple_prompts_with_padding() -> anyhow::Result<()
     Ok(())
 }
 
+#[test]
+fn gpt2_diverse_beam_search_invalid_groups() -> anyhow::Result<()> {
+    //    Resources definition
+    let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
+    let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
+    let merges_resource = Box::new(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2));
+    let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
+
+    let generate_config = GenerateConfig {
+        model_resource,
+        config_resource,
+        vocab_resource,
+        merges_resource: Some(merges_resource),
+        do_sample: false,
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let model = GPT2Generator::new(generate_config)?;
+
+    // 5 beams cannot be split in 2 groups
+    let generate_options = GenerateOptions {
+        num_beams: Some(5),
+        num_beam_groups: Some(2),
+        ..Default::default()
+    };
+    let output = model.generate_indices(Some(&["It was a nice and"]), Some(generate_options));
+    assert!(matches!(
+        output,
+        Err(RustBertError::InvalidConfigurationError(_))
+    ));
+
+    Ok(())
+}
+
 #[test]
 fn gpt2_prefix_allowed_token_greedy() -> anyhow::Result<()> {
     //    Resources definition
@@ -402,7 +570,7 @@ fn gpt2_prefix_allowed_token_greedy() -> anyhow::Result<()> {
     let output = model.generate(
         Some(&[input_context_1, input_context_2]),
         Some(generate_options),
-    );
+    )?;
 
     assert_eq!(output.len(), 2);
     assert_eq!(
@@ -420,7 +588,7 @@ fn gpt2_prefix_allowed_token_greedy() -> anyhow::Result<()> {
 }
 
 #[test]
-fn gpt2_bad_tokens_greedy() -> anyhow::Result<()> {
+fn gpt2_beam_search_hypotheses_output() -> anyhow::Result<()> {
     //    Resources definition
     let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
     let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
@@ -428,65 +596,54 @@ fn gpt2_bad_tokens_greedy() -> anyhow::Result<()> {
     let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
 
     let generate_config = GenerateConfig {
-        max_length: Some(36),
+        max_length: Some(20),
         model_resource,
         config_resource,
         vocab_resource,
         merges_resource: Some(merges_resource),
         do_sample: false,
-        num_beams: 1,
+        num_beams: 5,
+        num_return_sequences: 2,
         device: Device::Cpu,
         ..Default::default()
     };
     let model = GPT2Generator::new(generate_config)?;
 
-    let bad_words = vec![" honeybees", " a writer"];
-    let bad_word_ids = model
-        .get_tokenizer()
-        .encode_list(
-            bad_words.as_slice(),
-            512,
-            &TruncationStrategy::DoNotTruncate,
-            0,
-        )
-        .into_iter()
-        .map(|tokenized_input| tokenized_input.token_ids)
-        .collect::<Vec<Vec<i64>>>();
-
-    let input_context_1 = "Hello, my name is";
+    let input_context_1 = "The dog";
+    let input_context_2 = "The cat was";
 
-    let baseline_generate_options = GenerateOptions {
-        output_scores: true,
-        ..Default::default()
-    };
-    let test_generate_options = GenerateOptions {
-        bad_word_ids: Some(&bad_word_ids),
+    let generate_options = GenerateOptions {
         output_scores: true,
+        output_beam_hypotheses: true,
         ..Default::default()
     };
 
-    let baseline_output = model.generate(Some(&[input_context_1]), Some(baseline_generate_options));
-    let output = model.generate(Some(&[input_context_1]), Some(test_generate_options));
+    let output = model.generate(
+        Some(&[input_context_1, input_context_2]),
+        Some(generate_options),
+    )?;
 
-    assert_eq!(baseline_output.len(), 1);
-    assert_eq!(
-        baseline_output[0].text,
-        "Hello, my name is John. I'm a writer, and I'm writing a book. I've been writing for a long time. I was born in New York City,"
-    );
-    assert!((baseline_output[0].score.unwrap() - (-1.3316)).abs() < 1e-4);
+    assert_eq!(output.len(), 4);
+    for (sequence_index, sequence) in output.iter().enumerate() {
+        let hypotheses = sequence.beam_hypotheses.as_ref().unwrap();
+        assert_eq!(hypotheses.len(), 5);
+        assert!(hypotheses
+            .windows(2)
+            .all(|pair| pair[0].score.unwrap() >= pair[1].score.unwrap()));
+        //    The returned sequences are the best hypotheses of the beam
+        let rank = sequence_index % 2;
+        assert_eq!(hypotheses[rank].text, sequence.text);
+        assert!((hypotheses[rank].score.unwrap() - sequence.score.unwrap()).abs() < 1e-6);
+    }
 
-    assert_eq!(output.len(), 1);
-    assert_eq!(
-        output[0].text,
-        "Hello, my name is John. I'm a student at the University of California, Berkeley. I've been studying computer science for a year. I have a PhD in computer science"
-    );
-    assert!((output[0].score.unwrap() - (-1.2307)).abs() < 1e-4);
+    let output = model.generate(Some(&[input_context_1]), None)?;
+    assert!(output[0].beam_hypotheses.is_none());
 
     Ok(())
 }
 
 #[test]
-fn gpt2_bad_tokens_beam_search() -> anyhow::Result<()> {
+fn gpt2_dola_decoding() -> anyhow::Result<()> {
     //    Resources definition
     let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
     let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
@@ -494,130 +651,77 @@ fn gpt2_bad_tokens_beam_search() -> anyhow::Result<()> {
     let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
 
     let generate_config = GenerateConfig {
-        max_length: Some(36),
+        max_length: Some(20),
         model_resource,
         config_resource,
         vocab_resource,
         merges_resource: Some(merges_resource),
         do_sample: false,
-        num_beams: 3,
+        num_beams: 1,
+        dola_layers: Some(vec![0, 2, 4, 6]),
         device: Device::Cpu,
         ..Default::default()
     };
     let model = GPT2Generator::new(generate_config)?;
 
-    let bad_words = vec![" honeybees", " a member"];
-    let bad_word_ids = model
-        .get_tokenizer()
-        .encode_list(
-            bad_words.as_slice(),
-            512,
-            &TruncationStrategy::DoNotTruncate,
-            0,
-        )
-        .into_iter()
-        .map(|tokenized_input| tokenized_input.token_ids)
-        .collect::<Vec<Vec<i64>>>();
+    let input_context_1 = "The capital of France is";
+    let input_context_2 = "The dog";
 
-    let input_context_1 = "Hello, my name is";
+    let output = model.generate(Some(&[input_context_1, input_context_2]), None)?;
 
-    let baseline_generate_options = GenerateOptions {
-        output_scores: true,
-        ..Default::default()
-    };
-    let test_generate_options = GenerateOptions {
-        bad_word_ids: Some(&bad_word_ids),
-        output_scores: true,
+    assert_eq!(output.len(), 2);
+    assert!(output[0].text.starts_with(input_context_1));
+    assert!(output[0].text.len() > input_context_1.len());
+    assert!(output[1].text.starts_with(input_context_2));
+    assert!(output[1].text.len() > input_context_2.len());
+
+    //    Premature layers must exist in the model
+    let generate_config = GenerateConfig {
+        dola_layers: Some(vec![12]),
+        device: Device::Cpu,
         ..Default::default()
     };
-
-    let baseline_output = model.generate(Some(&[input_context_1]), Some(baseline_generate_options));
-    let output = model.generate(Some(&[input_context_1]), Some(test_generate_options));
-
-    assert_eq!(baseline_output.len(), 1);
-    assert_eq!(
-        baseline_output[0].text,
-        "Hello, my name is John, and I am a member of the Church of Jesus Christ of Latter-day Saints.\n\nI am a Mormon. I have been a member"
-    );
-    assert!((baseline_output[0].score.unwrap() - (-1.0503)).abs() < 1e-4);
-
-    assert_eq!(output.len(), 1);
-    assert_eq!(
-        output[0].text,
-        "Hello, my name is John, and I am the owner of a small business.\n\nI have been in business for over 20 years. I have been a business owner for"
-    );
-    assert!((output[0].score.unwrap() - (-1.3742)).abs() < 1e-4);
+    assert!(GPT2Generator::new(generate_config).is_err());
 
     Ok(())
 }
 
 #[test]
-fn gpt2_prefix_allowed_token_beam_search() -> anyhow::Result<()> {
+fn gpt2_prompt_truncation() -> anyhow::Result<()> {
     //    Resources definition
     let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
     let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
     let merges_resource = Box::new(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2));
     let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
 
-    fn force_one_paragraph(_batch_id: i64, previous_token_ids: &Tensor) -> Vec<i64> {
-        let paragraph_tokens = [198, 628];
-
-        for paragraph_token in paragraph_tokens.iter() {
-            if previous_token_ids
-                .iter::<i64>()
-                .unwrap()
-                .any(|x| x == *paragraph_token)
-            {
-                return vec![50256];
-            }
-        }
-        (0..50255).collect()
-    }
-
     let generate_config = GenerateConfig {
-        max_length: Some(32),
+        max_length: Some(8),
         model_resource,
         config_resource,
         vocab_resource,
         merges_resource: Some(merges_resource),
         do_sample: false,
-        num_beams: 3,
+        num_beams: 1,
+        max_prompt_length: Some(3),
+        prompt_truncation_side: TruncationSide::Left,
         device: Device::Cpu,
         ..Default::default()
     };
     let model = GPT2Generator::new(generate_config)?;
 
-    let input_context_1 = "Rust is a";
-    let input_context_2 = "There was a ";
-
-    let generate_options = GenerateOptions {
-        prefix_allowed_tokens_fn: Some(&force_one_paragraph),
-        output_scores: true,
-        ..Default::default()
-    };
-
-    let output = model.generate(
-        Some(&[input_context_1, input_context_2]),
-        Some(generate_options),
-    );
+    //    "Hello world, how are you" is encoded as [15496, 995, 11, 703, 389, 345]
+    let input_context = "Hello world, how are you";
+    let output = model.generate_indices(Some(&[input_context]), None)?;
 
-    assert_eq!(output.len(), 2);
-    assert_eq!(
-        output[0].text,
-        "Rust is a simple, fast, and easy-to-use framework for building web applications. It is designed to be easy to use and maintain, and"
-    );
-    assert!((output[0].score.unwrap() - (-1.2750)).abs() < 1e-4);
-    assert_eq!(
-        output[1].text,
-        "There was a urn in the back of the room, and I was sitting on it, and it looked like it was going to explode. And then I"
-    );
-    assert!((output[1].score.unwrap() - (-1.3326)).abs() < 1e-4);
+    assert_eq!(output.len(), 1);
+    assert_eq!(output[0].indices.len(), 8);
+    assert_eq!(output[0].indices[..3], [703, 389, 345]);
 
     Ok(())
 }
 
 #[test]
-fn gpt2_greedy_token_scores() -> anyhow::Result<()> {
+fn gpt2_batched_generation_with_eos_padding() -> anyhow::Result<()> {
     //    Resources definition
     let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
     let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
@@ -625,30 +729,303 @@ fn gpt2_greedy_token_scores() -> anyhow::Result<()> {
     let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
 
     let generate_config = GenerateConfig {
-        max_length: Some(16),
+        max_length: Some(24),
         model_resource,
         config_resource,
         vocab_resource,
         merges_resource: Some(merges_resource),
         do_sample: false,
         num_beams: 1,
+        pad_token_id: Some(50256),
         device: Device::Cpu,
         ..Default::default()
     };
     let model = GPT2Generator::new(generate_config)?;
 
-    let input_context_1 = "Hello, my name is";
-    let input_context_2 = "It is a beautiful";
+    let input_context_1 = "The dog";
+    let input_context_2 = "<|endoftext|>The cat was sitting on the";
 
-    let generate_options = GenerateOptions {
-        output_scores: true,
-        ..Default::default()
-    };
+    let batched_output = model.generate(Some(&[input_context_1, input_context_2]), None)?;
+    let single_output_1 = model.generate(Some(&[input_context_1]), None)?;
+    let single_output_2 = model.generate(Some(&[input_context_2]), None)?;
 
-    let output = model.generate_indices(
+    //    Padding does not affect the generation, and the EOS token of the prompt is attended
+    assert_eq!(batched_output.len(), 2);
+    assert_eq!(batched_output[0].text, single_output_1[0].text);
+    assert_eq!(batched_output[1].text, single_output_2[0].text);
+
+    Ok(())
+}
+
+#[test]
+fn gpt2_batched_generation_with_empty_prompt() -> anyhow::Result<()> {
+    //    Resources definition
+    let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
+    let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
+    let merges_resource = Box::new(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2));
+    let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
+
+    let generate_config = GenerateConfig {
+        max_length: Some(24),
+        model_resource,
+        config_resource,
+        vocab_resource,
+        merges_resource: Some(merges_resource),
+        do_sample: false,
+        num_beams: 1,
+        pad_token_id: Some(50256),
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let model = GPT2Generator::new(generate_config)?;
+
+    let input_context_1 = "";
+    let input_context_2 = "The dog";
+
+    let batched_output = model.generate(Some(&[input_context_1, input_context_2]), None)?;
+    let single_output_1 = model.generate::<&str>(None, None)?;
+    let single_output_2 = model.generate(Some(&[input_context_2]), None)?;
+
+    //    The empty prompt is seeded with the BOS token and masked independently of the other rows
+    assert_eq!(batched_output.len(), 2);
+    assert_eq!(batched_output[0].text, single_output_1[0].text);
+    assert_eq!(batched_output[1].text, single_output_2[0].text);
+
+    Ok(())
+}
+
+#[test]
+fn gpt2_bad_tokens_greedy() -> anyhow::Result<()> {
+    //    Resources definition
+    let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
+    let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
+    let merges_resource = Box::new(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2));
+    let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
+
+    let generate_config = GenerateConfig {
+        max_length: Some(36),
+        model_resource,
+        config_resource,
+        vocab_resource,
+        merges_resource: Some(merges_resource),
+        do_sample: false,
+        num_beams: 1,
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let model = GPT2Generator::new(generate_config)?;
+
+    let bad_words = vec![" honeybees", " a writer"];
+    let bad_word_ids = model
+        .get_tokenizer()
+        .encode_list(
+            bad_words.as_slice(),
+            512,
+            &TruncationStrategy::DoNotTruncate,
+            0,
+        )
+        .into_iter()
+        .map(|tokenized_input| tokenized_input.token_ids)
+        .collect::<Vec<Vec<i64>>>();
+
+    let input_context_1 = "Hello, my name is";
+
+    let baseline_generate_options = GenerateOptions {
+        output_scores: true,
+        ..Default::default()
+    };
+    let test_generate_options = GenerateOptions {
+        bad_word_ids: Some(&bad_word_ids),
+        output_scores: true,
+        ..Default::default()
+    };
+
+    let baseline_output =
+        model.generate(Some(&[input_context_1]), Some(baseline_generate_options))?;
+    let output = model.generate(Some(&[input_context_1]), Some(test_generate_options))?;
+
+    assert_eq!(baseline_output.len(), 1);
+    assert_eq!(
+        baseline_output[0].text,
+        "Hello, my name is John. I'm a writer, and I'm writing a book. I've been writing for a long time. I was born in New York City,"
+    );
+    assert!((baseline_output[0].score.unwrap() - (-1.3316)).abs() < 1e-4);
+
+    assert_eq!(output.len(), 1);
+    assert_eq!(
+        output[0].text,
+        "Hello, my name is John. I'm a student at the University of California, Berkeley. I've been studying computer science for a year. I have a PhD in computer science"
+    );
+    assert!((output[0].score.unwrap() - (-1.2307)).abs() < 1e-4);
+
+    Ok(())
+}
+
+#[test]
+fn gpt2_bad_tokens_beam_search() -> anyhow::Result<()> {
+    //    Resources definition
+    let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
+    let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
+    let merges_resource = Box::new(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2));
+    let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
+
+    let generate_config = GenerateConfig {
+        max_length: Some(36),
+        model_resource,
+        config_resource,
+        vocab_resource,
+        merges_resource: Some(merges_resource),
+        do_sample: false,
+        num_beams: 3,
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let model = GPT2Generator::new(generate_config)?;
+
+    let bad_words = vec![" honeybees", " a member"];
+    let bad_word_ids = model
+        .get_tokenizer()
+        .encode_list(
+            bad_words.as_slice(),
+            512,
+            &TruncationStrategy::DoNotTruncate,
+            0,
+        )
+        .into_iter()
+        .map(|tokenized_input| tokenized_input.token_ids)
+        .collect::<Vec<Vec<i64>>>();
+
+    let input_context_1 = "Hello, my name is";
+
+    let baseline_generate_options = GenerateOptions {
+        output_scores: true,
+        ..Default::default()
+    };
+    let test_generate_options = GenerateOptions {
+        bad_word_ids: Some(&bad_word_ids),
+        output_scores: true,
+        ..Default::default()
+    };
+
+    let baseline_output =
+        model.generate(Some(&[input_context_1]), Some(baseline_generate_options))?;
+    let output = model.generate(Some(&[input_context_1]), Some(test_generate_options))?;
+
+    assert_eq!(baseline_output.len(), 1);
+    assert_eq!(
+        baseline_output[0].text,
+        "Hello, my name is John, and I am a member of the Church of Jesus Christ of Latter-day Saints.\n\nI am a Mormon. I have been a member"
+    );
+    assert!((baseline_output[0].score.unwrap() - (-1.0503)).abs() < 1e-4);
+
+    assert_eq!(output.len(), 1);
+    assert_eq!(
+        output[0].text,
+        "Hello, my name is John, and I am the owner of a small business.\n\nI have been in business for over 20 years. I have been a business owner for"
+    );
+    assert!((output[0].score.unwrap() - (-1.3742)).abs() < 1e-4);
+
+    Ok(())
+}
+
+#[test]
+fn gpt2_prefix_allowed_token_beam_search() -> anyhow::Result<()> {
+    //    Resources definition
+    let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
+    let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
+    let merges_resource = Box::new(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2));
+    let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
+
+    fn force_one_paragraph(_batch_id: i64, previous_token_ids: &Tensor) -> Vec<i64> {
+        let paragraph_tokens = [198, 628];
+
+        for paragraph_token in paragraph_tokens.iter() {
+            if previous_token_ids
+                .iter::<i64>()
+                .unwrap()
+                .any(|x| x == *paragraph_token)
+            {
+                return vec![50256];
+            }
+        }
+        (0..50255).collect()
+    }
+
+    let generate_config = GenerateConfig {
+        max_length: Some(32),
+        model_resource,
+        config_resource,
+        vocab_resource,
+        merges_resource: Some(merges_resource),
+        do_sample: false,
+        num_beams: 3,
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let model = GPT2Generator::new(generate_config)?;
+
+    let input_context_1 = "Rust is a";
+    let input_context_2 = "There was a ";
+
+    let generate_options = GenerateOptions {
+        prefix_allowed_tokens_fn: Some(&force_one_paragraph),
+        output_scores: true,
+        ..Default::default()
+    };
+
+    let output = model.generate(
         Some(&[input_context_1, input_context_2]),
         Some(generate_options),
+    )?;
+
+    assert_eq!(output.len(), 2);
+    assert_eq!(
+        output[0].text,
+        "Rust is a simple, fast, and easy-to-use framework for building web applications. It is designed to be easy to use and maintain, and"
     );
+    assert!((output[0].score.unwrap() - (-1.2750)).abs() < 1e-4);
+    assert_eq!(
+        output[1].text,
+        "There was a urn in the back of the room, and I was sitting on it, and it looked like it was going to explode. And then I"
+    );
+    assert!((output[1].score.unwrap() - (-1.3326)).abs() < 1e-4);
+
+    Ok(())
+}
+
+#[test]
+fn gpt2_greedy_token_scores() -> anyhow::Result<()> {
+    //    Resources definition
+    let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
+    let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
+    let merges_resource = Box::new(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2));
+    let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
+
+    let generate_config = GenerateConfig {
+        max_length: Some(16),
+        model_resource,
+        config_resource,
+        vocab_resource,
+        merges_resource: Some(merges_resource),
+        do_sample: false,
+        num_beams: 1,
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let model = GPT2Generator::new(generate_config)?;
+
+    let input_context_1 = "Hello, my name is";
+    let input_context_2 = "It is a beautiful";
+
+    let generate_options = GenerateOptions {
+        output_scores: true,
+        ..Default::default()
+    };
+
+    let output = model.generate_indices(
+        Some(&[input_context_1, input_context_2]),
+        Some(generate_options),
+    )?;
 
     assert_eq!(output.len(), 2);
     assert_eq!(
@@ -687,43 +1064,337 @@ fn gpt2_beam_search_token_scores() -> anyhow::Result<()> {
         vocab_resource,
         merges_resource: Some(merges_resource),
         do_sample: false,
-        num_beams: 2,
+        num_beams: 2,
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let model = GPT2Generator::new(generate_config)?;
+
+    let input_context_1 = "Hello, my name is";
+    let input_context_2 = "It is a beautiful";
+
+    let generate_options = GenerateOptions {
+        output_scores: true,
+        ..Default::default()
+    };
+
+    let output = model.generate_indices(
+        Some(&[input_context_1, input_context_2]),
+        Some(generate_options),
+    )?;
+
+    assert_eq!(output.len(), 2);
+    assert_eq!(
+        output[0].indices,
+        vec![15496, 11, 616, 1438, 318, 1757, 11, 290, 314, 716, 257, 2888, 286, 262, 1578, 1829,]
+    );
+    assert!((output[0].score.unwrap() - (-1.1913)).abs() < 1e-4);
+    assert!((output[0].token_scores.as_ref().unwrap()[0] - (-4.6114)).abs() < 1e-4);
+    assert!((output[0].token_scores.as_ref().unwrap()[1] - (-2.1742)).abs() < 1e-4);
+    assert!((output[0].token_scores.as_ref().unwrap()[2] - (-0.7571)).abs() < 1e-4);
+
+    assert_eq!(
+        output[1].indices,
+        vec![50256, 1026, 318, 257, 4950, 1517, 284, 766, 13, 632, 318, 257, 845, 4950, 1517, 13]
+    );
+    assert!((output[1].score.unwrap() - (-1.1160)).abs() < 1e-4);
+    assert!((output[1].token_scores.as_ref().unwrap()[0] - (-2.6287)).abs() < 1e-4);
+    assert!((output[1].token_scores.as_ref().unwrap()[1] - (-1.3033)).abs() < 1e-4);
+    assert!((output[1].token_scores.as_ref().unwrap()[2] - (-0.6780)).abs() < 1e-4);
+
+    Ok(())
+}
+
+#[test]
+fn gpt2_generate_with_scores() -> anyhow::Result<()> {
+    //    Resources definition
+    let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
+    let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
+    let merges_resource = Box::new(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2));
+    let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
+
+    let generate_config = GenerateConfig {
+        max_length: Some(16),
+        model_resource,
+        config_resource,
+        vocab_resource,
+        merges_resource: Some(merges_resource),
+        do_sample: false,
+        num_beams: 1,
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let model = GPT2Generator::new(generate_config)?;
+
+    let input_context = "Hello, my name is";
+
+    let output = model.generate_with_scores(Some(&[input_context]), None)?;
+
+    assert_eq!(output.len(), 1);
+    assert_eq!(
+        output[0].token_ids,
+        vec![1757, 13, 314, 1101, 257, 6260, 11, 290, 314, 1101, 3597]
+    );
+    assert_eq!(
+        output[0].token_log_probabilities.len(),
+        output[0].token_ids.len()
+    );
+    assert!((output[0].token_log_probabilities[0] - (-4.6114)).abs() < 1e-4);
+    assert!((output[0].token_log_probabilities[1] - (-2.1742)).abs() < 1e-4);
+    assert!((output[0].token_log_probabilities[2] - (-0.7571)).abs() < 1e-4);
+    assert!(
+        (output[0].log_probability - output[0].token_log_probabilities.iter().sum::<f64>()).abs()
+            < 1e-6
+    );
+    assert!((output[0].score - (-1.3794)).abs() < 1e-4);
+
+    let generate_options = GenerateOptions {
+        num_beams: Some(3),
+        num_return_sequences: Some(3),
+        ..Default::default()
+    };
+    let output = model.generate_with_scores(Some(&[input_context]), Some(generate_options))?;
+
+    assert_eq!(output.len(), 3);
+    for sequence in output.iter() {
+        assert_eq!(
+            sequence.token_log_probabilities.len(),
+            sequence.token_ids.len()
+        );
+        assert!(sequence
+            .token_log_probabilities
+            .iter()
+            .all(|&log_probability| log_probability <= 0f64));
+    }
+    assert!(output[0].score >= output[1].score);
+    assert!(output[1].score >= output[2].score);
+
+    Ok(())
+}
+
+#[test]
+fn gpt2_generate_from_ids() -> anyhow::Result<()> {
+    //    Resources definition
+    let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
+    let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
+    let merges_resource = Box::new(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2));
+    let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
+
+    let generate_config = GenerateConfig {
+        max_length: Some(16),
+        model_resource,
+        config_resource,
+        vocab_resource,
+        merges_resource: Some(merges_resource),
+        do_sample: false,
+        num_beams: 1,
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let model = GPT2Generator::new(generate_config)?;
+
+    let input_context = "Hello, my name is";
+    let input_ids = Tensor::of_slice(&[15496i64, 11, 616, 1438, 318]).unsqueeze(0);
+
+    let output = model.generate_from_ids(input_ids, None, None)?;
+    let reference_output = model.generate(Some(&[input_context]), None)?;
+
+    assert_eq!(output.len(), 1);
+    assert_eq!(output[0].text, reference_output[0].text);
+
+    assert!(model
+        .generate_from_ids(Tensor::of_slice(&[15496i64, 11, 616]), None, None)
+        .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn gpt2_seeded_sampling() -> anyhow::Result<()> {
+    //    Resources definition
+    let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
+    let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
+    let merges_resource = Box::new(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2));
+    let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
+
+    let generate_config = GenerateConfig {
+        max_length: Some(24),
+        model_resource,
+        config_resource,
+        vocab_resource,
+        merges_resource: Some(merges_resource),
+        do_sample: true,
+        num_beams: 1,
+        seed: Some(42),
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let model = GPT2Generator::new(generate_config)?;
+
+    let input_context = "The dog";
+
+    let output_1 = model.generate(Some(&[input_context]), None)?;
+    let output_2 = model.generate(Some(&[input_context]), None)?;
+    assert_eq!(output_1[0].text, output_2[0].text);
+
+    let generate_options = GenerateOptions {
+        seed: Some(7),
+        ..Default::default()
+    };
+    let output_3 = model.generate(Some(&[input_context]), Some(generate_options))?;
+    let output_4 = model.generate(Some(&[input_context]), Some(generate_options))?;
+    assert_eq!(output_3[0].text, output_4[0].text);
+
+    Ok(())
+}
+
+#[test]
+fn gpt2_bad_words() -> anyhow::Result<()> {
+    //    Resources definition
+    let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
+    let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
+    let merges_resource = Box::new(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2));
+    let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
+
+    let generate_config = GenerateConfig {
+        max_length: Some(16),
+        model_resource,
+        config_resource,
+        vocab_resource,
+        merges_resource: Some(merges_resource),
+        do_sample: false,
+        num_beams: 1,
+        bad_words: vec!["John".to_string()],
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let model = GPT2Generator::new(generate_config)?;
+
+    let input_context = "Hello, my name is";
+
+    let output = model.generate_indices(Some(&[input_context]), None)?;
+    // " John" (1757) is the first token generated without banned words
+    assert!(!output[0].indices.contains(&1757));
+    let output = model.generate(Some(&[input_context]), None)?;
+    assert!(!output[0].text.contains("John"));
+
+    Ok(())
+}
+
+#[test]
+fn gpt2_forced_bos_eos_tokens() -> anyhow::Result<()> {
+    //    Resources definition
+    let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
+    let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
+    let merges_resource = Box::new(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2));
+    let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
+
+    let generate_config = GenerateConfig {
+        max_length: Some(16),
+        model_resource,
+        config_resource,
+        vocab_resource,
+        merges_resource: Some(merges_resource),
+        do_sample: false,
+        num_beams: 1,
+        forced_eos_token_id: Some(13),
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let model = GPT2Generator::new(generate_config)?;
+
+    let input_context = "Hello, my name is";
+    let generate_options = GenerateOptions {
+        forced_bos_token_id: Some(11),
+        ..Default::default()
+    };
+
+    let output = model.generate_indices(Some(&[input_context]), Some(generate_options))?;
+
+    assert_eq!(output[0].indices.len(), 16);
+    // "," (11) is forced after the 5 prompt tokens, "." (13) at the maximum length
+    assert_eq!(output[0].indices[5], 11);
+    assert_eq!(output[0].indices[15], 13);
+
+    Ok(())
+}
+
+#[test]
+fn gpt2_logit_bias() -> anyhow::Result<()> {
+    //    Resources definition
+    let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
+    let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
+    let merges_resource = Box::new(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2));
+    let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
+
+    let generate_config = GenerateConfig {
+        max_length: Some(16),
+        model_resource,
+        config_resource,
+        vocab_resource,
+        merges_resource: Some(merges_resource),
+        do_sample: false,
+        num_beams: 1,
+        // " John" (1757) is the first token generated without bias
+        logit_bias: vec![(1757, -100.0)].into_iter().collect(),
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let model = GPT2Generator::new(generate_config)?;
+
+    let input_context = "Hello, my name is";
+
+    let output = model.generate_indices(Some(&[input_context]), None)?;
+    assert!(!output[0].indices.contains(&1757));
+
+    // The bias of the generate options overrides the configuration
+    let logit_bias: HashMap<i64, f64> = vec![(11, 100.0)].into_iter().collect();
+    let generate_options = GenerateOptions {
+        logit_bias: Some(&logit_bias),
+        ..Default::default()
+    };
+    let output = model.generate_indices(Some(&[input_context]), Some(generate_options))?;
+    assert_eq!(output[0].indices[5], 11);
+
+    Ok(())
+}
+
+/// Bans a single token at every generation step
+struct BanToken(i64);
+
+impl LogitsProcessor for BanToken {
+    fn process(&self, _input_ids: &Tensor, scores: &mut Tensor, _current_length: i64) {
+        let _ = scores.narrow(1, self.0, 1).fill_(f64::NEG_INFINITY);
+    }
+}
+
+#[test]
+#[cfg_attr(not(feature = "all-tests"), ignore)]
+fn gpt2_custom_logits_processor() -> anyhow::Result<()> {
+    //    Resources definition
+    let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
+    let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
+    let merges_resource = Box::new(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2));
+    let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
+
+    let generate_config = GenerateConfig {
+        max_length: Some(16),
+        model_resource,
+        config_resource,
+        vocab_resource,
+        merges_resource: Some(merges_resource),
+        do_sample: false,
+        num_beams: 1,
+        logits_processors: vec![Box::new(BanToken(1757))],
         device: Device::Cpu,
         ..Default::default()
     };
     let model = GPT2Generator::new(generate_config)?;
 
-    let input_context_1 = "Hello, my name is";
-    let input_context_2 = "It is a beautiful";
-
-    let generate_options = GenerateOptions {
-        output_scores: true,
-        ..Default::default()
-    };
-
-    let output = model.generate_indices(
-        Some(&[input_context_1, input_context_2]),
-        Some(generate_options),
-    );
-
-    assert_eq!(output.len(), 2);
-    assert_eq!(
-        output[0].indices,
-        vec![15496, 11, 616, 1438, 318, 1757, 11, 290, 314, 716, 257, 2888, 286, 262, 1578, 1829,]
-    );
-    assert!((output[0].score.unwrap() - (-1.1913)).abs() < 1e-4);
-    assert!((output[0].token_scores.as_ref().unwrap()[0] - (-4.6114)).abs() < 1e-4);
-    assert!((output[0].token_scores.as_ref().unwrap()[1] - (-2.1742)).abs() < 1e-4);
-    assert!((output[0].token_scores.as_ref().unwrap()[2] - (-0.7571)).abs() < 1e-4);
-
-    assert_eq!(
-        output[1].indices,
-        vec![50256, 1026, 318, 257, 4950, 1517, 284, 766, 13, 632, 318, 257, 845, 4950, 1517, 13]
-    );
-    assert!((output[1].score.unwrap() - (-1.1160)).abs() < 1e-4);
-    assert!((output[1].token_scores.as_ref().unwrap()[0] - (-2.6287)).abs() < 1e-4);
-    assert!((output[1].token_scores.as_ref().unwrap()[1] - (-1.3033)).abs() < 1e-4);
-    assert!((output[1].token_scores.as_ref().unwrap()[2] - (-0.6780)).abs() < 1e-4);
+    let input_context = "Hello, my name is";
+    let output = model.generate_indices(Some(&[input_context]), None)?;
+    // " John" (1757) is the first token generated without processor
+    assert!(!output[0].indices.contains(&1757));
 
     Ok(())
 }
@@ -745,7 +1416,7 @@ fn dialogpt_single_multi_turn_conversation() -> anyhow::Result<()> {
         conversation_manager.create("Going to the movies tonight - any suggestions?");
 
     // Turn 1
-    let output = conversation_model.generate_responses(&mut conversation_manager);
+    let output = conversation_model.generate_responses(&mut conversation_manager)?;
     assert_eq!(output.len(), 1);
     assert_eq!(output.get(&conversation_id).unwrap(), &"The Big Lebowski");
 
@@ -754,12 +1425,12 @@ fn dialogpt_single_multi_turn_conversation() -> anyhow::Result<()> {
         .get(&conversation_id)
         .unwrap()
         .add_user_input("Is it an action movie?");
-    let output = conversation_model.generate_responses(&mut conversation_manager);
+    let output = conversation_model.generate_responses(&mut conversation_manager)?;
     assert_eq!(output.len(), 1);
     assert_eq!(output.get(&conversation_id).unwrap(), &"It\'s a comedy.");
 
     // Turn 3 (no new user input)
-    let output = conversation_model.generate_responses(&mut conversation_manager);
+    let output = conversation_model.generate_responses(&mut conversation_manager)?;
     assert_eq!(output.len(), 0);
 
     Ok(())
@@ -783,7 +1454,7 @@ fn dialogpt_multiple_multi_turn_conversation() -> anyhow::Result<()> {
     let conversation_2_id = conversation_manager.create("What's the last book you have read?");
 
     // Turn 1
-    let output = conversation_model.generate_responses(&mut conversation_manager);
+    let output = conversation_model.generate_responses(&mut conversation_manager)?;
     assert_eq!(output.len(), 2);
     assert_eq!(output.get(&conversation_1_id).unwrap(), &"The Big Lebowski");
     assert_eq!(
@@ -796,17 +1467,60 @@ fn dialogpt_multiple_multi_turn_conversation() -> anyhow::Result<()> {
         .get(&conversation_1_id)
         .unwrap()
         .add_user_input("Is it an action movie?");
-    let output = conversation_model.generate_responses(&mut conversation_manager);
+    let output = conversation_model.generate_responses(&mut conversation_manager)?;
     assert_eq!(output.len(), 1);
     assert_eq!(output.get(&conversation_1_id).unwrap(), &"It\'s a comedy.");
 
     // Turn 3 (no new user input)
-    let output = conversation_model.generate_responses(&mut conversation_manager);
+    let output = conversation_model.generate_responses(&mut conversation_manager)?;
     assert_eq!(output.len(), 0);
 
     Ok(())
 }
 
+#[test]
+fn dialogpt_conversations_with_generation_settings() -> anyhow::Result<()> {
+    //    Set-up conversation model
+    let conversation_config = ConversationConfig {
+        do_sample: false,
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let conversation_model = ConversationModel::new(conversation_config)?;
+
+    // Set-up conversation manager with conversations using different generation settings
+    let mut conversation_manager = ConversationManager::new();
+    let conversation_1_id =
+        conversation_manager.create("Going to the movies tonight - any suggestions?");
+    let conversation_2_id = conversation_manager.create("What's the last book you have read?");
+    conversation_manager
+        .get(&conversation_2_id)
+        .unwrap()
+        .set_generation_settings(ConversationGenerationSettings {
+            max_new_tokens: Some(2),
+            ..Default::default()
+        });
+    let conversation_3_id = conversation_manager.create("Where are you from?");
+    conversation_manager
+        .get(&conversation_3_id)
+        .unwrap()
+        .set_generation_settings(ConversationGenerationSettings {
+            do_sample: Some(true),
+            temperature: Some(0.7),
+            ..Default::default()
+        });
+
+    let output = conversation_model.generate_responses(&mut conversation_manager)?;
+    assert_eq!(output.len(), 3);
+    assert_eq!(output.get(&conversation_1_id).unwrap(), &"The Big Lebowski");
+    assert!(!output.get(&conversation_3_id).unwrap().is_empty());
+    let conversation_2 = conversation_manager.get(&conversation_2_id).unwrap();
+    assert!(conversation_2.history.last().unwrap().len() <= 2);
+    assert_eq!(conversation_2.generated_responses.len(), 1);
+
+    Ok(())
+}
+
 #[test]
 #[cfg_attr(not(feature = "all-tests"), ignore)]
 fn dialogpt_multiple_multi_turn_conversation_with_truncation() -> anyhow::Result<()> {
@@ -827,7 +1541,7 @@ fn dialogpt_multiple_multi_turn_conversation_with_truncation() -> anyhow::Result
     let conversation_2_id = conversation_manager.create("Hello how are you?");
 
     // Turn 1
-    let output = conversation_model.generate_responses(&mut conversation_manager);
+    let output = conversation_model.generate_responses(&mut conversation_manager)?;
     assert_eq!(output.len(), 2);
     assert_eq!(output.get(&conversation_1_id).unwrap(), &"The Big Lebowski");
     assert_eq!(
@@ -845,12 +1559,12 @@ fn dialogpt_multiple_multi_turn_conversation_with_truncation() -> anyhow::Result
         .unwrap()
         .add_user_input("Fine.");
 
-    let output = conversation_model.generate_responses(&mut conversation_manager);
+    let output = conversation_model.generate_responses(&mut conversation_manager)?;
     assert_eq!(output.len(), 2);
     assert_eq!(output.get(&conversation_1_id).unwrap(), &"It\'s a comedy.");
 
     // Turn 3 (no new user input)
-    let output = conversation_model.generate_responses(&mut conversation_manager);
+    let output = conversation_model.generate_responses(&mut conversation_manager)?;
     assert_eq!(output.len(), 0);
 
     Ok(())
@@ -874,7 +1588,7 @@ fn dialogpt_multiple_multi_turn_conversation_with_conversation_deletion() -> any
     let conversation_2_id = conversation_manager.create("What's the last book you have read?");
 
     // Turn 1
-    let output = conversation_model.generate_responses(&mut conversation_manager);
+    let output = conversation_model.generate_responses(&mut conversation_manager)?;
     assert_eq!(output.len(), 2);
     assert_eq!(output.get(&conversation_1_id).unwrap(), &"The Big Lebowski");
     assert_eq!(
@@ -888,7 +1602,7 @@ fn dialogpt_multiple_multi_turn_conversation_with_conversation_deletion() -> any
         .get(&conversation_2_id)
         .unwrap()
         .add_user_input("Why do you recommend it?");
-    let output = conversation_model.generate_responses(&mut conversation_manager);
+    let output = conversation_model.generate_responses(&mut conversation_manager)?;
     assert_eq!(output.len(), 1);
     assert_eq!(
         output.get(&conversation_2_id).unwrap(),
@@ -896,8 +1610,377 @@ fn dialogpt_multiple_multi_turn_conversation_with_conversation_deletion() -> any
     );
 
     // Turn 3 (no new user input)
-    let output = conversation_model.generate_responses(&mut conversation_manager);
+    let output = conversation_model.generate_responses(&mut conversation_manager)?;
     assert_eq!(output.len(), 0);
 
     Ok(())
 }
+
+#[test]
+fn gpt2_incremental_decoding() -> anyhow::Result<()> {
+    //    Set-up tokenizer
+    let vocab_resource = RemoteResource::from_pretrained(Gpt2VocabResources::GPT2);
+    let merges_resource = RemoteResource::from_pretrained(Gpt2MergesResources::GPT2);
+    let tokenizer = TokenizerOption::from_file(
+        ModelType::GPT2,
+        vocab_resource.get_local_path()?.to_str().unwrap(),
+        Some(merges_resource.get_local_path()?.to_str().unwrap()),
+        false,
+        None,
+        None,
+    )?;
+
+    //    Emoji and CJK characters are split across multiple byte-level tokens
+    let text = "Hello 👋, 世界! Streaming works 🎉";
+    let token_ids = tokenizer.convert_tokens_to_ids(&tokenizer.tokenize(text));
+    assert!(token_ids.len() > text.split_whitespace().count());
+
+    let mut decoder = IncrementalDecoder::new(&tokenizer, true);
+    let mut fragments = vec![];
+    for token_id in &token_ids {
+        if let Some(fragment) = decoder.push(*token_id) {
+            assert!(!fragment.contains('\u{FFFD}'));
+            fragments.push(fragment);
+        }
+    }
+    fragments.extend(decoder.finish());
+
+    assert!(fragments.len() > 1);
+    assert_eq!(fragments.concat(), text);
+    assert_eq!(decoder.token_ids(), token_ids.as_slice());
+
+    Ok(())
+}
+
+#[test]
+fn gpt2_perplexity() -> anyhow::Result<()> {
+    //    Set-up model
+    let generate_config = TextGenerationConfig {
+        model_type: ModelType::GPT2,
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let model = TextGenerationModel::new(generate_config)?;
+
+    let corpus = [
+        "The dog ran across the park to fetch the ball.",
+        "Rust is a multi-paradigm, general-purpose programming language designed for performance and safety.",
+    ];
+    let output = perplexity(&model, corpus, &PerplexityConfig::default())?;
+    assert!(output.perplexity.is_finite());
+    assert!(output.perplexity > 1.0);
+    assert_eq!(
+        output.num_tokens,
+        corpus
+            .iter()
+            .map(|text| model.perplexity_tokenizer().tokenize(text).len() - 1)
+            .sum::<usize>()
+    );
+
+    //    Sliding windows score every token once, with a shorter context
+    let windowed_config = PerplexityConfig {
+        max_length: Some(8),
+        stride: 4,
+        batch_size: 2,
+    };
+    let windowed_output = perplexity(&model, corpus, &windowed_config)?;
+    assert_eq!(windowed_output.num_tokens, output.num_tokens);
+    assert!(windowed_output.perplexity > output.perplexity);
+
+    //    Unlikely text has a higher perplexity
+    let shuffled_output = perplexity(
+        &model,
+        ["ball fetch the to park across ran dog the ."],
+        &PerplexityConfig::default(),
+    )?;
+    assert!(shuffled_output.perplexity > output.perplexity);
+
+    Ok(())
+}
+
+#[test]
+fn gpt2_generation_budget() -> anyhow::Result<()> {
+    //    Resources definition
+    let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
+    let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
+    let merges_resource = Box::new(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2));
+    let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
+
+    let generate_config = GenerateConfig {
+        max_length: Some(36),
+        model_resource,
+        config_resource,
+        vocab_resource,
+        merges_resource: Some(merges_resource),
+        do_sample: false,
+        num_beams: 1,
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let model = GPT2Generator::new(generate_config)?;
+    let prompts = ["Rust is a", "There was a "];
+
+    let output = model.generate_indices(Some(&prompts), None)?;
+    assert!(output.iter().all(|sequence| !sequence.truncated));
+    let full_length = output[0].indices.len();
+
+    // The token budget is shared by the 2 sequences of the call
+    let generate_options = GenerateOptions {
+        max_total_new_tokens: Some(10),
+        ..Default::default()
+    };
+    let output = model.generate_indices(Some(&prompts), Some(generate_options))?;
+    assert_eq!(output.len(), 2);
+    assert!(output.iter().all(|sequence| sequence.truncated));
+    assert!(output[0].indices.len() < full_length);
+
+    let generate_options = GenerateOptions {
+        max_time: Some(Duration::from_millis(0)),
+        num_beams: Some(3),
+        ..Default::default()
+    };
+    let output = model.generate(Some(&prompts), Some(generate_options))?;
+    assert_eq!(output.len(), 2);
+    assert!(output.iter().all(|sequence| sequence.truncated));
+
+    Ok(())
+}
+
+#[test]
+fn gpt2_stopping_criteria() -> anyhow::Result<()> {
+    //    Resources definition
+    let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
+    let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
+    let merges_resource = Box::new(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2));
+    let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
+
+    let generate_config = GenerateConfig {
+        max_length: Some(36),
+        model_resource,
+        config_resource,
+        vocab_resource,
+        merges_resource: Some(merges_resource),
+        do_sample: false,
+        num_beams: 1,
+        stopping_criteria: vec![
+            Box::new(MaxNewTokensCriteria::new(4)),
+            Box::new(DecodedTextCriteria::new(|texts: &[String]| {
+                texts.iter().all(|text| text.contains("language"))
+            })),
+        ],
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let model = GPT2Generator::new(generate_config)?;
+    let prompt = "Rust is a";
+    let prompt_length = model.get_tokenizer().tokenize(prompt).len();
+
+    let output = model.generate_indices(Some(&[prompt]), None)?;
+    assert!(output[0].truncated);
+    assert!(output[0].indices.len() <= prompt_length + 4);
+
+    Ok(())
+}
+
+#[test]
+fn gpt2_chunked_prefill() -> anyhow::Result<()> {
+    //    Resources definition
+    let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
+    let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
+    let merges_resource = Box::new(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2));
+    let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
+
+    let generate_config = GenerateConfig {
+        max_length: Some(48),
+        model_resource,
+        config_resource,
+        vocab_resource,
+        merges_resource: Some(merges_resource),
+        do_sample: false,
+        num_beams: 1,
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let model = GPT2Generator::new(generate_config)?;
+    let prompts = [
+        "The Rust programming language was designed for performance and safety, \
+        especially safe concurrency. It is syntactically similar to C++ and",
+        "There was a",
+    ];
+
+    let output = model.generate_indices(Some(&prompts), None)?;
+
+    let progress = std::cell::RefCell::new(vec![]);
+    let progress_fn = |processed: i64, total: i64| progress.borrow_mut().push((processed, total));
+    for num_beams in [1, 3] {
+        let generate_options = GenerateOptions {
+            num_beams: Some(num_beams),
+            prefill_chunk_size: Some(8),
+            prefill_progress_fn: Some(&progress_fn),
+            ..Default::default()
+        };
+        let chunked_output = model.generate_indices(Some(&prompts), Some(generate_options))?;
+        if num_beams == 1 {
+            assert_eq!(chunked_output[0].indices, output[0].indices);
+            assert_eq!(chunked_output[1].indices, output[1].indices);
+        }
+    }
+    let progress = progress.into_inner();
+    let prompt_length = progress[0].1;
+    assert!(progress.len() > 2);
+    assert_eq!(progress[0].0, 8);
+    assert!(progress
+        .iter()
+        .any(|&(processed, total)| processed == prompt_length - 1 && total == prompt_length));
+
+    Ok(())
+}
+
+#[test]
+fn gpt2_attention_sink() -> anyhow::Result<()> {
+    //    Resources definition
+    let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
+    let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
+    let merges_resource = Box::new(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2));
+    let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
+
+    let generate_config = GenerateConfig {
+        max_length: Some(1100),
+        min_length: 1100,
+        model_resource,
+        config_resource,
+        vocab_resource,
+        merges_resource: Some(merges_resource),
+        do_sample: false,
+        num_beams: 1,
+        no_repeat_ngram_size: 0,
+        attention_sink: Some(AttentionSinkConfig {
+            num_sink_tokens: 4,
+            window_size: 60,
+        }),
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let model = GPT2Generator::new(generate_config)?;
+    let prompts = ["The dog", "The cat was sleeping on the mat when"];
+
+    // The generation continues past the 1024 positions of GPT2 with a bounded cache
+    let output = model.generate_indices(Some(&prompts), None)?;
+    assert_eq!(output.len(), 2);
+    assert!(output.iter().all(|sequence| sequence.indices.len() == 1100));
+
+    // No cache position is evicted before the cache exceeds the attention sinks and window
+    let generate_options = GenerateOptions {
+        max_length: Some(64),
+        min_length: Some(64),
+        ..Default::default()
+    };
+    let short_output = model.generate_indices(Some(&prompts), Some(generate_options))?;
+    for (sequence, short_sequence) in output.iter().zip(short_output.iter()) {
+        assert_eq!(sequence.indices[..64], short_sequence.indices[..]);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn gpt2_generate_with_callback() -> anyhow::Result<()> {
+    //    Resources definition
+    let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
+    let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
+    let merges_resource = Box::new(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2));
+    let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
+
+    let generate_config = GenerateConfig {
+        max_length: Some(24),
+        model_resource,
+        config_resource,
+        vocab_resource,
+        merges_resource: Some(merges_resource),
+        do_sample: false,
+        num_beams: 1,
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let model = GPT2Generator::new(generate_config)?;
+    let prompts = ["The dog", "The cat was sleeping on the mat when"];
+
+    let mut streamed_text = vec![String::new(); prompts.len()];
+    let output = model.generate_with_callback(Some(&prompts), None, |sequence_index, text| {
+        streamed_text[sequence_index].push_str(text)
+    })?;
+    assert_eq!(output.len(), 2);
+    for (sequence, streamed) in output.iter().zip(streamed_text.iter()) {
+        assert!(!streamed.is_empty());
+        assert!(sequence.text.ends_with(streamed.as_str()));
+    }
+
+    // Streaming is not supported with beam search
+    let generate_options = GenerateOptions {
+        num_beams: Some(3),
+        ..Default::default()
+    };
+    let result = model.generate_with_callback(Some(&prompts), Some(generate_options), |_, _| {});
+    assert!(matches!(
+        result,
+        Err(RustBertError::InvalidConfigurationError(_))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn gpt2_stop_sequences() -> anyhow::Result<()> {
+    //    Resources definition
+    let config_resource = Box::new(RemoteResource::from_pretrained(Gpt2ConfigResources::GPT2));
+    let vocab_resource = Box::new(RemoteResource::from_pretrained(Gpt2VocabResources::GPT2));
+    let merges_resource = Box::new(RemoteResource::from_pretrained(Gpt2MergesResources::GPT2));
+    let model_resource = Box::new(RemoteResource::from_pretrained(Gpt2ModelResources::GPT2));
+
+    let generate_config = GenerateConfig {
+        max_length: Some(40),
+        model_resource,
+        config_resource,
+        vocab_resource,
+        merges_resource: Some(merges_resource),
+        do_sample: false,
+        num_beams: 1,
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let model = GPT2Generator::new(generate_config)?;
+    let prompt = "The cat was sleeping on the mat when";
+
+    let output = model.generate(Some(&[prompt]), None)?;
+    let continuation = &output[0].text[prompt.len()..];
+    // Use a piece of the greedy continuation as stop sequence
+    let stop_sequence = continuation.chars().skip(10).take(3).collect::<String>();
+    let stop_sequences = [stop_sequence.clone()];
+
+    for num_beams in [1, 3] {
+        let generate_options = GenerateOptions {
+            num_beams: Some(num_beams),
+            stop_sequences: Some(&stop_sequences),
+            ..Default::default()
+        };
+        let stopped_output = model.generate(Some(&[prompt]), Some(generate_options))?;
+        let stopped_continuation = &stopped_output[0].text[prompt.len()..];
+        assert!(!stopped_continuation.contains(stop_sequence.as_str()));
+        if num_beams == 1 {
+            assert!(stopped_continuation.len() < continuation.len());
+            assert!(continuation.starts_with(stopped_continuation));
+        }
+    }
+
+    let empty_stop_sequences = [String::new()];
+    let generate_options = GenerateOptions {
+        stop_sequences: Some(&empty_stop_sequences),
+        ..Default::default()
+    };
+    assert!(matches!(
+        model.generate(Some(&[prompt]), Some(generate_options)),
+        Err(RustBertError::InvalidConfigurationError(_))
+    ));
+
+    Ok(())
+}
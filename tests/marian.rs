@@ -72,3 +72,39 @@ fn test_translation_builder() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+// #[cfg_attr(not(feature = "all-tests"), ignore)]
+fn test_translation_n_best() -> anyhow::Result<()> {
+    let model = TranslationModelBuilder::new()
+        .with_device(Device::cuda_if_available())
+        .with_model_type(ModelType::Marian)
+        .with_source_languages(vec![Language::English])
+        .with_target_languages(vec![Language::French])
+        .create_model()?;
+
+    let input_context_1 = "The quick brown fox jumps over the lazy dog";
+    let input_context_2 = "The dog did not wake up";
+
+    let outputs = model.translate_n_best(
+        &[input_context_1, input_context_2],
+        None,
+        Language::French,
+        3,
+    )?;
+
+    assert_eq!(outputs.len(), 2);
+    for hypotheses in &outputs {
+        assert_eq!(hypotheses.len(), 3);
+        assert!(hypotheses
+            .iter()
+            .all(|hypothesis| hypothesis.score.is_some()));
+        assert!(hypotheses
+            .iter()
+            .all(|hypothesis| hypothesis.quality_score.is_none()));
+        assert!(hypotheses[0].score >= hypotheses[2].score);
+    }
+    assert_eq!(outputs[1][0].text, " Le chien ne s'est pas réveillé");
+
+    Ok(())
+}
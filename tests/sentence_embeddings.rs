@@ -1,9 +1,13 @@
 use rust_bert::pipelines::keywords_extraction::{
     KeywordExtractionConfig, KeywordExtractionModel, KeywordScorerType,
 };
+use rust_bert::pipelines::sentence_embeddings::drift::{DriftMonitor, DriftMonitorConfig};
 use rust_bert::pipelines::sentence_embeddings::{
-    SentenceEmbeddingsBuilder, SentenceEmbeddingsConfig, SentenceEmbeddingsModelType,
+    DocumentEmbeddingsConfig, DocumentPooling, SentenceEmbeddingsBuilder, SentenceEmbeddingsConfig,
+    SentenceEmbeddingsEncodeOptions, SentenceEmbeddingsModelType,
 };
+use rust_bert::pipelines::text_splitter::TextSplitterConfig;
+use tch::{Device, Kind};
 
 #[test]
 fn sbert_distilbert() -> anyhow::Result<()> {
@@ -159,6 +163,100 @@ fn sbert_t5() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn sbert_batched_encoding_with_output_options() -> anyhow::Result<()> {
+    let model = SentenceEmbeddingsBuilder::remote(SentenceEmbeddingsModelType::AllMiniLmL6V2)
+        .with_device(Device::Cpu)
+        .create_model()?;
+
+    let sentences = [
+        "This is an example sentence",
+        "Each sentence is converted",
+        "Sentences are encoded by batches",
+    ];
+    let reference = model.encode_as_tensor(&sentences)?.embeddings;
+    let output = model.encode_as_tensor_with_options(
+        &sentences,
+        SentenceEmbeddingsEncodeOptions {
+            batch_size: Some(2),
+            output_device: Some(Device::Cpu),
+            output_kind: Some(Kind::Half),
+        },
+    )?;
+
+    assert!(output.all_attentions.is_none());
+    assert_eq!(output.embeddings.kind(), Kind::Half);
+    assert_eq!(output.embeddings.size(), reference.size());
+    let max_difference = (output.embeddings.to_kind(Kind::Float) - reference)
+        .abs()
+        .max()
+        .double_value(&[]);
+    assert!(max_difference < 1e-2);
+
+    Ok(())
+}
+
+#[test]
+fn sbert_document_embeddings() -> anyhow::Result<()> {
+    let model = SentenceEmbeddingsBuilder::remote(SentenceEmbeddingsModelType::AllMiniLmL6V2)
+        .with_device(Device::Cpu)
+        .create_model()?;
+
+    let short_document = "Rust is a multi-paradigm, general-purpose programming language.";
+    let long_document = [
+        "Rust is a multi-paradigm, general-purpose programming language that emphasizes performance, type safety, and concurrency.",
+        "It enforces memory safety, meaning that all references point to valid memory, without a garbage collector.",
+    ]
+    .repeat(20)
+    .join(" ");
+
+    // Short documents fit in a single chunk and match their sentence embedding
+    let reference = model.encode(&[short_document])?;
+    let document_embeddings =
+        model.encode_documents(&[short_document], &DocumentEmbeddingsConfig::default())?;
+    let max_difference = reference[0]
+        .iter()
+        .zip(document_embeddings[0].iter())
+        .map(|(a, b)| (a - b).abs())
+        .fold(0f32, f32::max);
+    assert!(max_difference < 1e-4);
+
+    for pooling in [
+        DocumentPooling::Mean,
+        DocumentPooling::WeightedByLength,
+        DocumentPooling::Max,
+    ] {
+        let config = DocumentEmbeddingsConfig {
+            text_splitter_config: Some(TextSplitterConfig {
+                chunk_size: 64,
+                chunk_overlap: 8,
+                ..Default::default()
+            }),
+            pooling,
+            batch_size: Some(4),
+        };
+        let embeddings = model.encode_documents(&[long_document.as_str(), ""], &config)?;
+        assert_eq!(embeddings.len(), 2);
+        assert_eq!(embeddings[0].len(), 384);
+        let norm = embeddings[0].iter().map(|v| v * v).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-4);
+    }
+
+    let oversized_chunks = DocumentEmbeddingsConfig {
+        text_splitter_config: Some(TextSplitterConfig {
+            chunk_size: 4096,
+            chunk_overlap: 8,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    assert!(model
+        .encode_documents(&[long_document.as_str()], &oversized_chunks)
+        .is_err());
+
+    Ok(())
+}
+
 #[test]
 fn keyword_extraction_cosine_similarity() -> anyhow::Result<()> {
     let keyword_extraction_config = KeywordExtractionConfig {
@@ -356,3 +454,94 @@ fn keyword_extraction_cosine_similarity_n_grams() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn keyword_extraction_streaming() -> anyhow::Result<()> {
+    let keyword_extraction_config = KeywordExtractionConfig {
+        sentence_embeddings_config: SentenceEmbeddingsConfig::from(
+            SentenceEmbeddingsModelType::AllMiniLmL6V2,
+        ),
+        scorer_type: KeywordScorerType::CosineSimilarity,
+        ngram_range: (1, 1),
+        num_keywords: 5,
+        ..Default::default()
+    };
+
+    let mut keyword_extraction_model = KeywordExtractionModel::new(keyword_extraction_config)?;
+
+    let feed = [
+        "Rust is a multi-paradigm, general-purpose programming language. \
+ Rust emphasizes performance, type safety, and concurrency. Rust enforces memory safety—that is, \
+ that all references point to valid memory—without requiring the use of a garbage collector or \
+ reference counting present in other memory-safe languages. To simultaneously enforce \
+ memory safety and prevent concurrent data races, Rust's borrow checker tracks the object lifetime \
+ and variable scope of all references in a program during compilation. Rust is popular for \
+ systems programming but also offers high-level features including functional programming constructs.",
+        "Machine learning (ML) is a field of inquiry devoted to understanding and building methods \
+ that 'learn', that is, methods that leverage data to improve performance on some set of tasks.\
+ It is seen as a part of artificial intelligence. Machine learning algorithms build a model \
+ based on sample data, known as training data, in order to make predictions or decisions without being explicitly programmed to do so."
+    ];
+
+    let first_keywords = keyword_extraction_model.predict_streaming(&feed[..1])?;
+    let second_keywords = keyword_extraction_model.predict_streaming(&feed[1..])?;
+
+    assert_eq!(
+        keyword_extraction_model.corpus_statistics().num_documents(),
+        2
+    );
+    assert_eq!(
+        keyword_extraction_model
+            .corpus_statistics()
+            .document_frequency("performance"),
+        2
+    );
+    assert_eq!(first_keywords[0].len(), 5);
+    assert_eq!(first_keywords[0][0].text, "rust");
+    assert!((first_keywords[0][0].score - 0.3007).abs() < 1e-3);
+    assert_eq!(second_keywords[0].len(), 5);
+    assert_eq!(second_keywords[0][0].text, "ml");
+    assert!((second_keywords[0][0].score - 0.2745).abs() < 1e-3);
+
+    Ok(())
+}
+
+#[test]
+fn sbert_drift_monitor() -> anyhow::Result<()> {
+    let monitor = DriftMonitor::new(DriftMonitorConfig {
+        window_size: 3,
+        centroid_threshold: 0.2,
+        ..Default::default()
+    })?;
+    let model = SentenceEmbeddingsBuilder::remote(SentenceEmbeddingsModelType::AllMiniLmL6V2)
+        .with_device(Device::Cpu)
+        .create_model()?
+        .with_drift_monitor(monitor);
+
+    // The first window sets the reference distribution
+    let _ = model.encode(&[
+        "The cat sits on the mat.",
+        "A dog is playing in the garden.",
+        "Birds are singing in the trees.",
+    ])?;
+    assert!(model.drift_report().is_none());
+
+    let _ = model.encode(&[
+        "The kitten sleeps on the carpet.",
+        "A puppy runs in the park.",
+        "Birds are flying above the trees.",
+    ])?;
+    let report = model.drift_report().unwrap();
+    assert!(!report.drifted);
+
+    let _ = model.encode(&[
+        "Interest rates were raised by the central bank.",
+        "Inflation reached its highest level in a decade.",
+        "The stock market fell sharply on Monday.",
+    ])?;
+    let drifted_report = model.drift_report().unwrap();
+    assert!(drifted_report.centroid_distance > report.centroid_distance);
+    assert!(drifted_report.drifted);
+
+    Ok(())
+}
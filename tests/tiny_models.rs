@@ -0,0 +1,1810 @@
+use rust_bert::bart::BartGenerator;
+use rust_bert::gpt2::GPT2Generator;
+use rust_bert::gpt_neo::GptNeoGenerator;
+use rust_bert::m2m_100::M2M100Generator;
+use rust_bert::marian::MarianGenerator;
+use rust_bert::mbart::MBartGenerator;
+use rust_bert::openai_gpt::OpenAIGenerator;
+use rust_bert::pegasus::PegasusConditionalGenerator;
+use rust_bert::pipelines::common::{ConfigOption, ModelType, TokenizerOption};
+use rust_bert::pipelines::emotion::{EmotionConfig, EmotionModel};
+use rust_bert::pipelines::generation_utils::{GenerateConfig, GenerateOptions, LanguageGenerator};
+use rust_bert::pipelines::grammar::Grammar;
+use rust_bert::pipelines::multi_task::{
+    MultiTaskConfig, MultiTaskModel, MultiTaskOutput, TaskHeadOutput, TaskHeadType,
+};
+use rust_bert::pipelines::question_answering::{
+    QaInput, QuestionAnsweringConfig, QuestionAnsweringModel, QuestionAnsweringOption,
+};
+use rust_bert::pipelines::registry::{ModelSpec, Pipeline, TaskType};
+use rust_bert::pipelines::sequence_classification::{
+    Label, ScoreNormalization, SequenceClassificationConfig, SequenceClassificationModel,
+    SlidingWindowConfig, WindowAggregation,
+};
+use rust_bert::pipelines::summarization::{SummarizationConfig, SummarizationModel};
+use rust_bert::pipelines::token_classification::{
+    TokenClassificationConfig, TokenClassificationModel,
+};
+use rust_bert::pipelines::toxicity::{
+    SafetyFilter, ToxicityConfig, ToxicityModel, UnsafeOutputAction,
+};
+use rust_bert::pipelines::zero_shot_classification::{
+    ZeroShotClassificationConfig, ZeroShotClassificationModel,
+};
+use rust_bert::prophetnet::ProphetNetConditionalGenerator;
+use rust_bert::reformer::ReformerGenerator;
+use rust_bert::resources::ResourceProvider;
+use rust_bert::t5::T5Generator;
+use rust_bert::test_utils::{with_seed, TinyModel, TinyModelConfig, TinyModelHead};
+use rust_bert::xlnet::XLNetGenerator;
+use rust_bert::{LayerPlacement, RustBertError};
+use rust_tokenizers::tokenizer::TruncationStrategy;
+use std::collections::HashMap;
+use tch::{nn, Device, Kind, Tensor};
+
+const GENERATION_MODEL_TYPES: [ModelType; 12] = [
+    ModelType::GPT2,
+    ModelType::GPTNeo,
+    ModelType::OpenAiGpt,
+    ModelType::XLNet,
+    ModelType::Reformer,
+    ModelType::Bart,
+    ModelType::Marian,
+    ModelType::MBart,
+    ModelType::M2M100,
+    ModelType::Pegasus,
+    ModelType::ProphetNet,
+    ModelType::T5,
+];
+
+//    Reformer is covered separately: its tokenizer has no padding token, required by the question
+//    answering pipeline
+const QUESTION_ANSWERING_MODEL_TYPES: [ModelType; 11] = [
+    ModelType::Bert,
+    ModelType::Deberta,
+    ModelType::DebertaV2,
+    ModelType::DistilBert,
+    ModelType::MobileBert,
+    ModelType::Roberta,
+    ModelType::XLMRoberta,
+    ModelType::Albert,
+    ModelType::XLNet,
+    ModelType::Longformer,
+    ModelType::FNet,
+];
+
+fn tiny_model(
+    model_type: ModelType,
+    head: TinyModelHead,
+    seed: i64,
+    max_position_embeddings: i64,
+    labels: &[&str],
+) -> anyhow::Result<TinyModel> {
+    let config = TinyModelConfig {
+        seed,
+        max_position_embeddings,
+        ..TinyModelConfig::new(model_type, head)
+    };
+    Ok(TinyModel::new(config.with_labels(labels))?)
+}
+
+fn tiny_gpt2(seed: i64) -> anyhow::Result<TinyModel> {
+    tiny_model(ModelType::GPT2, TinyModelHead::Generation, seed, 64, &[])
+}
+
+fn tiny_bart(seed: i64) -> anyhow::Result<TinyModel> {
+    tiny_model(ModelType::Bart, TinyModelHead::Generation, seed, 128, &[])
+}
+
+fn tiny_bert_qa(seed: i64) -> anyhow::Result<TinyModel> {
+    tiny_model(
+        ModelType::Bert,
+        TinyModelHead::QuestionAnswering,
+        seed,
+        512,
+        &[],
+    )
+}
+
+fn tiny_bert_classifier(seed: i64, labels: &[&str]) -> anyhow::Result<TinyModel> {
+    tiny_model(
+        ModelType::Bert,
+        TinyModelHead::SequenceClassification,
+        seed,
+        64,
+        labels,
+    )
+}
+
+fn tiny_bert_token_classifier(seed: i64, labels: &[&str]) -> anyhow::Result<TinyModel> {
+    tiny_model(
+        ModelType::Bert,
+        TinyModelHead::TokenClassification,
+        seed,
+        512,
+        labels,
+    )
+}
+
+fn tiny_bert_masked_lm(seed: i64) -> anyhow::Result<TinyModel> {
+    tiny_model(
+        ModelType::Bert,
+        TinyModelHead::MaskedLanguageModel,
+        seed,
+        512,
+        &[],
+    )
+}
+
+fn gpt2_generator(model: &TinyModel, num_beams: i64) -> anyhow::Result<GPT2Generator> {
+    let generate_config = GenerateConfig {
+        model_resource: model.model_resource(),
+        config_resource: model.config_resource(),
+        vocab_resource: model.vocab_resource(),
+        merges_resource: model.merges_resource(),
+        max_length: Some(16),
+        do_sample: false,
+        num_beams,
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    Ok(GPT2Generator::new(generate_config)?)
+}
+
+#[test]
+fn tiny_gpt2_greedy_generation() -> anyhow::Result<()> {
+    let model = tiny_gpt2(42)?;
+    let generator = gpt2_generator(&model, 1)?;
+    let prompts = ["the dog is", "rust is a language"];
+
+    let output = generator.generate_indices(Some(&prompts), None)?;
+    assert_eq!(output.len(), 2);
+    for sequence in &output {
+        assert!(sequence.indices.len() <= 16);
+        assert!(sequence
+            .indices
+            .iter()
+            .all(|&index| index >= 0 && index < model.vocab_size));
+    }
+
+    //    Weights generated from the same seed give the same outputs
+    let other_generator = gpt2_generator(&tiny_gpt2(42)?, 1)?;
+    let other_output = other_generator.generate_indices(Some(&prompts), None)?;
+    for (sequence, other_sequence) in output.iter().zip(other_output.iter()) {
+        assert_eq!(sequence.indices, other_sequence.indices);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tiny_gpt2_beam_search() -> anyhow::Result<()> {
+    let model = tiny_gpt2(42)?;
+    let generator = gpt2_generator(&model, 3)?;
+    let prompts = ["the dog is", "what"];
+
+    let generate_options = GenerateOptions {
+        num_return_sequences: Some(3),
+        output_scores: true,
+        ..Default::default()
+    };
+    let output = generator.generate_indices(Some(&prompts), Some(generate_options))?;
+    assert_eq!(output.len(), 6);
+    for sequences in output.chunks(3) {
+        let scores: Vec<f64> = sequences
+            .iter()
+            .map(|sequence| sequence.score.unwrap())
+            .collect();
+        assert!(scores.windows(2).all(|pair| pair[0] >= pair[1]));
+        for sequence in sequences {
+            assert!(sequence.indices.len() <= 16);
+            let token_scores = sequence.token_scores.as_ref().unwrap();
+            assert!(token_scores.len() <= sequence.indices.len());
+            assert!(token_scores.iter().all(|&score| score <= 0.0));
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tiny_gpt2_seeded_sampling() -> anyhow::Result<()> {
+    let model = tiny_gpt2(42)?;
+    let generator = gpt2_generator(&model, 1)?;
+    let prompts = ["the cat"];
+
+    let generate_options = GenerateOptions {
+        do_sample: Some(true),
+        top_k: Some(20),
+        num_return_sequences: Some(2),
+        seed: Some(7),
+        ..Default::default()
+    };
+    let sample = || {
+        with_seed(0, || {
+            generator
+                .generate_indices(Some(&prompts), Some(generate_options))
+                .unwrap()
+        })
+    };
+    let output = sample();
+    let other_output = sample();
+    assert_eq!(output.len(), 2);
+    for (sequence, other_sequence) in output.iter().zip(other_output.iter()) {
+        assert_eq!(sequence.indices, other_sequence.indices);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tiny_bart_summarization() -> anyhow::Result<()> {
+    let model = tiny_bart(42)?;
+    let summarization_config = SummarizationConfig {
+        model_type: ModelType::Bart,
+        model_resource: model.model_resource(),
+        config_resource: model.config_resource(),
+        vocab_resource: model.vocab_resource(),
+        merges_resource: model.merges_resource(),
+        num_beams: 2,
+        min_length: 0,
+        max_length: Some(12),
+        no_repeat_ngram_size: 2,
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let summarization_model = SummarizationModel::new(summarization_config)?;
+
+    let input = [
+        "the dog is in the cat and the cat is in the dog",
+        "rust is a language",
+    ];
+    let output = summarization_model.summarize(&input)?;
+    assert_eq!(output.len(), 2);
+    assert_eq!(output, summarization_model.summarize(&input)?);
+
+    Ok(())
+}
+
+fn bert_question_answering(model: &TinyModel) -> anyhow::Result<QuestionAnsweringModel> {
+    let question_answering_config = QuestionAnsweringConfig {
+        model_type: ModelType::Bert,
+        model_resource: model.model_resource(),
+        config_resource: model.config_resource(),
+        vocab_resource: model.vocab_resource(),
+        merges_resource: None,
+        lower_case: true,
+        max_seq_length: 32,
+        doc_stride: 8,
+        max_query_length: 8,
+        max_answer_length: 5,
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    Ok(QuestionAnsweringModel::new(question_answering_config)?)
+}
+
+#[test]
+fn tiny_bert_question_answering() -> anyhow::Result<()> {
+    let model = tiny_bert_qa(42)?;
+    let question_answering_model = bert_question_answering(&model)?;
+
+    //    The context is longer than a single window and spans several overlapping features
+    let context = "the dog was in the cat and the cat was in the dog. rust is a language of the \
+    cat. who is the dog? it is the cat of the language and the dog of rust.";
+    let qa_input = QaInput {
+        question: "where is the dog?".to_string(),
+        context: context.to_string(),
+    };
+    let answers = question_answering_model.predict(&[qa_input], 3, 4);
+    assert_eq!(answers.len(), 1);
+    assert!(!answers[0].is_empty() && answers[0].len() <= 3);
+
+    let context_chars: Vec<char> = context.chars().collect();
+    for answer in &answers[0] {
+        assert!(answer.start <= answer.end && answer.end <= context_chars.len());
+        assert_eq!(
+            answer.answer,
+            context_chars[answer.start..answer.end]
+                .iter()
+                .collect::<String>()
+        );
+        assert!(answer.score >= 0.0 && answer.score <= 1.0);
+    }
+    assert!(answers[0]
+        .windows(2)
+        .all(|pair| pair[0].score >= pair[1].score));
+
+    Ok(())
+}
+
+#[test]
+fn tiny_bert_question_answering_shared_context() -> anyhow::Result<()> {
+    let model = tiny_bert_qa(42)?;
+    let question_answering_model = bert_question_answering(&model)?;
+
+    //    Several questions over a single context (including a repeated question) share the
+    //    encoded context and must give the same answers as predicting each input on its own
+    let context = "the dog was in the cat and the cat was in the dog. rust is a language of the \
+    cat. who is the dog? it is the cat of the language and the dog of rust.";
+    let qa_inputs: Vec<QaInput> = [
+        "where is the dog?",
+        "who is the cat?",
+        "what is rust?",
+        "where is the dog?",
+        "is the language a cat of the dog or of rust?",
+    ]
+    .iter()
+    .map(|&question| QaInput {
+        question: question.to_string(),
+        context: context.to_string(),
+    })
+    .collect();
+
+    let shared_answers = question_answering_model.predict(&qa_inputs, 3, 4);
+    assert_eq!(shared_answers.len(), qa_inputs.len());
+    for (qa_input, shared) in qa_inputs.iter().zip(shared_answers.iter()) {
+        let single_answers = question_answering_model.predict(
+            &[QaInput {
+                question: qa_input.question.clone(),
+                context: qa_input.context.clone(),
+            }],
+            3,
+            4,
+        );
+        assert_eq!(single_answers.len(), 1);
+        let single = &single_answers[0];
+        assert!(!shared.is_empty());
+        assert_eq!(shared.len(), single.len());
+        for (shared_answer, single_answer) in shared.iter().zip(single.iter()) {
+            assert_eq!(shared_answer.answer, single_answer.answer);
+            assert_eq!(shared_answer.start, single_answer.start);
+            assert_eq!(shared_answer.end, single_answer.end);
+            assert!((shared_answer.score - single_answer.score).abs() < 1e-4);
+        }
+    }
+    assert_eq!(shared_answers[0], shared_answers[3]);
+
+    Ok(())
+}
+
+fn generate_indices(model: &TinyModel, prompts: &[&str]) -> anyhow::Result<Vec<Vec<i64>>> {
+    let generate_config = GenerateConfig {
+        model_resource: model.model_resource(),
+        config_resource: model.config_resource(),
+        vocab_resource: model.vocab_resource(),
+        merges_resource: model.merges_resource(),
+        max_length: Some(12),
+        do_sample: false,
+        num_beams: 2,
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let output = match model.model_type {
+        ModelType::GPT2 => {
+            GPT2Generator::new(generate_config)?.generate_indices(Some(prompts), None)?
+        }
+        ModelType::GPTNeo => {
+            GptNeoGenerator::new(generate_config)?.generate_indices(Some(prompts), None)?
+        }
+        ModelType::OpenAiGpt => {
+            OpenAIGenerator::new(generate_config)?.generate_indices(Some(prompts), None)?
+        }
+        ModelType::XLNet => {
+            XLNetGenerator::new(generate_config)?.generate_indices(Some(prompts), None)?
+        }
+        ModelType::Reformer => {
+            ReformerGenerator::new(generate_config)?.generate_indices(Some(prompts), None)?
+        }
+        ModelType::Bart => {
+            BartGenerator::new(generate_config)?.generate_indices(Some(prompts), None)?
+        }
+        ModelType::Marian => {
+            MarianGenerator::new(generate_config)?.generate_indices(Some(prompts), None)?
+        }
+        ModelType::MBart => {
+            MBartGenerator::new(generate_config)?.generate_indices(Some(prompts), None)?
+        }
+        ModelType::M2M100 => {
+            M2M100Generator::new(generate_config)?.generate_indices(Some(prompts), None)?
+        }
+        ModelType::Pegasus => PegasusConditionalGenerator::new(generate_config)?
+            .generate_indices(Some(prompts), None)?,
+        ModelType::ProphetNet => ProphetNetConditionalGenerator::new(generate_config)?
+            .generate_indices(Some(prompts), None)?,
+        ModelType::T5 => {
+            T5Generator::new(generate_config)?.generate_indices(Some(prompts), None)?
+        }
+        model_type => panic!("{:?} is not a generation architecture", model_type),
+    };
+    Ok(output.into_iter().map(|output| output.indices).collect())
+}
+
+#[test]
+fn tiny_generation_architectures() -> anyhow::Result<()> {
+    let prompts = ["the dog is", "rust is a language"];
+    for &model_type in GENERATION_MODEL_TYPES.iter() {
+        let model = TinyModel::new(TinyModelConfig::new(model_type, TinyModelHead::Generation))?;
+
+        let output = generate_indices(&model, &prompts)?;
+        assert_eq!(output.len(), 2, "{:?}", model_type);
+        for sequence in &output {
+            assert!(
+                !sequence.is_empty() && sequence.len() <= 12,
+                "{:?}",
+                model_type
+            );
+            assert!(
+                sequence
+                    .iter()
+                    .all(|&index| index >= 0 && index < model.vocab_size),
+                "{:?}",
+                model_type
+            );
+        }
+        assert_eq!(
+            output,
+            generate_indices(&model, &prompts)?,
+            "{:?}",
+            model_type
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tiny_question_answering_architectures() -> anyhow::Result<()> {
+    let context = "the dog was in the cat and the cat was in the dog. rust is a language of the \
+    cat. who is the dog? it is the cat of the language and the dog of rust.";
+    let context_chars: Vec<char> = context.chars().collect();
+    for &model_type in QUESTION_ANSWERING_MODEL_TYPES.iter() {
+        let model = TinyModel::new(TinyModelConfig::new(
+            model_type,
+            TinyModelHead::QuestionAnswering,
+        ))?;
+        let question_answering_model = QuestionAnsweringModel::new(QuestionAnsweringConfig {
+            model_type,
+            model_resource: model.model_resource(),
+            config_resource: model.config_resource(),
+            vocab_resource: model.vocab_resource(),
+            merges_resource: model.merges_resource(),
+            lower_case: true,
+            max_seq_length: 32,
+            doc_stride: 8,
+            max_query_length: 8,
+            max_answer_length: 5,
+            device: Device::Cpu,
+            ..Default::default()
+        })?;
+
+        let qa_input = QaInput {
+            question: "where is the dog?".to_string(),
+            context: context.to_string(),
+        };
+        let answers = question_answering_model.predict(&[qa_input], 2, 4);
+        assert_eq!(answers.len(), 1, "{:?}", model_type);
+        assert!(
+            !answers[0].is_empty() && answers[0].len() <= 2,
+            "{:?}",
+            model_type
+        );
+        for answer in &answers[0] {
+            assert!(
+                answer.start <= answer.end && answer.end <= context_chars.len(),
+                "{:?}",
+                model_type
+            );
+            assert_eq!(
+                answer.answer,
+                context_chars[answer.start..answer.end]
+                    .iter()
+                    .collect::<String>(),
+                "{:?}",
+                model_type
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tiny_reformer_question_answering() -> anyhow::Result<()> {
+    let model = TinyModel::new(TinyModelConfig::new(
+        ModelType::Reformer,
+        TinyModelHead::QuestionAnswering,
+    ))?;
+    let config = ConfigOption::from_file(
+        ModelType::Reformer,
+        model.config_resource().get_local_path()?,
+    );
+    let mut var_store = nn::VarStore::new(Device::Cpu);
+    let question_answering_model =
+        QuestionAnsweringOption::new(ModelType::Reformer, &var_store.root(), &config)?;
+    var_store.load(model.model_resource().get_local_path()?)?;
+
+    let tokenizer = TokenizerOption::from_file(
+        ModelType::Reformer,
+        model.vocab_resource().get_local_path()?.to_str().unwrap(),
+        None,
+        true,
+        None,
+        None,
+    )?;
+    let input = tokenizer.encode_list(
+        &["where is the dog? the dog was in the cat."],
+        64,
+        &TruncationStrategy::LongestFirst,
+        0,
+    );
+    let sequence_length = input[0].token_ids.len() as i64;
+    assert!(input[0]
+        .token_ids
+        .iter()
+        .all(|&index| index >= 0 && index < model.vocab_size));
+    let input_ids = Tensor::of_slice(&input[0].token_ids).unsqueeze(0);
+
+    let (start_logits, end_logits) =
+        question_answering_model.forward_t(Some(&input_ids), None, None, false);
+    assert_eq!(start_logits.size(), vec![1, sequence_length]);
+    assert_eq!(end_logits.size(), vec![1, sequence_length]);
+
+    Ok(())
+}
+
+#[test]
+fn tiny_gpt2_min_p_sampling() -> anyhow::Result<()> {
+    let model = tiny_gpt2(42)?;
+    let generator = gpt2_generator(&model, 1)?;
+    let prompts = ["the dog is", "rust is a language"];
+
+    //    A min_p of 1 only keeps the most likely token: sampling reduces to greedy decoding
+    let generate_options = GenerateOptions {
+        do_sample: Some(true),
+        top_p: Some(1.0),
+        min_p: Some(1.0),
+        ..Default::default()
+    };
+    let sampled_output = with_seed(0, || {
+        generator.generate_indices(Some(&prompts), Some(generate_options))
+    })?;
+    let greedy_output = generator.generate_indices(Some(&prompts), None)?;
+    for (sampled, greedy) in sampled_output.iter().zip(greedy_output.iter()) {
+        assert_eq!(sampled.indices, greedy.indices);
+    }
+
+    let invalid_options = GenerateOptions {
+        do_sample: Some(true),
+        min_p: Some(1.5),
+        ..Default::default()
+    };
+    assert!(generator
+        .generate_indices(Some(&prompts), Some(invalid_options))
+        .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn tiny_gpt2_layer_placement() -> anyhow::Result<()> {
+    let model = tiny_gpt2(42)?;
+    let prompts = ["the dog is", "rust is a language"];
+    let placed_generator = |prefix: &str| {
+        let layer_placement: HashMap<String, LayerPlacement> = vec![(
+            prefix.to_string(),
+            LayerPlacement {
+                device: Some(Device::Cpu),
+                kind: Some(Kind::Double),
+            },
+        )]
+        .into_iter()
+        .collect();
+        GPT2Generator::new(GenerateConfig {
+            model_resource: model.model_resource(),
+            config_resource: model.config_resource(),
+            vocab_resource: model.vocab_resource(),
+            merges_resource: model.merges_resource(),
+            max_length: Some(16),
+            do_sample: false,
+            layer_placement,
+            device: Device::Cpu,
+            ..Default::default()
+        })
+    };
+
+    //    The transformer blocks run in double precision, the embeddings in single precision
+    let generator = placed_generator("transformer.h")?;
+    let output = generator.generate_indices(Some(&prompts), None)?;
+    assert_eq!(output.len(), 2);
+    for sequence in &output {
+        assert!(sequence.indices.len() <= 16);
+        assert!(sequence
+            .indices
+            .iter()
+            .all(|&index| index >= 0 && index < model.vocab_size));
+    }
+
+    //    Prefixes must match at least one variable of the model
+    assert!(placed_generator("transformer.missing").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn tiny_gpt2_beam_sampling() -> anyhow::Result<()> {
+    let model = tiny_gpt2(42)?;
+    let generator = gpt2_generator(&model, 3)?;
+    let prompts = ["the dog is", "what"];
+
+    //    Sampled beam search returns distinct hypotheses of a single search for each prompt
+    let generate_options = GenerateOptions {
+        do_sample: Some(true),
+        num_return_sequences: Some(3),
+        output_scores: true,
+        seed: Some(7),
+        ..Default::default()
+    };
+    let output = with_seed(0, || {
+        generator.generate_indices(Some(&prompts), Some(generate_options))
+    })?;
+    assert_eq!(output.len(), 6);
+    for sequences in output.chunks(3) {
+        for (index, sequence) in sequences.iter().enumerate() {
+            assert!(sequence.indices.len() <= 16);
+            assert!(sequences[..index]
+                .iter()
+                .all(|other| other.indices != sequence.indices));
+        }
+        let scores: Vec<f64> = sequences
+            .iter()
+            .map(|sequence| sequence.score.unwrap())
+            .collect();
+        assert!(scores.windows(2).all(|pair| pair[0] >= pair[1]));
+    }
+
+    let invalid_options = GenerateOptions {
+        do_sample: Some(true),
+        num_return_sequences: Some(4),
+        ..Default::default()
+    };
+    assert!(generator
+        .generate_indices(Some(&prompts), Some(invalid_options))
+        .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn tiny_gpt2_prefix_allowed_tokens() -> anyhow::Result<()> {
+    let model = tiny_gpt2(42)?;
+    let prompt = "the dog is";
+
+    for &(num_beams, do_sample) in [(1, false), (1, true), (3, false)].iter() {
+        let generator = gpt2_generator(&model, num_beams)?;
+        let tokenizer = generator.get_tokenizer();
+        let prompt_ids = tokenizer.convert_tokens_to_ids(&tokenizer.tokenize(prompt));
+        let allowed_ids = tokenizer.convert_tokens_to_ids(&["Ġcat", "Ġrust", "<|endoftext|>"]);
+        let allowed_tokens = |_batch_id: i64, _previous_token_ids: &Tensor| allowed_ids.clone();
+
+        let generate_options = GenerateOptions {
+            do_sample: Some(do_sample),
+            prefix_allowed_tokens_fn: Some(&allowed_tokens),
+            ..Default::default()
+        };
+        let output = with_seed(0, || {
+            generator.generate_indices(Some(&[prompt]), Some(generate_options))
+        })?;
+        assert_eq!(output.len(), 1);
+        let indices = &output[0].indices;
+        assert!(indices.len() > prompt_ids.len());
+        assert_eq!(indices[..prompt_ids.len()], prompt_ids[..]);
+        //    Every generated token is one of the allowed tokens
+        assert!(indices[prompt_ids.len()..]
+            .iter()
+            .all(|index| allowed_ids.contains(index)));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tiny_gpt2_streaming_stop_sequences() -> anyhow::Result<()> {
+    let model = tiny_gpt2(42)?;
+    let generator = gpt2_generator(&model, 1)?;
+    let tokenizer = generator.get_tokenizer();
+    let prompt = "the dog is";
+    let allowed_ids = tokenizer.convert_tokens_to_ids(&["Ġcat", "Ġdog"]);
+    let allowed_tokens = |_batch_id: i64, _previous_token_ids: &Tensor| allowed_ids.clone();
+
+    let stream = |stop_sequences: &[String]| -> anyhow::Result<String> {
+        let generate_options = GenerateOptions {
+            do_sample: Some(true),
+            prefix_allowed_tokens_fn: Some(&allowed_tokens),
+            stop_sequences: Some(stop_sequences),
+            ..Default::default()
+        };
+        let mut streamed = String::new();
+        with_seed(0, || {
+            generator.generate_with_callback(
+                Some(&[prompt]),
+                Some(generate_options),
+                |_sequence_index, text| streamed.push_str(text),
+            )
+        })?;
+        Ok(streamed)
+    };
+
+    //    Every generated token is " cat" or " dog": the stop sequence spans the third and fourth
+    //    generated tokens of the unconstrained stream and may already appear before them
+    let unconstrained = stream(&[])?;
+    assert!(unconstrained.len() >= 16, "{}", unconstrained);
+    let stop_sequence = unconstrained[8..16].to_string();
+    let expected = &unconstrained[..unconstrained.find(&stop_sequence).unwrap()];
+
+    //    The tokens of the stop sequence are never streamed
+    let streamed = stream(&[stop_sequence.clone()])?;
+    assert_eq!(streamed, expected);
+    assert!(!streamed.contains(&stop_sequence));
+
+    Ok(())
+}
+
+#[test]
+fn tiny_gpt2_grammar_constraint() -> anyhow::Result<()> {
+    let model = tiny_gpt2(42)?;
+    let prompt = "the dog is";
+    let grammar = Grammar::from_json_schema(&serde_json::json!({
+        "type": "object",
+        "properties": {"ok": {"type": "boolean"}},
+        "required": ["ok"]
+    }))?;
+
+    for &(num_beams, do_sample) in [(1, false), (1, true), (3, false)].iter() {
+        let generator = gpt2_generator(&model, num_beams)?;
+        let constraint = generator.grammar_constraint(grammar.clone());
+        let tokenizer = generator.get_tokenizer();
+        let prompt_length = tokenizer.tokenize(prompt).len();
+
+        let generate_options = GenerateOptions {
+            max_length: Some(40),
+            do_sample: Some(do_sample),
+            grammar: Some(&constraint),
+            ..Default::default()
+        };
+        let output = with_seed(0, || {
+            generator.generate_indices(Some(&[prompt]), Some(generate_options))
+        })?;
+        assert_eq!(output.len(), 1);
+        //    The untrained model is forced to generate a complete JSON object, then to stop
+        let generated = tokenizer.decode(&output[0].indices[prompt_length..], true, false);
+        assert!(grammar.matches(&generated), "{}", generated);
+        let value: serde_json::Value = serde_json::from_str(&generated)?;
+        assert!(value["ok"].is_boolean());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tiny_gpt2_single_token_bad_words() -> anyhow::Result<()> {
+    let model = tiny_gpt2(42)?;
+    let prompt = "the dog is";
+
+    for &num_beams in [1, 3].iter() {
+        let generator = gpt2_generator(&model, num_beams)?;
+        let tokenizer = generator.get_tokenizer();
+        let prompt_length = tokenizer.tokenize(prompt).len();
+        let eos_token_id = tokenizer.convert_tokens_to_ids(&["<|endoftext|>"])[0];
+
+        let output = generator.generate_indices(Some(&[prompt]), None)?;
+        let banned_ids = output[0].indices[prompt_length..]
+            .iter()
+            .filter(|&&index| index != eos_token_id)
+            .take(3)
+            .copied()
+            .collect::<Vec<i64>>();
+        assert!(!banned_ids.is_empty());
+
+        //    Only single-token words are banned: no dynamic bad words are checked
+        let bad_word_ids = banned_ids
+            .iter()
+            .map(|&index| vec![index])
+            .collect::<Vec<Vec<i64>>>();
+        let generate_options = GenerateOptions {
+            bad_word_ids: Some(&bad_word_ids),
+            ..Default::default()
+        };
+        let output = generator.generate_indices(Some(&[prompt]), Some(generate_options))?;
+        assert!(output[0].indices[prompt_length..]
+            .iter()
+            .all(|index| !banned_ids.contains(index)));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tiny_gpt2_mixed_length_bad_words() -> anyhow::Result<()> {
+    let model = tiny_gpt2(42)?;
+    let prompt = "the dog is";
+
+    for &num_beams in [1, 3].iter() {
+        let generator = gpt2_generator(&model, num_beams)?;
+        let prompt_length = generator.get_tokenizer().tokenize(prompt).len();
+
+        let output = generator.generate_indices(Some(&[prompt]), None)?;
+        let single_bad_word = vec![output[0].indices[prompt_length]];
+        let bad_word_ids = vec![single_bad_word.clone()];
+        let generate_options = GenerateOptions {
+            bad_word_ids: Some(&bad_word_ids),
+            min_length: Some(prompt_length as i64 + 2),
+            ..Default::default()
+        };
+        let output = generator.generate_indices(Some(&[prompt]), Some(generate_options))?;
+        assert!(output[0].indices.len() >= prompt_length + 2);
+        //    Bans the first two tokens generated once the single-token word is banned
+        let multi_bad_word = output[0].indices[prompt_length..prompt_length + 2].to_vec();
+
+        let bad_word_ids = vec![single_bad_word.clone(), multi_bad_word.clone()];
+        let generate_options = GenerateOptions {
+            bad_word_ids: Some(&bad_word_ids),
+            min_length: Some(prompt_length as i64 + 2),
+            ..Default::default()
+        };
+        let output = generator.generate_indices(Some(&[prompt]), Some(generate_options))?;
+        let generated = &output[0].indices[prompt_length - 1..];
+        assert!(!generated[1..].contains(&single_bad_word[0]));
+        assert!(generated
+            .windows(2)
+            .all(|window| window != multi_bad_word.as_slice()));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tiny_gpt2_lazy_beam_expansion() -> anyhow::Result<()> {
+    let model = tiny_gpt2(42)?;
+    let generator = gpt2_generator(&model, 3)?;
+    let prompts = ["the dog is a very good dog", "what"];
+
+    for &prefill_chunk_size in [None, Some(2)].iter() {
+        let generate = |lazy_beam_expansion: bool| {
+            let generate_options = GenerateOptions {
+                num_return_sequences: Some(3),
+                output_scores: true,
+                prefill_chunk_size,
+                lazy_beam_expansion: Some(lazy_beam_expansion),
+                ..Default::default()
+            };
+            generator.generate_indices(Some(&prompts), Some(generate_options))
+        };
+        let lazy_output = generate(true)?;
+        let eager_output = generate(false)?;
+
+        assert_eq!(lazy_output.len(), 6);
+        assert_eq!(lazy_output.len(), eager_output.len());
+        for (lazy, eager) in lazy_output.iter().zip(eager_output.iter()) {
+            assert_eq!(lazy.indices, eager.indices);
+            assert!((lazy.score.unwrap() - eager.score.unwrap()).abs() < 1e-4);
+            let lazy_token_scores = lazy.token_scores.as_ref().unwrap();
+            let eager_token_scores = eager.token_scores.as_ref().unwrap();
+            assert_eq!(lazy_token_scores.len(), eager_token_scores.len());
+            assert!(lazy_token_scores
+                .iter()
+                .zip(eager_token_scores.iter())
+                .all(|(lazy_score, eager_score)| (lazy_score - eager_score).abs() < 1e-4));
+        }
+    }
+
+    Ok(())
+}
+
+fn bert_classifier_config(
+    model: &TinyModel,
+    sliding_window: Option<SlidingWindowConfig>,
+) -> SequenceClassificationConfig {
+    SequenceClassificationConfig {
+        model_type: ModelType::Bert,
+        model_resource: model.model_resource(),
+        config_resource: model.config_resource(),
+        vocab_resource: model.vocab_resource(),
+        merges_resource: None,
+        lower_case: true,
+        device: Device::Cpu,
+        sliding_window,
+        ..Default::default()
+    }
+}
+
+fn bert_classifier(
+    model: &TinyModel,
+    sliding_window: Option<SlidingWindowConfig>,
+) -> anyhow::Result<SequenceClassificationModel> {
+    Ok(SequenceClassificationModel::new(bert_classifier_config(
+        model,
+        sliding_window,
+    ))?)
+}
+
+#[test]
+fn tiny_bert_sliding_window_classification() -> anyhow::Result<()> {
+    let model = tiny_bert_classifier(42, &["negative", "positive"])?;
+    //    Inputs shorter than a window and longer than the model maximum length (several windows)
+    let long_text = "the dog is in the cat and the cat is in the dog. ".repeat(8);
+    let inputs = ["the dog", long_text.as_str(), "rust is a language"];
+
+    for &aggregation in [
+        WindowAggregation::Mean,
+        WindowAggregation::Max,
+        WindowAggregation::Vote,
+    ]
+    .iter()
+    {
+        let classifier = bert_classifier(
+            &model,
+            Some(SlidingWindowConfig {
+                stride: 8,
+                aggregation,
+            }),
+        )?;
+        let labels = classifier.predict(&inputs);
+        assert_eq!(labels.len(), inputs.len());
+        for (index, label) in labels.iter().enumerate() {
+            assert_eq!(label.sentence, index);
+        }
+        assert_eq!(
+            classifier
+                .predict_full(&inputs, ScoreNormalization::Softmax)
+                .len(),
+            inputs.len()
+        );
+        assert_eq!(classifier.predict_logits(&inputs).len(), inputs.len());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tiny_bert_classification_ignores_padding() -> anyhow::Result<()> {
+    let model = tiny_bert_classifier(42, &["negative", "positive"])?;
+    let classifier = bert_classifier(&model, None)?;
+
+    //    The short input is padded to the length of the long input in the batch
+    let single = classifier.predict_logits(&["the dog"]);
+    let batch =
+        classifier.predict_logits(&["the dog", "the cat is in the dog and the dog is a cat"]);
+    assert_eq!(batch.len(), 2);
+    assert!(single[0]
+        .iter()
+        .zip(batch[0].iter())
+        .all(|(single, batched)| (single - batched).abs() < 1e-4));
+
+    Ok(())
+}
+
+fn assert_sorted_by_score(labels: &[Label]) {
+    assert!(labels.windows(2).all(|pair| pair[0].score >= pair[1].score));
+}
+
+#[test]
+fn tiny_bert_classification_full_distribution() -> anyhow::Result<()> {
+    let model = tiny_bert_classifier(42, &["negative", "neutral", "positive"])?;
+    let classifier = bert_classifier(&model, None)?;
+    let inputs = ["the dog is a cat", "rust is a language"];
+
+    let logits = classifier.predict_logits(&inputs);
+    let predictions = classifier.predict(&inputs);
+    let softmax = classifier.predict_full(&inputs, ScoreNormalization::Softmax);
+    let sigmoid = classifier.predict_full(&inputs, ScoreNormalization::Sigmoid);
+    assert_eq!(softmax.len(), inputs.len());
+    assert_eq!(sigmoid.len(), inputs.len());
+
+    for (sentence_idx, sentence_logits) in logits.iter().enumerate() {
+        let normalizer: f64 = sentence_logits.iter().map(|logit| logit.exp()).sum();
+        for (labels, expected) in [
+            (
+                &softmax[sentence_idx],
+                sentence_logits
+                    .iter()
+                    .map(|logit| logit.exp() / normalizer)
+                    .collect::<Vec<f64>>(),
+            ),
+            (
+                &sigmoid[sentence_idx],
+                sentence_logits
+                    .iter()
+                    .map(|logit| 1.0 / (1.0 + (-logit).exp()))
+                    .collect::<Vec<f64>>(),
+            ),
+        ]
+        .iter()
+        {
+            assert_eq!(labels.len(), 3);
+            assert_sorted_by_score(labels);
+            for label in labels.iter() {
+                assert_eq!(label.sentence, sentence_idx);
+                assert!((label.score - expected[label.id as usize]).abs() < 1e-4);
+            }
+        }
+        //    The most likely label is the label returned by `predict`
+        assert_eq!(softmax[sentence_idx][0].id, predictions[sentence_idx].id);
+        let total: f64 = softmax[sentence_idx].iter().map(|label| label.score).sum();
+        assert!((total - 1.0).abs() < 1e-4);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tiny_bert_zero_shot_full_distribution() -> anyhow::Result<()> {
+    let model = tiny_bert_classifier(42, &["contradiction", "neutral", "entailment"])?;
+    let config = ZeroShotClassificationConfig {
+        model_type: ModelType::Bert,
+        model_resource: model.model_resource(),
+        config_resource: model.config_resource(),
+        vocab_resource: model.vocab_resource(),
+        merges_resource: None,
+        lower_case: true,
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let classifier = ZeroShotClassificationModel::new(config)?;
+    let inputs = ["the dog is a cat", "rust is a language"];
+    let candidate_labels = ["dog", "cat", "rust", "language"];
+
+    let predictions = classifier.predict(&inputs, &candidate_labels, None, 64)?;
+    let multilabel = classifier.predict_multilabel(&inputs, &candidate_labels, None, 64)?;
+    let softmax = classifier.predict_full(
+        &inputs,
+        &candidate_labels,
+        None,
+        64,
+        ScoreNormalization::Softmax,
+    )?;
+    let sigmoid = classifier.predict_full(
+        &inputs,
+        &candidate_labels,
+        None,
+        64,
+        ScoreNormalization::Sigmoid,
+    )?;
+
+    for sentence_idx in 0..inputs.len() {
+        for labels in [&softmax[sentence_idx], &sigmoid[sentence_idx]].iter() {
+            assert_eq!(labels.len(), candidate_labels.len());
+            assert_sorted_by_score(labels);
+            assert!(labels
+                .iter()
+                .all(|label| label.text == candidate_labels[label.id as usize]));
+        }
+        let total: f64 = softmax[sentence_idx].iter().map(|label| label.score).sum();
+        assert!((total - 1.0).abs() < 1e-4);
+        assert_eq!(softmax[sentence_idx][0].id, predictions[sentence_idx].id);
+        assert!((softmax[sentence_idx][0].score - predictions[sentence_idx].score).abs() < 1e-6);
+        //    Independent label scores match the multi-label prediction
+        for label in &sigmoid[sentence_idx] {
+            let expected = &multilabel[sentence_idx][label.id as usize];
+            assert!((label.score - expected.score).abs() < 1e-6);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tiny_bert_token_classification_full_distribution() -> anyhow::Result<()> {
+    let model = tiny_bert_token_classifier(42, &["O", "B-PER", "I-PER"])?;
+    let config = TokenClassificationConfig {
+        model_type: ModelType::Bert,
+        model_resource: model.model_resource(),
+        config_resource: model.config_resource(),
+        vocab_resource: model.vocab_resource(),
+        merges_resource: None,
+        lower_case: true,
+        device: Device::Cpu,
+        ..Default::default()
+    };
+    let classifier = TokenClassificationModel::new(config)?;
+    let inputs = ["the dog is a cat", "who is rust?"];
+
+    let tokens = classifier.predict(&inputs, false, false);
+    for &normalization in [ScoreNormalization::Softmax, ScoreNormalization::Sigmoid].iter() {
+        let full_tokens = classifier.predict_full(&inputs, false, normalization);
+        assert_eq!(full_tokens.len(), inputs.len());
+        for (sentence_tokens, sentence_full_tokens) in tokens.iter().zip(full_tokens.iter()) {
+            assert_eq!(sentence_tokens.len(), sentence_full_tokens.len());
+            for (token, full_token) in sentence_tokens.iter().zip(sentence_full_tokens.iter()) {
+                assert_eq!(token.text, full_token.token.text);
+                assert_eq!(token.label_index, full_token.token.label_index);
+                assert_eq!(full_token.labels.len(), 3);
+                assert_sorted_by_score(&full_token.labels);
+                assert_eq!(full_token.labels[0].id, full_token.token.label_index);
+                assert!(full_token
+                    .labels
+                    .iter()
+                    .all(|label| (0.0..=1.0).contains(&label.score)));
+                if normalization == ScoreNormalization::Softmax {
+                    let total: f64 = full_token.labels.iter().map(|label| label.score).sum();
+                    assert!((total - 1.0).abs() < 1e-4);
+                    assert!((full_token.token.score - token.score).abs() < 1e-6);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tiny_bert_emotion_thresholds() -> anyhow::Result<()> {
+    let emotions = ["admiration", "anger", "joy", "sadness"];
+    let model = tiny_bert_classifier(42, &emotions)?;
+    let classifier = bert_classifier(&model, None)?;
+    let inputs = [
+        "the dog is a cat",
+        "rust is a language",
+        "the cat is in the dog",
+    ];
+    let expected_scores = classifier.predict_full(&inputs, ScoreNormalization::Sigmoid);
+
+    //    Anger is always detected and joy never, the other emotions use the default threshold
+    let mut config = EmotionConfig::new(bert_classifier_config(&model, None));
+    config.default_threshold = 0.5;
+    config.thresholds.insert("anger".to_string(), 0.0);
+    config.thresholds.insert("joy".to_string(), 1.0);
+    config.batch_size = 2;
+    let predictions = EmotionModel::new(config)?.predict(&inputs);
+    assert_eq!(predictions.len(), inputs.len());
+    for (sentence, (prediction, expected_scores)) in
+        predictions.iter().zip(expected_scores.iter()).enumerate()
+    {
+        assert_eq!(prediction.scores.len(), emotions.len());
+        for (label, expected) in prediction.scores.iter().zip(expected_scores.iter()) {
+            assert_eq!(label.text, expected.text);
+            assert!((label.score - expected.score).abs() < 1e-4);
+            assert_eq!(label.sentence, sentence);
+        }
+        let expected_emotions = prediction
+            .scores
+            .iter()
+            .filter(|label| match label.text.as_str() {
+                "anger" => true,
+                "joy" => false,
+                _ => label.score >= 0.5,
+            })
+            .map(|label| label.text.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(
+            prediction
+                .emotions
+                .iter()
+                .map(|label| label.text.as_str())
+                .collect::<Vec<&str>>(),
+            expected_emotions
+        );
+        assert_sorted_by_score(&prediction.emotions);
+    }
+
+    //    Only the emotions with the highest scores are kept
+    let mut config = EmotionConfig::new(bert_classifier_config(&model, None));
+    config.default_threshold = 0.0;
+    config.max_emotions = Some(2);
+    for prediction in EmotionModel::new(config)?.predict(&inputs) {
+        assert_eq!(prediction.emotions.len(), 2);
+        assert_eq!(prediction.emotions[0].text, prediction.scores[0].text);
+        assert_eq!(prediction.emotions[1].text, prediction.scores[1].text);
+        assert_eq!(
+            prediction.dominant_emotion().map(|label| label.id),
+            Some(prediction.scores[0].id)
+        );
+    }
+
+    let mut config = EmotionConfig::new(bert_classifier_config(&model, None));
+    config.default_threshold = 1.0;
+    for prediction in EmotionModel::new(config)?.predict(&inputs) {
+        assert!(prediction.emotions.is_empty());
+        assert!(prediction.dominant_emotion().is_none());
+    }
+
+    let invalid_configurations: [fn(&mut EmotionConfig); 4] = [
+        |config| config.default_threshold = -0.1,
+        |config| {
+            config.thresholds.insert("anger".to_string(), 1.5);
+        },
+        |config| config.max_emotions = Some(0),
+        |config| config.batch_size = 0,
+    ];
+    for configure in invalid_configurations.iter() {
+        let mut config = EmotionConfig::new(bert_classifier_config(&model, None));
+        configure(&mut config);
+        assert!(matches!(
+            EmotionModel::new(config),
+            Err(RustBertError::InvalidConfigurationError(_))
+        ));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tiny_bert_toxicity_thresholds() -> anyhow::Result<()> {
+    let categories = ["toxic", "obscene", "threat", "insult"];
+    let model = tiny_bert_classifier(42, &categories)?;
+    let sliding_window = SlidingWindowConfig {
+        stride: 8,
+        aggregation: WindowAggregation::Max,
+    };
+    let classifier = bert_classifier(&model, Some(sliding_window))?;
+    //    Inputs shorter than a window and longer than the model maximum length (several windows)
+    let long_text = "the dog is in the cat and the cat is in the dog. ".repeat(8);
+    let inputs = ["the dog", long_text.as_str(), "rust is a language"];
+    let expected_scores = classifier.predict_full(&inputs, ScoreNormalization::Sigmoid);
+
+    //    Long inputs are windowed keeping the maximum score of each category by default
+    let config = ToxicityConfig::new(bert_classifier_config(&model, None));
+    let default_window = config
+        .sequence_classification_config
+        .sliding_window
+        .unwrap();
+    assert_eq!(default_window.stride, 128);
+    assert_eq!(default_window.aggregation, WindowAggregation::Max);
+
+    //    Only threats are flagged
+    let mut config = ToxicityConfig::new(bert_classifier_config(&model, Some(sliding_window)));
+    config.default_threshold = 1.0;
+    config.thresholds.insert("threat".to_string(), 0.0);
+    config.batch_size = 2;
+    let toxicity_model = ToxicityModel::new(config)?;
+    let predictions = toxicity_model.predict(&inputs);
+    assert_eq!(predictions.len(), inputs.len());
+    for (sentence, (prediction, expected_scores)) in
+        predictions.iter().zip(expected_scores.iter()).enumerate()
+    {
+        assert_eq!(prediction.scores.len(), categories.len());
+        for (label, expected) in prediction.scores.iter().zip(expected_scores.iter()) {
+            assert_eq!(label.text, expected.text);
+            assert!((label.score - expected.score).abs() < 1e-4);
+            assert_eq!(label.sentence, sentence);
+        }
+        assert!(prediction.is_toxic());
+        assert_eq!(prediction.flagged.len(), 1);
+        assert_eq!(prediction.flagged[0].text, "threat");
+    }
+
+    //    Flagged outputs are regenerated, then replaced by the fallback
+    let filter = SafetyFilter::new(
+        toxicity_model,
+        UnsafeOutputAction::Regenerate {
+            max_attempts: 2,
+            fallback: "[filtered]".to_string(),
+        },
+    );
+    let mut regenerated = vec![];
+    let outputs = filter.filter(
+        vec!["the dog".to_string(), "rust is a language".to_string()],
+        |indices| {
+            regenerated.push(indices.to_vec());
+            Ok(indices.iter().map(|_| "the cat".to_string()).collect())
+        },
+    )?;
+    assert_eq!(outputs, ["[filtered]", "[filtered]"]);
+    assert_eq!(regenerated, [vec![0, 1], vec![0, 1]]);
+
+    //    Safe outputs are left unchanged
+    let mut config = ToxicityConfig::new(bert_classifier_config(&model, Some(sliding_window)));
+    config.default_threshold = 1.0;
+    let filter = SafetyFilter::new(
+        ToxicityModel::new(config)?,
+        UnsafeOutputAction::Replace("[filtered]".to_string()),
+    );
+    let outputs = filter.filter(vec!["the dog".to_string()], |_| {
+        unreachable!("Safe outputs are not regenerated")
+    })?;
+    assert_eq!(outputs, ["the dog"]);
+
+    let invalid_configurations: [fn(&mut ToxicityConfig); 3] = [
+        |config| config.default_threshold = 1.1,
+        |config| {
+            config.thresholds.insert("threat".to_string(), -0.5);
+        },
+        |config| config.batch_size = 0,
+    ];
+    for configure in invalid_configurations.iter() {
+        let mut config = ToxicityConfig::new(bert_classifier_config(&model, Some(sliding_window)));
+        configure(&mut config);
+        assert!(matches!(
+            ToxicityModel::new(config),
+            Err(RustBertError::InvalidConfigurationError(_))
+        ));
+    }
+
+    Ok(())
+}
+
+fn bert_multi_task(model: &TinyModel) -> anyhow::Result<MultiTaskModel> {
+    let config = MultiTaskConfig {
+        model_type: ModelType::Bert,
+        model_resource: model.model_resource(),
+        config_resource: model.config_resource(),
+        vocab_resource: model.vocab_resource(),
+        merges_resource: None,
+        classification_labels: None,
+        token_labels: None,
+        embeddings: false,
+        normalize_embeddings: false,
+        lower_case: true,
+        strip_accents: None,
+        add_prefix_space: None,
+        device: Device::Cpu,
+    };
+    Ok(MultiTaskModel::new(config)?)
+}
+
+fn head_label<'a>(output: &'a MultiTaskOutput, name: &str) -> &'a Label {
+    match &output.heads[name] {
+        TaskHeadOutput::Classification(label) => label,
+        TaskHeadOutput::Entities(_) => panic!("{} is not a sequence classification head", name),
+    }
+}
+
+#[test]
+fn tiny_bert_multi_task_runtime_heads() -> anyhow::Result<()> {
+    let labels = ["negative", "positive"];
+    let model = tiny_bert_classifier(42, &labels)?;
+    let token_model = tiny_bert_token_classifier(42, &["O", "B-PER", "I-PER"])?;
+    let classifier = bert_classifier(&model, None)?;
+    let inputs = ["the dog is a cat", "rust is a language"];
+    let expected = classifier.predict(&inputs);
+    let sentiment_labels = || {
+        labels
+            .iter()
+            .map(|label| label.to_string())
+            .collect::<Vec<String>>()
+    };
+
+    //    An encoder without built-in tasks only returns the outputs of the heads loaded at runtime
+    let mut multi_task_model = bert_multi_task(&model)?;
+    for output in multi_task_model.predict(&inputs) {
+        assert!(output.classification.is_none());
+        assert!(output.entities.is_none());
+        assert!(output.embedding.is_none());
+        assert!(output.heads.is_empty());
+    }
+
+    //    The head of the classifier on its own encoder returns the predictions of the classifier
+    multi_task_model.load_head(
+        "sentiment",
+        TaskHeadType::SequenceClassification,
+        sentiment_labels(),
+        &*model.model_resource(),
+        "classifier",
+    )?;
+    multi_task_model.load_head(
+        "entities",
+        TaskHeadType::TokenClassification,
+        vec!["O".to_string(), "B-PER".to_string(), "I-PER".to_string()],
+        &*token_model.model_resource(),
+        "classifier",
+    )?;
+    let mut head_names = multi_task_model.head_names();
+    head_names.sort_unstable();
+    assert_eq!(head_names, ["entities", "sentiment"]);
+
+    let outputs = multi_task_model.predict(&inputs);
+    assert_eq!(outputs.len(), inputs.len());
+    for (output, expected) in outputs.iter().zip(expected.iter()) {
+        let label = head_label(output, "sentiment");
+        assert_eq!(label.text, expected.text);
+        assert_eq!(label.id, expected.id);
+        assert_eq!(label.sentence, expected.sentence);
+        assert!((label.score - expected.score).abs() < 1e-4);
+        assert!(matches!(
+            output.heads["entities"],
+            TaskHeadOutput::Entities(_)
+        ));
+        assert!(output.classification.is_none());
+    }
+
+    //    Heads loaded on a half precision encoder are cast to half precision
+    multi_task_model.half();
+    multi_task_model.load_head(
+        "sentiment",
+        TaskHeadType::SequenceClassification,
+        sentiment_labels(),
+        &*model.model_resource(),
+        "classifier",
+    )?;
+    multi_task_model.float();
+    for (output, expected) in multi_task_model
+        .predict(&inputs)
+        .iter()
+        .zip(expected.iter())
+    {
+        assert!((head_label(output, "sentiment").score - expected.score).abs() < 1e-2);
+    }
+
+    assert!(matches!(
+        multi_task_model.load_head(
+            "sentiment",
+            TaskHeadType::SequenceClassification,
+            vec![],
+            &*model.model_resource(),
+            "classifier",
+        ),
+        Err(RustBertError::InvalidConfigurationError(_))
+    ));
+    assert!(matches!(
+        multi_task_model.load_head(
+            "sentiment",
+            TaskHeadType::SequenceClassification,
+            sentiment_labels(),
+            &*model.model_resource(),
+            "sentiment_classifier",
+        ),
+        Err(RustBertError::InvalidConfigurationError(_))
+    ));
+
+    assert!(multi_task_model.unload_head("sentiment"));
+    assert!(!multi_task_model.unload_head("sentiment"));
+    assert_eq!(multi_task_model.head_names(), ["entities"]);
+    for output in multi_task_model.predict(&inputs) {
+        assert!(!output.heads.contains_key("sentiment"));
+        assert!(output.heads.contains_key("entities"));
+    }
+
+    Ok(())
+}
+
+fn model_spec(model: &TinyModel, model_type: ModelType) -> ModelSpec {
+    ModelSpec {
+        model_type,
+        model_resource: model.model_resource(),
+        config_resource: model.config_resource(),
+        vocab_resource: model.vocab_resource(),
+        merges_resource: model.merges_resource(),
+        lower_case: true,
+        strip_accents: None,
+        add_prefix_space: None,
+        device: Device::Cpu,
+    }
+}
+
+#[test]
+fn tiny_models_registry_pipelines() -> anyhow::Result<()> {
+    let classifier = tiny_bert_classifier(42, &["contradiction", "neutral", "entailment"])?;
+    let token_classifier = tiny_bert_token_classifier(42, &["O", "B-PER", "I-PER"])?;
+    let question_answering = tiny_bert_qa(42)?;
+    let masked_lm = tiny_bert_masked_lm(42)?;
+    let gpt2 = tiny_gpt2(42)?;
+    let bart = tiny_bart(42)?;
+
+    //    Every task maps the model specification to the configuration of its pipeline
+    let supported_tasks = [
+        (
+            TaskType::SequenceClassification,
+            &classifier,
+            ModelType::Bert,
+        ),
+        (TaskType::Sentiment, &classifier, ModelType::Bert),
+        (
+            TaskType::ZeroShotClassification,
+            &classifier,
+            ModelType::Bert,
+        ),
+        (
+            TaskType::TokenClassification,
+            &token_classifier,
+            ModelType::Bert,
+        ),
+        (TaskType::NER, &token_classifier, ModelType::Bert),
+        (TaskType::POSTagging, &token_classifier, ModelType::Bert),
+        (
+            TaskType::QuestionAnswering,
+            &question_answering,
+            ModelType::Bert,
+        ),
+        (TaskType::MaskedLanguage, &masked_lm, ModelType::Bert),
+        (TaskType::Summarization, &bart, ModelType::Bart),
+        (TaskType::TextGeneration, &gpt2, ModelType::GPT2),
+        (TaskType::Conversation, &gpt2, ModelType::GPT2),
+    ];
+    for &(task, model, model_type) in supported_tasks.iter() {
+        let pipeline = Pipeline::new(task, model_spec(model, model_type))?;
+        assert_eq!(pipeline.task(), task);
+    }
+
+    //    Model types without an implementation for the task are rejected with a configuration error
+    let unsupported_tasks = [
+        (TaskType::Summarization, &classifier, ModelType::Bert),
+        (TaskType::TextGeneration, &classifier, ModelType::Bert),
+        (TaskType::Conversation, &classifier, ModelType::Bert),
+        (TaskType::TokenClassification, &gpt2, ModelType::GPT2),
+        (TaskType::MaskedLanguage, &gpt2, ModelType::GPT2),
+    ];
+    for &(task, model, model_type) in unsupported_tasks.iter() {
+        assert!(
+            matches!(
+                Pipeline::new(task, model_spec(model, model_type)),
+                Err(RustBertError::InvalidConfigurationError(_))
+            ),
+            "{:?} should not be supported for {:?}",
+            task,
+            model_type
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "tracing")]
+mod tracing_spans {
+    use super::*;
+    use std::fmt::Debug;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    struct RecordedSpan {
+        name: &'static str,
+        fields: HashMap<String, String>,
+    }
+
+    impl Visit for RecordedSpan {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.fields
+                .insert(field.name().to_string(), value.to_string());
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.fields
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    /// Subscriber recording the name and fields of the spans created
+    #[derive(Clone, Default)]
+    struct SpanRecorder {
+        spans: Arc<Mutex<Vec<RecordedSpan>>>,
+    }
+
+    impl SpanRecorder {
+        fn spans(&self, name: &str) -> Vec<HashMap<String, String>> {
+            self.spans
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|span| span.name == name)
+                .map(|span| span.fields.clone())
+                .collect()
+        }
+    }
+
+    impl Subscriber for SpanRecorder {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+            let mut span = RecordedSpan {
+                name: attributes.metadata().name(),
+                fields: HashMap::new(),
+            };
+            attributes.record(&mut span);
+            let mut spans = self.spans.lock().unwrap();
+            spans.push(span);
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, _event: &Event<'_>) {}
+
+        fn enter(&self, _span: &Id) {}
+
+        fn exit(&self, _span: &Id) {}
+    }
+
+    #[test]
+    fn tiny_bert_classification_spans() -> anyhow::Result<()> {
+        let model = tiny_bert_classifier(42, &["negative", "positive"])?;
+        let recorder = SpanRecorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || -> anyhow::Result<()> {
+            let classifier = bert_classifier(&model, None)?;
+            classifier.predict(["the dog was in the cat", "rust"]);
+            Ok(())
+        })?;
+
+        let load_spans = recorder.spans("load_model");
+        assert_eq!(load_spans.len(), 1);
+        assert_eq!(load_spans[0]["pipeline"], "sequence_classification");
+        assert_eq!(load_spans[0]["model_type"], "Bert");
+        assert_eq!(load_spans[0]["device"], "Cpu");
+
+        let tokenize_spans = recorder.spans("tokenize");
+        assert!(!tokenize_spans.is_empty());
+        assert_eq!(tokenize_spans[0]["batch_size"], "2");
+
+        let forward_spans = recorder.spans("forward");
+        assert!(!forward_spans.is_empty());
+        assert!(forward_spans[0]["input_shape"].starts_with("Some([2, "));
+        assert_eq!(forward_spans[0]["device"], "Some(Cpu)");
+        Ok(())
+    }
+
+    #[test]
+    fn tiny_gpt2_generation_spans() -> anyhow::Result<()> {
+        let model = tiny_gpt2(42)?;
+        let generator = gpt2_generator(&model, 1)?;
+        let recorder = SpanRecorder::default();
+        let output = tracing::subscriber::with_default(recorder.clone(), || {
+            generator.generate_indices(Some(&["the dog is"]), None)
+        })?;
+
+        let generate_spans = recorder.spans("generate");
+        assert_eq!(generate_spans.len(), 1);
+        assert_eq!(generate_spans[0]["batch_size"], "1");
+        assert_eq!(generate_spans[0]["num_beams"], "1");
+        assert_eq!(generate_spans[0]["do_sample"], "false");
+        assert_eq!(generate_spans[0]["device"], "Cpu");
+
+        //    One decoding step per generated token
+        let input_length: usize = generate_spans[0]["input_length"].parse()?;
+        let decode_steps = recorder.spans("decode_step");
+        assert_eq!(decode_steps.len(), output[0].indices.len() - input_length);
+        assert_eq!(decode_steps[0]["current_length"], input_length.to_string());
+        Ok(())
+    }
+}
+
+#[cfg(feature = "arrow")]
+mod arrow_dataframes {
+    use super::*;
+    use arrow::array::{Array, ArrayRef, Float64Array, Int64Array, StringArray, StructArray};
+    use arrow::record_batch::RecordBatch;
+    use rust_bert::pipelines::dataframe::arrow::{classify, map_strings};
+    use std::sync::Arc;
+
+    #[test]
+    fn tiny_bert_record_batch_classification() -> anyhow::Result<()> {
+        let model = tiny_bert_classifier(42, &["negative", "positive"])?;
+        let classifier = bert_classifier(&model, None)?;
+        let expected = classifier.predict(&["the dog is a cat", "rust is a language"]);
+
+        let reviews: ArrayRef = Arc::new(StringArray::from(vec![
+            Some("the dog is a cat"),
+            None,
+            Some("rust is a language"),
+        ]));
+        let batch = RecordBatch::try_from_iter(vec![("review", reviews)])?;
+
+        //    The predictions are appended to the record batch as new columns
+        let review_column = batch.column(batch.schema().index_of("review")?).clone();
+        let reviews = review_column
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        let labels: ArrayRef = Arc::new(classify(&classifier, reviews)?);
+        let label_texts: ArrayRef = Arc::new(map_strings(reviews, |texts| {
+            Ok(classifier
+                .predict(texts)
+                .into_iter()
+                .map(|label| label.text)
+                .collect())
+        })?);
+        let batch = RecordBatch::try_from_iter(vec![
+            ("review", review_column),
+            ("label", labels),
+            ("label_text", label_texts),
+        ])?;
+        assert_eq!(batch.num_rows(), 3);
+        assert_eq!(batch.num_columns(), 3);
+
+        let labels = batch
+            .column(batch.schema().index_of("label")?)
+            .as_any()
+            .downcast_ref::<StructArray>()
+            .unwrap();
+        let texts = labels
+            .column_by_name("label")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        let scores = labels
+            .column_by_name("score")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        let ids = labels
+            .column_by_name("id")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        let label_texts = batch
+            .column(batch.schema().index_of("label_text")?)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+
+        //    Null inputs are not classified and result in null outputs
+        for (row, label) in [0, 2].iter().zip(expected.iter()) {
+            assert_eq!(texts.value(*row), label.text);
+            assert!((scores.value(*row) - label.score).abs() < 1e-4);
+            assert_eq!(ids.value(*row), label.id);
+            assert_eq!(label_texts.value(*row), label.text);
+        }
+        assert!(texts.is_null(1));
+        assert!(scores.is_null(1));
+        assert!(ids.is_null(1));
+        assert!(label_texts.is_null(1));
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "polars")]
+mod polars_dataframes {
+    use super::*;
+    use polars::prelude::{DataFrame, NamedFrom, Series};
+    use rust_bert::pipelines::dataframe::polars::{classify, map_strings};
+
+    #[test]
+    fn tiny_bert_dataframe_classification() -> anyhow::Result<()> {
+        let model = tiny_bert_classifier(42, &["negative", "positive"])?;
+        let classifier = bert_classifier(&model, None)?;
+        let expected = classifier.predict(&["the dog is a cat", "rust is a language"]);
+
+        let mut dataframe = DataFrame::new(vec![Series::new(
+            "review",
+            vec![Some("the dog is a cat"), None, Some("rust is a language")],
+        )])?;
+
+        //    The predictions are appended to the dataframe as new columns
+        let mut labels = classify(&classifier, dataframe.column("review")?)?;
+        labels.rename("label");
+        let mut label_texts = map_strings(dataframe.column("review")?, |texts| {
+            Ok(classifier
+                .predict(texts)
+                .into_iter()
+                .map(|label| label.text)
+                .collect())
+        })?;
+        label_texts.rename("label_text");
+        dataframe.with_column(labels)?;
+        dataframe.with_column(label_texts)?;
+        assert_eq!(dataframe.shape(), (3, 3));
+
+        let labels = dataframe.column("label")?.struct_()?;
+        let field = |name: &str| {
+            labels
+                .fields()
+                .iter()
+                .find(|field| field.name() == name)
+                .unwrap()
+                .clone()
+        };
+        let (texts, scores, ids) = (field("label"), field("score"), field("id"));
+        let label_texts = dataframe.column("label_text")?.utf8()?;
+
+        //    Null inputs are not classified and result in null outputs
+        for (row, label) in [0, 2].iter().zip(expected.iter()) {
+            assert_eq!(texts.utf8()?.get(*row), Some(label.text.as_str()));
+            assert!((scores.f64()?.get(*row).unwrap() - label.score).abs() < 1e-4);
+            assert_eq!(ids.i64()?.get(*row), Some(label.id));
+            assert_eq!(label_texts.get(*row), Some(label.text.as_str()));
+        }
+        assert_eq!(texts.utf8()?.get(1), None);
+        assert_eq!(scores.f64()?.get(1), None);
+        assert_eq!(ids.i64()?.get(1), None);
+        assert_eq!(label_texts.get(1), None);
+
+        Ok(())
+    }
+}